@@ -0,0 +1,121 @@
+//! Benchmarks for the overhead of dispatching `rustc` through a rustup proxy.
+//!
+//! Run with `cargo bench --features test --bench proxy`. Each case uses a mock
+//! toolchain linked into a scratch `RUSTUP_HOME`, so the numbers mostly reflect
+//! the time rustup spends before handing over to the real binary. The
+//! `settings` cases measure just the loading of a large `settings.toml`, with
+//! and without the cache of it that rustup keeps.
+
+#![recursion_limit = "256"]
+
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tokio::runtime::Runtime;
+
+use rustup::test::load_settings;
+use rustup::test::mock::clitools::{CliTestContext, Scenario};
+
+async fn linked_toolchain_context() -> CliTestContext {
+    let mut cx = CliTestContext::new(Scenario::None).await;
+    let path = cx.config.customdir.join("custom-1");
+    let path = path.to_string_lossy();
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "link", "custom", &path])
+        .await;
+    cx.config.expect_ok(&["rustup", "default", "custom"]).await;
+    cx
+}
+
+/// A home whose settings have as many directory overrides as a busy
+/// workstation accumulates, last written long enough ago to be cached.
+async fn many_overrides_context() -> CliTestContext {
+    let mut cx = linked_toolchain_context().await;
+    for i in 0..100 {
+        let dir = cx.config.homedir.join(format!("project-{i}"));
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_string_lossy();
+        cx.config
+            .expect_ok(&["rustup", "override", "set", "custom", "--path", &dir])
+            .await;
+    }
+    let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+    fs::File::options()
+        .append(true)
+        .open(cx.config.rustupdir.join("settings.toml"))
+        .unwrap()
+        .set_modified(an_hour_ago)
+        .unwrap();
+    cx
+}
+
+fn run_rustc(cx: &CliTestContext, args: &[&str]) {
+    let out = cx.config.run_subprocess("rustc", args, &[]);
+    assert_eq!(
+        out.status,
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+fn proxy_dispatch(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("proxy");
+
+    // The first invocation through a proxy in a freshly configured home.
+    group.bench_function("cold", |b| {
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let cx = rt.block_on(linked_toolchain_context());
+                let start = Instant::now();
+                run_rustc(&cx, &["--version"]);
+                total += start.elapsed();
+            }
+            total
+        })
+    });
+
+    let cx = rt.block_on(linked_toolchain_context());
+    group.bench_function("warm", |b| b.iter(|| run_rustc(&cx, &["--version"])));
+    group.bench_function("warm +toolchain", |b| {
+        b.iter(|| run_rustc(&cx, &["+custom", "--version"]))
+    });
+    group.finish();
+}
+
+fn settings_loading(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("settings");
+    let cx = rt.block_on(many_overrides_context());
+    let settings = cx.config.rustupdir.join("settings.toml");
+    let cache = cx.config.rustupdir.join("settings.cache");
+
+    // Parsing `settings.toml`, as when it has just been changed.
+    group.bench_function("uncached", |b| {
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let _ = fs::remove_file(&cache);
+                let start = Instant::now();
+                load_settings(&settings).unwrap();
+                total += start.elapsed();
+            }
+            total
+        })
+    });
+
+    load_settings(&settings).unwrap();
+    assert!(cache.exists());
+    group.bench_function("cached", |b| b.iter(|| load_settings(&settings).unwrap()));
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = proxy_dispatch, settings_loading
+}
+criterion_main!(benches);
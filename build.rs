@@ -61,6 +61,19 @@ fn main() {
     // https://learn.microsoft.com/en-us/cpp/build/reference/dependentloadflag?view=msvc-170
     println!("cargo:rustc-link-arg-bin=rustup-init=delayimp.lib");
 
+    // # Static C runtime
+    //
+    // Without `+crt-static`, rustup-init needs the Visual C++ runtime DLLs,
+    // which a fresh Windows install doesn't have, and fails to start with
+    // nothing but a loader error. Release builds always link it statically.
+    let static_crt = env::var("CARGO_CFG_TARGET_FEATURE")
+        .is_ok_and(|features| features.split(',').any(|f| f == "crt-static"));
+    if !static_crt {
+        println!(
+            "cargo:warning=rustup-init built without `-Ctarget-feature=+crt-static` needs the Visual C++ runtime installed to start"
+        );
+    }
+
     // # Turn linker warnings into errors
     //
     // Rust hides linker warnings meaning mistakes may go unnoticed.
@@ -0,0 +1,602 @@
+//! Diagnosis of TLS connections, for `rustup self diagnose-tls`.
+//!
+//! A failed download only says that a certificate was not trusted. Here a
+//! handshake is made with each TLS backend compiled in, outside of reqwest,
+//! so that the certificates the server presented can be shown along with
+//! why they were rejected. Behind a proxy that inspects TLS traffic, that is
+//! where its own certificate shows up.
+
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use anyhow::{anyhow, bail, Context, Result};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{TlsConfig, TlsHost};
+
+/// What a handshake with one TLS backend found.
+#[derive(Debug)]
+pub struct TlsDiagnosis {
+    pub backend: &'static str,
+    /// Where the backend gets the certificates it trusts from.
+    pub verifier: String,
+    /// The negotiated protocol version, if the backend tells.
+    pub protocol: Option<String>,
+    /// The certificates presented by the server, its own first.
+    pub chain: Vec<Certificate>,
+    /// Why the handshake failed, if it did.
+    pub error: Option<String>,
+    /// What a failure most likely means.
+    pub hint: Option<&'static str>,
+}
+
+/// The parts of a certificate that tell it apart from an impostor.
+#[derive(Debug, PartialEq)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    pub not_after: String,
+    pub sha256: String,
+}
+
+const UNKNOWN_ISSUER_HINT: &str = "the certificates do not lead up to a trusted root; \
+    if they are not issued by a public CA, a proxy or security software that \
+    inspects TLS traffic is probably presenting its own, and its root \
+    certificate needs to be added to the system's certificate store";
+const EXPIRED_HINT: &str =
+    "a certificate is expired or not valid yet; check that the system clock is right";
+const WRONG_NAME_HINT: &str = "the certificate is for another host; a proxy or captive \
+    portal is probably answering in place of the server";
+
+/// Makes a TLS handshake with the server of `url` with each TLS backend.
+pub fn diagnose_tls(url: &Url) -> Result<Vec<TlsDiagnosis>> {
+    if url.scheme() != "https" {
+        bail!("'{url}' is not an https URL");
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("'{url}' has no host"))?
+        .to_owned();
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    Ok(vec![
+        #[cfg(feature = "reqwest-rustls-tls")]
+        with_rustls::diagnose(url, &host, port),
+        #[cfg(feature = "reqwest-native-tls")]
+        with_native_tls::diagnose(url, &host, port),
+    ])
+}
+
+/// Adds what the settings of the server itself change to the description
+/// of a backend's `verifier`.
+fn with_host_settings(verifier: String, tls: &TlsConfig) -> String {
+    match &tls.host {
+        Some((host, TlsHost { insecure: true, .. })) => {
+            format!("none, the settings of '{host}' turn verification off")
+        }
+        Some((
+            host,
+            TlsHost {
+                roots: Some(path), ..
+            },
+        )) => format!(
+            "{verifier}, and for '{host}' the roots in '{}'",
+            path.display()
+        ),
+        _ => verifier,
+    }
+}
+
+/// The proxy that connections to `url` go through, if any.
+pub fn proxy_for(url: &Url) -> Option<Url> {
+    env_proxy::for_url(url).to_url()
+}
+
+/// Opens a TCP connection to `host`, tunneled through the HTTP proxy for
+/// `url` if there is one.
+fn connect(url: &Url, host: &str, port: u16) -> Result<TcpStream> {
+    let proxy = proxy_for(url);
+    let (connect_host, connect_port) = match &proxy {
+        Some(proxy) if proxy.scheme() != "http" => {
+            bail!("only HTTP proxies are supported, not '{}'", proxy.scheme())
+        }
+        Some(proxy) => (
+            proxy
+                .host_str()
+                .ok_or_else(|| anyhow!("the proxy '{proxy}' has no host"))?,
+            proxy.port_or_known_default().unwrap_or(80),
+        ),
+        None => (host, port),
+    };
+
+    let addr = (connect_host, connect_port)
+        .to_socket_addrs()
+        .with_context(|| format!("could not resolve '{connect_host}'"))?
+        .next()
+        .ok_or_else(|| anyhow!("'{connect_host}' has no addresses"))?;
+    let stream = TcpStream::connect_timeout(&addr, super::CONNECT_TIMEOUT)
+        .with_context(|| format!("could not connect to '{connect_host}:{connect_port}'"))?;
+    stream.set_read_timeout(Some(super::READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(super::READ_TIMEOUT))?;
+    if proxy.is_none() {
+        return Ok(stream);
+    }
+
+    // Ask the proxy for a tunnel, and read its answer up to the blank line
+    // that ends it, leaving the stream at the start of the tunnel.
+    (&stream).write_all(
+        format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n\r\n").as_bytes(),
+    )?;
+    let mut reader = BufReader::new(&stream);
+    let mut status = String::new();
+    reader.read_line(&mut status)?;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+    }
+    if reader.buffer().is_empty() && status.split(' ').nth(1) == Some("200") {
+        Ok(stream)
+    } else {
+        bail!(
+            "the proxy refused to connect to '{host}:{port}': {}",
+            status.trim()
+        )
+    }
+}
+
+#[cfg(feature = "reqwest-rustls-tls")]
+mod with_rustls {
+    use std::sync::{Arc, Mutex};
+
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::crypto::aws_lc_rs;
+    use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+    use rustls::{
+        CertificateError, ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme,
+    };
+    use url::Url;
+
+    use super::{Certificate, TlsDiagnosis};
+    use crate::{reqwest_be, TlsConfig, TlsRoots};
+
+    /// The verifier used for downloads, keeping the certificates it was asked
+    /// about.
+    #[derive(Debug)]
+    struct Recorder {
+        verifier: Arc<dyn ServerCertVerifier>,
+        chain: Mutex<Vec<CertificateDer<'static>>>,
+    }
+
+    impl ServerCertVerifier for Recorder {
+        fn verify_server_cert(
+            &self,
+            end_entity: &CertificateDer<'_>,
+            intermediates: &[CertificateDer<'_>],
+            server_name: &ServerName<'_>,
+            ocsp_response: &[u8],
+            now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            *self.chain.lock().unwrap() = [end_entity]
+                .into_iter()
+                .chain(intermediates)
+                .map(|cert| cert.clone().into_owned())
+                .collect();
+            self.verifier.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                now,
+            )
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            self.verifier.verify_tls12_signature(message, cert, dss)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            self.verifier.verify_tls13_signature(message, cert, dss)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.verifier.supported_verify_schemes()
+        }
+    }
+
+    pub(super) fn diagnose(url: &Url, host: &str, port: u16) -> TlsDiagnosis {
+        let tls = TlsConfig::for_host(Some(host));
+        let provider = Arc::new(aws_lc_rs::default_provider());
+        let mut diagnosis = TlsDiagnosis {
+            backend: "rustls",
+            verifier: super::with_host_settings(verifier(&tls.roots), &tls),
+            protocol: None,
+            chain: Vec::new(),
+            error: None,
+            hint: None,
+        };
+        let recorder = match reqwest_be::rustls_verifier(&tls, &provider) {
+            Ok(verifier) => Arc::new(Recorder {
+                verifier,
+                chain: Mutex::new(Vec::new()),
+            }),
+            Err(e) => {
+                diagnosis.error = Some(format!("{e:#}"));
+                return diagnosis;
+            }
+        };
+
+        let result = (|| -> anyhow::Result<_> {
+            let config = ClientConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()?
+                .dangerous()
+                .with_custom_certificate_verifier(recorder.clone())
+                .with_no_client_auth();
+            let mut tcp = super::connect(url, host, port)?;
+            let mut conn =
+                ClientConnection::new(Arc::new(config), ServerName::try_from(host.to_owned())?)?;
+            while conn.is_handshaking() {
+                conn.complete_io(&mut tcp)?;
+            }
+            Ok(conn.protocol_version())
+        })();
+        match result {
+            Ok(protocol) => diagnosis.protocol = protocol.map(|p| format!("{p:?}")),
+            Err(e) => {
+                diagnosis.hint = e
+                    .chain()
+                    .find_map(|e| e.downcast_ref::<rustls::Error>())
+                    .or_else(|| {
+                        // `complete_io` wraps errors from rustls in IO errors.
+                        e.chain()
+                            .find_map(|e| e.downcast_ref::<std::io::Error>())
+                            .and_then(|e| e.get_ref())
+                            .and_then(|e| e.downcast_ref::<rustls::Error>())
+                    })
+                    .and_then(hint);
+                diagnosis.error = Some(format!("{e:#}"));
+            }
+        }
+        diagnosis.chain = recorder
+            .chain
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|der| Certificate::from_der(der))
+            .collect();
+        diagnosis
+    }
+
+    fn hint(error: &rustls::Error) -> Option<&'static str> {
+        match error {
+            rustls::Error::InvalidCertificate(error) => match error {
+                CertificateError::UnknownIssuer | CertificateError::BadSignature => {
+                    Some(super::UNKNOWN_ISSUER_HINT)
+                }
+                CertificateError::Expired | CertificateError::NotValidYet => {
+                    Some(super::EXPIRED_HINT)
+                }
+                CertificateError::NotValidForName => Some(super::WRONG_NAME_HINT),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn verifier(roots: &TlsRoots) -> String {
+        let platform = if cfg!(any(target_os = "macos", target_os = "ios")) {
+            "the platform verifier, with the roots trusted by the Security framework"
+        } else if cfg!(windows) {
+            "the platform verifier, with the roots in the Windows certificate store"
+        } else {
+            "webpki, with the roots in the system's certificate store \
+             (SSL_CERT_FILE and SSL_CERT_DIR override where it is)"
+        };
+        match roots {
+            TlsRoots::Platform => platform.to_owned(),
+            TlsRoots::Bundled => {
+                format!("{platform}, then for rust-lang.org the roots bundled with rustup")
+            }
+            TlsRoots::File(path) => format!("webpki, with only the roots in '{}'", path.display()),
+        }
+    }
+}
+
+#[cfg(feature = "reqwest-native-tls")]
+mod with_native_tls {
+    use native_tls::{HandshakeError, TlsConnector};
+    use url::Url;
+
+    use super::{Certificate, TlsDiagnosis};
+    use crate::{read_roots, TlsConfig, TlsRoots};
+
+    pub(super) fn diagnose(url: &Url, host: &str, port: u16) -> TlsDiagnosis {
+        let tls = TlsConfig::for_host(Some(host));
+        let mut diagnosis = TlsDiagnosis {
+            backend: "native-tls",
+            verifier: super::with_host_settings(verifier(&tls.roots), &tls),
+            protocol: None,
+            chain: Vec::new(),
+            error: None,
+            hint: None,
+        };
+
+        match handshake(url, host, port, Some(&tls)) {
+            Ok(leaf) => diagnosis.chain.extend(leaf),
+            Err(e) => {
+                let error = format!("{e:#}");
+                diagnosis.hint = hint(&error);
+                diagnosis.error = Some(error);
+                // The certificate that was rejected is only available from a
+                // handshake that doesn't check it.
+                if let Ok(leaf) = handshake(url, host, port, None) {
+                    diagnosis.chain.extend(leaf);
+                }
+            }
+        }
+        diagnosis
+    }
+
+    /// Returns the certificate of the server; native-tls doesn't give the
+    /// rest of the chain. Without `tls`, the certificate isn't verified.
+    fn handshake(
+        url: &Url,
+        host: &str,
+        port: u16,
+        tls: Option<&TlsConfig>,
+    ) -> anyhow::Result<Option<Certificate>> {
+        let mut builder = TlsConnector::builder();
+        let Some(tls) = tls else {
+            builder.danger_accept_invalid_certs(true);
+            return handshake_with(url, host, port, builder);
+        };
+        let mut roots = Vec::new();
+        if let TlsRoots::File(path) = &tls.roots {
+            builder.disable_built_in_roots(true);
+            roots.extend(read_roots(path)?);
+        }
+        if let Some((_, settings)) = &tls.host {
+            if let Some(path) = &settings.roots {
+                roots.extend(read_roots(path)?);
+            }
+            builder.danger_accept_invalid_certs(settings.insecure);
+        }
+        for root in roots {
+            builder.add_root_certificate(native_tls::Certificate::from_der(&root)?);
+        }
+        handshake_with(url, host, port, builder)
+    }
+
+    fn handshake_with(
+        url: &Url,
+        host: &str,
+        port: u16,
+        builder: native_tls::TlsConnectorBuilder,
+    ) -> anyhow::Result<Option<Certificate>> {
+        let connector = builder.build()?;
+        let tcp = super::connect(url, host, port)?;
+        let stream = match connector.connect(host, tcp) {
+            Ok(stream) => stream,
+            Err(HandshakeError::Failure(e)) => return Err(e.into()),
+            Err(HandshakeError::WouldBlock(_)) => unreachable!("the socket is blocking"),
+        };
+        Ok(match stream.peer_certificate()? {
+            Some(cert) => Some(Certificate::from_der(&cert.to_der()?)),
+            None => None,
+        })
+    }
+
+    fn hint(error: &str) -> Option<&'static str> {
+        let error = error.to_lowercase();
+        if [
+            "local issuer",
+            "self signed",
+            "self-signed",
+            "not trusted",
+            "untrusted root",
+        ]
+        .iter()
+        .any(|s| error.contains(s))
+        {
+            Some(super::UNKNOWN_ISSUER_HINT)
+        } else if ["expired", "not yet valid"]
+            .iter()
+            .any(|s| error.contains(s))
+        {
+            Some(super::EXPIRED_HINT)
+        } else if ["hostname mismatch", "name mismatch", "does not match"]
+            .iter()
+            .any(|s| error.contains(s))
+        {
+            Some(super::WRONG_NAME_HINT)
+        } else {
+            None
+        }
+    }
+
+    fn verifier(roots: &TlsRoots) -> String {
+        let library = if cfg!(any(target_os = "macos", target_os = "ios")) {
+            "the Security framework"
+        } else if cfg!(windows) {
+            "SChannel"
+        } else {
+            "OpenSSL"
+        };
+        match roots {
+            TlsRoots::File(path) => {
+                format!("{library}, with only the roots in '{}'", path.display())
+            }
+            _ if cfg!(any(target_os = "macos", target_os = "ios")) => {
+                format!("{library}, with the roots it trusts")
+            }
+            _ if cfg!(windows) => {
+                format!("{library}, with the roots in the Windows certificate store")
+            }
+            _ => format!(
+                "{library}, with the roots in the system's certificate store \
+                 (SSL_CERT_FILE and SSL_CERT_DIR override where it is)"
+            ),
+        }
+    }
+}
+
+impl Certificate {
+    /// Reads what is shown of a DER-encoded X.509 certificate. Parts that
+    /// can't be read are left empty, as a certificate is only shown here.
+    pub fn from_der(der: &[u8]) -> Self {
+        let mut cert = Self {
+            subject: String::new(),
+            issuer: String::new(),
+            not_after: String::new(),
+            sha256: Sha256::digest(der)
+                .iter()
+                .map(|b| format!("{b:02X}"))
+                .collect::<Vec<_>>()
+                .join(":"),
+        };
+        _ = cert.read_tbs(der);
+        cert
+    }
+
+    fn read_tbs(&mut self, der: &[u8]) -> Option<()> {
+        let (_, certificate, _) = tlv(der)?;
+        let (_, tbs, _) = tlv(certificate)?;
+        let (tag, _, mut rest) = tlv(tbs)?;
+        // The version is optional, and tagged to tell it apart.
+        if tag == 0xa0 {
+            (_, _, rest) = tlv(rest)?;
+        }
+        let (_, _, rest) = tlv(rest)?; // signature algorithm
+        let (_, issuer, rest) = tlv(rest)?;
+        let (_, validity, rest) = tlv(rest)?;
+        let (_, subject, _) = tlv(rest)?;
+        self.issuer = name(issuer)?;
+        self.subject = name(subject)?;
+        let (_, _, validity) = tlv(validity)?;
+        let (tag, not_after, _) = tlv(validity)?;
+        self.not_after = time(tag, not_after)?;
+        Some(())
+    }
+}
+
+/// Splits the first DER value off `der`, as its tag, its contents and what
+/// follows it.
+fn tlv(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&len, mut rest) = rest.split_first()?;
+    let len = match len {
+        0..=0x7f => usize::from(len),
+        0x81..=0x84 => {
+            let (bytes, after) = rest.split_at_checked(usize::from(len - 0x80))?;
+            rest = after;
+            bytes.iter().fold(0, |len, &b| len << 8 | usize::from(b))
+        }
+        _ => return None,
+    };
+    let (contents, rest) = rest.split_at_checked(len)?;
+    Some((tag, contents, rest))
+}
+
+/// Formats an X.509 name as e.g. `CN=static.rust-lang.org, O=...`, with the
+/// attributes that are commonly shown.
+fn name(mut der: &[u8]) -> Option<String> {
+    let mut name = String::new();
+    while !der.is_empty() {
+        let (_, set, rest) = tlv(der)?;
+        der = rest;
+        let (_, attribute, _) = tlv(set)?;
+        let (_, oid, rest) = tlv(attribute)?;
+        let (_, value, _) = tlv(rest)?;
+        let key = match oid {
+            [0x55, 0x04, 0x03] => "CN",
+            [0x55, 0x04, 0x06] => "C",
+            [0x55, 0x04, 0x07] => "L",
+            [0x55, 0x04, 0x08] => "ST",
+            [0x55, 0x04, 0x0a] => "O",
+            [0x55, 0x04, 0x0b] => "OU",
+            _ => continue,
+        };
+        if !name.is_empty() {
+            name.push_str(", ");
+        }
+        _ = write!(name, "{key}={}", String::from_utf8_lossy(value));
+    }
+    Some(name)
+}
+
+/// Formats a UTCTime or GeneralizedTime, both of which are in UTC in
+/// certificates.
+fn time(tag: u8, der: &[u8]) -> Option<String> {
+    let time = std::str::from_utf8(der).ok()?.strip_suffix('Z')?;
+    let time = match tag {
+        0x17 => {
+            let year = time.get(..2)?.parse::<u32>().ok()?;
+            let century = if year < 50 { "20" } else { "19" };
+            format!("{century}{time}")
+        }
+        0x18 => time.to_owned(),
+        _ => return None,
+    };
+    if time.len() != 14 || !time.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(format!(
+        "{}-{}-{} {}:{}:{} UTC",
+        &time[..4],
+        &time[4..6],
+        &time[6..8],
+        &time[8..10],
+        &time[10..12],
+        &time[12..14]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_certificate() {
+        let cert = Certificate::from_der(include_bytes!("../tests/support/self-signed.der"));
+        assert_eq!(
+            cert,
+            Certificate {
+                subject: "C=US, O=Rustup Test, CN=localhost".to_owned(),
+                issuer: "C=US, O=Rustup Test, CN=localhost".to_owned(),
+                not_after: "2126-09-23 10:57:26 UTC".to_owned(),
+                sha256: "DD:3A:35:F1:CF:B9:4E:F3:64:B5:1E:5D:71:F5:5F:DE:\
+                         FD:45:F0:76:E5:32:96:98:26:54:BB:1F:BC:73:6B:BF"
+                    .to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn reads_utc_time() {
+        assert_eq!(
+            time(0x17, b"260101120000Z").as_deref(),
+            Some("2026-01-01 12:00:00 UTC")
+        );
+        assert_eq!(time(0x17, b"2601011200Z"), None);
+    }
+
+    #[test]
+    fn shows_garbage_as_fingerprint_only() {
+        let cert = Certificate::from_der(b"\x30\x82\xff");
+        assert!(cert.subject.is_empty());
+        assert_eq!(cert.sha256.len(), 32 * 3 - 1);
+    }
+}
@@ -1,13 +1,26 @@
 //! Easy file downloading
 
+use std::collections::BTreeMap;
 use std::fs::remove_file;
-use std::path::Path;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, SystemTime};
 
 use anyhow::Context;
 pub use anyhow::Result;
+use rustls_pki_types::{pem::PemObject, CertificateDer};
 use thiserror::Error;
 use url::Url;
 
+#[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
+mod diagnose;
+#[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
+pub use diagnose::{diagnose_tls, proxy_for, Certificate, TlsDiagnosis};
+mod object_store;
+pub use object_store::ObjectStore;
+
 /// User agent header value for HTTP request.
 /// See: https://github.com/rust-lang/rustup/issues/2860.
 #[cfg(feature = "curl-backend")]
@@ -24,6 +37,32 @@ const REQWEST_DEFAULT_TLS_USER_AGENT: &str = concat!(
 const REQWEST_RUSTLS_TLS_USER_AGENT: &str =
     concat!("rustup/", env!("CARGO_PKG_VERSION"), " (reqwest; rustls)");
 
+/// How long to wait for a connection to the server.
+#[cfg(any(
+    feature = "curl-backend",
+    feature = "reqwest-rustls-tls",
+    feature = "reqwest-native-tls"
+))]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a download may go without receiving any data.
+#[cfg(any(
+    feature = "curl-backend",
+    feature = "reqwest-rustls-tls",
+    feature = "reqwest-native-tls"
+))]
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many redirects to follow before giving up.
+#[cfg(any(
+    feature = "curl-backend",
+    feature = "reqwest-rustls-tls",
+    feature = "reqwest-native-tls"
+))]
+const MAX_REDIRECTS: usize = 10;
+
+/// A way of downloading files. All backends follow the same redirect policy,
+/// timeouts and proxy settings from the environment.
 #[derive(Debug, Copy, Clone)]
 pub enum Backend {
     #[cfg(feature = "curl-backend")]
@@ -40,93 +79,562 @@ impl Backend {
         resume_from_partial: bool,
         callback: Option<DownloadCallback<'_>>,
     ) -> Result<()> {
-        let Err(err) = self
-            .download_impl(url, path, resume_from_partial, callback)
-            .await
-        else {
-            return Ok(());
-        };
-
-        // TODO: We currently clear up the cached download on any error, should we restrict it to a subset?
-        Err(
-            if let Err(file_err) = remove_file(path).context("cleaning up cached downloads") {
-                file_err.context(err)
-            } else {
-                err
-            },
-        )
+        download_to_path(&self, url, path, resume_from_partial, callback).await
     }
 
-    async fn download_impl(
+    #[cfg_attr(
+        all(
+            not(feature = "curl-backend"),
+            not(feature = "reqwest-rustls-tls"),
+            not(feature = "reqwest-native-tls")
+        ),
+        allow(unused_variables)
+    )]
+    async fn download(
         self,
         url: &Url,
-        path: &Path,
-        resume_from_partial: bool,
-        callback: Option<DownloadCallback<'_>>,
+        resume_from: u64,
+        headers: &[(String, String)],
+        callback: DownloadCallback<'_>,
     ) -> Result<()> {
-        use std::cell::RefCell;
-        use std::fs::OpenOptions;
-        use std::io::{Read, Seek, SeekFrom, Write};
-
-        let (file, resume_from) = if resume_from_partial {
-            // TODO: blocking call
-            let possible_partial = OpenOptions::new().read(true).open(path);
-
-            let downloaded_so_far = if let Ok(mut partial) = possible_partial {
-                if let Some(cb) = callback {
-                    cb(Event::ResumingPartialDownload)?;
-
-                    let mut buf = vec![0; 32768];
-                    let mut downloaded_so_far = 0;
-                    loop {
-                        let n = partial.read(&mut buf)?;
-                        downloaded_so_far += n as u64;
-                        if n == 0 {
-                            break;
-                        }
-                        cb(Event::DownloadDataReceived(&buf[..n]))?;
+        match self {
+            #[cfg(feature = "curl-backend")]
+            Self::Curl => curl::download(url, resume_from, headers, callback),
+            #[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
+            Self::Reqwest(tls) => tls.download(url, resume_from, headers, callback).await,
+        }
+    }
+}
+
+impl Transport for Backend {
+    fn name(&self) -> &str {
+        match self {
+            #[cfg(feature = "curl-backend")]
+            Self::Curl => "curl",
+            #[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
+            Self::Reqwest(_) => "reqwest",
+        }
+    }
+
+    fn handles(&self, _: &Url) -> bool {
+        true
+    }
+
+    fn download<'a>(
+        &'a self,
+        url: &'a Url,
+        resume_from: u64,
+        callback: DownloadCallback<'a>,
+    ) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let headers = auth_headers(url);
+            Backend::download(*self, url, resume_from, &headers, callback).await
+        })
+    }
+}
+
+/// A way of fetching the data at a URL: either one of the built-in
+/// [`Backend`]s, or one supplied by an embedder of rustup with
+/// [`register_transport`], such as a client for an internal artifact store.
+pub trait Transport: Send + Sync {
+    /// A short name for the transport, used in messages.
+    fn name(&self) -> &str;
+
+    /// Returns whether this transport should be used to download `url`.
+    fn handles(&self, url: &Url) -> bool;
+
+    /// Fetches `url`, skipping its first `resume_from` bytes, and reports the
+    /// response and the data received to `callback`.
+    fn download<'a>(
+        &'a self,
+        url: &'a Url,
+        resume_from: u64,
+        callback: DownloadCallback<'a>,
+    ) -> TransportFuture<'a>;
+}
+
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + 'a>>;
+
+static TRANSPORTS: RwLock<Vec<Arc<dyn Transport>>> = RwLock::new(Vec::new());
+
+/// Registers a transport to use instead of the built-in backends for the URLs
+/// it handles. Transports registered later take precedence.
+pub fn register_transport(transport: Arc<dyn Transport>) {
+    TRANSPORTS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(transport);
+}
+
+/// Returns the most recently registered transport that handles `url`, if any.
+pub fn registered_transport(url: &Url) -> Option<Arc<dyn Transport>> {
+    TRANSPORTS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .rev()
+        .find(|transport| transport.handles(url))
+        .cloned()
+}
+
+/// Where the reqwest backends get the root certificates they trust from.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum TlsRoots {
+    /// The platform's certificate store.
+    #[default]
+    Platform,
+    /// The platform's certificate store, and for the rust-lang.org servers
+    /// the roots bundled with rustup if the platform's don't verify them.
+    /// Only rustls supports this, native-tls uses the platform's alone.
+    Bundled,
+    /// Only the certificates in a PEM file.
+    File(PathBuf),
+}
+
+static TLS_ROOTS: RwLock<TlsRoots> = RwLock::new(TlsRoots::Platform);
+
+/// Sets where the root certificates trusted by downloads from now on come
+/// from.
+pub fn set_tls_roots(roots: TlsRoots) {
+    *TLS_ROOTS.write().unwrap_or_else(PoisonError::into_inner) = roots;
+}
+
+/// Where the root certificates trusted by downloads come from.
+pub fn tls_roots() -> TlsRoots {
+    TLS_ROOTS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// TLS settings of a single server, such as a mirror behind a proxy that
+/// intercepts TLS, on top of [`TlsRoots`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TlsHost {
+    /// Roots trusted for this server in addition to the others.
+    pub roots: Option<PathBuf>,
+    /// Don't verify the server's certificate at all.
+    pub insecure: bool,
+}
+
+static TLS_HOSTS: RwLock<BTreeMap<String, TlsHost>> = RwLock::new(BTreeMap::new());
+
+/// Sets the TLS settings of the servers that have their own, by lowercase
+/// host name. Only the reqwest backends use them.
+pub fn set_tls_hosts(hosts: BTreeMap<String, TlsHost>) {
+    *TLS_HOSTS.write().unwrap_or_else(PoisonError::into_inner) = hosts;
+}
+
+/// Reads the certificates in the PEM file at `path`, failing if there are
+/// none. Settings are checked with it whichever backends are built.
+pub fn read_roots(path: &Path) -> Result<Vec<Vec<u8>>> {
+    let pem = std::fs::read(path)
+        .with_context(|| format!("could not read root certificates from '{}'", path.display()))?;
+    let roots = CertificateDer::pem_slice_iter(&pem)
+        .map(|cert| cert.map(|cert| cert.to_vec()))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("could not parse root certificates in '{}'", path.display()))?;
+    if roots.is_empty() {
+        anyhow::bail!("'{}' contains no certificates", path.display());
+    }
+    Ok(roots)
+}
+
+/// Whether `host` is rust-lang.org or one of its subdomains, whose
+/// certificates are verified whatever their settings say.
+pub fn is_rust_lang_host(host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    host == "rust-lang.org" || host.ends_with(".rust-lang.org")
+}
+
+#[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
+pub(crate) fn tls_host(host: Option<&str>) -> Option<(String, TlsHost)> {
+    let host = host?.trim_end_matches('.').to_ascii_lowercase();
+    let mut settings = TLS_HOSTS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&host)
+        .cloned()?;
+    // Whoever set them, rustup itself is never downloaded unverified.
+    settings.insecure &= !is_rust_lang_host(&host);
+    Some((host, settings))
+}
+
+/// The TLS settings a client is built with, for connecting to one server.
+#[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct TlsConfig {
+    pub(crate) roots: TlsRoots,
+    /// The server's own settings, if it has any.
+    pub(crate) host: Option<(String, TlsHost)>,
+}
+
+#[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
+impl TlsConfig {
+    pub(crate) fn for_host(host: Option<&str>) -> Self {
+        Self {
+            roots: tls_roots(),
+            host: tls_host(host),
+        }
+    }
+}
+
+/// Looks up the token that authenticates downloads from a server, by its
+/// host name with the port, if not the default one.
+pub type TokenProvider = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+static TOKEN_PROVIDER: RwLock<Option<Arc<TokenProvider>>> = RwLock::new(None);
+
+/// Sets where the built-in backends get the bearer tokens they send to
+/// servers that require authentication.
+pub fn set_token_provider(provider: Arc<TokenProvider>) {
+    *TOKEN_PROVIDER
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = Some(provider);
+}
+
+/// The key a server's token is looked up by: its host name, with the port
+/// if it isn't the default one for the scheme.
+pub fn token_key(url: &Url) -> Option<String> {
+    let host = url.host_str()?.trim_end_matches('.').to_ascii_lowercase();
+    Some(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host,
+    })
+}
+
+/// The `Authorization` header for `url`, if a token is known for its
+/// server. Tokens are never sent over plain HTTP.
+fn auth_headers(url: &Url) -> Vec<(String, String)> {
+    if url.scheme() != "https" {
+        return Vec::new();
+    }
+    let Some(provider) = TOKEN_PROVIDER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+    else {
+        return Vec::new();
+    };
+    token_key(url)
+        .and_then(|key| provider(&key))
+        .map(|token| vec![("Authorization".to_owned(), format!("Bearer {token}"))])
+        .unwrap_or_default()
+}
+
+/// The headers to send to `to`, which a request to `from` with `headers` was
+/// redirected to. Credentials go only to the server they were meant for:
+/// once the scheme, host or port changes, `Authorization` and session tokens
+/// are dropped, and the token of the new server, if there is one, is looked
+/// up instead.
+#[cfg(any(
+    feature = "curl-backend",
+    feature = "reqwest-rustls-tls",
+    feature = "reqwest-native-tls"
+))]
+fn redirect_headers(from: &Url, headers: &[(String, String)], to: &Url) -> Vec<(String, String)> {
+    let same_origin = from.scheme() == to.scheme()
+        && from.host_str() == to.host_str()
+        && from.port_or_known_default() == to.port_or_known_default();
+    if same_origin {
+        return headers.to_vec();
+    }
+    let mut kept = headers
+        .iter()
+        .filter(|(name, _)| {
+            !name.eq_ignore_ascii_case("authorization")
+                && !name.eq_ignore_ascii_case("x-amz-security-token")
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    kept.extend(auth_headers(to));
+    kept
+}
+
+/// Downloads `url` to `path` with `transport`, resuming a partial download
+/// at `path` if asked to.
+pub async fn download_to_path(
+    transport: &dyn Transport,
+    url: &Url,
+    path: &Path,
+    resume_from_partial: bool,
+    callback: Option<DownloadCallback<'_>>,
+) -> Result<()> {
+    download_with_retry(
+        transport,
+        url,
+        path,
+        resume_from_partial,
+        callback,
+        &Retry::NEVER,
+    )
+    .await
+}
+
+/// How long to wait after being rate limited by a server that doesn't say.
+const RATE_LIMIT_WAIT: Duration = Duration::from_secs(5);
+
+/// How a download that failed in a way that may not happen again, such as a
+/// server error or a dropped connection, is retried.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Retry {
+    /// How many times to retry after the first attempt.
+    pub attempts: usize,
+    /// The wait before the first retry, which doubles with each one after it.
+    /// A server that rate limits the download says how long to wait instead.
+    pub initial_delay: Duration,
+    /// The longest wait before a retry.
+    pub max_delay: Duration,
+}
+
+impl Retry {
+    /// Downloads are only tried once.
+    pub const NEVER: Self = Self {
+        attempts: 0,
+        initial_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    /// How long to wait before the retry numbered `retry`, from 0, after the
+    /// previous attempt failed with `err`.
+    pub fn delay(&self, retry: usize, err: &anyhow::Error) -> Duration {
+        let rate_limited = err.chain().find_map(|cause| match cause.downcast_ref() {
+            Some(DownloadError::RateLimited { retry_after, .. }) => {
+                Some(retry_after.unwrap_or(RATE_LIMIT_WAIT))
+            }
+            _ => None,
+        });
+        let backoff = self.initial_delay.saturating_mul(1 << retry.min(16) as u32);
+        rate_limited.unwrap_or(backoff).min(self.max_delay)
+    }
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Whether the download that failed with `err` may succeed if it is tried
+/// again: the server failed or rate limited it, or the connection to the
+/// server failed.
+fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(err) = cause.downcast_ref::<DownloadError>() {
+            return match err {
+                DownloadError::HttpStatus(500..=599)
+                | DownloadError::RateLimited { .. }
+                | DownloadError::Incomplete { .. }
+                | DownloadError::ResumeNotSupported => true,
+                #[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
+                DownloadError::Reqwest(err) => reqwest_be::is_transient(err),
+                #[cfg(feature = "curl-backend")]
+                DownloadError::CurlError(err) => curl::is_transient(err),
+                _ => false,
+            };
+        }
+        #[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
+        if let Some(err) = cause.downcast_ref::<::reqwest::Error>() {
+            return reqwest_be::is_transient(err);
+        }
+        #[cfg(feature = "curl-backend")]
+        if let Some(err) = cause.downcast_ref::<::curl::Error>() {
+            return curl::is_transient(err);
+        }
+        match cause.downcast_ref::<std::io::Error>() {
+            Some(err) => matches!(
+                err.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::UnexpectedEof
+            ),
+            None => false,
+        }
+    })
+}
+
+/// Downloads `url` to `path` with `transport` like [`download_to_path`], and
+/// retries as `retry` says when the download fails in a way that may not
+/// happen again. A retry resumes from the data already downloaded if the
+/// server takes ranges, and starts over otherwise. Either way `callback`
+/// sees the data once, as if the download happened all at once, and is told
+/// of each retry before waiting for it.
+pub async fn download_with_retry(
+    transport: &dyn Transport,
+    url: &Url,
+    path: &Path,
+    resume_from_partial: bool,
+    callback: Option<DownloadCallback<'_>>,
+    retry: &Retry,
+) -> Result<()> {
+    use std::cell::Cell;
+
+    let forward = |event: Event<'_>| match callback {
+        Some(cb) => cb(event),
+        None => Ok(()),
+    };
+    // How much data `callback` has seen, whether it has seen the length,
+    // and whether the server takes ranges.
+    let delivered = Cell::new(0);
+    let length_delivered = Cell::new(false);
+    let ranges = Cell::new(false);
+
+    let mut resume = resume_from_partial;
+    let mut retries = 0;
+    let result = loop {
+        let first = retries == 0;
+        // The data of this attempt, including that of the partial download
+        // it resumes.
+        let received = Cell::new(0);
+        let attempt = download_impl(
+            transport,
+            url,
+            path,
+            resume,
+            Some(&|event| match event {
+                Event::ResponseReceived(res) => {
+                    let accepts_ranges = res.headers.iter().any(|(name, value)| {
+                        name.eq_ignore_ascii_case("accept-ranges") && value.trim() == "bytes"
+                    });
+                    ranges.set(ranges.get() || accepts_ranges || res.status == 206);
+                    forward(event)
+                }
+                Event::ResumingPartialDownload if !first => Ok(()),
+                Event::DownloadContentLengthReceived(_) if length_delivered.replace(true) => Ok(()),
+                Event::DownloadDataReceived(data) => {
+                    let start = received.get();
+                    received.set(start + data.len() as u64);
+                    let seen = (delivered.get() - start.min(delivered.get())) as usize;
+                    if seen >= data.len() {
+                        return Ok(());
                     }
+                    delivered.set(received.get());
+                    forward(Event::DownloadDataReceived(&data[seen..]))
+                }
+                _ => forward(event),
+            }),
+        )
+        .await;
 
-                    downloaded_so_far
-                } else {
-                    let file_info = partial.metadata()?;
-                    file_info.len()
+        let err = match attempt {
+            Ok(()) => break Ok(()),
+            Err(err) if retries < retry.attempts && is_transient(&err) => err,
+            Err(err) => break Err(err),
+        };
+        let delay = retry.delay(retries, &err);
+        if let Err(err) = forward(Event::Retrying(delay)) {
+            break Err(err);
+        }
+        tokio::time::sleep(delay).await;
+        resume = ranges.get()
+            && !matches!(
+                err.downcast_ref::<DownloadError>(),
+                Some(DownloadError::ResumeNotSupported)
+            );
+        retries += 1;
+    };
+
+    let Err(err) = result else {
+        return Ok(());
+    };
+    // TODO: We currently clear up the cached download on any error, should we restrict it to a subset?
+    Err(
+        if let Err(file_err) = remove_file(path).context("cleaning up cached downloads") {
+            file_err.context(err)
+        } else {
+            err
+        },
+    )
+}
+
+async fn download_impl(
+    transport: &dyn Transport,
+    url: &Url,
+    path: &Path,
+    resume_from_partial: bool,
+    callback: Option<DownloadCallback<'_>>,
+) -> Result<()> {
+    use std::cell::{Cell, RefCell};
+    use std::fs::OpenOptions;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let (file, resume_from) = if resume_from_partial {
+        // TODO: blocking call
+        let possible_partial = OpenOptions::new().read(true).open(path);
+
+        let downloaded_so_far = if let Ok(mut partial) = possible_partial {
+            if let Some(cb) = callback {
+                cb(Event::ResumingPartialDownload)?;
+
+                let mut buf = vec![0; 32768];
+                let mut downloaded_so_far = 0;
+                loop {
+                    let n = partial.read(&mut buf)?;
+                    downloaded_so_far += n as u64;
+                    if n == 0 {
+                        break;
+                    }
+                    cb(Event::DownloadDataReceived(&buf[..n]))?;
                 }
+
+                downloaded_so_far
             } else {
-                0
-            };
+                let file_info = partial.metadata()?;
+                file_info.len()
+            }
+        } else {
+            0
+        };
+
+        // TODO: blocking call
+        let mut possible_partial = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .context("error opening file for download")?;
 
-            // TODO: blocking call
-            let mut possible_partial = OpenOptions::new()
+        possible_partial.seek(SeekFrom::End(0))?;
+
+        (possible_partial, downloaded_so_far)
+    } else {
+        (
+            OpenOptions::new()
                 .write(true)
                 .create(true)
-                .truncate(false)
+                .truncate(true)
                 .open(path)
-                .context("error opening file for download")?;
-
-            possible_partial.seek(SeekFrom::End(0))?;
-
-            (possible_partial, downloaded_so_far)
-        } else {
-            (
-                OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .open(path)
-                    .context("error creating file for download")?,
-                0,
-            )
-        };
+                .context("error creating file for download")?,
+            0,
+        )
+    };
 
-        let file = RefCell::new(file);
+    let file = RefCell::new(file);
+    let expected = Cell::new(None);
+    let received = Cell::new(resume_from);
 
-        // TODO: the sync callback will stall the async runtime if IO calls block, which is OS dependent. Rearrange.
-        self.download(url, resume_from, &|event| {
-            if let Event::DownloadDataReceived(data) = event {
-                file.borrow_mut()
-                    .write_all(data)
-                    .context("unable to write download to disk")?;
+    // TODO: the sync callback will stall the async runtime if IO calls block, which is OS dependent. Rearrange.
+    transport
+        .download(url, resume_from, &|event| {
+            match event {
+                // A server that ignores the range sends the whole file, which
+                // must not be appended to the partial download.
+                Event::ResponseReceived(res) if resume_from > 0 && res.status == 200 => {
+                    return Err(DownloadError::ResumeNotSupported.into());
+                }
+                Event::DownloadContentLengthReceived(len) => expected.set(Some(len)),
+                Event::DownloadDataReceived(data) => {
+                    received.set(received.get() + data.len() as u64);
+                    file.borrow_mut()
+                        .write_all(data)
+                        .context("unable to write download to disk")?;
+                }
+                _ => {}
             }
             match callback {
                 Some(cb) => cb(event),
@@ -135,34 +643,21 @@ impl Backend {
         })
         .await?;
 
-        file.borrow_mut()
-            .sync_data()
-            .context("unable to sync download to disk")?;
-
-        Ok::<(), anyhow::Error>(())
-    }
-
-    #[cfg_attr(
-        all(
-            not(feature = "curl-backend"),
-            not(feature = "reqwest-rustls-tls"),
-            not(feature = "reqwest-native-tls")
-        ),
-        allow(unused_variables)
-    )]
-    async fn download(
-        self,
-        url: &Url,
-        resume_from: u64,
-        callback: DownloadCallback<'_>,
-    ) -> Result<()> {
-        match self {
-            #[cfg(feature = "curl-backend")]
-            Self::Curl => curl::download(url, resume_from, callback),
-            #[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
-            Self::Reqwest(tls) => tls.download(url, resume_from, callback).await,
+    if let Some(expected) = expected.get() {
+        if received.get() != expected {
+            return Err(DownloadError::Incomplete {
+                expected,
+                received: received.get(),
+            }
+            .into());
         }
     }
+
+    file.borrow_mut()
+        .sync_data()
+        .context("unable to sync download to disk")?;
+
+    Ok::<(), anyhow::Error>(())
 }
 
 #[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
@@ -180,49 +675,153 @@ impl TlsBackend {
         self,
         url: &Url,
         resume_from: u64,
+        headers: &[(String, String)],
         callback: DownloadCallback<'_>,
     ) -> Result<()> {
-        let client = match self {
+        let (client, user_agent): (fn(&Url) -> Result<_>, _) = match self {
             #[cfg(feature = "reqwest-rustls-tls")]
-            Self::Rustls => &reqwest_be::CLIENT_RUSTLS_TLS,
+            Self::Rustls => (reqwest_be::client_rustls_tls, REQWEST_RUSTLS_TLS_USER_AGENT),
             #[cfg(feature = "reqwest-native-tls")]
-            Self::NativeTls => &reqwest_be::CLIENT_NATIVE_TLS,
+            Self::NativeTls => (
+                reqwest_be::client_native_tls,
+                REQWEST_DEFAULT_TLS_USER_AGENT,
+            ),
         };
 
-        reqwest_be::download(url, resume_from, callback, client).await
+        reqwest_be::download(url, resume_from, headers, callback, client, user_agent).await
     }
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum Event<'a> {
     ResumingPartialDownload,
+    /// Received the status and headers of an HTTP response.
+    ResponseReceived(Response<'a>),
     /// Received the Content-Length of the to-be downloaded data.
     DownloadContentLengthReceived(u64),
     /// Received some data.
     DownloadDataReceived(&'a [u8]),
+    /// About to connect to a server whose certificate isn't verified, as its
+    /// TLS settings say.
+    InsecureTls(&'a str),
+    /// The download failed in a way that may not happen again, and is retried
+    /// after waiting this long.
+    Retrying(Duration),
 }
 
-type DownloadCallback<'a> = &'a dyn Fn(Event<'_>) -> Result<()>;
+/// The headers of an HTTP request and its response, for tracing.
+#[derive(Debug, Copy, Clone)]
+pub struct Response<'a> {
+    pub http_version: &'a str,
+    pub status: u32,
+    pub request_headers: &'a [(String, String)],
+    pub headers: &'a [(String, String)],
+}
+
+/// Returns the headers set by rustup on a request, besides those added by the
+/// backend itself. Credentials among the `extra` headers are redacted.
+#[cfg(any(
+    feature = "curl-backend",
+    feature = "reqwest-rustls-tls",
+    feature = "reqwest-native-tls"
+))]
+fn request_headers(
+    user_agent: &str,
+    resume_from: u64,
+    extra: &[(String, String)],
+) -> Vec<(String, String)> {
+    let mut headers = vec![("User-Agent".to_owned(), user_agent.to_owned())];
+    if resume_from > 0 {
+        headers.push(("Range".to_owned(), format!("bytes={resume_from}-")));
+    }
+    for (name, value) in extra {
+        let value = match name.to_ascii_lowercase().as_str() {
+            "authorization" | "x-amz-security-token" => "<redacted>".to_owned(),
+            _ => value.clone(),
+        };
+        headers.push((name.clone(), value));
+    }
+    headers
+}
+
+pub type DownloadCallback<'a> = &'a dyn Fn(Event<'_>) -> Result<()>;
+
+/// Returns the error for an unsuccessful status code, which is
+/// [`DownloadError::RateLimited`] for 429 responses and for 503 responses
+/// that say when to retry.
+fn status_error(code: u32, retry_after: Option<&str>) -> DownloadError {
+    let retry_after = retry_after.and_then(parse_retry_after);
+    match (code, retry_after) {
+        (429, _) | (503, Some(_)) => DownloadError::RateLimited {
+            status: code,
+            retry_after,
+        },
+        _ => DownloadError::HttpStatus(code),
+    }
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an
+/// HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
 
 /// Download via libcurl; encrypt with the native (or OpenSSl) TLS
 /// stack via libcurl
 #[cfg(feature = "curl-backend")]
 pub mod curl {
-    use std::cell::RefCell;
+    use std::cell::{Cell, RefCell};
     use std::str;
-    use std::time::Duration;
 
-    use anyhow::{Context, Result};
-    use curl::easy::Easy;
+    use anyhow::{bail, Context, Result};
+    use curl::easy::{Easy, List};
     use url::Url;
 
     use super::{DownloadError, Event};
 
+    /// Whether `err` is a failure to connect or to keep a connection, which
+    /// may not happen again.
+    pub(super) fn is_transient(err: &curl::Error) -> bool {
+        err.is_couldnt_connect()
+            || err.is_operation_timedout()
+            || err.is_send_error()
+            || err.is_recv_error()
+            || err.is_partial_file()
+            || err.is_got_nothing()
+    }
+
+    /// Downloads `url`, following redirects here rather than in libcurl so
+    /// that each server only gets the credentials meant for it.
     pub fn download(
         url: &Url,
         resume_from: u64,
+        headers: &[(String, String)],
         callback: &dyn Fn(Event<'_>) -> Result<()>,
     ) -> Result<()> {
+        let mut url = url.clone();
+        let mut headers = headers.to_vec();
+        for _ in 0..=super::MAX_REDIRECTS {
+            let Some(next) = fetch(&url, resume_from, &headers, callback)? else {
+                return Ok(());
+            };
+            headers = super::redirect_headers(&url, &headers, &next);
+            url = next;
+        }
+        bail!("too many redirects downloading '{url}'")
+    }
+
+    /// Makes one request, returning where it was redirected to, if it was.
+    fn fetch(
+        url: &Url,
+        resume_from: u64,
+        headers: &[(String, String)],
+        callback: &dyn Fn(Event<'_>) -> Result<()>,
+    ) -> Result<Option<Url>> {
         // Fetch either a cached libcurl handle (which will preserve open
         // connections) or create a new one if it isn't listed.
         //
@@ -233,9 +832,23 @@ pub mod curl {
             let mut handle = handle.borrow_mut();
 
             handle.url(url.as_ref())?;
-            handle.follow_location(true)?;
+            handle.follow_location(false)?;
             handle.useragent(super::CURL_USER_AGENT)?;
 
+            // The handle is reused, so the list replaces any headers of the
+            // previous download.
+            let mut list = List::new();
+            for (name, value) in headers {
+                list.append(&format!("{name}: {value}"))?;
+            }
+            handle.http_headers(list)?;
+
+            // Use the same proxy settings as reqwest rather than letting
+            // libcurl read the environment itself. An empty proxy disables
+            // proxying.
+            let proxy = env_proxy::for_url(url).to_url();
+            handle.proxy(proxy.as_ref().map_or("", |proxy| proxy.as_str()))?;
+
             if resume_from > 0 {
                 handle.resume_from(resume_from)?;
             } else {
@@ -244,9 +857,16 @@ pub mod curl {
                 let _ = handle.resume_from(0);
             }
 
-            // Take at most 30s to connect
-            handle.connect_timeout(Duration::new(30, 0))?;
+            handle.connect_timeout(super::CONNECT_TIMEOUT)?;
+            // Give up once less than a byte per second arrives for the whole
+            // read timeout.
+            handle.low_speed_limit(1)?;
+            handle.low_speed_time(super::READ_TIMEOUT)?;
 
+            let retry_after = RefCell::new(None);
+            let http_version = RefCell::new(String::new());
+            let status = Cell::new(0);
+            let response_headers = RefCell::new(Vec::new());
             {
                 let cberr = RefCell::new(None);
                 let mut transfer = handle.transfer();
@@ -255,6 +875,10 @@ pub mod curl {
                 // downloaded. We just feed it into our hasher and also write it out
                 // to disk.
                 transfer.write_function(|data| {
+                    // The body of a redirect is not the file.
+                    if (300..400).contains(&status.get()) {
+                        return Ok(data.len());
+                    }
                     match callback(Event::DownloadDataReceived(data)) {
                         Ok(()) => Ok(data.len()),
                         Err(e) => {
@@ -265,24 +889,72 @@ pub mod curl {
                 })?;
 
                 // Listen for headers and parse out a `Content-Length` (case-insensitive) if it
-                // comes so we know how much we're downloading.
+                // comes so we know how much we're downloading. Only the headers
+                // of the final response count when following redirects.
                 transfer.header_function(|header| {
-                    if let Ok(data) = str::from_utf8(header) {
+                    let Ok(data) = str::from_utf8(header) else {
+                        return true;
+                    };
+                    let final_response = matches!(status.get(), 200..=299 | 400..);
+
+                    let event = if data.starts_with("HTTP/") {
+                        let mut status_line = data.split(' ');
+                        let version = status_line.next().unwrap_or_default();
+                        *http_version.borrow_mut() = version.to_owned();
+                        status.set(status_line.next().and_then(|s| s.parse().ok()).unwrap_or(0));
+                        response_headers.borrow_mut().clear();
+                        None
+                    } else if data.trim().is_empty() {
+                        final_response.then(|| {
+                            callback(Event::ResponseReceived(super::Response {
+                                http_version: &http_version.borrow(),
+                                status: status.get(),
+                                request_headers: &super::request_headers(
+                                    super::CURL_USER_AGENT,
+                                    resume_from,
+                                    headers,
+                                ),
+                                headers: &response_headers.borrow(),
+                            }))
+                        })
+                    } else {
+                        if let Some((name, value)) = data.split_once(':') {
+                            response_headers
+                                .borrow_mut()
+                                .push((name.to_owned(), value.trim().to_owned()));
+                        }
+
+                        let retry_prefix = "retry-after:";
+                        if data.to_ascii_lowercase().starts_with(retry_prefix) {
+                            *retry_after.borrow_mut() =
+                                Some(data[retry_prefix.len()..].trim().to_owned());
+                        }
+
+                        // Files report their whole length, even when resuming.
                         let prefix = "content-length: ";
-                        if data.to_ascii_lowercase().starts_with(prefix) {
-                            if let Ok(s) = data[prefix.len()..].trim().parse::<u64>() {
-                                let msg = Event::DownloadContentLengthReceived(s + resume_from);
-                                match callback(msg) {
-                                    Ok(()) => (),
-                                    Err(e) => {
-                                        *cberr.borrow_mut() = Some(e);
-                                        return false;
-                                    }
-                                }
+                        let len = data
+                            .to_ascii_lowercase()
+                            .strip_prefix(prefix)
+                            .and_then(|len| len.trim().parse::<u64>().ok())
+                            .map(|len| match url.scheme() {
+                                "file" => len,
+                                _ => len + resume_from,
+                            });
+                        match len {
+                            Some(len) if final_response || url.scheme() == "file" => {
+                                Some(callback(Event::DownloadContentLengthReceived(len)))
                             }
+                            _ => None,
                         }
+                    };
+
+                    match event {
+                        Some(Err(e)) => {
+                            *cberr.borrow_mut() = Some(e);
+                            false
+                        }
+                        _ => true,
                     }
-                    true
                 })?;
 
                 // If an error happens check to see if we had a filesystem error up
@@ -304,16 +976,22 @@ pub mod curl {
                 })?;
             }
 
-            // If we didn't get a 20x or 0 ("OK" for files) then return an error
             let code = handle.response_code()?;
+            if (300..400).contains(&code) {
+                if let Some(next) = handle.redirect_url()? {
+                    return Ok(Some(Url::parse(next)?));
+                }
+            }
+
+            // If we didn't get a 20x or 0 ("OK" for files) then return an error
             match code {
                 0 | 200..=299 => {}
                 _ => {
-                    return Err(DownloadError::HttpStatus(code).into());
+                    return Err(super::status_error(code, retry_after.borrow().as_deref()).into());
                 }
             };
 
-            Ok(())
+            Ok(None)
         })
     }
 }
@@ -323,39 +1001,95 @@ pub mod reqwest_be {
     use std::io;
     #[cfg(feature = "reqwest-rustls-tls")]
     use std::sync::Arc;
-    #[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
-    use std::sync::LazyLock;
-    use std::time::Duration;
+    use std::sync::{Mutex, PoisonError};
 
-    use anyhow::{anyhow, Context, Result};
-    use reqwest::{header, Client, ClientBuilder, Proxy, Response};
+    use anyhow::{anyhow, bail, Context, Result};
+    #[cfg(feature = "reqwest-native-tls")]
+    use reqwest::Certificate;
+    use reqwest::{header, redirect::Policy, Client, ClientBuilder, Proxy, Response};
+    #[cfg(feature = "reqwest-rustls-tls")]
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    #[cfg(feature = "reqwest-rustls-tls")]
+    use rustls::client::WebPkiServerVerifier;
+    #[cfg(feature = "reqwest-rustls-tls")]
+    use rustls::crypto::{aws_lc_rs, CryptoProvider};
     #[cfg(feature = "reqwest-rustls-tls")]
-    use rustls::crypto::aws_lc_rs;
+    use rustls::pki_types::{ServerName, UnixTime};
     #[cfg(feature = "reqwest-rustls-tls")]
-    use rustls_platform_verifier::BuilderVerifierExt;
+    use rustls::{DigitallySignedStruct, SignatureScheme};
+    #[cfg(feature = "reqwest-rustls-tls")]
+    use rustls_pki_types::CertificateDer;
+    #[cfg(feature = "reqwest-rustls-tls")]
+    use rustls_platform_verifier::Verifier;
     use tokio_stream::StreamExt;
     use url::Url;
 
-    use super::{DownloadError, Event};
+    use super::{read_roots, DownloadError, Event, TlsConfig, TlsHost, TlsRoots};
 
+    /// Downloads `url` with the client that `client` returns for each server
+    /// on the way, so that each is connected to with its own TLS settings.
     pub async fn download(
         url: &Url,
         resume_from: u64,
+        headers: &[(String, String)],
         callback: &dyn Fn(Event<'_>) -> Result<()>,
-        client: &Client,
+        client: fn(&Url) -> Result<Client>,
+        user_agent: &str,
     ) -> Result<()> {
         // Short-circuit reqwest for the "file:" URL scheme
         if download_from_file_url(url, resume_from, callback)? {
             return Ok(());
         }
 
-        let res = request(url, resume_from, client)
-            .await
-            .context("failed to make network request")?;
+        let mut url = url.clone();
+        let mut headers = headers.to_vec();
+        let mut redirects = 0;
+        let res = loop {
+            warn_if_insecure(&url, callback)?;
+            let res = request(&url, resume_from, &headers, &client(&url)?)
+                .await
+                .context("failed to make network request")?;
+            // Redirects are followed here rather than by the client, so that
+            // each server is connected to with its own TLS settings and only
+            // gets the credentials meant for it.
+            let location = res
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|location| location.to_str().ok());
+            let next = match location {
+                Some(location) if res.status().is_redirection() => url.join(location)?,
+                _ => break res,
+            };
+            redirects += 1;
+            if redirects > super::MAX_REDIRECTS {
+                bail!("too many redirects downloading '{url}'");
+            }
+            headers = super::redirect_headers(&url, &headers, &next);
+            url = next;
+        };
+
+        let response_headers = res
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                (name.to_string(), value)
+            })
+            .collect::<Vec<_>>();
+        callback(Event::ResponseReceived(super::Response {
+            http_version: &format!("{:?}", res.version()),
+            status: res.status().as_u16().into(),
+            request_headers: &super::request_headers(user_agent, resume_from, &headers),
+            headers: &response_headers,
+        }))?;
 
         if !res.status().is_success() {
             let code: u16 = res.status().into();
-            return Err(anyhow!(DownloadError::HttpStatus(u32::from(code))));
+            let retry_after = res
+                .headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok());
+            return Err(anyhow!(super::status_error(u32::from(code), retry_after)));
         }
 
         if let Some(len) = res.content_length() {
@@ -371,6 +1105,12 @@ pub mod reqwest_be {
         Ok(())
     }
 
+    /// Whether `err` is a failure to connect or to keep a connection, which
+    /// may not happen again.
+    pub(super) fn is_transient(err: &reqwest::Error) -> bool {
+        err.is_connect() || err.is_timeout() || err.is_request() || err.is_body()
+    }
+
     fn client_generic() -> ClientBuilder {
         Client::builder()
             // HACK: set `pool_max_idle_per_host` to `0` to avoid an issue in the underlying
@@ -379,51 +1119,277 @@ pub mod reqwest_be {
             .pool_max_idle_per_host(0)
             .gzip(false)
             .proxy(Proxy::custom(env_proxy))
-            .timeout(Duration::from_secs(30))
+            // `download` follows redirects itself.
+            .redirect(Policy::none())
+            .connect_timeout(super::CONNECT_TIMEOUT)
+            .read_timeout(super::READ_TIMEOUT)
+    }
+
+    /// The clients built so far, along with the TLS settings each was
+    /// built for so that they are only rebuilt when those change.
+    type CachedClients = Mutex<Vec<(TlsConfig, Client)>>;
+
+    fn cached_client(
+        cache: &CachedClients,
+        url: &Url,
+        build: impl FnOnce(&TlsConfig) -> Result<Client>,
+    ) -> Result<Client> {
+        let tls = TlsConfig::for_host(url.host_str());
+        let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, client)) = cache.iter().find(|(cached, _)| *cached == tls) {
+            return Ok(client.clone());
+        }
+        let client = build(&tls)?;
+        cache.push((tls, client.clone()));
+        Ok(client)
+    }
+
+    fn warn_if_insecure(url: &Url, callback: &dyn Fn(Event<'_>) -> Result<()>) -> Result<()> {
+        match super::tls_host(url.host_str()) {
+            Some((host, TlsHost { insecure: true, .. })) => callback(Event::InsecureTls(&host)),
+            _ => Ok(()),
+        }
     }
 
     #[cfg(feature = "reqwest-rustls-tls")]
-    pub(super) static CLIENT_RUSTLS_TLS: LazyLock<Client> = LazyLock::new(|| {
-        let catcher = || {
-            client_generic()
-                .use_preconfigured_tls(
-                    rustls::ClientConfig::builder_with_provider(Arc::new(
-                        aws_lc_rs::default_provider(),
-                    ))
-                    .with_safe_default_protocol_versions()
-                    .unwrap()
-                    .with_platform_verifier()
-                    .with_no_client_auth(),
-                )
+    pub(super) fn client_rustls_tls(url: &Url) -> Result<Client> {
+        static CLIENTS: CachedClients = Mutex::new(Vec::new());
+        cached_client(&CLIENTS, url, |tls| {
+            let provider = Arc::new(aws_lc_rs::default_provider());
+            let config = rustls::ClientConfig::builder_with_provider(provider.clone())
+                .with_safe_default_protocol_versions()?
+                .dangerous()
+                .with_custom_certificate_verifier(rustls_verifier(tls, &provider)?)
+                .with_no_client_auth();
+            Ok(client_generic()
+                .use_preconfigured_tls(config)
                 .user_agent(super::REQWEST_RUSTLS_TLS_USER_AGENT)
-                .build()
+                .build()?)
+        })
+    }
+
+    /// The verifier of server certificates for the given settings.
+    #[cfg(feature = "reqwest-rustls-tls")]
+    pub(super) fn rustls_verifier(
+        tls: &TlsConfig,
+        provider: &Arc<CryptoProvider>,
+    ) -> Result<Arc<dyn ServerCertVerifier>> {
+        let platform = || Arc::new(Verifier::new().with_provider(provider.clone()));
+        let verifier: Arc<dyn ServerCertVerifier> = match &tls.roots {
+            TlsRoots::Platform => platform(),
+            TlsRoots::Bundled => Arc::new(Fallback {
+                primary: platform(),
+                fallback: webpki_verifier(
+                    webpki_root_certs::TLS_SERVER_ROOT_CERTS.iter().cloned(),
+                    provider,
+                )?,
+                scope: FallbackScope::RustLang,
+            }),
+            TlsRoots::File(path) => webpki_verifier(
+                read_roots(path)?.into_iter().map(CertificateDer::from),
+                provider,
+            )?,
         };
+        Ok(match &tls.host {
+            Some((_, TlsHost { insecure: true, .. })) => Arc::new(NoVerification(provider.clone())),
+            Some((
+                host,
+                TlsHost {
+                    roots: Some(path), ..
+                },
+            )) => Arc::new(Fallback {
+                primary: verifier,
+                fallback: webpki_verifier(
+                    read_roots(path)?.into_iter().map(CertificateDer::from),
+                    provider,
+                )?,
+                scope: FallbackScope::Host(host.clone()),
+            }),
+            _ => verifier,
+        })
+    }
 
-        // woah, an unwrap?!
-        // It's OK. This is the same as what is happening in curl.
-        //
-        // The curl::Easy::new() internally assert!s that the initialized
-        // Easy is not null. Inside reqwest, the errors here would be from
-        // the TLS library returning a null pointer as well.
-        catcher().unwrap()
-    });
+    #[cfg(feature = "reqwest-rustls-tls")]
+    fn webpki_verifier(
+        roots: impl IntoIterator<Item = CertificateDer<'static>>,
+        provider: &Arc<CryptoProvider>,
+    ) -> Result<Arc<WebPkiServerVerifier>> {
+        let mut store = rustls::RootCertStore::empty();
+        store.add_parsable_certificates(roots);
+        Ok(WebPkiServerVerifier::builder_with_provider(store.into(), provider.clone()).build()?)
+    }
 
-    #[cfg(feature = "reqwest-native-tls")]
-    pub(super) static CLIENT_NATIVE_TLS: LazyLock<Client> = LazyLock::new(|| {
-        let catcher = || {
-            client_generic()
-                .user_agent(super::REQWEST_DEFAULT_TLS_USER_AGENT)
-                .build()
-        };
+    /// Verifies certificates with the `primary` verifier, and those of the
+    /// servers in `scope` with other roots if that fails: the bundled roots
+    /// for rust-lang.org, so that a broken certificate store doesn't keep
+    /// rustup from updating itself, or the extra roots of a single server.
+    #[cfg(feature = "reqwest-rustls-tls")]
+    #[derive(Debug)]
+    struct Fallback {
+        primary: Arc<dyn ServerCertVerifier>,
+        fallback: Arc<WebPkiServerVerifier>,
+        scope: FallbackScope,
+    }
 
-        // woah, an unwrap?!
-        // It's OK. This is the same as what is happening in curl.
-        //
-        // The curl::Easy::new() internally assert!s that the initialized
-        // Easy is not null. Inside reqwest, the errors here would be from
-        // the TLS library returning a null pointer as well.
-        catcher().unwrap()
-    });
+    #[cfg(feature = "reqwest-rustls-tls")]
+    #[derive(Debug)]
+    enum FallbackScope {
+        RustLang,
+        Host(String),
+    }
+
+    #[cfg(feature = "reqwest-rustls-tls")]
+    impl FallbackScope {
+        fn contains(&self, name: &ServerName<'_>) -> bool {
+            match (self, name) {
+                (Self::RustLang, name) => is_rust_lang(name),
+                (Self::Host(host), ServerName::DnsName(name)) => name
+                    .as_ref()
+                    .trim_end_matches('.')
+                    .eq_ignore_ascii_case(host),
+                (Self::Host(host), ServerName::IpAddress(ip)) => {
+                    host.parse::<std::net::IpAddr>().ok() == Some((*ip).into())
+                }
+                (Self::Host(_), _) => false,
+            }
+        }
+    }
+
+    #[cfg(feature = "reqwest-rustls-tls")]
+    impl ServerCertVerifier for Fallback {
+        fn verify_server_cert(
+            &self,
+            end_entity: &CertificateDer<'_>,
+            intermediates: &[CertificateDer<'_>],
+            server_name: &ServerName<'_>,
+            ocsp_response: &[u8],
+            now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            let result = self.primary.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                now,
+            );
+            match result {
+                Err(e) if self.scope.contains(server_name) => self
+                    .fallback
+                    .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+                    // The primary verifier's reason is the one worth reporting.
+                    .map_err(|_| e),
+                result => result,
+            }
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            self.primary.verify_tls12_signature(message, cert, dss)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            self.primary.verify_tls13_signature(message, cert, dss)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.primary.supported_verify_schemes()
+        }
+    }
+
+    /// Accepts any certificate, for a server whose settings say so. The
+    /// handshake signatures are still checked against the certificate.
+    #[cfg(feature = "reqwest-rustls-tls")]
+    #[derive(Debug)]
+    struct NoVerification(Arc<CryptoProvider>);
+
+    #[cfg(feature = "reqwest-rustls-tls")]
+    impl ServerCertVerifier for NoVerification {
+        fn verify_server_cert(
+            &self,
+            _: &CertificateDer<'_>,
+            _: &[CertificateDer<'_>],
+            _: &ServerName<'_>,
+            _: &[u8],
+            _: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            rustls::crypto::verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            rustls::crypto::verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+
+    /// Whether `name` is rust-lang.org or one of its subdomains.
+    #[cfg(feature = "reqwest-rustls-tls")]
+    pub(super) fn is_rust_lang(name: &ServerName<'_>) -> bool {
+        match name {
+            ServerName::DnsName(name) => super::is_rust_lang_host(name.as_ref()),
+            _ => false,
+        }
+    }
+
+    #[cfg(feature = "reqwest-native-tls")]
+    pub(super) fn client_native_tls(url: &Url) -> Result<Client> {
+        static CLIENTS: CachedClients = Mutex::new(Vec::new());
+        cached_client(&CLIENTS, url, |tls| {
+            let mut builder = client_generic().user_agent(super::REQWEST_DEFAULT_TLS_USER_AGENT);
+            if let TlsRoots::File(path) = &tls.roots {
+                builder = builder.tls_built_in_root_certs(false);
+                for root in read_roots(path)? {
+                    builder = builder.add_root_certificate(Certificate::from_der(&root)?);
+                }
+            }
+            // The client only connects to this server, as redirects to
+            // others are followed with their own clients.
+            if let Some((_, host)) = &tls.host {
+                if let Some(path) = &host.roots {
+                    for root in read_roots(path)? {
+                        builder = builder.add_root_certificate(Certificate::from_der(&root)?);
+                    }
+                }
+                builder = builder.danger_accept_invalid_certs(host.insecure);
+            }
+            Ok(builder.build()?)
+        })
+    }
 
     fn env_proxy(url: &Url) -> Option<Url> {
         env_proxy::for_url(url).to_url()
@@ -432,6 +1398,7 @@ pub mod reqwest_be {
     async fn request(
         url: &Url,
         resume_from: u64,
+        headers: &[(String, String)],
         client: &Client,
     ) -> Result<Response, DownloadError> {
         let mut req = client.get(url.as_str());
@@ -439,6 +1406,9 @@ pub mod reqwest_be {
         if resume_from != 0 {
             req = req.header(header::RANGE, format!("bytes={resume_from}-"));
         }
+        for (name, value) in headers {
+            req = req.header(name, value);
+        }
 
         Ok(req.send().await?)
     }
@@ -480,12 +1450,75 @@ pub mod reqwest_be {
             Ok(false)
         }
     }
+
+    #[cfg(all(test, feature = "reqwest-rustls-tls"))]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn extra_roots_only_for_their_host() {
+            let name = |s: &str| ServerName::try_from(s.to_owned()).unwrap();
+            let scope = FallbackScope::Host("mirror.example.com".to_owned());
+            assert!(scope.contains(&name("mirror.example.com")));
+            assert!(scope.contains(&name("Mirror.Example.com.")));
+            assert!(!scope.contains(&name("other.example.com")));
+            assert!(!scope.contains(&name("a.mirror.example.com")));
+
+            let scope = FallbackScope::Host("10.0.0.5".to_owned());
+            assert!(scope.contains(&name("10.0.0.5")));
+            assert!(!scope.contains(&name("10.0.0.6")));
+        }
+
+        #[test]
+        fn bundled_roots_only_for_rust_lang() {
+            let name = |s: &str| ServerName::try_from(s.to_owned()).unwrap();
+            assert!(is_rust_lang(&name("static.rust-lang.org")));
+            assert!(is_rust_lang(&name("Static.Rust-Lang.org.")));
+            assert!(is_rust_lang(&name("rust-lang.org")));
+            assert!(!is_rust_lang(&name("rust-lang.org.example.com")));
+            assert!(!is_rust_lang(&name("evilrust-lang.org")));
+            assert!(!is_rust_lang(&name("127.0.0.1")));
+        }
+
+        #[test]
+        fn rust_lang_is_verified_whatever_its_settings() {
+            let insecure = TlsHost {
+                roots: None,
+                insecure: true,
+            };
+            super::super::set_tls_hosts(
+                [
+                    ("static.rust-lang.org".to_owned(), insecure.clone()),
+                    ("mirror.example.com".to_owned(), insecure),
+                ]
+                .into(),
+            );
+            let provider = Arc::new(aws_lc_rs::default_provider());
+            let unverified = |host| {
+                let verifier = rustls_verifier(&TlsConfig::for_host(Some(host)), &provider);
+                format!("{:?}", verifier.unwrap()).starts_with("NoVerification")
+            };
+            assert!(!unverified("static.rust-lang.org"));
+            assert!(!unverified("Static.Rust-Lang.org."));
+            assert!(unverified("mirror.example.com"));
+        }
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum DownloadError {
     #[error("http request returned an unsuccessful status code: {0}")]
     HttpStatus(u32),
+    #[error("http request was rate limited by the server with status code {status}")]
+    RateLimited {
+        status: u32,
+        /// How long the server asked to wait before retrying.
+        retry_after: Option<Duration>,
+    },
+    #[error("server does not support resuming downloads")]
+    ResumeNotSupported,
+    #[error("download ended after {received} of {expected} bytes")]
+    Incomplete { expected: u64, received: u64 },
     #[error("file not found")]
     FileNotFound,
     #[error("download backend '{0}' unavailable")]
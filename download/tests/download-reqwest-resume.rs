@@ -8,7 +8,7 @@ use url::Url;
 use download::*;
 
 mod support;
-use crate::support::{serve_file, tmp_dir, write_file};
+use crate::support::{check_conformance, serve_file, serve_redirect, tmp_dir, write_file};
 
 #[tokio::test]
 async fn resume_partial_from_file_url() {
@@ -40,6 +40,7 @@ async fn callback_gets_all_data_as_if_the_download_happened_all_at_once() {
 
     let callback_partial = AtomicBool::new(false);
     let callback_len = Mutex::new(None);
+    let callback_status = Mutex::new(None);
     let received_in_callback = Mutex::new(Vec::new());
 
     Backend::Reqwest(TlsBackend::NativeTls)
@@ -53,6 +54,15 @@ async fn callback_gets_all_data_as_if_the_download_happened_all_at_once() {
                         assert!(!callback_partial.load(Ordering::SeqCst));
                         callback_partial.store(true, Ordering::SeqCst);
                     }
+                    Event::ResponseReceived(res) => {
+                        let range = ("Range".to_owned(), "bytes=3-".to_owned());
+                        assert!(res.request_headers.contains(&range));
+                        assert!(res
+                            .headers
+                            .iter()
+                            .any(|(name, _)| name.eq_ignore_ascii_case("content-range")));
+                        *callback_status.lock().unwrap() = Some(res.status);
+                    }
                     Event::DownloadContentLengthReceived(len) => {
                         let mut flag = callback_len.lock().unwrap();
                         assert!(flag.is_none());
@@ -63,6 +73,8 @@ async fn callback_gets_all_data_as_if_the_download_happened_all_at_once() {
                             received_in_callback.lock().unwrap().push(*b);
                         }
                     }
+                    Event::InsecureTls(_) => panic!("no server is insecure"),
+                    Event::Retrying(_) => panic!("the download does not fail"),
                 }
 
                 Ok(())
@@ -72,8 +84,52 @@ async fn callback_gets_all_data_as_if_the_download_happened_all_at_once() {
         .expect("Test download failed");
 
     assert!(callback_partial.into_inner());
+    assert_eq!(*callback_status.lock().unwrap(), Some(206));
     assert_eq!(*callback_len.lock().unwrap(), Some(5));
     let observed_bytes = received_in_callback.into_inner().unwrap();
     assert_eq!(observed_bytes, vec![b'1', b'2', b'3', b'4', b'5']);
     assert_eq!(std::fs::read_to_string(&target_path).unwrap(), "12345");
 }
+
+#[tokio::test]
+async fn conformance() {
+    check_conformance(Backend::Reqwest(TlsBackend::NativeTls)).await;
+}
+
+#[tokio::test]
+async fn redirect_to_server_with_tls_settings() {
+    let tmpdir = tmp_dir();
+    let target_path = tmpdir.path().join("downloaded");
+    // Other tests connect to 127.0.0.1, so only `localhost` gets settings.
+    set_tls_hosts(
+        [(
+            "localhost".to_owned(),
+            TlsHost {
+                roots: None,
+                insecure: true,
+            },
+        )]
+        .into(),
+    );
+    let file = serve_file(b"12345".to_vec());
+    let addr = serve_redirect(format!("http://localhost:{}/", file.port()));
+
+    let insecure = Mutex::new(Vec::new());
+    let url = format!("http://{addr}/").parse().unwrap();
+    Backend::Reqwest(TlsBackend::NativeTls)
+        .download_to_path(
+            &url,
+            &target_path,
+            false,
+            Some(&|msg| {
+                if let Event::InsecureTls(host) = msg {
+                    insecure.lock().unwrap().push(host.to_owned());
+                }
+                Ok(())
+            }),
+        )
+        .await
+        .expect("the redirect should be followed with the settings of its server");
+    assert_eq!(std::fs::read_to_string(&target_path).unwrap(), "12345");
+    assert_eq!(insecure.into_inner().unwrap(), ["localhost"]);
+}
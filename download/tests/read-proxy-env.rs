@@ -8,6 +8,7 @@ use std::sync::LazyLock;
 use std::thread;
 use std::time::Duration;
 
+use download::{Backend, TlsBackend};
 use env_proxy::for_url;
 use reqwest::{Client, Proxy};
 use tokio::sync::Mutex;
@@ -82,3 +83,36 @@ async fn socks_proxy_request() {
         panic!("Socks proxy was ignored")
     }
 }
+
+// Tests that every backend sends its requests through the proxy from the environment
+#[tokio::test]
+async fn backends_use_env_proxy() {
+    let _guard = SERIALISE_TESTS.lock().await;
+    let tmpdir = tempfile::tempdir().unwrap();
+
+    let backends = [
+        #[cfg(feature = "curl-backend")]
+        Backend::Curl,
+        #[cfg(feature = "reqwest-rustls-tls")]
+        Backend::Reqwest(TlsBackend::Rustls),
+        #[cfg(feature = "reqwest-native-tls")]
+        Backend::Reqwest(TlsBackend::NativeTls),
+    ];
+
+    for backend in backends {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = format!("http://{}", listener.local_addr().unwrap());
+        let proxied = thread::spawn(move || listener.accept().is_ok());
+
+        scrub_env();
+        set_var("http_proxy", &proxy);
+        let url = Url::parse("http://192.168.0.1/").unwrap();
+        let res = backend
+            .download_to_path(&url, &tmpdir.path().join("downloaded"), false, None)
+            .await;
+
+        assert!(res.is_err(), "{backend:?} bypassed the proxy");
+        assert!(proxied.join().unwrap(), "{backend:?} bypassed the proxy");
+    }
+    scrub_env();
+}
@@ -4,7 +4,9 @@ use std::io;
 use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
 use http_body_util::Full;
 use hyper::body::Bytes;
@@ -13,6 +15,8 @@ use hyper::service::service_fn;
 use hyper::Request;
 use tempfile::TempDir;
 
+use download::{Backend, DownloadError, Event, ObjectStore, Result, Retry};
+
 pub fn tmp_dir() -> TempDir {
     tempfile::Builder::new()
         .prefix("rustup-download-test-")
@@ -36,13 +40,13 @@ pub fn write_file(path: &Path, contents: &str) {
 // A dead simple hyper server implementation.
 // For more info, see:
 // https://hyper.rs/guides/1/server/hello-world/
-async fn run_server(addr_tx: Sender<SocketAddr>, addr: SocketAddr, contents: Vec<u8>) {
+async fn run_server<F>(addr_tx: Sender<SocketAddr>, addr: SocketAddr, respond: F)
+where
+    F: Fn(Request<hyper::body::Incoming>) -> hyper::Response<Full<Bytes>> + Clone + Send + 'static,
+{
     let svc = service_fn(move |req: Request<hyper::body::Incoming>| {
-        let contents = contents.clone();
-        async move {
-            let res = serve_contents(req, contents);
-            Ok::<_, Infallible>(res)
-        }
+        let respond = respond.clone();
+        async move { Ok::<_, Infallible>(respond(req)) }
     });
 
     let listener = tokio::net::TcpListener::bind(&addr)
@@ -68,12 +72,15 @@ async fn run_server(addr_tx: Sender<SocketAddr>, addr: SocketAddr, contents: Vec
     }
 }
 
-pub fn serve_file(contents: Vec<u8>) -> SocketAddr {
+fn serve<F>(respond: F) -> SocketAddr
+where
+    F: Fn(Request<hyper::body::Incoming>) -> hyper::Response<Full<Bytes>> + Clone + Send + 'static,
+{
     let addr = ([127, 0, 0, 1], 0).into();
     let (addr_tx, addr_rx) = channel();
 
     thread::spawn(move || {
-        let server = run_server(addr_tx, addr, contents);
+        let server = run_server(addr_tx, addr, respond);
         let rt = tokio::runtime::Runtime::new().expect("could not creating Runtime");
         rt.block_on(server);
     });
@@ -82,6 +89,57 @@ pub fn serve_file(contents: Vec<u8>) -> SocketAddr {
     addr.unwrap()
 }
 
+pub fn serve_file(contents: Vec<u8>) -> SocketAddr {
+    serve(move |req| serve_contents(req, contents.clone()))
+}
+
+/// Serves every request with the given status and `Retry-After` header.
+fn serve_status(status: u16, retry_after: Option<&'static str>) -> SocketAddr {
+    serve(move |_| {
+        let mut res = hyper::Response::builder().status(status);
+        if let Some(retry_after) = retry_after {
+            res = res.header(hyper::header::RETRY_AFTER, retry_after);
+        }
+        res.body(Full::new(Bytes::new())).unwrap()
+    })
+}
+
+/// Serves `/redirect/{n}` by redirecting `n` times before serving `contents`,
+/// ignoring any `Range` header.
+fn serve_redirects(contents: Vec<u8>) -> SocketAddr {
+    serve(move |req| {
+        let n = req
+            .uri()
+            .path()
+            .strip_prefix("/redirect/")
+            .and_then(|n| n.parse::<u32>().ok())
+            .unwrap_or_default();
+        let res = hyper::Response::builder();
+        match n {
+            0 => res
+                .header(hyper::header::CONTENT_LENGTH, contents.len())
+                .body(Full::new(Bytes::from(contents.clone()))),
+            _ => res
+                .status(hyper::StatusCode::FOUND)
+                .header(hyper::header::LOCATION, format!("/redirect/{}", n - 1))
+                .header(hyper::header::CONTENT_LENGTH, 3)
+                .body(Full::new(Bytes::from_static(b"see"))),
+        }
+        .unwrap()
+    })
+}
+
+/// Serves every request by redirecting to `location`.
+pub fn serve_redirect(location: String) -> SocketAddr {
+    serve(move |_| {
+        hyper::Response::builder()
+            .status(hyper::StatusCode::FOUND)
+            .header(hyper::header::LOCATION, location.as_str())
+            .body(Full::new(Bytes::new()))
+            .unwrap()
+    })
+}
+
 fn serve_contents(
     req: hyper::Request<hyper::body::Incoming>,
     contents: Vec<u8>,
@@ -117,3 +175,426 @@ fn serve_contents(
     }
     res
 }
+
+async fn download_status(
+    backend: Backend,
+    status: u16,
+    retry_after: Option<&'static str>,
+) -> anyhow::Error {
+    let tmpdir = tmp_dir();
+    let addr = serve_status(status, retry_after);
+    let from_url = format!("http://{addr}").parse().unwrap();
+    backend
+        .download_to_path(&from_url, &tmpdir.path().join("downloaded"), false, None)
+        .await
+        .expect_err("download should fail")
+}
+
+/// Checks the behavior that all backends must share.
+pub async fn check_conformance(backend: Backend) {
+    check_rate_limited(backend).await;
+    check_redirects(backend).await;
+    check_redirect_to_other_server(backend).await;
+    check_resume_ignored(backend).await;
+    check_object_store(backend).await;
+    check_credentials_on_redirect(backend).await;
+    check_retries(backend).await;
+}
+
+/// Retries right away, for tests.
+const RETRY_NOW: Retry = Retry {
+    attempts: 3,
+    initial_delay: Duration::ZERO,
+    max_delay: Duration::ZERO,
+};
+
+/// Serves `contents` after failing the first requests with `fail`, counting
+/// the requests and keeping their `Range` headers.
+fn serve_flaky(
+    contents: Vec<u8>,
+    fail: impl Fn(usize) -> Option<hyper::Response<Full<Bytes>>> + Clone + Send + 'static,
+) -> (SocketAddr, Arc<Mutex<Vec<Option<String>>>>) {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let seen = requests.clone();
+    let addr = serve(move |req| {
+        let range = req
+            .headers()
+            .get(hyper::header::RANGE)
+            .map(|range| range.to_str().unwrap().to_owned());
+        let n = {
+            let mut seen = seen.lock().unwrap();
+            seen.push(range);
+            seen.len()
+        };
+        fail(n).unwrap_or_else(|| serve_contents(req, contents.clone()))
+    });
+    (addr, requests)
+}
+
+/// Serves `contents` over plain TCP, closing the first connection after
+/// `cut` bytes of the body, and keeps the `Range` header of each request.
+fn serve_cut_short(
+    contents: &'static [u8],
+    cut: usize,
+) -> (SocketAddr, Arc<Mutex<Vec<Option<String>>>>) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let seen = requests.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut range = None;
+            for line in BufReader::new(&stream).lines() {
+                let line = line.unwrap();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("range") {
+                        range = Some(value.trim().to_owned());
+                    }
+                }
+            }
+            let start = range
+                .as_deref()
+                .and_then(|range| {
+                    range
+                        .strip_prefix("bytes=")?
+                        .strip_suffix('-')?
+                        .parse()
+                        .ok()
+                })
+                .unwrap_or(0);
+            let first = {
+                let mut seen = seen.lock().unwrap();
+                seen.push(range);
+                seen.len() == 1
+            };
+            let len = contents.len();
+            let (status, end) = match (start, first) {
+                (0, true) => ("200 OK".to_owned(), cut),
+                (0, false) => ("200 OK".to_owned(), len),
+                _ => {
+                    let range = format!("bytes {start}-{}/{len}", len - 1);
+                    (
+                        format!("206 Partial Content\r\nContent-Range: {range}"),
+                        len,
+                    )
+                }
+            };
+            let head = format!(
+                "HTTP/1.1 {status}\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                len - start
+            );
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(&contents[start..end]);
+        }
+    });
+    (addr, requests)
+}
+
+async fn download_retrying(backend: Backend, addr: SocketAddr) -> (Result<()>, Vec<u8>, usize) {
+    let tmpdir = tmp_dir();
+    let target_path = tmpdir.path().join("downloaded");
+    let received = Mutex::new(Vec::new());
+    let retries = Mutex::new(0);
+    let url = format!("http://{addr}/").parse().unwrap();
+    let res = download::download_with_retry(
+        &backend,
+        &url,
+        &target_path,
+        false,
+        Some(&|msg| {
+            match msg {
+                Event::DownloadDataReceived(data) => received.lock().unwrap().extend(data),
+                Event::Retrying(_) => *retries.lock().unwrap() += 1,
+                _ => {}
+            }
+            Ok(())
+        }),
+        &RETRY_NOW,
+    )
+    .await;
+    if res.is_ok() {
+        assert_eq!(
+            fs::read(&target_path).unwrap(),
+            received.lock().unwrap()[..]
+        );
+    }
+    (
+        res,
+        received.into_inner().unwrap(),
+        retries.into_inner().unwrap(),
+    )
+}
+
+async fn check_retries(backend: Backend) {
+    let status = |status: u16| {
+        hyper::Response::builder()
+            .status(status)
+            .body(Full::new(Bytes::new()))
+            .unwrap()
+    };
+
+    // Server errors are retried.
+    let (addr, requests) = serve_flaky(b"12345".to_vec(), move |n| (n <= 2).then(|| status(503)));
+    let (res, received, retries) = download_retrying(backend, addr).await;
+    res.expect("the download should succeed once the server does");
+    assert_eq!(received, b"12345");
+    assert_eq!(retries, 2);
+    assert_eq!(requests.lock().unwrap().len(), 3);
+
+    // A download cut short resumes where it stopped, and the callback only
+    // sees the data once.
+    let (addr, requests) = serve_cut_short(b"12345", 3);
+    let (res, received, retries) = download_retrying(backend, addr).await;
+    res.expect("the download should resume");
+    assert_eq!(received, b"12345");
+    assert_eq!(retries, 1);
+    assert_eq!(
+        *requests.lock().unwrap(),
+        [None, Some("bytes=3-".to_owned())]
+    );
+
+    // Client errors are not.
+    let (addr, requests) = serve_flaky(Vec::new(), move |_| Some(status(404)));
+    let (res, _, retries) = download_retrying(backend, addr).await;
+    let err = res.expect_err("a missing file should not be retried");
+    assert!(matches!(
+        err.downcast_ref::<DownloadError>(),
+        Some(DownloadError::HttpStatus(404))
+    ));
+    assert_eq!(retries, 0);
+    assert_eq!(requests.lock().unwrap().len(), 1);
+
+    // Nor is anything once the retries run out.
+    let (addr, requests) = serve_flaky(Vec::new(), move |_| Some(status(500)));
+    let (res, _, retries) = download_retrying(backend, addr).await;
+    res.expect_err("the server never succeeds");
+    assert_eq!(retries, 3);
+    assert_eq!(requests.lock().unwrap().len(), 4);
+}
+
+async fn check_object_store(backend: Backend) {
+    // Serves `/bucket/key` to requests signed by the `AKID` key or carrying
+    // the `token` access token.
+    let addr = serve(|req| {
+        let authorization = req
+            .headers()
+            .get(hyper::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let signed = authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKID/")
+            && req.headers().contains_key("x-amz-date");
+        if req.uri().path() != "/bucket/dist/file" {
+            let res = hyper::Response::builder().status(hyper::StatusCode::NOT_FOUND);
+            res.body(Full::new(Bytes::new())).unwrap()
+        } else if !signed && authorization != "Bearer token" {
+            let res = hyper::Response::builder().status(hyper::StatusCode::FORBIDDEN);
+            res.body(Full::new(Bytes::new())).unwrap()
+        } else {
+            serve_contents(req, b"12345".to_vec())
+        }
+    });
+
+    let endpoint = format!("http://{addr}");
+    let emulator = addr.to_string();
+    let env = [
+        ("AWS_ENDPOINT_URL", endpoint.as_str()),
+        ("AWS_ACCESS_KEY_ID", "AKID"),
+        ("AWS_SECRET_ACCESS_KEY", "secret"),
+        ("STORAGE_EMULATOR_HOST", emulator.as_str()),
+        ("GOOGLE_OAUTH_ACCESS_TOKEN", "token"),
+    ];
+    let var = |name: &str| {
+        env.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_string())
+    };
+    let store = ObjectStore::from_env(backend, &var);
+
+    let tmpdir = tmp_dir();
+    let target_path = tmpdir.path().join("downloaded");
+    for url in ["s3://bucket/dist/file", "gs://bucket/dist/file"] {
+        write_file(&target_path, "123");
+        download::download_to_path(&store, &url.parse().unwrap(), &target_path, true, None)
+            .await
+            .unwrap_or_else(|e| panic!("downloading {url} failed: {e:?}"));
+        assert_eq!(fs::read_to_string(&target_path).unwrap(), "12345");
+    }
+
+    let anonymous = ObjectStore::from_env(backend, &|name| match name {
+        "AWS_ENDPOINT_URL" => Some(endpoint.clone()),
+        _ => None,
+    });
+    let url = "s3://bucket/dist/file".parse().unwrap();
+    let err = download::download_to_path(&anonymous, &url, &target_path, false, None)
+        .await
+        .expect_err("unsigned requests should be refused");
+    assert!(matches!(
+        err.downcast_ref::<DownloadError>(),
+        Some(DownloadError::HttpStatus(403))
+    ));
+}
+
+async fn check_credentials_on_redirect(backend: Backend) {
+    // Keeps the `Authorization` header of each request to the file.
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let authorizations = seen.clone();
+    let file = serve(move |req| {
+        let authorization = req
+            .headers()
+            .get(hyper::header::AUTHORIZATION)
+            .map(|value| value.to_str().unwrap().to_owned());
+        authorizations.lock().unwrap().push(authorization);
+        serve_contents(req, b"12345".to_vec())
+    });
+    // Redirects `/bucket/same` to itself, and everything else to the file
+    // on the other server.
+    let addr = serve(move |req| {
+        let authorization = req
+            .headers()
+            .get(hyper::header::AUTHORIZATION)
+            .map(|value| value.to_str().unwrap().to_owned());
+        let location = match req.uri().path() {
+            "/bucket/same" => "/bucket/file".to_owned(),
+            "/bucket/file" if authorization.is_some() => {
+                return serve_contents(req, b"12345".to_vec());
+            }
+            _ => format!("http://{file}/file"),
+        };
+        hyper::Response::builder()
+            .status(hyper::StatusCode::TEMPORARY_REDIRECT)
+            .header(hyper::header::LOCATION, location)
+            .body(Full::new(Bytes::new()))
+            .unwrap()
+    });
+
+    let endpoint = format!("http://{addr}");
+    let store = ObjectStore::from_env(backend, &|name| match name {
+        "AWS_ENDPOINT_URL" => Some(endpoint.clone()),
+        "AWS_ACCESS_KEY_ID" => Some("AKID".to_owned()),
+        "AWS_SECRET_ACCESS_KEY" => Some("secret".to_owned()),
+        _ => None,
+    });
+    let tmpdir = tmp_dir();
+    let target_path = tmpdir.path().join("downloaded");
+
+    // The signature goes along a redirect on the same server...
+    let url = "s3://bucket/same".parse().unwrap();
+    download::download_to_path(&store, &url, &target_path, false, None)
+        .await
+        .expect("the redirect should be followed");
+    assert_eq!(fs::read_to_string(&target_path).unwrap(), "12345");
+    assert!(seen.lock().unwrap().is_empty());
+
+    // ...but not to another one.
+    let url = "s3://bucket/other".parse().unwrap();
+    download::download_to_path(&store, &url, &target_path, false, None)
+        .await
+        .expect("the redirect should be followed");
+    assert_eq!(fs::read_to_string(&target_path).unwrap(), "12345");
+    assert_eq!(*seen.lock().unwrap(), [None]);
+}
+
+async fn check_redirects(backend: Backend) {
+    let tmpdir = tmp_dir();
+    let target_path = tmpdir.path().join("downloaded");
+    let addr = serve_redirects(b"12345".to_vec());
+
+    let callback_len = Mutex::new(None);
+    let url = format!("http://{addr}/redirect/10").parse().unwrap();
+    backend
+        .download_to_path(
+            &url,
+            &target_path,
+            false,
+            Some(&|msg| {
+                if let Event::DownloadContentLengthReceived(len) = msg {
+                    *callback_len.lock().unwrap() = Some(len);
+                }
+                Ok(())
+            }),
+        )
+        .await
+        .expect("ten redirects should be followed");
+    assert_eq!(fs::read_to_string(&target_path).unwrap(), "12345");
+    assert_eq!(*callback_len.lock().unwrap(), Some(5));
+
+    let url = format!("http://{addr}/redirect/11").parse().unwrap();
+    backend
+        .download_to_path(&url, &target_path, false, None)
+        .await
+        .expect_err("more than ten redirects should fail");
+}
+
+async fn check_redirect_to_other_server(backend: Backend) {
+    let tmpdir = tmp_dir();
+    let target_path = tmpdir.path().join("downloaded");
+    let file = serve_file(b"12345".to_vec());
+    let addr = serve_redirect(format!("http://{file}/"));
+
+    let url = format!("http://{addr}/").parse().unwrap();
+    backend
+        .download_to_path(&url, &target_path, false, None)
+        .await
+        .expect("a redirect to another server should be followed");
+    assert_eq!(fs::read_to_string(&target_path).unwrap(), "12345");
+}
+
+async fn check_resume_ignored(backend: Backend) {
+    let tmpdir = tmp_dir();
+    let target_path = tmpdir.path().join("downloaded");
+    write_file(&target_path, "123");
+    let addr = serve_redirects(b"12345".to_vec());
+
+    let url = format!("http://{addr}/redirect/0").parse().unwrap();
+    let err = backend
+        .download_to_path(&url, &target_path, true, None)
+        .await
+        .expect_err("appending the whole file should fail");
+    assert!(matches!(
+        err.downcast_ref::<DownloadError>(),
+        Some(DownloadError::ResumeNotSupported)
+    ));
+    assert!(!target_path.exists());
+}
+
+async fn check_rate_limited(backend: Backend) {
+    let err = download_status(backend, 429, Some("7")).await;
+    match err.downcast_ref::<DownloadError>() {
+        Some(DownloadError::RateLimited {
+            status: 429,
+            retry_after,
+        }) => assert_eq!(*retry_after, Some(Duration::from_secs(7))),
+        _ => panic!("unexpected error: {err:?}"),
+    }
+
+    let err = download_status(backend, 429, None).await;
+    assert!(matches!(
+        err.downcast_ref::<DownloadError>(),
+        Some(DownloadError::RateLimited {
+            status: 429,
+            retry_after: None
+        })
+    ));
+
+    // A date in the past means retrying right away.
+    let err = download_status(backend, 503, Some("Wed, 21 Oct 2015 07:28:00 GMT")).await;
+    assert!(matches!(
+        err.downcast_ref::<DownloadError>(),
+        Some(DownloadError::RateLimited {
+            status: 503,
+            retry_after: Some(Duration::ZERO)
+        })
+    ));
+
+    let err = download_status(backend, 503, None).await;
+    assert!(matches!(
+        err.downcast_ref::<DownloadError>(),
+        Some(DownloadError::HttpStatus(503))
+    ));
+}
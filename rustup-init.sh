@@ -60,6 +60,9 @@ Options:
           Don't update any existing default toolchain after install
       --no-modify-path
           Don't configure the PATH environment variable
+      --allow-root
+          Install as root even where files it creates would belong to root, such as in a container
+          build
   -h, --help
           Print help
   -V, --version
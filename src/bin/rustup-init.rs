@@ -13,10 +13,11 @@
 
 #![recursion_limit = "1024"]
 
+use std::panic::AssertUnwindSafe;
 use std::process::ExitCode;
 
-use anyhow::{anyhow, Context, Result};
-use cfg_if::cfg_if;
+use anyhow::Result;
+use futures_util::FutureExt;
 // Public macros require availability of the internal symbols
 use rs_tracing::{
     close_trace_file, close_trace_file_internal, open_trace_file, trace_to_file_internal,
@@ -24,40 +25,74 @@ use rs_tracing::{
 use tracing_subscriber::{reload::Handle, EnvFilter, Registry};
 
 use rustup::cli::common;
-use rustup::cli::proxy_mode;
-use rustup::cli::rustup_mode;
-#[cfg(windows)]
-use rustup::cli::self_update;
-use rustup::cli::setup_mode;
-use rustup::env_var::RUST_RECURSION_COUNT_MAX;
-use rustup::errors::RustupError;
-use rustup::is_proxyable_tools;
+use rustup::cli::crash_report;
+use rustup::cli::dispatch;
 use rustup::process::Process;
 use rustup::utils;
 
-#[tokio::main]
-async fn main() -> Result<ExitCode> {
+fn main() -> Result<ExitCode> {
     #[cfg(windows)]
     pre_rustup_main_init();
 
     let process = Process::os();
+    // Proxies run for every invocation of a toolchain tool, so they are
+    // told apart before anything else is set up, and set up only what
+    // running the tool needs.
+    if dispatch::is_proxy(&process) {
+        return utils::runtime::block_on(proxy_main(process));
+    }
+    // The telemetry exporter runs on the tokio runtime from the start.
+    // Otherwise the runtime is only started if something is downloaded.
+    #[cfg(feature = "otel")]
+    return utils::runtime::runtime().block_on(async_main(process));
+    #[cfg(not(feature = "otel"))]
+    utils::runtime::block_on(async_main(process))
+}
+
+async fn async_main(process: Process) -> Result<ExitCode> {
     #[cfg(feature = "otel")]
     opentelemetry::global::set_text_map_propagator(
         opentelemetry_sdk::propagation::TraceContextPropagator::new(),
     );
     let (subscriber, console_filter) = rustup::cli::log::tracing_subscriber(&process);
     tracing::subscriber::set_global_default(subscriber)?;
-    let result = run_rustup(&process, console_filter).await;
+    crash_report::install_panic_hook();
+    // Panics are caught here and reported as errors, rather than left to
+    // unwind out of `main`, so that the exit code tells them apart from a
+    // panic in a proxied tool.
+    let result = AssertUnwindSafe(run_rustup(&process, console_filter))
+        .catch_unwind()
+        .await;
     // We're tracing, so block until all spans are exported.
     #[cfg(feature = "otel")]
     opentelemetry::global::shutdown_tracer_provider();
 
+    exit(result, &process)
+}
+
+/// The thin entry point of the proxies, which skips the telemetry, tracing
+/// and command-line parsing of rustup's own modes.
+async fn proxy_main(process: Process) -> Result<ExitCode> {
+    let (subscriber, console_filter) = rustup::cli::log::proxy_tracing_subscriber(&process);
+    tracing::subscriber::set_global_default(subscriber)?;
+    crash_report::install_panic_hook();
+    let result = AssertUnwindSafe(dispatch::proxy(&process, console_filter))
+        .catch_unwind()
+        .await;
+    exit(result, &process)
+}
+
+fn exit(result: std::thread::Result<Result<utils::ExitCode>>, process: &Process) -> ! {
     match result {
-        Err(e) => {
-            common::report_error(&e, &process);
+        Err(payload) => {
+            crash_report::report_panic(&*payload, process);
+            std::process::exit(common::PANIC_EXIT_CODE)
+        }
+        Ok(Err(e)) => {
+            common::report_error(&e, process);
             std::process::exit(1)
         }
-        Ok(utils::ExitCode(c)) => std::process::exit(c),
+        Ok(Ok(utils::ExitCode(c))) => std::process::exit(c),
     }
 }
 
@@ -69,75 +104,13 @@ async fn run_rustup(
     if let Ok(dir) = process.var("RUSTUP_TRACE_DIR") {
         open_trace_file!(dir)?;
     }
-    let result = run_rustup_inner(process, console_filter).await;
+    let result = dispatch::main(process, console_filter).await;
     if process.var("RUSTUP_TRACE_DIR").is_ok() {
         close_trace_file!();
     }
     result
 }
 
-#[tracing::instrument(level = "trace", err(level = "trace"))]
-async fn run_rustup_inner(
-    process: &Process,
-    console_filter: Handle<EnvFilter, Registry>,
-) -> Result<utils::ExitCode> {
-    // Guard against infinite proxy recursion. This mostly happens due to
-    // bugs in rustup.
-    do_recursion_guard(process)?;
-
-    // Before we do anything else, ensure we know where we are and who we
-    // are because otherwise we cannot proceed usefully.
-    let current_dir = process
-        .current_dir()
-        .context(RustupError::LocatingWorkingDir)?;
-    utils::current_exe()?;
-
-    match process.name().as_deref() {
-        Some("rustup") => rustup_mode::main(current_dir, process, console_filter).await,
-        Some(n) if n.starts_with("rustup-setup") || n.starts_with("rustup-init") => {
-            // NB: The above check is only for the prefix of the file
-            // name. Browsers rename duplicates to
-            // e.g. rustup-setup(2), and this allows all variations
-            // to work.
-            setup_mode::main(current_dir, process, console_filter).await
-        }
-        Some(n) if n.starts_with("rustup-gc-") => {
-            // This is the final uninstallation stage on windows where
-            // rustup deletes its own exe
-            cfg_if! {
-                if #[cfg(windows)] {
-                    self_update::complete_windows_uninstall(process)
-                } else {
-                    unreachable!("Attempted to use Windows-specific code on a non-Windows platform. Aborting.")
-                }
-            }
-        }
-        Some(n) => {
-            is_proxyable_tools(n)?;
-            proxy_mode::main(n, current_dir, process)
-                .await
-                .map(utils::ExitCode::from)
-        }
-        None => {
-            // Weird case. No arg0, or it's unparsable.
-            Err(rustup::cli::errors::CLIError::NoExeName.into())
-        }
-    }
-}
-
-fn do_recursion_guard(process: &Process) -> Result<()> {
-    let recursion_count = process
-        .var("RUST_RECURSION_COUNT")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(0);
-    if recursion_count > RUST_RECURSION_COUNT_MAX {
-        return Err(anyhow!("infinite recursion detected"));
-    }
-
-    Ok(())
-}
-
 /// Windows pre-main security mitigations.
 ///
 /// This attempts to defend against malicious DLLs that may sit alongside
@@ -1,14 +1,34 @@
 /// The CLI specific code lives in the cli module and sub-modules.
 #[macro_use]
 pub mod log;
+#[cfg(unix)]
+mod agent;
 pub mod common;
+pub mod crash_report;
+mod describe_toolchain;
+mod diagnose_tls;
+pub mod dispatch;
+mod doctor;
 mod download_tracker;
 pub mod errors;
+mod fingerprint;
 mod help;
 mod job;
+mod json;
+mod listing;
 mod markdown;
+mod messages;
+mod proxy_bench;
 pub mod proxy_mode;
+mod rate_limit;
+mod report;
 pub mod rustup_mode;
+mod schedule;
 pub mod self_update;
+mod serve_cache;
 pub mod setup_mode;
+mod stats;
+mod status;
+mod target_doctor;
 mod topical_doc;
+mod usage;
@@ -0,0 +1,230 @@
+//! `rustup agent`, a long-running process that answers what proxies and IDEs
+//! would otherwise start a rustup for.
+//!
+//! It listens on the Unix socket `RUSTUP_HOME/agent.sock`, reads one request
+//! of JSON per connection, and answers with one line of JSON in the format
+//! of `--output json`:
+//!
+//! - `{"method": "resolve", "dir": "..."}` asks which toolchain is active in
+//!   `dir`, without installing it.
+//! - `{"method": "show", "dir": "...", "verbose": false}` asks what `rustup
+//!   show --output json` would print in `dir`.
+//! - `{"method": "invalidate-resolution"}` forgets every toolchain resolved
+//!   so far.
+//!
+//! The settings are only read again when `settings.toml` changes, and the
+//! toolchain of a directory only resolved again when a file it was resolved
+//! from changes, so when an IDE runs rustc hundreds of times a minute, its
+//! proxies ask the agent rather than each looking for overrides itself.
+//! Proxies do so when nothing on their command line or in their environment
+//! names a toolchain and toolchains aren't installed automatically, and fall
+//! back to resolving the toolchain themselves when no agent answers.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use self::resolution::ResolutionCache;
+use super::{fingerprint::Inputs, json};
+use crate::{
+    config::{ActiveReason, Cfg},
+    toolchain::{LocalToolchainName, ResolvableLocalToolchainName},
+    utils::ExitCode,
+};
+
+mod resolution;
+
+/// The socket in `RUSTUP_HOME` that the agent listens on.
+const SOCKET: &str = "agent.sock";
+
+/// How long the agent waits on a client, and a proxy on the agent.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Deserialize)]
+#[serde(tag = "method", rename_all = "kebab-case")]
+enum Request {
+    Resolve {
+        dir: PathBuf,
+    },
+    Show {
+        dir: PathBuf,
+        #[serde(default)]
+        verbose: bool,
+    },
+    InvalidateResolution,
+}
+
+#[derive(Deserialize)]
+struct Resolved {
+    toolchain: Option<String>,
+    reason: Option<json::Reason>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Invalidated {
+    invalidated: Option<usize>,
+    error: Option<String>,
+}
+
+pub(super) fn serve(cfg: &Cfg<'_>) -> Result<ExitCode> {
+    let path = cfg.rustup_dir.join(SOCKET);
+    if UnixStream::connect(&path).is_ok() {
+        bail!("rustup agent is already running on '{}'", path.display());
+    }
+    // Left behind by an agent that didn't exit cleanly.
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("could not listen on '{}'", path.display()))?;
+    info!("listening on '{}'", path.display());
+
+    let settings = cfg.rustup_dir.join("settings.toml");
+    let modified = || fs::metadata(&settings).and_then(|m| m.modified()).ok();
+    let mut loaded = (load(cfg)?, modified());
+    let mut cache = ResolutionCache::default();
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("could not accept connection: {e}");
+                continue;
+            }
+        };
+        let now_modified = modified();
+        if now_modified != loaded.1 {
+            debug!("reloading settings");
+            match load(cfg) {
+                Ok(reloaded) => loaded = (reloaded, now_modified),
+                Err(e) => warn!("could not reload settings: {e:#}"),
+            }
+        }
+        if let Err(e) = handle(&mut loaded.0, &mut cache, stream) {
+            debug!("{e:#}");
+        }
+    }
+    Ok(ExitCode(0))
+}
+
+fn load<'a>(cfg: &Cfg<'a>) -> Result<Cfg<'a>> {
+    let mut loaded = Cfg::from_env(
+        cfg.current_dir.clone(),
+        cfg.notify_handler.clone(),
+        cfg.process,
+    )?;
+    // Proxies with `RUSTUP_TOOLCHAIN` set don't ask the agent, so its own
+    // must not answer for them either.
+    loaded.env_override = None;
+    Ok(loaded)
+}
+
+fn handle(cfg: &mut Cfg<'_>, cache: &mut ResolutionCache, stream: UnixStream) -> Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+
+    let response = match serde_json::from_str(&request) {
+        Ok(Request::Resolve { dir }) => match cache.get(&dir) {
+            Some(response) => Ok(response.to_owned()),
+            None => {
+                debug!("resolving the toolchain of '{}'", dir.display());
+                cfg.current_dir = dir;
+                // Taken first, so that a file changed while resolving makes
+                // the answer stale.
+                let inputs = Inputs::take(cfg.resolution_inputs());
+                json::resolved_line(cfg).inspect(|response| {
+                    cache.insert(cfg.current_dir.clone(), inputs, response.clone())
+                })
+            }
+        },
+        Ok(Request::Show { dir, verbose }) => {
+            cfg.current_dir = dir;
+            json::show_line(cfg, verbose)
+        }
+        Ok(Request::InvalidateResolution) => json::invalidated_line(cache.clear()),
+        Err(e) => Err(e.into()),
+    };
+    let response = response.unwrap_or_else(|e| json::error_line(&e));
+    (&stream).write_all(response.as_bytes())?;
+    Ok(())
+}
+
+/// Tells a running agent to resolve toolchains again.
+pub(super) fn invalidate_resolution(cfg: &Cfg<'_>) -> Result<ExitCode> {
+    let path = cfg.rustup_dir.join(SOCKET);
+    let Ok(stream) = UnixStream::connect(&path) else {
+        info!("no rustup agent is running");
+        return Ok(ExitCode(0));
+    };
+    let response = request(
+        &stream,
+        serde_json::json!({ "method": "invalidate-resolution" }),
+    )?;
+    let invalidated: Invalidated = serde_json::from_str(&response)?;
+    if let Some(error) = invalidated.error {
+        bail!(error);
+    }
+    let dirs = match invalidated.invalidated.unwrap_or_default() {
+        1 => "1 directory".to_owned(),
+        n => format!("{n} directories"),
+    };
+    info!("forgot the resolved toolchains of {dirs}");
+    Ok(ExitCode(0))
+}
+
+/// Asks a running agent which toolchain is active in `cfg.current_dir` and
+/// why, returning `None` if none answers.
+pub(crate) fn resolve(cfg: &Cfg<'_>) -> Option<(LocalToolchainName, ActiveReason)> {
+    let path = cfg.rustup_dir.join(SOCKET);
+    if !path.exists() {
+        return None;
+    }
+    match ask(cfg, &path) {
+        Ok(name) => name,
+        Err(e) => {
+            debug!("could not ask rustup agent for the toolchain: {e:#}");
+            None
+        }
+    }
+}
+
+fn ask(cfg: &Cfg<'_>, path: &Path) -> Result<Option<(LocalToolchainName, ActiveReason)>> {
+    let stream = UnixStream::connect(path)?;
+    let response = request(
+        &stream,
+        serde_json::json!({ "method": "resolve", "dir": cfg.current_dir }),
+    )?;
+    let resolved: Resolved = serde_json::from_str(&response)?;
+    if let Some(error) = resolved.error {
+        return Err(anyhow!(error));
+    }
+    let Some(name) = resolved.toolchain else {
+        return Ok(None);
+    };
+    debug!("rustup agent resolved the toolchain to '{name}'");
+    let name = ResolvableLocalToolchainName::try_from(name.as_str())?;
+    let reason = resolved
+        .reason
+        .map_or(ActiveReason::Default, ActiveReason::from);
+    Ok(Some((
+        name.resolve(&cfg.get_default_host_triple()?)?,
+        reason,
+    )))
+}
+
+/// Sends `request` to the agent on `stream`, returning its answer.
+fn request(stream: &UnixStream, request: serde_json::Value) -> Result<String> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut stream = stream;
+    stream.write_all(format!("{request}\n").as_bytes())?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    Ok(response)
+}
@@ -0,0 +1,116 @@
+//! Remembers the toolchain each directory resolved to, along with a
+//! fingerprint of every file the resolution read, so that `rustup agent`
+//! only resolves a directory again once one of them has changed.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::cli::fingerprint::Inputs;
+
+#[derive(Debug, Default)]
+pub(super) struct ResolutionCache {
+    entries: HashMap<PathBuf, Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// The answer to a `resolve` request for the directory.
+    response: String,
+    inputs: Inputs,
+}
+
+impl ResolutionCache {
+    /// The answer cached for `dir`, if nothing it was resolved from has
+    /// changed since.
+    pub(super) fn get(&mut self, dir: &Path) -> Option<&str> {
+        let entry = self.entries.get_mut(dir)?;
+        if !entry.inputs.are_current() {
+            self.entries.remove(dir);
+            return None;
+        }
+        self.entries.get(dir).map(|entry| entry.response.as_str())
+    }
+
+    /// Caches `response` for `dir`, where `inputs` were fingerprinted
+    /// before resolving it.
+    pub(super) fn insert(&mut self, dir: PathBuf, inputs: Inputs, response: String) {
+        self.entries.insert(dir, Entry { response, inputs });
+    }
+
+    /// Forgets every cached answer, returning how many there were.
+    pub(super) fn clear(&mut self) -> usize {
+        let len = self.entries.len();
+        self.entries.clear();
+        len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn cache_for(dir: &Path, file: &Path) -> ResolutionCache {
+        let mut cache = ResolutionCache::default();
+        let inputs = Inputs::take(vec![file.to_owned()]);
+        cache.insert(dir.to_owned(), inputs, "stable".to_owned());
+        cache
+    }
+
+    #[test]
+    fn answers_are_kept_until_a_file_changes() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let file = dir.path().join("rust-toolchain.toml");
+
+        // A file that appears invalidates the answer given without it.
+        let mut cache = cache_for(dir.path(), &file);
+        assert_eq!(cache.get(dir.path()), Some("stable"));
+        fs::write(&file, "[toolchain]\nchannel = \"stable\"\n").unwrap();
+        assert_eq!(cache.get(dir.path()), None);
+
+        // Rewriting it with the same content does not.
+        let mut cache = cache_for(dir.path(), &file);
+        fs::write(&file, "[toolchain]\nchannel = \"stable\"\n").unwrap();
+        assert_eq!(cache.get(dir.path()), Some("stable"));
+
+        fs::write(&file, "[toolchain]\nchannel = \"beta\"\n").unwrap();
+        assert_eq!(cache.get(dir.path()), None);
+
+        let mut cache = cache_for(dir.path(), &file);
+        fs::remove_file(&file).unwrap();
+        assert_eq!(cache.get(dir.path()), None);
+        assert_eq!(cache.clear(), 0);
+    }
+
+    #[test]
+    fn edits_that_keep_the_modification_time_are_noticed() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let file = dir.path().join("rust-toolchain");
+        fs::write(&file, "stable").unwrap();
+        let modified = fs::metadata(&file).unwrap().modified().unwrap();
+
+        let mut cache = cache_for(dir.path(), &file);
+        fs::write(&file, "nightl").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(cache.get(dir.path()), None);
+    }
+
+    #[test]
+    fn directories_are_compared_by_their_entries() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let toolchains = dir.path().join("toolchains");
+        fs::create_dir(&toolchains).unwrap();
+
+        let mut cache = cache_for(dir.path(), &toolchains);
+        assert_eq!(cache.get(dir.path()), Some("stable"));
+        fs::create_dir(toolchains.join("stable")).unwrap();
+        assert_eq!(cache.get(dir.path()), None);
+        assert_eq!(cache.clear(), 0);
+    }
+}
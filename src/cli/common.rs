@@ -1,20 +1,27 @@
 //! Just a dumping ground for cli stuff
 
+use std::any::Any;
 use std::cell::RefCell;
 use std::fmt::Display;
 use std::fs;
 #[cfg(not(windows))]
 use std::io::ErrorKind;
-use std::io::{BufRead, Write};
+use std::io::{self, BufRead, IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, LazyLock, Mutex};
 use std::{cmp, env};
 
 use anyhow::{anyhow, Context, Result};
 use git_testament::{git_testament, render_testament};
+use itertools::Itertools;
 use tracing::{debug, error, info, trace, warn};
 use tracing_subscriber::{reload::Handle, EnvFilter, Registry};
 
+use super::errors::CLIError;
+use super::json::{self, OutputFormat};
+use super::listing::{Item, Listing};
+use super::messages::message;
+use super::rate_limit::WarningLimiter;
 use super::self_update;
 use crate::{
     cli::download_tracker::DownloadTracker,
@@ -24,10 +31,12 @@ use crate::{
     },
     errors::RustupError,
     install::UpdateStatus,
-    notifications::Notification,
+    notifications::{Event, EventHandler, Notification},
     process::{terminalsource, Process},
     toolchain::{DistributableToolchain, LocalToolchainName, Toolchain, ToolchainName},
-    utils::{self, notifications as util_notifications, notify::NotificationLevel},
+    utils::{
+        self, notifications as util_notifications, notify::NotificationLevel, units::format_bytes,
+    },
 };
 
 pub(crate) const WARN_COMPLETE_PROFILE: &str = "downloading with complete profile isn't recommended unless you are a developer of the rust language";
@@ -58,12 +67,12 @@ pub(crate) enum Confirm {
 pub(crate) fn confirm_advanced(customized_install: bool, process: &Process) -> Result<Confirm> {
     writeln!(process.stdout().lock())?;
     let first_option = match customized_install {
-        true => "1) Proceed with selected options (default - just press enter)",
-        false => "1) Proceed with standard installation (default - just press enter)",
+        true => "setup-proceed-selected",
+        false => "setup-proceed-standard",
     };
-    writeln!(process.stdout().lock(), "{first_option}")?;
-    writeln!(process.stdout().lock(), "2) Customize installation")?;
-    writeln!(process.stdout().lock(), "3) Cancel installation")?;
+    for id in [first_option, "setup-customize", "setup-cancel"] {
+        writeln!(process.stdout().lock(), "{}", message(process, id, &[]))?;
+    }
     write!(process.stdout().lock(), ">")?;
 
     let _ = std::io::stdout().flush();
@@ -114,6 +123,48 @@ pub(crate) fn question_bool(question: &str, default: bool, process: &Process) ->
     }
 }
 
+/// Shows a numbered list of checkboxes and toggles the entries whose numbers
+/// the user enters, until they enter an empty line.
+pub(crate) fn question_checklist(
+    question: &str,
+    items: &[String],
+    checked: &mut [bool],
+    process: &Process,
+) -> Result<()> {
+    loop {
+        {
+            let stdout = process.stdout();
+            let mut stdout = stdout.lock();
+            let hint = message(process, "checklist-hint", &[]);
+            writeln!(stdout, "{question} {hint}")?;
+            for (i, (item, checked)) in items.iter().zip(&*checked).enumerate() {
+                let mark = if *checked { 'x' } else { ' ' };
+                writeln!(stdout, "  {}) [{mark}] {item}", i + 1)?;
+            }
+        }
+        let _ = std::io::stdout().flush();
+        let input = read_line(process)?;
+
+        writeln!(process.stdout().lock())?;
+
+        if input.trim().is_empty() {
+            return Ok(());
+        }
+        for n in input
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|n| !n.is_empty())
+        {
+            match n.parse::<usize>() {
+                Ok(i) if (1..=items.len()).contains(&i) => checked[i - 1] = !checked[i - 1],
+                _ => warn!(
+                    "{}",
+                    message(process, "checklist-not-listed", &[("input", n.into())])
+                ),
+            }
+        }
+    }
+}
+
 pub(crate) fn read_line(process: &Process) -> Result<String> {
     let stdin = process.stdin();
     let stdin = stdin.lock();
@@ -126,23 +177,147 @@ pub(crate) fn read_line(process: &Process) -> Result<String> {
     .context("unable to read from stdin for confirmation")
 }
 
+/// Reads a line as [`read_line`] does, without echoing it on a terminal.
+pub(crate) fn read_secret_line(process: &Process) -> Result<String> {
+    let _echo_off = match process {
+        Process::OsProcess(_) if io::stdin().is_terminal() => Some(EchoOff::new()?),
+        _ => None,
+    };
+    read_line(process)
+}
+
+/// Turns off echoing what is typed on the terminal of stdin until dropped.
+#[cfg(unix)]
+struct EchoOff(libc::termios);
+
+#[cfg(unix)]
+impl EchoOff {
+    fn new() -> io::Result<Self> {
+        // SAFETY: the terminal settings are plain data, written by the call.
+        unsafe {
+            let mut term = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut term) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let old = term;
+            // The newline ending the line is still echoed.
+            term.c_lflag = (term.c_lflag & !libc::ECHO) | libc::ECHONL;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &term) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self(old))
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        // SAFETY: restores the settings read in `new`.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+    }
+}
+
+#[cfg(windows)]
+struct EchoOff(windows_sys::Win32::System::Console::CONSOLE_MODE);
+
+#[cfg(windows)]
+impl EchoOff {
+    fn new() -> io::Result<Self> {
+        use windows_sys::Win32::System::Console::{
+            GetConsoleMode, GetStdHandle, SetConsoleMode, ENABLE_ECHO_INPUT, STD_INPUT_HANDLE,
+        };
+
+        // SAFETY: the mode is plain data, written by the call.
+        unsafe {
+            let handle = GetStdHandle(STD_INPUT_HANDLE);
+            let mut mode = 0;
+            if GetConsoleMode(handle, &mut mode) == 0
+                || SetConsoleMode(handle, mode & !ENABLE_ECHO_INPUT) == 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self(mode))
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        use windows_sys::Win32::System::Console::{GetStdHandle, SetConsoleMode, STD_INPUT_HANDLE};
+
+        // SAFETY: restores the mode read in `new`.
+        unsafe { SetConsoleMode(GetStdHandle(STD_INPUT_HANDLE), self.0) };
+    }
+}
+
 pub(super) struct Notifier {
     tracker: Mutex<DownloadTracker>,
     ram_notice_shown: RefCell<bool>,
+    io_notice_shown: RefCell<bool>,
+    /// Receives every notification as a line of JSON, see `RUSTUP_EVENT_LOG`.
+    event_log: Option<RefCell<fs::File>>,
+    /// Set by embedders on the process to receive every event.
+    event_handler: Option<Arc<EventHandler>>,
+    /// Only set for proxies, which may run many times in a row.
+    warning_limiter: Option<RefCell<WarningLimiter>>,
 }
 
 impl Notifier {
     pub(super) fn new(quiet: bool, process: &Process) -> Self {
+        let event_log = process.var_os("RUSTUP_EVENT_LOG").and_then(|path| {
+            match fs::OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(file) => Some(RefCell::new(file)),
+                Err(e) => {
+                    warn!(
+                        "unable to open event log '{}': {e}",
+                        Path::new(&path).display()
+                    );
+                    None
+                }
+            }
+        });
         Self {
             tracker: Mutex::new(DownloadTracker::new_with_display_progress(!quiet, process)),
             ram_notice_shown: RefCell::new(false),
+            io_notice_shown: RefCell::new(false),
+            event_log,
+            event_handler: process.event_handler().cloned(),
+            warning_limiter: None,
         }
     }
 
     pub(super) fn handle(&self, n: Notification<'_>) {
-        if self.tracker.lock().unwrap().handle_notification(&n) {
+        let handler = self.event_handler.as_ref();
+        let emit = |event: &Event<'_>| {
+            if let Some(handler) = &handler {
+                handler(event);
+            }
+            if let Some(event_log) = &self.event_log {
+                if let Ok(mut line) = serde_json::to_string(event) {
+                    line.push('\n');
+                    let _ = event_log.borrow_mut().write_all(line.as_bytes());
+                }
+            }
+        };
+        let emitting = self.event_log.is_some() || handler.is_some();
+        if emitting {
+            emit(&n.to_event());
+        }
+
+        let mut tracker = self.tracker.lock().unwrap();
+        if let (true, Notification::Install(dist_notifications::Notification::UpdateFinished)) =
+            (emitting, &n)
+        {
+            if let Some(stats) = tracker.summary_stats() {
+                emit(&Event::install_summary(&stats));
+            }
+        }
+        if tracker.handle_notification(&n) {
             return;
         }
+        drop(tracker);
 
         if let Notification::Install(dist_notifications::Notification::Utils(
             util_notifications::Notification::SetDefaultBufferSize(_),
@@ -154,8 +329,23 @@ impl Notifier {
                 *self.ram_notice_shown.borrow_mut() = true;
             }
         };
+        // Every component is unpacked the same way, so say how just once.
+        if let Notification::Install(dist_notifications::Notification::Utils(
+            util_notifications::Notification::IoTuning(..),
+        )) = &n
+        {
+            if self.io_notice_shown.replace(true) {
+                return;
+            }
+        };
         let level = n.level();
-        for n in format!("{n}").lines() {
+        let message = n.to_string();
+        if let (NotificationLevel::Warn, Some(limiter)) = (level, &self.warning_limiter) {
+            if !limiter.borrow_mut().allow(&message) {
+                return;
+            }
+        }
+        for n in message.lines() {
             match level {
                 NotificationLevel::Debug => {
                     debug!("{}", n);
@@ -183,6 +373,13 @@ pub(crate) fn set_globals(current_dir: PathBuf, quiet: bool, process: &Process)
     Cfg::from_env(current_dir, Arc::new(move |n| notifier.handle(n)), process)
 }
 
+/// Like [`set_globals`], but rate limits repeated warnings.
+pub(crate) fn set_proxy_globals(current_dir: PathBuf, process: &Process) -> Result<Cfg<'_>> {
+    let mut notifier = Notifier::new(true, process);
+    notifier.warning_limiter = WarningLimiter::new(&current_dir, process).map(RefCell::new);
+    Cfg::from_env(current_dir, Arc::new(move |n| notifier.handle(n)), process)
+}
+
 pub(crate) fn show_channel_update(
     cfg: &Cfg<'_>,
     name: PackageUpdate,
@@ -280,14 +477,19 @@ fn show_channel_updates(
     Ok(())
 }
 
+/// Updates all installed channels, returning the exit code and whether any
+/// toolchain or rustup itself was updated.
 pub(crate) async fn update_all_channels(
     cfg: &Cfg<'_>,
     do_self_update: bool,
     force_update: bool,
-) -> Result<utils::ExitCode> {
+) -> Result<(utils::ExitCode, bool)> {
     let toolchains = cfg.update_all_channels(force_update).await?;
     let has_update_error = toolchains.iter().any(|(_, r)| r.is_err());
     let mut exit_code = utils::ExitCode(if has_update_error { 1 } else { 0 });
+    let mut changed = toolchains
+        .iter()
+        .any(|(_, r)| matches!(r, Ok(UpdateStatus::Installed | UpdateStatus::Updated(_))));
 
     if toolchains.is_empty() {
         info!("no updatable toolchains installed");
@@ -307,11 +509,13 @@ pub(crate) async fn update_all_channels(
     };
 
     if do_self_update {
-        exit_code &= self_update(show_channel_updates, cfg.process).await?;
+        let (code, updated) = self_update(show_channel_updates, cfg.process).await?;
+        exit_code &= code;
+        changed |= updated;
     } else {
         show_channel_updates()?;
     }
-    Ok(exit_code)
+    Ok((exit_code, changed))
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -352,17 +556,22 @@ pub(crate) fn self_update_permitted(explicit: bool) -> Result<SelfUpdatePermissi
 }
 
 /// Performs all of a self-update: check policy, download, apply and exit.
-pub(crate) async fn self_update<F>(before_restart: F, process: &Process) -> Result<utils::ExitCode>
+/// Updates rustup itself, returning the exit code and whether rustup was
+/// updated.
+pub(crate) async fn self_update<F>(
+    before_restart: F,
+    process: &Process,
+) -> Result<(utils::ExitCode, bool)>
 where
     F: FnOnce() -> Result<()>,
 {
     match self_update_permitted(false)? {
         SelfUpdatePermission::HardFail => {
             error!("Unable to self-update.  STOP");
-            return Ok(utils::ExitCode(1));
+            return Ok((utils::ExitCode(1), false));
         }
         #[cfg(not(windows))]
-        SelfUpdatePermission::Skip => return Ok(utils::ExitCode(0)),
+        SelfUpdatePermission::Skip => return Ok((utils::ExitCode(0), false)),
         SelfUpdatePermission::Permit => {}
     }
 
@@ -371,46 +580,129 @@ where
     before_restart()?;
 
     if let Some(ref setup_path) = setup_path {
-        return self_update::run_update(setup_path);
+        return Ok((self_update::run_update(setup_path)?, true));
     } else {
         // Try again in case we emitted "tool `{}` is already installed" last time.
         self_update::install_proxies(process)?;
     }
 
+    Ok((utils::ExitCode(0), false))
+}
+
+pub(super) fn list_components(
+    distributable: DistributableToolchain<'_>,
+    installed_only: bool,
+    quiet: bool,
+    verbose: bool,
+    porcelain: bool,
+    format: OutputFormat,
+    process: &Process,
+) -> Result<utils::ExitCode> {
+    let components = distributable
+        .components()?
+        .into_iter()
+        .filter(|c| c.available && (c.installed || !installed_only))
+        .collect::<Vec<_>>();
+    if let OutputFormat::Json(version) = format {
+        return json::list_components(&distributable, components, version, process);
+    }
+
+    let items = components
+        .into_iter()
+        .map(|c| Item {
+            detail: verbose.then(|| component_sizes(&c)),
+            name: c.name,
+            installed: c.installed,
+        })
+        .collect();
+
+    let mut listing = Listing::new(!quiet && !installed_only);
+    listing.push_group(None, items);
+    listing.print(porcelain, process)?;
     Ok(utils::ExitCode(0))
 }
 
-pub(super) fn list_items(
+/// Lists the targets of `distributable` whose name contains `filter`.
+///
+/// When the manifest declares the support tier of the listed targets, they are
+/// grouped by tier, with the targets of unknown tier last, unless they are
+/// listed quietly or for scripts.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn list_targets(
     distributable: DistributableToolchain<'_>,
-    f: impl Fn(&ComponentStatus) -> Option<&str>,
     installed_only: bool,
+    available_only: bool,
+    filter: Option<&str>,
     quiet: bool,
+    porcelain: bool,
+    format: OutputFormat,
     process: &Process,
 ) -> Result<utils::ExitCode> {
-    let mut t = process.stdout().terminal(process);
-    for component in distributable.components()? {
-        let Some(name) = f(&component) else { continue };
-        match (component.available, component.installed, installed_only) {
-            (false, _, _) | (_, false, true) => continue,
-            (true, true, false) if !quiet => {
-                t.attr(terminalsource::Attr::Bold)?;
-                writeln!(t.lock(), "{name} (installed)")?;
-                t.reset()?;
-            }
-            (true, _, false) | (_, true, true) => {
-                writeln!(t.lock(), "{name}")?;
-            }
-        }
+    let targets = distributable
+        .components()?
+        .into_iter()
+        .filter(|c| c.available && c.component.short_name_in_manifest() == "rust-std")
+        .filter(|c| !(installed_only && !c.installed || available_only && c.installed))
+        .filter(|c| match (filter, &c.component.target) {
+            (Some(filter), Some(target)) => target.contains(filter),
+            _ => true,
+        })
+        .collect::<Vec<_>>();
+    if let OutputFormat::Json(version) = format {
+        return json::list_targets(&distributable, targets, version, process);
     }
 
+    let targets = targets
+        .into_iter()
+        .filter_map(|c| {
+            let item = Item {
+                name: c.component.target?.to_string(),
+                installed: c.installed,
+                detail: None,
+            };
+            Some((c.tier, item))
+        })
+        .collect::<Vec<_>>();
+
+    let mut listing = Listing::new(!quiet && !installed_only);
+    if quiet || porcelain || targets.iter().all(|(tier, _)| tier.is_none()) {
+        listing.push_group(None, targets.into_iter().map(|(_, item)| item).collect());
+    } else {
+        let groups = targets
+            .into_iter()
+            .sorted_by_key(|(tier, _)| (tier.is_none(), *tier))
+            .chunk_by(|(tier, _)| *tier);
+        for (tier, targets) in &groups {
+            let heading = match tier {
+                Some(tier) => format!("tier {tier}"),
+                None => "unknown tier".to_owned(),
+            };
+            listing.push_group(Some(heading), targets.map(|(_, item)| item).collect());
+        }
+    }
+    listing.print(porcelain, process)?;
     Ok(utils::ExitCode(0))
 }
 
+fn component_sizes(component: &ComponentStatus) -> String {
+    let size = |size: Option<u64>| size.map_or_else(|| "unknown".to_owned(), format_bytes);
+    format!(
+        "download: {}, installed: {}",
+        size(component.download_size),
+        size(component.installed_size)
+    )
+}
+
 pub(crate) fn list_toolchains(
     cfg: &Cfg<'_>,
     verbose: bool,
     quiet: bool,
+    format: OutputFormat,
 ) -> Result<utils::ExitCode> {
+    cfg.warn_expired_pins()?;
+    if let OutputFormat::Json(version) = format {
+        return json::list_toolchains(cfg, verbose, version);
+    }
     let toolchains = cfg.list_toolchains()?;
     if toolchains.is_empty() {
         writeln!(cfg.process.stdout().lock(), "no installed toolchains")?;
@@ -428,12 +720,14 @@ pub(crate) fn list_toolchains(
         for toolchain in toolchains {
             let is_default_toolchain = default_toolchain_name.as_ref() == Some(&toolchain);
             let is_active_toolchain = active_toolchain_name.as_ref() == Some(&toolchain);
+            let is_pinned_toolchain = cfg.is_pinned(&toolchain)?;
 
             print_toolchain(
                 cfg,
                 &toolchain.to_string(),
                 is_default_toolchain,
                 is_active_toolchain,
+                is_pinned_toolchain,
                 verbose,
                 quiet,
             )
@@ -446,6 +740,7 @@ pub(crate) fn list_toolchains(
         toolchain: &str,
         is_default: bool,
         is_active: bool,
+        is_pinned: bool,
         verbose: bool,
         quiet: bool,
     ) -> Result<()> {
@@ -465,11 +760,17 @@ pub(crate) fn list_toolchains(
         } else {
             String::new()
         };
-        let status_str = match (is_default, is_active) {
-            (true, true) => " (active, default)",
-            (true, false) => " (default)",
-            (false, true) => " (active)",
-            (false, false) => "",
+        let labels = [
+            (is_active, "active"),
+            (is_default, "default"),
+            (is_pinned, "pinned"),
+        ]
+        .into_iter()
+        .filter_map(|(is, label)| is.then_some(label))
+        .join(", ");
+        let status_str = match labels.is_empty() {
+            true => String::new(),
+            false => format!(" ({labels})"),
         };
 
         writeln!(
@@ -597,6 +898,55 @@ fn show_backtrace(process: &Process) -> bool {
     false
 }
 
+/// The exit code used when rustup itself panicked.
+///
+/// Rust programs exit with 101 on a panic, but so does e.g. `rustc` on an
+/// internal compiler error, so a proxy could not tell the two apart.
+pub const PANIC_EXIT_CODE: i32 = 70;
+
+/// Returns the message a panic was raised with.
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(s) => s,
+        None => match payload.downcast_ref::<String>() {
+            Some(s) => s.as_str(),
+            None => "Box<dyn Any>",
+        },
+    }
+}
+
+/// Reports a panic as an ordinary error, in place of the default panic
+/// message.
+///
+/// This is called once the panic has unwound out of rustup, after any
+/// unfinished transaction was rolled back and its temporary files deleted.
+pub(crate) fn report_panic(message: &str, location: &str, process: &Process) {
+    restore_terminal(process);
+    let e = CLIError::Panicked {
+        message: message.to_owned(),
+        location: location.to_owned(),
+    }
+    .into();
+    report_error(&e, process);
+}
+
+/// Leaves the terminal in a usable state after output was interrupted, e.g.
+/// while a progress line was being redrawn.
+fn restore_terminal(process: &Process) {
+    for stream in [process.stdout(), process.stderr()] {
+        if !stream.is_a_tty(process) {
+            continue;
+        }
+        let mut term = stream.terminal(process);
+        let _ = term.reset();
+        if cfg!(not(windows)) {
+            // Show the cursor again.
+            let _ = write!(term.lock(), "\x1b[?25h");
+        }
+        let _ = writeln!(term.lock());
+    }
+}
+
 pub fn report_error(e: &anyhow::Error, process: &Process) {
     // NB: This shows one error: even for multiple causes and backtraces etc,
     // rather than one per cause, and one for the backtrace. This seems like a
@@ -605,26 +955,62 @@ pub fn report_error(e: &anyhow::Error, process: &Process) {
     // has to detect when backtrace is available.
     if show_backtrace(process) {
         error!("{:?}", e);
+    } else if let Some(msg) = localized_error(e, process) {
+        error!("{msg}");
     } else {
         error!("{:#}", e);
     }
 }
 
-pub(crate) fn ignorable_error(
-    error: &'static str,
-    no_prompt: bool,
-    process: &Process,
-) -> Result<()> {
-    let error = anyhow!(error);
-    report_error(&error, process);
-    if no_prompt {
-        warn!("continuing (because the -y flag is set and the error is ignorable)");
-        Ok(())
-    } else if confirm("\nContinue? (y/N)", false, process).unwrap_or(false) {
-        Ok(())
-    } else {
-        Err(error)
-    }
+/// Shows `e` like `{:#}` does, in the user's language if its outermost error
+/// has a message in the catalogs.
+fn localized_error(e: &anyhow::Error, process: &Process) -> Option<String> {
+    let mut chain = e.chain();
+    let msg = match chain.next()?.downcast_ref::<RustupError>()? {
+        RustupError::ToolchainNotInstalled { name } => {
+            let msg = message(
+                process,
+                "error-toolchain-not-installed",
+                &[("name", name.to_string().into())],
+            );
+            match name {
+                ToolchainName::Official(t) => {
+                    let args = [("name", t.to_string().into())];
+                    let help = message(process, "error-toolchain-install-help", &args);
+                    format!("{msg}\n{help}")
+                }
+                _ => msg,
+            }
+        }
+        RustupError::ToolchainNotSelected(tool) => message(
+            process,
+            "error-toolchain-not-selected",
+            &[("tool", tool.as_str().into())],
+        ),
+        RustupError::DownloadingFile { url, path } => message(
+            process,
+            "error-downloading-file",
+            &[
+                ("url", url.to_string().into()),
+                ("path", path.display().to_string().into()),
+            ],
+        ),
+        RustupError::DownloadServerFailed { url, status } => message(
+            process,
+            "error-download-server-failed",
+            &[
+                ("url", url.to_string().into()),
+                ("status", (*status).into()),
+            ],
+        ),
+        RustupError::DownloadNotConfirmed(size) => message(
+            process,
+            "error-download-not-confirmed",
+            &[("size", format_bytes(*size).into())],
+        ),
+        _ => return None,
+    };
+    Some(chain.fold(msg, |msg, cause| format!("{msg}: {cause}")))
 }
 
 /// Returns an error for a toolchain if both conditions are met:
@@ -0,0 +1,263 @@
+//! Machine-readable descriptions of installed toolchains.
+//!
+//! `rustup describe-toolchain` prints where a toolchain keeps its tools and
+//! standard libraries, so that hermetic build systems can use a toolchain
+//! installed by rustup instead of downloading their own copy. By default the
+//! description is JSON; with `--bazel` it is a `BUILD` file defining the
+//! toolchains for the `rust_toolchain` rule of `rules_rust`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::Cfg,
+    toolchain::{LocalToolchainName, ResolvableLocalToolchainName, Toolchain, ToolchainName},
+    utils::ExitCode,
+};
+
+/// The tools described, by the name of their executable.
+const TOOLS: &[&str] = &["rustc", "rustdoc", "cargo", "clippy-driver", "rustfmt"];
+
+#[derive(Serialize)]
+struct Description {
+    name: String,
+    path: PathBuf,
+    version: String,
+    host: String,
+    layout_hash: String,
+    tools: BTreeMap<&'static str, Tool>,
+    targets: BTreeMap<String, Target>,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    path: PathBuf,
+    sha256: String,
+}
+
+#[derive(Serialize)]
+struct Target {
+    std_lib: PathBuf,
+}
+
+pub(super) fn describe(
+    cfg: &Cfg<'_>,
+    toolchain: Option<ResolvableLocalToolchainName>,
+    bazel: bool,
+) -> Result<ExitCode> {
+    let toolchain = cfg.resolve_local_toolchain(toolchain)?;
+    let description = Description::new(cfg, &toolchain)?;
+    let output = match bazel {
+        true => description.to_bazel(),
+        false => serde_json::to_string_pretty(&description)? + "\n",
+    };
+    cfg.process.stdout().lock().write_all(output.as_bytes())?;
+    Ok(ExitCode(0))
+}
+
+impl Description {
+    fn new(cfg: &Cfg<'_>, toolchain: &Toolchain<'_>) -> Result<Self> {
+        let path = toolchain.path();
+        let host = match toolchain.name() {
+            LocalToolchainName::Named(ToolchainName::Official(desc)) => desc.target.to_string(),
+            _ => cfg.get_default_host_triple()?.to_string(),
+        };
+
+        let mut tools = BTreeMap::new();
+        for &name in TOOLS {
+            let path = toolchain.binary_file(name);
+            if !path.is_file() {
+                continue;
+            }
+            let mut hasher = Sha256::new();
+            io::copy(&mut File::open(&path)?, &mut hasher)?;
+            let sha256 = format!("{:x}", hasher.finalize());
+            tools.insert(name, Tool { path, sha256 });
+        }
+
+        // Any directory in `lib/rustlib` with a `lib` directory of its own
+        // holds the standard library for that target.
+        let mut targets = BTreeMap::new();
+        if let Ok(entries) = fs::read_dir(path.join("lib").join("rustlib")) {
+            for entry in entries {
+                let entry = entry?;
+                let std_lib = entry.path().join("lib");
+                if std_lib.is_dir() {
+                    let target = entry.file_name().to_string_lossy().into_owned();
+                    targets.insert(target, Target { std_lib });
+                }
+            }
+        }
+
+        Ok(Self {
+            name: toolchain.name().to_string(),
+            path: path.to_owned(),
+            version: toolchain.rustc_version(),
+            host,
+            layout_hash: toolchain.layout_hash()?,
+            tools,
+            targets,
+        })
+    }
+
+    /// Renders a `BUILD` file for a `new_local_repository` rooted at the
+    /// toolchain, with a `rust_toolchain` for each installed target.
+    fn to_bazel(&self) -> String {
+        let relative = |path: &Path| {
+            let path = path.strip_prefix(&self.path).unwrap_or(path);
+            path.to_string_lossy().replace('\\', "/")
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# Generated by `rustup describe-toolchain --bazel` for {} ({}).\n\
+             #\n\
+             # Use this file as the `build_file` of a `new_local_repository` with\n\
+             #     path = {:?}\n\
+             # and register its toolchains with `register_toolchains(\"@<repo>//:all\")`.\n",
+            self.name,
+            self.version,
+            self.path.to_string_lossy(),
+        );
+        out.push_str(
+            "load(\"@rules_rust//rust:toolchain.bzl\", \"rust_stdlib_filegroup\", \"rust_toolchain\")\n\
+             load(\"@rules_rust//rust/platform:triple_mappings.bzl\", \"triple_to_constraint_set\")\n\
+             \n\
+             package(default_visibility = [\"//visibility:public\"])\n\n",
+        );
+
+        // The libraries rustc itself links against, next to it on Windows.
+        let rustc_lib = match self.host.contains("windows") {
+            true => "bin/*.dll",
+            false => "lib/*",
+        };
+        let _ = writeln!(
+            out,
+            "filegroup(\n    name = \"rustc_lib\",\n    \
+             srcs = glob([\"{rustc_lib}\"], allow_empty = True),\n)\n"
+        );
+
+        for (triple, target) in &self.targets {
+            let info = TargetInfo::new(triple);
+            let _ = writeln!(
+                out,
+                "rust_stdlib_filegroup(\n    name = \"rust_std-{triple}\",\n    \
+                 srcs = glob([\"{}/*\"]),\n)\n",
+                relative(&target.std_lib),
+            );
+
+            let _ = writeln!(
+                out,
+                "rust_toolchain(\n    name = \"rust_toolchain-{triple}\","
+            );
+            for (attr, tool) in [
+                ("rustc", "rustc"),
+                ("rust_doc", "rustdoc"),
+                ("cargo", "cargo"),
+                ("clippy_driver", "clippy-driver"),
+                ("rustfmt", "rustfmt"),
+            ] {
+                if let Some(tool) = self.tools.get(tool) {
+                    let _ = writeln!(out, "    {attr} = \"{}\",", relative(&tool.path));
+                }
+            }
+            let linkflags = info
+                .stdlib_linkflags
+                .iter()
+                .map(|flag| format!("\"{flag}\""))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let _ = writeln!(
+                out,
+                "    rustc_lib = \":rustc_lib\",\n    \
+                 rust_std = \":rust_std-{triple}\",\n    \
+                 binary_ext = \"{}\",\n    \
+                 staticlib_ext = \"{}\",\n    \
+                 dylib_ext = \"{}\",\n    \
+                 stdlib_linkflags = [{linkflags}],\n    \
+                 exec_triple = \"{}\",\n    \
+                 target_triple = \"{triple}\",\n    \
+                 default_edition = \"2021\",\n)\n",
+                info.binary_ext, info.staticlib_ext, info.dylib_ext, self.host,
+            );
+
+            let _ = writeln!(
+                out,
+                "toolchain(\n    name = \"toolchain-{triple}\",\n    \
+                 exec_compatible_with = triple_to_constraint_set(\"{}\"),\n    \
+                 target_compatible_with = triple_to_constraint_set(\"{triple}\"),\n    \
+                 toolchain = \":rust_toolchain-{triple}\",\n    \
+                 toolchain_type = \"@rules_rust//rust:toolchain_type\",\n)\n",
+                self.host,
+            );
+        }
+
+        out.truncate(out.trim_end().len());
+        out.push('\n');
+        out
+    }
+}
+
+/// What `rust_toolchain` needs to know about a target beyond its triple.
+struct TargetInfo {
+    binary_ext: &'static str,
+    staticlib_ext: &'static str,
+    dylib_ext: &'static str,
+    stdlib_linkflags: &'static [&'static str],
+}
+
+impl TargetInfo {
+    fn new(triple: &str) -> Self {
+        if triple.contains("windows-msvc") {
+            Self {
+                binary_ext: ".exe",
+                staticlib_ext: ".lib",
+                dylib_ext: ".dll",
+                stdlib_linkflags: &["advapi32.lib", "ws2_32.lib", "userenv.lib", "bcrypt.lib"],
+            }
+        } else if triple.contains("windows") {
+            Self {
+                binary_ext: ".exe",
+                staticlib_ext: ".a",
+                dylib_ext: ".dll",
+                stdlib_linkflags: &["-ladvapi32", "-lws2_32", "-luserenv"],
+            }
+        } else if triple.contains("apple") {
+            Self {
+                binary_ext: "",
+                staticlib_ext: ".a",
+                dylib_ext: ".dylib",
+                stdlib_linkflags: &["-lSystem", "-lresolv"],
+            }
+        } else if triple.starts_with("wasm32") {
+            Self {
+                binary_ext: ".wasm",
+                staticlib_ext: ".a",
+                dylib_ext: ".wasm",
+                stdlib_linkflags: &[],
+            }
+        } else if triple.contains("linux") {
+            Self {
+                binary_ext: "",
+                staticlib_ext: ".a",
+                dylib_ext: ".so",
+                stdlib_linkflags: &["-ldl", "-lpthread"],
+            }
+        } else {
+            Self {
+                binary_ext: "",
+                staticlib_ext: ".a",
+                dylib_ext: ".so",
+                stdlib_linkflags: &[],
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! `rustup self diagnose-tls`: what each TLS backend makes of a server.
+//!
+//! Reports of downloads failing with `UnknownIssuer` are rarely about the
+//! dist server itself, but about what sits between it and the user. Showing
+//! the certificates actually received, and where each backend looks for the
+//! roots it trusts, is usually enough to tell what that is.
+
+use anyhow::Result;
+use url::Url;
+
+use crate::{config::Cfg, utils};
+
+#[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
+pub(crate) fn diagnose(cfg: &Cfg<'_>, server: Option<Url>) -> Result<utils::ExitCode> {
+    use std::io::Write;
+
+    let server = match server {
+        Some(server) => server,
+        None => utils::parse_url(&crate::config::dist_root_server(cfg.process)?)?,
+    };
+    let diagnoses = download::diagnose_tls(&server)?;
+
+    let stdout = cfg.process.stdout();
+    let mut stdout = stdout.lock();
+    writeln!(stdout, "server: {server}")?;
+    match download::proxy_for(&server) {
+        Some(proxy) => writeln!(stdout, "proxy: {proxy}")?,
+        None => writeln!(stdout, "proxy: none")?,
+    }
+
+    let mut failed = false;
+    for diagnosis in &diagnoses {
+        writeln!(stdout)?;
+        match (&diagnosis.error, &diagnosis.protocol) {
+            (Some(_), _) => writeln!(stdout, "{}: failed", diagnosis.backend)?,
+            (None, Some(protocol)) => writeln!(stdout, "{}: ok ({protocol})", diagnosis.backend)?,
+            (None, None) => writeln!(stdout, "{}: ok", diagnosis.backend)?,
+        }
+        writeln!(stdout, "  verifier: {}", diagnosis.verifier)?;
+        if let Some(error) = &diagnosis.error {
+            failed = true;
+            writeln!(stdout, "  error: {error}")?;
+        }
+        if let Some(hint) = diagnosis.hint {
+            writeln!(stdout, "  hint: {hint}")?;
+        }
+        if diagnosis.chain.is_empty() {
+            writeln!(stdout, "  no certificates received")?;
+            continue;
+        }
+        writeln!(stdout, "  certificates received:")?;
+        for (i, cert) in diagnosis.chain.iter().enumerate() {
+            writeln!(stdout, "    {i}: {}", cert.subject)?;
+            writeln!(stdout, "       issuer: {}", cert.issuer)?;
+            writeln!(stdout, "       expires: {}", cert.not_after)?;
+            writeln!(stdout, "       sha256: {}", cert.sha256)?;
+        }
+    }
+
+    Ok(utils::ExitCode(if failed { 1 } else { 0 }))
+}
+
+#[cfg(not(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls")))]
+pub(crate) fn diagnose(_: &Cfg<'_>, _: Option<Url>) -> Result<utils::ExitCode> {
+    anyhow::bail!("this build of rustup has no TLS backends to diagnose")
+}
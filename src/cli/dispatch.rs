@@ -0,0 +1,118 @@
+//! Dispatch on the name rustup was invoked as.
+//!
+//! The rustup binary is a chimera: it is the rustup CLI, the installer or a
+//! proxy for a toolchain tool depending on its name. The same dispatch is
+//! used by the binary, by embedders and by the in-process test harness.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use cfg_if::cfg_if;
+use tracing_subscriber::{reload::Handle, EnvFilter, Registry};
+
+#[cfg(windows)]
+use crate::cli::self_update;
+use crate::{
+    cli::{errors::CLIError, proxy_mode, rustup_mode, setup_mode},
+    env_var::RUST_RECURSION_COUNT_MAX,
+    errors::RustupError,
+    is_proxyable_tools,
+    process::Process,
+    utils,
+};
+
+#[tracing::instrument(level = "trace", err(level = "trace"))]
+pub async fn main(
+    process: &Process,
+    console_filter: Handle<EnvFilter, Registry>,
+) -> Result<utils::ExitCode> {
+    // Guard against infinite proxy recursion. This mostly happens due to
+    // bugs in rustup.
+    do_recursion_guard(process)?;
+
+    // Before we do anything else, ensure we know where we are and who we
+    // are because otherwise we cannot proceed usefully.
+    let current_dir = process
+        .current_dir()
+        .context(RustupError::LocatingWorkingDir)?;
+    utils::current_exe()?;
+
+    match process.name().as_deref() {
+        Some("rustup") => rustup_mode::main(current_dir, process, console_filter).await,
+        Some(n) if n.starts_with("rustup-setup") || n.starts_with("rustup-init") => {
+            // NB: The above check is only for the prefix of the file
+            // name. Browsers rename duplicates to
+            // e.g. rustup-setup(2), and this allows all variations
+            // to work.
+            setup_mode::main(current_dir, process, console_filter).await
+        }
+        Some(n) if n.starts_with("rustup-gc-") => {
+            // This is the final uninstallation stage on windows where
+            // rustup deletes its own exe
+            cfg_if! {
+                if #[cfg(windows)] {
+                    self_update::complete_windows_uninstall(process)
+                } else {
+                    unreachable!("Attempted to use Windows-specific code on a non-Windows platform. Aborting.")
+                }
+            }
+        }
+        Some(n) => {
+            is_proxyable_tools(n)?;
+            run_proxy(n, current_dir, process, console_filter).await
+        }
+        None => {
+            // Weird case. No arg0, or it's unparsable.
+            Err(CLIError::NoExeName.into())
+        }
+    }
+}
+
+/// Whether rustup was invoked as a proxy for a toolchain tool rather than as
+/// rustup itself.
+pub fn is_proxy(process: &Process) -> bool {
+    process
+        .name()
+        .is_some_and(|name| is_proxyable_tools(&name).is_ok())
+}
+
+/// Runs the toolchain tool rustup was invoked as a proxy for, which
+/// [`is_proxy`] has checked, without going through rustup's other modes.
+#[tracing::instrument(level = "trace", err(level = "trace"))]
+pub async fn proxy(
+    process: &Process,
+    console_filter: Handle<EnvFilter, Registry>,
+) -> Result<utils::ExitCode> {
+    do_recursion_guard(process)?;
+    let current_dir = process
+        .current_dir()
+        .context(RustupError::LocatingWorkingDir)?;
+    utils::current_exe()?;
+    let arg0 = process.name().ok_or(CLIError::NoExeName)?;
+    run_proxy(&arg0, current_dir, process, console_filter).await
+}
+
+async fn run_proxy(
+    arg0: &str,
+    current_dir: PathBuf,
+    process: &Process,
+    console_filter: Handle<EnvFilter, Registry>,
+) -> Result<utils::ExitCode> {
+    let args = process.args_os().skip(1).collect();
+    proxy_mode::main(arg0, args, current_dir, process, console_filter)
+        .await
+        .map(utils::ExitCode::from)
+}
+
+fn do_recursion_guard(process: &Process) -> Result<()> {
+    let recursion_count = process
+        .var("RUST_RECURSION_COUNT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+    if recursion_count > RUST_RECURSION_COUNT_MAX {
+        return Err(anyhow!("infinite recursion detected"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,602 @@
+//! `rustup self doctor`: whether the installed rustup can start. `rustup
+//! doctor`: whether the rest of the setup around it is what rustup expects.
+//!
+//! A rustup built against the dynamic Visual C++ runtime doesn't start on a
+//! fresh Windows install, and all the user sees is a loader dialog naming one
+//! DLL. Running the binary and reading what it imports turns that into an
+//! explanation, both here and before a self-update replaces a working rustup.
+//!
+//! Most other reports of a broken rustup come down to a handful of causes
+//! outside of it: another `rustc` earlier in `PATH`, proxies left behind by
+//! an older rustup, a linked toolchain whose directory has moved, a system
+//! without CA certificates, two installations each half in use, a rustup
+//! home on a network file system, and files in it left to root by `sudo`.
+//! `rustup doctor` looks for each, and says how to fix what it finds.
+
+use std::env::{self, consts::EXE_SUFFIX};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use download::TlsRoots;
+
+use crate::{
+    cli::self_update,
+    config::Cfg,
+    diskio::storage::Storage,
+    process::Process,
+    utils::{self, permissions},
+    TOOLS,
+};
+
+/// The exit status the Windows loader gives a process missing a DLL.
+const STATUS_DLL_NOT_FOUND: u32 = 0xC000_0135;
+/// The exit status the Windows loader gives a process importing a function
+/// its DLL doesn't have, as with an outdated runtime.
+const STATUS_ENTRYPOINT_NOT_FOUND: u32 = 0xC000_0139;
+
+pub(crate) fn doctor(process: &Process) -> Result<utils::ExitCode> {
+    let rustup = process
+        .cargo_home()?
+        .join("bin")
+        .join(format!("rustup{EXE_SUFFIX}"));
+
+    let stdout = process.stdout();
+    let mut stdout = stdout.lock();
+    let mut ok = true;
+    writeln!(stdout, "rustup: {}", rustup.display())?;
+    if !rustup.exists() {
+        writeln!(stdout, "  not installed")?;
+        ok = false;
+    } else {
+        match check_starts(&rustup) {
+            Ok(version) => writeln!(stdout, "  starts: ok ({version})")?,
+            Err(e) => {
+                writeln!(stdout, "  starts: failed")?;
+                writeln!(stdout, "  error: {e}")?;
+                ok = false;
+            }
+        }
+    }
+    Ok(utils::ExitCode(if ok { 0 } else { 1 }))
+}
+
+/// Something `rustup doctor` found wrong, and how to put it right.
+pub(super) struct Problem {
+    pub(super) what: String,
+    pub(super) fix: String,
+}
+
+pub(crate) fn check_setup(cfg: &Cfg<'_>, fix_permissions: bool) -> Result<utils::ExitCode> {
+    let checks = [
+        ("PATH", check_path(cfg)?),
+        ("proxies", check_proxies(cfg.process)?),
+        ("linked toolchains", check_linked_toolchains(cfg)?),
+        ("CA certificates", check_ca_certificates(cfg.process)),
+        ("temporary directory", check_tmp_dir(cfg)),
+        (
+            "storage",
+            check_storage(&cfg.rustup_dir, Storage::of(&cfg.rustup_dir)),
+        ),
+        ("homes", check_homes(cfg)?),
+        ("ownership", check_ownership(cfg, fix_permissions)?),
+    ];
+
+    let stdout = cfg.process.stdout();
+    let mut stdout = stdout.lock();
+    let mut ok = true;
+    for (name, problems) in checks {
+        if problems.is_empty() {
+            writeln!(stdout, "{name}: ok")?;
+            continue;
+        }
+        ok = false;
+        match problems.len() {
+            1 => writeln!(stdout, "{name}: 1 problem")?,
+            n => writeln!(stdout, "{name}: {n} problems")?,
+        }
+        for Problem { what, fix } in problems {
+            writeln!(stdout, "  {what}")?;
+            writeln!(stdout, "    fix: {fix}")?;
+        }
+    }
+    Ok(utils::ExitCode(if ok { 0 } else { 1 }))
+}
+
+/// Whether `CARGO_HOME/bin` is in `PATH`, and nothing before it has its own
+/// `rustup`, `rustc` or `cargo`. A Rust installation the user chose to keep
+/// ahead of rustup during setup is left alone, and so are `rustc` and `cargo`
+/// once the proxies are moved out of the way with `rustup set proxy-dir`.
+fn check_path(cfg: &Cfg<'_>) -> Result<Vec<Problem>> {
+    let process = cfg.process;
+    let kept_first = cfg
+        .settings_file
+        .with(|s| Ok(s.system_rust.clone()))?
+        .filter(|system_rust| !system_rust.rustup_first)
+        .map(|system_rust| system_rust.path);
+    let tools: &[&str] = match cfg.settings_file.with(|s| Ok(s.proxy_dir.is_some()))? {
+        true => &["rustup"],
+        false => &["rustup", "rustc", "cargo"],
+    };
+    let cargo_home = process.cargo_home()?;
+    let bin = cargo_home.join("bin");
+    let paths = process
+        .var_os("PATH")
+        .map(|paths| env::split_paths(&paths).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let Some(position) = paths.iter().position(|dir| is_same_path(dir, &bin)) else {
+        let env_script = cargo_home.join("env");
+        let fix = if cfg!(windows) {
+            format!("add '{}' to PATH, then open a new terminal", bin.display())
+        } else if env_script.exists() {
+            format!(
+                "add `. \"{}\"` to your shell's startup file, then start a new shell",
+                env_script.display()
+            )
+        } else {
+            format!(
+                "add '{}' to PATH in your shell's startup file, then start a new shell",
+                bin.display()
+            )
+        };
+        return Ok(vec![Problem {
+            what: format!("'{}' is not in PATH", bin.display()),
+            fix,
+        }]);
+    };
+
+    let mut problems = Vec::new();
+    for dir in paths[..position]
+        .iter()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .filter(|dir| {
+            !kept_first
+                .as_ref()
+                .is_some_and(|kept| is_same_path(dir, kept))
+        })
+    {
+        for tool in tools {
+            let exe = dir.join(format!("{tool}{EXE_SUFFIX}"));
+            if !exe.is_file() || is_same_path(&exe, &bin.join(format!("{tool}{EXE_SUFFIX}"))) {
+                continue;
+            }
+            problems.push(Problem {
+                what: format!("'{}' comes before rustup's {tool} in PATH", exe.display()),
+                fix: format!(
+                    "remove it, or put '{}' before '{}' in PATH",
+                    bin.display(),
+                    dir.display()
+                ),
+            });
+        }
+    }
+    Ok(problems)
+}
+
+/// Whether each proxy, in `CARGO_HOME/bin` or the directory they were moved
+/// to, is rustup, rather than missing or
+/// a copy of an older rustup that a self-update didn't replace.
+///
+/// `rustfmt`, `cargo-fmt` and `rust-analyzer` are left out, as rustup leaves
+/// them alone when they were installed some other way.
+fn check_proxies(process: &Process) -> Result<Vec<Problem>> {
+    let bin = self_update::proxy_dir(process)?;
+    let rustup = process
+        .cargo_home()?
+        .join("bin")
+        .join(format!("rustup{EXE_SUFFIX}"));
+    // A missing rustup is for `check_homes` and `rustup self doctor`.
+    if !rustup.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut problems = Vec::new();
+    for tool in TOOLS {
+        let proxy = bin.join(format!("{tool}{EXE_SUFFIX}"));
+        let what = if !proxy.exists() {
+            format!("'{}' is missing", proxy.display())
+        } else if is_same_path(&proxy, &rustup) || same_contents(&proxy, &rustup)? {
+            continue;
+        } else {
+            format!(
+                "'{}' is not rustup, and may be left from an older rustup",
+                proxy.display()
+            )
+        };
+        problems.push(Problem {
+            what,
+            fix: "run `rustup self update` to link the proxies to rustup again".to_owned(),
+        });
+    }
+    Ok(problems)
+}
+
+/// Whether each toolchain made with `rustup toolchain link` still links to a
+/// toolchain.
+fn check_linked_toolchains(cfg: &Cfg<'_>) -> Result<Vec<Problem>> {
+    let entries = match fs::read_dir(&cfg.toolchains_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut links = entries
+        .map(|entry| Ok(entry?.path()))
+        .filter(|path| {
+            path.as_ref()
+                .map_or(true, |path: &PathBuf| path.is_symlink())
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    links.sort();
+
+    let mut problems = Vec::new();
+    for link in links {
+        let name = link.file_name().unwrap_or_default().to_string_lossy();
+        let target = fs::read_link(&link)?;
+        let rustc = format!("bin/rustc{EXE_SUFFIX}");
+        let what = if !link.exists() {
+            format!(
+                "toolchain '{name}' links to '{}', which doesn't exist",
+                target.display()
+            )
+        } else if !link.join(&rustc).exists() {
+            format!(
+                "toolchain '{name}' links to '{}', which has no '{rustc}'",
+                target.display()
+            )
+        } else {
+            continue;
+        };
+        problems.push(Problem {
+            what,
+            fix: format!(
+                "run `rustup toolchain uninstall {name}`, then `rustup toolchain link {name} <path>` \
+                 with where the toolchain is now"
+            ),
+        });
+    }
+    Ok(problems)
+}
+
+/// The CA bundles that OpenSSL looks for on the various Unix systems,
+/// where the platform's certificate store is read from.
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios"))))]
+const CA_BUNDLES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/pki/ca-trust/extracted/pem/tls-ca-bundle.pem",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/pki/tls/cacert.pem",
+    "/etc/ssl/cert.pem",
+    "/usr/local/share/certs/ca-root-nss.crt",
+    "/usr/local/etc/ssl/cert.pem",
+    "/etc/openssl/certs/ca-certificates.crt",
+];
+
+/// Whether downloads have root certificates to verify servers with.
+///
+/// Windows and macOS always have a certificate store, so only the other
+/// systems are checked for one.
+fn check_ca_certificates(process: &Process) -> Vec<Problem> {
+    let file = match download::tls_roots() {
+        // Enough for the official servers, whatever the platform has.
+        TlsRoots::Bundled => return Vec::new(),
+        TlsRoots::File(file) => file,
+        TlsRoots::Platform => return check_platform_ca_certificates(process),
+    };
+    let what = match fs::read_to_string(&file) {
+        Ok(pem) if pem.contains("-----BEGIN CERTIFICATE-----") => return Vec::new(),
+        Ok(_) => format!("'{}' contains no certificates", file.display()),
+        Err(e) => format!("could not read '{}': {e}", file.display()),
+    };
+    vec![Problem {
+        what,
+        fix: "run `rustup set tls-root-source` with a PEM file of root certificates, \
+              or `rustup set tls-root-source platform`"
+            .to_owned(),
+    }]
+}
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios"))))]
+fn check_platform_ca_certificates(process: &Process) -> Vec<Problem> {
+    for var in ["SSL_CERT_FILE", "SSL_CERT_DIR"] {
+        let Some(path) = process.var_os(var).map(PathBuf::from) else {
+            continue;
+        };
+        if path.exists() {
+            return Vec::new();
+        }
+        return vec![Problem {
+            what: format!("{var} is '{}', which doesn't exist", path.display()),
+            fix: format!("point {var} at your system's CA certificates, or unset it"),
+        }];
+    }
+    if CA_BUNDLES.iter().any(|bundle| Path::new(bundle).is_file()) {
+        return Vec::new();
+    }
+    vec![Problem {
+        what: "no CA certificates were found in the system's certificate store".to_owned(),
+        fix: "install your distribution's `ca-certificates` package, \
+              or run `rustup set tls-root-source bundled`"
+            .to_owned(),
+    }]
+}
+
+#[cfg(not(all(unix, not(any(target_os = "macos", target_os = "ios")))))]
+fn check_platform_ca_certificates(_: &Process) -> Vec<Problem> {
+    Vec::new()
+}
+
+/// Whether `RUSTUP_HOME/tmp` is on the same file system as the directories
+/// rustup moves files from it into. Across file systems, every file is
+/// copied instead, which is slower and leaves an interrupted update less to
+/// roll back with.
+///
+/// Only Unix tells which file system a directory is on, so elsewhere
+/// nothing is checked.
+fn check_tmp_dir(cfg: &Cfg<'_>) -> Vec<Problem> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        // What doesn't exist yet is made in the nearest directory that does.
+        let device = |path: &Path| {
+            path.ancestors()
+                .find_map(|dir| fs::metadata(dir).ok())
+                .map(|meta| meta.dev())
+        };
+        let tmp = cfg.rustup_dir.join("tmp");
+        let Some(tmp_device) = device(&tmp) else {
+            return Vec::new();
+        };
+        let mut problems = Vec::new();
+        for dir in [&cfg.toolchains_dir, &cfg.download_dir] {
+            if device(dir).is_some_and(|device| device != tmp_device) {
+                problems.push(Problem {
+                    what: format!(
+                        "'{}' is on another file system than '{}', so files are copied \
+                         between them instead of moved",
+                        tmp.display(),
+                        dir.display()
+                    ),
+                    fix: format!(
+                        "make '{}' a directory on the same file system as '{}'",
+                        tmp.display(),
+                        dir.display()
+                    ),
+                });
+            }
+        }
+        problems
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = cfg;
+        Vec::new()
+    }
+}
+
+/// Whether RUSTUP_HOME is on local storage. On a network file system rustup
+/// is slower, and running it from two machines sharing the directory at
+/// once can break toolchains.
+fn check_storage(home: &Path, storage: Storage) -> Vec<Problem> {
+    if storage != Storage::Network {
+        return Vec::new();
+    }
+    vec![Problem {
+        what: format!("'{}' is on a network file system", home.display()),
+        fix: "set RUSTUP_HOME to a local directory, or don't run rustup on more \
+              than one machine sharing it at once"
+            .to_owned(),
+    }]
+}
+
+/// Whether everything in RUSTUP_HOME belongs to its user, who can read and
+/// change it. With `fix`, gives what doesn't back to that user first, so
+/// only what couldn't be fixed is reported.
+fn check_ownership(cfg: &Cfg<'_>, fix: bool) -> Result<Vec<Problem>> {
+    let home = &cfg.rustup_dir;
+    if !home.exists() {
+        return Ok(Vec::new());
+    }
+    let (uid, gid) = permissions::intended_owner(home, cfg.process);
+    let mut problems = permissions::audit(home, uid);
+    if fix && !problems.is_empty() {
+        permissions::fix(&problems, uid, gid)?;
+        problems = permissions::audit(home, uid);
+    }
+    Ok(problems
+        .into_iter()
+        .map(|problem| Problem {
+            what: problem.to_string(),
+            fix: format!(
+                "run `sudo env RUSTUP_HOME='{}' rustup doctor --fix-permissions`",
+                home.display()
+            ),
+        })
+        .collect())
+}
+
+/// Whether RUSTUP_HOME and CARGO_HOME belong to the same installation, and
+/// no other installation in the default places is left half in use.
+fn check_homes(cfg: &Cfg<'_>) -> Result<Vec<Problem>> {
+    let process = cfg.process;
+    let cargo_home = process.cargo_home()?;
+    let mut problems = Vec::new();
+
+    // Setting one of them and not the other is the usual way to end up
+    // with half of each installation.
+    let rustup = cargo_home.join("bin").join(format!("rustup{EXE_SUFFIX}"));
+    let installed = cfg.rustup_dir.join("settings.toml").exists();
+    match (
+        process.var_os("RUSTUP_HOME").is_some(),
+        process.var_os("CARGO_HOME").is_some(),
+    ) {
+        (true, false) if installed && !rustup.exists() => problems.push(Problem {
+            what: format!(
+                "RUSTUP_HOME is set but CARGO_HOME isn't, and '{}' has no rustup",
+                cargo_home.display()
+            ),
+            fix: "set CARGO_HOME to where rustup was installed along with RUSTUP_HOME".to_owned(),
+        }),
+        (false, true) if rustup.exists() && !installed => problems.push(Problem {
+            what: format!(
+                "CARGO_HOME is set but RUSTUP_HOME isn't, and '{}' has no toolchains",
+                cfg.rustup_dir.display()
+            ),
+            fix: "set RUSTUP_HOME to where rustup was installed along with CARGO_HOME".to_owned(),
+        }),
+        _ => {}
+    }
+
+    let Some(home) = process.home_dir() else {
+        return Ok(problems);
+    };
+    let default_rustup_home = home.join(".rustup");
+    if !is_same_path(&default_rustup_home, &cfg.rustup_dir)
+        && default_rustup_home.join("settings.toml").exists()
+    {
+        problems.push(Problem {
+            what: format!(
+                "RUSTUP_HOME is '{}', but '{}' has another rustup installation",
+                cfg.rustup_dir.display(),
+                default_rustup_home.display()
+            ),
+            fix: format!(
+                "unset RUSTUP_HOME to use that one, or remove '{}' if it's no longer needed",
+                default_rustup_home.display()
+            ),
+        });
+    }
+    let default_cargo_home = home.join(".cargo");
+    if !is_same_path(&default_cargo_home, &cargo_home)
+        && default_cargo_home
+            .join("bin")
+            .join(format!("rustup{EXE_SUFFIX}"))
+            .exists()
+    {
+        problems.push(Problem {
+            what: format!(
+                "CARGO_HOME is '{}', but '{}' has another rustup",
+                cargo_home.display(),
+                default_cargo_home.display()
+            ),
+            fix: "unset CARGO_HOME to use that one, or run `rustup self uninstall` with \
+                  CARGO_HOME and RUSTUP_HOME unset to remove it"
+                .to_owned(),
+        });
+    }
+    Ok(problems)
+}
+
+/// Whether `a` and `b` are the same file or directory, by any path.
+fn is_same_path(a: &Path, b: &Path) -> bool {
+    a == b || same_file::is_same_file(a, b).unwrap_or(false)
+}
+
+/// Whether the files `a` and `b` have the same contents.
+fn same_contents(a: &Path, b: &Path) -> Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    Ok(fs::read(a)? == fs::read(b)?)
+}
+
+/// Runs `binary --version`, returning what it prints, or an error that says
+/// what stopped it from starting.
+pub(crate) fn check_starts(binary: &Path) -> Result<String> {
+    let output = Command::new(binary)
+        .arg("--version")
+        .output()
+        .with_context(|| format!("could not run '{}'", binary.display()))?;
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned());
+    }
+
+    let code = output.status.code().map(|code| code as u32);
+    if !matches!(
+        code,
+        Some(STATUS_DLL_NOT_FOUND | STATUS_ENTRYPOINT_NOT_FOUND)
+    ) {
+        bail!(
+            "'{}' exited with {}: {}",
+            binary.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let runtime = utils::pe::imports(binary)
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|dll| is_vc_runtime(dll))
+        .collect::<Vec<_>>();
+    if runtime.is_empty() {
+        bail!(
+            "'{}' could not start, as a DLL it needs is missing or outdated",
+            binary.display()
+        );
+    }
+    bail!(
+        "'{}' could not start, as the Visual C++ runtime it needs ({}) is missing or outdated; \
+         install the Visual C++ Redistributable from {}, \
+         or use a rustup built with a static C runtime",
+        binary.display(),
+        runtime.join(", "),
+        vc_redist_url(),
+    )
+}
+
+/// Whether `dll` is part of the Visual C++ runtime, which a fresh Windows
+/// install may lack, rather than of Windows itself.
+fn is_vc_runtime(dll: &str) -> bool {
+    let dll = dll.to_ascii_lowercase();
+    [
+        "vcruntime",
+        "msvcp",
+        "concrt",
+        "ucrtbase",
+        "api-ms-win-crt-",
+    ]
+    .iter()
+    .any(|prefix| dll.starts_with(prefix))
+}
+
+/// Where to download the Visual C++ Redistributable for this architecture.
+fn vc_redist_url() -> String {
+    let arch = match std::env::consts::ARCH {
+        "x86" => "x86",
+        "aarch64" => "arm64",
+        _ => "x64",
+    };
+    format!("https://aka.ms/vs/17/release/vc_redist.{arch}.exe")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_home_is_reported() {
+        let home = Path::new("/home/user/.rustup");
+        assert!(check_storage(home, Storage::Solid).is_empty());
+        let problems = check_storage(home, Storage::Network);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(
+            problems[0].what,
+            "'/home/user/.rustup' is on a network file system"
+        );
+    }
+
+    #[test]
+    fn vc_runtime_dlls() {
+        assert!(is_vc_runtime("VCRUNTIME140.dll"));
+        assert!(is_vc_runtime("MSVCP140.dll"));
+        assert!(is_vc_runtime("api-ms-win-crt-runtime-l1-1-0.dll"));
+        assert!(!is_vc_runtime("KERNEL32.dll"));
+        assert!(!is_vc_runtime("api-ms-win-core-synch-l1-2-0.dll"));
+    }
+}
@@ -3,8 +3,10 @@ use std::fmt;
 use std::io::Write;
 use std::time::{Duration, Instant};
 
+use tracing::{debug, info};
+
 use crate::dist::Notification as In;
-use crate::notifications::Notification;
+use crate::notifications::{InstallStats, Notification};
 use crate::process::{terminalsource, Process};
 use crate::utils::units::{Size, Unit, UnitMode};
 use crate::utils::Notification as Un;
@@ -14,11 +16,13 @@ const DOWNLOAD_TRACK_COUNT: usize = 5;
 
 /// Tracks download progress and displays information about it to a terminal.
 ///
-/// *not* safe for tracking concurrent downloads yet - it is basically undefined
-/// what will happen.
+/// Concurrent downloads are shown as one, adding up their lengths and the
+/// data received, until the last of them finishes.
 pub(crate) struct DownloadTracker {
-    /// Content-Length of the to-be downloaded object.
+    /// Content-Length of the to-be downloaded objects.
     content_len: Option<usize>,
+    /// Downloads started and not yet finished.
+    active_downloads: usize,
     /// Total data downloaded in bytes.
     total_downloaded: usize,
     /// Data downloaded this second.
@@ -41,11 +45,18 @@ pub(crate) struct DownloadTracker {
     /// If we have displayed progress, this is the number of characters we
     /// rendered, so we can erase it cleanly.
     displayed_charcount: Option<usize>,
+    /// Whether progress is shown as a line for every tenth of the download,
+    /// instead of a line redrawn in place, for the plain output style.
+    plain_output: bool,
+    /// The tenths of the download shown so far in plain output.
+    displayed_tenths: Option<usize>,
     /// What units to show progress in
     units: Vec<Unit>,
     /// Whether we display progress
     display_progress: bool,
     stdout_is_a_tty: bool,
+    /// Statistics about the install or update in progress, if any.
+    summary: Option<InstallSummary>,
 }
 
 impl DownloadTracker {
@@ -53,6 +64,7 @@ impl DownloadTracker {
     pub(crate) fn new_with_display_progress(display_progress: bool, process: &Process) -> Self {
         Self {
             content_len: None,
+            active_downloads: 0,
             total_downloaded: 0,
             downloaded_this_sec: 0,
             downloaded_last_few_secs: VecDeque::with_capacity(DOWNLOAD_TRACK_COUNT),
@@ -60,27 +72,69 @@ impl DownloadTracker {
             last_sec: None,
             term: process.stdout().terminal(process),
             displayed_charcount: None,
+            plain_output: process.stdout().is_a_tty(process) && process.plain_output(),
+            displayed_tenths: None,
             units: vec![Unit::B],
             display_progress,
             stdout_is_a_tty: process.stdout().is_a_tty(process),
+            summary: None,
         }
     }
 
     pub(crate) fn handle_notification(&mut self, n: &Notification<'_>) -> bool {
         match *n {
+            Notification::Install(In::DownloadingComponent(..)) => {
+                // A summary that has reached the install phase belongs to an
+                // update that failed, so start over.
+                if !matches!(&self.summary, Some(s) if s.install_start.is_none()) {
+                    self.summary = Some(InstallSummary::new());
+                }
+                false
+            }
+            Notification::Install(In::FileAlreadyDownloaded) => {
+                if let Some(summary) = &mut self.summary {
+                    summary.cache_hits += 1;
+                }
+                false
+            }
+            Notification::Install(In::InstallingComponent(..)) => {
+                if let Some(summary) = &mut self.summary {
+                    summary.installing();
+                }
+                false
+            }
+            Notification::Install(In::UpdateFinished) => {
+                if let Some(summary) = self.summary.take() {
+                    self.show_summary(&summary);
+                }
+                true
+            }
             Notification::Install(In::Utils(Un::DownloadContentLengthReceived(content_len))) => {
                 self.content_length_received(content_len);
 
                 true
             }
             Notification::Install(In::Utils(Un::DownloadDataReceived(data))) => {
-                if self.stdout_is_a_tty {
-                    self.data_received(data.len());
+                if let Some(summary) = &mut self.summary {
+                    summary.data_received(data.len());
                 }
+                self.data_received(data.len());
                 true
             }
+            Notification::Install(In::Utils(Un::DownloadingFile(..))) => {
+                if self.active_downloads == 0 {
+                    self.prepare_for_new_download();
+                }
+                self.active_downloads += 1;
+                false
+            }
             Notification::Install(In::Utils(Un::DownloadFinished)) => {
-                self.download_finished();
+                // Unpacking reports its progress as a download, without
+                // starting one.
+                self.active_downloads = self.active_downloads.saturating_sub(1);
+                if self.active_downloads == 0 {
+                    self.download_finished();
+                }
                 true
             }
             Notification::Install(In::Utils(Un::DownloadPushUnit(unit))) => {
@@ -96,9 +150,10 @@ impl DownloadTracker {
         }
     }
 
-    /// Notifies self that Content-Length information has been received.
+    /// Notifies self that Content-Length information has been received, which
+    /// adds to that of the other downloads in progress.
     pub(crate) fn content_length_received(&mut self, content_len: u64) {
-        self.content_len = Some(content_len as usize);
+        self.content_len = Some(self.content_len.unwrap_or(0) + content_len as usize);
     }
 
     /// Notifies self that data of size `len` has been received.
@@ -113,7 +168,7 @@ impl DownloadTracker {
             Some(prev) => {
                 let elapsed = current_time.saturating_duration_since(prev);
                 if elapsed >= Duration::from_secs(1) {
-                    if self.display_progress {
+                    if self.display_progress && self.stdout_is_a_tty {
                         self.display();
                     }
                     self.last_sec = Some(current_time);
@@ -129,6 +184,9 @@ impl DownloadTracker {
     }
     /// Notifies self that the download has finished.
     pub(crate) fn download_finished(&mut self) {
+        if self.displayed_tenths.is_some() {
+            self.display_plain();
+        }
         if self.displayed_charcount.is_some() {
             // Display the finished state
             self.display();
@@ -145,9 +203,14 @@ impl DownloadTracker {
         self.start_sec = Some(Instant::now());
         self.last_sec = None;
         self.displayed_charcount = None;
+        self.displayed_tenths = None;
     }
     /// Display the tracked download information to the terminal.
     fn display(&mut self) {
+        if self.plain_output {
+            self.display_plain();
+            return;
+        }
         match self.start_sec {
             // Maybe forgot to call `prepare_for_new_download` first
             None => {}
@@ -155,10 +218,7 @@ impl DownloadTracker {
                 // Panic if someone pops the default bytes unit...
                 let unit = *self.units.last().unwrap();
                 let total_h = Size::new(self.total_downloaded, unit, UnitMode::Norm);
-                let sum: usize = self.downloaded_last_few_secs.iter().sum();
-                let len = self.downloaded_last_few_secs.len();
-                let speed = if len > 0 { sum / len } else { 0 };
-                let speed_h = Size::new(speed, unit, UnitMode::Rate);
+                let speed_h = Size::new(self.speed(), unit, UnitMode::Rate);
                 let elapsed_h = Instant::now().saturating_duration_since(start_sec);
 
                 // First, move to the start of the current line and clear it.
@@ -180,12 +240,7 @@ impl DownloadTracker {
                     Some(content_len) => {
                         let content_len_h = Size::new(content_len, unit, UnitMode::Norm);
                         let percent = (self.total_downloaded as f64 / content_len as f64) * 100.;
-                        let remaining = content_len - self.total_downloaded;
-                        let eta_h = Duration::from_secs(if speed == 0 {
-                            u64::MAX
-                        } else {
-                            (remaining / speed) as u64
-                        });
+                        let eta_h = self.eta().unwrap_or(Duration::from_secs(u64::MAX));
                         format!(
                             "{} / {} ({:3.0} %) {} in {}{}",
                             total_h,
@@ -212,6 +267,71 @@ impl DownloadTracker {
         }
     }
 
+    /// The average download speed of the last few seconds, in bytes per second.
+    fn speed(&self) -> usize {
+        let sum: usize = self.downloaded_last_few_secs.iter().sum();
+        sum.checked_div(self.downloaded_last_few_secs.len())
+            .unwrap_or(0)
+    }
+
+    /// How long the download in progress should still take at the current
+    /// speed, unless its length or speed is not known yet.
+    fn eta(&self) -> Option<Duration> {
+        let remaining = self.content_len?.saturating_sub(self.total_downloaded);
+        let secs = remaining.checked_div(self.speed())?;
+        Some(Duration::from_secs(secs as u64))
+    }
+
+    /// Prints a line with the progress of the download each time it reaches
+    /// another tenth of its length, which is unknown for some downloads.
+    fn display_plain(&mut self) {
+        let Some(content_len) = self.content_len else {
+            return;
+        };
+        let tenths = (self.total_downloaded * 10)
+            .checked_div(content_len)
+            .unwrap_or(10);
+        if self.displayed_tenths.is_some_and(|shown| shown >= tenths) {
+            return;
+        }
+        self.displayed_tenths = Some(tenths);
+
+        let unit = *self.units.last().unwrap();
+        let _ = writeln!(
+            self.term.lock(),
+            "{} / {} ({} %)",
+            Size::new(self.total_downloaded, unit, UnitMode::Norm),
+            Size::new(content_len, unit, UnitMode::Norm),
+            (self.total_downloaded * 100)
+                .checked_div(content_len)
+                .unwrap_or(100),
+        );
+    }
+
+    /// Reports what an install or update did once it has finished.
+    ///
+    /// Like the progress bar this is only shown on a terminal, as the timings
+    /// would make the output of scripted installs nondeterministic.
+    fn show_summary(&self, summary: &InstallSummary) {
+        let show_on_console = self.display_progress && self.stdout_is_a_tty;
+        for line in summary.to_string().lines() {
+            match show_on_console {
+                true => info!("{line}"),
+                false => debug!("{line}"),
+            }
+        }
+    }
+
+    /// What the install or update in progress did so far, for the
+    /// machine-readable outputs.
+    pub(crate) fn summary_stats(&self) -> Option<InstallStats> {
+        let mut stats = self.summary.as_ref()?.stats(Instant::now());
+        if self.active_downloads > 0 {
+            stats.eta_secs = self.eta().map(|eta| eta.as_secs_f64());
+        }
+        Some(stats)
+    }
+
     pub(crate) fn push_unit(&mut self, new_unit: Unit) {
         self.units.push(new_unit);
     }
@@ -221,6 +341,61 @@ impl DownloadTracker {
     }
 }
 
+/// Statistics collected while downloading and installing components.
+struct InstallSummary {
+    start: Instant,
+    /// When the first component started installing, ending the download phase.
+    install_start: Option<Instant>,
+    components: usize,
+    /// Bytes downloaded, not counting files found in the download cache.
+    downloaded: usize,
+    cache_hits: usize,
+}
+
+impl InstallSummary {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            install_start: None,
+            components: 0,
+            downloaded: 0,
+            cache_hits: 0,
+        }
+    }
+
+    fn installing(&mut self) {
+        self.install_start.get_or_insert_with(Instant::now);
+        self.components += 1;
+    }
+
+    fn data_received(&mut self, len: usize) {
+        // Unpacking reuses the download notifications to report its progress.
+        if self.install_start.is_none() {
+            self.downloaded += len;
+        }
+    }
+
+    fn stats(&self, now: Instant) -> InstallStats {
+        let install_start = self.install_start.unwrap_or(now);
+        InstallStats {
+            components: self.components,
+            downloaded: self.downloaded,
+            cache_hits: self.cache_hits,
+            download_secs: install_start
+                .saturating_duration_since(self.start)
+                .as_secs_f64(),
+            install_secs: now.saturating_duration_since(install_start).as_secs_f64(),
+            eta_secs: None,
+        }
+    }
+}
+
+impl fmt::Display for InstallSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.stats(Instant::now()).fmt(f)
+    }
+}
+
 struct Eta(Duration);
 
 impl fmt::Display for Eta {
@@ -272,7 +447,71 @@ fn format_dhms(sec: u64) -> (u64, u8, u8, u8) {
 
 #[cfg(test)]
 mod tests {
-    use super::format_dhms;
+    use std::time::{Duration, Instant};
+
+    use super::{format_dhms, DownloadTracker, InstallSummary};
+    use crate::process::TestProcess;
+
+    #[test]
+    fn install_summary() {
+        let start = Instant::now();
+        let summary = InstallSummary {
+            start,
+            install_start: Some(start + Duration::from_millis(2500)),
+            components: 3,
+            downloaded: 3 * 1024 * 1024,
+            cache_hits: 1,
+        };
+        let stats = summary.stats(start + Duration::from_secs(4));
+        assert_eq!(
+            stats.to_string(),
+            "installed 3 components in 4.0s (downloading 2.5s, installing 1.5s)\n\
+             downloaded 3.0 MiB, 1 from the download cache"
+        );
+        assert_eq!(
+            serde_json::to_string(&stats).unwrap(),
+            r#"{"components":3,"downloaded":3145728,"cache-hits":1,"download-secs":2.5,"install-secs":1.5,"eta-secs":null}"#
+        );
+    }
+
+    #[test]
+    fn summary_estimates_time_remaining() {
+        let tp = TestProcess::default();
+        let mut tracker = DownloadTracker::new_with_display_progress(false, &tp.process);
+        tracker.summary = Some(InstallSummary::new());
+        tracker.active_downloads = 1;
+        tracker.prepare_for_new_download();
+        tracker.content_length_received(1000);
+        tracker.total_downloaded = 400;
+        assert_eq!(tracker.summary_stats().unwrap().eta_secs, None);
+
+        tracker.downloaded_last_few_secs.extend([100, 200]);
+        assert_eq!(tracker.summary_stats().unwrap().eta_secs, Some(4.0));
+
+        tracker.active_downloads = 0;
+        assert_eq!(tracker.summary_stats().unwrap().eta_secs, None);
+    }
+
+    #[test]
+    fn plain_progress_prints_tenths() {
+        let tp = TestProcess::default();
+        let mut tracker = DownloadTracker::new_with_display_progress(true, &tp.process);
+        tracker.plain_output = true;
+        tracker.prepare_for_new_download();
+        tracker.content_length_received(1000);
+        for downloaded in [350, 20, 30, 600] {
+            tracker.total_downloaded += downloaded;
+            tracker.display();
+        }
+        tracker.download_finished();
+
+        assert_eq!(
+            String::from_utf8(tp.stdout()).unwrap(),
+            "350 B / 1000 B (35 %)\n\
+             400 B / 1000 B (40 %)\n\
+             1000 B / 1000 B (100 %)\n"
+        );
+    }
 
     #[test]
     fn download_tracker_format_dhms_test() {
@@ -19,6 +19,8 @@ pub enum CLIError {
     ReadDirError { p: PathBuf, source: io::Error },
     #[error("failure during windows uninstall")]
     WindowsUninstallMadness,
+    #[error("rustup panicked at {location}: {message}; this is a bug, please report it at https://github.com/rust-lang/rustup/issues")]
+    Panicked { message: String, location: String },
 }
 
 fn maybe_suggest_toolchain(bad_name: &str) -> String {
@@ -0,0 +1,118 @@
+//! Fingerprints of the files that resolving a directory's toolchain reads,
+//! for telling when an answer cached by `rustup agent` or by proxies has
+//! gone stale.
+//!
+//! A file is taken to be unchanged while its modification time and length
+//! are, unless it was modified so shortly before its fingerprint was taken
+//! that a further write may have left both alone. Then, as when they have
+//! changed, its content is hashed again and compared. Directories are
+//! compared by the names of their entries.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use sha2::{Digest, Sha256};
+
+/// How long after a write the modification time may not yet tell it apart
+/// from the next one, on file systems with coarse timestamps.
+const RACY: Duration = Duration::from_secs(2);
+
+/// The fingerprints of the files a resolution reads, taken before reading
+/// them so that a file changed meanwhile makes the answer stale.
+#[derive(Debug)]
+pub(crate) struct Inputs(pub(crate) Vec<Fingerprint>);
+
+impl Inputs {
+    pub(crate) fn take(paths: Vec<PathBuf>) -> Self {
+        Self(paths.into_iter().map(Fingerprint::take).collect())
+    }
+
+    /// Fingerprints `path` as well.
+    pub(crate) fn with(mut self, path: PathBuf) -> Self {
+        self.0.push(Fingerprint::take(path));
+        self
+    }
+
+    pub(crate) fn are_current(&mut self) -> bool {
+        self.0.iter_mut().all(Fingerprint::is_current)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Fingerprint {
+    pub(crate) path: PathBuf,
+    /// `None` if there was nothing at `path`.
+    pub(crate) state: Option<State>,
+}
+
+#[derive(Debug)]
+pub(crate) struct State {
+    pub(crate) modified: SystemTime,
+    pub(crate) len: u64,
+    pub(crate) hash: [u8; 32],
+    pub(crate) taken: SystemTime,
+}
+
+impl Fingerprint {
+    fn take(path: PathBuf) -> Self {
+        let taken = SystemTime::now();
+        let state = State::read(&path, taken);
+        Self { path, state }
+    }
+
+    fn is_current(&mut self) -> bool {
+        let meta = fs::metadata(&self.path).ok();
+        let (old, meta) = match (&self.state, meta) {
+            (None, None) => return true,
+            (Some(old), Some(meta)) => (old, meta),
+            _ => return false,
+        };
+        let (Ok(modified), len) = (meta.modified(), meta.len()) else {
+            return false;
+        };
+        let settled = old.modified + RACY < old.taken;
+        if settled && modified == old.modified && len == old.len {
+            return true;
+        }
+
+        // Take the fingerprint again, so that the file is only hashed
+        // again while it may still change unnoticed.
+        let taken = SystemTime::now();
+        match State::read(&self.path, taken) {
+            Some(new) if new.hash == old.hash => {
+                self.state = Some(new);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl State {
+    fn read(path: &Path, taken: SystemTime) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        let mut hasher = Sha256::new();
+        if meta.is_dir() {
+            let mut names = fs::read_dir(path)
+                .ok()?
+                .map(|entry| Ok(entry?.file_name()))
+                .collect::<io::Result<Vec<_>>>()
+                .ok()?;
+            names.sort();
+            for name in names {
+                hasher.update(name.as_encoded_bytes());
+                hasher.update([0]);
+            }
+        } else {
+            io::copy(&mut fs::File::open(path).ok()?, &mut hasher).ok()?;
+        }
+        Some(Self {
+            modified: meta.modified().ok()?,
+            len: meta.len(),
+            hash: hasher.finalize().into(),
+            taken,
+        })
+    }
+}
@@ -32,7 +32,29 @@ pub(crate) static UPDATE_HELP: &str = r"Discussion:
     updates rustup itself.
 
     If given a toolchain argument then `update` updates that
-    toolchain, the same as `rustup toolchain install`.";
+    toolchain, the same as `rustup toolchain install`.
+
+    With `--exit-code`, the exit status is 0 if everything was already
+    up to date, 2 if a toolchain or rustup was updated, 3 if nothing
+    was but rustup has an update that was only checked for, and 1 on
+    error.
+
+    With `--dry-run`, only the channel manifests are downloaded, and
+    the components each toolchain would install and remove are
+    printed instead.";
+
+pub(crate) static CHECK_HELP: &str = r"Discussion:
+    Checks the installed release channel toolchains and rustup itself
+    for updates, without installing them.
+
+    With `--exit-code`, the exit status is 0 if everything is up to
+    date, 2 if toolchain updates are available, 3 if only rustup has
+    an update, for `rustup self update` to install, and 1 on error.
+    `--quiet` does the same without printing anything, for CI jobs to
+    gate on.
+
+    With `--output json`, it prints each toolchain with its current and
+    available version, and the same for rustup itself.";
 
 pub(crate) static INSTALL_HELP: &str = r"Discussion:
     Installs a specific rust toolchain.
@@ -91,10 +113,12 @@ pub(crate) static TOOLCHAIN_LINK_HELP: &str = r"Discussion:
     'toolchain' is the custom name to be assigned to the new toolchain.
     Any name is permitted as long as:
     - it does not include '/' or '\' except as the last character
-    - it is not equal to 'none'
-    - it does not fully match an initialsubstring of a standard release channel.
+    - it is not equal to 'none', 'self', '.' or '..'
+    - it does not start with '+' or contain control characters
+    - it does not fully match an initialsubstring of a standard release channel,
+      ignoring case.
     For example, you can use the names 'latest' or '2017-04-01' but you cannot
-    use 'stable' or 'beta-i686' or 'nightly-x86_64-unknown-linux-gnu'.
+    use 'stable', 'Nightly' or 'beta-i686' or 'nightly-x86_64-unknown-linux-gnu'.
 
     'path' specifies the directory where the binaries and libraries for
     the custom toolchain can be found. For example, when used for
@@ -108,6 +132,176 @@ pub(crate) static TOOLCHAIN_LINK_HELP: &str = r"Discussion:
     If you now compile a crate in the current directory, the custom
     toolchain 'latest-stage1' will be used.";
 
+pub(crate) static TOOLCHAIN_RENAME_HELP: &str = r"Discussion:
+    Renames a custom toolchain, such as one created with `rustup
+    toolchain link`. The default toolchain and any directory overrides
+    that refer to the toolchain by its old name are updated to use the
+    new name.
+
+    Toolchain files such as `rust-toolchain.toml` are not updated.
+    Official toolchains cannot be renamed.";
+
+pub(crate) static SCHEDULE_HELP: &str = r"Discussion:
+    Rustup can ask the system's task scheduler to run `rustup --quiet
+    update` in the background, so that installed toolchains don't fall
+    behind. On Linux this is a systemd user timer, on macOS a launchd
+    agent and on Windows a Task Scheduler task.
+
+    The scheduled job runs the rustup installed in `CARGO_HOME/bin`
+    with the current `RUSTUP_HOME` and `CARGO_HOME`.";
+
+pub(crate) static TOOLCHAIN_PIN_HELP: &str = r"Discussion:
+    Pinned toolchains are skipped when `rustup update` updates all
+    installed toolchains. Naming a pinned toolchain explicitly, as in
+    `rustup update nightly`, still updates it.
+
+    With `--until`, rustup warns once the given date has passed, as a
+    reminder to revisit the pin. Run `rustup toolchain pin` again to
+    change the date, or `rustup toolchain unpin` to remove the pin.";
+
+pub(crate) static TOOLCHAIN_GC_HELP: &str = r"Discussion:
+    Uninstalls the toolchains that haven't been used for the given
+    number of days, or for the period set with `rustup set
+    toolchain-retention`. A toolchain is used when a proxy such as
+    `cargo` runs it, and when it is installed or updated.
+
+    The default and active toolchains, toolchains used by a directory
+    override, pinned toolchains and custom toolchains are never
+    removed. Pass `--dry-run` to see what would be removed first:
+
+        $ rustup toolchain gc --older-than 90 --dry-run";
+
+pub(crate) static TOOLCHAIN_HASH_HELP: &str = r"Discussion:
+    Prints a SHA-256 digest of every file, directory and symlink
+    installed in the toolchain, taking their paths, contents and
+    executable bits into account but not their timestamps. The
+    channel manifest, which records where the toolchain was
+    downloaded from, is left out.
+
+    Two machines with bit-identical installations of a toolchain
+    print the same digest, so it can be compared in CI or used as
+    part of a cache key.";
+
+pub(crate) static TOOLCHAIN_PACKAGE_HELP: &str = r"Discussion:
+    Writes an installed toolchain to a zstd-compressed tarball that can
+    be unpacked on a machine with the same host and used without
+    rustup, for vendoring a compiler into a hermetic build system.
+
+    The archive holds a single directory named after the toolchain,
+    containing:
+
+        toolchain/               the toolchain, as rustup installed it
+        toolchain-manifest.toml  its name, host and `rustup toolchain hash`
+        install.sh               copies the toolchain to a given directory
+
+    Rust toolchains find their libraries relative to `rustc`, so the
+    `toolchain` directory works from wherever it ends up. The channel
+    manifest, which records where the toolchain was downloaded from, is
+    left out, and timestamps and owners are fixed, so packaging the same
+    toolchain twice gives the same archive.
+
+        $ rustup toolchain package stable -o stable.tar.zst";
+
+pub(crate) static TOOLCHAIN_LINKAGE_HELP: &str = r"Discussion:
+    Lists the ELF files in a toolchain with the dynamic linker each one
+    asks for and the shared libraries it expects the system to provide,
+    leaving out those shipped in the toolchain itself. A dynamic linker
+    that does not exist on this system is marked as not found.
+
+    Toolchains from the dist server are built for a conventional Linux
+    layout, so on NixOS and similar systems their binaries do not run
+    as downloaded. This report shows what a post-install hook has to
+    patch; see `rustup set post-install-hook --help`.
+
+        $ rustup toolchain linkage stable";
+
+pub(crate) static COMPONENT_ADD_HELP: &str = r"Discussion:
+    Components are normally taken from the channel the toolchain was
+    installed from. With `--url`, a component that is not part of the
+    channel is installed from an archive in rust-installer format
+    instead, so that extra tools can be distributed without a dist
+    server of their own. `--sha256` gives the hash the archive must
+    have, and `--name` the component in it to install. Adding the
+    component again replaces it.
+
+        $ rustup component add --url <url> --sha256 <hash> --name my-tool
+
+    With `--file`, a component of the channel is installed from its
+    archive on the local disk, such as one copied from another machine
+    over a faster network, rather than downloaded. The archive must
+    be one that the toolchain's manifest lists, which tells which
+    component it is and that it is intact.
+
+        $ rustup component add --file clippy-nightly-x86_64-unknown-linux-gnu.tar.xz";
+
+pub(crate) static KEY_HELP: &str = r"Discussion:
+    Once a key is trusted for a dist server, every manifest downloaded
+    from that server must be signed by one of its trusted keys. Since
+    a manifest records the hash of every component, this covers the
+    components too. Keys are trusted per dist server, so keys for an
+    internal server don't apply to the official one.
+
+    For an OpenPGP key, the signature is expected next to the manifest
+    as `<manifest>.asc` and is checked with `gpg`. For a Sigstore
+    identity, a keyless signature bundle is expected as
+    `<manifest>.sigstore.json` and is checked with `cosign`.
+
+        $ rustup key add release-key.asc --dist-server https://rust.example.com";
+
+pub(crate) static AUTH_HELP: &str = r"Discussion:
+    A dist server that requires authentication, such as an internal
+    mirror, is sent the token stored for it as a bearer token, over
+    HTTPS only. Tokens are stored per host, so the token of a dist
+    server also applies to self-updates from the same host.
+
+    Tokens are kept in the platform's credential store: the Credential
+    Manager on Windows, the login keychain on macOS, and the Secret
+    Service, through `secret-tool`, elsewhere. Without one, they are
+    kept in `credentials.toml` in the rustup home, which only its owner
+    can read. Set `RUSTUP_CREDENTIAL_STORE` to `keychain` or `file` to
+    choose.
+
+    The token is read from stdin, and isn't shown as it is pasted on a
+    terminal. It can't be passed as an argument, which other users may
+    see.
+
+        $ rustup auth login --dist-server https://rust.example.com";
+
+pub(crate) static CONFIG_HELP: &str = r"Discussion:
+    An organization can keep the rustup configuration its machines share
+    in a signed bundle, and have them apply it with `rustup config sync`.
+    The bundle becomes the policy, in `policy.toml` in the rustup home,
+    which sits below the user's own settings.";
+
+pub(crate) static CONFIG_SYNC_HELP: &str = r#"Discussion:
+    Downloads the configuration bundle, checks its signature, and makes
+    it the policy. `--from` takes the URL of the bundle, or of a git
+    repository with a `rustup-config.toml` at its root; the URL of a
+    repository ends in `.git`, or is an SSH or git URL. Later syncs use
+    the same URL unless given another.
+
+    The bundle must be signed by a key trusted for its URL, with the
+    signature next to it, as for manifests:
+
+        $ rustup key add corp-key.asc --dist-server https://rust.corp.example/rustup-config.toml
+        $ rustup config sync --from https://rust.corp.example/rustup-config.toml
+
+    A bundle can set:
+
+        # The dist server to use unless `RUSTUP_DIST_SERVER` is set
+        dist_server = "https://rust-mirror.corp.example"
+        # The profile to use unless the user sets one
+        profile = "minimal"
+        # The only channels toolchains may be installed from
+        allowed_channels = ["stable", "1.80"]
+
+        # Components added together with `rustup component add @embedded`
+        [component_groups]
+        embedded = ["rust-src", "llvm-tools"]
+
+    With `--check`, nothing is changed: each setting that differs from
+    the bundle is printed, and rustup exits with 1 if there are any."#;
+
 pub(crate) static OVERRIDE_HELP: &str = r"Discussion:
     Overrides configure Rustup to use a specific toolchain when
     running in a specific directory.
@@ -129,6 +323,17 @@ pub(crate) static OVERRIDE_HELP: &str = r"Discussion:
     override and use the default toolchain again, `rustup override
     unset`.";
 
+pub(crate) static OVERRIDE_SET_HELP: &str = r"Discussion:
+    Sets the override toolchain for the current directory, or for each
+    directory given with `--path`. `--path` may be repeated, and
+    `--from-file` reads further directories from a file with one path
+    per line, skipping empty lines and lines starting with `#`.
+
+    Paths containing `*`, `?` or `[` are expanded as glob patterns,
+    relative to the current directory:
+
+        $ rustup override set nightly --path 'crates/*'";
+
 pub(crate) static OVERRIDE_UNSET_HELP: &str = r"Discussion:
     If `--path` argument is present, removes the override toolchain
     for the specified directory. If `--nonexistent` argument is
@@ -149,14 +354,472 @@ pub(crate) static RUN_HELP: &str = r"Discussion:
 
         $ cargo +nightly build
 
-        $ rustup run nightly cargo build";
+        $ rustup run nightly cargo build
+
+    `--rustc-from` and `--cargo-from` take that tool from another
+    toolchain, wherever it is run from, e.g. to test a custom rustc
+    with a known-good cargo:
+
+        $ rustup run stable --rustc-from my-rustc -- cargo build
+
+    With `--install`, or with `rustup set auto-install` enabled, a
+    toolchain that isn't installed yet is installed first, with the
+    minimal profile, so that one-shot commands stay quick:
+
+        $ rustup run --install nightly-2024-01-01 cargo bench";
+
+pub(crate) static PROXY_HELP: &str = r"Discussion:
+    Rustup usually runs a tool like `cargo` through a proxy, a link to
+    the rustup binary named after the tool. Where such links can't be
+    made, e.g. on some network shares, `rustup proxy run` finds the
+    active toolchain and runs the tool from it the same way. These are
+    equivalent:
+
+        $ cargo +nightly build
+
+        $ rustup proxy run cargo +nightly build
+
+    `--help` is rustup's own, so to pass it to the tool it follows a
+    `--`:
+
+        $ rustup proxy run cargo -- --help";
+
+pub(crate) static SELF_BENCH_PROXY_HELP: &str = r"Discussion:
+    Runs `<tool> --version` repeatedly, both through the rustup proxy
+    installed next to rustup and directly from the active toolchain,
+    and reports the time rustup adds to each invocation. The first
+    proxied invocation is reported separately as the cold timing.
+
+    Timings can be saved and compared against a later run, which
+    exits with a non-zero status if the proxy overhead grew by more
+    than the given threshold:
+
+        $ rustup self bench-proxy --save before.toml
+
+        $ rustup self bench-proxy --baseline before.toml";
+
+pub(crate) static SELF_DIAGNOSE_TLS_HELP: &str = r"Discussion:
+    Connects to the dist server, through the proxy configured in the
+    environment if there is one, and makes a TLS handshake with each
+    TLS backend rustup was built with: rustls with the platform
+    verifier, and native-tls. For each backend it shows where the
+    trusted root certificates come from, the certificates the server
+    presented, and why they were rejected if they were.
+
+    A certificate that is not issued by a public CA usually means
+    that a proxy or security software is inspecting TLS traffic. Its
+    root certificate then has to be added to the system's certificate
+    store.
+
+    Exits with a non-zero status if any handshake fails.";
+
+pub(crate) static SELF_DOCTOR_HELP: &str = r"Discussion:
+    Runs the rustup installed in `CARGO_HOME/bin` and reports whether
+    it starts. On Windows, a rustup built against the dynamic Visual
+    C++ runtime can't start without it, in which case the runtime
+    DLLs it imports are listed, along with where to get them.
+
+    `rustup self update` makes the same check on the new rustup
+    before installing it.
+
+    `rustup doctor` checks the setup around rustup instead, such as
+    PATH, the proxies and who owns RUSTUP_HOME.
+
+    Exits with a non-zero status if rustup doesn't start.";
+
+pub(crate) static SET_CRASH_REPORTS_HELP: &str = r"Discussion:
+    When enabled, a crash of rustup writes a report containing the
+    backtrace, the rustup version, the platform and the command line
+    to the `crash-reports` directory in RUSTUP_HOME. Paths inside your
+    home directory are shortened to `~` and credentials in URLs are
+    removed.
+
+    Reports are never uploaded. Instead, rustup prints where the
+    report was written so that you can review it and attach it to an
+    issue yourself.";
+
+pub(crate) static SET_OUTPUT_STYLE_HELP: &str = r"Discussion:
+    With `plain`, rustup never colors its output or redraws it in
+    place, even on a terminal. Download progress is then shown as a
+    line for every tenth of the download, which suits screen readers
+    and terminals that don't handle cursor control sequences.
+
+    The `RUSTUP_OUTPUT_STYLE` environment variable takes precedence
+    over this setting, and also applies to `rustup-init`.";
+
+pub(crate) static SET_PALETTE_HELP: &str = r"Discussion:
+    The `light` palette suits terminals with a light background: it
+    shows warnings in magenta rather than yellow, and avoids the other
+    colors that are hard to read on white.
+
+    The `RUSTUP_PALETTE` environment variable takes precedence over
+    this setting. Whether colors are used at all is up to
+    `RUSTUP_TERM_COLOR`, `NO_COLOR` and `CLICOLOR_FORCE`.";
+
+pub(crate) static SET_AUTO_INSTALL_HELP: &str = r"Discussion:
+    By default, running a tool such as `cargo` with a toolchain that
+    is not installed is an error. With `enable`, the proxy installs
+    the toolchain first, along with any components and targets named
+    in a `rust-toolchain.toml` file. `quiet` does the same but only
+    prints warnings and errors, which suits ephemeral CI runners.
+
+    The RUSTUP_AUTO_INSTALL environment variable takes precedence
+    over this setting.";
+
+pub(crate) static SET_PROXY_LINKS_HELP: &str = r"Discussion:
+    Proxies such as `cargo` are links to the rustup binary in
+    `CARGO_HOME/bin`. By default, with `auto`, they are symbolic
+    links where those can be made, and hard links elsewhere.
+
+    `symlink` makes them symbolic links relative to `CARGO_HOME/bin`,
+    which keep working when `CARGO_HOME` is moved, including to
+    another drive. On Windows, making symbolic links needs Developer
+    Mode to be on, or an administrator. `hardlink` always makes them
+    hard links. `copy` makes them copies of rustup, which updating
+    rustup copies again; rustup falls back to it by itself where the
+    file system can make neither kind of link, such as exFAT.
+
+    The proxies are linked again as soon as this is set.";
+
+pub(crate) static SET_PROXY_DIR_HELP: &str = r"Discussion:
+    Installs the proxies, such as `rustc` and `cargo`, in a directory
+    of their own instead of `CARGO_HOME/bin`, and removes them from
+    where they were. rustup itself and the tools installed with
+    `cargo install` stay in `CARGO_HOME/bin`.
+
+    This keeps a Rust installed by the OS package manager, such as the
+    one from apt or Homebrew, as the `rustc` and `cargo` in PATH, even
+    with `CARGO_HOME/bin` first. Add the directory to PATH to use the
+    toolchains managed by rustup, or run them with `rustup run`.
+
+        $ rustup set proxy-dir ~/.rustup-proxies
+
+    Set it to `default` to move the proxies back to `CARGO_HOME/bin`.";
+
+pub(crate) static SET_DIST_SERVERS_HELP: &str = r"Discussion:
+    Sets the dist servers that toolchains are installed and updated
+    from, such as a mirror followed by the official server. A
+    manifest or component that the first server doesn't have, or
+    that can't be downloaded from it, is tried on the next one, and
+    so on. Signatures are checked with the keys trusted for the
+    first server.
+
+        $ rustup set dist-servers https://mirror.example.com https://static.rust-lang.org
+
+    The RUSTUP_DIST_SERVER environment variable takes precedence
+    over this setting, and names a single server.";
+
+pub(crate) static SET_CACHE_PEERS_HELP: &str = r"Discussion:
+    Machines on the same network installing the same toolchain can
+    share one download. Before going to the dist server, rustup asks
+    each cache in turn for a component, as `<url>/<sha256>` where
+    `<sha256>` is the hash of the component from the manifest. A
+    cache can be any HTTP server with the archives named by their
+    hash. What a cache returns is only used if its hash matches.
+
+        $ rustup set cache-peers http://10.0.0.5:8000";
+
+pub(crate) static SET_CONFIRM_DOWNLOADS_OVER_HELP: &str = r"Discussion:
+    On a metered connection, or to catch an accidental install with
+    the complete profile, rustup can ask before an update downloads
+    more than a given size. The size is worked out from the manifest
+    before anything is downloaded. Sizes take binary (KiB, MiB, GiB)
+    or decimal (KB, MB, GB) units. Pass `--yes` to rustup to answer
+    the question up front, e.g. in scripts.
+
+        $ rustup set confirm-downloads-over 500MiB";
+
+pub(crate) static SET_POST_INSTALL_HOOK_HELP: &str = r"Discussion:
+    Runs a program on each ELF file installed by an update, with the
+    path of the file as its only argument, before the update is
+    finished. If the program fails, the update is rolled back. This
+    lets toolchains be patched as they are installed on systems where
+    downloaded binaries do not run unmodified, such as NixOS:
+
+        #!/bin/sh
+        # Only executables have a dynamic linker to replace.
+        case $1 in
+            */bin/*) exec patchelf --set-interpreter \
+                $(cat $NIX_CC/nix-support/dynamic-linker) $1 ;;
+        esac
+
+        $ rustup set post-install-hook ~/.config/rustup/patch.sh
+
+    Use `rustup toolchain linkage` to see what an installed toolchain
+    expects from the system.";
+
+pub(crate) static SET_DEDUP_HELP: &str = r"Discussion:
+    When enabled, each file an update installs is replaced by a hard
+    link to a single copy of its content in `RUSTUP_HOME/store`, or a
+    reflink where hard links can't be made. Nightlies a few days apart
+    share most of their docs and sources, so keeping several of them
+    then takes little more disk space than keeping one.
+
+        $ rustup set dedup true
+
+    Files are only shared by toolchains installed or updated while this
+    is enabled, and stop being shared as the toolchains are removed.";
+
+pub(crate) static SET_TOOLCHAIN_RETENTION_HELP: &str = r"Discussion:
+    Sets how many days a toolchain may go unused before `rustup
+    toolchain gc` removes it. Nothing is removed until `rustup
+    toolchain gc` runs, e.g. from a scheduled job, and passing
+    `--older-than` to it overrides this period.
+
+        $ rustup set toolchain-retention 60";
+
+pub(crate) static SET_IO_PROFILE_HELP: &str = r"Discussion:
+    Components are unpacked with several threads writing files at
+    once, and with file contents buffered in memory. With the `auto`
+    profile, the number of threads follows the number of CPUs and the
+    kind of storage being written to: fewer for a spinning disk, and
+    more for a network file system, where each file written waits on
+    the network. Buffers use the memory that is available.
+
+    `low-memory` unpacks on one thread with the smallest buffers, and
+    `max-throughput` uses twice as many threads as CPUs whatever the
+    storage. `RUSTUP_IO_THREADS` and `RUSTUP_UNPACK_RAM` take
+    precedence over the profile, and `rustup --jobs` over
+    `RUSTUP_IO_THREADS`. Run with `--verbose` to see what unpacking
+    was tuned to.
+
+    Uninstalling a toolchain removes its files with as many threads
+    as unpacking would use.";
+
+pub(crate) static SET_MAX_DOWNLOADS_HELP: &str = r"Discussion:
+    Installing or updating a toolchain downloads several components,
+    such as rustc, rust-std, cargo and rust-docs. By default up to 4
+    of them are downloaded at once, with their progress shown as a
+    single download. Use 1 on a connection that is slow or shared, to
+    download one component after another. `RUSTUP_MAX_DOWNLOADS`
+    takes precedence over this setting.
+
+        $ rustup set max-downloads 1";
+
+pub(crate) static SET_TLS_ROOT_SOURCE_HELP: &str = r"Discussion:
+    Downloads over HTTPS trust the root certificates of the platform
+    by default. When those are missing or out of date, downloads fail
+    with errors such as `UnknownIssuer`, and rustup can't even be
+    updated to a version that might work around them.
+
+    With `bundled`, servers under rust-lang.org that the platform's
+    roots don't verify are checked against the roots built into
+    rustup instead; other servers still need the platform's roots.
+    Only the rustls backend has bundled roots. A path to a PEM file
+    trusts only the certificates in it, for every server, and has to
+    hold at least one.
+
+    The platform's roots are read afresh on every run, so once they
+    are repaired, setting `platform` again is all that is left to do.
+
+    `RUSTUP_TLS_ROOT_SOURCE` takes precedence over this setting, so
+    that the first install can use it as well:
+
+        $ RUSTUP_TLS_ROOT_SOURCE=bundled rustup self update
+
+    `rustup self diagnose-tls` shows which roots are in use.";
+
+pub(crate) static SET_TLS_HOST_HELP: &str = r"Discussion:
+    Proxies that intercept TLS present certificates of their own,
+    which downloads from servers behind them are rejected for unless
+    the proxy's root certificate is trusted. Rather than adding that
+    root for every server, it can be trusted for a single one, such
+    as a mirror on the company network:
+
+        $ rustup set tls-host mirror.corp.example --roots corp-ca.pem
+
+    As a last resort, `--insecure` turns off verification of the
+    server's certificate altogether, and rustup warns about it on
+    each run that connects to the server. Components are still
+    checked against the hashes in their manifest, but the manifest
+    itself is only as trustworthy as the network. rust-lang.org
+    servers are always verified.
+
+    Redirects to another server use the settings of that server, and
+    `--remove` goes back to the settings of every other server. Only
+    the reqwest backends use these settings, not curl.";
+
+pub(crate) static AGENT_HELP: &str = r#"Discussion:
+    Keeps running, listening on the socket `RUSTUP_HOME/agent.sock`,
+    so that the settings are read once rather than by every proxy. An
+    IDE that runs rustc hundreds of times a minute can start it along
+    with a project:
+
+        $ rustup agent &
+
+    While it runs, proxies ask it which toolchain is active in their
+    directory, unless `+toolchain` or `RUSTUP_TOOLCHAIN` names one or
+    auto-install is enabled. IDEs can also ask it directly, writing one
+    line of JSON per connection and reading one line back in the format
+    of `--output json`:
+
+        {"method": "resolve", "dir": "/path/to/project"}
+        {"method": "show", "dir": "/path/to/project"}
+
+    The toolchain of a directory is only resolved again once a file it
+    was resolved from changes, or `rustup cache invalidate-resolution`
+    runs.
+
+    It is only available on Unix."#;
+
+pub(crate) static CACHE_INVALIDATE_RESOLUTION_HELP: &str = r"Discussion:
+    Proxies and `rustup agent` remember the toolchain they resolved for
+    each directory, and resolve it again once `settings.toml`, a
+    `rust-toolchain` file in the directory or above it, or the set of
+    installed toolchains changes. This makes them forget them all at
+    once, e.g. after changing one of those files in a way they can't
+    see, such as on a network file system.";
+
+pub(crate) static SERVE_CACHE_HELP: &str = r"Discussion:
+    Lets one machine act as a mirror for the others on its network.
+    Manifests are passed through from the dist server this rustup
+    uses, while each archive is downloaded from it only once, checked
+    against the hash in the manifest and kept in the download cache.
+    Other machines use the mirror as their dist server,
+
+        $ RUSTUP_DIST_SERVER=http://10.0.0.5:8080 rustup update
+
+    or as a cache peer, which falls back to the dist server for any
+    archive the mirror does not have yet.
+
+        $ rustup set cache-peers http://10.0.0.5:8080
+
+    Only `127.0.0.1` is listened on unless `--listen` says otherwise,
+    for example `--listen 0.0.0.0:8080`.";
+
+pub(crate) static DESCRIBE_TOOLCHAIN_HELP: &str = r"Discussion:
+    Prints where a toolchain, by default the active one, keeps its
+    tools and standard libraries, so that hermetic build systems can
+    use it instead of downloading their own copy. The description is
+    JSON with the toolchain's version, the path and SHA-256 of each
+    tool, the standard library directory of each installed target, and
+    the digest printed by `rustup toolchain hash`.
+
+    With `--bazel`, it is instead a BUILD file for a
+    `new_local_repository` rooted at the toolchain, defining a
+    `rust_toolchain` from rules_rust for each installed target:
+
+        $ rustup describe-toolchain stable --bazel > rust.BUILD";
+
+pub(crate) static DIST_EXPORT_HELP: &str = r"Discussion:
+    Downloads the channel manifest of a toolchain and the archives of
+    the components that installing it would need, and writes them to a
+    single archive. On a machine without network access, the toolchain
+    can then be installed from it:
+
+        $ rustup dist export stable -o stable.tar.zst
+        $ rustup toolchain install stable --from-bundle stable.tar.zst
+
+    The components are those of the profile, by default the configured
+    one, and those given with `--component` and `--target`, which are
+    given again when installing from the bundle. The archive is
+    compressed according to its name, which ends in `.tar`, `.tar.gz`,
+    `.tar.xz` or `.tar.zst`. The manifest goes in with its hash, and
+    with its signature if keys are trusted for the dist server; since
+    it lists the hash of every component archive, the install checks
+    the whole bundle.";
+
+pub(crate) static STATS_HELP: &str = r"Discussion:
+    Shows how many toolchains are installed and the disk space each
+    one takes, when each was last installed or updated, and how often
+    the proxies have run each one. The download directory's size is
+    shown along with how many archives were found in it or in a cache
+    instead of being downloaded from the dist server.
+
+    Runs are counted in `$RUSTUP_HOME/usage.log`, so toolchains run
+    directly from their directory aren't counted.";
+
+pub(crate) static STATUS_HELP: &str = r"Discussion:
+    Asks the dist server for a small file, and the status feed of the
+    Rust infrastructure whether it has an incident, and says what that
+    means for downloads: that they should work, that the network or a
+    proxy between rustup and the dist server is in the way, or that
+    the problem is upstream and only waiting helps.
+
+    Set RUSTUP_STATUS_URL to read another feed in the format of
+    Statuspage's `status.json`.";
+
+pub(crate) static TOGGLE_SYSTEM_HELP: &str = r"Discussion:
+    For packaging work it can be necessary to keep the Rust that the
+    OS package manager installed, such as the one from apt or Homebrew,
+    as the `rustc` and `cargo` found in PATH. rustup's proxies live in
+    their own directory and never replace those binaries, so only the
+    order in PATH decides which one runs.
+
+    This command rewrites the env scripts that the shell profiles
+    source so that rustup's directory goes after the rest of PATH, or
+    before it again if it was already after. The change takes effect
+    in new shells. While the system Rust comes first, the toolchains
+    managed by rustup remain available through `rustup run`.
+
+    This command is not available on Windows.";
+
+pub(crate) static TARGET_DOCTOR_HELP: &str = r"Discussion:
+    `rustup target add` installs only a target's standard library.
+    Building for another platform usually also needs a linker for it,
+    which cargo must be told of, and a C compiler for the crates that
+    build C code. This command checks that the target is installed,
+    which linker cargo would use for it and whether that linker is
+    there, and which C compiler the `cc` crate would find from
+    `CC_<target>`, `TARGET_CC` and `CC`, printing how to install what
+    is missing on this system.
+
+    With `--write-config`, it sets the linker for targets built with a
+    GCC cross toolchain, such as `aarch64-linux-gnu-gcc`, in
+    `.cargo/config.toml` of the current directory:
+
+        $ rustup target doctor aarch64-unknown-linux-gnu --write-config";
+
+pub(crate) static WHICH_HELP: &str = r"Discussion:
+    Prints the path of the binary that running the command would run,
+    from the active toolchain or the one given with `--toolchain`.
+    With `--all`, prints it for every installed toolchain that has the
+    command, marking the active one.
+
+    With `--output json`, each binary comes with its toolchain, the
+    toolchain's sysroot and, for the toolchain that would run it, why
+    that toolchain is active, as in `rustup show --output json`.";
+
+pub(crate) static DOCTOR_HELP: &str = r"Discussion:
+    Looks for the causes most often behind a rustup that seems
+    broken, and prints how to fix each one it finds:
+
+    - `CARGO_HOME/bin` missing from PATH, or another `rustup`, `rustc`
+      or `cargo` coming before it
+    - proxies in `CARGO_HOME/bin` that are missing, or left from an
+      older rustup instead of linking to it
+    - toolchains made with `rustup toolchain link` whose directory has
+      moved or no longer holds a toolchain
+    - no CA certificates to verify the dist server with
+    - `RUSTUP_HOME/tmp` on another file system than the toolchains,
+      which makes rustup copy files instead of moving them (checked
+      on Unix only)
+    - RUSTUP_HOME set without CARGO_HOME or the other way around, or
+      another installation left in `~/.rustup` or `~/.cargo`
+    - files in RUSTUP_HOME that its user can't read or change, as
+      running rustup with `sudo` leaves behind (checked on Unix only)
+
+    `--fix-permissions` gives those files back: run as root, it gives
+    them to the user who ran `sudo`, or else to the owner of
+    RUSTUP_HOME.
+
+        $ sudo env RUSTUP_HOME=$HOME/.rustup rustup doctor --fix-permissions
+
+    `rustup self doctor` checks whether rustup itself starts.
+
+    Exits with a non-zero status if any problem is left unfixed.";
 
 pub(crate) static DOC_HELP: &str = r"Discussion:
     Opens the documentation for the currently active toolchain with
     the default browser.
 
     By default, it opens the documentation index. Use the various
-    flags to open specific pieces of documentation.";
+    flags to open specific pieces of documentation.
+
+    With `--target`, it opens the documentation installed for that
+    target by its `rust-docs` component. Targets without a standard
+    library, such as `thumbv7em-none-eabihf`, open the `core` docs,
+    which are the same for every target.";
 
 pub(crate) static COMPLETIONS_HELP: &str = r"Discussion:
     Enable tab completion for Bash, Fish, Zsh, or PowerShell
@@ -0,0 +1,603 @@
+//! The JSON that `rustup show`, `rustup check`, `rustup which`, `rustup
+//! toolchain list`, `rustup component list`, `rustup target list` and `rustup
+//! override list` print with `--output json`, for IDEs and scripts that would otherwise
+//! scrape the human output.
+//!
+//! Every document is an object whose `version` field is [`SCHEMA_VERSION`].
+//! Fields may be added without changing it, but it is bumped whenever a
+//! field is removed, renamed or changes meaning. `--format-version` asks for
+//! a version, which is refused rather than silently changed once it is no
+//! longer printed.
+//!
+//! These are contracts: fields are printed in the order they are declared
+//! here, and lists are sorted, so that the output of the same state is the
+//! same byte for byte and can be diffed. New fields go last.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::{builder::PossibleValue, Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{ActiveReason, Cfg},
+    dist::manifest::ComponentStatus,
+    process::Process,
+    toolchain::{DistributableToolchain, LocalToolchainName, Toolchain, ToolchainName},
+    utils::ExitCode,
+};
+
+/// The version of the schema of the documents printed here.
+pub(super) const SCHEMA_VERSION: u32 = 1;
+
+/// How a listing is printed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum OutputFormat {
+    #[default]
+    Human,
+    /// JSON, in this version of the schema.
+    Json(u32),
+}
+
+/// The `--format-version` that goes with an `--output`.
+#[derive(Args, Clone, Copy, Debug, Default)]
+pub(super) struct FormatVersion {
+    /// Print the JSON in this version of its format, or fail
+    #[arg(
+        long,
+        value_name = "VERSION",
+        requires = "output",
+        value_parser = format_version
+    )]
+    format_version: Option<u32>,
+}
+
+impl OutputFormat {
+    /// The format with the version of the schema `--format-version` asked
+    /// for, if any.
+    pub(super) fn with_version(self, version: FormatVersion) -> Self {
+        match (self, version.format_version) {
+            (Self::Json(_), Some(version)) => Self::Json(version),
+            _ => self,
+        }
+    }
+}
+
+impl ValueEnum for OutputFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Human, Self::Json(SCHEMA_VERSION)]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(match self {
+            Self::Human => "human",
+            Self::Json(_) => "json",
+        }))
+    }
+}
+
+/// Parses `--format-version`, which must be a version that is printed.
+pub(super) fn format_version(version: &str) -> Result<u32, String> {
+    match version.parse() {
+        Ok(SCHEMA_VERSION) => Ok(SCHEMA_VERSION),
+        _ => Err(format!(
+            "this rustup only prints version {SCHEMA_VERSION} of the JSON output"
+        )),
+    }
+}
+
+#[derive(Serialize)]
+struct Document<T> {
+    version: u32,
+    #[serde(flatten)]
+    body: T,
+}
+
+/// A document on a single line, as `rustup agent` answers requests.
+fn line<T: Serialize>(body: T) -> Result<String> {
+    let document = Document {
+        version: SCHEMA_VERSION,
+        body,
+    };
+    Ok(serde_json::to_string(&document)? + "\n")
+}
+
+/// Prints a document in `version` of the schema, which `format_version`
+/// has checked.
+fn print<T: Serialize>(process: &Process, version: u32, body: T) -> Result<()> {
+    let document = Document { version, body };
+    let json = serde_json::to_string_pretty(&document)? + "\n";
+    process.stdout().lock().write_all(json.as_bytes())?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Show {
+    default_host: String,
+    rustup_home: PathBuf,
+    installed_toolchains: Vec<InstalledToolchain>,
+    active_toolchain: Option<ActiveToolchain>,
+}
+
+#[derive(Serialize)]
+struct Toolchains {
+    toolchains: Vec<InstalledToolchain>,
+}
+
+#[derive(Serialize)]
+struct InstalledToolchain {
+    name: String,
+    path: PathBuf,
+    default: bool,
+    active: bool,
+    pinned: bool,
+    /// Whether the toolchain was added with `rustup toolchain link`.
+    linked: bool,
+    /// The output of `rustc --version`, only with `--verbose`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rustc_version: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ActiveToolchain {
+    name: String,
+    path: PathBuf,
+    reason: Reason,
+    /// The targets and components, which are only known for toolchains
+    /// installed from a channel.
+    installed_targets: Vec<String>,
+    installed_components: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rustc_version: Option<String>,
+}
+
+/// Why a toolchain is active.
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub(super) enum Reason {
+    Default,
+    /// `RUSTUP_TOOLCHAIN`.
+    Environment,
+    /// `+toolchain` on the command line.
+    CommandLine,
+    /// `rustup override set` for the directory `path`.
+    DirectoryOverride {
+        path: PathBuf,
+    },
+    /// The `rust-toolchain.toml` at `path`.
+    ToolchainFile {
+        path: PathBuf,
+    },
+}
+
+impl From<&ActiveReason> for Reason {
+    fn from(reason: &ActiveReason) -> Self {
+        match reason {
+            ActiveReason::Default => Self::Default,
+            ActiveReason::Environment => Self::Environment,
+            ActiveReason::CommandLine => Self::CommandLine,
+            ActiveReason::OverrideDB(path) => Self::DirectoryOverride { path: path.clone() },
+            ActiveReason::ToolchainFile(path) => Self::ToolchainFile { path: path.clone() },
+        }
+    }
+}
+
+impl From<Reason> for ActiveReason {
+    fn from(reason: Reason) -> Self {
+        match reason {
+            Reason::Default => Self::Default,
+            Reason::Environment => Self::Environment,
+            Reason::CommandLine => Self::CommandLine,
+            Reason::DirectoryOverride { path } => Self::OverrideDB(path),
+            Reason::ToolchainFile { path } => Self::ToolchainFile(path),
+        }
+    }
+}
+
+/// Which toolchain a directory uses, as `rustup agent` tells proxies.
+#[derive(Serialize)]
+struct Resolved {
+    toolchain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<Reason>,
+}
+
+#[derive(Serialize)]
+struct Invalidated {
+    /// How many directories' toolchains were forgotten.
+    invalidated: usize,
+}
+
+#[derive(Serialize)]
+struct Failed {
+    error: String,
+}
+
+#[derive(Serialize)]
+struct Check {
+    /// Whether any toolchain or rustup itself can be updated.
+    update_available: bool,
+    toolchains: Vec<ToolchainCheck>,
+    rustup: Versions,
+}
+
+#[derive(Serialize)]
+struct ToolchainCheck {
+    name: String,
+    #[serde(flatten)]
+    versions: Versions,
+}
+
+#[derive(Serialize)]
+struct Versions {
+    current_version: Option<String>,
+    /// The newest version, which is the current one when it is up to date.
+    available_version: Option<String>,
+    update_available: bool,
+}
+
+#[derive(Serialize)]
+struct Which {
+    command: String,
+    binaries: Vec<Binary>,
+}
+
+#[derive(Serialize)]
+struct Binary {
+    toolchain: String,
+    path: PathBuf,
+    sysroot: PathBuf,
+    /// Why the toolchain is active, only for the one that runs the command.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<Reason>,
+}
+
+#[derive(Serialize)]
+struct Overrides {
+    overrides: Vec<OverrideEntry>,
+}
+
+#[derive(Serialize)]
+struct OverrideEntry {
+    path: PathBuf,
+    toolchain: String,
+    /// Whether the directory still exists.
+    exists: bool,
+}
+
+#[derive(Serialize)]
+struct Components {
+    toolchain: String,
+    components: Vec<ComponentEntry>,
+}
+
+#[derive(Serialize)]
+struct ComponentEntry {
+    name: String,
+    /// The name of the component without its target.
+    component: String,
+    target: Option<String>,
+    installed: bool,
+    download_size: Option<u64>,
+    installed_size: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Targets {
+    toolchain: String,
+    targets: Vec<TargetEntry>,
+}
+
+#[derive(Serialize)]
+struct TargetEntry {
+    name: String,
+    installed: bool,
+    tier: Option<u8>,
+}
+
+pub(super) fn show(cfg: &Cfg<'_>, verbose: bool, version: u32) -> Result<ExitCode> {
+    print(cfg.process, version, show_body(cfg, verbose)?)?;
+    Ok(ExitCode(0))
+}
+
+pub(super) fn show_line(cfg: &Cfg<'_>, verbose: bool) -> Result<String> {
+    line(show_body(cfg, verbose)?)
+}
+
+fn show_body(cfg: &Cfg<'_>, verbose: bool) -> Result<Show> {
+    let active_toolchain = match cfg.find_active_toolchain()? {
+        Some((name, reason)) => {
+            let toolchain = Toolchain::with_reason(cfg, name.clone(), &reason)?;
+            let components = match &name {
+                LocalToolchainName::Named(ToolchainName::Official(desc)) => {
+                    DistributableToolchain::new(cfg, desc.clone())?.components()?
+                }
+                _ => Vec::new(),
+            };
+            let installed = components.into_iter().filter(|c| c.installed);
+            let (targets, components): (Vec<_>, Vec<_>) =
+                installed.partition(|c| c.component.short_name_in_manifest() == "rust-std");
+            let mut installed_targets = targets
+                .into_iter()
+                .filter_map(|c| Some(c.component.target?.to_string()))
+                .collect::<Vec<_>>();
+            installed_targets.sort();
+            let mut installed_components =
+                components.into_iter().map(|c| c.name).collect::<Vec<_>>();
+            installed_components.sort();
+            Some(ActiveToolchain {
+                name: name.to_string(),
+                path: toolchain.path().to_owned(),
+                reason: Reason::from(&reason),
+                installed_targets,
+                installed_components,
+                rustc_version: verbose.then(|| toolchain.rustc_version()),
+            })
+        }
+        None => None,
+    };
+
+    Ok(Show {
+        default_host: cfg.get_default_host_triple()?.to_string(),
+        rustup_home: cfg.rustup_dir.clone(),
+        installed_toolchains: installed_toolchains(cfg, verbose)?,
+        active_toolchain,
+    })
+}
+
+/// The toolchain active in `cfg.current_dir`, without installing it.
+pub(super) fn resolved_line(cfg: &Cfg<'_>) -> Result<String> {
+    let active = cfg.find_active_toolchain()?;
+    line(Resolved {
+        toolchain: active.as_ref().map(|(name, _)| name.to_string()),
+        reason: active.as_ref().map(|(_, reason)| Reason::from(reason)),
+    })
+}
+
+pub(super) fn invalidated_line(invalidated: usize) -> Result<String> {
+    line(Invalidated { invalidated })
+}
+
+pub(super) fn error_line(error: &anyhow::Error) -> String {
+    line(Failed {
+        error: format!("{error:#}"),
+    })
+    .unwrap_or_default()
+}
+
+pub(super) fn list_toolchains(cfg: &Cfg<'_>, verbose: bool, version: u32) -> Result<ExitCode> {
+    let toolchains = installed_toolchains(cfg, verbose)?;
+    print(cfg.process, version, Toolchains { toolchains })?;
+    Ok(ExitCode(0))
+}
+
+/// The installed toolchains, in the order `rustup toolchain list` prints them.
+fn installed_toolchains(cfg: &Cfg<'_>, verbose: bool) -> Result<Vec<InstalledToolchain>> {
+    let default = cfg.get_default()?;
+    let active = match cfg.find_active_toolchain() {
+        Ok(Some((LocalToolchainName::Named(name), _))) => Some(name),
+        _ => None,
+    };
+    cfg.list_toolchains()?
+        .into_iter()
+        .map(|name| {
+            let path = cfg.toolchains_dir.join(name.to_string());
+            let linked = fs::symlink_metadata(&path)?.is_symlink();
+            let rustc_version = match verbose {
+                true => Some(Toolchain::new(cfg, name.clone().into())?.rustc_version()),
+                false => None,
+            };
+            Ok(InstalledToolchain {
+                default: default.as_ref() == Some(&name),
+                active: active.as_ref() == Some(&name),
+                pinned: cfg.is_pinned(&name)?,
+                name: name.to_string(),
+                path,
+                linked,
+                rustc_version,
+            })
+        })
+        .collect()
+}
+
+/// The overrides, sorted by path.
+pub(super) fn list_overrides(cfg: &Cfg<'_>, version: u32) -> Result<ExitCode> {
+    let overrides = cfg.settings_file.with(|s| Ok(s.overrides.clone()))?;
+    let overrides = overrides
+        .into_iter()
+        .map(|(path, toolchain)| {
+            let path = PathBuf::from(path);
+            OverrideEntry {
+                exists: path.is_dir(),
+                path,
+                toolchain,
+            }
+        })
+        .collect();
+    print(cfg.process, version, Overrides { overrides })?;
+    Ok(ExitCode(0))
+}
+
+/// `components` are those of the toolchain that are listed, already
+/// filtered. They are printed sorted by name.
+pub(super) fn list_components(
+    distributable: &DistributableToolchain<'_>,
+    components: Vec<ComponentStatus>,
+    version: u32,
+    process: &Process,
+) -> Result<ExitCode> {
+    let mut components = components
+        .into_iter()
+        .map(|c| ComponentEntry {
+            component: c.component.short_name_in_manifest().to_owned(),
+            target: c.component.target.as_ref().map(|t| t.to_string()),
+            installed: c.installed,
+            download_size: c.download_size,
+            installed_size: c.installed_size,
+            name: c.name,
+        })
+        .collect::<Vec<_>>();
+    components.sort_by(|a, b| a.name.cmp(&b.name));
+    let toolchain = distributable.desc().to_string();
+    print(
+        process,
+        version,
+        Components {
+            toolchain,
+            components,
+        },
+    )?;
+    Ok(ExitCode(0))
+}
+
+/// `targets` are those of the toolchain that are listed, already filtered.
+/// They are printed sorted by name.
+pub(super) fn list_targets(
+    distributable: &DistributableToolchain<'_>,
+    targets: Vec<ComponentStatus>,
+    version: u32,
+    process: &Process,
+) -> Result<ExitCode> {
+    let mut targets = targets
+        .into_iter()
+        .filter_map(|c| {
+            Some(TargetEntry {
+                name: c.component.target?.to_string(),
+                installed: c.installed,
+                tier: c.tier,
+            })
+        })
+        .collect::<Vec<_>>();
+    targets.sort_by(|a, b| a.name.cmp(&b.name));
+    let toolchain = distributable.desc().to_string();
+    print(process, version, Targets { toolchain, targets })?;
+    Ok(ExitCode(0))
+}
+
+/// `found` are the toolchains that have the command, in the order `rustup
+/// toolchain list` prints them, and why the one that runs it is active.
+pub(super) fn which(
+    process: &Process,
+    version: u32,
+    command: &str,
+    found: &[(Toolchain<'_>, Option<&ActiveReason>)],
+) -> Result<ExitCode> {
+    let binaries = found
+        .iter()
+        .map(|(toolchain, reason)| Binary {
+            toolchain: toolchain.name().to_string(),
+            path: toolchain.binary_file(command),
+            sysroot: toolchain.path().to_owned(),
+            reason: reason.map(Reason::from),
+        })
+        .collect();
+    let command = command.to_owned();
+    print(process, version, Which { command, binaries })?;
+    Ok(ExitCode(0))
+}
+
+/// `toolchains` are the name, installed version and newer version, if any,
+/// of each toolchain installed from a channel, in the order `rustup toolchain
+/// list` prints them, and `rustup` the running and the newest version of
+/// rustup.
+pub(super) fn check(
+    process: &Process,
+    version: u32,
+    toolchains: Vec<(String, Option<String>, Option<String>)>,
+    rustup: (&str, &str),
+) -> Result<()> {
+    let toolchains = toolchains
+        .into_iter()
+        .map(|(name, current, newer)| ToolchainCheck {
+            name,
+            versions: Versions {
+                update_available: newer.is_some(),
+                available_version: newer.or_else(|| current.clone()),
+                current_version: current,
+            },
+        })
+        .collect::<Vec<_>>();
+    let (current, available) = rustup;
+    let rustup = Versions {
+        current_version: Some(current.to_owned()),
+        available_version: Some(available.to_owned()),
+        update_available: current != available,
+    };
+    let check = Check {
+        update_available: rustup.update_available
+            || toolchains.iter().any(|t| t.versions.update_available),
+        toolchains,
+        rustup,
+    };
+    print(process, version, check)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_version_is_checked() {
+        assert_eq!(format_version("1"), Ok(1));
+        assert!(format_version("2").is_err());
+        assert!(format_version("latest").is_err());
+
+        let json = OutputFormat::Json(SCHEMA_VERSION);
+        let v1 = FormatVersion {
+            format_version: Some(1),
+        };
+        assert_eq!(json.with_version(FormatVersion::default()), json);
+        assert_eq!(json.with_version(v1), OutputFormat::Json(1));
+        assert_eq!(OutputFormat::Human.with_version(v1), OutputFormat::Human);
+    }
+
+    /// Consumers may rely on the order of the fields, so it is pinned here.
+    #[test]
+    fn fields_keep_their_order() {
+        let toolchains = Document {
+            version: 1,
+            body: Toolchains {
+                toolchains: vec![InstalledToolchain {
+                    name: "stable".to_owned(),
+                    path: PathBuf::from("/toolchains/stable"),
+                    default: true,
+                    active: false,
+                    pinned: false,
+                    linked: false,
+                    rustc_version: None,
+                }],
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&toolchains).unwrap(),
+            r#"{"version":1,"toolchains":[{"name":"stable","path":"/toolchains/stable","default":true,"active":false,"pinned":false,"linked":false}]}"#
+        );
+
+        let versions = |current: &str, available: &str| Versions {
+            current_version: Some(current.to_owned()),
+            available_version: Some(available.to_owned()),
+            update_available: current != available,
+        };
+        let check = Document {
+            version: 1,
+            body: Check {
+                update_available: true,
+                toolchains: vec![ToolchainCheck {
+                    name: "stable".to_owned(),
+                    versions: versions("1.0.0", "1.1.0"),
+                }],
+                rustup: versions("1.28.0", "1.28.0"),
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&check).unwrap(),
+            concat!(
+                r#"{"version":1,"update_available":true,"toolchains":[{"name":"stable","#,
+                r#""current_version":"1.0.0","available_version":"1.1.0","update_available":true}],"#,
+                r#""rustup":{"current_version":"1.28.0","available_version":"1.28.0","update_available":false}}"#,
+            )
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Printing the lists of `rustup component list` and `rustup target list`.
+//!
+//! On a terminal, the items are laid out in as many columns as fit its width,
+//! and a list taller than the terminal is shown through a pager. `--porcelain`
+//! instead prints one item per line with its status, in a format that scripts
+//! can rely on.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use anyhow::Result;
+use termcolor::{Ansi, ColorChoice, ColorSpec, NoColor, WriteColor};
+
+use crate::process::{
+    terminalsource::{self, color_choice},
+    Process,
+};
+
+/// The space between two columns.
+const GUTTER: usize = 2;
+
+/// An entry of a [`Listing`].
+pub(super) struct Item {
+    pub(super) name: String,
+    pub(super) installed: bool,
+    /// Shown after the name, which keeps the list in a single column.
+    pub(super) detail: Option<String>,
+}
+
+/// A list of items in optional groups, printed all at once.
+pub(super) struct Listing {
+    groups: Vec<(Option<String>, Vec<Item>)>,
+    mark_installed: bool,
+}
+
+impl Listing {
+    /// An empty listing, marking installed items as such if `mark_installed`.
+    pub(super) fn new(mark_installed: bool) -> Self {
+        Self {
+            groups: Vec::new(),
+            mark_installed,
+        }
+    }
+
+    /// Appends a group of items, shown under `heading` if there is one.
+    pub(super) fn push_group(&mut self, heading: Option<String>, items: Vec<Item>) {
+        self.groups.push((heading, items));
+    }
+
+    pub(super) fn print(&self, porcelain: bool, process: &Process) -> Result<()> {
+        if porcelain {
+            let stdout = process.stdout();
+            let mut stdout = stdout.lock();
+            for item in self.groups.iter().flat_map(|(_, items)| items) {
+                let status = match item.installed {
+                    true => "installed",
+                    false => "available",
+                };
+                writeln!(stdout, "{}\t{status}", item.name)?;
+            }
+            return Ok(());
+        }
+
+        let size = match process.stdout().is_a_tty(process) {
+            true => terminal_size(process),
+            false => None,
+        };
+        let lines = self.lines(size.map(|(width, _)| width));
+        if let Some((_, height)) = size {
+            if lines.len() >= height && page(&lines, process)? {
+                return Ok(());
+            }
+        }
+
+        let mut t = process.stdout().terminal(process);
+        for line in &lines {
+            for (text, bold) in line {
+                if *bold {
+                    t.attr(terminalsource::Attr::Bold)?;
+                    write!(t.lock(), "{text}")?;
+                    t.reset()?;
+                } else {
+                    write!(t.lock(), "{text}")?;
+                }
+            }
+            writeln!(t.lock())?;
+        }
+        Ok(())
+    }
+
+    /// Renders the listing to lines of text spans, which are bold or not.
+    fn lines(&self, width: Option<usize>) -> Vec<Vec<(String, bool)>> {
+        let single_column = self
+            .groups
+            .iter()
+            .flat_map(|(_, items)| items)
+            .any(|item| item.detail.is_some());
+
+        let mut lines = Vec::new();
+        for (i, (heading, items)) in self.groups.iter().enumerate() {
+            if i > 0 && heading.is_some() {
+                lines.push(Vec::new());
+            }
+            if let Some(heading) = heading {
+                lines.push(vec![(format!("{heading}:"), true)]);
+            }
+
+            let cells = items
+                .iter()
+                .map(|item| {
+                    let mark = self.mark_installed && item.installed;
+                    let text = match mark {
+                        true => format!("{} (installed)", item.name),
+                        false => item.name.clone(),
+                    };
+                    let detail = item.detail.as_ref().map(|d| format!("\t{d}"));
+                    (text, mark, detail)
+                })
+                .collect::<Vec<_>>();
+
+            let widths = match (width, single_column) {
+                (Some(width), false) => {
+                    column_widths(&cells.iter().map(|c| c.0.len()).collect::<Vec<_>>(), width)
+                }
+                _ => vec![0],
+            };
+            let rows = cells.len().div_ceil(widths.len());
+            for row in 0..rows {
+                let mut line = Vec::new();
+                for col in 0..widths.len() {
+                    let Some((text, bold, detail)) = cells.get(col * rows + row) else {
+                        break;
+                    };
+                    if col > 0 {
+                        let previous = &cells[(col - 1) * rows + row].0;
+                        let padding = widths[col - 1] + GUTTER - previous.len();
+                        line.push((" ".repeat(padding), false));
+                    }
+                    line.push((text.clone(), *bold));
+                    if let Some(detail) = detail {
+                        line.push((detail.clone(), false));
+                    }
+                }
+                lines.push(line);
+            }
+        }
+        lines
+    }
+}
+
+/// The widths of the columns that lay out items of `lengths`, filled column by
+/// column, in `width` characters. A single column is used when even two don't
+/// fit.
+fn column_widths(lengths: &[usize], width: usize) -> Vec<usize> {
+    for columns in (2..=lengths.len()).rev() {
+        let rows = lengths.len().div_ceil(columns);
+        let widths = lengths
+            .chunks(rows)
+            .map(|column| column.iter().copied().max().unwrap_or(0))
+            .collect::<Vec<_>>();
+        // Fewer items than columns can leave the last ones empty.
+        if widths.len() < columns {
+            continue;
+        }
+        let total = widths.iter().sum::<usize>() + GUTTER * (widths.len() - 1);
+        if total <= width {
+            return widths;
+        }
+    }
+    vec![lengths.iter().copied().max().unwrap_or(0)]
+}
+
+/// Shows `lines` through the pager, returning whether there was one to run.
+///
+/// `NO_PAGER` and the plain output style disable the pager, and `PAGER` picks
+/// it, defaulting to `less` on Unix and to none on Windows.
+fn page(lines: &[Vec<(String, bool)>], process: &Process) -> Result<bool> {
+    if process.var_os("NO_PAGER").is_some() || process.plain_output() {
+        return Ok(false);
+    }
+    let pager = match process.var("PAGER") {
+        Ok(pager) => pager,
+        Err(_) if cfg!(unix) => "less -FRX".to_owned(),
+        Err(_) => return Ok(false),
+    };
+    let mut words = pager.split_whitespace();
+    let Some(program) = words.next() else {
+        return Ok(false);
+    };
+    let Ok(mut child) = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn()
+    else {
+        return Ok(false);
+    };
+
+    let stdin = child.stdin.take().expect("stdin of the pager is piped");
+    let color = color_choice(true, process) != ColorChoice::Never;
+    let written = match color {
+        true => write_lines(lines, &mut Ansi::new(stdin)),
+        false => write_lines(lines, &mut NoColor::new(stdin)),
+    };
+    match written {
+        // The pager was quit before the end of the list.
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        result => result?,
+    }
+    child.wait()?;
+    Ok(true)
+}
+
+fn write_lines(lines: &[Vec<(String, bool)>], out: &mut impl WriteColor) -> io::Result<()> {
+    let bold = ColorSpec::new().set_bold(true).clone();
+    for line in lines {
+        for (text, is_bold) in line {
+            if *is_bold {
+                out.set_color(&bold)?;
+                write!(out, "{text}")?;
+                out.reset()?;
+            } else {
+                write!(out, "{text}")?;
+            }
+        }
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+/// The width and height of the terminal on stdout, which `COLUMNS` and
+/// `LINES` override.
+fn terminal_size(process: &Process) -> Option<(usize, usize)> {
+    let var = |key| process.var(key).ok()?.parse::<usize>().ok();
+    let (width, height) = os_terminal_size().unwrap_or((80, 24));
+    Some((
+        var("COLUMNS").unwrap_or(width),
+        var("LINES").unwrap_or(height),
+    ))
+}
+
+#[cfg(unix)]
+fn os_terminal_size() -> Option<(usize, usize)> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: TIOCGWINSZ only writes to the winsize it is given.
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    (result == 0 && size.ws_col > 0).then_some((size.ws_col.into(), size.ws_row.into()))
+}
+
+#[cfg(windows)]
+fn os_terminal_size() -> Option<(usize, usize)> {
+    use windows_sys::Win32::System::Console::{
+        GetConsoleScreenBufferInfo, GetStdHandle, CONSOLE_SCREEN_BUFFER_INFO, STD_OUTPUT_HANDLE,
+    };
+
+    // SAFETY: the buffer info is plain data, written by the call.
+    let window = unsafe {
+        let mut info = std::mem::zeroed::<CONSOLE_SCREEN_BUFFER_INFO>();
+        if GetConsoleScreenBufferInfo(GetStdHandle(STD_OUTPUT_HANDLE), &mut info) == 0 {
+            return None;
+        }
+        info.srWindow
+    };
+    let width = usize::try_from(window.Right - window.Left + 1).ok()?;
+    let height = usize::try_from(window.Bottom - window.Top + 1).ok()?;
+    Some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, installed: bool) -> Item {
+        Item {
+            name: name.to_owned(),
+            installed,
+            detail: None,
+        }
+    }
+
+    fn render(listing: &Listing, width: Option<usize>) -> Vec<String> {
+        listing
+            .lines(width)
+            .into_iter()
+            .map(|line| line.into_iter().map(|(text, _)| text).collect())
+            .collect()
+    }
+
+    #[test]
+    fn columns_fill_the_width() {
+        assert_eq!(column_widths(&[3, 5, 4, 2, 6], 80), [3, 5, 4, 2, 6]);
+        assert_eq!(column_widths(&[3, 5, 4, 2, 6], 20), [5, 4, 6]);
+        assert_eq!(column_widths(&[3, 5, 4, 2, 6], 13), [5, 6]);
+        assert_eq!(column_widths(&[3, 5, 4, 2, 6], 4), [6]);
+        assert_eq!(column_widths(&[], 80), [0]);
+    }
+
+    #[test]
+    fn items_are_laid_out_in_columns() {
+        let mut listing = Listing::new(true);
+        listing.push_group(
+            Some("tier 1".to_owned()),
+            vec![item("aaa", true), item("bb", false), item("c", false)],
+        );
+        listing.push_group(Some("tier 2".to_owned()), vec![item("dddd", false)]);
+
+        assert_eq!(
+            render(&listing, Some(20)),
+            ["tier 1:", "aaa (installed)  c", "bb", "", "tier 2:", "dddd",]
+        );
+        assert_eq!(render(&listing, None)[1..4], ["aaa (installed)", "bb", "c"]);
+    }
+
+    #[test]
+    fn details_keep_a_single_column() {
+        let mut listing = Listing::new(false);
+        let mut sized = item("aaa", true);
+        sized.detail = Some("download: 1 B".to_owned());
+        listing.push_group(None, vec![sized, item("bb", false)]);
+
+        assert_eq!(render(&listing, Some(80)), ["aaa\tdownload: 1 B", "bb"]);
+    }
+}
@@ -2,11 +2,12 @@ use std::{fmt, io::Write};
 
 #[cfg(feature = "otel")]
 use opentelemetry_sdk::trace::Tracer;
-use termcolor::{Color, ColorSpec, WriteColor};
+use termcolor::{Color, ColorChoice, ColorSpec, WriteColor};
 use tracing::{level_filters::LevelFilter, Event, Subscriber};
 use tracing_subscriber::{
+    filter::Targets,
     fmt::{
-        format::{self, FormatEvent, FormatFields},
+        format::{self, FmtSpan, FormatEvent, FormatFields},
         FmtContext,
     },
     layer::SubscriberExt,
@@ -14,7 +15,11 @@ use tracing_subscriber::{
     reload, EnvFilter, Layer, Registry,
 };
 
-use crate::{process::Process, utils::notify::NotificationLevel};
+use crate::{
+    dist::timings::{self, Timings},
+    process::{terminalsource::color_choice, Process},
+    utils::notify::NotificationLevel,
+};
 
 pub fn tracing_subscriber(
     process: &Process,
@@ -25,19 +30,48 @@ pub fn tracing_subscriber(
     #[cfg(feature = "otel")]
     let telemetry = telemetry(process);
     let (console_logger, console_filter) = console_logger(process);
+    let timings = timings();
     #[cfg(feature = "otel")]
     {
         (
-            Registry::default().with(console_logger).with(telemetry),
+            Registry::default()
+                .with(console_logger)
+                .with(timings)
+                .with(telemetry),
             console_filter,
         )
     }
     #[cfg(not(feature = "otel"))]
     {
-        (Registry::default().with(console_logger), console_filter)
+        (
+            Registry::default().with(console_logger).with(timings),
+            console_filter,
+        )
     }
 }
 
+/// The subscriber of the proxies, which only log to the console: they record
+/// no `--timings`, and export no telemetry, which would need the tokio
+/// runtime from the start.
+pub fn proxy_tracing_subscriber(
+    process: &Process,
+) -> (
+    impl tracing::Subscriber,
+    reload::Handle<EnvFilter, Registry>,
+) {
+    let (console_logger, console_filter) = console_logger(process);
+    (Registry::default().with(console_logger), console_filter)
+}
+
+/// A [`tracing::Subscriber`] [`Layer`][`tracing_subscriber::Layer`] that records the
+/// per-component timings behind `rustup --timings`. It does nothing until started.
+fn timings<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    Timings::default().with_filter(Targets::new().with_target(timings::TARGET, LevelFilter::TRACE))
+}
+
 /// A [`tracing::Subscriber`] [`Layer`][`tracing_subscriber::Layer`] that prints out the log
 /// lines to the current [`Process`]' `stderr`.
 ///
@@ -48,17 +82,17 @@ fn console_logger<S>(process: &Process) -> (impl Layer<S>, reload::Handle<EnvFil
 where
     S: Subscriber + for<'span> LookupSpan<'span>,
 {
-    let has_ansi = match process.var("RUSTUP_TERM_COLOR") {
-        Ok(s) if s.eq_ignore_ascii_case("always") => true,
-        Ok(s) if s.eq_ignore_ascii_case("never") => false,
-        // `RUSTUP_TERM_COLOR` is prioritized over `NO_COLOR`.
-        _ if process.var("NO_COLOR").is_ok() => false,
-        _ => process.stderr().is_a_tty(process),
-    };
+    // Log lines are colored with escape sequences, which legacy Windows
+    // consoles would show as they are.
+    let stderr = process.stderr();
+    let has_ansi = color_choice(stderr.is_a_tty(process), process) != ColorChoice::Never
+        && !stderr.is_legacy_console(process);
     let maybe_rustup_log_directives = process.var("RUSTUP_LOG");
-    let process = process.clone();
     let logger = tracing_subscriber::fmt::layer()
-        .with_writer(move || process.stderr())
+        .with_writer({
+            let process = process.clone();
+            move || process.stderr()
+        })
         .with_ansi(has_ansi);
     if let Ok(directives) = maybe_rustup_log_directives {
         let (env_filter, handle) = reload::Layer::new(
@@ -66,13 +100,20 @@ where
                 .with_default_directive(LevelFilter::INFO.into())
                 .parse_lossy(directives),
         );
+        // A span's closing says how long it was open, which is what the
+        // spans of proxies are for.
+        let logger = logger
+            .with_ansi(has_ansi && !process.plain_output())
+            .with_span_events(FmtSpan::CLOSE);
         (logger.compact().with_filter(env_filter).boxed(), handle)
     } else {
         // Receive log lines from Rustup only.
         let (env_filter, handle) = reload::Layer::new(EnvFilter::new("rustup=INFO"));
         (
             logger
-                .event_format(EventFormatter)
+                .event_format(EventFormatter {
+                    process: process.clone(),
+                })
                 .with_filter(env_filter)
                 .boxed(),
             handle,
@@ -82,7 +123,11 @@ where
 
 // Adapted from
 // https://docs.rs/tracing-subscriber/latest/tracing_subscriber/fmt/trait.FormatEvent.html#examples
-struct EventFormatter;
+struct EventFormatter {
+    /// Asked for the output style only once there is something to log, as
+    /// that may have to read the settings.
+    process: Process,
+}
 
 impl<S, N> FormatEvent<S, N> for EventFormatter
 where
@@ -95,12 +140,13 @@ where
         mut writer: format::Writer<'_>,
         event: &Event<'_>,
     ) -> fmt::Result {
-        let has_ansi = writer.has_ansi_escapes();
+        let has_ansi = writer.has_ansi_escapes() && !self.process.plain_output();
         let level = NotificationLevel::from(*event.metadata().level());
         {
             let mut buf = termcolor::Buffer::ansi();
             if has_ansi {
-                _ = buf.set_color(ColorSpec::new().set_bold(true).set_fg(level.fg_color()));
+                let fg = level.fg_color().map(|c| self.process.palette().fg(c));
+                _ = buf.set_color(ColorSpec::new().set_bold(true).set_fg(fg));
             }
             _ = write!(buf, "{level}: ");
             if has_ansi {
@@ -193,8 +193,8 @@ impl<'a> LineFormatter<'a> {
             }
             TagEnd::Strong => {}
             TagEnd::Strikethrough => {}
-            TagEnd::Link { .. } => {}
-            TagEnd::Image { .. } => {} // shouldn't happen, handled in start
+            TagEnd::Link => {}
+            TagEnd::Image => {} // shouldn't happen, handled in start
             TagEnd::FootnoteDefinition => {}
             TagEnd::MetadataBlock(_) => {}
         }
@@ -0,0 +1,196 @@
+//! The catalogs of the messages that rustup shows to users, in each of the
+//! languages it speaks.
+//!
+//! The catalogs are [Fluent] files in the `messages` directory, named after
+//! their language. The language is chosen by `RUSTUP_LANG`, or else by the
+//! locale of the user, and messages missing from its catalog are taken from
+//! the English one.
+//!
+//! [Fluent]: https://projectfluent.org/
+
+use fluent_bundle::{FluentArgs, FluentBundle, FluentResource, FluentValue};
+use tracing::debug;
+use unic_langid::LanguageIdentifier;
+
+use crate::process::Process;
+
+const CATALOGS: &[(&str, &str)] = &[
+    ("en-US", include_str!("messages/en-US.ftl")),
+    ("es", include_str!("messages/es.ftl")),
+];
+
+/// Looks up the message `id` in the catalog of the user's language, and fills
+/// in its variables from `args`.
+pub(crate) fn message(process: &Process, id: &str, args: &[(&str, FluentValue<'_>)]) -> String {
+    let args = args
+        .iter()
+        .map(|(name, value)| (*name, value.clone()))
+        .collect::<FluentArgs<'_>>();
+    let language = language(process);
+    [language, CATALOGS[0].0]
+        .into_iter()
+        .find_map(|language| format(language, id, &args))
+        .unwrap_or_else(|| {
+            debug!("message '{id}' is missing from the catalogs");
+            id.to_owned()
+        })
+}
+
+fn format(language: &str, id: &str, args: &FluentArgs<'_>) -> Option<String> {
+    let (_, source) = CATALOGS.iter().find(|(name, _)| *name == language)?;
+    let resource =
+        FluentResource::try_new(source.to_string()).expect("message catalogs should parse");
+    let mut bundle = FluentBundle::new(vec![language.parse().ok()?]);
+    // The isolation marks around variables are meant for mixing scripts of
+    // both directions, and show up as garbage in terminals.
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .expect("message catalogs should not repeat an id");
+
+    let pattern = bundle.get_message(id)?.value()?;
+    let mut errors = Vec::new();
+    let message = bundle.format_pattern(pattern, Some(args), &mut errors);
+    for error in errors {
+        debug!("could not format message '{id}' in {language}: {error}");
+    }
+    Some(message.into_owned())
+}
+
+/// The name of the catalog in the user's language, falling back to English.
+fn language(process: &Process) -> &'static str {
+    let requested = ["RUSTUP_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|key| process.var(key).ok())
+        .find(|value| !value.is_empty());
+    #[cfg(windows)]
+    let requested = requested.or_else(os_locale);
+    let Some(requested) = requested.as_deref().and_then(parse_locale) else {
+        return CATALOGS[0].0;
+    };
+
+    CATALOGS
+        .iter()
+        .map(|(name, _)| *name)
+        .find(|name| {
+            name.parse::<LanguageIdentifier>()
+                .is_ok_and(|catalog| catalog.language == requested.language)
+        })
+        .unwrap_or(CATALOGS[0].0)
+}
+
+/// Parses a POSIX locale such as `es_ES.UTF-8`, or a language tag such as
+/// `es-ES`.
+fn parse_locale(locale: &str) -> Option<LanguageIdentifier> {
+    let locale = locale.split(['.', '@']).next()?;
+    match locale {
+        "C" | "POSIX" => None,
+        _ => locale.replace('_', "-").parse().ok(),
+    }
+}
+
+#[cfg(windows)]
+fn os_locale() -> Option<String> {
+    use windows_sys::Win32::Globalization::GetUserDefaultLocaleName;
+
+    // LOCALE_NAME_MAX_LENGTH
+    let mut name = [0u16; 85];
+    // SAFETY: the name is written to the buffer, within the given length.
+    let len = unsafe { GetUserDefaultLocaleName(name.as_mut_ptr(), name.len() as i32) };
+    // The length includes the terminating NUL.
+    let len = usize::try_from(len).ok()?.checked_sub(1)?;
+    String::from_utf16(&name[..len]).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::process::TestProcess;
+
+    fn process(vars: &[(&str, &str)]) -> Process {
+        let vars = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        TestProcess::with_vars(vars).process
+    }
+
+    #[test]
+    fn catalogs_have_the_english_messages() {
+        let ids = |source: &str| {
+            assert!(FluentResource::try_new(source.to_owned()).is_ok());
+            source
+                .lines()
+                .filter(|line| line.starts_with(|c: char| c.is_ascii_lowercase()))
+                .filter_map(|line| line.split_once(" =").map(|(id, _)| id.to_owned()))
+                .collect::<Vec<_>>()
+        };
+        let english = ids(CATALOGS[0].1);
+        for (language, source) in &CATALOGS[1..] {
+            for id in ids(source) {
+                assert!(
+                    english.contains(&id),
+                    "'{id}' of {language} is not in English"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn language_follows_the_locale() {
+        let en = process(&[]);
+        assert_eq!(language(&en), "en-US");
+        let es = process(&[("LANG", "es_MX.UTF-8")]);
+        assert_eq!(language(&es), "es");
+        let overridden = process(&[("LANG", "es_ES.UTF-8"), ("RUSTUP_LANG", "en")]);
+        assert_eq!(language(&overridden), "en-US");
+        let unknown = process(&[("LC_ALL", "tlh"), ("LANG", "es_ES")]);
+        assert_eq!(language(&unknown), "en-US");
+        let posix = process(&[("LC_ALL", "C")]);
+        assert_eq!(language(&posix), "en-US");
+    }
+
+    #[test]
+    fn messages_are_formatted() {
+        let es = process(&[("RUSTUP_LANG", "es")]);
+        assert_eq!(
+            message(
+                &es,
+                "error-toolchain-not-installed",
+                &[("name", "nightly".into())]
+            ),
+            "la toolchain 'nightly' no está instalada"
+        );
+        assert_eq!(
+            message(
+                &es,
+                "error-download-server-failed",
+                &[
+                    ("url", "https://example.com/".into()),
+                    ("status", 503.into())
+                ]
+            ),
+            "el servidor no pudo enviar 'https://example.com/' y respondió con el estado 503; \
+             ejecuta `rustup status` para ver si la infraestructura de Rust tiene una incidencia"
+        );
+        let en = process(&[]);
+        assert_eq!(
+            message(
+                &en,
+                "setup-path-unix",
+                &[("count", 1.into()), ("rcfiles", "    ~/.profile".into())]
+            ),
+            "This path will then be added to your `PATH` environment variable by\n\
+             modifying the profile file located at:\n\n    ~/.profile"
+        );
+        assert!(message(
+            &en,
+            "setup-source-env",
+            &[("cargo_home", "~/.cargo".into())]
+        )
+        .contains("\n    . \"~/.cargo/env\"            # For sh/"));
+        assert_eq!(message(&en, "no-such-message", &[]), "no-such-message");
+    }
+}
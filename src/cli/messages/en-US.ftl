@@ -0,0 +1,132 @@
+# The messages of rustup in English, which are also used for the messages
+# missing from the catalogs of other languages.
+
+## Installation
+
+setup-welcome =
+    # Welcome to Rust!
+
+    This will download and install the official compiler for the Rust
+    programming language, and its package manager, Cargo.
+
+    Rustup metadata and toolchains will be installed into the Rustup
+    home directory, located at:
+
+        { $rustup_home }
+
+    This can be modified with the RUSTUP_HOME environment variable.
+
+    The Cargo home directory is located at:
+
+        { $cargo_home }
+
+    This can be modified with the CARGO_HOME environment variable.
+
+    The `cargo`, `rustc`, `rustup` and other commands will be added to
+    Cargo's bin directory, located at:
+
+        { $cargo_home_bin }
+
+    { $path_note }
+
+    You can uninstall at any time with `rustup self uninstall` and
+    these changes will be reverted.
+
+setup-path-unix =
+    This path will then be added to your `PATH` environment variable by
+    modifying the profile { $count ->
+        [one] file
+       *[other] files
+    } located at:
+
+    { $rcfiles }
+
+setup-path-windows =
+    This path will then be added to your `PATH` environment variable by
+    modifying the `HKEY_CURRENT_USER/Environment/PATH` registry key.
+
+setup-path-unchanged =
+    This path needs to be in your `PATH` environment variable,
+    but will not be added automatically.
+
+# The labels are right-aligned by the code spans of spaces before them.
+setup-options =
+    Current installation options:
+
+    - ` `default host triple: `{ $host }`
+    - `   `default toolchain: `{ $toolchain }`
+    - `             `profile: `{ $profile }`
+    - modify PATH variable: `{ $modify_path ->
+        [yes] yes
+       *[no] no
+    }`
+
+setup-options-components = - `          `components: `{ $components }`
+setup-options-targets = - `             `targets: `{ $targets }`
+setup-default-toolchain = { $toolchain } (default)
+
+setup-proceed-standard = 1) Proceed with standard installation (default - just press enter)
+setup-proceed-selected = 1) Proceed with selected options (default - just press enter)
+setup-customize = 2) Customize installation
+setup-cancel = 3) Cancel installation
+
+setup-customize-intro =
+    I'm going to ask you the value of each of these installation options.
+    You may simply press the Enter key to leave unchanged.
+setup-ask-host = Default host triple?
+setup-ask-toolchain = Default toolchain? (stable/beta/nightly/none)
+setup-ask-profile = Profile (which tools and data to install)? ({ $profiles })
+setup-ask-extras = Additional components and targets?
+setup-extras-target = { $target } (target)
+setup-ask-modify-path = Modify PATH variable?
+
+checklist-hint = (enter numbers to toggle, or press Enter to continue)
+checklist-not-listed = '{ $input }' is not one of the listed numbers
+
+setup-aborted = aborting installation
+
+setup-done =
+    # Rust is installed now. Great!
+
+    To get started you may need to restart your current shell.
+    This would reload your `PATH` environment variable to include
+    Cargo's bin directory ({ $cargo_bin }).
+
+setup-done-no-modify-path =
+    # Rust is installed now. Great!
+
+    To get started you need Cargo's bin directory ({ $cargo_bin }) in your `PATH`
+    environment variable. This has not been done automatically.
+
+# Lines can't start with a dot, so the one below is a literal, along with
+# its indentation.
+setup-source-env =
+    To configure your current shell, you need to source
+    the corresponding `env` file under { $cargo_home }.
+
+    This is usually done by running one of the following (note the leading DOT):
+        { "    ." } "{ $cargo_home }/env"            # For sh/bash/zsh/ash/dash/pdksh
+        source "{ $cargo_home }/env.fish"  # For fish
+        source "{ $cargo_home }/env.nu"    # For nushell
+
+## Uninstallation
+
+uninstall-welcome =
+    # Thanks for hacking in Rust!
+
+    This will uninstall all Rust toolchains and data, and remove
+    `{ $cargo_home }/bin` from your `PATH` environment variable.
+
+uninstall-confirm = Continue? (y/N)
+uninstall-aborted = aborting uninstallation
+
+## Errors
+
+error-toolchain-not-installed = toolchain '{ $name }' is not installed
+error-toolchain-install-help = help: run `rustup toolchain install { $name }` to install it
+error-toolchain-not-selected =
+    rustup could not choose a version of { $tool } to run, because one wasn't specified explicitly, and no default is configured.
+    help: run 'rustup default stable' to download the latest stable release of Rust and set it as your default toolchain.
+error-downloading-file = could not download file from '{ $url }' to '{ $path }'
+error-download-server-failed = the server failed to send '{ $url }' with status { $status }; run `rustup status` to see whether the Rust infrastructure has an incident
+error-download-not-confirmed = update would download { $size }, which was not confirmed (pass `--yes` to rustup to skip the question)
@@ -0,0 +1,130 @@
+# The messages of rustup in Spanish.
+
+## Instalación
+
+setup-welcome =
+    # ¡Bienvenido a Rust!
+
+    Esto descargará e instalará el compilador oficial del lenguaje de
+    programación Rust y su gestor de paquetes, Cargo.
+
+    Los metadatos y las toolchains de Rustup se instalarán en el directorio
+    raíz de Rustup, ubicado en:
+
+        { $rustup_home }
+
+    Esto se puede cambiar con la variable de entorno RUSTUP_HOME.
+
+    El directorio raíz de Cargo está ubicado en:
+
+        { $cargo_home }
+
+    Esto se puede cambiar con la variable de entorno CARGO_HOME.
+
+    Los comandos `cargo`, `rustc`, `rustup` y otros se añadirán al
+    directorio bin de Cargo, ubicado en:
+
+        { $cargo_home_bin }
+
+    { $path_note }
+
+    Puedes desinstalar en cualquier momento con `rustup self uninstall` y
+    estos cambios se revertirán.
+
+setup-path-unix =
+    Luego esta ruta se añadirá a tu variable de entorno `PATH`
+    modificando { $count ->
+        [one] el archivo de perfil ubicado
+       *[other] los archivos de perfil ubicados
+    } en:
+
+    { $rcfiles }
+
+setup-path-windows =
+    Luego esta ruta se añadirá a tu variable de entorno `PATH`
+    modificando la clave del registro `HKEY_CURRENT_USER/Environment/PATH`.
+
+setup-path-unchanged =
+    Esta ruta tiene que estar en tu variable de entorno `PATH`,
+    pero no se añadirá automáticamente.
+
+setup-options =
+    Opciones de instalación actuales:
+
+    - tripleta de host predeterminada: `{ $host }`
+    - `       `toolchain predeterminada: `{ $toolchain }`
+    - `                         `perfil: `{ $profile }`
+    - `     `modificar la variable PATH: `{ $modify_path ->
+        [yes] sí
+       *[no] no
+    }`
+
+setup-options-components = - `                    `componentes: `{ $components }`
+setup-options-targets = - `                      `objetivos: `{ $targets }`
+setup-default-toolchain = { $toolchain } (predeterminada)
+
+setup-proceed-standard = 1) Continuar con la instalación estándar (predeterminado: pulsa Intro)
+setup-proceed-selected = 1) Continuar con las opciones elegidas (predeterminado: pulsa Intro)
+setup-customize = 2) Personalizar la instalación
+setup-cancel = 3) Cancelar la instalación
+
+setup-customize-intro =
+    Te preguntaré el valor de cada una de estas opciones de instalación.
+    Puedes pulsar Intro para dejar cada una sin cambios.
+setup-ask-host = ¿Tripleta de host predeterminada?
+setup-ask-toolchain = ¿Toolchain predeterminada? (stable/beta/nightly/none)
+setup-ask-profile = ¿Perfil (qué herramientas y datos instalar)? ({ $profiles })
+setup-ask-extras = ¿Componentes y objetivos adicionales?
+setup-extras-target = { $target } (objetivo)
+setup-ask-modify-path = ¿Modificar la variable PATH?
+
+checklist-hint = (escribe números para marcarlos o desmarcarlos, o pulsa Intro para continuar)
+checklist-not-listed = '{ $input }' no es uno de los números de la lista
+
+setup-aborted = instalación cancelada
+
+setup-done =
+    # ¡Rust ya está instalado! ¡Genial!
+
+    Para empezar puede que tengas que reiniciar tu shell actual.
+    Así se recargará tu variable de entorno `PATH` para que incluya
+    el directorio bin de Cargo ({ $cargo_bin }).
+
+setup-done-no-modify-path =
+    # ¡Rust ya está instalado! ¡Genial!
+
+    Para empezar necesitas el directorio bin de Cargo ({ $cargo_bin }) en tu
+    variable de entorno `PATH`. Esto no se ha hecho automáticamente.
+
+# Lines can't start with a dot, so the one below is a literal, along with
+# its indentation.
+setup-source-env =
+    Para configurar tu shell actual, tienes que cargar con `source`
+    el archivo `env` correspondiente de { $cargo_home }.
+
+    Normalmente se hace ejecutando una de las siguientes órdenes (fíjate en el PUNTO inicial):
+        { "    ." } "{ $cargo_home }/env"            # Para sh/bash/zsh/ash/dash/pdksh
+        source "{ $cargo_home }/env.fish"  # Para fish
+        source "{ $cargo_home }/env.nu"    # Para nushell
+
+## Desinstalación
+
+uninstall-welcome =
+    # ¡Gracias por programar en Rust!
+
+    Esto desinstalará todas las toolchains y los datos de Rust, y quitará
+    `{ $cargo_home }/bin` de tu variable de entorno `PATH`.
+
+uninstall-confirm = ¿Continuar? (y/N)
+uninstall-aborted = desinstalación cancelada
+
+## Errores
+
+error-toolchain-not-installed = la toolchain '{ $name }' no está instalada
+error-toolchain-install-help = ayuda: ejecuta `rustup toolchain install { $name }` para instalarla
+error-toolchain-not-selected =
+    rustup no pudo elegir qué versión de { $tool } ejecutar, porque no se indicó ninguna y no hay ninguna predeterminada.
+    ayuda: ejecuta 'rustup default stable' para descargar la última versión estable de Rust y usarla como toolchain predeterminada.
+error-downloading-file = no se pudo descargar el archivo de '{ $url }' a '{ $path }'
+error-download-server-failed = el servidor no pudo enviar '{ $url }' y respondió con el estado { $status }; ejecuta `rustup status` para ver si la infraestructura de Rust tiene una incidencia
+error-download-not-confirmed = la actualización descargaría { $size }, lo que no se ha confirmado (pasa `--yes` a rustup para omitir la pregunta)
@@ -0,0 +1,216 @@
+//! Measurement of the dispatch overhead added by rustup's proxies.
+//!
+//! Every invocation of e.g. `rustc` through a proxy has to start rustup,
+//! read its settings, resolve the active toolchain and finally spawn the real
+//! binary. This module times that path against invoking the toolchain binary
+//! directly, so that regressions can be spotted by comparing the results of
+//! two releases.
+
+use std::env::consts::EXE_SUFFIX;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{config::Cfg, utils};
+
+/// Timings collected by a single `rustup self bench-proxy` run.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct ProxyTimings {
+    /// The version of rustup that produced these timings.
+    pub rustup_version: String,
+    pub tool: String,
+    pub iterations: usize,
+    /// The first invocation through the proxy.
+    pub cold_ms: f64,
+    /// The median of all the following invocations through the proxy.
+    pub warm_ms: f64,
+    /// The median of all invocations of the toolchain binary itself.
+    pub direct_ms: f64,
+}
+
+impl ProxyTimings {
+    /// The time spent in rustup itself for a warm proxy invocation.
+    pub(crate) fn overhead_ms(&self) -> f64 {
+        (self.warm_ms - self.direct_ms).max(0.0)
+    }
+
+    fn parse(data: &str) -> Result<Self> {
+        toml::from_str(data).context("error parsing proxy timings")
+    }
+
+    fn stringify(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+}
+
+pub(crate) struct BenchOpts<'a> {
+    pub tool: &'a str,
+    pub iterations: usize,
+    pub save: Option<&'a Path>,
+    pub baseline: Option<&'a Path>,
+    pub threshold: f64,
+}
+
+pub(crate) fn bench(cfg: &Cfg<'_>, opts: BenchOpts<'_>) -> Result<utils::ExitCode> {
+    crate::is_proxyable_tools(opts.tool)?;
+    if opts.iterations < 2 {
+        return Err(anyhow!(
+            "at least 2 iterations are needed to measure warm proxy invocations"
+        ));
+    }
+
+    // Proxies are installed next to the rustup binary itself.
+    let proxy = utils::current_exe()?
+        .parent()
+        .map(|dir| dir.join(format!("{}{EXE_SUFFIX}", opts.tool)))
+        .ok_or_else(|| anyhow!("unable to locate the rustup installation directory"))?;
+    utils::assert_is_file(&proxy)?;
+
+    let toolchain = cfg.resolve_toolchain(None)?;
+    let direct = toolchain.binary_file(opts.tool);
+    utils::assert_is_file(&direct)?;
+
+    info!(
+        "timing {} invocations of '{}' through '{}'",
+        opts.iterations,
+        opts.tool,
+        proxy.display()
+    );
+    let mut proxied = (0..opts.iterations)
+        .map(|_| time_invocation(Command::new(&proxy)))
+        .collect::<Result<Vec<_>>>()?;
+    let cold = proxied.remove(0);
+    let direct = (0..opts.iterations)
+        .map(|_| {
+            let mut cmd = Command::new(&direct);
+            toolchain.set_env(&mut cmd);
+            time_invocation(cmd)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let timings = ProxyTimings {
+        rustup_version: env!("CARGO_PKG_VERSION").to_owned(),
+        tool: opts.tool.to_owned(),
+        iterations: opts.iterations,
+        cold_ms: as_millis(cold),
+        warm_ms: as_millis(median(proxied)),
+        direct_ms: as_millis(median(direct)),
+    };
+
+    let stdout = cfg.process.stdout();
+    let mut stdout = stdout.lock();
+    writeln!(stdout, "proxy:    {}", proxy.display())?;
+    writeln!(stdout, "cold:     {:.3} ms", timings.cold_ms)?;
+    writeln!(stdout, "warm:     {:.3} ms", timings.warm_ms)?;
+    writeln!(stdout, "direct:   {:.3} ms", timings.direct_ms)?;
+    writeln!(stdout, "overhead: {:.3} ms", timings.overhead_ms())?;
+    drop(stdout);
+
+    if let Some(path) = opts.save {
+        utils::write_file("proxy timings", path, &timings.stringify()?)?;
+    }
+
+    let Some(path) = opts.baseline else {
+        return Ok(utils::ExitCode(0));
+    };
+    let baseline = ProxyTimings::parse(&utils::read_file("proxy timings", path)?)?;
+    if baseline.tool != timings.tool {
+        warn!(
+            "baseline timings are for '{}', not '{}'",
+            baseline.tool, timings.tool
+        );
+    }
+    if is_regression(&baseline, &timings, opts.threshold) {
+        warn!(
+            "proxy overhead regressed from {:.3} ms (rustup {}) to {:.3} ms (rustup {})",
+            baseline.overhead_ms(),
+            baseline.rustup_version,
+            timings.overhead_ms(),
+            timings.rustup_version,
+        );
+        return Ok(utils::ExitCode(1));
+    }
+    info!(
+        "proxy overhead is within {}% of the baseline ({:.3} ms)",
+        opts.threshold,
+        baseline.overhead_ms()
+    );
+    Ok(utils::ExitCode(0))
+}
+
+fn time_invocation(mut cmd: Command) -> Result<Duration> {
+    cmd.arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    let start = Instant::now();
+    let status = cmd.status().with_context(|| {
+        format!(
+            "failed to run '{}'",
+            PathBuf::from(cmd.get_program()).display()
+        )
+    })?;
+    let elapsed = start.elapsed();
+    if !status.success() {
+        return Err(anyhow!(
+            "'{}' exited with {status}",
+            PathBuf::from(cmd.get_program()).display()
+        ));
+    }
+    Ok(elapsed)
+}
+
+fn median(mut samples: Vec<Duration>) -> Duration {
+    samples.sort_unstable();
+    samples[samples.len() / 2]
+}
+
+fn as_millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn is_regression(baseline: &ProxyTimings, current: &ProxyTimings, threshold: f64) -> bool {
+    current.overhead_ms() > baseline.overhead_ms() * (1.0 + threshold / 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timings(warm_ms: f64, direct_ms: f64) -> ProxyTimings {
+        ProxyTimings {
+            rustup_version: "1.28.0".to_owned(),
+            tool: "rustc".to_owned(),
+            iterations: 10,
+            cold_ms: warm_ms * 2.0,
+            warm_ms,
+            direct_ms,
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let t = timings(3.0, 1.0);
+        let parsed = ProxyTimings::parse(&t.stringify().unwrap()).unwrap();
+        assert_eq!(parsed.tool, "rustc");
+        assert_eq!(parsed.overhead_ms(), 2.0);
+    }
+
+    #[test]
+    fn regression_threshold() {
+        let baseline = timings(3.0, 1.0);
+        assert!(!is_regression(&baseline, &timings(3.3, 1.0), 20.0));
+        assert!(is_regression(&baseline, &timings(3.5, 1.0), 20.0));
+    }
+
+    #[test]
+    fn median_of_samples() {
+        let samples = [5, 1, 3].map(Duration::from_millis).to_vec();
+        assert_eq!(median(samples), Duration::from_millis(3));
+    }
+}
@@ -1,37 +1,240 @@
-use std::{path::PathBuf, process::ExitStatus};
+use std::{ffi::OsString, path::PathBuf, process::ExitStatus, str::FromStr};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use clap::{builder::PossibleValue, ValueEnum};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace, trace_span, Instrument};
+use tracing_subscriber::{reload::Handle, EnvFilter, Registry};
 
+#[cfg(unix)]
+use crate::cli::agent;
 use crate::{
-    cli::{common::set_globals, job, self_update},
+    cli::{
+        common::{self, set_proxy_globals},
+        fingerprint::Inputs,
+        job, self_update, usage,
+    },
     command::run_command_for_dir,
+    config::{no_toolchain_error, ActiveReason},
+    dist::{manifestation::CONFIG_FILE, prefix::InstallPrefix},
     process::Process,
-    toolchain::ResolvableLocalToolchainName,
+    toolchain::{ResolvableLocalToolchainName, Toolchain},
 };
 
-#[tracing::instrument(level = "trace")]
-pub async fn main(arg0: &str, current_dir: PathBuf, process: &Process) -> Result<ExitStatus> {
+pub(crate) mod resolution_cache;
+
+/// Whether proxies install a missing toolchain instead of failing.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoInstallMode {
+    Enable,
+    #[default]
+    Disable,
+    /// Install without printing anything but warnings and errors, for CI.
+    Quiet,
+}
+
+impl AutoInstallMode {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Enable => "enable",
+            Self::Disable => "disable",
+            Self::Quiet => "quiet",
+        }
+    }
+}
+
+impl ValueEnum for AutoInstallMode {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Enable, Self::Disable, Self::Quiet]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.as_str()))
+    }
+
+    fn from_str(input: &str, _: bool) -> Result<Self, String> {
+        <Self as FromStr>::from_str(input).map_err(|e| e.to_string())
+    }
+}
+
+impl FromStr for AutoInstallMode {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> Result<Self> {
+        match mode {
+            "enable" => Ok(Self::Enable),
+            "disable" => Ok(Self::Disable),
+            "quiet" => Ok(Self::Quiet),
+            _ => Err(anyhow!(
+                "unknown auto install mode: '{}'; valid modes are {}",
+                mode,
+                Self::value_variants().iter().join(", ")
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for AutoInstallMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The environment variable that overrides the toolchain `tool` is run
+/// from, ahead of `RUSTUP_TOOLCHAIN`.
+pub(crate) fn overlay_var(tool: &str) -> String {
+    format!("RUSTUP_{}_TOOLCHAIN", tool.to_uppercase().replace('-', "_"))
+}
+
+/// How a proxy came to know the active toolchain.
+enum Found {
+    /// It was named, an agent told it, or it is yet to be found.
+    Other,
+    /// From the resolution cache, which only holds checked answers.
+    Cached,
+    /// By looking at the directory, with the fingerprints of what was looked
+    /// at if the answer can be cached.
+    Resolved(Option<Inputs>),
+}
+
+/// The target of the spans of each phase of running a proxy, so that
+/// `RUSTUP_LOG=rustup::proxy=trace` shows how long each one took.
+const TRACE_TARGET: &str = "rustup::proxy";
+
+#[tracing::instrument(level = "trace", skip(console_filter))]
+pub async fn main(
+    arg0: &str,
+    args: Vec<OsString>,
+    current_dir: PathBuf,
+    process: &Process,
+    console_filter: Handle<EnvFilter, Registry>,
+) -> Result<ExitStatus> {
     self_update::cleanup_self_updater(process)?;
 
     let _setup = job::setup();
-    let mut args = process.args_os().skip(1);
-
-    // Check for a + toolchain specifier
-    let arg1 = args.next();
-    let toolchain = arg1
-        .as_ref()
-        .map(|arg| arg.to_string_lossy())
-        .filter(|arg| arg.starts_with('+'))
-        .map(|name| ResolvableLocalToolchainName::try_from(&name.as_ref()[1..]))
-        .transpose()?;
-
-    // Build command args now while we know whether or not to skip arg 1.
-    let cmd_args: Vec<_> = process
-        .args_os()
-        .skip(1 + toolchain.is_some() as usize)
-        .collect();
-
-    let cfg = set_globals(current_dir, true, process)?;
-    let cmd = cfg.resolve_local_toolchain(toolchain)?.command(arg0)?;
-    run_command_for_dir(cmd, arg0, &cmd_args)
+
+    let (toolchain, overlay, cmd_args) =
+        trace_span!(target: TRACE_TARGET, "arg_parse").in_scope(|| -> Result<_> {
+            // Check for a + toolchain specifier
+            let toolchain = args
+                .first()
+                .map(|arg| arg.to_string_lossy())
+                .filter(|arg| arg.starts_with('+'))
+                .map(|name| ResolvableLocalToolchainName::try_from(&name.as_ref()[1..]))
+                .transpose()?;
+            // Set by `rustup run --rustc-from` and the like.
+            let overlay = match process.var(&overlay_var(arg0)) {
+                Ok(name) if !name.is_empty() => {
+                    Some(ResolvableLocalToolchainName::try_from(&*name)?)
+                }
+                _ => None,
+            };
+
+            // Build command args now while we know whether or not to skip arg 1.
+            let cmd_args = args[toolchain.is_some() as usize..].to_vec();
+            Ok((toolchain, overlay, cmd_args))
+        })?;
+
+    let (cfg, mode) = trace_span!(target: TRACE_TARGET, "settings_load").in_scope(|| {
+        let cfg = set_proxy_globals(current_dir, process)?;
+        let mode = cfg.get_auto_install_mode()?;
+        anyhow::Ok((cfg, mode))
+    })?;
+    if mode == AutoInstallMode::Quiet {
+        common::update_console_filter(process, &console_filter, true, false);
+    }
+
+    // Which toolchain is active and why. Installing toolchains automatically
+    // finds them along with what it must have installed, in the next phase.
+    let (active, found) = trace_span!(target: TRACE_TARGET, "override_walk").in_scope(|| {
+        let explicit = match (toolchain, overlay) {
+            (Some(name), _) => Some((name, ActiveReason::CommandLine)),
+            (None, Some(name)) => Some((name, ActiveReason::Environment)),
+            (None, None) => None,
+        };
+        if let Some((name, reason)) = explicit {
+            let name = name.resolve(&cfg.get_default_host_triple()?)?;
+            return Ok((Some((name, reason)), Found::Other));
+        }
+        if mode != AutoInstallMode::Disable {
+            return Ok((None, Found::Other));
+        }
+        // Only the directory decides, which a running agent or the
+        // resolution cache may already know.
+        let mut inputs = None;
+        if cfg.env_override.is_none() {
+            #[cfg(unix)]
+            if let Some(active) = agent::resolve(&cfg) {
+                return Ok((Some(active), Found::Other));
+            }
+            if let Some(active) = resolution_cache::lookup(&cfg) {
+                return Ok((Some(active), Found::Cached));
+            }
+            inputs = Some(Inputs::take(cfg.resolution_inputs()));
+        }
+        match cfg.find_active_toolchain()? {
+            Some(active) => Ok((Some(active), Found::Resolved(inputs))),
+            None => Err(no_toolchain_error(process)),
+        }
+    })?;
+
+    let (toolchain, cmd) = async {
+        let (toolchain, reason) = match (mode, active) {
+            (AutoInstallMode::Disable, Some((name, reason))) => {
+                let toolchain = Toolchain::new(&cfg, name)?;
+                let (checked, mut inputs) = match found {
+                    Found::Other => (false, None),
+                    // It was checked before it was cached.
+                    Found::Cached => (true, None),
+                    Found::Resolved(inputs) => (false, inputs),
+                };
+                if let (ActiveReason::ToolchainFile(path), false) = (&reason, checked) {
+                    // Fingerprinted before the check reads it, so that what
+                    // it lacks is warned about once, and again only once
+                    // something else is installed.
+                    let installed =
+                        InstallPrefix::from(toolchain.path().to_owned()).manifest_file(CONFIG_FILE);
+                    inputs = inputs.map(|inputs| inputs.with(installed));
+                    // A warning isn't worth failing the tool for.
+                    if let Err(e) = cfg.check_toolchain_file(toolchain.name(), path) {
+                        debug!("could not check '{}': {e:#}", path.display());
+                    }
+                }
+                if let Some(inputs) = inputs {
+                    resolution_cache::store(&cfg, inputs, toolchain.name(), &reason);
+                }
+                (toolchain, reason)
+            }
+            (_, Some((name, reason))) => {
+                let profile = cfg.get_profile()?;
+                (
+                    Toolchain::from_local(name, Some(profile), &cfg).await?,
+                    reason,
+                )
+            }
+            (_, None) => {
+                let (name, reason) = cfg.find_or_install_active_toolchain(false, false).await?;
+                (Toolchain::new(&cfg, name)?, reason)
+            }
+        };
+        let mut cmd = toolchain.command(arg0)?;
+        for (key, value) in cfg.toolchain_env(toolchain.name(), &reason)? {
+            // As with cargo's `[env]`, the environment wins over the settings.
+            if process.var_os(&key).is_none() {
+                cmd.env(key, value);
+            }
+        }
+        anyhow::Ok((toolchain, cmd))
+    }
+    .instrument(trace_span!(target: TRACE_TARGET, "manifest_check"))
+    .await?;
+
+    // On Unix, the tool replaces rustup, so this span never closes, and
+    // only its event tells when the tool started.
+    let _exec = trace_span!(target: TRACE_TARGET, "exec").entered();
+    usage::record(&cfg.rustup_dir, &toolchain.name().to_string());
+    trace!(target: TRACE_TARGET, "running '{}'", cmd.get_program().to_string_lossy());
+    run_command_for_dir(cmd, arg0, &cmd_args, process)
 }
@@ -0,0 +1,336 @@
+//! Remembers on disk which toolchain proxies found active in each directory,
+//! so that the next proxy run there neither walks up the directory looking
+//! for overrides nor parses the toolchain file and the toolchain's list of
+//! installed components again.
+//!
+//! Each directory has a small binary file in `RUSTUP_HOME/resolutions`, named
+//! after a hash of its path. It holds the toolchain, why it is active, and the
+//! fingerprint of every file the answer was found from, such as
+//! `settings.toml` and each `rust-toolchain.toml` that could override it, as
+//! `rustup agent` takes them. The answer is used only while none of those
+//! have changed. Once there are more than [`MAX_ENTRIES`] files, those
+//! written longest ago are removed.
+//!
+//! Only the directory decides what is cached, so proxies skip the cache when
+//! a toolchain is named on their command line or in their environment, and
+//! when they install toolchains automatically. `rustup cache
+//! invalidate-resolution` empties it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::{
+    cli::fingerprint::{Fingerprint, Inputs, State},
+    config::{ActiveReason, Cfg},
+    toolchain::{LocalToolchainName, ResolvableLocalToolchainName},
+};
+
+/// The directory in `RUSTUP_HOME` that holds the cache.
+const DIR: &str = "resolutions";
+
+/// How many directories the cache remembers.
+const MAX_ENTRIES: usize = 256;
+
+/// Starts every file, so that one of another format is never misread.
+const MAGIC: &[u8] = b"rustup-resolution-2\n";
+
+/// The toolchain cached as active in `cfg.current_dir`, if nothing it was
+/// found from has changed since.
+pub(super) fn lookup(cfg: &Cfg<'_>) -> Option<(LocalToolchainName, ActiveReason)> {
+    let data = fs::read(path(cfg)).ok()?;
+    let (name, reason, mut inputs) = decode(&data)?;
+    if !inputs.are_current() {
+        debug!(
+            "the cached toolchain of '{}' is stale",
+            cfg.current_dir.display()
+        );
+        return None;
+    }
+    let name = ResolvableLocalToolchainName::try_from(name.as_str())
+        .ok()?
+        .resolve(&cfg.get_default_host_triple().ok()?)
+        .ok()?;
+    debug!("using the cached toolchain '{name}'");
+    Some((name, reason))
+}
+
+/// Caches `name` as the toolchain active in `cfg.current_dir` for `reason`.
+/// This never fails: the cache is only an optimization.
+pub(super) fn store(
+    cfg: &Cfg<'_>,
+    inputs: Inputs,
+    name: &LocalToolchainName,
+    reason: &ActiveReason,
+) {
+    let Some(data) = encode(&name.to_string(), reason, &inputs) else {
+        return;
+    };
+    let path = path(cfg);
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    // Written aside and renamed, so that a proxy running meanwhile reads
+    // either the old answer or the new one.
+    let stored = fs::create_dir_all(path.parent().unwrap())
+        .and_then(|()| fs::write(&tmp, data))
+        .and_then(|()| fs::rename(&tmp, &path));
+    if let Err(e) = stored {
+        let _ = fs::remove_file(&tmp);
+        debug!(
+            "can't cache the toolchain of '{}': {e}",
+            cfg.current_dir.display()
+        );
+        return;
+    }
+    if let Err(e) = evict(path.parent().unwrap(), MAX_ENTRIES) {
+        debug!("can't trim the cache of toolchains: {e}");
+    }
+}
+
+/// Removes the files written longest ago while there are more than `max`.
+fn evict(dir: &Path, max: usize) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| {
+            let entry = entry?;
+            Ok((entry.metadata()?.modified()?, entry.path()))
+        })
+        .collect::<io::Result<Vec<(SystemTime, PathBuf)>>>()?;
+    if entries.len() <= max {
+        return Ok(());
+    }
+    entries.sort();
+    let excess = entries.len() - max;
+    for (_, path) in entries.into_iter().take(excess) {
+        // Another proxy may have removed it already.
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Forgets every cached toolchain, returning how many there were.
+pub(crate) fn clear(rustup_dir: &Path) -> Result<usize> {
+    let dir = rustup_dir.join(DIR);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries.count(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e).with_context(|| format!("could not read '{}'", dir.display())),
+    };
+    fs::remove_dir_all(&dir).with_context(|| format!("could not remove '{}'", dir.display()))?;
+    Ok(entries)
+}
+
+fn path(cfg: &Cfg<'_>) -> PathBuf {
+    let hash = Sha256::digest(cfg.current_dir.as_os_str().as_encoded_bytes());
+    let name = hash[..16]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    cfg.rustup_dir.join(DIR).join(name)
+}
+
+fn encode(name: &str, reason: &ActiveReason, inputs: &Inputs) -> Option<Vec<u8>> {
+    let mut data = MAGIC.to_vec();
+    put_str(&mut data, name);
+    match reason {
+        ActiveReason::Default => data.push(0),
+        ActiveReason::OverrideDB(path) => {
+            data.push(1);
+            put_str(&mut data, path.to_str()?);
+        }
+        ActiveReason::ToolchainFile(path) => {
+            data.push(2);
+            put_str(&mut data, path.to_str()?);
+        }
+        ActiveReason::CommandLine | ActiveReason::Environment => return None,
+    }
+    data.extend((inputs.0.len() as u32).to_le_bytes());
+    for Fingerprint { path, state } in &inputs.0 {
+        put_str(&mut data, path.to_str()?);
+        match state {
+            None => data.push(0),
+            Some(state) => {
+                data.push(1);
+                data.extend(state.len.to_le_bytes());
+                put_time(&mut data, state.modified)?;
+                put_time(&mut data, state.taken)?;
+                data.extend(state.hash);
+            }
+        }
+    }
+    Some(data)
+}
+
+fn decode(data: &[u8]) -> Option<(String, ActiveReason, Inputs)> {
+    let mut reader = Reader(data.strip_prefix(MAGIC)?);
+    let name = reader.string()?;
+    let reason = match reader.u8()? {
+        0 => ActiveReason::Default,
+        1 => ActiveReason::OverrideDB(reader.string()?.into()),
+        2 => ActiveReason::ToolchainFile(reader.string()?.into()),
+        _ => return None,
+    };
+    let count = reader.u32()?;
+    let mut inputs = Vec::new();
+    for _ in 0..count {
+        let path = reader.string()?.into();
+        let state = match reader.u8()? {
+            0 => None,
+            1 => Some(State {
+                len: reader.u64()?,
+                modified: reader.time()?,
+                taken: reader.time()?,
+                hash: reader.take()?,
+            }),
+            _ => return None,
+        };
+        inputs.push(Fingerprint { path, state });
+    }
+    reader
+        .0
+        .is_empty()
+        .then_some((name, reason, Inputs(inputs)))
+}
+
+fn put_str(data: &mut Vec<u8>, s: &str) {
+    data.extend((s.len() as u32).to_le_bytes());
+    data.extend(s.as_bytes());
+}
+
+fn put_time(data: &mut Vec<u8>, time: SystemTime) -> Option<()> {
+    let time = time.duration_since(UNIX_EPOCH).ok()?;
+    data.extend(time.as_secs().to_le_bytes());
+    data.extend(time.subsec_nanos().to_le_bytes());
+    Some(())
+}
+
+/// Reads what `encode` wrote, returning `None` from each method once the
+/// data runs out, as it does when the file was cut short.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, rest) = self.0.split_first_chunk::<N>()?;
+        self.0 = rest;
+        Some(*bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn time(&mut self) -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::new(self.u64()?, self.u32()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        if self.0.len() < len {
+            return None;
+        }
+        let (s, rest) = self.0.split_at(len);
+        self.0 = rest;
+        String::from_utf8(s.to_vec()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    fn old_file(path: &Path, contents: &str) {
+        fs::write(path, contents).unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+    }
+
+    #[test]
+    fn answers_round_trip_until_a_file_changes() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let file = dir.path().join("rust-toolchain.toml");
+        let absent = dir.path().join("rust-toolchain");
+        old_file(&file, "[toolchain]\nchannel = \"stable\"\n");
+
+        let inputs = Inputs::take(vec![file.clone(), absent.clone()]);
+        let reason = ActiveReason::ToolchainFile(file.clone());
+        let data = encode("stable", &reason, &inputs).unwrap();
+        let (name, reason, mut inputs) = decode(&data).unwrap();
+        assert_eq!(name, "stable");
+        assert!(matches!(reason, ActiveReason::ToolchainFile(path) if path == file));
+        assert!(inputs.are_current());
+
+        // A file that appears makes the answer stale, as does an edit.
+        fs::write(&absent, "beta").unwrap();
+        assert!(!inputs.are_current());
+        fs::remove_file(&absent).unwrap();
+        assert!(inputs.are_current());
+        old_file(&file, "[toolchain]\nchannel = \"beta\"\n");
+        assert!(!inputs.are_current());
+
+        // A cut short or foreign file is ignored.
+        assert!(decode(&data[..data.len() - 1]).is_none());
+        assert!(decode(b"[toolchain]").is_none());
+    }
+
+    #[test]
+    fn edits_that_keep_the_modification_time_are_noticed() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let file = dir.path().join("rust-toolchain");
+        fs::write(&file, "stable").unwrap();
+        let modified = fs::metadata(&file).unwrap().modified().unwrap();
+
+        let inputs = Inputs::take(vec![file.clone()]);
+        let data = encode("stable", &ActiveReason::Default, &inputs).unwrap();
+        fs::write(&file, "nightl").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let (_, _, mut inputs) = decode(&data).unwrap();
+        assert!(!inputs.are_current());
+    }
+
+    #[test]
+    fn oldest_answers_are_evicted() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        for name in ["a", "b", "c"] {
+            old_file(&dir.path().join(name), name);
+        }
+        fs::File::options()
+            .write(true)
+            .open(dir.path().join("b"))
+            .unwrap()
+            .set_modified(an_hour_ago - Duration::from_secs(60))
+            .unwrap();
+
+        evict(dir.path(), 3).unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+        evict(dir.path(), 2).unwrap();
+        assert!(!dir.path().join("b").exists());
+        assert!(dir.path().join("a").exists() && dir.path().join("c").exists());
+    }
+}
@@ -0,0 +1,122 @@
+//! Rate limiting of the warnings printed by proxies.
+//!
+//! A single build can run a proxy hundreds of times, and a warning about, say,
+//! the toolchain file would otherwise be repeated by every invocation. Each
+//! warning is instead shown at most once per interval for a given directory,
+//! with the time it was last shown kept in `RUSTUP_HOME/proxy-warnings.json`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::process::Process;
+
+/// How long a warning stays quiet after being shown, unless overridden with
+/// `RUSTUP_PROXY_WARNING_INTERVAL`.
+const DEFAULT_INTERVAL_MINUTES: u64 = 10;
+
+pub(super) struct WarningLimiter {
+    path: PathBuf,
+    dir: PathBuf,
+    interval: u64,
+    now: u64,
+    /// When each warning was last shown, in seconds since the Unix epoch. Read
+    /// the first time a warning is raised, as most runs don't raise any.
+    shown: Option<BTreeMap<String, u64>>,
+}
+
+impl WarningLimiter {
+    /// Returns `None` if rate limiting has been disabled.
+    pub(super) fn new(current_dir: &Path, process: &Process) -> Option<Self> {
+        let minutes = match process.var("RUSTUP_PROXY_WARNING_INTERVAL") {
+            Ok(minutes) => minutes.parse().unwrap_or(DEFAULT_INTERVAL_MINUTES),
+            Err(_) => DEFAULT_INTERVAL_MINUTES,
+        };
+        if minutes == 0 {
+            return None;
+        }
+
+        let path = process.rustup_home().ok()?.join("proxy-warnings.json");
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Some(Self {
+            path,
+            dir: current_dir.to_owned(),
+            interval: minutes * 60,
+            now,
+            shown: None,
+        })
+    }
+
+    /// Returns whether `message` should be shown, and remembers it if so.
+    pub(super) fn allow(&mut self, message: &str) -> bool {
+        let key = self.key(message);
+        let (now, interval) = (self.now, self.interval);
+        let shown = self.shown.get_or_insert_with(|| {
+            fs::read_to_string(&self.path)
+                .ok()
+                .and_then(|data| serde_json::from_str(&data).ok())
+                .unwrap_or_default()
+        });
+        if let Some(&last) = shown.get(&key) {
+            if now.saturating_sub(last) < interval {
+                debug!("suppressed repeated warning: {message}");
+                return false;
+            }
+        }
+
+        shown.insert(key, now);
+        shown.retain(|_, last| now.saturating_sub(*last) < interval);
+        if let Err(e) = self.save() {
+            debug!("unable to save shown warnings: {e}");
+        }
+        true
+    }
+
+    fn key(&self, message: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.dir.as_os_str().as_encoded_bytes());
+        hasher.update(b"\0");
+        hasher.update(message.as_bytes());
+        format!("{:x}", hasher.finalize())[..32].to_owned()
+    }
+
+    /// Many proxies may run at once, so the file is replaced atomically. Any
+    /// update lost to a race only means a warning is shown once more.
+    fn save(&self) -> std::io::Result<()> {
+        let tmp = self
+            .path
+            .with_extension(format!("json.{}.tmp", std::process::id()));
+        fs::write(&tmp, serde_json::to_string(&self.shown.as_ref())?)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn once_per_interval_and_directory() {
+        let home = tempfile::tempdir().unwrap();
+        let limiter = |dir: &str, now: u64| WarningLimiter {
+            path: home.path().join("proxy-warnings.json"),
+            dir: PathBuf::from(dir),
+            interval: 600,
+            now,
+            shown: None,
+        };
+
+        assert!(limiter("/a", 1000).allow("oops"));
+        assert!(!limiter("/a", 1300).allow("oops"));
+        assert!(limiter("/a", 1300).allow("something else"));
+        assert!(limiter("/b", 1300).allow("oops"));
+        assert!(limiter("/a", 1600).allow("oops"));
+    }
+}
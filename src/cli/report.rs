@@ -0,0 +1,124 @@
+//! The summary that `--report-file` writes after `rustup install`, `rustup
+//! update` and `rustup toolchain install`, for CI systems to archive and
+//! compare from one run to the next.
+//!
+//! What was done to each toolchain is told by the hash of the manifest it was
+//! installed from, before and after the command, so that toolchains installed
+//! along the way, such as by a toolchain file, are reported like the others.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::{
+    config::Cfg, dist::download::DownloadStats, toolchain::ToolchainName, utils::ExitCode,
+};
+
+/// What is known before the command runs.
+pub(super) struct Report {
+    started: Instant,
+    hashes: BTreeMap<String, String>,
+    downloads: DownloadStats,
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    version: u32,
+    rustup_version: &'static str,
+    command: &'a str,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// How long the command took, in seconds.
+    duration: f64,
+    toolchains: Vec<ToolchainEntry>,
+    downloads: DownloadStats,
+}
+
+#[derive(Serialize)]
+struct ToolchainEntry {
+    name: String,
+    action: Action,
+    /// The start of the hash of the manifest it is installed from.
+    hash: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Action {
+    Installed,
+    Updated,
+    Unchanged,
+    Removed,
+}
+
+impl Report {
+    pub(super) fn start(cfg: &Cfg<'_>) -> Result<Self> {
+        Ok(Self {
+            started: Instant::now(),
+            hashes: hashes(cfg)?,
+            downloads: DownloadStats::load(&cfg.download_dir),
+        })
+    }
+
+    /// Writes the report on `command`, which ended with `result`, to `path`.
+    pub(super) fn write(
+        self,
+        cfg: &Cfg<'_>,
+        path: &Path,
+        command: &str,
+        result: &Result<ExitCode>,
+    ) -> Result<()> {
+        let mut before = self.hashes;
+        let mut toolchains = Vec::new();
+        for (name, hash) in hashes(cfg)? {
+            let action = match before.remove(&name) {
+                None => Action::Installed,
+                Some(old) if old != hash => Action::Updated,
+                Some(_) => Action::Unchanged,
+            };
+            toolchains.push(ToolchainEntry {
+                name,
+                action,
+                hash: Some(hash),
+            });
+        }
+        toolchains.extend(before.into_keys().map(|name| ToolchainEntry {
+            name,
+            action: Action::Removed,
+            hash: None,
+        }));
+
+        let downloads = DownloadStats::load(&cfg.download_dir);
+        let document = Document {
+            version: 1,
+            rustup_version: env!("CARGO_PKG_VERSION"),
+            command,
+            success: matches!(result, Ok(ExitCode(0))),
+            error: result.as_ref().err().map(|e| format!("{e:#}")),
+            duration: self.started.elapsed().as_secs_f64(),
+            toolchains,
+            downloads: downloads.since(&self.downloads),
+        };
+        let json = serde_json::to_string_pretty(&document)? + "\n";
+        fs::write(path, json).with_context(|| format!("could not write '{}'", path.display()))
+    }
+}
+
+/// The manifest hashes of the toolchains installed from a channel, by name.
+fn hashes(cfg: &Cfg<'_>) -> Result<BTreeMap<String, String>> {
+    let mut hashes = BTreeMap::new();
+    for name in cfg.list_toolchains()? {
+        let ToolchainName::Official(desc) = name else {
+            continue;
+        };
+        if let Some(installed) = cfg.manifest_cache.installed(&desc)? {
+            hashes.insert(desc.to_string(), installed.hash);
+        }
+    }
+    Ok(hashes)
+}
@@ -1,44 +1,69 @@
 use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::env::consts::EXE_SUFFIX;
+use std::ffi::OsString;
 use std::fmt;
+use std::fs;
 use std::io::Write;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
-use std::process::ExitStatus;
+use std::process::{Command, ExitStatus};
 use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::{anyhow, Context, Error, Result};
-use clap::{builder::PossibleValue, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use anyhow::{anyhow, bail, Context, Error, Result};
+use chrono::NaiveDate;
+use clap::{builder::PossibleValue, ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_complete::Shell;
 use itertools::Itertools;
 use tracing::{info, trace, warn};
 use tracing_subscriber::{reload::Handle, EnvFilter, Registry};
+use url::Url;
 
+#[cfg(unix)]
+use crate::cli::agent;
 use crate::{
     cli::{
         common::{self, update_console_filter, PackageUpdate},
+        crash_report::CrashReportMode,
+        describe_toolchain, diagnose_tls, doctor,
         errors::CLIError,
         help::*,
-        self_update::{self, check_rustup_update, SelfUpdateMode},
-        topical_doc,
+        json::{self, FormatVersion, OutputFormat},
+        proxy_bench,
+        proxy_mode::{self, AutoInstallMode},
+        report::Report,
+        schedule,
+        self_update::{self, check_rustup_update, ProxyLinks, SelfUpdateMode},
+        serve_cache, stats, status, target_doctor, topical_doc, usage,
     },
     command,
-    config::{ActiveReason, Cfg},
+    config::{self, no_toolchain_error, ActiveReason, Cfg},
+    diskio::IoProfile,
     dist::{
-        manifest::{Component, ComponentStatus},
+        download::{unpack_bundle, DownloadCfg},
+        export::export_bundle,
+        manifest::{Component, ComponentStatus, Manifest},
+        manifestation::ChangePlan,
+        timings::Timings,
+        trust::{self, TrustedKey},
         PartialToolchainDesc, Profile, TargetTriple,
     },
     errors::RustupError,
     install::{InstallMethod, UpdateStatus},
+    is_proxyable_tools,
+    policy::{Policy, CONFIG_BUNDLE},
     process::{
-        terminalsource::{self, ColorableTerminal},
+        terminalsource::{self, ColorableTerminal, OutputStyle, Palette},
         Process,
     },
+    settings::{TlsHost, TlsRootSource},
     toolchain::{
         CustomToolchainName, DistributableToolchain, LocalToolchainName,
         MaybeResolvableToolchainName, ResolvableLocalToolchainName, ResolvableToolchainName,
         Toolchain, ToolchainName,
     },
-    utils::{self, ExitCode},
+    utils::{self, units::format_bytes, ExitCode},
 };
 
 const TOOLCHAIN_OVERRIDE_ERROR: &str =
@@ -78,6 +103,22 @@ struct Rustup {
     #[arg(short, long, conflicts_with = "verbose")]
     quiet: bool,
 
+    /// Answer yes to confirmation prompts
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Write a report of how long each component took to download, verify and unpack
+    #[arg(long)]
+    timings: bool,
+
+    /// How many threads to unpack and remove files with, in place of RUSTUP_IO_THREADS
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Run as root even where files it creates would belong to root, such as in a container build
+    #[arg(long)]
+    allow_root: bool,
+
     /// Release channel (e.g. +stable) or custom toolchain to set override
     #[arg(
         name = "+toolchain",
@@ -127,6 +168,13 @@ enum RustupSubcmd {
         #[arg(short, long)]
         verbose: bool,
 
+        /// Print as human-readable text, or as JSON for tools
+        #[arg(long, value_enum, default_value_t, value_name = "FORMAT")]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        format_version: FormatVersion,
+
         #[command(subcommand)]
         subcmd: Option<ShowSubcmd>,
     },
@@ -152,10 +200,42 @@ enum RustupSubcmd {
         /// Install toolchains that require an emulator. See https://github.com/rust-lang/rustup/wiki/Non-host-toolchains
         #[arg(long)]
         force_non_host: bool,
+
+        /// Don't check the signatures of channel manifests
+        #[arg(long)]
+        no_verify: bool,
+
+        /// Exit with status 2 if anything was updated, or 3 if only rustup has an update
+        #[arg(long)]
+        exit_code: bool,
+
+        /// Write a JSON summary of what was done to this file, for CI systems
+        #[arg(long, value_name = "FILE")]
+        report_file: Option<PathBuf>,
+
+        /// Show what would be downloaded and removed without changing anything
+        #[arg(long, conflicts_with = "report_file")]
+        dry_run: bool,
     },
 
     /// Check for updates to Rust toolchains and rustup
-    Check,
+    #[command(after_help = CHECK_HELP)]
+    Check {
+        /// Exit with status 2 if toolchain updates are available, or 3 if only rustup has one
+        #[arg(long)]
+        exit_code: bool,
+
+        /// Print nothing, and exit with the status of `--exit-code`
+        #[arg(short, long, conflicts_with = "output")]
+        quiet: bool,
+
+        /// Print as human-readable text, or as JSON for tools
+        #[arg(long, value_enum, default_value_t, value_name = "FORMAT")]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        format_version: FormatVersion,
+    },
 
     /// Set the default toolchain
     #[command(after_help = DEFAULT_HELP)]
@@ -201,17 +281,39 @@ enum RustupSubcmd {
         #[arg(required = true, num_args = 1..)]
         command: Vec<String>,
 
-        /// Install the requested toolchain if needed
+        /// Install the requested toolchain with the minimal profile if needed
         #[arg(long)]
         install: bool,
+
+        #[command(flatten)]
+        overlays: Box<RunOverlays>,
+    },
+
+    /// Run a tool the way its proxy would, for where proxies can't be linked
+    #[command(after_help = PROXY_HELP)]
+    Proxy {
+        #[command(subcommand)]
+        subcmd: ProxySubcmd,
     },
 
     /// Display which binary will be run for a given command
+    #[command(after_help = WHICH_HELP)]
     Which {
         command: String,
 
         #[arg(long, help = RESOLVABLE_TOOLCHAIN_ARG_HELP)]
         toolchain: Option<ResolvableToolchainName>,
+
+        /// Display the binary of every installed toolchain that has one
+        #[arg(long, conflicts_with = "toolchain")]
+        all: bool,
+
+        /// Print as human-readable text, or as JSON for tools
+        #[arg(long, value_enum, default_value_t, value_name = "FORMAT")]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        format_version: FormatVersion,
     },
 
     /// Open the documentation for the current toolchain
@@ -227,6 +329,10 @@ enum RustupSubcmd {
         #[arg(long, help = OFFICIAL_TOOLCHAIN_ARG_HELP)]
         toolchain: Option<PartialToolchainDesc>,
 
+        /// Open the documentation for this target instead of the host's
+        #[arg(long)]
+        target: Option<String>,
+
         #[arg(help = TOPIC_ARG_HELP)]
         topic: Option<String>,
 
@@ -243,6 +349,14 @@ enum RustupSubcmd {
         toolchain: Option<PartialToolchainDesc>,
     },
 
+    /// Check the setup around rustup for common problems
+    #[command(after_help = DOCTOR_HELP)]
+    Doctor {
+        /// Give the files in RUSTUP_HOME back to their user
+        #[arg(long)]
+        fix_permissions: bool,
+    },
+
     /// Modify the rustup installation
     Self_ {
         #[command(subcommand)]
@@ -255,6 +369,77 @@ enum RustupSubcmd {
         subcmd: SetSubcmd,
     },
 
+    /// Schedule periodic background updates of the installed toolchains
+    Schedule {
+        #[command(subcommand)]
+        subcmd: ScheduleSubcmd,
+    },
+
+    /// Answer proxies and IDEs from a long-running process
+    #[command(after_help = AGENT_HELP)]
+    Agent,
+
+    /// Manage what rustup caches
+    Cache {
+        #[command(subcommand)]
+        subcmd: CacheSubcmd,
+    },
+
+    /// Serve the download cache as a mirror of the dist server
+    #[command(after_help = SERVE_CACHE_HELP)]
+    ServeCache {
+        /// The address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+    },
+
+    /// Describe a toolchain for use by other build systems
+    #[command(after_help = DESCRIBE_TOOLCHAIN_HELP)]
+    DescribeToolchain {
+        #[arg(help = RESOLVABLE_LOCAL_TOOLCHAIN_ARG_HELP)]
+        toolchain: Option<ResolvableLocalToolchainName>,
+
+        /// Print a BUILD file for Bazel's rules_rust instead of JSON
+        #[arg(long)]
+        bazel: bool,
+    },
+
+    /// Switch whether rustup or a system-installed Rust comes first in PATH
+    #[command(after_help = TOGGLE_SYSTEM_HELP)]
+    ToggleSystem,
+
+    /// Manage the keys that manifests from dist servers must be signed with
+    Key {
+        #[command(subcommand)]
+        subcmd: KeySubcmd,
+    },
+
+    /// Manage the tokens of dist servers that require authentication
+    Auth {
+        #[command(subcommand)]
+        subcmd: AuthSubcmd,
+    },
+
+    /// Apply configuration shared by an organization
+    Config {
+        #[command(subcommand)]
+        subcmd: ConfigSubcmd,
+    },
+
+    /// Work with releases on the dist server without installing them
+    Dist {
+        #[command(subcommand)]
+        subcmd: DistSubcmd,
+    },
+
+    /// Summarize the installed toolchains and how they are used
+    #[command(after_help = STATS_HELP)]
+    Stats,
+
+    /// Check whether the dist server can be reached and the Rust infrastructure is up
+    #[command(after_help = STATUS_HELP)]
+    Status,
+
     /// Generate tab-completion scripts for your shell
     #[command(after_help = COMPLETIONS_HELP, arg_required_else_help = true)]
     Completions {
@@ -265,6 +450,11 @@ enum RustupSubcmd {
     },
 }
 
+fn pin_date_parser(s: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .with_context(|| format!("invalid date '{s}', expected YYYY-MM-DD"))
+}
+
 fn update_toolchain_value_parser(s: &str) -> Result<PartialToolchainDesc> {
     PartialToolchainDesc::from_str(s).inspect_err(|_| {
         if s == "self" {
@@ -273,6 +463,152 @@ fn update_toolchain_value_parser(s: &str) -> Result<PartialToolchainDesc> {
     })
 }
 
+#[derive(Debug, Subcommand)]
+#[command(
+    after_help = SCHEDULE_HELP,
+    arg_required_else_help = true,
+    subcommand_required = true
+)]
+enum ScheduleSubcmd {
+    /// Run `rustup update` periodically with the system's task scheduler
+    EnableAutoUpdate {
+        #[arg(value_enum, default_value_t)]
+        interval: schedule::Interval,
+    },
+
+    /// Show whether automatic updates are scheduled
+    Status,
+
+    /// Remove the scheduled automatic updates
+    Disable,
+}
+
+#[derive(Debug, Subcommand)]
+#[command(arg_required_else_help = true, subcommand_required = true)]
+enum CacheSubcmd {
+    /// Make proxies and `rustup agent` resolve the toolchain of every directory again
+    #[command(after_help = CACHE_INVALIDATE_RESOLUTION_HELP)]
+    InvalidateResolution,
+}
+
+#[derive(Debug, Subcommand)]
+#[command(
+    after_help = KEY_HELP,
+    arg_required_else_help = true,
+    subcommand_required = true
+)]
+enum KeySubcmd {
+    /// Trust an OpenPGP public key or a Sigstore identity for a dist server
+    Add {
+        /// A file with an ASCII-armored OpenPGP public key
+        #[arg(
+            required_unless_present = "sigstore_identity",
+            conflicts_with = "sigstore_identity"
+        )]
+        file: Option<PathBuf>,
+
+        /// The identity that keyless Sigstore signatures are made by
+        #[arg(long, value_name = "IDENTITY", requires = "sigstore_issuer")]
+        sigstore_identity: Option<String>,
+
+        /// The OIDC issuer that vouches for the Sigstore identity
+        #[arg(long, value_name = "URL", requires = "sigstore_identity")]
+        sigstore_issuer: Option<String>,
+
+        /// The dist server to trust the key for [default: the one in use]
+        #[arg(long, value_name = "URL")]
+        dist_server: Option<String>,
+    },
+
+    /// List the keys trusted for each dist server
+    List,
+
+    /// Stop trusting a key
+    Remove {
+        /// The fingerprint of an OpenPGP key, or a Sigstore identity
+        key: String,
+
+        /// The dist server to stop trusting the key for [default: the one in use]
+        #[arg(long, value_name = "URL")]
+        dist_server: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+#[command(
+    after_help = AUTH_HELP,
+    arg_required_else_help = true,
+    subcommand_required = true
+)]
+enum AuthSubcmd {
+    /// Store the token to authenticate to a dist server with
+    Login {
+        /// The dist server the token is for [default: the one in use]
+        #[arg(long, value_name = "URL")]
+        dist_server: Option<String>,
+    },
+
+    /// Remove the token of a dist server
+    Logout {
+        /// The dist server to remove the token of [default: the one in use]
+        #[arg(long, value_name = "URL")]
+        dist_server: Option<String>,
+    },
+
+    /// Show whether there is a token for a dist server
+    Status {
+        /// The dist server to look for a token for [default: the one in use]
+        #[arg(long, value_name = "URL")]
+        dist_server: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+#[command(
+    after_help = CONFIG_HELP,
+    arg_required_else_help = true,
+    subcommand_required = true
+)]
+enum ConfigSubcmd {
+    /// Apply an organization's configuration bundle as the policy
+    #[command(after_help = CONFIG_SYNC_HELP)]
+    Sync {
+        /// The bundle, or a git repository with it [default: the last one synced]
+        #[arg(long, value_name = "URL")]
+        from: Option<String>,
+
+        /// Only report how the policy differs from the bundle
+        #[arg(long)]
+        check: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+#[command(arg_required_else_help = true, subcommand_required = true)]
+enum DistSubcmd {
+    /// Download a toolchain into a bundle for installing it offline
+    #[command(after_help = DIST_EXPORT_HELP)]
+    Export {
+        #[arg(help = OFFICIAL_TOOLCHAIN_ARG_HELP)]
+        toolchain: PartialToolchainDesc,
+
+        #[arg(long, value_enum)]
+        profile: Option<Profile>,
+
+        /// Comma-separated list of components to add to the bundle
+        #[arg(short, long, value_delimiter = ',')]
+        component: Vec<String>,
+
+        /// Comma-separated list of targets to add to the bundle
+        #[arg(short, long, value_delimiter = ',')]
+        target: Vec<String>,
+
+        /// Where to write the bundle [default: <toolchain>-bundle.tar]
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
 #[derive(Debug, Subcommand)]
 enum ShowSubcmd {
     /// Show the active toolchain
@@ -306,6 +642,19 @@ enum ToolchainSubcmd {
         /// Force the output to be a single column
         #[arg(short, long, conflicts_with = "verbose")]
         quiet: bool,
+
+        /// Print as human-readable text, or as JSON for tools
+        #[arg(
+            long,
+            value_enum,
+            default_value_t,
+            value_name = "FORMAT",
+            conflicts_with = "quiet"
+        )]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        format_version: FormatVersion,
     },
 
     /// Install or update the given toolchains, or by default the active toolchain
@@ -331,6 +680,83 @@ enum ToolchainSubcmd {
         /// Path to the directory
         path: PathBuf,
     },
+
+    /// Rename a custom toolchain
+    #[command(after_help = TOOLCHAIN_RENAME_HELP)]
+    Rename {
+        /// Custom toolchain name
+        toolchain: CustomToolchainName,
+
+        /// New custom toolchain name
+        new_name: CustomToolchainName,
+    },
+
+    /// Exclude a toolchain from `rustup update`
+    #[command(after_help = TOOLCHAIN_PIN_HELP)]
+    Pin {
+        #[arg(help = RESOLVABLE_TOOLCHAIN_ARG_HELP)]
+        toolchain: ResolvableToolchainName,
+
+        /// Warn once this date (YYYY-MM-DD) has passed
+        #[arg(long, value_name = "DATE", value_parser = pin_date_parser)]
+        until: Option<NaiveDate>,
+    },
+
+    /// Allow `rustup update` to update a pinned toolchain again
+    Unpin {
+        #[arg(help = RESOLVABLE_TOOLCHAIN_ARG_HELP)]
+        toolchain: ResolvableToolchainName,
+    },
+
+    /// Uninstall the toolchains that haven't been used for a while
+    #[command(after_help = TOOLCHAIN_GC_HELP)]
+    Gc {
+        /// Remove toolchains unused for this many days, instead of the
+        /// retention period from the settings
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+
+        /// Only show which toolchains would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Print a digest of the files installed in a toolchain
+    #[command(after_help = TOOLCHAIN_HASH_HELP)]
+    Hash {
+        #[arg(help = RESOLVABLE_LOCAL_TOOLCHAIN_ARG_HELP)]
+        toolchain: ResolvableLocalToolchainName,
+    },
+
+    /// Package a toolchain as an archive that can be used without rustup
+    #[command(after_help = TOOLCHAIN_PACKAGE_HELP)]
+    Package {
+        #[arg(help = RESOLVABLE_LOCAL_TOOLCHAIN_ARG_HELP)]
+        toolchain: ResolvableLocalToolchainName,
+
+        /// Where to write the archive [default: <toolchain>.tar.zst]
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Report the dynamic linker and system libraries a toolchain needs
+    #[command(after_help = TOOLCHAIN_LINKAGE_HELP)]
+    Linkage {
+        #[arg(help = RESOLVABLE_LOCAL_TOOLCHAIN_ARG_HELP)]
+        toolchain: ResolvableLocalToolchainName,
+    },
+}
+
+/// Tools that `rustup run` takes from other toolchains.
+#[derive(Debug, Args)]
+struct RunOverlays {
+    /// Run rustc from this toolchain instead
+    #[arg(long, value_name = "TOOLCHAIN")]
+    rustc_from: Option<ResolvableLocalToolchainName>,
+
+    /// Run cargo from this toolchain instead
+    #[arg(long, value_name = "TOOLCHAIN")]
+    cargo_from: Option<ResolvableLocalToolchainName>,
 }
 
 #[derive(Debug, Default, Args)]
@@ -367,6 +793,31 @@ struct UpdateOpts {
     /// Install toolchains that require an emulator. See https://github.com/rust-lang/rustup/wiki/Non-host-toolchains
     #[arg(long)]
     force_non_host: bool,
+
+    /// Also install rustc-dev, llvm-tools and rust-src, for building tools that link against rustc
+    #[arg(long, requires = "toolchain")]
+    for_rustc_dev: bool,
+
+    /// Install from a directory or archive holding the manifest and component archives, without
+    /// using the network
+    #[arg(long, value_name = "PATH", requires = "toolchain")]
+    from_bundle: Option<PathBuf>,
+
+    /// Don't check the signatures of channel manifests
+    #[arg(long)]
+    no_verify: bool,
+
+    /// Write a JSON summary of what was done to this file, for CI systems
+    #[arg(long, value_name = "FILE")]
+    report_file: Option<PathBuf>,
+
+    /// Show what would be downloaded and removed without changing anything
+    #[arg(long, conflicts_with = "report_file")]
+    dry_run: bool,
+
+    /// Only set by `rustup update --exit-code`
+    #[arg(skip)]
+    exit_code: bool,
 }
 
 #[derive(Debug, Default, Args)]
@@ -377,6 +828,10 @@ struct UninstallOpts {
         num_args = 1..,
     )]
     toolchain: Vec<ResolvableToolchainName>,
+
+    /// Remove the toolchain even if processes are running from it
+    #[arg(long)]
+    force: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -394,9 +849,34 @@ enum TargetSubcmd {
         #[arg(long)]
         installed: bool,
 
+        /// List only targets that are available but not installed
+        #[arg(long, conflicts_with = "installed")]
+        available: bool,
+
+        /// List only targets whose name contains the given text
+        #[arg(long, value_name = "TEXT")]
+        filter: Option<String>,
+
         /// Force the output to be a single column
         #[arg(long, short)]
         quiet: bool,
+
+        /// Print each target and its status on a line, in a stable format
+        #[arg(long, conflicts_with = "quiet")]
+        porcelain: bool,
+
+        /// Print as human-readable text, or as JSON for tools
+        #[arg(
+            long,
+            value_enum,
+            default_value_t,
+            value_name = "FORMAT",
+            conflicts_with_all = ["quiet", "porcelain"],
+        )]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        format_version: FormatVersion,
     },
 
     /// Add a target to a Rust toolchain
@@ -408,6 +888,10 @@ enum TargetSubcmd {
 
         #[arg(long, help = OFFICIAL_TOOLCHAIN_ARG_HELP)]
         toolchain: Option<PartialToolchainDesc>,
+
+        /// Show what would be downloaded and removed without changing anything
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// Remove a target from a Rust toolchain
@@ -419,6 +903,24 @@ enum TargetSubcmd {
 
         #[arg(long, help = OFFICIAL_TOOLCHAIN_ARG_HELP)]
         toolchain: Option<PartialToolchainDesc>,
+
+        /// Show what would be downloaded and removed without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Check what else cross-compiling for a target needs
+    #[command(after_help = TARGET_DOCTOR_HELP)]
+    Doctor {
+        /// The target to check, such as aarch64-unknown-linux-gnu
+        target: String,
+
+        #[arg(long, help = OFFICIAL_TOOLCHAIN_ARG_HELP)]
+        toolchain: Option<PartialToolchainDesc>,
+
+        /// Set the target's linker in .cargo/config.toml of the current directory
+        #[arg(long)]
+        write_config: bool,
     },
 }
 
@@ -435,20 +937,68 @@ enum ComponentSubcmd {
         installed: bool,
 
         /// Force the output to be a single column
-        #[arg(long, short)]
+        #[arg(long, short, conflicts_with = "verbose")]
         quiet: bool,
+
+        /// Show the download and installed size of each component
+        #[arg(long, short)]
+        verbose: bool,
+
+        /// Print each component and its status on a line, in a stable format
+        #[arg(long, conflicts_with_all = ["quiet", "verbose"])]
+        porcelain: bool,
+
+        /// Print as human-readable text, or as JSON for tools
+        #[arg(
+            long,
+            value_enum,
+            default_value_t,
+            value_name = "FORMAT",
+            conflicts_with_all = ["quiet", "porcelain"],
+        )]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        format_version: FormatVersion,
     },
 
     /// Add a component to a Rust toolchain
+    #[command(after_help = COMPONENT_ADD_HELP)]
     Add {
-        #[arg(required = true, num_args = 1..)]
+        #[arg(
+            required_unless_present_any = ["url", "file"],
+            conflicts_with_all = ["url", "file"],
+            num_args = 1..,
+        )]
         component: Vec<String>,
 
         #[arg(long, help = OFFICIAL_TOOLCHAIN_ARG_HELP)]
         toolchain: Option<PartialToolchainDesc>,
 
-        #[arg(long)]
+        /// Target triple, `installed` for every installed target, or `all` for
+        /// every target the component is available for
+        #[arg(long, conflicts_with_all = ["url", "file"])]
         target: Option<String>,
+
+        /// Install the component that this archive from the channel is for
+        #[arg(long, conflicts_with = "url")]
+        file: Option<PathBuf>,
+
+        /// Install a component in rust-installer format from this URL
+        #[arg(long, requires_all = ["sha256", "name"])]
+        url: Option<String>,
+
+        /// The SHA-256 hash of the archive at the URL
+        #[arg(long, requires = "url")]
+        sha256: Option<String>,
+
+        /// The name of the component in the archive at the URL
+        #[arg(long, requires = "url")]
+        name: Option<String>,
+
+        /// Show what would be downloaded and removed without changing anything
+        #[arg(long, conflicts_with_all = ["url", "file"])]
+        dry_run: bool,
     },
 
     /// Remove a component from a Rust toolchain
@@ -459,8 +1009,30 @@ enum ComponentSubcmd {
         #[arg(long, help = OFFICIAL_TOOLCHAIN_ARG_HELP)]
         toolchain: Option<PartialToolchainDesc>,
 
+        /// Target triple, `installed` for every installed target, or `all` for
+        /// every target the component is installed for
         #[arg(long)]
         target: Option<String>,
+
+        /// Show what would be downloaded and removed without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+#[command(arg_required_else_help = true, subcommand_required = true)]
+enum ProxySubcmd {
+    /// Run a tool from the active toolchain, such as `cargo` or `rustc`
+    #[command(trailing_var_arg = true)]
+    Run {
+        /// The tool to run
+        tool: String,
+
+        /// Arguments passed to the tool, the first of which may be a
+        /// `+toolchain`
+        #[arg(allow_hyphen_values = true)]
+        args: Vec<OsString>,
     },
 }
 
@@ -472,17 +1044,32 @@ enum ComponentSubcmd {
 )]
 enum OverrideSubcmd {
     /// List directory toolchain overrides
-    List,
+    List {
+        /// Remove the overrides of directories that no longer exist first
+        #[arg(long)]
+        clean: bool,
 
-    /// Set the override toolchain for a directory
-    #[command(alias = "add")]
+        /// Print as human-readable text, or as JSON for tools
+        #[arg(long, value_enum, default_value_t, value_name = "FORMAT")]
+        output: OutputFormat,
+
+        #[command(flatten)]
+        format_version: FormatVersion,
+    },
+
+    /// Set the override toolchain for one or more directories
+    #[command(alias = "add", after_help = OVERRIDE_SET_HELP)]
     Set {
         #[arg(help = RESOLVABLE_TOOLCHAIN_ARG_HELP)]
         toolchain: ResolvableToolchainName,
 
-        /// Path to the directory
+        /// Path to the directory, or a glob pattern matching directories
         #[arg(long)]
-        path: Option<PathBuf>,
+        path: Vec<PathBuf>,
+
+        /// Read directories from a file, one path or glob pattern per line
+        #[arg(long, value_name = "FILE")]
+        from_file: Option<PathBuf>,
     },
 
     /// Remove the override toolchain for a directory
@@ -516,6 +1103,41 @@ enum SelfSubcmd {
 
     /// Upgrade the internal data format
     UpgradeData,
+
+    /// Measure the overhead of running a tool through its rustup proxy
+    #[command(after_help = SELF_BENCH_PROXY_HELP)]
+    BenchProxy {
+        /// The proxied tool to invoke with `--version`
+        #[arg(long, default_value = "rustc")]
+        tool: String,
+
+        /// Number of invocations to time, both through the proxy and directly
+        #[arg(long, default_value_t = 20)]
+        iterations: usize,
+
+        /// Write the measured timings to this file
+        #[arg(long)]
+        save: Option<PathBuf>,
+
+        /// Compare against timings previously written with `--save`
+        #[arg(long)]
+        baseline: Option<PathBuf>,
+
+        /// Allowed increase of the proxy overhead over the baseline, in percent
+        #[arg(long, default_value_t = 20.0, requires = "baseline")]
+        threshold: f64,
+    },
+
+    /// Try TLS handshakes with the dist server and show the certificates it presents
+    #[command(after_help = SELF_DIAGNOSE_TLS_HELP)]
+    DiagnoseTls {
+        /// The server to connect to, instead of the dist server
+        server: Option<Url>,
+    },
+
+    /// Check that the installed rustup can start on this system
+    #[command(after_help = SELF_DOCTOR_HELP)]
+    Doctor,
 }
 
 #[derive(Debug, Subcommand)]
@@ -535,6 +1157,131 @@ enum SetSubcmd {
         #[arg(value_enum, default_value_t)]
         auto_self_update_mode: SelfUpdateMode,
     },
+
+    /// Write a local crash report when rustup panics
+    #[command(after_help = SET_CRASH_REPORTS_HELP)]
+    CrashReports {
+        #[arg(value_enum)]
+        crash_report_mode: CrashReportMode,
+    },
+
+    /// Keep output plain, without colors or progress bars
+    #[command(after_help = SET_OUTPUT_STYLE_HELP)]
+    OutputStyle {
+        #[arg(value_enum)]
+        output_style: OutputStyle,
+    },
+
+    /// The colors of rustup's output
+    #[command(after_help = SET_PALETTE_HELP)]
+    Palette {
+        #[arg(value_enum)]
+        palette: Palette,
+    },
+
+    /// Whether proxies install a missing toolchain instead of failing
+    #[command(after_help = SET_AUTO_INSTALL_HELP)]
+    AutoInstall {
+        #[arg(value_enum)]
+        auto_install_mode: AutoInstallMode,
+    },
+
+    /// How proxies such as `cargo` are linked to rustup
+    #[command(after_help = SET_PROXY_LINKS_HELP)]
+    ProxyLinks {
+        #[arg(value_enum)]
+        proxy_links: ProxyLinks,
+    },
+
+    /// Where proxies such as `cargo` are installed
+    #[command(after_help = SET_PROXY_DIR_HELP)]
+    ProxyDir {
+        /// The directory, or default for `CARGO_HOME/bin`
+        dir: PathBuf,
+    },
+
+    /// Dist servers to try in turn for manifests and components
+    #[command(after_help = SET_DIST_SERVERS_HELP)]
+    DistServers {
+        /// The URLs of the servers, first to last, or none to use the default
+        servers: Vec<String>,
+    },
+
+    /// Caches on the local network to fetch components from
+    #[command(after_help = SET_CACHE_PEERS_HELP)]
+    CachePeers {
+        /// The URLs of the caches, or none to stop using caches
+        peers: Vec<String>,
+    },
+
+    /// Ask before updates that download more than the given size
+    #[command(after_help = SET_CONFIRM_DOWNLOADS_OVER_HELP)]
+    ConfirmDownloadsOver {
+        /// A size such as 500MiB, or none to never ask
+        size: String,
+    },
+
+    /// A program to run on each ELF file an update installs
+    #[command(after_help = SET_POST_INSTALL_HOOK_HELP)]
+    PostInstallHook {
+        /// The path of the program, or none to stop running it
+        hook: String,
+    },
+
+    /// Whether to share files identical between toolchains
+    #[command(after_help = SET_DEDUP_HELP)]
+    Dedup {
+        #[arg(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+
+    /// How long `rustup toolchain gc` keeps unused toolchains
+    #[command(after_help = SET_TOOLCHAIN_RETENTION_HELP)]
+    ToolchainRetention {
+        /// A number of days, or none to keep toolchains
+        days: String,
+    },
+
+    /// How much memory and how many threads unpacking components may use
+    #[command(after_help = SET_IO_PROFILE_HELP)]
+    IoProfile {
+        #[arg(value_enum, default_value_t)]
+        io_profile: IoProfile,
+    },
+
+    /// How many components to download at once
+    #[command(after_help = SET_MAX_DOWNLOADS_HELP)]
+    MaxDownloads {
+        #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+        max: u16,
+    },
+
+    /// Where the root certificates trusted by downloads come from
+    #[command(after_help = SET_TLS_ROOT_SOURCE_HELP)]
+    TlsRootSource {
+        /// platform, bundled, or the path of a PEM file
+        source: TlsRootSource,
+    },
+
+    /// TLS settings for a single server, such as a mirror behind a proxy
+    #[command(after_help = SET_TLS_HOST_HELP)]
+    #[command(group(ArgGroup::new("setting").required(true)))]
+    TlsHost {
+        /// The host name of the server
+        host: String,
+
+        /// Also trust the roots in this PEM file for the server
+        #[arg(long, group = "setting")]
+        roots: Option<PathBuf>,
+
+        /// Don't verify the certificate of the server at all
+        #[arg(long, group = "setting")]
+        insecure: bool,
+
+        /// Remove the settings of the server
+        #[arg(long, group = "setting")]
+        remove: bool,
+    },
 }
 
 #[tracing::instrument(level = "trace", fields(args = format!("{:?}", process.args_os().collect::<Vec<_>>())))]
@@ -580,9 +1327,30 @@ pub async fn main(
         }
     };
 
+    // Proxies load the settings their own way, and are as quiet as those
+    // linked to rustup.
+    if let Some(RustupSubcmd::Proxy {
+        subcmd: ProxySubcmd::Run { tool, mut args },
+    }) = matches.subcmd
+    {
+        is_proxyable_tools(&tool)?;
+        if let Some(t) = &matches.plus_toolchain {
+            args.insert(0, format!("+{t}").into());
+        }
+        return proxy_mode::main(&tool, args, current_dir, process, console_filter)
+            .await
+            .map(ExitCode::from);
+    }
+
     update_console_filter(process, &console_filter, matches.quiet, matches.verbose);
 
     let cfg = &mut common::set_globals(current_dir, matches.quiet, process)?;
+    if matches.yes {
+        cfg.confirm_downloads_over = None;
+    }
+    if let Some(jobs) = matches.jobs {
+        cfg.io_threads = Some(jobs);
+    }
 
     if let Some(t) = &matches.plus_toolchain {
         cfg.set_toolchain_override(t);
@@ -594,12 +1362,85 @@ pub async fn main(
         return Ok(utils::ExitCode(1));
     };
 
+    if subcmd.changes_home() {
+        utils::permissions::check_home(&cfg.rustup_dir, process, matches.allow_root)?;
+    }
+
+    if !matches.timings {
+        return run_subcmd(cfg, subcmd).await;
+    }
+    Timings::start();
+    let result = run_subcmd(cfg, subcmd).await;
+    // A report on a failed install is as useful as one on a slow install.
+    match Timings::finish(&cfg.rustup_dir.join("timings")) {
+        Ok(Some(report)) => info!("timing report saved to '{}'", report.display()),
+        Ok(None) => {}
+        Err(e) => warn!("could not write the timing report: {e:#}"),
+    }
+    result
+}
+
+impl RustupSubcmd {
+    /// Whether the command may write to RUSTUP_HOME, so that it has to be
+    /// this user's. Commands that only look at it work whoever owns it,
+    /// and `rustup doctor` is what gives it back to this user.
+    fn changes_home(&self) -> bool {
+        !matches!(
+            self,
+            Self::DumpTestament
+                | Self::Show { .. }
+                | Self::Which { .. }
+                | Self::Doc { .. }
+                | Self::Man { .. }
+                | Self::Doctor { .. }
+                | Self::DescribeToolchain { .. }
+                | Self::Stats
+                | Self::Status
+                | Self::Completions { .. }
+                | Self::Toolchain {
+                    subcmd: ToolchainSubcmd::List { .. },
+                }
+                | Self::Target {
+                    subcmd: TargetSubcmd::List { .. } | TargetSubcmd::Doctor { .. },
+                }
+                | Self::Component {
+                    subcmd: ComponentSubcmd::List { .. },
+                }
+                | Self::Override {
+                    subcmd: OverrideSubcmd::List { clean: false, .. },
+                }
+                | Self::Key {
+                    subcmd: KeySubcmd::List,
+                }
+                | Self::Auth {
+                    subcmd: AuthSubcmd::Status { .. },
+                }
+                | Self::Schedule {
+                    subcmd: ScheduleSubcmd::Status,
+                }
+                | Self::Self_ {
+                    subcmd: SelfSubcmd::DiagnoseTls { .. } | SelfSubcmd::Doctor,
+                }
+        )
+    }
+}
+
+async fn run_subcmd(cfg: &mut Cfg<'_>, subcmd: RustupSubcmd) -> Result<utils::ExitCode> {
+    let process = cfg.process;
     match subcmd {
         RustupSubcmd::DumpTestament => common::dump_testament(process),
         RustupSubcmd::Install { opts } => update(cfg, opts, true).await,
         RustupSubcmd::Uninstall { opts } => toolchain_remove(cfg, opts),
-        RustupSubcmd::Show { verbose, subcmd } => handle_epipe(match subcmd {
-            None => show(cfg, verbose),
+        RustupSubcmd::Show {
+            verbose,
+            output,
+            format_version,
+            subcmd,
+        } => handle_epipe(match subcmd {
+            None => match output.with_version(format_version) {
+                OutputFormat::Human => show(cfg, verbose),
+                OutputFormat::Json(version) => json::show(cfg, verbose, version),
+            },
             Some(ShowSubcmd::ActiveToolchain { verbose }) => show_active_toolchain(cfg, verbose),
             Some(ShowSubcmd::Home) => show_rustup_home(cfg),
             Some(ShowSubcmd::Profile) => {
@@ -612,6 +1453,10 @@ pub async fn main(
             no_self_update,
             force,
             force_non_host,
+            no_verify,
+            exit_code,
+            report_file,
+            dry_run,
         } => {
             update(
                 cfg,
@@ -620,6 +1465,10 @@ pub async fn main(
                     no_self_update,
                     force,
                     force_non_host,
+                    no_verify,
+                    exit_code,
+                    report_file,
+                    dry_run,
                     ..UpdateOpts::default()
                 },
                 false,
@@ -628,15 +1477,43 @@ pub async fn main(
         }
         RustupSubcmd::Toolchain { subcmd } => match subcmd {
             ToolchainSubcmd::Install { opts } => update(cfg, opts, true).await,
-            ToolchainSubcmd::List { verbose, quiet } => {
-                handle_epipe(common::list_toolchains(cfg, verbose, quiet))
-            }
+            ToolchainSubcmd::List {
+                verbose,
+                quiet,
+                output,
+                format_version,
+            } => handle_epipe(common::list_toolchains(
+                cfg,
+                verbose,
+                quiet,
+                output.with_version(format_version),
+            )),
             ToolchainSubcmd::Link { toolchain, path } => {
                 toolchain_link(cfg, &toolchain, &path).await
             }
             ToolchainSubcmd::Uninstall { opts } => toolchain_remove(cfg, opts),
+            ToolchainSubcmd::Rename {
+                toolchain,
+                new_name,
+            } => toolchain_rename(cfg, &toolchain, &new_name),
+            ToolchainSubcmd::Pin { toolchain, until } => toolchain_pin(cfg, toolchain, until),
+            ToolchainSubcmd::Unpin { toolchain } => toolchain_unpin(cfg, toolchain),
+            ToolchainSubcmd::Gc {
+                older_than,
+                dry_run,
+            } => toolchain_gc(cfg, older_than, dry_run),
+            ToolchainSubcmd::Hash { toolchain } => toolchain_hash(cfg, toolchain),
+            ToolchainSubcmd::Package { toolchain, output } => {
+                toolchain_package(cfg, toolchain, output)
+            }
+            ToolchainSubcmd::Linkage { toolchain } => toolchain_linkage(cfg, toolchain),
         },
-        RustupSubcmd::Check => check_updates(cfg).await,
+        RustupSubcmd::Check {
+            exit_code,
+            quiet,
+            output,
+            format_version,
+        } => check_updates(cfg, exit_code, quiet, output.with_version(format_version)).await,
         RustupSubcmd::Default {
             toolchain,
             force_non_host,
@@ -645,35 +1522,99 @@ pub async fn main(
             TargetSubcmd::List {
                 toolchain,
                 installed,
+                available,
+                filter,
                 quiet,
-            } => handle_epipe(target_list(cfg, toolchain, installed, quiet).await),
-            TargetSubcmd::Add { target, toolchain } => target_add(cfg, target, toolchain).await,
-            TargetSubcmd::Remove { target, toolchain } => {
-                target_remove(cfg, target, toolchain).await
-            }
+                porcelain,
+                output,
+                format_version,
+            } => handle_epipe(
+                target_list(
+                    cfg,
+                    toolchain,
+                    installed,
+                    available,
+                    filter,
+                    quiet,
+                    porcelain,
+                    output.with_version(format_version),
+                )
+                .await,
+            ),
+            TargetSubcmd::Add {
+                target,
+                toolchain,
+                dry_run,
+            } => target_add(cfg, target, toolchain, dry_run).await,
+            TargetSubcmd::Remove {
+                target,
+                toolchain,
+                dry_run,
+            } => target_remove(cfg, target, toolchain, dry_run).await,
+            TargetSubcmd::Doctor {
+                target,
+                toolchain,
+                write_config,
+            } => target_doctor::doctor(cfg, &target, toolchain, write_config),
         },
         RustupSubcmd::Component { subcmd } => match subcmd {
             ComponentSubcmd::List {
                 toolchain,
                 installed,
                 quiet,
-            } => handle_epipe(component_list(cfg, toolchain, installed, quiet).await),
+                verbose,
+                porcelain,
+                output,
+                format_version,
+            } => handle_epipe(
+                component_list(
+                    cfg,
+                    toolchain,
+                    installed,
+                    quiet,
+                    verbose,
+                    porcelain,
+                    output.with_version(format_version),
+                )
+                .await,
+            ),
+            ComponentSubcmd::Add {
+                toolchain,
+                url: Some(url),
+                sha256: Some(sha256),
+                name: Some(name),
+                ..
+            } => component_add_from_url(cfg, toolchain, &url, &sha256, &name).await,
+            ComponentSubcmd::Add {
+                toolchain,
+                file: Some(file),
+                ..
+            } => component_add_from_file(cfg, toolchain, &file).await,
             ComponentSubcmd::Add {
                 component,
                 toolchain,
                 target,
-            } => component_add(cfg, component, toolchain, target).await,
+                dry_run,
+                ..
+            } => component_add(cfg, component, toolchain, target, dry_run).await,
             ComponentSubcmd::Remove {
                 component,
                 toolchain,
                 target,
-            } => component_remove(cfg, component, toolchain, target).await,
+                dry_run,
+            } => component_remove(cfg, component, toolchain, target, dry_run).await,
         },
         RustupSubcmd::Override { subcmd } => match subcmd {
-            OverrideSubcmd::List => handle_epipe(common::list_overrides(cfg)),
-            OverrideSubcmd::Set { toolchain, path } => {
-                override_add(cfg, toolchain, path.as_deref()).await
-            }
+            OverrideSubcmd::List {
+                clean,
+                output,
+                format_version,
+            } => override_list(cfg, clean, output.with_version(format_version)),
+            OverrideSubcmd::Set {
+                toolchain,
+                path,
+                from_file,
+            } => override_add(cfg, toolchain, &path, from_file.as_deref()).await,
             OverrideSubcmd::Unset { path, nonexistent } => {
                 override_remove(cfg, path.as_deref(), nonexistent)
             }
@@ -682,22 +1623,56 @@ pub async fn main(
             toolchain,
             command,
             install,
-        } => run(cfg, toolchain, command, install)
+            overlays,
+        } => run(cfg, toolchain, command, install, *overlays)
             .await
             .map(ExitCode::from),
-        RustupSubcmd::Which { command, toolchain } => which(cfg, &command, toolchain).await,
+        RustupSubcmd::Proxy { .. } => unreachable!("proxies are run before loading settings"),
+        RustupSubcmd::Which {
+            command,
+            toolchain,
+            all,
+            output,
+            format_version,
+        } => which(
+            cfg,
+            &command,
+            toolchain,
+            all,
+            output.with_version(format_version),
+        ),
         RustupSubcmd::Doc {
             path,
             toolchain,
+            target,
             topic,
             page,
-        } => doc(cfg, path, toolchain, topic.as_deref(), &page).await,
+        } => doc(cfg, path, toolchain, target, topic.as_deref(), &page).await,
         #[cfg(not(windows))]
         RustupSubcmd::Man { command, toolchain } => man(cfg, &command, toolchain).await,
+        RustupSubcmd::Doctor { fix_permissions } => doctor::check_setup(cfg, fix_permissions),
         RustupSubcmd::Self_ { subcmd } => match subcmd {
             SelfSubcmd::Update => self_update::update(cfg).await,
             SelfSubcmd::Uninstall { no_prompt } => self_update::uninstall(no_prompt, process),
             SelfSubcmd::UpgradeData => cfg.upgrade_data().map(|_| ExitCode(0)),
+            SelfSubcmd::BenchProxy {
+                tool,
+                iterations,
+                save,
+                baseline,
+                threshold,
+            } => proxy_bench::bench(
+                cfg,
+                proxy_bench::BenchOpts {
+                    tool: &tool,
+                    iterations,
+                    save: save.as_deref(),
+                    baseline: baseline.as_deref(),
+                    threshold,
+                },
+            ),
+            SelfSubcmd::DiagnoseTls { server } => diagnose_tls::diagnose(cfg, server),
+            SelfSubcmd::Doctor => doctor::doctor(process),
         },
         RustupSubcmd::Set { subcmd } => match subcmd {
             SetSubcmd::DefaultHost { host_triple } => cfg
@@ -709,13 +1684,315 @@ pub async fn main(
             SetSubcmd::AutoSelfUpdate {
                 auto_self_update_mode,
             } => set_auto_self_update(cfg, auto_self_update_mode),
+            SetSubcmd::CrashReports { crash_report_mode } => cfg
+                .set_crash_reports(crash_report_mode)
+                .map(|_| utils::ExitCode(0)),
+            SetSubcmd::OutputStyle { output_style } => cfg
+                .set_output_style(output_style)
+                .map(|_| utils::ExitCode(0)),
+            SetSubcmd::Palette { palette } => cfg.set_palette(palette).map(|_| utils::ExitCode(0)),
+            SetSubcmd::AutoInstall { auto_install_mode } => cfg
+                .set_auto_install(auto_install_mode)
+                .map(|_| utils::ExitCode(0)),
+            SetSubcmd::ProxyLinks { proxy_links } => {
+                // Only kept once the proxies could be linked that way.
+                self_update::install_proxies_as(
+                    proxy_links,
+                    &self_update::proxy_dir(process)?,
+                    process,
+                )?;
+                cfg.set_proxy_links(proxy_links)?;
+                Ok(utils::ExitCode(0))
+            }
+            SetSubcmd::ProxyDir { dir } => {
+                let dir = (dir != Path::new("default")).then_some(dir);
+                self_update::move_proxies(cfg, dir)?;
+                Ok(utils::ExitCode(0))
+            }
+            SetSubcmd::DistServers { servers } => {
+                cfg.set_dist_servers(servers).map(|_| utils::ExitCode(0))
+            }
+            SetSubcmd::CachePeers { peers } => {
+                cfg.set_cache_peers(peers).map(|_| utils::ExitCode(0))
+            }
+            SetSubcmd::ConfirmDownloadsOver { size } => {
+                let limit = match size.as_str() {
+                    "none" => None,
+                    size => Some(utils::units::parse_bytes(size)?),
+                };
+                cfg.set_confirm_downloads_over(limit)
+                    .map(|_| utils::ExitCode(0))
+            }
+            SetSubcmd::PostInstallHook { hook } => {
+                let hook = (hook != "none").then_some(hook);
+                cfg.set_post_install_hook(hook).map(|_| utils::ExitCode(0))
+            }
+            SetSubcmd::Dedup { enabled } => cfg.set_dedup(enabled).map(|_| utils::ExitCode(0)),
+            SetSubcmd::ToolchainRetention { days } => {
+                let days = match days.as_str() {
+                    "none" => None,
+                    days => Some(days.parse().map_err(|_| {
+                        anyhow!("invalid retention period '{days}': expected a number of days or 'none'")
+                    })?),
+                };
+                cfg.set_toolchain_retention(days)
+                    .map(|_| utils::ExitCode(0))
+            }
+            SetSubcmd::IoProfile { io_profile } => {
+                cfg.set_io_profile(io_profile).map(|_| utils::ExitCode(0))
+            }
+            SetSubcmd::MaxDownloads { max } => cfg
+                .set_max_downloads(max.into())
+                .map(|_| utils::ExitCode(0)),
+            SetSubcmd::TlsRootSource { source } => {
+                cfg.set_tls_root_source(source).map(|_| utils::ExitCode(0))
+            }
+            SetSubcmd::TlsHost {
+                host,
+                roots,
+                insecure,
+                remove,
+            } => {
+                let settings = (!remove).then_some(TlsHost { roots, insecure });
+                cfg.set_tls_host(&host, settings)
+                    .map(|_| utils::ExitCode(0))
+            }
         },
+        RustupSubcmd::Schedule { subcmd } => match subcmd {
+            ScheduleSubcmd::EnableAutoUpdate { interval } => schedule::enable(process, interval),
+            ScheduleSubcmd::Status => schedule::status(process),
+            ScheduleSubcmd::Disable => schedule::disable(process),
+        },
+        #[cfg(unix)]
+        RustupSubcmd::Agent => agent::serve(cfg),
+        #[cfg(not(unix))]
+        RustupSubcmd::Agent => Err(anyhow!("rustup agent is only available on Unix")),
+        RustupSubcmd::Cache { subcmd } => match subcmd {
+            CacheSubcmd::InvalidateResolution => cache_invalidate_resolution(cfg),
+        },
+        RustupSubcmd::ServeCache { listen } => serve_cache::serve(cfg, listen),
+        RustupSubcmd::DescribeToolchain { toolchain, bazel } => {
+            describe_toolchain::describe(cfg, toolchain, bazel)
+        }
+        RustupSubcmd::ToggleSystem => self_update::toggle_system(cfg),
+        RustupSubcmd::Key { subcmd } => match subcmd {
+            KeySubcmd::Add {
+                file,
+                sigstore_identity,
+                sigstore_issuer,
+                dist_server,
+            } => {
+                let key = match (file, sigstore_identity, sigstore_issuer) {
+                    (Some(file), _, _) => {
+                        TrustedKey::gpg(utils::read_file("key", &file)?, &cfg.tmp_cx)?
+                    }
+                    (None, Some(identity), Some(issuer)) => {
+                        TrustedKey::Sigstore { identity, issuer }
+                    }
+                    _ => unreachable!("clap requires a key"),
+                };
+                let server = key_dist_server(dist_server, process)?;
+                cfg.add_trusted_key(&server, key)
+                    .map(|_| utils::ExitCode(0))
+            }
+            KeySubcmd::List => key_list(cfg),
+            KeySubcmd::Remove { key, dist_server } => {
+                let server = key_dist_server(dist_server, process)?;
+                if !cfg.remove_trusted_key(&server, &key)? {
+                    return Err(anyhow!("'{key}' is not trusted for '{server}'"));
+                }
+                Ok(utils::ExitCode(0))
+            }
+        },
+        RustupSubcmd::Auth { subcmd } => auth(cfg, subcmd),
+        RustupSubcmd::Config {
+            subcmd: ConfigSubcmd::Sync { from, check },
+        } => config_sync(cfg, from, check).await,
+        RustupSubcmd::Dist {
+            subcmd:
+                DistSubcmd::Export {
+                    toolchain,
+                    profile,
+                    component,
+                    target,
+                    output,
+                },
+        } => dist_export(cfg, toolchain, profile, component, target, output).await,
+        RustupSubcmd::Stats => stats::stats(cfg),
+        RustupSubcmd::Status => status::status(cfg).await,
         RustupSubcmd::Completions { shell, command } => {
             output_completion_script(shell, command, process)
         }
     }
 }
 
+/// The dist server named on the command line, or the one in use.
+fn key_dist_server(dist_server: Option<String>, process: &Process) -> Result<String> {
+    Ok(match dist_server {
+        Some(server) => server.trim_end_matches('/').to_owned(),
+        None => config::dist_root_server(process)?,
+    })
+}
+
+fn auth(cfg: &Cfg<'_>, subcmd: AuthSubcmd) -> Result<utils::ExitCode> {
+    let (AuthSubcmd::Login { dist_server, .. }
+    | AuthSubcmd::Logout { dist_server }
+    | AuthSubcmd::Status { dist_server }) = &subcmd;
+    let server = key_dist_server(dist_server.clone(), cfg.process)?;
+    let Some(host) = download::token_key(&utils::parse_url(&server)?) else {
+        bail!("'{server}' has no host to authenticate to");
+    };
+    let store = &cfg.credential_store;
+
+    match subcmd {
+        AuthSubcmd::Login { .. } => {
+            writeln!(cfg.process.stdout().lock(), "token for '{host}':")?;
+            let token = common::read_secret_line(cfg.process)?;
+            let token = token.trim();
+            if token.is_empty() {
+                bail!("the token for '{host}' is empty");
+            }
+            store.set(&host, token)?;
+            info!("token for '{host}' stored in {store}");
+        }
+        AuthSubcmd::Logout { .. } => {
+            if !store.remove(&host)? {
+                bail!("there is no token for '{host}' in {store}");
+            }
+            info!("token for '{host}' removed from {store}");
+        }
+        AuthSubcmd::Status { .. } => {
+            let status = match store.get(&host)? {
+                Some(_) => format!("logged in, with a token in {store}"),
+                None => "not logged in".to_owned(),
+            };
+            writeln!(cfg.process.stdout().lock(), "{host}: {status}")?;
+        }
+    }
+    Ok(utils::ExitCode(0))
+}
+
+async fn dist_export(
+    cfg: &Cfg<'_>,
+    toolchain: PartialToolchainDesc,
+    profile: Option<Profile>,
+    components: Vec<String>,
+    targets: Vec<String>,
+    output: Option<PathBuf>,
+) -> Result<utils::ExitCode> {
+    let desc = toolchain.resolve(&cfg.get_default_host_triple()?)?;
+    let profile = match profile {
+        Some(profile) => profile,
+        None => cfg.get_profile()?,
+    };
+    let output = output.unwrap_or_else(|| format!("{desc}-bundle.tar").into());
+    let components = components.iter().map(|s| &**s).collect::<Vec<_>>();
+    let targets = targets.iter().map(|s| &**s).collect::<Vec<_>>();
+    let notify = |n: crate::dist::Notification<'_>| (cfg.notify_handler)(n.into());
+    export_bundle(
+        cfg.download_cfg(&notify),
+        &desc,
+        profile,
+        &components,
+        &targets,
+        &output,
+    )
+    .await?;
+    Ok(utils::ExitCode(0))
+}
+
+async fn config_sync(cfg: &Cfg<'_>, from: Option<String>, check: bool) -> Result<utils::ExitCode> {
+    let Some(source) = from.or_else(|| cfg.policy.source.clone()) else {
+        bail!("no configuration has been synced yet, pass `--from` to name its bundle");
+    };
+    let source = source.trim_end_matches('/').to_owned();
+    let trusted_keys = cfg
+        .settings_file
+        .with(|s| Ok(s.trusted_keys.get(&source).cloned().unwrap_or_default()))?;
+    if trusted_keys.is_empty() {
+        bail!(
+            "no key is trusted to sign the configuration from '{source}'\n\
+             help: trust one with `rustup key add --dist-server {source}`"
+        );
+    }
+
+    // A git repository has the bundle at its root, and is cloned first so
+    // that the bundle and its signature can be read from the clone.
+    let dir = cfg.tmp_cx.new_directory()?;
+    let url = match is_git_url(&source) {
+        true => {
+            let clone = dir.join("repo");
+            let output = Command::new("git")
+                .args(["clone", "--quiet", "--depth", "1", "--", &source])
+                .arg(&clone)
+                .output()
+                .context("could not run git, which is needed to sync from a repository")?;
+            if !output.status.success() {
+                bail!(
+                    "could not clone '{source}': {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            Url::from_file_path(clone.join(CONFIG_BUNDLE))
+                .map_err(|()| anyhow!("invalid path of clone '{}'", clone.display()))?
+        }
+        false => utils::parse_url(&source)?,
+    };
+    let bundle = dir.join(CONFIG_BUNDLE);
+    let notify = |n: crate::dist::Notification<'_>| (cfg.notify_handler)(n.into());
+    utils::download_file(&url, &bundle, None, &|n| notify(n.into()), cfg.process)
+        .await
+        .with_context(|| format!("could not download the configuration '{url}'"))?;
+    let download = DownloadCfg {
+        trusted_keys: &trusted_keys,
+        fallback_trusted_keys: &[],
+        pgp_key_file: None,
+        ..cfg.download_cfg(&notify)
+    };
+    trust::verify_manifest(&download, url.as_str(), &bundle).await?;
+
+    let mut policy = Policy::parse(&utils::read_file("configuration", &bundle)?)
+        .with_context(|| format!("invalid configuration from '{source}'"))?;
+    policy.source = Some(source.clone());
+
+    let drift = cfg.policy.drift(&policy);
+    if check {
+        if drift.is_empty() {
+            info!("policy is up to date with '{source}'");
+            return Ok(utils::ExitCode(0));
+        }
+        write!(cfg.process.stdout().lock(), "{drift}")?;
+        return Ok(utils::ExitCode(1));
+    }
+    policy.save(&cfg.rustup_dir)?;
+    match drift.is_empty() {
+        true => info!("policy is up to date with '{source}'"),
+        false => info!("policy synced from '{source}'"),
+    }
+    Ok(utils::ExitCode(0))
+}
+
+/// Whether a configuration bundle is in the git repository at `url`.
+fn is_git_url(url: &str) -> bool {
+    url.ends_with(".git")
+        || url.starts_with("git@")
+        || url.starts_with("git://")
+        || url.starts_with("ssh://")
+}
+
+fn key_list(cfg: &Cfg<'_>) -> Result<utils::ExitCode> {
+    let trusted_keys = cfg.settings_file.with(|s| Ok(s.trusted_keys.clone()))?;
+    let stdout = cfg.process.stdout();
+    let mut stdout = stdout.lock();
+    for (server, keys) in trusted_keys {
+        writeln!(stdout, "{server}")?;
+        for key in keys {
+            writeln!(stdout, "  {key}")?;
+        }
+    }
+    Ok(utils::ExitCode(0))
+}
+
 async fn default_(
     cfg: &Cfg<'_>,
     toolchain: Option<MaybeResolvableToolchainName>,
@@ -762,14 +2039,30 @@ async fn default_(
     Ok(utils::ExitCode(0))
 }
 
-async fn check_updates(cfg: &Cfg<'_>) -> Result<utils::ExitCode> {
+async fn check_updates(
+    cfg: &Cfg<'_>,
+    exit_code: bool,
+    quiet: bool,
+    output: OutputFormat,
+) -> Result<utils::ExitCode> {
     let mut t = cfg.process.stdout().terminal(cfg.process);
     let channels = cfg.list_channels()?;
+    let mut update_available = false;
+    let self_update_available;
+    cfg.warn_expired_pins()?;
 
+    // Only the human output is printed as the versions come in.
+    let print = output == OutputFormat::Human && !quiet;
+    let mut checked = Vec::new();
     for channel in channels {
         let (name, distributable) = channel;
         let current_version = distributable.show_version()?;
         let dist_version = distributable.show_dist_version().await?;
+        update_available |= dist_version.is_some();
+        if !print {
+            checked.push((name.to_string(), current_version, dist_version));
+            continue;
+        }
         let _ = t.attr(terminalsource::Attr::Bold);
         write!(t.lock(), "{name} - ")?;
         match (current_version, dist_version) {
@@ -798,17 +2091,136 @@ async fn check_updates(cfg: &Cfg<'_>) -> Result<utils::ExitCode> {
         }
     }
 
-    check_rustup_update(cfg.process).await?;
+    match print {
+        true => self_update_available = check_rustup_update(cfg.process).await?,
+        false => {
+            let (current, available) = self_update::rustup_versions(cfg.process).await?;
+            self_update_available = current != available;
+            if let OutputFormat::Json(version) = output {
+                json::check(cfg.process, version, checked, (current, &available))?;
+            }
+        }
+    }
 
-    Ok(utils::ExitCode(0))
+    Ok(utils::ExitCode(
+        match (exit_code || quiet, update_available, self_update_available) {
+            (false, ..) => 0,
+            (true, true, _) => UPDATES_EXIT_CODE,
+            (true, false, true) => SELF_UPDATE_EXIT_CODE,
+            (true, false, false) => 0,
+        },
+    ))
 }
 
+/// The exit status of `rustup update --exit-code` when a toolchain or rustup
+/// was updated, and of `rustup check --exit-code` when a toolchain can be.
+const UPDATES_EXIT_CODE: i32 = 2;
+
+/// The exit status of `rustup update --exit-code` and `rustup check --exit-code`
+/// when nothing else is to be done, but rustup has an update that was not
+/// installed, for `rustup self update` to install.
+const SELF_UPDATE_EXIT_CODE: i32 = 3;
+
+/// The components installed by `--for-rustc-dev`, for tools such as Clippy
+/// that link against the compiler's own crates.
+const RUSTC_DEV_COMPONENTS: &[&str] = &["rustc-dev", "llvm-tools", "rust-src"];
+
 async fn update(
+    cfg: &mut Cfg<'_>,
+    mut opts: UpdateOpts,
+    ensure_active_toolchain: bool,
+) -> Result<utils::ExitCode> {
+    if opts.dry_run {
+        return update_dry_run(cfg, opts, ensure_active_toolchain).await;
+    }
+    let Some(report_file) = opts.report_file.take() else {
+        return update_toolchains(cfg, opts, ensure_active_toolchain).await;
+    };
+    let report = Report::start(cfg)?;
+    let result = update_toolchains(cfg, opts, ensure_active_toolchain).await;
+    // A report on a failed update is what CI most needs to keep.
+    let command = match ensure_active_toolchain {
+        true => "install",
+        false => "update",
+    };
+    if let Err(e) = report.write(cfg, &report_file, command, &result) {
+        warn!("could not write the report: {e:#}");
+    }
+    result
+}
+
+/// Prints what `rustup update` or `rustup toolchain install` would download
+/// and remove for each toolchain, for `--dry-run`. Only the channel
+/// manifests are downloaded, and rustup itself is left out.
+async fn update_dry_run(
+    cfg: &mut Cfg<'_>,
+    opts: UpdateOpts,
+    ensure_active_toolchain: bool,
+) -> Result<utils::ExitCode> {
+    if let Some(p) = opts.profile {
+        cfg.set_profile_override(p);
+    }
+    let cfg = &*cfg;
+    let host = cfg.get_default_host_triple()?;
+    let toolchains = if !opts.toolchain.is_empty() {
+        opts.toolchain
+            .into_iter()
+            .map(|name| name.resolve(&host))
+            .collect::<Result<Vec<_>>>()?
+    } else if ensure_active_toolchain {
+        match cfg.find_active_toolchain()? {
+            Some((LocalToolchainName::Named(ToolchainName::Official(desc)), _)) => vec![desc],
+            _ => Vec::new(),
+        }
+    } else {
+        let pins = cfg.settings_file.with(|s| Ok(s.pins.clone()))?;
+        cfg.list_channels()?
+            .into_iter()
+            .map(|(desc, _)| desc)
+            .filter(|desc| !pins.contains_key(&desc.to_string()))
+            .collect()
+    };
+
+    let mut components = cfg.policy.expand_component_groups(&opts.component)?;
+    if opts.for_rustc_dev {
+        for component in RUSTC_DEV_COMPONENTS {
+            if !components.iter().any(|c| c == component) {
+                components.push(component.to_string());
+            }
+        }
+    }
+    let components = components.iter().map(|s| &**s).collect::<Vec<_>>();
+    let targets = opts.target.iter().map(|s| &**s).collect::<Vec<_>>();
+    let profile = cfg.get_profile()?;
+    let mut plans = Vec::new();
+    for desc in toolchains {
+        let plan =
+            DistributableToolchain::plan_update(cfg, &desc, &components, &targets, profile).await?;
+        plans.push((desc, plan));
+    }
+
+    let stdout = cfg.process.stdout();
+    let mut stdout = stdout.lock();
+    if plans.is_empty() {
+        writeln!(stdout, "no toolchains to install or update")?;
+    }
+    for (desc, plan) in plans {
+        writeln!(stdout, "{desc}:")?;
+        match plan {
+            Some((manifest, plan)) => write_change_plan(&mut stdout, "  ", &manifest, &plan)?,
+            None => writeln!(stdout, "  up to date")?,
+        }
+    }
+    Ok(utils::ExitCode(0))
+}
+
+async fn update_toolchains(
     cfg: &mut Cfg<'_>,
     opts: UpdateOpts,
     ensure_active_toolchain: bool,
 ) -> Result<utils::ExitCode> {
     let mut exit_code = utils::ExitCode(0);
+    let mut changed = false;
 
     common::warn_if_host_is_emulated(cfg.process);
     let self_update_mode = cfg.get_self_update_mode()?;
@@ -818,16 +2230,51 @@ async fn update(
     // and has **no** no-self-update parameter.
     let self_update = !self_update::NEVER_SELF_UPDATE
         && self_update_mode == SelfUpdateMode::Enable
-        && !opts.no_self_update;
+        && !opts.no_self_update
+        && opts.from_bundle.is_none();
     let force_non_host = opts.force_non_host;
     if let Some(p) = opts.profile {
         cfg.set_profile_override(p);
     }
+    if opts.no_verify
+        && (!cfg.trusted_keys.is_empty()
+            || cfg.pgp_key_file.is_some()
+            || cfg
+                .fallback_trusted_keys
+                .iter()
+                .any(|keys| !keys.is_empty()))
+    {
+        warn!("not checking the signatures of channel manifests");
+        cfg.trusted_keys.clear();
+        cfg.fallback_trusted_keys.clear();
+        cfg.pgp_key_file = None;
+    }
+    // An archive is unpacked into a directory that is removed once the
+    // toolchains are installed.
+    let mut _unpacked_bundle = None;
+    if let Some(bundle) = &opts.from_bundle {
+        let dir = match bundle.is_dir() {
+            true => bundle.clone(),
+            false => {
+                cfg.tmp_cx.create_root()?;
+                let unpacked = tempfile::Builder::new()
+                    .prefix("bundle")
+                    .tempdir_in(cfg.rustup_dir.join("tmp"))?;
+                let dir = unpack_bundle(bundle, unpacked.path())?;
+                _unpacked_bundle = Some(unpacked);
+                dir
+            }
+        };
+        info!("installing from the bundle '{}'", bundle.display());
+        cfg.dist_bundle = Some(dir);
+    }
     let cfg = &cfg;
+    cfg.warn_expired_pins()?;
     if cfg.get_profile()? == Profile::Complete {
         warn!("{}", common::WARN_COMPLETE_PROFILE);
     }
     let names = opts.toolchain;
+    let component_names = cfg.policy.expand_component_groups(&opts.component)?;
     if !names.is_empty() {
         for name in names {
             // This needs another pass to fix it all up
@@ -843,7 +2290,14 @@ async fn update(
             }
             let desc = name.resolve(&cfg.get_default_host_triple()?)?;
 
-            let components = opts.component.iter().map(|s| &**s).collect::<Vec<_>>();
+            let mut components = component_names.iter().map(|s| &**s).collect::<Vec<_>>();
+            if opts.for_rustc_dev {
+                for component in RUSTC_DEV_COMPONENTS {
+                    if !components.contains(component) {
+                        components.push(component);
+                    }
+                }
+            }
             let targets = opts.target.iter().map(|s| &**s).collect::<Vec<_>>();
 
             let force = opts.force;
@@ -868,6 +2322,11 @@ async fn update(
                 }
                 Err(e) => Err(e)?,
             };
+            changed |= !matches!(status, UpdateStatus::Unchanged);
+
+            if opts.for_rustc_dev {
+                DistributableToolchain::new(cfg, desc.clone())?.verify_rustc_dev()?;
+            }
 
             writeln!(cfg.process.stdout().lock())?;
             common::show_channel_update(
@@ -880,7 +2339,9 @@ async fn update(
             }
         }
         if self_update {
-            exit_code &= common::self_update(|| Ok(()), cfg.process).await?;
+            let (code, updated) = common::self_update(|| Ok(()), cfg.process).await?;
+            exit_code &= code;
+            changed |= updated;
         }
     } else if ensure_active_toolchain {
         let (toolchain, reason) = cfg
@@ -889,14 +2350,20 @@ async fn update(
         info!("the active toolchain `{toolchain}` has been installed");
         info!("it's active because: {reason}");
     } else {
-        exit_code &= common::update_all_channels(cfg, self_update, opts.force).await?;
+        let (code, updated) = common::update_all_channels(cfg, self_update, opts.force).await?;
+        exit_code &= code;
+        changed |= updated;
         info!("cleaning up downloads & tmp directories");
         utils::delete_dir_contents_following_links(&cfg.download_dir);
         cfg.tmp_cx.clean();
     }
 
-    if !self_update::NEVER_SELF_UPDATE && self_update_mode == SelfUpdateMode::CheckOnly {
-        check_rustup_update(cfg.process).await?;
+    let mut self_update_pending = false;
+    if !self_update::NEVER_SELF_UPDATE
+        && self_update_mode == SelfUpdateMode::CheckOnly
+        && opts.from_bundle.is_none()
+    {
+        self_update_pending = check_rustup_update(cfg.process).await?;
     }
 
     if self_update::NEVER_SELF_UPDATE {
@@ -904,6 +2371,13 @@ async fn update(
         info!("any updates to rustup will need to be fetched with your system package manager")
     }
 
+    if opts.exit_code && exit_code.0 == 0 {
+        if changed {
+            exit_code = utils::ExitCode(UPDATES_EXIT_CODE);
+        } else if self_update_pending {
+            exit_code = utils::ExitCode(SELF_UPDATE_EXIT_CODE);
+        }
+    }
     Ok(exit_code)
 }
 
@@ -912,29 +2386,106 @@ async fn run(
     toolchain: ResolvableLocalToolchainName,
     command: Vec<String>,
     install: bool,
+    overlays: RunOverlays,
 ) -> Result<ExitStatus> {
-    let toolchain = toolchain.resolve(&cfg.get_default_host_triple()?)?;
-    let toolchain = Toolchain::from_local(toolchain, install, cfg).await?;
-    let cmd = toolchain.command(&command[0])?;
-    command::run_command_for_dir(cmd, &command[0], &command[1..])
+    let host = cfg.get_default_host_triple()?;
+    // A toolchain only needed for this command is installed with as little
+    // as it can be.
+    let install = (install || cfg.get_auto_install_mode()? != AutoInstallMode::Disable)
+        .then_some(Profile::Minimal);
+    let toolchain = Toolchain::from_local(toolchain.resolve(&host)?, install, cfg).await?;
+
+    // Tools taken from other toolchains are found by the proxies through
+    // environment variables, so that they are also used when run by other
+    // tools, such as rustc by cargo.
+    let mut tools = Vec::new();
+    let overlays = [
+        ("rustc", overlays.rustc_from),
+        ("cargo", overlays.cargo_from),
+    ];
+    for (tool, name) in overlays {
+        if let Some(name) = name {
+            let overlay = Toolchain::from_local(name.resolve(&host)?, install, cfg).await?;
+            tools.push((tool, overlay));
+        }
+    }
+
+    let binary = command[0].strip_suffix(EXE_SUFFIX).unwrap_or(&command[0]);
+    let mut cmd = match tools.iter().find(|(tool, _)| *tool == binary) {
+        Some((_, overlay)) => overlay.command(&command[0])?,
+        None => toolchain.command(&command[0])?,
+    };
+    cmd.env("RUSTUP_TOOLCHAIN", toolchain.name().to_string());
+    for (tool, overlay) in &tools {
+        cmd.env(proxy_mode::overlay_var(tool), overlay.name().to_string());
+    }
+    command::run_command_for_dir(cmd, &command[0], &command[1..], cfg.process)
 }
 
-async fn which(
+fn which(
     cfg: &Cfg<'_>,
     binary: &str,
     toolchain: Option<ResolvableToolchainName>,
+    all: bool,
+    output: OutputFormat,
 ) -> Result<utils::ExitCode> {
-    let binary_path = cfg.resolve_toolchain(toolchain)?.binary_file(binary);
+    let active = match toolchain {
+        Some(name) => {
+            let name = name.resolve(&cfg.get_default_host_triple()?)?;
+            Some((name.into(), ActiveReason::CommandLine))
+        }
+        None => cfg.find_active_toolchain()?,
+    };
 
-    utils::assert_is_file(&binary_path)?;
+    // Each toolchain whose binary is displayed, and why it is active if it is.
+    let mut found = Vec::new();
+    if all {
+        for name in cfg.list_toolchains()? {
+            let toolchain = Toolchain::new(cfg, name.into())?;
+            if !toolchain.binary_file(binary).is_file() {
+                continue;
+            }
+            let reason = match &active {
+                Some((name, reason)) if name == toolchain.name() => Some(reason),
+                _ => None,
+            };
+            found.push((toolchain, reason));
+        }
+        if found.is_empty() {
+            return Err(anyhow!("no installed toolchain has the command '{binary}'"));
+        }
+    } else {
+        let (name, reason) = active
+            .as_ref()
+            .ok_or_else(|| no_toolchain_error(cfg.process))?;
+        let toolchain = Toolchain::new(cfg, name.clone())?;
+        utils::assert_is_file(&toolchain.binary_file(binary))?;
+        found.push((toolchain, Some(reason)));
+    }
 
-    writeln!(cfg.process.stdout().lock(), "{}", binary_path.display())?;
+    if let OutputFormat::Json(version) = output {
+        return json::which(cfg.process, version, binary, &found);
+    }
+    let stdout = cfg.process.stdout();
+    let mut stdout = stdout.lock();
+    for (toolchain, reason) in &found {
+        let path = toolchain.binary_file(binary);
+        match (all, reason) {
+            (false, _) => writeln!(stdout, "{}", path.display())?,
+            (true, None) => writeln!(stdout, "{}: {}", toolchain.name(), path.display())?,
+            (true, Some(_)) => {
+                writeln!(stdout, "{} (active): {}", toolchain.name(), path.display())?
+            }
+        }
+    }
     Ok(utils::ExitCode(0))
 }
 
 #[tracing::instrument(level = "trace", skip_all)]
 fn show(cfg: &Cfg<'_>, verbose: bool) -> Result<utils::ExitCode> {
     common::warn_if_host_is_emulated(cfg.process);
+    cfg.warn_expired_pins()?;
+    cfg.warn_if_home_on_network_fs();
 
     // Print host triple
     {
@@ -1107,26 +2658,27 @@ fn show_rustup_home(cfg: &Cfg<'_>) -> Result<utils::ExitCode> {
     Ok(utils::ExitCode(0))
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn target_list(
     cfg: &Cfg<'_>,
     toolchain: Option<PartialToolchainDesc>,
     installed_only: bool,
+    available_only: bool,
+    filter: Option<String>,
     quiet: bool,
+    porcelain: bool,
+    output: OutputFormat,
 ) -> Result<utils::ExitCode> {
     // downcasting required because the toolchain files can name any toolchain
     let distributable = DistributableToolchain::from_partial(toolchain, cfg)?;
-    common::list_items(
+    common::list_targets(
         distributable,
-        |c| {
-            (c.component.short_name_in_manifest() == "rust-std").then(|| {
-                c.component
-                    .target
-                    .as_deref()
-                    .expect("rust-std should have a target")
-            })
-        },
         installed_only,
+        available_only,
+        filter.as_deref(),
         quiet,
+        porcelain,
+        output,
         cfg.process,
     )
 }
@@ -1135,6 +2687,7 @@ async fn target_add(
     cfg: &Cfg<'_>,
     mut targets: Vec<String>,
     toolchain: Option<PartialToolchainDesc>,
+    dry_run: bool,
 ) -> Result<utils::ExitCode> {
     // XXX: long term move this error to cli ? the normal .into doesn't work
     // because Result here is the wrong sort and expression type ascription
@@ -1168,14 +2721,20 @@ async fn target_add(
         }
     }
 
-    for target in targets {
-        let new_component = Component::new(
-            "rust-std".to_string(),
-            Some(TargetTriple::new(target)),
-            false,
-        );
-        distributable.add_component(new_component).await?;
+    let new_components = targets
+        .into_iter()
+        .map(|target| {
+            Component::new(
+                "rust-std".to_string(),
+                Some(TargetTriple::new(target)),
+                false,
+            )
+        })
+        .collect();
+    if dry_run {
+        return print_change_plan(cfg, &distributable, new_components, vec![]);
     }
+    distributable.add_components(new_components).await?;
 
     Ok(utils::ExitCode(0))
 }
@@ -1184,32 +2743,40 @@ async fn target_remove(
     cfg: &Cfg<'_>,
     targets: Vec<String>,
     toolchain: Option<PartialToolchainDesc>,
+    dry_run: bool,
 ) -> Result<utils::ExitCode> {
     let distributable = DistributableToolchain::from_partial(toolchain, cfg)?;
+    let targets = targets
+        .into_iter()
+        .map(TargetTriple::new)
+        .collect::<Vec<_>>();
+    let components = targets
+        .iter()
+        .map(|target| Component::new("rust-std".to_string(), Some(target.clone()), false))
+        .collect();
+    if dry_run {
+        return print_change_plan(cfg, &distributable, vec![], components);
+    }
 
-    for target in targets {
-        let target = TargetTriple::new(target);
-        let default_target = cfg.get_default_host_triple()?;
-        if target == default_target {
-            warn!("removing the default host target; proc-macros and build scripts might no longer build");
-        }
-        // Whether we have at most 1 component target that is not `None` (wildcard).
-        let has_at_most_one_target = distributable
-            .components()?
-            .into_iter()
-            .filter_map(|c| match (c.installed, c.component.target) {
-                (true, Some(t)) => Some(t),
-                _ => None,
-            })
-            .unique()
-            .at_most_one()
-            .is_ok();
-        if has_at_most_one_target {
-            warn!("removing the last target; no build targets will be available");
-        }
-        let new_component = Component::new("rust-std".to_string(), Some(target), false);
-        distributable.remove_component(new_component).await?;
+    let default_target = cfg.get_default_host_triple()?;
+    if targets.contains(&default_target) {
+        warn!(
+            "removing the default host target; proc-macros and build scripts might no longer build"
+        );
+    }
+    // Whether no target that is not `None` (wildcard) would be left.
+    let removes_every_target = distributable
+        .components()?
+        .into_iter()
+        .filter_map(|c| match (c.installed, c.component.target) {
+            (true, Some(t)) => Some(t),
+            _ => None,
+        })
+        .all(|t| targets.contains(&t));
+    if removes_every_target {
+        warn!("removing the last target; no build targets will be available");
     }
+    distributable.remove_components(components).await?;
 
     Ok(utils::ExitCode(0))
 }
@@ -1219,14 +2786,19 @@ async fn component_list(
     toolchain: Option<PartialToolchainDesc>,
     installed_only: bool,
     quiet: bool,
+    verbose: bool,
+    porcelain: bool,
+    output: OutputFormat,
 ) -> Result<utils::ExitCode> {
     // downcasting required because the toolchain files can name any toolchain
     let distributable = DistributableToolchain::from_partial(toolchain, cfg)?;
-    common::list_items(
+    common::list_components(
         distributable,
-        |c| Some(&c.name),
         installed_only,
         quiet,
+        verbose,
+        porcelain,
+        output,
         cfg.process,
     )
 }
@@ -1236,15 +2808,48 @@ async fn component_add(
     components: Vec<String>,
     toolchain: Option<PartialToolchainDesc>,
     target: Option<String>,
+    dry_run: bool,
 ) -> Result<utils::ExitCode> {
     let distributable = DistributableToolchain::from_partial(toolchain, cfg)?;
-    let target = get_target(target, &distributable);
 
-    for component in &components {
-        let new_component = Component::try_new(component, &distributable, target.as_ref())?;
-        distributable.add_component(new_component).await?;
+    let components = cfg.policy.expand_component_groups(&components)?;
+    let components = select_components(&components, target, &distributable, false)?;
+    if dry_run {
+        return print_change_plan(cfg, &distributable, components, vec![]);
+    }
+    distributable.add_components(components).await?;
+
+    Ok(utils::ExitCode(0))
+}
+
+async fn component_add_from_url(
+    cfg: &Cfg<'_>,
+    toolchain: Option<PartialToolchainDesc>,
+    url: &str,
+    sha256: &str,
+    name: &str,
+) -> Result<utils::ExitCode> {
+    let url = utils::parse_url(url)?;
+    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("'{sha256}' is not a SHA-256 hash"));
     }
+    let distributable = DistributableToolchain::from_partial(toolchain, cfg)?;
+    distributable
+        .add_component_from_url(&url, &sha256.to_ascii_lowercase(), name)
+        .await?;
+    Ok(utils::ExitCode(0))
+}
 
+async fn component_add_from_file(
+    cfg: &Cfg<'_>,
+    toolchain: Option<PartialToolchainDesc>,
+    file: &Path,
+) -> Result<utils::ExitCode> {
+    if !file.is_file() {
+        return Err(anyhow!("'{}' is not a file", file.display()));
+    }
+    let distributable = DistributableToolchain::from_partial(toolchain, cfg)?;
+    distributable.add_component_from_file(file).await?;
     Ok(utils::ExitCode(0))
 }
 
@@ -1262,18 +2867,135 @@ async fn component_remove(
     components: Vec<String>,
     toolchain: Option<PartialToolchainDesc>,
     target: Option<String>,
+    dry_run: bool,
 ) -> Result<utils::ExitCode> {
     let distributable = DistributableToolchain::from_partial(toolchain, cfg)?;
-    let target = get_target(target, &distributable);
 
-    for component in &components {
-        let new_component = Component::try_new(component, &distributable, target.as_ref())?;
-        distributable.remove_component(new_component).await?;
+    let components = select_components(&components, target, &distributable, true)?;
+    if dry_run {
+        return print_change_plan(cfg, &distributable, vec![], components);
     }
+    distributable.remove_components(components).await?;
+
+    Ok(utils::ExitCode(0))
+}
 
+/// Prints what adding and removing the given components would download and
+/// remove, for `--dry-run`.
+fn print_change_plan(
+    cfg: &Cfg<'_>,
+    distributable: &DistributableToolchain<'_>,
+    add: Vec<Component>,
+    remove: Vec<Component>,
+) -> Result<utils::ExitCode> {
+    let (manifest, plan) = distributable.plan_changes(add, remove)?;
+    let stdout = cfg.process.stdout();
+    write_change_plan(&mut stdout.lock(), "", &manifest, &plan)?;
     Ok(utils::ExitCode(0))
 }
 
+/// Writes `plan` one change per line, each starting with `indent`.
+fn write_change_plan(
+    out: &mut impl Write,
+    indent: &str,
+    manifest: &Manifest,
+    plan: &ChangePlan,
+) -> Result<()> {
+    if plan.is_empty() && plan.unavailable.is_empty() {
+        writeln!(out, "{indent}nothing to change")?;
+        return Ok(());
+    }
+    for component in &plan.removals {
+        writeln!(out, "{indent}remove {}", component.description(manifest))?;
+    }
+    for download in &plan.downloads {
+        let description = download.component.description(manifest);
+        match download.size {
+            Some(size) => writeln!(
+                out,
+                "{indent}install {description} ({})",
+                format_bytes(size)
+            )?,
+            None => writeln!(out, "{indent}install {description}")?,
+        }
+    }
+    for component in &plan.unavailable {
+        writeln!(
+            out,
+            "{indent}unavailable {}",
+            component.description(manifest)
+        )?;
+    }
+    if let Some(download) = plan.download_size {
+        write!(out, "{indent}total: download {}", format_bytes(download))?;
+        match plan.installed_size {
+            Some(installed) => writeln!(out, ", use {} on disk", format_bytes(installed))?,
+            None => writeln!(out)?,
+        }
+    }
+    Ok(())
+}
+
+/// Resolves the components named on the command line for the given target.
+///
+/// Besides a target triple, `target` may be `installed`, to select the
+/// component for every target whose standard library is installed, or
+/// `all`, to select it for every target the manifest has it for. Either way,
+/// only components that would be affected are selected: ones that are
+/// installed when `removing`, and ones that are missing otherwise.
+fn select_components(
+    names: &[String],
+    target: Option<String>,
+    distributable: &DistributableToolchain<'_>,
+    removing: bool,
+) -> Result<Vec<Component>> {
+    let all_targets = match target.as_deref() {
+        Some("all") => true,
+        Some("installed") => false,
+        _ => {
+            let target = get_target(target, distributable);
+            return names
+                .iter()
+                .map(|name| Component::try_new(name, distributable, target.as_ref()))
+                .collect();
+        }
+    };
+
+    let manifest = distributable.get_manifest()?;
+    let statuses = distributable.components()?;
+    let installed_targets = statuses
+        .iter()
+        .filter(|s| s.installed && s.component.short_name_in_manifest() == "rust-std")
+        .filter_map(|s| s.component.target.as_ref())
+        .collect::<Vec<_>>();
+
+    let mut selected = Vec::new();
+    for name in names {
+        let components = statuses
+            .iter()
+            .filter(|s| {
+                (name == s.component.short_name_in_manifest()
+                    || *name == s.component.short_name(&manifest))
+                    && s.component
+                        .target
+                        .as_ref()
+                        .is_some_and(|t| all_targets || installed_targets.contains(&t))
+                    && s.available
+                    && s.installed == removing
+            })
+            .map(|s| s.component.clone())
+            .collect::<Vec<_>>();
+        if components.is_empty() {
+            // Nothing to do for any target; let the host component report
+            // whether it is unknown or already (un)installed.
+            let host = get_target(None, distributable);
+            selected.push(Component::try_new(name, distributable, host.as_ref())?);
+        }
+        selected.extend(components);
+    }
+    Ok(selected)
+}
+
 async fn toolchain_link(
     cfg: &Cfg<'_>,
     dest: &CustomToolchainName,
@@ -1305,6 +3027,192 @@ async fn toolchain_link(
     Ok(utils::ExitCode(0))
 }
 
+fn toolchain_rename(
+    cfg: &Cfg<'_>,
+    toolchain: &CustomToolchainName,
+    new_name: &CustomToolchainName,
+) -> Result<utils::ExitCode> {
+    let name = ToolchainName::Custom(toolchain.clone());
+    if !Toolchain::exists(cfg, &(&name).into())? {
+        return Err(RustupError::ToolchainNotInstalled { name }.into());
+    }
+    cfg.rename_toolchain(toolchain, new_name)?;
+    Ok(utils::ExitCode(0))
+}
+
+fn toolchain_pin(
+    cfg: &Cfg<'_>,
+    toolchain: ResolvableToolchainName,
+    until: Option<NaiveDate>,
+) -> Result<utils::ExitCode> {
+    let name = toolchain.resolve(&cfg.get_default_host_triple()?)?;
+    if !Toolchain::exists(cfg, &(&name).into())? {
+        return Err(RustupError::ToolchainNotInstalled { name }.into());
+    }
+    cfg.pin_toolchain(&name, until)?;
+    cfg.warn_expired_pins()?;
+    Ok(utils::ExitCode(0))
+}
+
+fn toolchain_unpin(cfg: &Cfg<'_>, toolchain: ResolvableToolchainName) -> Result<utils::ExitCode> {
+    let name = toolchain.resolve(&cfg.get_default_host_triple()?)?;
+    if !cfg.unpin_toolchain(&name)? {
+        info!("toolchain '{name}' is not pinned");
+    }
+    Ok(utils::ExitCode(0))
+}
+
+fn toolchain_gc(cfg: &Cfg<'_>, older_than: Option<u64>, dry_run: bool) -> Result<utils::ExitCode> {
+    let Some(days) = older_than.or(cfg.get_toolchain_retention()?) else {
+        bail!(
+            "no retention period is set; pass `--older-than <DAYS>` or set one with `rustup set toolchain-retention`"
+        );
+    };
+
+    // Toolchains that something refers to are kept however long ago they
+    // were used.
+    let mut kept = BTreeSet::new();
+    kept.extend(cfg.get_default()?.map(|name| name.to_string()));
+    if let Some((LocalToolchainName::Named(name), _)) = cfg.find_active_toolchain()? {
+        kept.insert(name.to_string());
+    }
+    cfg.settings_file.with(|s| {
+        kept.extend(s.overrides.values().cloned());
+        kept.extend(s.pins.keys().cloned());
+        Ok(())
+    })?;
+
+    let usage = usage::read(&cfg.rustup_dir)?;
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for name in cfg.list_toolchains()? {
+        // Linked toolchains aren't rustup's to remove.
+        let ToolchainName::Official(desc) = &name else {
+            continue;
+        };
+        if kept.contains(&name.to_string()) {
+            continue;
+        }
+
+        // A toolchain counts as used when a proxy ran it, or when it was
+        // installed or updated.
+        let run = usage
+            .get(&name.to_string())
+            .map(|usage| UNIX_EPOCH + Duration::from_secs(usage.last_used));
+        let updated = match cfg.manifest_cache.installed(desc)? {
+            Some(installed) => Some(UNIX_EPOCH + Duration::from_secs(installed.updated)),
+            None => fs::metadata(cfg.toolchain_path(&(&name).into()))
+                .and_then(|meta| meta.modified())
+                .ok(),
+        };
+        let Some(last_used) = run.max(updated) else {
+            continue;
+        };
+        let unused = now.duration_since(last_used).unwrap_or_default().as_secs() / 86400;
+        if unused < days {
+            continue;
+        }
+        if !utils::processes::running_from(&cfg.toolchain_path(&(&name).into())).is_empty() {
+            info!("keeping toolchain '{name}', which is in use");
+            continue;
+        }
+
+        removed += 1;
+        if dry_run {
+            info!("would remove toolchain '{name}', unused for {unused} days");
+            continue;
+        }
+        info!("removing toolchain '{name}', unused for {unused} days");
+        Toolchain::ensure_removed(cfg, (&name).into())?;
+    }
+    if removed == 0 {
+        info!("no toolchain has been unused for {days} days");
+    }
+    Ok(utils::ExitCode(0))
+}
+
+fn toolchain_hash(
+    cfg: &Cfg<'_>,
+    toolchain: ResolvableLocalToolchainName,
+) -> Result<utils::ExitCode> {
+    let toolchain = toolchain.resolve(&cfg.get_default_host_triple()?)?;
+    let hash = Toolchain::new(cfg, toolchain)?.layout_hash()?;
+    writeln!(cfg.process.stdout().lock(), "{hash}")?;
+    Ok(utils::ExitCode(0))
+}
+
+fn toolchain_package(
+    cfg: &Cfg<'_>,
+    toolchain: ResolvableLocalToolchainName,
+    output: Option<PathBuf>,
+) -> Result<utils::ExitCode> {
+    let toolchain = toolchain.resolve(&cfg.get_default_host_triple()?)?;
+    let toolchain = Toolchain::new(cfg, toolchain)?;
+    let output = output.unwrap_or_else(|| format!("{}.tar.zst", toolchain.name()).into());
+    toolchain.package(&output)?;
+    info!(
+        "packaged toolchain '{}' as '{}'",
+        toolchain.name(),
+        output.display()
+    );
+    Ok(utils::ExitCode(0))
+}
+
+fn toolchain_linkage(
+    cfg: &Cfg<'_>,
+    toolchain: ResolvableLocalToolchainName,
+) -> Result<utils::ExitCode> {
+    let toolchain = toolchain.resolve(&cfg.get_default_host_triple()?)?;
+    let toolchain = Toolchain::new(cfg, toolchain)?;
+    let root = toolchain.path();
+    let mut files = Vec::new();
+    utils::elf::find(root, &mut files)?;
+
+    let stdout = cfg.process.stdout();
+    let mut stdout = stdout.lock();
+    let mut missing_interpreters = 0;
+    for path in files {
+        let Some(linkage) = utils::elf::linkage(&path)? else {
+            continue;
+        };
+        // Libraries shipped in the toolchain are found through the runpath,
+        // so only the ones expected from the system are of interest.
+        let system = linkage
+            .needed
+            .iter()
+            .filter(|lib| !root.join("lib").join(lib).exists())
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if linkage.interpreter.is_none() && system.is_empty() {
+            continue;
+        }
+
+        let rel = path.strip_prefix(root).unwrap_or(&path);
+        writeln!(stdout, "{}", rel.display())?;
+        if let Some(interpreter) = &linkage.interpreter {
+            let found = Path::new(interpreter).exists();
+            if !found {
+                missing_interpreters += 1;
+            }
+            let note = if found { "" } else { " (not found)" };
+            writeln!(stdout, "    interpreter: {interpreter}{note}")?;
+        }
+        if !system.is_empty() {
+            writeln!(stdout, "    system libraries: {}", system.join(", "))?;
+        }
+        if let Some(runpath) = &linkage.runpath {
+            writeln!(stdout, "    runpath: {runpath}")?;
+        }
+    }
+    drop(stdout);
+
+    if missing_interpreters > 0 {
+        warn!("{missing_interpreters} file(s) ask for a dynamic linker that is not on this system");
+        warn!("they can be patched as they are installed with `rustup set post-install-hook`");
+    }
+    Ok(utils::ExitCode(0))
+}
+
 fn toolchain_remove(cfg: &mut Cfg<'_>, opts: UninstallOpts) -> Result<utils::ExitCode> {
     let default_toolchain = cfg.get_default().ok().flatten();
     let active_toolchain = cfg.find_active_toolchain().ok().flatten().map(|(it, _)| it);
@@ -1325,7 +3233,29 @@ fn toolchain_remove(cfg: &mut Cfg<'_>, opts: UninstallOpts) -> Result<utils::Exi
             warn!("removing the default toolchain; proc-macros and build scripts might no longer build");
         }
 
+        // Removing a link can't break what runs from the linked directory.
+        let path = cfg.toolchain_path(&(&toolchain_name).into());
+        let linked = fs::symlink_metadata(&path).is_ok_and(|meta| meta.is_symlink());
+        let running = match linked {
+            true => Vec::new(),
+            false => utils::processes::running_from(&path),
+        };
+        if !running.is_empty() {
+            let list = running
+                .iter()
+                .map(|p| format!("\n  {} {}", p.pid, p.exe.display()))
+                .collect::<String>();
+            if !opts.force {
+                bail!(
+                    "toolchain '{toolchain_name}' is in use by:{list}\n\
+                     stop these processes first, or pass `--force` to remove it anyway"
+                );
+            }
+            warn!("removing toolchain '{toolchain_name}' while it is in use by:{list}");
+        }
+
         Toolchain::ensure_removed(cfg, (&toolchain_name).into())?;
+        cfg.unpin_toolchain(&toolchain_name)?;
     }
     Ok(utils::ExitCode(0))
 }
@@ -1333,8 +3263,10 @@ fn toolchain_remove(cfg: &mut Cfg<'_>, opts: UninstallOpts) -> Result<utils::Exi
 async fn override_add(
     cfg: &Cfg<'_>,
     toolchain: ResolvableToolchainName,
-    path: Option<&Path>,
+    paths: &[PathBuf],
+    from_file: Option<&Path>,
 ) -> Result<utils::ExitCode> {
+    let paths = override_paths(cfg, paths, from_file)?;
     let toolchain_name = toolchain.resolve(&cfg.get_default_host_triple()?)?;
     match Toolchain::new(cfg, (&toolchain_name).into()) {
         Ok(_) => {}
@@ -1356,25 +3288,100 @@ async fn override_add(
         Err(e) => Err(e)?,
     }
 
-    cfg.make_override(path.unwrap_or(&cfg.current_dir), &toolchain_name)?;
+    cfg.make_overrides(&paths, &toolchain_name)?;
+    Ok(utils::ExitCode(0))
+}
+
+/// Collects the directories passed to `rustup override set`, expanding any
+/// glob patterns among them. Defaults to the current directory.
+fn override_paths(
+    cfg: &Cfg<'_>,
+    paths: &[PathBuf],
+    from_file: Option<&Path>,
+) -> Result<Vec<PathBuf>> {
+    let mut patterns = paths.to_vec();
+    if let Some(file) = from_file {
+        let content = utils::read_file("override paths", file)?;
+        let listed = content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        if listed.is_empty() {
+            return Err(anyhow!("no directories listed in '{}'", file.display()));
+        }
+        patterns.extend(listed);
+    }
+    if patterns.is_empty() {
+        return Ok(vec![cfg.current_dir.clone()]);
+    }
+
+    let mut dirs = Vec::new();
+    for pattern in patterns {
+        let Some(glob) = pattern.to_str().filter(|p| p.contains(['*', '?', '['])) else {
+            dirs.push(cfg.current_dir.join(pattern));
+            continue;
+        };
+        let glob = if pattern.is_absolute() {
+            glob.to_owned()
+        } else {
+            let base = glob::Pattern::escape(&cfg.current_dir.to_string_lossy());
+            Path::new(&base).join(glob).to_string_lossy().into_owned()
+        };
+        let matches = glob::glob(&glob)
+            .with_context(|| format!("invalid glob pattern '{}'", pattern.display()))?
+            .filter_map(Result::ok)
+            .filter(|p| p.is_dir())
+            .collect::<Vec<_>>();
+        if matches.is_empty() {
+            return Err(anyhow!("no directories match '{}'", pattern.display()));
+        }
+        dirs.extend(matches);
+    }
+    Ok(dirs)
+}
+
+fn cache_invalidate_resolution(cfg: &Cfg<'_>) -> Result<utils::ExitCode> {
+    let forgotten = proxy_mode::resolution_cache::clear(&cfg.rustup_dir)?;
+    if forgotten > 0 {
+        let dirs = match forgotten {
+            1 => "1 directory".to_owned(),
+            n => format!("{n} directories"),
+        };
+        info!("forgot the toolchains proxies cached for {dirs}");
+    }
+    #[cfg(unix)]
+    return agent::invalidate_resolution(cfg);
+    // No agent can run.
+    #[cfg(not(unix))]
     Ok(utils::ExitCode(0))
 }
 
+fn override_list(cfg: &Cfg<'_>, clean: bool, output: OutputFormat) -> Result<utils::ExitCode> {
+    if clean {
+        let removed = cfg
+            .settings_file
+            .with_mut(|s| Ok(s.remove_stale_overrides()))?;
+        for key in removed {
+            info!("override toolchain for '{key}' removed");
+        }
+    }
+    match output {
+        OutputFormat::Human => handle_epipe(common::list_overrides(cfg)),
+        OutputFormat::Json(version) => json::list_overrides(cfg, version),
+    }
+}
+
 fn override_remove(
     cfg: &Cfg<'_>,
     path: Option<&Path>,
     nonexistent: bool,
 ) -> Result<utils::ExitCode> {
     let paths = if nonexistent {
-        let list: Vec<_> = cfg.settings_file.with(|s| {
-            Ok(s.overrides
-                .iter()
-                .filter_map(|(k, _)| {
-                    let path = Path::new(k);
-                    (!path.is_dir()).then(|| path.to_owned())
-                })
-                .collect())
-        })?;
+        let list: Vec<_> = cfg
+            .settings_file
+            .with(|s| Ok(s.stale_overrides().into_iter().map(PathBuf::from).collect()))?;
         if list.is_empty() {
             info!("no nonexistent paths detected");
         }
@@ -1494,12 +3501,43 @@ async fn doc(
     cfg: &Cfg<'_>,
     path_only: bool,
     toolchain: Option<PartialToolchainDesc>,
+    target: Option<String>,
     mut topic: Option<&str>,
     doc_page: &DocPage,
 ) -> Result<utils::ExitCode> {
     let toolchain = cfg.toolchain_from_partial(toolchain)?;
 
-    if let Ok(distributable) = DistributableToolchain::try_from(&toolchain) {
+    // Targets without a standard library only have `core` and `alloc`,
+    // whose docs are the same for every target, so they can be read from
+    // the docs of another target.
+    let mut no_std = None;
+    if let Some(target) = target.map(TargetTriple::new) {
+        let doc_sets = match DistributableToolchain::try_from(&toolchain) {
+            Ok(distributable) => Some(distributable.doc_sets()?),
+            Err(_) => None,
+        };
+        let has_docs = doc_sets.as_ref().is_none_or(|sets| sets.contains(&target));
+        let any_docs = doc_sets.as_ref().is_none_or(|sets| !sets.is_empty());
+        if toolchain.has_std(&target) == Some(false) && any_docs {
+            no_std = Some(target);
+        } else if let Some(sets) = doc_sets.filter(|_| !has_docs) {
+            let installed = match &sets[..] {
+                [] => "no documentation is installed".to_owned(),
+                sets => format!(
+                    "documentation is installed for '{}'",
+                    sets.iter().join("', '")
+                ),
+            };
+            info!(
+                "To install, try `rustup component add --toolchain {} --target {target} rust-docs`",
+                toolchain.name()
+            );
+            bail!(
+                "no documentation for target '{target}' in toolchain '{}'; {installed}",
+                toolchain.name()
+            );
+        }
+    } else if let Ok(distributable) = DistributableToolchain::try_from(&toolchain) {
         if let [_] = distributable
             .components()?
             .into_iter()
@@ -1535,12 +3573,22 @@ async fn doc(
             let doc_path = topical_doc::local_path(&toolchain.doc_path("").unwrap(), topic)?;
             (Cow::Owned(doc_path), None)
         }
+        (None, None) if no_std.is_some() => {
+            topic = Some("core");
+            (Cow::Borrowed(Path::new("core/index.html")), None)
+        }
         (None, name) => {
             topic = name;
             let doc_path = doc_page.path().unwrap_or(Path::new("index.html"));
             (Cow::Borrowed(doc_path), None)
         }
     };
+    if let Some(target) = &no_std {
+        if doc_path.starts_with("std") {
+            bail!("target '{target}' has no standard library; its docs are those of `core` and `alloc`");
+        }
+        info!("target '{target}' has no standard library, so its `core` and `alloc` docs are those of every target");
+    }
 
     if path_only {
         let doc_path = toolchain.doc_path(&doc_path)?;
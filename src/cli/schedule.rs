@@ -0,0 +1,510 @@
+//! Periodic `rustup update` runs through the platform's own scheduler.
+//!
+//! `rustup schedule enable-auto-update` installs a systemd user timer on
+//! Linux, a launchd agent on macOS and a Task Scheduler task on Windows, each
+//! running `rustup --quiet update`. The job itself is the source of truth:
+//! `status` and `disable` inspect what is installed rather than a setting.
+
+use std::env::consts::EXE_SUFFIX;
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+use std::process::Command;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+use anyhow::{bail, Context};
+use clap::{builder::PossibleValue, ValueEnum};
+use itertools::Itertools;
+use tracing::info;
+
+use crate::{process::Process, utils};
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Interval {
+    Daily,
+    #[default]
+    Weekly,
+    Monthly,
+}
+
+impl Interval {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Daily => "daily",
+            Self::Weekly => "weekly",
+            Self::Monthly => "monthly",
+        }
+    }
+}
+
+impl ValueEnum for Interval {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Daily, Self::Weekly, Self::Monthly]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.as_str()))
+    }
+
+    fn from_str(input: &str, _: bool) -> Result<Self, String> {
+        <Self as FromStr>::from_str(input).map_err(|e| e.to_string())
+    }
+}
+
+impl FromStr for Interval {
+    type Err = anyhow::Error;
+
+    fn from_str(interval: &str) -> Result<Self> {
+        match interval {
+            "daily" => Ok(Self::Daily),
+            "weekly" => Ok(Self::Weekly),
+            "monthly" => Ok(Self::Monthly),
+            _ => Err(anyhow!(
+                "unknown update interval: '{}'; valid intervals are {}",
+                interval,
+                Self::value_variants().iter().join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Everything a scheduled job needs to run the same rustup as the user.
+struct Job {
+    rustup: PathBuf,
+    rustup_home: PathBuf,
+    cargo_home: PathBuf,
+}
+
+impl Job {
+    fn new(process: &Process) -> Result<Self> {
+        let cargo_home = process.cargo_home()?;
+        Ok(Self {
+            rustup: cargo_home.join(format!("bin/rustup{EXE_SUFFIX}")),
+            rustup_home: process.rustup_home()?,
+            cargo_home,
+        })
+    }
+}
+
+pub(crate) fn enable(process: &Process, interval: Interval) -> Result<utils::ExitCode> {
+    let job = Job::new(process)?;
+    utils::assert_is_file(&job.rustup)?;
+    let location = platform::install(process, &job, interval)?;
+    info!("scheduled `rustup update` to run {interval} ({location})");
+    Ok(utils::ExitCode(0))
+}
+
+pub(crate) fn status(process: &Process) -> Result<utils::ExitCode> {
+    let stdout = process.stdout();
+    let mut stdout = stdout.lock();
+    match platform::installed(process)? {
+        Some((Some(interval), location)) => {
+            writeln!(stdout, "automatic updates run {interval} ({location})")?
+        }
+        Some((None, location)) => writeln!(stdout, "automatic updates are scheduled ({location})")?,
+        None => writeln!(stdout, "automatic updates are not scheduled")?,
+    }
+    Ok(utils::ExitCode(0))
+}
+
+pub(crate) fn disable(process: &Process) -> Result<utils::ExitCode> {
+    if platform::installed(process)?.is_none() {
+        info!("automatic updates are not scheduled");
+        return Ok(utils::ExitCode(0));
+    }
+    platform::uninstall(process)?;
+    info!("automatic updates are no longer scheduled");
+    Ok(utils::ExitCode(0))
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to run `{program}`"))?;
+    if !output.status.success() {
+        bail!(
+            "`{program} {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::path::PathBuf;
+
+    use anyhow::{anyhow, Result};
+    use tracing::warn;
+
+    use super::{run, Interval, Job};
+    use crate::{
+        process::Process,
+        utils::{self, Notification},
+    };
+
+    const SERVICE: &str = "rustup-update.service";
+    const TIMER: &str = "rustup-update.timer";
+
+    fn unit_dir(process: &Process) -> Result<PathBuf> {
+        let config = match process.var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => process
+                .home_dir()
+                .ok_or_else(|| anyhow!("unable to find the home directory"))?
+                .join(".config"),
+        };
+        Ok(config.join("systemd/user"))
+    }
+
+    pub(super) fn install(process: &Process, job: &Job, interval: Interval) -> Result<String> {
+        let dir = unit_dir(process)?;
+        utils::ensure_dir_exists("systemd user units", &dir, &|_: Notification<'_>| ())?;
+        utils::write_file("systemd service", &dir.join(SERVICE), &render_service(job))?;
+        let timer = dir.join(TIMER);
+        utils::write_file("systemd timer", &timer, &render_timer(interval))?;
+        run("systemctl", &["--user", "daemon-reload"])?;
+        run("systemctl", &["--user", "enable", "--now", TIMER])?;
+        Ok(format!("systemd timer '{}'", timer.display()))
+    }
+
+    pub(super) fn installed(process: &Process) -> Result<Option<(Option<Interval>, String)>> {
+        let timer = unit_dir(process)?.join(TIMER);
+        if !utils::is_file(&timer) {
+            return Ok(None);
+        }
+        let interval = parse_timer(&utils::read_file("systemd timer", &timer)?);
+        Ok(Some((
+            interval,
+            format!("systemd timer '{}'", timer.display()),
+        )))
+    }
+
+    pub(super) fn uninstall(process: &Process) -> Result<()> {
+        if let Err(e) = run("systemctl", &["--user", "disable", "--now", TIMER]) {
+            warn!("{e:#}");
+        }
+        let dir = unit_dir(process)?;
+        utils::ensure_file_removed("systemd timer", &dir.join(TIMER))?;
+        utils::ensure_file_removed("systemd service", &dir.join(SERVICE))?;
+        if let Err(e) = run("systemctl", &["--user", "daemon-reload"]) {
+            warn!("{e:#}");
+        }
+        Ok(())
+    }
+
+    /// Quotes a value for a systemd unit file, escaping specifiers too.
+    fn quote(value: &str) -> String {
+        let value = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%");
+        format!("\"{value}\"")
+    }
+
+    pub(super) fn render_service(job: &Job) -> String {
+        format!(
+            "# Installed by `rustup schedule enable-auto-update`.
+[Unit]
+Description=Update Rust toolchains with rustup
+
+[Service]
+Type=oneshot
+Environment={}
+Environment={}
+ExecStart={} --quiet update
+",
+            quote(&format!("RUSTUP_HOME={}", job.rustup_home.display())),
+            quote(&format!("CARGO_HOME={}", job.cargo_home.display())),
+            quote(&job.rustup.to_string_lossy()),
+        )
+    }
+
+    pub(super) fn render_timer(interval: Interval) -> String {
+        format!(
+            "# Installed by `rustup schedule enable-auto-update`.
+[Unit]
+Description=Periodically update Rust toolchains with rustup
+
+[Timer]
+OnCalendar={interval}
+Persistent=true
+RandomizedDelaySec=1h
+
+[Install]
+WantedBy=timers.target
+"
+        )
+    }
+
+    pub(super) fn parse_timer(timer: &str) -> Option<Interval> {
+        timer
+            .lines()
+            .find_map(|l| l.trim().strip_prefix("OnCalendar="))
+            .and_then(|i| i.trim().parse().ok())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::path::{Path, PathBuf};
+
+    use anyhow::{anyhow, Result};
+    use tracing::warn;
+
+    use super::{run, Interval, Job};
+    use crate::{
+        process::Process,
+        utils::{self, Notification},
+    };
+
+    const LABEL: &str = "org.rust-lang.rustup.update";
+
+    fn plist_path(process: &Process) -> Result<PathBuf> {
+        Ok(process
+            .home_dir()
+            .ok_or_else(|| anyhow!("unable to find the home directory"))?
+            .join(format!("Library/LaunchAgents/{LABEL}.plist")))
+    }
+
+    pub(super) fn install(process: &Process, job: &Job, interval: Interval) -> Result<String> {
+        let plist = plist_path(process)?;
+        if let Some(dir) = plist.parent() {
+            utils::ensure_dir_exists("launch agents", dir, &|_: Notification<'_>| ())?;
+        }
+        let plist_str = plist.to_string_lossy();
+        // Loading an agent that is already loaded fails, so replace it.
+        let _ = run("launchctl", &["unload", &plist_str]);
+        utils::write_file("launch agent", &plist, &render_plist(job, interval))?;
+        run("launchctl", &["load", "-w", &plist_str])?;
+        Ok(format!("launch agent '{}'", plist.display()))
+    }
+
+    pub(super) fn installed(process: &Process) -> Result<Option<(Option<Interval>, String)>> {
+        let plist = plist_path(process)?;
+        if !utils::is_file(&plist) {
+            return Ok(None);
+        }
+        let interval = parse_plist(&utils::read_file("launch agent", &plist)?);
+        Ok(Some((
+            interval,
+            format!("launch agent '{}'", plist.display()),
+        )))
+    }
+
+    pub(super) fn uninstall(process: &Process) -> Result<()> {
+        let plist = plist_path(process)?;
+        if let Err(e) = run("launchctl", &["unload", "-w", &plist.to_string_lossy()]) {
+            warn!("{e:#}");
+        }
+        utils::ensure_file_removed("launch agent", &plist)
+    }
+
+    fn escape(path: &Path) -> String {
+        path.to_string_lossy()
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    pub(super) fn render_plist(job: &Job, interval: Interval) -> String {
+        let calendar = match interval {
+            Interval::Daily => "",
+            Interval::Weekly => "\n        <key>Weekday</key>\n        <integer>1</integer>",
+            Interval::Monthly => "\n        <key>Day</key>\n        <integer>1</integer>",
+        };
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<!-- Installed by `rustup schedule enable-auto-update`. -->
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{LABEL}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>--quiet</string>
+        <string>update</string>
+    </array>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>RUSTUP_HOME</key>
+        <string>{}</string>
+        <key>CARGO_HOME</key>
+        <string>{}</string>
+    </dict>
+    <key>StartCalendarInterval</key>
+    <dict>{calendar}
+        <key>Hour</key>
+        <integer>10</integer>
+        <key>Minute</key>
+        <integer>0</integer>
+    </dict>
+</dict>
+</plist>
+"#,
+            escape(&job.rustup),
+            escape(&job.rustup_home),
+            escape(&job.cargo_home),
+        )
+    }
+
+    pub(super) fn parse_plist(plist: &str) -> Option<Interval> {
+        let (_, calendar) = plist.split_once("<key>StartCalendarInterval</key>")?;
+        Some(if calendar.contains("<key>Weekday</key>") {
+            Interval::Weekly
+        } else if calendar.contains("<key>Day</key>") {
+            Interval::Monthly
+        } else {
+            Interval::Daily
+        })
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::process::{Command, Stdio};
+
+    use anyhow::Result;
+
+    use super::{run, Interval, Job};
+    use crate::process::Process;
+
+    const TASK: &str = "rustup-update";
+
+    pub(super) fn install(_: &Process, job: &Job, interval: Interval) -> Result<String> {
+        let schedule = match interval {
+            Interval::Daily => "DAILY",
+            Interval::Weekly => "WEEKLY",
+            Interval::Monthly => "MONTHLY",
+        };
+        let command = render_command(job);
+        run(
+            "schtasks",
+            &[
+                "/Create", "/F", "/TN", TASK, "/SC", schedule, "/ST", "10:00", "/TR", &command,
+            ],
+        )?;
+        Ok(format!("scheduled task '{TASK}'"))
+    }
+
+    /// A task can't be given an environment, so `cmd` sets it before running
+    /// rustup. With `/S`, `cmd` only strips the outermost quotes.
+    pub(super) fn render_command(job: &Job) -> String {
+        format!(
+            "cmd.exe /S /C \"set \"RUSTUP_HOME={}\"&& set \"CARGO_HOME={}\"&& \"{}\" --quiet update\"",
+            job.rustup_home.display(),
+            job.cargo_home.display(),
+            job.rustup.display(),
+        )
+    }
+
+    pub(super) fn installed(_: &Process) -> Result<Option<(Option<Interval>, String)>> {
+        // The output of `schtasks /Query` is localized, so the interval is
+        // not reported.
+        let found = Command::new("schtasks")
+            .args(["/Query", "/TN", TASK])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success());
+        Ok(found.then(|| (None, format!("scheduled task '{TASK}'"))))
+    }
+
+    pub(super) fn uninstall(_: &Process) -> Result<()> {
+        run("schtasks", &["/Delete", "/F", "/TN", TASK])
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    use anyhow::{bail, Result};
+
+    use super::{Interval, Job};
+    use crate::process::Process;
+
+    pub(super) fn install(_: &Process, _: &Job, _: Interval) -> Result<String> {
+        bail!("scheduling automatic updates is not supported on this platform")
+    }
+
+    pub(super) fn installed(_: &Process) -> Result<Option<(Option<Interval>, String)>> {
+        Ok(None)
+    }
+
+    pub(super) fn uninstall(_: &Process) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+    fn job() -> Job {
+        Job {
+            rustup: PathBuf::from("/home/ferris/.cargo/bin/rustup"),
+            rustup_home: PathBuf::from("/home/ferris/.rustup"),
+            cargo_home: PathBuf::from("/home/ferris/.cargo"),
+        }
+    }
+
+    #[test]
+    fn interval_roundtrip() {
+        for interval in Interval::value_variants() {
+            assert_eq!(
+                <Interval as FromStr>::from_str(interval.as_str()).unwrap(),
+                *interval
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn systemd_units() {
+        let service = platform::render_service(&job());
+        assert!(service.contains("ExecStart=\"/home/ferris/.cargo/bin/rustup\" --quiet update\n"));
+        assert!(service.contains("Environment=\"RUSTUP_HOME=/home/ferris/.rustup\"\n"));
+        for interval in Interval::value_variants() {
+            let timer = platform::render_timer(*interval);
+            assert_eq!(platform::parse_timer(&timer), Some(*interval));
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "macos")]
+    fn launch_agent() {
+        for interval in Interval::value_variants() {
+            let plist = platform::render_plist(&job(), *interval);
+            assert!(plist.contains("<string>/home/ferris/.cargo/bin/rustup</string>"));
+            assert_eq!(platform::parse_plist(&plist), Some(*interval));
+        }
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn scheduled_task() {
+        assert_eq!(
+            platform::render_command(&job()),
+            "cmd.exe /S /C \"set \"RUSTUP_HOME=/home/ferris/.rustup\"&& \
+             set \"CARGO_HOME=/home/ferris/.cargo\"&& \
+             \"/home/ferris/.cargo/bin/rustup\" --quiet update\""
+        );
+    }
+}
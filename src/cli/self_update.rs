@@ -34,12 +34,12 @@ use std::borrow::Cow;
 use std::env::consts::EXE_SUFFIX;
 use std::fs;
 use std::io::Write;
-use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
 use std::process::Command;
 use std::str::FromStr;
 use std::{env, fmt};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use cfg_if::cfg_if;
 use clap::builder::PossibleValue;
 use clap::ValueEnum;
@@ -50,15 +50,18 @@ use tracing::{error, info, trace, warn};
 
 use crate::{
     cli::{
-        common::{self, ignorable_error, report_error, Confirm, PackageUpdate},
+        common::{self, report_error, Confirm, PackageUpdate},
+        doctor,
         errors::*,
         markdown::md,
+        messages::message,
     },
     config::Cfg,
     dist::{self, PartialToolchainDesc, Profile, TargetTriple, ToolchainDesc},
     errors::RustupError,
     install::UpdateStatus,
     process::{terminalsource, Process},
+    settings::{Settings, SettingsFile},
     toolchain::{
         DistributableToolchain, MaybeOfficialToolchainName, ResolvableToolchainName, Toolchain,
         ToolchainName,
@@ -70,6 +73,10 @@ use crate::{
 #[cfg(unix)]
 mod shell;
 
+mod system_rust;
+pub(crate) use system_rust::toggle_system;
+pub use system_rust::SystemRust;
+
 #[cfg(unix)]
 mod unix;
 #[cfg(unix)]
@@ -88,17 +95,25 @@ pub use windows::{get_path, RegistryGuard, RegistryValueId, USER_PATH};
 #[cfg(windows)]
 pub(crate) use windows::{run_update, self_replace};
 
-pub(crate) struct InstallOpts<'a> {
+pub(crate) struct InstallOpts {
     pub default_host_triple: Option<String>,
     pub default_toolchain: Option<MaybeOfficialToolchainName>,
     pub profile: Profile,
     pub no_modify_path: bool,
     pub no_update_toolchain: bool,
-    pub components: &'a [&'a str],
-    pub targets: &'a [&'a str],
+    pub components: Vec<String>,
+    pub targets: Vec<String>,
+    /// A Rust installation found in `PATH` that rustup didn't make.
+    pub system_rust: Option<SystemRust>,
 }
 
-impl InstallOpts<'_> {
+/// Components offered when customizing the installation.
+const CUSTOMIZE_COMPONENTS: &[&str] = &["clippy", "rustfmt", "rust-src", "rust-analyzer"];
+
+/// Targets offered when customizing the installation.
+const CUSTOMIZE_TARGETS: &[&str] = &["wasm32-unknown-unknown", "wasm32-wasip1"];
+
+impl InstallOpts {
     fn install(self, cfg: &mut Cfg<'_>) -> Result<Option<ToolchainDesc>> {
         let Self {
             default_host_triple,
@@ -108,9 +123,13 @@ impl InstallOpts<'_> {
             no_update_toolchain,
             components,
             targets,
+            system_rust,
         } = self;
 
         cfg.set_profile(profile)?;
+        if let Some(system_rust) = system_rust {
+            cfg.set_system_rust(system_rust)?;
+        }
 
         if let Some(default_host_triple) = &default_host_triple {
             // Set host triple now as it will affect resolution of toolchain_str
@@ -182,14 +201,14 @@ impl InstallOpts<'_> {
     fn customize(&mut self, process: &Process) -> Result<()> {
         writeln!(
             process.stdout().lock(),
-            "I'm going to ask you the value of each of these installation options.\n\
-         You may simply press the Enter key to leave unchanged."
+            "{}",
+            message(process, "setup-customize-intro", &[])
         )?;
 
         writeln!(process.stdout().lock())?;
 
         self.default_host_triple = Some(common::question_str(
-            "Default host triple?",
+            &message(process, "setup-ask-host", &[]),
             &self
                 .default_host_triple
                 .take()
@@ -198,7 +217,7 @@ impl InstallOpts<'_> {
         )?);
 
         self.default_toolchain = Some(MaybeOfficialToolchainName::try_from(common::question_str(
-            "Default toolchain? (stable/beta/nightly/none)",
+            &message(process, "setup-ask-toolchain", &[]),
             &self
                 .default_toolchain
                 .as_ref()
@@ -208,16 +227,67 @@ impl InstallOpts<'_> {
         )?)?);
 
         self.profile = <Profile as FromStr>::from_str(&common::question_str(
-            &format!(
-                "Profile (which tools and data to install)? ({})",
-                Profile::value_variants().iter().join("/"),
+            &message(
+                process,
+                "setup-ask-profile",
+                &[(
+                    "profiles",
+                    Profile::value_variants().iter().join("/").into(),
+                )],
             ),
             self.profile.as_str(),
             process,
         )?)?;
 
-        self.no_modify_path =
-            !common::question_bool("Modify PATH variable?", !self.no_modify_path, process)?;
+        let items = CUSTOMIZE_COMPONENTS
+            .iter()
+            .map(|c| c.to_string())
+            .chain(
+                CUSTOMIZE_TARGETS
+                    .iter()
+                    .map(|t| message(process, "setup-extras-target", &[("target", (*t).into())])),
+            )
+            .collect::<Vec<_>>();
+        let mut checked = CUSTOMIZE_COMPONENTS
+            .iter()
+            .map(|c| self.components.iter().any(|s| s == c))
+            .chain(
+                CUSTOMIZE_TARGETS
+                    .iter()
+                    .map(|t| self.targets.iter().any(|s| s == t)),
+            )
+            .collect::<Vec<_>>();
+        common::question_checklist(
+            &message(process, "setup-ask-extras", &[]),
+            &items,
+            &mut checked,
+            process,
+        )?;
+        let (components, targets) = checked.split_at(CUSTOMIZE_COMPONENTS.len());
+        self.components
+            .retain(|c| !CUSTOMIZE_COMPONENTS.contains(&c.as_str()));
+        self.components.extend(
+            CUSTOMIZE_COMPONENTS
+                .iter()
+                .zip(components)
+                .filter(|(_, checked)| **checked)
+                .map(|(c, _)| c.to_string()),
+        );
+        self.targets
+            .retain(|t| !CUSTOMIZE_TARGETS.contains(&t.as_str()));
+        self.targets.extend(
+            CUSTOMIZE_TARGETS
+                .iter()
+                .zip(targets)
+                .filter(|(_, checked)| **checked)
+                .map(|(t, _)| t.to_string()),
+        );
+
+        self.no_modify_path = !common::question_bool(
+            &message(process, "setup-ask-modify-path", &[]),
+            !self.no_modify_path,
+            process,
+        )?;
 
         Ok(())
     }
@@ -303,156 +373,174 @@ impl std::fmt::Display for SelfUpdateMode {
     }
 }
 
-// The big installation messages. These are macros because the first
-// argument of format! needs to be a literal.
-
-macro_rules! pre_install_msg_template {
-    ($platform_msg:literal) => {
-        concat!(
-            r"
-# Welcome to Rust!
-
-This will download and install the official compiler for the Rust
-programming language, and its package manager, Cargo.
-
-Rustup metadata and toolchains will be installed into the Rustup
-home directory, located at:
-
-    {rustup_home}
-
-This can be modified with the RUSTUP_HOME environment variable.
-
-The Cargo home directory is located at:
-
-    {cargo_home}
-
-This can be modified with the CARGO_HOME environment variable.
-
-The `cargo`, `rustc`, `rustup` and other commands will be added to
-Cargo's bin directory, located at:
-
-    {cargo_home_bin}
+/// How proxies are linked to the rustup binary.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProxyLinks {
+    /// Symbolic links where they can be made, and hard links elsewhere.
+    #[default]
+    Auto,
+    /// Relative symbolic links, which survive moving `CARGO_HOME`.
+    Symlink,
+    Hardlink,
+    /// Copies of the rustup binary, for file systems that can't link, such
+    /// as exFAT. Every update of rustup copies it again.
+    Copy,
+}
 
-",
-            $platform_msg,
-            r#"
+impl ProxyLinks {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Symlink => "symlink",
+            Self::Hardlink => "hardlink",
+            Self::Copy => "copy",
+        }
+    }
 
-You can uninstall at any time with `rustup self uninstall` and
-these changes will be reverted.
-"#
-        )
-    };
-}
+    /// Records that proxies are copies, once linking them failed.
+    fn remember(&self, process: &Process) -> Result<()> {
+        let rustup_home = process.rustup_home()?;
+        utils::ensure_dir_exists("rustup_home", &rustup_home, &|_: Notification<'_>| {})?;
+        SettingsFile::new(rustup_home.join("settings.toml")).with_mut(|s| {
+            s.proxy_links = Some(*self);
+            Ok(())
+        })
+    }
 
-#[cfg(not(windows))]
-macro_rules! pre_install_msg_unix {
-    () => {
-        pre_install_msg_template!(
-            "This path will then be added to your `PATH` environment variable by
-modifying the profile file{plural} located at:
+    /// The setting of the rustup home.
+    fn of(process: &Process) -> Self {
+        proxy_settings(process)
+            .and_then(|s| s.proxy_links)
+            .unwrap_or_default()
+    }
 
-{rcfiles}"
-        )
-    };
-}
+    /// Whether the existing link to rustup at `path` is of this kind.
+    fn is_kind_of(&self, rustup_path: &Path, path: &Path) -> bool {
+        match self {
+            Self::Auto => true,
+            Self::Symlink => {
+                fs::read_link(path).is_ok_and(|target| target == symlink_target(rustup_path, path))
+            }
+            Self::Hardlink => fs::symlink_metadata(path).is_ok_and(|m| !m.file_type().is_symlink()),
+            Self::Copy => false,
+        }
+    }
 
-#[cfg(windows)]
-macro_rules! pre_install_msg_win {
-    () => {
-        pre_install_msg_template!(
-            "This path will then be added to your `PATH` environment variable by
-modifying the `HKEY_CURRENT_USER/Environment/PATH` registry key."
-        )
-    };
-}
+    /// Links `tool_path` to rustup, falling back to copying rustup there
+    /// for good if no kind was chosen and the file system can't link.
+    fn link_or_copy(
+        &mut self,
+        rustup_path: &Path,
+        tool_path: &Path,
+        process: &Process,
+    ) -> Result<()> {
+        match self.link(rustup_path, tool_path) {
+            Err(e) if *self == Self::Auto => {
+                warn!("could not link proxies to rustup, so they are copies of it instead: {e:#}");
+                *self = Self::Copy;
+                self.remember(process)?;
+                self.link(rustup_path, tool_path)
+            }
+            result => result,
+        }
+    }
 
-macro_rules! pre_install_msg_no_modify_path {
-    () => {
-        pre_install_msg_template!(
-            "This path needs to be in your `PATH` environment variable,
-but will not be added automatically."
-        )
-    };
+    fn link(&self, rustup_path: &Path, tool_path: &Path) -> Result<()> {
+        if *self == Self::Auto {
+            return utils::symlink_or_hardlink_file(rustup_path, tool_path);
+        }
+        // Made aside and renamed over the old proxy, which is left alone if
+        // the new one can't be made, as when Windows refuses symbolic links.
+        let mut tmp = tool_path.as_os_str().to_owned();
+        tmp.push(".rustup-tmp");
+        let tmp = PathBuf::from(tmp);
+        let _ = fs::remove_file(&tmp);
+        let made = match self {
+            Self::Auto => unreachable!(),
+            Self::Symlink => {
+                let linked = utils::symlink_file(symlink_target(rustup_path, tool_path), &tmp);
+                #[cfg(windows)]
+                let linked = linked.context(
+                    "symbolic links can only be made with Developer Mode on or as an administrator",
+                );
+                linked
+            }
+            Self::Hardlink => utils::hardlink_file(rustup_path, &tmp),
+            Self::Copy => utils::copy_file(rustup_path, &tmp),
+        };
+        let renamed =
+            made.and_then(|()| utils::rename("proxy", &tmp, tool_path, &|_: Notification<'_>| {}));
+        if renamed.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        renamed
+    }
 }
 
-#[cfg(not(windows))]
-macro_rules! post_install_msg_unix_source_env {
-    () => {
-        r#"To configure your current shell, you need to source
-the corresponding `env` file under {cargo_home}.
-
-This is usually done by running one of the following (note the leading DOT):
-    . "{cargo_home}/env"            # For sh/bash/zsh/ash/dash/pdksh
-    source "{cargo_home}/env.fish"  # For fish
-    source "{cargo_home}/env.nu"    # For nushell
-"#
-    };
+/// What a symbolic link to rustup at `tool_path` points to: rustup by name
+/// next to it, which survives moving `CARGO_HOME`, or else its full path.
+fn symlink_target<'a>(rustup_path: &'a Path, tool_path: &Path) -> &'a Path {
+    match tool_path.parent() == rustup_path.parent() {
+        true => Path::new(rustup_path.file_name().unwrap()),
+        false => rustup_path,
+    }
 }
 
-#[cfg(not(windows))]
-macro_rules! post_install_msg_unix {
-    () => {
-        concat!(
-            r"# Rust is installed now. Great!
-
-To get started you may need to restart your current shell.
-This would reload your `PATH` environment variable to include
-Cargo's bin directory ({cargo_home}/bin).
-
-",
-            post_install_msg_unix_source_env!(),
-        )
-    };
+/// The settings of the rustup home, read without a `Cfg` since proxies are
+/// also linked while installing rustup.
+fn proxy_settings(process: &Process) -> Option<Settings> {
+    let rustup_home = process.rustup_home().ok()?;
+    utils::read_file("settings", &rustup_home.join("settings.toml"))
+        .and_then(|content| Settings::parse(&content))
+        .ok()
 }
 
-#[cfg(windows)]
-macro_rules! post_install_msg_win {
-    () => {
-        r"# Rust is installed now. Great!
-
-
-To get started you may need to restart your current shell.
-This would reload its `PATH` environment variable to include
-Cargo's bin directory ({cargo_home}\\bin).
-"
-    };
+/// The directory the proxies are in: the one `rustup set proxy-dir` chose,
+/// or `CARGO_HOME/bin`, next to rustup.
+pub(crate) fn proxy_dir(process: &Process) -> Result<PathBuf> {
+    match proxy_settings(process).and_then(|s| s.proxy_dir) {
+        Some(dir) => Ok(dir),
+        None => Ok(process.cargo_home()?.join("bin")),
+    }
 }
 
-#[cfg(not(windows))]
-macro_rules! post_install_msg_unix_no_modify_path {
-    () => {
-        concat!(
-            r"# Rust is installed now. Great!
+impl ValueEnum for ProxyLinks {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Auto, Self::Symlink, Self::Hardlink, Self::Copy]
+    }
 
-To get started you need Cargo's bin directory ({cargo_home}/bin) in your `PATH`
-environment variable. This has not been done automatically.
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.as_str()))
+    }
 
-",
-            post_install_msg_unix_source_env!(),
-        )
-    };
+    fn from_str(input: &str, _: bool) -> Result<Self, String> {
+        <Self as FromStr>::from_str(input).map_err(|e| e.to_string())
+    }
 }
 
-#[cfg(windows)]
-macro_rules! post_install_msg_win_no_modify_path {
-    () => {
-        r"# Rust is installed now. Great!
+impl FromStr for ProxyLinks {
+    type Err = anyhow::Error;
 
-To get started you need Cargo's bin directory ({cargo_home}\\bin) in your `PATH`
-environment variable. This has not been done automatically.
-"
-    };
+    fn from_str(links: &str) -> Result<Self> {
+        match links {
+            "auto" => Ok(Self::Auto),
+            "symlink" => Ok(Self::Symlink),
+            "hardlink" => Ok(Self::Hardlink),
+            "copy" => Ok(Self::Copy),
+            _ => Err(anyhow!(
+                "unknown kind of proxy links: '{}'; valid kinds are {}",
+                links,
+                Self::value_variants().iter().join(", ")
+            )),
+        }
+    }
 }
 
-macro_rules! pre_uninstall_msg {
-    () => {
-        r"# Thanks for hacking in Rust!
-
-This will uninstall all Rust toolchains and data, and remove
-`{cargo_home}/bin` from your `PATH` environment variable.
-
-"
-    };
+impl std::fmt::Display for ProxyLinks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 static DEFAULT_UPDATE_ROOT: &str = "https://static.rust-lang.org/rustup";
@@ -493,7 +581,7 @@ pub(crate) async fn install(
     current_dir: PathBuf,
     no_prompt: bool,
     quiet: bool,
-    mut opts: InstallOpts<'_>,
+    mut opts: InstallOpts,
     process: &Process,
 ) -> Result<utils::ExitCode> {
     #[cfg_attr(not(unix), allow(unused_mut))]
@@ -512,7 +600,10 @@ pub(crate) async fn install(
         .var_os("RUSTUP_INIT_SKIP_EXISTENCE_CHECKS")
         .is_none_or(|s| s != "yes")
     {
-        check_existence_of_rustc_or_cargo_in_path(no_prompt, process)?;
+        if !system_rust::check(&mut opts, no_prompt, process)? {
+            info!("{}", message(process, "setup-aborted", &[]));
+            return Ok(utils::ExitCode(1));
+        }
         check_existence_of_settings_file(process)?;
     }
 
@@ -535,7 +626,7 @@ pub(crate) async fn install(
             md(&mut term, current_install_opts(&opts, process));
             match common::confirm_advanced(customized_install, process)? {
                 Confirm::No => {
-                    info!("aborting installation");
+                    info!("{}", message(process, "setup-aborted", &[]));
                     return Ok(utils::ExitCode(0));
                 }
                 Confirm::Yes => {
@@ -567,25 +658,26 @@ pub(crate) async fn install(
 
     let cargo_home = canonical_cargo_home(process)?;
     #[cfg(windows)]
-    let cargo_home = cargo_home.replace('\\', r"\\");
-    #[cfg(windows)]
-    let msg = if no_modify_path {
-        format!(
-            post_install_msg_win_no_modify_path!(),
-            cargo_home = cargo_home
-        )
-    } else {
-        format!(post_install_msg_win!(), cargo_home = cargo_home)
-    };
+    let cargo_bin = format!(r"{}\\bin", cargo_home.replace('\\', r"\\"));
     #[cfg(not(windows))]
-    let msg = if no_modify_path {
-        format!(
-            post_install_msg_unix_no_modify_path!(),
-            cargo_home = cargo_home
-        )
-    } else {
-        format!(post_install_msg_unix!(), cargo_home = cargo_home)
+    let cargo_bin = format!("{cargo_home}/bin");
+    let id = match no_modify_path {
+        true => "setup-done-no-modify-path",
+        false => "setup-done",
     };
+    let mut msg = message(process, id, &[("cargo_bin", cargo_bin.into())]);
+    msg.push('\n');
+    #[cfg(not(windows))]
+    {
+        let cargo_home = cargo_home.to_string();
+        msg.push('\n');
+        msg += &message(
+            process,
+            "setup-source-env",
+            &[("cargo_home", cargo_home.into())],
+        );
+        msg.push('\n');
+    }
     md(&mut term, msg);
 
     #[cfg(windows)]
@@ -599,52 +691,6 @@ pub(crate) async fn install(
     Ok(exit_code)
 }
 
-fn rustc_or_cargo_exists_in_path(process: &Process) -> Result<()> {
-    // Ignore rustc and cargo if present in $HOME/.cargo/bin or a few other directories
-    #[allow(clippy::ptr_arg)]
-    fn ignore_paths(path: &PathBuf) -> bool {
-        !path
-            .components()
-            .any(|c| c == Component::Normal(".cargo".as_ref()))
-    }
-
-    if let Some(paths) = process.var_os("PATH") {
-        let paths = env::split_paths(&paths).filter(ignore_paths);
-
-        for path in paths {
-            let rustc = path.join(format!("rustc{EXE_SUFFIX}"));
-            let cargo = path.join(format!("cargo{EXE_SUFFIX}"));
-
-            if rustc.exists() || cargo.exists() {
-                return Err(anyhow!("{}", path.to_str().unwrap().to_owned()));
-            }
-        }
-    }
-    Ok(())
-}
-
-fn check_existence_of_rustc_or_cargo_in_path(no_prompt: bool, process: &Process) -> Result<()> {
-    // Only the test runner should set this
-    let skip_check = process.var_os("RUSTUP_INIT_SKIP_PATH_CHECK");
-
-    // Skip this if the environment variable is set
-    if skip_check == Some("yes".into()) {
-        return Ok(());
-    }
-
-    if let Err(path) = rustc_or_cargo_exists_in_path(process) {
-        warn!("It looks like you have an existing installation of Rust at:");
-        warn!("{}", path);
-        warn!("It is recommended that rustup be the primary Rust installation.");
-        warn!("Otherwise you may have confusion unless you are careful with your PATH.");
-        warn!("If you are sure that you want both rustup and your already installed Rust");
-        warn!("then please reply `y' or `yes' or set RUSTUP_INIT_SKIP_PATH_CHECK to yes");
-        warn!("or pass `-y' to ignore all ignorable checks.");
-        ignorable_error("cannot install while Rust is installed", no_prompt, process)?;
-    }
-    Ok(())
-}
-
 fn check_existence_of_settings_file(process: &Process) -> Result<()> {
     let rustup_dir = process.rustup_home()?;
     let settings_file_path = rustup_dir.join("settings.toml");
@@ -662,7 +708,9 @@ fn pre_install_msg(no_modify_path: bool, process: &Process) -> Result<String> {
     let cargo_home_bin = cargo_home.join("bin");
     let rustup_home = home::rustup_home()?;
 
-    if !no_modify_path {
+    let path_note = if no_modify_path {
+        message(process, "setup-path-unchanged", &[])
+    } else {
         // Brittle code warning: some duplication in unix::do_add_to_path
         #[cfg(not(windows))]
         {
@@ -670,54 +718,80 @@ fn pre_install_msg(no_modify_path: bool, process: &Process) -> Result<String> {
                 .flat_map(|sh| sh.update_rcs(process).into_iter())
                 .map(|rc| format!("    {}", rc.display()))
                 .collect::<Vec<_>>();
-            let plural = if rcfiles.len() > 1 { "s" } else { "" };
-            let rcfiles = rcfiles.join("\n");
-            Ok(format!(
-                pre_install_msg_unix!(),
-                cargo_home = cargo_home.display(),
-                cargo_home_bin = cargo_home_bin.display(),
-                plural = plural,
-                rcfiles = rcfiles,
-                rustup_home = rustup_home.display(),
-            ))
+            message(
+                process,
+                "setup-path-unix",
+                &[
+                    ("count", rcfiles.len().into()),
+                    ("rcfiles", rcfiles.join("\n").into()),
+                ],
+            )
         }
         #[cfg(windows)]
-        Ok(format!(
-            pre_install_msg_win!(),
-            cargo_home = cargo_home.display(),
-            cargo_home_bin = cargo_home_bin.display(),
-            rustup_home = rustup_home.display(),
-        ))
-    } else {
-        Ok(format!(
-            pre_install_msg_no_modify_path!(),
-            cargo_home = cargo_home.display(),
-            cargo_home_bin = cargo_home_bin.display(),
-            rustup_home = rustup_home.display(),
-        ))
-    }
-}
+        message(process, "setup-path-windows", &[])
+    };
 
-fn current_install_opts(opts: &InstallOpts<'_>, process: &Process) -> String {
-    format!(
-        r"Current installation options:
+    let msg = message(
+        process,
+        "setup-welcome",
+        &[
+            ("rustup_home", rustup_home.display().to_string().into()),
+            ("cargo_home", cargo_home.display().to_string().into()),
+            (
+                "cargo_home_bin",
+                cargo_home_bin.display().to_string().into(),
+            ),
+            ("path_note", path_note.into()),
+        ],
+    );
+    Ok(format!("\n{msg}\n"))
+}
 
-- ` `default host triple: `{}`
-- `   `default toolchain: `{}`
-- `             `profile: `{}`
-- modify PATH variable: `{}`
-",
-        opts.default_host_triple
-            .as_ref()
-            .map(TargetTriple::new)
-            .unwrap_or_else(|| TargetTriple::from_host_or_build(process)),
-        opts.default_toolchain
-            .as_ref()
-            .map(ToString::to_string)
-            .unwrap_or("stable (default)".into()),
-        opts.profile,
-        if !opts.no_modify_path { "yes" } else { "no" }
-    )
+fn current_install_opts(opts: &InstallOpts, process: &Process) -> String {
+    let host = opts
+        .default_host_triple
+        .as_ref()
+        .map(TargetTriple::new)
+        .unwrap_or_else(|| TargetTriple::from_host_or_build(process));
+    let toolchain = match &opts.default_toolchain {
+        Some(toolchain) => toolchain.to_string(),
+        None => message(
+            process,
+            "setup-default-toolchain",
+            &[("toolchain", "stable".into())],
+        ),
+    };
+    let modify_path = if !opts.no_modify_path { "yes" } else { "no" };
+    let mut msg = message(
+        process,
+        "setup-options",
+        &[
+            ("host", host.to_string().into()),
+            ("toolchain", toolchain.into()),
+            ("profile", opts.profile.to_string().into()),
+            ("modify_path", modify_path.into()),
+        ],
+    );
+    msg.push('\n');
+    if !opts.components.is_empty() {
+        let components = opts.components.join(", ");
+        msg += &message(
+            process,
+            "setup-options-components",
+            &[("components", components.into())],
+        );
+        msg.push('\n');
+    }
+    if !opts.targets.is_empty() {
+        let targets = opts.targets.join(", ");
+        msg += &message(
+            process,
+            "setup-options-targets",
+            &[("targets", targets.into())],
+        );
+        msg.push('\n');
+    }
+    msg
 }
 
 fn install_bins(process: &Process) -> Result<()> {
@@ -737,8 +811,21 @@ fn install_bins(process: &Process) -> Result<()> {
 }
 
 pub(crate) fn install_proxies(process: &Process) -> Result<()> {
-    let bin_path = process.cargo_home()?.join("bin");
-    let rustup_path = bin_path.join(format!("rustup{EXE_SUFFIX}"));
+    install_proxies_as(ProxyLinks::of(process), &proxy_dir(process)?, process)
+}
+
+/// Links the proxies in `bin_path` as `links` asks, before either is the
+/// setting.
+pub(crate) fn install_proxies_as(
+    mut links: ProxyLinks,
+    bin_path: &Path,
+    process: &Process,
+) -> Result<()> {
+    let rustup_path = process
+        .cargo_home()?
+        .join("bin")
+        .join(format!("rustup{EXE_SUFFIX}"));
+    utils::ensure_dir_exists("proxies", bin_path, &|_: Notification<'_>| {})?;
 
     let rustup = Handle::from_path(&rustup_path)?;
 
@@ -746,7 +833,8 @@ pub(crate) fn install_proxies(process: &Process) -> Result<()> {
     let mut link_afterwards = Vec::new();
 
     // Try to symlink all the Rust exes to the rustup exe. Some systems,
-    // like Windows, do not always support symlinks, so we fallback to hard links.
+    // like Windows, do not always support symlinks, so we fallback to hard links,
+    // and on file systems that support neither, such as exFAT, to copies.
     //
     // Note that this function may not be running in the context of a fresh
     // self update but rather as part of a normal update to fill in missing
@@ -759,7 +847,8 @@ pub(crate) fn install_proxies(process: &Process) -> Result<()> {
     // To avoid unnecessary errors from being returned here we use the
     // `same-file` crate and its `Handle` type to avoid clobbering hard links
     // that are already valid. If a hard link already points to the
-    // `rustup.exe` file then we leave it alone and move to the next one.
+    // `rustup.exe` file then we leave it alone and move to the next one,
+    // unless it isn't the kind of link `rustup set proxy-links` asks for.
     //
     // As yet one final caveat, when we're looking at handles for files we can't
     // actually delete files (they'll say they're deleted but they won't
@@ -770,7 +859,8 @@ pub(crate) fn install_proxies(process: &Process) -> Result<()> {
         let tool_path = bin_path.join(format!("{tool}{EXE_SUFFIX}"));
         if let Ok(handle) = Handle::from_path(&tool_path) {
             tool_handles.push(handle);
-            if rustup == *tool_handles.last().unwrap() {
+            if rustup == *tool_handles.last().unwrap() && links.is_kind_of(&rustup_path, &tool_path)
+            {
                 continue;
             }
         }
@@ -782,7 +872,7 @@ pub(crate) fn install_proxies(process: &Process) -> Result<()> {
         if let Ok(handle) = Handle::from_path(&tool_path) {
             // Like above, don't clobber anything that's already linked to
             // avoid extraneous errors from being returned.
-            if rustup == handle {
+            if rustup == handle && links.is_kind_of(&rustup_path, &tool_path) {
                 continue;
             }
 
@@ -796,35 +886,92 @@ pub(crate) fn install_proxies(process: &Process) -> Result<()> {
             //
             // If the file is managed by rustup it should be equivalent to some
             // previous file, and if it's not equivalent to anything then it's
-            // pretty likely that it needs to be dealt with manually.
-            if tool_handles.iter().all(|h| *h != handle) {
+            // pretty likely that it needs to be dealt with manually. Copies
+            // are only equivalent in content, to the copy that is `cargo`.
+            let cargo = bin_path.join(format!("cargo{EXE_SUFFIX}"));
+            if tool_handles.iter().all(|h| *h != handle)
+                && !(links == ProxyLinks::Copy && same_content(&cargo, &tool_path))
+            {
                 warn!("tool `{}` is already installed, remove it from `{}`, then run `rustup update` \
                        to have rustup manage this tool.",
                       tool, bin_path.display());
                 continue;
             }
         }
-        utils::symlink_or_hardlink_file(&rustup_path, &tool_path)?;
+        links.link_or_copy(&rustup_path, &tool_path, process)?;
     }
 
     drop(tool_handles);
     for path in link_afterwards {
-        utils::symlink_or_hardlink_file(&rustup_path, &path)?;
+        links.link_or_copy(&rustup_path, &path, process)?;
     }
 
     Ok(())
 }
 
+/// Moves the proxies to `dir`, or back to `CARGO_HOME/bin` without one.
+/// Only files that are rustup are removed from where they were, so that
+/// whatever else is there, such as a system `cargo`, is left alone.
+pub(crate) fn move_proxies(cfg: &Cfg<'_>, dir: Option<PathBuf>) -> Result<()> {
+    let process = cfg.process;
+    let bin_path = process.cargo_home()?.join("bin");
+    let rustup_path = bin_path.join(format!("rustup{EXE_SUFFIX}"));
+    let dir = match dir {
+        Some(dir) if dir.is_relative() => Some(process.current_dir()?.join(dir)),
+        dir => dir,
+    };
+    let new = dir.clone().unwrap_or_else(|| bin_path.clone());
+    let old = proxy_dir(process)?;
+    let same_dir = |a: &Path, b: &Path| a == b || same_file::is_same_file(a, b).unwrap_or(false);
+
+    for tool in TOOLS {
+        let tool_path = new.join(format!("{tool}{EXE_SUFFIX}"));
+        if tool_path.exists() && !is_proxy(&rustup_path, &tool_path) {
+            bail!(
+                "'{}' is already there and is not rustup; choose a directory for the proxies alone",
+                tool_path.display()
+            );
+        }
+    }
+
+    install_proxies_as(ProxyLinks::of(process), &new, process)?;
+    if !same_dir(&old, &new) {
+        for tool in TOOLS.iter().chain(DUP_TOOLS) {
+            let tool_path = old.join(format!("{tool}{EXE_SUFFIX}"));
+            if is_proxy(&rustup_path, &tool_path) {
+                utils::remove_file("proxy", &tool_path)?;
+            }
+        }
+    }
+    let dir = dir.filter(|dir| !same_dir(dir, &bin_path));
+    cfg.set_proxy_dir(dir, &new)
+}
+
+/// Whether `path` is rustup at `rustup_path`, linked or copied.
+fn is_proxy(rustup_path: &Path, path: &Path) -> bool {
+    same_file::is_same_file(rustup_path, path).unwrap_or(false) || same_content(rustup_path, path)
+}
+
+fn same_content(a: &Path, b: &Path) -> bool {
+    match (fs::read(a), fs::read(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
 async fn maybe_install_rust(
     current_dir: PathBuf,
     quiet: bool,
-    opts: InstallOpts<'_>,
+    opts: InstallOpts,
     process: &Process,
 ) -> Result<()> {
     install_bins(process)?;
 
     #[cfg(unix)]
-    unix::do_write_env_files(process)?;
+    unix::do_write_env_files(
+        opts.system_rust.as_ref().is_some_and(|s| !s.rustup_first),
+        process,
+    )?;
 
     if !opts.no_modify_path {
         do_add_to_path(process)?;
@@ -842,7 +989,9 @@ async fn maybe_install_rust(
 
     let mut cfg = common::set_globals(current_dir, quiet, process)?;
 
-    let (components, targets) = (opts.components, opts.targets);
+    let (components, targets) = (opts.components.clone(), opts.targets.clone());
+    let components = components.iter().map(|s| &**s).collect::<Vec<_>>();
+    let targets = targets.iter().map(|s| &**s).collect::<Vec<_>>();
     let toolchain = opts.install(&mut cfg)?;
     if let Some(ref desc) = toolchain {
         let status = if Toolchain::exists(&cfg, &desc.into())? {
@@ -854,14 +1003,14 @@ async fn maybe_install_rust(
             // For now, we error.
             let mut toolchain = DistributableToolchain::new(&cfg, desc.clone())?;
             toolchain
-                .update(components, targets, cfg.get_profile()?)
+                .update(&components, &targets, cfg.get_profile()?)
                 .await?
         } else {
             DistributableToolchain::install(
                 &cfg,
                 desc,
-                components,
-                targets,
+                &components,
+                &targets,
                 cfg.get_profile()?,
                 true,
             )
@@ -891,17 +1040,36 @@ pub(crate) fn uninstall(no_prompt: bool, process: &Process) -> Result<utils::Exi
 
     if !no_prompt {
         writeln!(process.stdout().lock())?;
-        let msg = format!(
-            pre_uninstall_msg!(),
-            cargo_home = canonical_cargo_home(process)?
+        let cargo_home = canonical_cargo_home(process)?.to_string();
+        let msg = message(
+            process,
+            "uninstall-welcome",
+            &[("cargo_home", cargo_home.into())],
         );
-        md(&mut process.stdout().terminal(process), msg);
-        if !common::confirm("\nContinue? (y/N)", false, process)? {
-            info!("aborting uninstallation");
+        md(
+            &mut process.stdout().terminal(process),
+            format!("{msg}\n\n"),
+        );
+        let question = format!("\n{}", message(process, "uninstall-confirm", &[]));
+        if !common::confirm(&question, false, process)? {
+            info!("{}", message(process, "uninstall-aborted", &[]));
             return Ok(utils::ExitCode(0));
         }
     }
 
+    // Proxies moved out of CARGO_HOME/bin, while the setting saying where
+    // they are is still there.
+    let proxy_dir = proxy_dir(process)?;
+    if !proxy_dir.starts_with(&cargo_home) {
+        let rustup_path = cargo_home.join(format!("bin/rustup{EXE_SUFFIX}"));
+        for tool in TOOLS.iter().chain(DUP_TOOLS) {
+            let tool_path = proxy_dir.join(format!("{tool}{EXE_SUFFIX}"));
+            if is_proxy(&rustup_path, &tool_path) {
+                utils::remove_file("proxy", &tool_path)?;
+            }
+        }
+    }
+
     info!("removing rustup home");
 
     // Delete RUSTUP_HOME
@@ -1050,10 +1218,7 @@ fn get_and_parse_new_rustup_version(path: &Path) -> Option<String> {
 fn get_new_rustup_version(path: &Path) -> Option<String> {
     match Command::new(path).arg("--version").output() {
         Err(_) => None,
-        Ok(output) => match String::from_utf8(output.stdout) {
-            Ok(version) => Some(version),
-            Err(_) => None,
-        },
+        Ok(output) => String::from_utf8(output.stdout).ok(),
     }
 }
 
@@ -1126,6 +1291,12 @@ pub(crate) async fn prepare_update(process: &Process) -> Result<Option<PathBuf>>
     // Mark as executable
     utils::make_executable(&setup_path)?;
 
+    // A rustup that can't start must not replace one that can.
+    if let Err(e) = doctor::check_starts(&setup_path) {
+        let _ = utils::remove_file("setup", &setup_path);
+        return Err(e.context("the new rustup can't run on this system"));
+    }
+
     Ok(Some(setup_path))
 }
 
@@ -1187,18 +1358,16 @@ impl fmt::Display for SchemaVersion {
     }
 }
 
-pub(crate) async fn check_rustup_update(process: &Process) -> Result<()> {
+/// Reports whether a newer rustup is available, returning `true` if so.
+pub(crate) async fn check_rustup_update(process: &Process) -> Result<bool> {
     let mut t = process.stdout().terminal(process);
-    // Get current rustup version
-    let current_version = env!("CARGO_PKG_VERSION");
-
-    // Get available rustup version
-    let available_version = get_available_rustup_version(process).await?;
+    let (current_version, available_version) = rustup_versions(process).await?;
 
     let _ = t.attr(terminalsource::Attr::Bold);
     write!(t.lock(), "rustup - ")?;
 
-    if current_version != available_version {
+    let update_available = current_version != available_version;
+    if update_available {
         let _ = t.fg(terminalsource::Color::Yellow);
         write!(t.lock(), "Update available")?;
         let _ = t.reset();
@@ -1210,7 +1379,13 @@ pub(crate) async fn check_rustup_update(process: &Process) -> Result<()> {
         writeln!(t.lock(), " : {current_version}")?;
     }
 
-    Ok(())
+    Ok(update_available)
+}
+
+/// The version of this rustup and the newest version available.
+pub(crate) async fn rustup_versions(process: &Process) -> Result<(&'static str, String)> {
+    let available_version = get_available_rustup_version(process).await?;
+    Ok((env!("CARGO_PKG_VERSION"), available_version))
 }
 
 #[tracing::instrument(level = "trace")]
@@ -1249,8 +1424,9 @@ mod tests {
                 default_toolchain: None,   // No toolchain specified
                 profile: Profile::Default, // default profile
                 no_modify_path: false,
-                components: &[],
-                targets: &[],
+                components: Vec::new(),
+                targets: Vec::new(),
+                system_rust: None,
                 no_update_toolchain: false,
             };
 
@@ -0,0 +1,5 @@
+# rustup shell setup
+if not contains "{cargo_bin}" $PATH
+    # Appending path so that a system-installed rustc takes precedence
+    set -x PATH $PATH "{cargo_bin}"
+end
@@ -0,0 +1,11 @@
+#!/bin/sh
+# rustup shell setup
+# affix colons on either side of $PATH to simplify matching
+case ":${PATH}:" in
+    *:"{cargo_bin}":*)
+        ;;
+    *)
+        # Appending path so that a system-installed rustc takes precedence
+        export PATH="$PATH:{cargo_bin}"
+        ;;
+esac
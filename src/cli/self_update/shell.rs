@@ -36,15 +36,21 @@ pub(crate) type Shell = Box<dyn UnixShell>;
 #[derive(Debug, PartialEq)]
 pub(crate) struct ShellScript {
     content: &'static str,
+    /// The script used when a system-installed Rust takes precedence.
+    system_first: &'static str,
     name: &'static str,
 }
 
 impl ShellScript {
-    pub(crate) fn write(&self, process: &Process) -> Result<()> {
+    pub(crate) fn write(&self, system_first: bool, process: &Process) -> Result<()> {
         let home = process.cargo_home()?;
         let cargo_bin = format!("{}/bin", cargo_home_str(process)?);
         let env_name = home.join(self.name);
-        let env_file = self.content.replace("{cargo_bin}", &cargo_bin);
+        let content = match system_first {
+            true => self.system_first,
+            false => self.content,
+        };
+        let env_file = content.replace("{cargo_bin}", &cargo_bin);
         utils::write_file(self.name, &env_name, &env_file)?;
         Ok(())
     }
@@ -104,6 +110,7 @@ pub(crate) trait UnixShell {
         ShellScript {
             name: "env",
             content: include_str!("env.sh"),
+            system_first: include_str!("env_system_first.sh"),
         }
     }
 
@@ -248,6 +255,7 @@ impl UnixShell for Fish {
         ShellScript {
             name: "env.fish",
             content: include_str!("env.fish"),
+            system_first: include_str!("env_system_first.fish"),
         }
     }
 
@@ -295,6 +303,7 @@ impl UnixShell for Nu {
         ShellScript {
             name: "env.nu",
             content: include_str!("env.nu"),
+            system_first: include_str!("env_system_first.nu"),
         }
     }
 
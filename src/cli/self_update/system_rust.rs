@@ -0,0 +1,250 @@
+//! Detection of a Rust installation that rustup did not make, typically one
+//! from the OS package manager, which mixes up versions when both are in
+//! `PATH`.
+
+use std::env::{self, consts::EXE_SUFFIX};
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::InstallOpts;
+use crate::cli::common;
+use crate::config::Cfg;
+use crate::process::Process;
+use crate::utils;
+
+/// An existing Rust installation found in `PATH` during setup, and what the
+/// user chose to do about it.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SystemRust {
+    /// The directory holding its `rustc` or `cargo`.
+    pub path: PathBuf,
+    /// The package manager that installed it, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub installed_by: Option<String>,
+    /// Whether rustup was put ahead of it in `PATH`.
+    pub rustup_first: bool,
+}
+
+struct PackageManager {
+    name: &'static str,
+    remove: &'static str,
+}
+
+/// Looks for `rustc` or `cargo` in `PATH` outside of rustup's directories,
+/// and if there is one, asks whether to put rustup ahead of it, leave `PATH`
+/// alone, or stop to remove it first. Returns `false` if setup should stop,
+/// which it then does with an error status.
+pub(super) fn check(opts: &mut InstallOpts, no_prompt: bool, process: &Process) -> Result<bool> {
+    // Only the test runner should set this
+    if process.var_os("RUSTUP_INIT_SKIP_PATH_CHECK") == Some("yes".into()) {
+        return Ok(true);
+    }
+
+    let Some((path, exe)) = find_in_path(process) else {
+        return Ok(true);
+    };
+    let exe = fs::canonicalize(&exe).unwrap_or(exe);
+    let os_release = fs::read_to_string("/etc/os-release").ok();
+    let manager = package_manager(&exe, os_release.as_deref());
+
+    warn!("It looks like you have an existing installation of Rust at:");
+    warn!("{}", path.display());
+    if let Some(manager) = &manager {
+        warn!("It appears to have been installed by {}.", manager.name);
+    }
+    warn!("It is recommended that rustup be the primary Rust installation.");
+    warn!("Otherwise you may have confusion unless you are careful with your PATH.");
+    warn!("If you are sure that you want both rustup and your already installed Rust");
+    warn!("then please choose below, set RUSTUP_INIT_SKIP_PATH_CHECK to yes");
+    warn!("or pass `-y' to ignore all ignorable checks.");
+
+    let rustup_first = if no_prompt {
+        warn!("continuing (because the -y flag is set and the error is ignorable)");
+        true
+    } else {
+        let stdout = process.stdout();
+        let mut stdout = stdout.lock();
+        writeln!(stdout)?;
+        writeln!(
+            stdout,
+            "1) Put rustup first in PATH and continue (default - just press enter)"
+        )?;
+        writeln!(
+            stdout,
+            "2) Continue and keep the existing installation first in PATH"
+        )?;
+        if manager.is_some() {
+            writeln!(stdout, "3) Show how to remove the existing installation")?;
+        }
+        write!(stdout, ">")?;
+        drop(stdout);
+        let _ = std::io::stdout().flush();
+        let input = common::read_line(process)
+            .map_err(|_| anyhow!("cannot install while Rust is installed"))?;
+        writeln!(process.stdout().lock())?;
+
+        match (&*input, &manager) {
+            ("1" | "", _) => {
+                opts.no_modify_path = false;
+                true
+            }
+            ("2", _) => false,
+            ("3", Some(manager)) => {
+                writeln!(
+                    process.stdout().lock(),
+                    "To remove the existing installation, run:\n\n    {}\n\n\
+                     and then run rustup-init again.\n",
+                    manager.remove
+                )?;
+                return Ok(false);
+            }
+            _ => return Ok(false),
+        }
+    };
+
+    opts.system_rust = Some(SystemRust {
+        path,
+        installed_by: manager.map(|m| m.name.to_owned()),
+        rustup_first,
+    });
+    Ok(true)
+}
+
+/// Switches whether rustup's proxies or the Rust installation outside of
+/// rustup come first in `PATH`, by rewriting the env scripts.
+pub(crate) fn toggle_system(cfg: &Cfg<'_>) -> Result<utils::ExitCode> {
+    if cfg!(windows) {
+        return Err(anyhow!(
+            "toggling the system Rust is not supported on Windows"
+        ));
+    }
+
+    let recorded = cfg.settings_file.with(|s| Ok(s.system_rust.clone()))?;
+    let mut system_rust = match recorded {
+        Some(system_rust) => system_rust,
+        None => {
+            let Some((path, exe)) = find_in_path(cfg.process) else {
+                return Err(anyhow!(
+                    "no Rust installation outside of rustup was found in PATH"
+                ));
+            };
+            let exe = fs::canonicalize(&exe).unwrap_or(exe);
+            let os_release = fs::read_to_string("/etc/os-release").ok();
+            SystemRust {
+                path,
+                installed_by: package_manager(&exe, os_release.as_deref())
+                    .map(|m| m.name.to_owned()),
+                rustup_first: true,
+            }
+        }
+    };
+    system_rust.rustup_first = !system_rust.rustup_first;
+
+    #[cfg(unix)]
+    super::unix::do_write_env_files(!system_rust.rustup_first, cfg.process)?;
+    match system_rust.rustup_first {
+        true => info!(
+            "rustup now comes before '{}' in PATH",
+            system_rust.path.display()
+        ),
+        false => info!(
+            "'{}' now comes before rustup in PATH",
+            system_rust.path.display()
+        ),
+    }
+    info!("start a new shell for the change to take effect");
+    cfg.set_system_rust(system_rust)?;
+    Ok(utils::ExitCode(0))
+}
+
+/// Finds the first directory in `PATH` with `rustc` or `cargo` that isn't
+/// managed by rustup, along with the executable found there.
+fn find_in_path(process: &Process) -> Option<(PathBuf, PathBuf)> {
+    let paths = process.var_os("PATH")?;
+    env::split_paths(&paths)
+        // Ignore rustc and cargo if present in $HOME/.cargo/bin or a few other directories
+        .filter(|path| {
+            !path
+                .components()
+                .any(|c| c == Component::Normal(".cargo".as_ref()))
+        })
+        .find_map(|path| {
+            let exe = ["rustc", "cargo"]
+                .iter()
+                .map(|name| path.join(format!("{name}{EXE_SUFFIX}")))
+                .find(|exe| exe.exists())?;
+            Some((path, exe))
+        })
+}
+
+/// Works out which package manager installed `exe`, from where it lives
+/// once symlinks are resolved and the contents of `/etc/os-release`.
+fn package_manager(exe: &Path, os_release: Option<&str>) -> Option<PackageManager> {
+    if ["/opt/homebrew", "/usr/local/Cellar", "/home/linuxbrew"]
+        .iter()
+        .any(|prefix| exe.starts_with(prefix))
+    {
+        return Some(PackageManager {
+            name: "Homebrew",
+            remove: "brew uninstall rust",
+        });
+    }
+    if !(exe.starts_with("/usr/bin") || exe.starts_with("/bin")) {
+        return None;
+    }
+
+    let os_release = os_release?;
+    let field = |key: &str| {
+        os_release.lines().find_map(|line| {
+            let value = line.strip_prefix(key)?.strip_prefix('=')?;
+            Some(value.trim_matches('"'))
+        })
+    };
+    let ids = field("ID")
+        .into_iter()
+        .chain(field("ID_LIKE").into_iter().flat_map(str::split_whitespace));
+    for id in ids {
+        let (name, remove) = match id {
+            "debian" | "ubuntu" => ("apt", "sudo apt remove rustc cargo"),
+            "fedora" | "rhel" | "centos" => ("dnf", "sudo dnf remove rust cargo"),
+            "arch" => ("pacman", "sudo pacman -Rs rust"),
+            "opensuse" | "suse" => ("zypper", "sudo zypper remove rust cargo"),
+            "alpine" => ("apk", "sudo apk del rust cargo"),
+            _ => continue,
+        };
+        return Some(PackageManager { name, remove });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::package_manager;
+
+    #[test]
+    fn package_manager_from_os_release() {
+        let name = |exe: &str, os_release: &str| {
+            package_manager(Path::new(exe), Some(os_release)).map(|m| m.name)
+        };
+
+        assert_eq!(name("/usr/bin/rustc", "ID=debian\n"), Some("apt"));
+        assert_eq!(
+            name(
+                "/usr/bin/cargo",
+                "NAME=\"Pop!_OS\"\nID=pop\nID_LIKE=\"ubuntu debian\"\n"
+            ),
+            Some("apt")
+        );
+        assert_eq!(name("/usr/bin/rustc", "ID=\"fedora\"\n"), Some("dnf"));
+        assert_eq!(name("/usr/bin/rustc", "ID=gentoo\n"), None);
+        assert_eq!(name("/usr/local/bin/rustc", "ID=debian\n"), None);
+        assert_eq!(name("/opt/homebrew/bin/rustc", ""), Some("Homebrew"));
+    }
+}
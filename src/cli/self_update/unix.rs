@@ -108,14 +108,16 @@ pub(crate) fn do_add_to_path(process: &Process) -> Result<()> {
     Ok(())
 }
 
-pub(crate) fn do_write_env_files(process: &Process) -> Result<()> {
+/// Writes the env scripts, which put rustup's proxies ahead of the rest of
+/// `PATH` unless `system_first` is set.
+pub(crate) fn do_write_env_files(system_first: bool, process: &Process) -> Result<()> {
     let mut written = vec![];
 
     for sh in shell::get_available_shells(process) {
         let script = sh.env_script();
         // Only write each possible script once.
         if !written.contains(&script) {
-            script.write(process)?;
+            script.write(system_first, process)?;
             written.push(script);
         }
     }
@@ -20,7 +20,7 @@ use windows_sys::Win32::Foundation::{ERROR_FILE_NOT_FOUND, ERROR_INVALID_DATA};
 use super::super::errors::*;
 use super::common;
 use super::{install_bins, report_error, InstallOpts};
-use crate::cli::{download_tracker::DownloadTracker, markdown::md};
+use crate::cli::{download_tracker::DownloadTracker, markdown::md, messages::message};
 use crate::dist::TargetTriple;
 use crate::process::{terminalsource::ColorableTerminal, Process};
 use crate::utils::{self, Notification};
@@ -91,7 +91,7 @@ pub(super) async fn maybe_install_msvc(
     term: &mut ColorableTerminal,
     no_prompt: bool,
     quiet: bool,
-    opts: &InstallOpts<'_>,
+    opts: &InstallOpts,
     process: &Process,
 ) -> Result<()> {
     let Some(plan) = do_msvc_check(opts, process) else {
@@ -110,7 +110,7 @@ pub(super) async fn maybe_install_msvc(
                         // see the error and give the option to continue anyway.
                         report_error(&e, process);
                         if !common::question_bool("\nContinue?", false, process)? {
-                            info!("aborting installation");
+                            info!("{}", message(process, "setup-aborted", &[]));
                         }
                     }
                     Ok(ContinueInstall::No) => ensure_prompt(process)?,
@@ -120,7 +120,7 @@ pub(super) async fn maybe_install_msvc(
             Some(VsInstallPlan::Manual) => {
                 md(term, MSVC_MANUAL_INSTALL_MESSAGE);
                 if !common::question_bool("\nContinue?", false, process)? {
-                    info!("aborting installation");
+                    info!("{}", message(process, "setup-aborted", &[]));
                 }
             }
             None => {}
@@ -129,7 +129,7 @@ pub(super) async fn maybe_install_msvc(
         md(term, MSVC_MESSAGE);
         md(term, MSVC_MANUAL_INSTALL_MESSAGE);
         if !common::question_bool("\nContinue?", false, process)? {
-            info!("aborting installation");
+            info!("{}", message(process, "setup-aborted", &[]));
         }
     }
 
@@ -179,7 +179,7 @@ pub(crate) enum VsInstallPlan {
 
 // Provide guidance about setting up MSVC if it doesn't appear to be
 // installed
-pub(crate) fn do_msvc_check(opts: &InstallOpts<'_>, process: &Process) -> Option<VsInstallPlan> {
+pub(crate) fn do_msvc_check(opts: &InstallOpts, process: &Process) -> Option<VsInstallPlan> {
     // Test suite skips this since it's env dependent
     if process.var("RUSTUP_INIT_SKIP_MSVC_CHECK").is_ok() {
         return None;
@@ -256,7 +256,7 @@ pub(crate) enum ContinueInstall {
 /// Returns `Ok(ContinueInstall::No)` if installing Visual Studio was successful
 /// but the rustup install should not be continued at this time.
 pub(crate) async fn try_install_msvc(
-    opts: &InstallOpts<'_>,
+    opts: &InstallOpts,
     process: &Process,
 ) -> Result<ContinueInstall> {
     // download the installer
@@ -0,0 +1,564 @@
+//! A mirror of the dist server backed by the download cache.
+//!
+//! `rustup serve-cache` answers requests in the layout of the dist server, so
+//! that other machines on the network can use it as their `RUSTUP_DIST_SERVER`,
+//! and serves archives by their hash to machines that list it in
+//! `cache-peers`. Manifests and other metadata always come from the upstream
+//! dist server, and the mirror remembers the hash of every archive they list.
+//! Archives are then served from the download cache, having been fetched from
+//! upstream and verified against that hash the first time they are asked for.
+//! Nothing else is served: an archive no manifest lists is refused.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::{
+    config::Cfg,
+    dist::manifest::{Manifest, PackageTargets},
+    process::Process,
+    utils::{self, ExitCode},
+};
+
+/// How long to wait for a client to send its request, or to take a part of
+/// the response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How many requests are handled at once. Others wait for their turn.
+const WORKERS: usize = 8;
+/// The channels whose manifests are looked up for archives of a date that no
+/// manifest served so far lists.
+const CHANNELS: &[&str] = &["stable", "beta", "nightly"];
+
+pub(super) fn serve(cfg: &Cfg<'_>, listen: SocketAddr) -> Result<ExitCode> {
+    utils::ensure_dir_exists(
+        "Download Directory",
+        &cfg.download_dir,
+        cfg.notify_handler.as_ref(),
+    )?;
+    let listener =
+        TcpListener::bind(listen).with_context(|| format!("could not listen on {listen}"))?;
+    let mirror = Arc::new(Mirror {
+        upstream: cfg
+            .dist_root_url
+            .strip_suffix("/dist")
+            .unwrap_or(&cfg.dist_root_url)
+            .to_owned(),
+        download_dir: cfg.download_dir.clone(),
+        process: cfg.process.clone(),
+        hashes: Mutex::default(),
+        indexed_dates: Mutex::default(),
+        fetching: Mutex::default(),
+        next_temp: AtomicU64::new(0),
+    });
+
+    info!(
+        "serving the download cache on http://{}, mirroring {}",
+        listener.local_addr()?,
+        mirror.upstream
+    );
+    mirror.serve(listener);
+    Ok(ExitCode(0))
+}
+
+struct Mirror {
+    /// The dist server being mirrored, without the `/dist` suffix.
+    upstream: String,
+    download_dir: PathBuf,
+    process: Process,
+    /// The hash of each archive listed by the manifests served so far, by the
+    /// path of its URL.
+    hashes: Mutex<HashMap<String, String>>,
+    /// The dates whose manifests have been looked up for unknown archives.
+    indexed_dates: Mutex<HashSet<String>>,
+    /// Held while fetching the archive with a hash, so that clients asking
+    /// for it at the same time only cause one download.
+    fetching: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    next_temp: AtomicU64,
+}
+
+/// A file to send in response to a request.
+struct Body {
+    path: PathBuf,
+    /// Whether the file only exists for this response.
+    temporary: bool,
+}
+
+impl Mirror {
+    fn serve(self: Arc<Self>, listener: TcpListener) {
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(WORKERS);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..WORKERS {
+            let (mirror, receiver) = (self.clone(), receiver.clone());
+            thread::spawn(move || loop {
+                let received = receiver
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .recv();
+                let Ok(stream) = received else {
+                    return;
+                };
+                if let Err(e) = mirror.handle(stream) {
+                    debug!("{e:#}");
+                }
+            });
+        }
+
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("could not accept connection: {e}");
+                    continue;
+                }
+            };
+            if sender.send(stream).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+        let (method, path, range) = read_request(&mut BufReader::new(&stream))?;
+        if method != "GET" {
+            return respond(&mut stream, "405 Method Not Allowed", &[], None);
+        }
+
+        match self.body(&path) {
+            Ok(Some(body)) => {
+                info!("GET {path}");
+                let res = send_file(&mut stream, &body.path, range);
+                if body.temporary {
+                    let _ = fs::remove_file(&body.path);
+                }
+                res
+            }
+            Ok(None) => {
+                info!("GET {path}: not found");
+                respond(&mut stream, "404 Not Found", &[], None)
+            }
+            Err(e) => {
+                warn!("GET {path}: {e:#}");
+                respond(&mut stream, "502 Bad Gateway", &[], None)
+            }
+        }
+    }
+
+    /// Returns the file to serve at `path`, or `None` if there is none.
+    fn body(&self, path: &str) -> Result<Option<Body>> {
+        // The hash of an archive alone, as asked for by peers.
+        let name = path.trim_start_matches('/');
+        if name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit()) {
+            let cached = self.download_dir.join(name);
+            return Ok(cached.is_file().then_some(Body {
+                path: cached,
+                temporary: false,
+            }));
+        }
+
+        if !path.starts_with("/dist/") || path.split('/').any(|part| part == "..") {
+            return Ok(None);
+        }
+
+        if let Some(hash) = self.hash(path) {
+            return self.archive(path, &hash).map(Some);
+        }
+
+        // Manifests and their hashes and signatures are passed through.
+        let file_name = path.rsplit('/').next().unwrap_or_default();
+        if !file_name.starts_with("channel-rust-") {
+            // The client may have its manifest from before the mirror
+            // started, so look for the archive in those of its date.
+            self.index_date(path)?;
+            return match self.hash(path) {
+                Some(hash) => self.archive(path, &hash).map(Some),
+                None => {
+                    debug!("'{path}' is not listed by any manifest");
+                    Ok(None)
+                }
+            };
+        }
+
+        let temp = self.temp_path();
+        match self.fetch(path, &temp, None) {
+            Ok(()) => {}
+            Err(e) if is_not_found(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        if file_name.ends_with(".toml") {
+            if let Err(e) = self.index(&temp) {
+                warn!("could not read the archives listed in '{path}': {e:#}");
+            }
+        }
+        Ok(Some(Body {
+            path: temp,
+            temporary: true,
+        }))
+    }
+
+    fn hash(&self, path: &str) -> Option<String> {
+        self.hashes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)
+            .cloned()
+    }
+
+    /// Remembers the hashes of the archives listed by the manifests of the
+    /// date in `path`, as in `/dist/2024-05-02/rustc-1.78.0-....tar.xz`.
+    fn index_date(&self, path: &str) -> Result<()> {
+        let Some(date) = path
+            .strip_prefix("/dist/")
+            .and_then(|rest| rest.split_once('/'))
+            .map(|(date, _)| date)
+            .filter(|date| date.len() == 10 && date.split('-').all(|n| n.parse::<u16>().is_ok()))
+        else {
+            return Ok(());
+        };
+        if !self
+            .indexed_dates
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(date.to_owned())
+        {
+            return Ok(());
+        }
+
+        for channel in CHANNELS {
+            let temp = self.temp_path();
+            let res = self
+                .fetch(
+                    &format!("/dist/{date}/channel-rust-{channel}.toml"),
+                    &temp,
+                    None,
+                )
+                .and_then(|()| self.index(&temp));
+            let _ = fs::remove_file(&temp);
+            match res {
+                Err(e) if !is_not_found(&e) => {
+                    // Try again with the next request.
+                    self.indexed_dates
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .remove(date);
+                    return Err(e);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the cached archive with `hash`, fetching it from `path` on the
+    /// upstream server first if need be.
+    fn archive(&self, path: &str, hash: &str) -> Result<Body> {
+        let lock = self
+            .fetching
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(hash.to_owned())
+            .or_default()
+            .clone();
+        let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let cached = self.download_dir.join(hash);
+        if !cached.is_file() {
+            let temp = self.temp_path();
+            if let Err(e) = self.fetch(path, &temp, Some(hash)) {
+                let _ = fs::remove_file(&temp);
+                return Err(e);
+            }
+            fs::rename(&temp, &cached).context("could not add the archive to the cache")?;
+        }
+        Ok(Body {
+            path: cached,
+            temporary: false,
+        })
+    }
+
+    /// Downloads `path` from the upstream server to `dest`, checking that it
+    /// has the `expected` hash if given.
+    fn fetch(&self, path: &str, dest: &Path, expected: Option<&str>) -> Result<()> {
+        let url = utils::parse_url(&format!("{}{path}", self.upstream))?;
+        let mut hasher = Sha256::new();
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(utils::download_file(
+                &url,
+                dest,
+                Some(&mut hasher),
+                &|n| debug!("{n}"),
+                &self.process,
+            ))?;
+
+        let actual = format!("{:x}", hasher.finalize());
+        match expected {
+            Some(expected) if expected != actual => Err(anyhow!(
+                "checksum failed for '{url}', expected: '{expected}', calculated: '{actual}'"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remembers the hash of every archive listed by the manifest at `path`.
+    fn index(&self, path: &Path) -> Result<()> {
+        let manifest = Manifest::parse(&utils::read_file("manifest", path)?)?;
+        let mut hashes = self.hashes.lock().unwrap_or_else(PoisonError::into_inner);
+        for package in manifest.packages.values() {
+            let targets = match &package.targets {
+                PackageTargets::Wildcard(target) => vec![target],
+                PackageTargets::Targeted(targets) => targets.values().collect(),
+            };
+            for bin in targets.into_iter().flat_map(|target| &target.bins) {
+                if let Ok(url) = Url::parse(&bin.url) {
+                    hashes.insert(url.path().to_owned(), bin.hash.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        let n = self.next_temp.fetch_add(1, Ordering::Relaxed);
+        self.download_dir
+            .join(format!("serve-{}-{n}.partial", std::process::id()))
+    }
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<download::DownloadError>(),
+            Some(download::DownloadError::FileNotFound | download::DownloadError::HttpStatus(404))
+        )
+    })
+}
+
+/// Reads the method and path of a request, and the start of the range asked
+/// for, if any.
+fn read_request(reader: &mut impl BufRead) -> Result<(String, String, Option<u64>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(anyhow!("malformed request line '{}'", line.trim()));
+    };
+    let (method, path) = (
+        method.to_owned(),
+        target.split('?').next().unwrap_or_default(),
+    );
+    let path = path.to_owned();
+
+    let mut range = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("range") {
+                range = value
+                    .trim()
+                    .strip_prefix("bytes=")
+                    .and_then(|r| r.strip_suffix('-'))
+                    .and_then(|start| start.parse().ok());
+            }
+        }
+    }
+    Ok((method, path, range))
+}
+
+fn send_file(stream: &mut TcpStream, path: &Path, range: Option<u64>) -> Result<()> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    match range {
+        Some(start) if start >= len => respond(
+            stream,
+            "416 Range Not Satisfiable",
+            &[("Content-Range", format!("bytes */{len}"))],
+            None,
+        ),
+        Some(start) => {
+            file.seek(SeekFrom::Start(start))?;
+            let headers = [("Content-Range", format!("bytes {start}-{}/{len}", len - 1))];
+            respond(
+                stream,
+                "206 Partial Content",
+                &headers,
+                Some((&mut file, len - start)),
+            )
+        }
+        None => respond(stream, "200 OK", &[], Some((&mut file, len))),
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, String)],
+    body: Option<(&mut File, u64)>,
+) -> Result<()> {
+    let len = body.as_ref().map_or(0, |(_, len)| *len);
+    let mut head = format!("HTTP/1.1 {status}\r\nContent-Length: {len}\r\nConnection: close\r\n");
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    if let Some((file, _)) = body {
+        io::copy(file, stream)?;
+    }
+    Ok(stream.flush()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    use super::*;
+    use crate::process::TestProcess;
+    use crate::test::test_dir;
+
+    fn get(addr: SocketAddr, path: &str, range: Option<u64>) -> (String, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let range = range.map_or(String::new(), |r| format!("Range: bytes={r}-\r\n"));
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: test\r\n{range}\r\n").unwrap();
+        let mut res = Vec::new();
+        stream.read_to_end(&mut res).unwrap();
+        let split = res.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(res[..split].to_vec()).unwrap();
+        let status = head.lines().next().unwrap()[9..].to_owned();
+        (status, res[split + 4..].to_vec())
+    }
+
+    #[test]
+    fn mirrors_dist_server() {
+        let upstream = test_dir().unwrap();
+        let archive = b"not really an archive";
+        let hash = format!("{:x}", Sha256::digest(archive));
+        let dist = upstream.path().join("dist");
+        fs::create_dir_all(dist.join("2015-01-01")).unwrap();
+        fs::write(dist.join("2015-01-01/rustc.tar.gz"), archive).unwrap();
+        fs::write(dist.join("2015-01-01/unlisted.tar.gz"), archive).unwrap();
+        let manifest = format!(
+            r#"manifest-version = "2"
+date = "2015-01-01"
+
+[pkg.rustc]
+version = "1.0.0"
+
+[pkg.rustc.target."*"]
+available = true
+url = "https://static.rust-lang.org/dist/2015-01-01/rustc.tar.gz"
+hash = "{hash}"
+"#
+        );
+        fs::write(dist.join("channel-rust-nightly.toml"), &manifest).unwrap();
+        fs::write(dist.join("2015-01-01/channel-rust-nightly.toml"), &manifest).unwrap();
+
+        let tp = TestProcess::new(
+            upstream.path(),
+            &["rustup"],
+            HashMap::<String, String>::default(),
+            "",
+        );
+        let start = |download_dir: &Path| {
+            let mirror = Arc::new(Mirror {
+                upstream: Url::from_directory_path(upstream.path())
+                    .unwrap()
+                    .as_str()
+                    .trim_end_matches('/')
+                    .to_owned(),
+                download_dir: download_dir.to_owned(),
+                process: tp.process.clone(),
+                hashes: Mutex::default(),
+                indexed_dates: Mutex::default(),
+                fetching: Mutex::default(),
+                next_temp: AtomicU64::new(0),
+            });
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            thread::spawn(move || mirror.serve(listener));
+            addr
+        };
+
+        let downloads = test_dir().unwrap();
+        let addr = start(downloads.path());
+        let (status, body) = get(addr, "/dist/channel-rust-nightly.toml", None);
+        assert_eq!(status, "200 OK");
+        assert!(String::from_utf8(body).unwrap().contains(&hash));
+
+        let (status, body) = get(addr, "/dist/2015-01-01/rustc.tar.gz", None);
+        assert_eq!(status, "200 OK");
+        assert_eq!(body, archive);
+        assert!(downloads.path().join(&hash).is_file());
+
+        let (status, body) = get(addr, &format!("/{hash}"), Some(4));
+        assert_eq!(status, "206 Partial Content");
+        assert_eq!(body, &archive[4..]);
+
+        assert_eq!(get(addr, "/dist/missing.toml", None).0, "404 Not Found");
+        assert_eq!(get(addr, "/dist/../secret", None).0, "404 Not Found");
+        assert_eq!(
+            get(addr, &format!("/{}", "0".repeat(64)), None).0,
+            "404 Not Found"
+        );
+        assert_eq!(
+            get(addr, "/dist/2015-01-01/unlisted.tar.gz", None).0,
+            "404 Not Found"
+        );
+
+        // Without the manifest having been served, the archive is found in
+        // the manifest of its date.
+        let downloads = test_dir().unwrap();
+        let addr = start(downloads.path());
+        let (status, body) = get(addr, "/dist/2015-01-01/rustc.tar.gz", None);
+        assert_eq!(status, "200 OK");
+        assert_eq!(body, archive);
+    }
+
+    #[test]
+    fn bounded_workers() {
+        let downloads = test_dir().unwrap();
+        let tp = TestProcess::default();
+        let mirror = Arc::new(Mirror {
+            upstream: "file:///no-such-server".to_owned(),
+            download_dir: downloads.path().to_owned(),
+            process: tp.process.clone(),
+            hashes: Mutex::default(),
+            indexed_dates: Mutex::default(),
+            fetching: Mutex::default(),
+            next_temp: AtomicU64::new(0),
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || mirror.serve(listener));
+
+        // Clients that never send a request take up every worker...
+        let idle = (0..WORKERS)
+            .map(|_| TcpStream::connect(addr).unwrap())
+            .collect::<Vec<_>>();
+        thread::sleep(Duration::from_millis(200));
+        let waiting = thread::spawn(move || get(addr, "/dist/missing.toml", None).0);
+        thread::sleep(Duration::from_millis(200));
+        assert!(!waiting.is_finished());
+
+        // ...until they go away.
+        drop(idle);
+        assert_eq!(waiting.join().unwrap(), "404 Not Found");
+    }
+}
@@ -64,6 +64,11 @@ struct RustupInit {
     #[arg(long)]
     no_modify_path: bool,
 
+    /// Install as root even where files it creates would belong to root, such as in a container
+    /// build
+    #[arg(long)]
+    allow_root: bool,
+
     /// Secret command used during self-update. Not for users
     #[arg(long, hide = true)]
     self_replace: bool,
@@ -92,6 +97,7 @@ pub async fn main(
         target,
         no_update_default_toolchain,
         no_modify_path,
+        allow_root,
         self_replace,
         dump_testament,
     } = match RustupInit::try_parse() {
@@ -117,14 +123,21 @@ pub async fn main(
 
     update_console_filter(process, &console_filter, quiet, verbose);
 
+    utils::permissions::check_root(
+        &[&process.rustup_home()?, &process.cargo_home()?],
+        process,
+        allow_root,
+    )?;
+
     let opts = InstallOpts {
         default_host_triple: default_host,
         default_toolchain,
         profile,
         no_modify_path,
         no_update_toolchain: no_update_default_toolchain,
-        components: &component.iter().map(|s| &**s).collect::<Vec<_>>(),
-        targets: &target.iter().map(|s| &**s).collect::<Vec<_>>(),
+        components: component,
+        targets: target,
+        system_rust: None,
     };
 
     self_update::install(current_dir, no_prompt, quiet, opts, process).await
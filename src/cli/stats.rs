@@ -0,0 +1,133 @@
+//! `rustup stats`: a summary of what rustup keeps in `RUSTUP_HOME` and
+//! how it is used.
+//!
+//! Nothing here is needed to run a toolchain, so the numbers come from
+//! records kept on the side: the usage log written by the proxies, the
+//! counts of where downloaded archives came from, and the update hashes,
+//! whose modification time is when a toolchain was last installed or
+//! updated.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::DateTime;
+
+use crate::{
+    cli::usage,
+    config::Cfg,
+    dist::download::DownloadStats,
+    toolchain::ToolchainName,
+    utils::{self, units::format_bytes, ExitCode},
+};
+
+/// How many toolchains are listed as the most used.
+const MOST_USED: usize = 3;
+
+pub(super) fn stats(cfg: &Cfg<'_>) -> Result<ExitCode> {
+    let toolchains = cfg.list_toolchains()?;
+    let usage = usage::read(&cfg.rustup_dir)?;
+
+    let mut rows = Vec::new();
+    let mut total = 0;
+    for name in &toolchains {
+        let path = cfg.toolchains_dir.join(name.to_string());
+        let size = match fs::symlink_metadata(&path)?.is_symlink() {
+            true => "linked".to_owned(),
+            false => {
+                let size = disk_usage(&path);
+                total += size;
+                format_bytes(size)
+            }
+        };
+        let updated = match name {
+            ToolchainName::Official(desc) => cfg
+                .manifest_cache
+                .installed(desc)?
+                .map(|installed| format_secs(installed.updated)),
+            ToolchainName::Custom(_) => None,
+        };
+        let runs = match usage.get(&name.to_string()) {
+            Some(usage) => format!("{} runs, last {}", usage.runs, format_secs(usage.last_used)),
+            None => "never run".to_owned(),
+        };
+        rows.push((
+            name.to_string(),
+            size,
+            updated.map_or_else(String::new, |date| format!("updated {date}")),
+            runs,
+        ));
+    }
+
+    let stdout = cfg.process.stdout();
+    let mut stdout = stdout.lock();
+    writeln!(
+        stdout,
+        "toolchains: {}, using {}",
+        toolchains.len(),
+        format_bytes(total)
+    )?;
+    let width = |column: fn(&(String, String, String, String)) -> &String| {
+        rows.iter().map(|row| column(row).len()).max().unwrap_or(0)
+    };
+    let (name_width, size_width, updated_width) =
+        (width(|r| &r.0), width(|r| &r.1), width(|r| &r.2));
+    for (name, size, updated, runs) in &rows {
+        writeln!(
+            stdout,
+            "  {name:name_width$}  {size:>size_width$}  {updated:updated_width$}  {runs}"
+        )?;
+    }
+
+    let downloads = DownloadStats::load(&cfg.download_dir);
+    let archives = downloads.reused + downloads.cached + downloads.downloaded;
+    write!(
+        stdout,
+        "downloads: {} kept",
+        format_bytes(disk_usage(&cfg.download_dir))
+    )?;
+    match archives {
+        0 => writeln!(stdout)?,
+        _ => writeln!(
+            stdout,
+            ", {}% of {archives} archives found in the cache",
+            (downloads.reused + downloads.cached) * 100 / archives
+        )?,
+    }
+
+    let mut most_used = usage.into_iter().collect::<Vec<_>>();
+    most_used.sort_by_key(|(_, usage)| std::cmp::Reverse(usage.runs));
+    if !most_used.is_empty() {
+        writeln!(stdout, "most used:")?;
+        for (name, usage) in most_used.iter().take(MOST_USED) {
+            writeln!(stdout, "  {name} ({} runs)", usage.runs)?;
+        }
+    }
+    Ok(ExitCode(0))
+}
+
+/// The total size of the files under `path`, without following symlinks.
+/// Whatever can't be read counts as nothing.
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    utils::read_dir("directory", path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+fn format_secs(secs: u64) -> String {
+    DateTime::from_timestamp(secs as i64, 0)
+        .map(|time| time.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,199 @@
+//! `rustup status`: whether downloads from the dist server can work right
+//! now, and if not, whose problem that is.
+//!
+//! A failed download looks the same whether the user's network is down,
+//! a proxy is in the way, or the Rust infrastructure has an incident. The
+//! dist server is asked for a small file, and the status feed of the Rust
+//! infrastructure for what it knows; together they tell these apart.
+
+use std::io::Write;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use download::DownloadError;
+use serde::Deserialize;
+use url::Url;
+
+use crate::{
+    config::{self, Cfg},
+    utils::{self, ExitCode},
+};
+
+/// The status feed of the Rust infrastructure, in the format of Statuspage's
+/// `status.json`.
+const STATUS_URL: &str = "https://status.rust-lang.org/api/v2/status.json";
+
+/// What asking the dist server for a file found.
+#[derive(Debug)]
+enum Probe {
+    Reachable,
+    /// The server answered with this unsuccessful status.
+    Status(u32),
+    /// No answer came back, for this reason.
+    Unreachable(String),
+}
+
+/// What the status feed says.
+#[derive(Debug)]
+enum Feed {
+    Operational(String),
+    /// The infrastructure reports an incident, so described.
+    Incident(String),
+    /// The feed couldn't be read, for this reason.
+    Unreachable(String),
+}
+
+#[derive(Deserialize)]
+struct StatusJson {
+    status: StatusIndicator,
+}
+
+#[derive(Deserialize)]
+struct StatusIndicator {
+    indicator: String,
+    description: String,
+}
+
+pub(super) async fn status(cfg: &Cfg<'_>) -> Result<ExitCode> {
+    let server = config::dist_root_server(cfg.process)?;
+    let tempdir = tempfile::Builder::new()
+        .prefix("rustup-status")
+        .tempdir()
+        .context("error creating temp directory")?;
+
+    let start = Instant::now();
+    let probe = match utils::parse_url(&format!("{server}/dist/channel-rust-stable.toml.sha256")) {
+        Ok(url) => probe(cfg, &url, &tempdir.path().join("probe")).await,
+        Err(e) => Probe::Unreachable(format!("{e:#}")),
+    };
+    let elapsed = start.elapsed();
+    let feed_url = cfg
+        .process
+        .var("RUSTUP_STATUS_URL")
+        .unwrap_or_else(|_| STATUS_URL.to_owned());
+    let feed = feed(cfg, &feed_url, &tempdir.path().join("status.json")).await;
+
+    let stdout = cfg.process.stdout();
+    let mut stdout = stdout.lock();
+    writeln!(stdout, "dist server: {server}")?;
+    match &probe {
+        Probe::Reachable => writeln!(stdout, "  reachable: ok ({} ms)", elapsed.as_millis())?,
+        Probe::Status(status) => writeln!(stdout, "  reachable: answered with status {status}")?,
+        Probe::Unreachable(e) => {
+            writeln!(stdout, "  reachable: failed")?;
+            writeln!(stdout, "  error: {e}")?;
+        }
+    }
+    writeln!(stdout, "status feed: {feed_url}")?;
+    match &feed {
+        Feed::Operational(description) | Feed::Incident(description) => {
+            writeln!(stdout, "  status: {description}")?
+        }
+        Feed::Unreachable(e) => {
+            writeln!(stdout, "  status: unknown")?;
+            writeln!(stdout, "  error: {e}")?;
+        }
+    }
+    let (ok, verdict) = verdict(&probe, &feed, &server);
+    writeln!(stdout, "verdict: {verdict}")?;
+    Ok(ExitCode(if ok { 0 } else { 1 }))
+}
+
+async fn probe(cfg: &Cfg<'_>, url: &Url, path: &std::path::Path) -> Probe {
+    match utils::download_file(url, path, None, &|_| (), cfg.process).await {
+        Ok(()) => Probe::Reachable,
+        Err(e) => match e.chain().find_map(|e| e.downcast_ref::<DownloadError>()) {
+            Some(DownloadError::HttpStatus(status))
+            | Some(DownloadError::RateLimited { status, .. }) => Probe::Status(*status),
+            _ => Probe::Unreachable(format!("{e:#}")),
+        },
+    }
+}
+
+async fn feed(cfg: &Cfg<'_>, url: &str, path: &std::path::Path) -> Feed {
+    let read = async {
+        let url = utils::parse_url(url)?;
+        utils::download_file(&url, path, None, &|_| (), cfg.process).await?;
+        let json = utils::read_file("status feed", path)?;
+        serde_json::from_str::<StatusJson>(&json).context("could not parse the status feed")
+    };
+    match read.await {
+        Ok(json) if json.status.indicator == "none" => Feed::Operational(json.status.description),
+        Ok(json) => Feed::Incident(json.status.description),
+        Err(e) => Feed::Unreachable(format!("{e:#}")),
+    }
+}
+
+/// Whether all is well, and what the findings mean.
+fn verdict(probe: &Probe, feed: &Feed, server: &str) -> (bool, String) {
+    match (probe, feed) {
+        (_, Feed::Incident(description)) => (
+            false,
+            format!("upstream incident: the Rust infrastructure reports '{description}'"),
+        ),
+        (Probe::Reachable, _) => (true, "downloads should work".to_owned()),
+        (Probe::Status(status @ 500..=599), _) => (
+            false,
+            format!("upstream incident: the dist server is failing with status {status}"),
+        ),
+        (Probe::Status(status), _) => (
+            false,
+            format!("the dist server answered with status {status}; check that '{server}' is the right server"),
+        ),
+        (Probe::Unreachable(_), Feed::Unreachable(_)) => (
+            false,
+            "your network looks broken: neither the dist server nor the status feed can be reached".to_owned(),
+        ),
+        (Probe::Unreachable(_), Feed::Operational(_)) => (
+            false,
+            format!("'{server}' can't be reached though the Rust infrastructure reports no incident; check the network, proxy or firewall between you and it"),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: &str = "https://static.rust-lang.org";
+
+    #[test]
+    fn incidents_are_upstream() {
+        let (ok, said) = verdict(
+            &Probe::Status(503),
+            &Feed::Incident("Partial outage".to_owned()),
+            SERVER,
+        );
+        assert!(!ok);
+        assert!(said.contains("'Partial outage'"));
+
+        let (ok, said) = verdict(
+            &Probe::Status(502),
+            &Feed::Operational("All Systems Operational".to_owned()),
+            SERVER,
+        );
+        assert!(!ok);
+        assert!(said.starts_with("upstream incident"));
+    }
+
+    #[test]
+    fn nothing_reachable_is_the_network() {
+        let (ok, said) = verdict(
+            &Probe::Unreachable("could not resolve".to_owned()),
+            &Feed::Unreachable("could not resolve".to_owned()),
+            SERVER,
+        );
+        assert!(!ok);
+        assert!(said.starts_with("your network looks broken"));
+    }
+
+    #[test]
+    fn reachable_is_fine() {
+        let (ok, _) = verdict(
+            &Probe::Reachable,
+            &Feed::Unreachable("timed out".to_owned()),
+            SERVER,
+        );
+        assert!(ok);
+    }
+}
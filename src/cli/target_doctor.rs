@@ -0,0 +1,616 @@
+//! `rustup target doctor`: whether what cross-compiling for a target needs
+//! besides its standard library is there, and how to get what isn't.
+//!
+//! `rustup target add` installs only the standard library, and the first
+//! build for another platform then usually fails to link, with an error
+//! from `cc` that says nothing of what is missing. Most targets need a
+//! linker for the platform, which cargo must be told about, and a C compiler
+//! for the crates that build C code, which the `cc` crate finds from
+//! `CC_<target>` or by the name cross toolchains conventionally give it.
+
+use std::env::consts::{EXE_SUFFIX, OS};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use tracing::info;
+
+use super::doctor::Problem;
+use crate::{
+    config::Cfg, dist::PartialToolchainDesc, process::Process, toolchain::DistributableToolchain,
+    utils::ExitCode,
+};
+
+/// What links for a target and compiles its C code, when nothing else is
+/// configured.
+#[derive(Debug, PartialEq)]
+enum Kit {
+    /// The host's own C compiler, which links for the target too.
+    Host,
+    /// The linker that comes with Rust, such as `rust-lld`.
+    Bundled,
+    /// A GCC cross toolchain whose tools are named after `prefix`, as in
+    /// `aarch64-linux-gnu-gcc`.
+    Gcc {
+        prefix: String,
+        musl: bool,
+    },
+    Android,
+    /// Apple's tools, which run only on macOS.
+    Apple,
+    /// Visual Studio's tools, which run only on Windows.
+    Msvc,
+    Emscripten,
+    /// A target rustup doesn't know the needs of.
+    Unknown,
+}
+
+impl Kit {
+    fn of(target: &str, host: &str) -> Self {
+        if target == host {
+            return Self::Host;
+        }
+        let arch = |triple: &str| triple.split('-').next().unwrap_or_default().to_owned();
+        if target == "wasm32-unknown-emscripten" {
+            Self::Emscripten
+        } else if target.starts_with("wasm") || target.contains("-none") {
+            Self::Bundled
+        } else if target.contains("-linux-android") {
+            Self::Android
+        } else if target.contains("-apple-") {
+            match host.contains("-apple-") {
+                true => Self::Host,
+                false => Self::Apple,
+            }
+        } else if target.ends_with("-windows-msvc") {
+            match host.ends_with("-windows-msvc") {
+                true => Self::Host,
+                false => Self::Msvc,
+            }
+        } else if target.ends_with("-windows-gnu") {
+            // The MinGW that links on Windows comes with the toolchain.
+            match (host.contains("-windows-"), arch(target).as_str()) {
+                (true, _) => Self::Bundled,
+                (false, "x86_64") => Self::Gcc {
+                    prefix: "x86_64-w64-mingw32".to_owned(),
+                    musl: false,
+                },
+                (false, "i686") => Self::Gcc {
+                    prefix: "i686-w64-mingw32".to_owned(),
+                    musl: false,
+                },
+                _ => Self::Unknown,
+            }
+        } else if let Some((_, env)) = target.split_once("-linux-") {
+            if host.contains("-linux-") && arch(host) == arch(target) {
+                return Self::Host;
+            }
+            let gcc_arch = match arch(target).as_str() {
+                arch if arch.starts_with("arm") || arch.starts_with("thumb") => "arm".to_owned(),
+                "i586" | "i686" => "i686".to_owned(),
+                "riscv64gc" => "riscv64".to_owned(),
+                arch => arch.to_owned(),
+            };
+            Self::Gcc {
+                prefix: format!("{gcc_arch}-linux-{env}"),
+                musl: env.starts_with("musl"),
+            }
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+/// What a check found: what is used, if nothing is wrong, or a note when
+/// there is nothing to check.
+enum Status {
+    Ok(String),
+    Problems(Vec<Problem>),
+    Note(String),
+}
+
+pub(crate) fn doctor(
+    cfg: &Cfg<'_>,
+    target: &str,
+    toolchain: Option<PartialToolchainDesc>,
+    write_config: bool,
+) -> Result<ExitCode> {
+    let host = cfg.get_default_host_triple()?.to_string();
+    let kit = Kit::of(target, &host);
+    if write_config {
+        write_linker_config(cfg, target, &kit)?;
+    }
+
+    let checks = [
+        ("target", check_installed(cfg, target, toolchain)?),
+        ("linker", check_linker(cfg, target, &kit)?),
+        ("C compiler", check_c_compiler(cfg, target, &kit)),
+    ];
+    let stdout = cfg.process.stdout();
+    let mut stdout = stdout.lock();
+    let mut ok = true;
+    for (name, status) in checks {
+        let problems = match status {
+            Status::Ok(found) => {
+                writeln!(stdout, "{name}: ok ({found})")?;
+                continue;
+            }
+            Status::Note(note) => {
+                writeln!(stdout, "{name}: {note}")?;
+                continue;
+            }
+            Status::Problems(problems) => problems,
+        };
+        ok = false;
+        match problems.len() {
+            1 => writeln!(stdout, "{name}: 1 problem")?,
+            n => writeln!(stdout, "{name}: {n} problems")?,
+        }
+        for Problem { what, fix } in problems {
+            writeln!(stdout, "  {what}")?;
+            writeln!(stdout, "    fix: {fix}")?;
+        }
+    }
+    Ok(ExitCode(if ok { 0 } else { 1 }))
+}
+
+/// Whether the toolchain has the standard library for `target`.
+fn check_installed(
+    cfg: &Cfg<'_>,
+    target: &str,
+    toolchain: Option<PartialToolchainDesc>,
+) -> Result<Status> {
+    let toolchain = cfg.toolchain_from_partial(toolchain)?;
+    let name = toolchain.name().to_string();
+    // Custom toolchains have no components, only what is in their sysroot.
+    let Ok(distributable) = DistributableToolchain::try_from(&toolchain) else {
+        if toolchain
+            .path()
+            .join("lib")
+            .join("rustlib")
+            .join(target)
+            .is_dir()
+        {
+            return Ok(Status::Ok(format!("in '{name}'")));
+        }
+        return Ok(Status::Problems(vec![Problem {
+            what: format!("'{name}' has no standard library for '{target}'"),
+            fix: "build it into the toolchain, or build the standard library with \
+                  cargo's `-Zbuild-std`"
+                .to_owned(),
+        }]));
+    };
+    let rust_std = distributable.components()?.into_iter().find(|status| {
+        status.component.short_name_in_manifest() == "rust-std"
+            && status.component.target.as_ref().map(|t| t.to_string()) == Some(target.to_owned())
+    });
+    let problem = match rust_std {
+        Some(status) if status.installed => {
+            return Ok(Status::Ok(format!("installed for '{name}'")));
+        }
+        Some(status) if status.available => Problem {
+            what: format!("'{target}' is not installed for '{name}'"),
+            fix: format!("run `rustup target add --toolchain {name} {target}`"),
+        },
+        _ => Problem {
+            what: format!("'{name}' has no standard library for '{target}'"),
+            fix: "see `rustup target list` for the targets it has, \
+                  or build the standard library with cargo's `-Zbuild-std`"
+                .to_owned(),
+        },
+    };
+    Ok(Status::Problems(vec![problem]))
+}
+
+/// Whether cargo is told of a linker that links for `target`, and it is
+/// there.
+fn check_linker(cfg: &Cfg<'_>, target: &str, kit: &Kit) -> Result<Status> {
+    let process = cfg.process;
+    if let Some(configured) = configured_linker(cfg, target)? {
+        let Configured { linker, from, dir } = configured;
+        if find(&linker, &dir, process).is_some() {
+            return Ok(Status::Ok(format!("`{linker}`, from {from}")));
+        }
+        return Ok(Status::Problems(vec![Problem {
+            what: format!("the linker `{linker}` from {from} was not found"),
+            fix: format!("install it, or change {from}"),
+        }]));
+    }
+
+    let problems = match kit {
+        Kit::Host => return Ok(check_host_compiler(cfg)),
+        Kit::Bundled => return Ok(Status::Ok("the linker that comes with Rust".to_owned())),
+        Kit::Gcc { prefix, musl } => {
+            let gcc = format!("{prefix}-gcc");
+            let mut problems = Vec::new();
+            if find(&gcc, &cfg.current_dir, process).is_none() {
+                problems.push(Problem {
+                    what: format!("`{gcc}` was not found in PATH"),
+                    fix: gcc_install_hint(prefix, *musl, OS),
+                });
+            }
+            problems.push(Problem {
+                what: format!("cargo links for '{target}' with `cc`, which links only for the host"),
+                fix: format!(
+                    "run `rustup target doctor {target} --write-config` to set `linker = \"{gcc}\"` \
+                     for it in '.cargo/config.toml'"
+                ),
+            });
+            problems
+        }
+        Kit::Android => {
+            let ndk = ["ANDROID_NDK_HOME", "ANDROID_NDK_ROOT"]
+                .into_iter()
+                .find_map(|var| process.var_os(var).map(PathBuf::from));
+            let what = match ndk {
+                Some(ndk) if ndk.is_dir() => format!(
+                    "cargo links for '{target}' with `cc`, rather than the clang of the NDK in '{}'",
+                    ndk.display()
+                ),
+                Some(ndk) => format!("the Android NDK in '{}' doesn't exist", ndk.display()),
+                None => "the Android NDK was not found, as ANDROID_NDK_HOME is not set".to_owned(),
+            };
+            vec![Problem {
+                what,
+                fix: "install the Android NDK from https://developer.android.com/ndk/downloads, \
+                      set ANDROID_NDK_HOME to where it is, and build with `cargo ndk`, \
+                      which tells cargo of its linker and C compiler"
+                    .to_owned(),
+            }]
+        }
+        Kit::Apple => vec![Problem {
+            what: format!("linking for '{target}' needs Apple's SDK, which comes only with macOS"),
+            fix: "build on macOS, or try `cargo zigbuild`".to_owned(),
+        }],
+        Kit::Msvc => vec![Problem {
+            what: format!(
+                "linking for '{target}' needs the Windows SDK and the Visual C++ libraries"
+            ),
+            fix: "build on Windows, or try `cargo xwin`, which downloads them".to_owned(),
+        }],
+        Kit::Emscripten => match find("emcc", &cfg.current_dir, process) {
+            Some(_) => return Ok(Status::Ok("`emcc`".to_owned())),
+            None => vec![emscripten_problem()],
+        },
+        Kit::Unknown => {
+            return Ok(Status::Note(format!(
+                "rustup doesn't know what links for '{target}'; see \
+                 https://doc.rust-lang.org/nightly/rustc/platform-support.html"
+            )));
+        }
+    };
+    Ok(Status::Problems(problems))
+}
+
+/// Whether the `cc` crate finds a C compiler for `target`, from the same
+/// variables it reads or by the name it guesses.
+fn check_c_compiler(cfg: &Cfg<'_>, target: &str, kit: &Kit) -> Status {
+    let process = cfg.process;
+    let vars = [
+        format!("CC_{target}"),
+        format!("CC_{}", target.replace('-', "_")),
+        "TARGET_CC".to_owned(),
+        "CC".to_owned(),
+    ];
+    for var in vars {
+        let Some(cc) = process.var(&var).ok().filter(|cc| !cc.is_empty()) else {
+            continue;
+        };
+        // The compiler may come after a wrapper, as in `sccache cc`.
+        let command = cc.split_whitespace().last().unwrap_or_default();
+        if find(command, &cfg.current_dir, process).is_some() {
+            return Status::Ok(format!("`{cc}`, from {var}"));
+        }
+        return Status::Problems(vec![Problem {
+            what: format!("the C compiler `{cc}` from {var} was not found"),
+            fix: format!("install it, or change {var}"),
+        }]);
+    }
+
+    match kit {
+        Kit::Host => check_host_compiler(cfg),
+        Kit::Gcc { prefix, musl } => {
+            let gcc = format!("{prefix}-gcc");
+            match find(&gcc, &cfg.current_dir, process) {
+                Some(_) => Status::Ok(format!("`{gcc}`")),
+                None => Status::Problems(vec![Problem {
+                    what: format!("`{gcc}` was not found in PATH"),
+                    fix: gcc_install_hint(prefix, *musl, OS),
+                }]),
+            }
+        }
+        Kit::Emscripten => match find("emcc", &cfg.current_dir, process) {
+            Some(_) => Status::Ok("`emcc`".to_owned()),
+            None => Status::Problems(vec![emscripten_problem()]),
+        },
+        Kit::Bundled => {
+            Status::Note("none is needed, unless a dependency builds C code".to_owned())
+        }
+        Kit::Android | Kit::Apple | Kit::Msvc | Kit::Unknown => Status::Note(format!(
+            "set CC_{} if a dependency builds C code",
+            target.replace('-', "_")
+        )),
+    }
+}
+
+/// Whether the host has the C compiler that rustc links with by default.
+fn check_host_compiler(cfg: &Cfg<'_>) -> Status {
+    if cfg!(windows) {
+        return Status::Ok("found by rustc in Visual Studio or the toolchain".to_owned());
+    }
+    if find("cc", &cfg.current_dir, cfg.process).is_some() {
+        return Status::Ok("`cc`".to_owned());
+    }
+    let fix = match OS {
+        "linux" => {
+            "install your distribution's C compiler, as with \
+                    `sudo apt install build-essential` on Debian and Ubuntu"
+        }
+        "macos" => "run `xcode-select --install`",
+        _ => "install a C compiler, and put it in PATH as `cc`",
+    };
+    Status::Problems(vec![Problem {
+        what: "`cc` was not found in PATH".to_owned(),
+        fix: fix.to_owned(),
+    }])
+}
+
+fn emscripten_problem() -> Problem {
+    Problem {
+        what: "`emcc` was not found in PATH".to_owned(),
+        fix: "install the Emscripten SDK from \
+              https://emscripten.org/docs/getting_started/downloads.html, and activate it"
+            .to_owned(),
+    }
+}
+
+/// How to install the GCC cross toolchain named after `prefix` on `host_os`.
+fn gcc_install_hint(prefix: &str, musl: bool, host_os: &str) -> String {
+    if musl {
+        return format!(
+            "download the `{prefix}` cross toolchain from https://musl.cc and put its `bin` \
+             directory in PATH, or build with `cargo zigbuild`"
+        );
+    }
+    let mingw = prefix.ends_with("-w64-mingw32");
+    match host_os {
+        "linux" => {
+            let package = match mingw {
+                true => format!(
+                    "gcc-mingw-w64-{}",
+                    prefix.split('-').next().unwrap_or_default()
+                ),
+                false => format!("gcc-{prefix}"),
+            }
+            .replace('_', "-");
+            format!(
+                "install it with your package manager, as with `sudo apt install {package}` \
+                 on Debian and Ubuntu"
+            )
+        }
+        "macos" if mingw => "run `brew install mingw-w64`".to_owned(),
+        "macos" => format!(
+            "install a cross toolchain that has `{prefix}-gcc`, or build with `cargo zigbuild`"
+        ),
+        "windows" => format!(
+            "build in WSL, or install a cross toolchain that has `{prefix}-gcc` and put it in PATH"
+        ),
+        _ => format!("install a cross toolchain that has `{prefix}-gcc`, and put it in PATH"),
+    }
+}
+
+/// A linker cargo is told to use.
+struct Configured {
+    linker: String,
+    /// Where cargo is told, for messages.
+    from: String,
+    /// What a relative path to the linker is relative to.
+    dir: PathBuf,
+}
+
+/// The linker cargo uses for `target` in the current directory, if one is
+/// set, from its environment or the nearest config file that sets one.
+fn configured_linker(cfg: &Cfg<'_>, target: &str) -> Result<Option<Configured>> {
+    let var = format!(
+        "CARGO_TARGET_{}_LINKER",
+        target.to_uppercase().replace(['-', '.'], "_")
+    );
+    if let Ok(linker) = cfg.process.var(&var) {
+        return Ok(Some(Configured {
+            linker,
+            from: var,
+            dir: cfg.current_dir.clone(),
+        }));
+    }
+
+    let cargo_home = cfg.process.cargo_home()?;
+    let files = cfg
+        .current_dir
+        .ancestors()
+        .flat_map(|dir| {
+            let dir = dir.join(".cargo");
+            [dir.join("config.toml"), dir.join("config")]
+        })
+        .chain([cargo_home.join("config.toml"), cargo_home.join("config")]);
+    for file in files {
+        let Some(linker) = linker_in(&file, target)? else {
+            continue;
+        };
+        // Cargo takes paths relative to the directory the config is for.
+        let dir = file
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or(Path::new("."))
+            .to_owned();
+        return Ok(Some(Configured {
+            linker,
+            from: format!("'{}'", file.display()),
+            dir,
+        }));
+    }
+    Ok(None)
+}
+
+/// The `linker` that the cargo config `file` sets for `target`, if it exists
+/// and sets one.
+fn linker_in(file: &Path, target: &str) -> Result<Option<String>> {
+    let Some(config) = read_config(file)? else {
+        return Ok(None);
+    };
+    Ok(config
+        .get("target")
+        .and_then(|targets| targets.get(target))
+        .and_then(|table| table.get("linker"))
+        .and_then(|linker| linker.as_str())
+        .map(str::to_owned))
+}
+
+fn read_config(file: &Path) -> Result<Option<toml::Table>> {
+    if !file.is_file() {
+        return Ok(None);
+    }
+    let text =
+        fs::read_to_string(file).with_context(|| format!("could not read '{}'", file.display()))?;
+    let config = text
+        .parse()
+        .with_context(|| format!("could not parse '{}'", file.display()))?;
+    Ok(Some(config))
+}
+
+/// Tells cargo in `.cargo/config.toml` of the current directory which
+/// linker to use for `target`, unless it already knows of one.
+fn write_linker_config(cfg: &Cfg<'_>, target: &str, kit: &Kit) -> Result<()> {
+    let Kit::Gcc { prefix, .. } = kit else {
+        info!("rustup knows of no cargo configuration to write for '{target}'");
+        return Ok(());
+    };
+    if let Some(Configured { linker, from, .. }) = configured_linker(cfg, target)? {
+        info!("the linker for '{target}' is already `{linker}`, from {from}");
+        return Ok(());
+    }
+
+    let file = cfg.current_dir.join(".cargo").join("config.toml");
+    let linker = format!("{prefix}-gcc");
+    let mut text = match read_config(&file)? {
+        Some(config) if config.get("target").and_then(|t| t.get(target)).is_some() => bail!(
+            "'{}' already has a [target.{target}] table; add `linker = \"{linker}\"` to it",
+            file.display()
+        ),
+        Some(_) => fs::read_to_string(&file)?,
+        None => String::new(),
+    };
+    if !text.is_empty() {
+        text.push_str(if text.ends_with('\n') { "\n" } else { "\n\n" });
+    }
+    text.push_str(&format!("[target.{target}]\nlinker = \"{linker}\"\n"));
+    fs::create_dir_all(file.parent().unwrap())
+        .and_then(|()| fs::write(&file, text))
+        .with_context(|| format!("could not write '{}'", file.display()))?;
+    info!(
+        "set the linker for '{target}' to `{linker}` in '{}'",
+        file.display()
+    );
+    Ok(())
+}
+
+/// Where `command` is: the file itself, if it is a path, which may be
+/// relative to `dir`, or else the first of its name in PATH.
+fn find(command: &str, dir: &Path, process: &Process) -> Option<PathBuf> {
+    let path = Path::new(command);
+    if path.components().count() > 1 {
+        let path = dir.join(path);
+        return path.is_file().then_some(path);
+    }
+    let name = match command.ends_with(EXE_SUFFIX) {
+        true => command.to_owned(),
+        false => format!("{command}{EXE_SUFFIX}"),
+    };
+    let paths = process.var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gcc(prefix: &str, musl: bool) -> Kit {
+        Kit::Gcc {
+            prefix: prefix.to_owned(),
+            musl,
+        }
+    }
+
+    #[test]
+    fn kits() {
+        let linux = "x86_64-unknown-linux-gnu";
+        let cases = [
+            (linux, Kit::Host),
+            ("x86_64-unknown-linux-musl", Kit::Host),
+            ("aarch64-unknown-linux-gnu", gcc("aarch64-linux-gnu", false)),
+            (
+                "armv7-unknown-linux-gnueabihf",
+                gcc("arm-linux-gnueabihf", false),
+            ),
+            (
+                "riscv64gc-unknown-linux-gnu",
+                gcc("riscv64-linux-gnu", false),
+            ),
+            ("i686-unknown-linux-gnu", gcc("i686-linux-gnu", false)),
+            (
+                "aarch64-unknown-linux-musl",
+                gcc("aarch64-linux-musl", true),
+            ),
+            ("x86_64-pc-windows-gnu", gcc("x86_64-w64-mingw32", false)),
+            ("x86_64-pc-windows-msvc", Kit::Msvc),
+            ("aarch64-apple-darwin", Kit::Apple),
+            ("aarch64-linux-android", Kit::Android),
+            ("wasm32-unknown-unknown", Kit::Bundled),
+            ("wasm32-wasip1", Kit::Bundled),
+            ("wasm32-unknown-emscripten", Kit::Emscripten),
+            ("thumbv7em-none-eabihf", Kit::Bundled),
+            ("riscv32imac-unknown-none-elf", Kit::Bundled),
+            ("nvptx64-nvidia-cuda", Kit::Unknown),
+        ];
+        for (target, kit) in cases {
+            assert_eq!(Kit::of(target, linux), kit, "{target}");
+        }
+
+        assert_eq!(
+            Kit::of("x86_64-pc-windows-gnu", "x86_64-pc-windows-msvc"),
+            Kit::Bundled
+        );
+        assert_eq!(
+            Kit::of("aarch64-pc-windows-msvc", "x86_64-pc-windows-msvc"),
+            Kit::Host
+        );
+        assert_eq!(
+            Kit::of("x86_64-apple-ios", "aarch64-apple-darwin"),
+            Kit::Host
+        );
+        assert_eq!(
+            Kit::of("x86_64-unknown-linux-gnu", "aarch64-apple-darwin"),
+            gcc("x86_64-linux-gnu", false)
+        );
+    }
+
+    #[test]
+    fn install_hints() {
+        let hint = gcc_install_hint("x86_64-linux-gnu", false, "linux");
+        assert!(
+            hint.contains("`sudo apt install gcc-x86-64-linux-gnu`"),
+            "{hint}"
+        );
+        let hint = gcc_install_hint("i686-w64-mingw32", false, "linux");
+        assert!(
+            hint.contains("`sudo apt install gcc-mingw-w64-i686`"),
+            "{hint}"
+        );
+        let hint = gcc_install_hint("x86_64-w64-mingw32", false, "macos");
+        assert_eq!(hint, "run `brew install mingw-w64`");
+        let hint = gcc_install_hint("aarch64-linux-musl", true, "linux");
+        assert!(hint.contains("https://musl.cc"), "{hint}");
+    }
+}
@@ -0,0 +1,249 @@
+//! A log of the toolchains the proxies run, for `rustup stats` and
+//! `rustup toolchain gc`.
+//!
+//! Every proxy invocation appends a line `<time> <runs> <toolchain>` to
+//! `RUSTUP_HOME/usage.log`, where the time is in seconds since the Unix
+//! epoch. Appending a short line is cheap enough to do on every run; once
+//! the log grows past a limit it is compacted: its totals are added to the
+//! metadata database, or, in builds without the `sqlite` feature, rewritten
+//! as one line per toolchain.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use tracing::debug;
+
+#[cfg(feature = "sqlite")]
+use crate::dist::manifest_cache::sqlite::MetadataDb;
+
+const USAGE_FILE: &str = "usage.log";
+/// The size past which the log is compacted.
+const COMPACT_SIZE: u64 = 1024 * 1024;
+/// How long a compaction's lock is respected.
+const STALE_LOCK: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct ToolchainUsage {
+    /// How many times a proxy ran the toolchain.
+    pub runs: u64,
+    /// When the toolchain was last run, in seconds since the Unix epoch.
+    pub last_used: u64,
+}
+
+fn path(rustup_dir: &Path) -> PathBuf {
+    rustup_dir.join(USAGE_FILE)
+}
+
+/// Logs that a proxy is running `toolchain`. This never fails: the log is
+/// only informational, and a proxy must not stop because of it.
+pub(crate) fn record(rustup_dir: &Path, toolchain: &str) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    if let Err(e) = append(rustup_dir, now, toolchain) {
+        debug!("can't log toolchain usage: {e:#}");
+    }
+}
+
+fn append(rustup_dir: &Path, now: u64, toolchain: &str) -> Result<()> {
+    let path = path(rustup_dir);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("can't open '{}'", path.display()))?;
+    // A single write, so that concurrent proxies don't interleave lines.
+    file.write_all(format!("{now} 1 {toolchain}\n").as_bytes())
+        .with_context(|| format!("can't write '{}'", path.display()))?;
+    if file.metadata()?.len() > COMPACT_SIZE {
+        compact(rustup_dir)?;
+    }
+    Ok(())
+}
+
+/// Moves the totals of the log into the metadata database, or rewrites the
+/// log with one line per toolchain.
+///
+/// Only one proxy compacts at a time, holding `usage.log.lock`; the others
+/// leave it be. The log is first moved aside so that proxies appending in the
+/// meantime start a new one, to which any compacted lines are then added.
+fn compact(rustup_dir: &Path) -> Result<()> {
+    let path = path(rustup_dir);
+    let lock_path = path.with_extension("log.lock");
+    match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&lock_path)
+    {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::AlreadyExists && !is_stale(&lock_path) => {
+            debug!("usage log is being compacted by another process");
+            return Ok(());
+        }
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            // Left behind by a proxy that didn't finish: take it over.
+        }
+        Err(e) => return Err(e).with_context(|| format!("can't create '{}'", lock_path.display())),
+    }
+
+    let tmp_path = path.with_extension(format!("log.{}.tmp", std::process::id()));
+    let result = (|| {
+        fs::rename(&path, &tmp_path).with_context(|| format!("can't move '{}'", path.display()))?;
+        let data = fs::read_to_string(&tmp_path)
+            .with_context(|| format!("can't read '{}'", tmp_path.display()))?;
+        let usage = parse(&data);
+        #[cfg(feature = "sqlite")]
+        MetadataDb::new(rustup_dir).add_usage(
+            usage
+                .iter()
+                .map(|(toolchain, usage)| (&**toolchain, usage.runs, usage.last_used)),
+        )?;
+        #[cfg(not(feature = "sqlite"))]
+        {
+            let mut compacted = String::new();
+            for (toolchain, usage) in usage {
+                compacted.push_str(&format!("{} {} {toolchain}\n", usage.last_used, usage.runs));
+            }
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(compacted.as_bytes()))
+                .with_context(|| format!("can't write '{}'", path.display()))?;
+        }
+        fs::remove_file(&tmp_path).with_context(|| format!("can't remove '{}'", tmp_path.display()))
+    })();
+    let _ = fs::remove_file(&lock_path);
+    result
+}
+
+/// Whether a lock is older than any compaction takes.
+fn is_stale(lock_path: &Path) -> bool {
+    fs::metadata(lock_path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age > STALE_LOCK)
+}
+
+/// The usage of each toolchain that has been run since the log was
+/// started.
+pub(crate) fn read(rustup_dir: &Path) -> Result<BTreeMap<String, ToolchainUsage>> {
+    let path = path(rustup_dir);
+    let data = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("can't read '{}'", path.display())),
+    };
+    #[allow(unused_mut)]
+    let mut usage = parse(&data);
+    #[cfg(feature = "sqlite")]
+    for (toolchain, runs, last_used) in MetadataDb::new(rustup_dir).usage()? {
+        let entry = usage.entry(toolchain).or_default();
+        entry.runs += runs;
+        entry.last_used = entry.last_used.max(last_used);
+    }
+    Ok(usage)
+}
+
+/// Lines that can't be parsed, such as one cut short by a crash, are
+/// skipped.
+fn parse(data: &str) -> BTreeMap<String, ToolchainUsage> {
+    let mut usage = BTreeMap::<String, ToolchainUsage>::new();
+    for line in data.lines() {
+        let mut fields = line.splitn(3, ' ');
+        let (Some(time), Some(runs), Some(toolchain)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let (Ok(time), Ok(runs)) = (time.parse::<u64>(), runs.parse::<u64>()) else {
+            continue;
+        };
+        let entry = usage.entry(toolchain.to_owned()).or_default();
+        entry.runs += runs;
+        entry.last_used = entry.last_used.max(time);
+    }
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_log() {
+        let usage = parse("100 1 stable\n90 5 nightly\n120 1 stable\nbad line\n130 1");
+        assert_eq!(
+            usage.into_iter().collect::<Vec<_>>(),
+            [
+                (
+                    "nightly".to_owned(),
+                    ToolchainUsage {
+                        runs: 5,
+                        last_used: 90
+                    }
+                ),
+                (
+                    "stable".to_owned(),
+                    ToolchainUsage {
+                        runs: 2,
+                        last_used: 120
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn compact_log() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            path(dir.path()),
+            "100 1 stable\n90 5 nightly\n120 1 stable\n",
+        )
+        .unwrap();
+        let before = read(dir.path()).unwrap();
+
+        // Another process is compacting.
+        let lock_path = path(dir.path()).with_extension("log.lock");
+        fs::write(&lock_path, "").unwrap();
+        compact(dir.path()).unwrap();
+        assert_eq!(
+            fs::read_to_string(path(dir.path()))
+                .unwrap()
+                .lines()
+                .count(),
+            3
+        );
+
+        fs::remove_file(&lock_path).unwrap();
+        compact(dir.path()).unwrap();
+        let lines = fs::read_to_string(path(dir.path()))
+            .unwrap_or_default()
+            .lines()
+            .count();
+        if cfg!(feature = "sqlite") {
+            assert_eq!(lines, 0);
+            append(dir.path(), 130, "stable").unwrap();
+            let usage = read(dir.path()).unwrap();
+            assert_eq!(
+                usage["stable"],
+                ToolchainUsage {
+                    runs: 3,
+                    last_used: 130
+                }
+            );
+            assert_eq!(usage["nightly"], before["nightly"]);
+        } else {
+            assert_eq!(lines, 2);
+            assert_eq!(read(dir.path()).unwrap(), before);
+            assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        }
+    }
+}
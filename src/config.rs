@@ -1,27 +1,45 @@
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::{self, Debug, Display};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
-use std::{env, io};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{env, fs, io};
 
 use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, NaiveDate};
 use serde::Deserialize;
 use thiserror::Error as ThisError;
 use tokio_stream::StreamExt;
 use tracing::trace;
 
 use crate::{
-    cli::{common, self_update::SelfUpdateMode},
+    cli::{
+        common,
+        crash_report::CrashReportMode,
+        proxy_mode::AutoInstallMode,
+        self_update::{ProxyLinks, SelfUpdateMode, SystemRust},
+    },
+    diskio::{storage::Storage, IoProfile},
     dist::{
-        self, download::DownloadCfg, temp, PartialToolchainDesc, Profile, TargetTriple,
-        ToolchainDesc,
+        self,
+        credentials::CredentialStore,
+        download::DownloadCfg,
+        manifest_cache::{self, ManifestCache},
+        temp,
+        trust::TrustedKey,
+        PartialToolchainDesc, Profile, TargetTriple, ToolchainDesc,
     },
     errors::RustupError,
     fallback_settings::FallbackSettings,
     install::UpdateStatus,
     notifications::*,
-    process::Process,
-    settings::{MetadataVersion, Settings, SettingsFile},
+    policy::Policy,
+    process::{
+        terminalsource::{OutputStyle, Palette},
+        Process,
+    },
+    settings::{MetadataVersion, Pin, Settings, SettingsFile, TlsHost, TlsRootSource},
     toolchain::{
         CustomToolchainName, DistributableToolchain, LocalToolchainName, PathBasedToolchainName,
         ResolvableLocalToolchainName, ResolvableToolchainName, Toolchain, ToolchainName,
@@ -229,18 +247,55 @@ impl From<LocalToolchainName> for OverrideCfg {
 #[cfg(unix)]
 pub(crate) const UNIX_FALLBACK_SETTINGS: &str = "/etc/rustup/settings.toml";
 
+/// How many components are downloaded at once unless configured otherwise.
+const DEFAULT_MAX_DOWNLOADS: usize = 4;
+
 pub(crate) struct Cfg<'a> {
     profile_override: Option<Profile>,
     pub rustup_dir: PathBuf,
     pub settings_file: SettingsFile,
-    pub fallback_settings: Option<FallbackSettings>,
+    /// The centralised settings file, which is only read when needed.
+    pub fallback_settings: Option<PathBuf>,
     pub toolchains_dir: PathBuf,
     pub update_hash_dir: PathBuf,
+    pub manifest_cache: ManifestCache,
     pub download_dir: PathBuf,
     pub tmp_cx: temp::Context,
     pub toolchain_override: Option<ResolvableToolchainName>,
     pub env_override: Option<LocalToolchainName>,
     pub dist_root_url: String,
+    /// The dist roots of further servers, tried in turn for files that
+    /// can't be downloaded from `dist_root_url`.
+    pub fallback_dist_roots: Vec<String>,
+    /// A local dist bundle to install from instead of the dist server.
+    pub dist_bundle: Option<PathBuf>,
+    pub cache_peers: Vec<String>,
+    /// The cache peers that failed during this run, which are not tried
+    /// again.
+    pub failed_cache_peers: Mutex<HashSet<String>>,
+    /// Updates that download more than this many bytes ask for confirmation.
+    pub confirm_downloads_over: Option<u64>,
+    /// A program run on each ELF file an update installs.
+    pub post_install_hook: Option<String>,
+    /// Where files identical between toolchains are kept once.
+    pub store_dir: PathBuf,
+    /// Whether updates link installed files to `store_dir`.
+    pub dedup: bool,
+    /// Keys that manifests from the dist server must be signed with.
+    pub trusted_keys: Vec<TrustedKey>,
+    /// The keys trusted for each of `fallback_dist_roots`.
+    pub fallback_trusted_keys: Vec<Vec<TrustedKey>>,
+    /// The file named by `RUSTUP_PGP_KEY`, trusted instead of `trusted_keys`.
+    pub pgp_key_file: Option<PathBuf>,
+    /// Where the tokens of dist servers that require authentication are.
+    pub credential_store: CredentialStore,
+    pub io_profile: IoProfile,
+    /// How many threads to do disk IO with, set by `rustup --jobs`.
+    pub io_threads: Option<usize>,
+    /// How many components are downloaded at once.
+    pub max_downloads: usize,
+    /// The configuration synced from an organization's bundle.
+    pub policy: Policy,
     pub notify_handler: Arc<dyn Fn(Notification<'_>)>,
     pub current_dir: PathBuf,
     pub process: &'a Process,
@@ -271,14 +326,14 @@ impl<'a> Cfg<'a> {
 
         // Centralised file for multi-user systems to provide admin/distributor set initial values.
         #[cfg(unix)]
-        let fallback_settings = FallbackSettings::new(
+        let fallback_settings = Some(
             // If present, use the RUSTUP_OVERRIDE_UNIX_FALLBACK_SETTINGS environment
             // variable as settings path, or UNIX_FALLBACK_SETTINGS otherwise
             match process.var("RUSTUP_OVERRIDE_UNIX_FALLBACK_SETTINGS") {
                 Ok(s) => PathBuf::from(s),
                 Err(_) => PathBuf::from(UNIX_FALLBACK_SETTINGS),
             },
-        )?;
+        );
         #[cfg(windows)]
         let fallback_settings = None;
 
@@ -296,15 +351,91 @@ impl<'a> Cfg<'a> {
             .map(|t| t.resolve(&default_host_triple))
             .transpose()?;
 
-        let dist_root_server = dist_root_server(process)?;
-
+        let policy = Policy::load(&rustup_dir)?;
+        let mut dist_root_servers = dist_root_servers_with(process, &policy)?;
+        let dist_root_server = dist_root_servers.remove(0);
         let notify_clone = notify_handler.clone();
+        // Kept inside RUSTUP_HOME, so that what is unpacked there can be
+        // renamed into a toolchain rather than copied.
         let tmp_cx = temp::Context::new(
             rustup_dir.join("tmp"),
             dist_root_server.as_str(),
             Box::new(move |n| (notify_clone)(n.into())),
         );
+
+        // A key named by `RUSTUP_PGP_KEY` replaces the keys trusted for the
+        // server. It is only read once a manifest is checked, as most runs
+        // of rustup, and every proxy, never download one.
+        let pgp_key_file = non_empty_env_var("RUSTUP_PGP_KEY", process)?.map(PathBuf::from);
+        // The official server is checked against the Rust release key unless
+        // other keys are trusted for it.
+        let keys_for = |server: &str| {
+            settings_file.with(|s| {
+                Ok(match s.trusted_keys.get(server) {
+                    Some(keys) => keys.clone(),
+                    None if server == dist::DEFAULT_DIST_SERVER => {
+                        vec![TrustedKey::rust_release_key()]
+                    }
+                    None => Vec::new(),
+                })
+            })
+        };
+        let trusted_keys = keys_for(&dist_root_server)?;
+        let fallback_trusted_keys = dist_root_servers
+            .iter()
+            .map(|server| keys_for(server))
+            .collect::<Result<_>>()?;
         let dist_root = dist_root_server + "/dist";
+        let fallback_dist_roots = dist_root_servers
+            .into_iter()
+            .map(|server| server + "/dist")
+            .collect();
+        let cache_peers = settings_file.with(|s| Ok(s.cache_peers.clone()))?;
+        let confirm_downloads_over = settings_file.with(|s| Ok(s.confirm_downloads_over))?;
+        let post_install_hook = settings_file.with(|s| {
+            Ok(s.post_install_hook
+                .clone()
+                .or_else(|| policy.post_install_hook.clone()))
+        })?;
+        let store_dir = rustup_dir.join("store");
+        let dedup = settings_file.with(|s| Ok(s.dedup))?;
+        let io_profile = settings_file.with(|s| Ok(s.io_profile.unwrap_or_default()))?;
+        let max_downloads = match non_empty_env_var("RUSTUP_MAX_DOWNLOADS", process)? {
+            Some(max) => max
+                .parse()
+                .with_context(|| format!("invalid RUSTUP_MAX_DOWNLOADS '{max}'"))?,
+            None => settings_file
+                .with(|s| Ok(s.max_downloads))?
+                .unwrap_or(DEFAULT_MAX_DOWNLOADS),
+        };
+        let manifest_ttl = match non_empty_env_var("RUSTUP_MANIFEST_TTL", process)? {
+            Some(ttl) => Duration::from_secs(
+                ttl.parse()
+                    .with_context(|| format!("invalid RUSTUP_MANIFEST_TTL '{ttl}'"))?,
+            ),
+            None => Duration::ZERO,
+        };
+        let manifest_cache = ManifestCache::new(
+            manifest_cache::default_store(&rustup_dir),
+            manifest_ttl,
+            update_hash_dir.clone(),
+        );
+        // The environment lets a user whose certificate store is broken
+        // install rustup, or update it, before there are settings to change.
+        let tls_root_source = match non_empty_env_var("RUSTUP_TLS_ROOT_SOURCE", process)? {
+            Some(source) => source.parse()?,
+            None => settings_file.with(|s| Ok(s.tls_root_source.clone().unwrap_or_default()))?,
+        };
+        download::set_tls_roots(tls_root_source.into());
+        let tls_hosts = settings_file.with(|s| Ok(s.tls_hosts.clone()))?;
+        download::set_tls_hosts(
+            tls_hosts
+                .into_iter()
+                .map(|(host, settings)| (host, settings.into()))
+                .collect(),
+        );
+        let credential_store = CredentialStore::from_env(&rustup_dir, process)?;
+        credential_store.clone().provide_tokens();
 
         let cfg = Self {
             profile_override: None,
@@ -313,12 +444,29 @@ impl<'a> Cfg<'a> {
             fallback_settings,
             toolchains_dir,
             update_hash_dir,
+            manifest_cache,
             download_dir,
             tmp_cx,
             notify_handler,
             toolchain_override: None,
             env_override,
             dist_root_url: dist_root,
+            fallback_dist_roots,
+            dist_bundle: None,
+            cache_peers,
+            failed_cache_peers: Mutex::default(),
+            confirm_downloads_over,
+            post_install_hook,
+            store_dir,
+            dedup,
+            trusted_keys,
+            fallback_trusted_keys,
+            pgp_key_file,
+            credential_store,
+            io_profile,
+            io_threads: None,
+            max_downloads,
+            policy,
             current_dir,
             process,
         };
@@ -341,8 +489,21 @@ impl<'a> Cfg<'a> {
     ) -> DownloadCfg<'a> {
         DownloadCfg {
             dist_root: &self.dist_root_url,
+            fallback_dist_roots: &self.fallback_dist_roots,
+            bundle: self.dist_bundle.as_deref(),
             tmp_cx: &self.tmp_cx,
             download_dir: &self.download_dir,
+            cache_peers: &self.cache_peers,
+            failed_cache_peers: &self.failed_cache_peers,
+            confirm_downloads_over: self.confirm_downloads_over,
+            post_install_hook: self.post_install_hook.as_deref(),
+            store: self.dedup.then_some(self.store_dir.as_path()),
+            trusted_keys: &self.trusted_keys,
+            fallback_trusted_keys: &self.fallback_trusted_keys,
+            pgp_key_file: self.pgp_key_file.as_deref(),
+            io_profile: self.io_profile,
+            io_threads: self.io_threads,
+            max_downloads: self.max_downloads,
             notify_handler,
             process: self.process,
         }
@@ -380,6 +541,339 @@ impl<'a> Cfg<'a> {
         Ok(())
     }
 
+    pub(crate) fn set_auto_install(&self, mode: AutoInstallMode) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.auto_install = Some(mode);
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetAutoInstall(mode.as_str()));
+        Ok(())
+    }
+
+    pub(crate) fn set_proxy_links(&self, links: ProxyLinks) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.proxy_links = Some(links);
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetProxyLinks(links.as_str()));
+        Ok(())
+    }
+
+    /// Records where the proxies are, once they have been moved there.
+    pub(crate) fn set_proxy_dir(&self, dir: Option<PathBuf>, moved_to: &Path) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.proxy_dir = dir;
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetProxyDir(moved_to));
+        Ok(())
+    }
+
+    pub(crate) fn set_dist_servers(&self, servers: Vec<String>) -> Result<()> {
+        let servers = servers
+            .iter()
+            .map(|server| server.trim_end_matches('/').to_owned())
+            .collect::<Vec<_>>();
+        for server in &servers {
+            utils::parse_url(server)?;
+        }
+        self.settings_file.with_mut(|s| {
+            s.dist_servers = servers.clone();
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetDistServers(&servers));
+        Ok(())
+    }
+
+    pub(crate) fn set_cache_peers(&self, peers: Vec<String>) -> Result<()> {
+        for peer in &peers {
+            utils::parse_url(peer)?;
+        }
+        self.settings_file.with_mut(|s| {
+            s.cache_peers = peers.clone();
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetCachePeers(&peers));
+        Ok(())
+    }
+
+    /// Records what was decided about a Rust installation found in `PATH`
+    /// during setup.
+    pub(crate) fn set_system_rust(&self, system_rust: SystemRust) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.system_rust = Some(system_rust);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn set_confirm_downloads_over(&self, limit: Option<u64>) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.confirm_downloads_over = limit;
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetConfirmDownloadsOver(limit));
+        Ok(())
+    }
+
+    pub(crate) fn set_post_install_hook(&self, hook: Option<String>) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.post_install_hook = hook.clone();
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetPostInstallHook(hook.as_deref()));
+        Ok(())
+    }
+
+    pub(crate) fn set_dedup(&self, dedup: bool) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.dedup = dedup;
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetDedup(dedup));
+        Ok(())
+    }
+
+    pub(crate) fn set_toolchain_retention(&self, days: Option<u64>) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.toolchain_retention_days = days;
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetToolchainRetention(days));
+        Ok(())
+    }
+
+    pub(crate) fn get_toolchain_retention(&self) -> Result<Option<u64>> {
+        self.settings_file.with(|s| Ok(s.toolchain_retention_days))
+    }
+
+    pub(crate) fn set_io_profile(&self, profile: IoProfile) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.io_profile = Some(profile);
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetIoProfile(profile.as_str()));
+        Ok(())
+    }
+
+    pub(crate) fn set_max_downloads(&self, max: usize) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.max_downloads = Some(max);
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetMaxDownloads(max));
+        Ok(())
+    }
+
+    pub(crate) fn set_tls_root_source(&self, source: TlsRootSource) -> Result<()> {
+        let source = match source {
+            TlsRootSource::File(path) => {
+                let path = self.current_dir.join(path);
+                download::read_roots(&path)?;
+                TlsRootSource::File(path)
+            }
+            source => source,
+        };
+        self.settings_file.with_mut(|s| {
+            s.tls_root_source = Some(source.clone());
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetTlsRootSource(&source.to_string()));
+        download::set_tls_roots(source.into());
+        Ok(())
+    }
+
+    /// Sets the TLS settings of the server `host`, or removes them to go back
+    /// to those of every other server.
+    pub(crate) fn set_tls_host(&self, host: &str, settings: Option<TlsHost>) -> Result<()> {
+        let host = url::Host::parse(host)
+            .map_err(|_| anyhow!("'{host}' is not a host name"))?
+            .to_string()
+            .to_ascii_lowercase();
+        let Some(mut settings) = settings else {
+            let removed = self
+                .settings_file
+                .with_mut(|s| Ok(s.tls_hosts.remove(&host).is_some()))?;
+            if !removed {
+                bail!("'{host}' has no TLS settings");
+            }
+            (self.notify_handler)(Notification::RemovedTlsHost(&host));
+            return Ok(());
+        };
+
+        if settings.insecure && download::is_rust_lang_host(&host) {
+            bail!(
+                "certificates of rust-lang.org servers are always verified; \
+                 see `rustup set tls-root-source` for other roots to verify them with"
+            );
+        }
+        if let Some(path) = &mut settings.roots {
+            *path = self.current_dir.join(&*path);
+            download::read_roots(path)?;
+        }
+        self.settings_file.with_mut(|s| {
+            s.tls_hosts.insert(host.clone(), settings.clone());
+            Ok(())
+        })?;
+        match &settings.roots {
+            Some(path) => (self.notify_handler)(Notification::SetTlsHostRoots(&host, path)),
+            None => (self.notify_handler)(Notification::SetTlsHostInsecure(&host)),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_crash_reports(&self, mode: CrashReportMode) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.crash_reports = Some(mode);
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetCrashReports(mode.as_str()));
+        Ok(())
+    }
+
+    pub(crate) fn set_output_style(&self, style: OutputStyle) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.output_style = Some(style);
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetOutputStyle(style.as_str()));
+        Ok(())
+    }
+
+    pub(crate) fn set_palette(&self, palette: Palette) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            s.palette = Some(palette);
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::SetPalette(palette.as_str()));
+        Ok(())
+    }
+
+    /// Pins a toolchain, so that updating all toolchains leaves it alone.
+    pub(crate) fn pin_toolchain(
+        &self,
+        name: &ToolchainName,
+        until: Option<NaiveDate>,
+    ) -> Result<()> {
+        let until = until.map(|d| d.format("%Y-%m-%d").to_string());
+        self.settings_file.with_mut(|s| {
+            s.pins.insert(
+                name.to_string(),
+                Pin {
+                    until: until.clone(),
+                },
+            );
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::PinnedToolchain(name, until.as_deref()));
+        Ok(())
+    }
+
+    /// Removes the pin on a toolchain, returning whether it was pinned.
+    pub(crate) fn unpin_toolchain(&self, name: &ToolchainName) -> Result<bool> {
+        let unpinned = self
+            .settings_file
+            .with_mut(|s| Ok(s.pins.remove(&name.to_string()).is_some()))?;
+        if unpinned {
+            (self.notify_handler)(Notification::UnpinnedToolchain(name));
+        }
+        Ok(unpinned)
+    }
+
+    /// Requires manifests from `server` to be signed with `key`, in addition
+    /// to any keys already trusted for it.
+    pub(crate) fn add_trusted_key(&self, server: &str, key: TrustedKey) -> Result<()> {
+        self.settings_file.with_mut(|s| {
+            let keys = s.trusted_keys.entry(server.to_owned()).or_default();
+            keys.retain(|k| k.id() != key.id());
+            keys.push(key.clone());
+            Ok(())
+        })?;
+        (self.notify_handler)(Notification::AddedTrustedKey(server, key.id()));
+        Ok(())
+    }
+
+    /// Stops trusting the key with the given ID for `server`, returning
+    /// whether it was trusted.
+    pub(crate) fn remove_trusted_key(&self, server: &str, id: &str) -> Result<bool> {
+        let removed = self.settings_file.with_mut(|s| {
+            let Some(keys) = s.trusted_keys.get_mut(server) else {
+                return Ok(false);
+            };
+            let len = keys.len();
+            keys.retain(|k| k.id() != id);
+            let removed = keys.len() != len;
+            if keys.is_empty() {
+                s.trusted_keys.remove(server);
+            }
+            Ok(removed)
+        })?;
+        if removed {
+            (self.notify_handler)(Notification::RemovedTrustedKey(server, id));
+        }
+        Ok(removed)
+    }
+
+    /// The variables `settings.toml` sets for the tools of `name`: its own,
+    /// and then those of the directory override that made it active.
+    pub(crate) fn toolchain_env(
+        &self,
+        name: &LocalToolchainName,
+        reason: &ActiveReason,
+    ) -> Result<BTreeMap<String, String>> {
+        self.settings_file.with(|s| {
+            let mut env = s
+                .toolchain_env
+                .get(&name.to_string())
+                .cloned()
+                .unwrap_or_default();
+            if let ActiveReason::OverrideDB(dir) = reason {
+                if let Some(dir_env) = s.dir_override_env(dir, self.notify_handler.as_ref()) {
+                    env.extend(dir_env.clone());
+                }
+            }
+            Ok(env)
+        })
+    }
+
+    pub(crate) fn is_pinned(&self, name: &ToolchainName) -> Result<bool> {
+        self.settings_file
+            .with(|s| Ok(s.pins.contains_key(&name.to_string())))
+    }
+
+    /// Warns when the rustup home is on a network file system, where rustup
+    /// is slower and running it from several machines at once can leave
+    /// toolchains broken.
+    pub(crate) fn warn_if_home_on_network_fs(&self) {
+        if Storage::of(&self.rustup_dir) == Storage::Network {
+            (self.notify_handler)(Notification::HomeOnNetworkFs(&self.rustup_dir));
+        }
+    }
+
+    /// Warns about every pin whose expiry date has passed.
+    pub(crate) fn warn_expired_pins(&self) -> Result<()> {
+        let Some(today) = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()
+            .and_then(|d| DateTime::from_timestamp(d.as_secs() as i64, 0))
+            .map(|t| t.date_naive())
+        else {
+            return Ok(());
+        };
+        self.settings_file.with(|s| {
+            for (name, pin) in &s.pins {
+                let Some(until) = &pin.until else { continue };
+                match NaiveDate::parse_from_str(until, "%Y-%m-%d") {
+                    Ok(date) if date < today => {
+                        (self.notify_handler)(Notification::PinExpired(name, until))
+                    }
+                    Ok(_) => {}
+                    Err(_) => trace!("ignoring invalid pin expiry '{until}' for '{name}'"),
+                }
+            }
+            Ok(())
+        })
+    }
+
     pub(crate) fn set_toolchain_override(&mut self, toolchain_override: &ResolvableToolchainName) {
         self.toolchain_override = Some(toolchain_override.to_owned());
     }
@@ -396,7 +890,7 @@ impl<'a> Cfg<'a> {
             return Ok(p);
         }
         self.settings_file
-            .with(|s| Ok(s.profile.unwrap_or_default()))
+            .with(|s| Ok(s.profile.or(self.policy.profile).unwrap_or_default()))
     }
 
     pub(crate) fn get_self_update_mode(&self) -> Result<SelfUpdateMode> {
@@ -414,6 +908,17 @@ impl<'a> Cfg<'a> {
         })
     }
 
+    /// Returns whether proxies should install a missing toolchain, preferring
+    /// `RUSTUP_AUTO_INSTALL` over the settings file.
+    pub(crate) fn get_auto_install_mode(&self) -> Result<AutoInstallMode> {
+        if let Ok(mode) = self.process.var("RUSTUP_AUTO_INSTALL") {
+            return mode.parse();
+        }
+
+        self.settings_file
+            .with(|s| Ok(s.auto_install.unwrap_or_default()))
+    }
+
     pub(crate) fn ensure_toolchains_dir(&self) -> Result<(), anyhow::Error> {
         utils::ensure_dir_exists("toolchains", &self.toolchains_dir, &|n| {
             (self.notify_handler)(n)
@@ -421,38 +926,10 @@ impl<'a> Cfg<'a> {
         Ok(())
     }
 
-    pub(crate) fn installed_paths<'b>(
-        &self,
-        desc: &ToolchainDesc,
-        path: &'b Path,
-    ) -> anyhow::Result<Vec<InstalledPath<'b>>> {
-        Ok(vec![
-            InstalledPath::File {
-                name: "update hash",
-                path: self.get_hash_file(desc, false)?,
-            },
-            InstalledPath::Dir { path },
-        ])
-    }
-
-    pub(crate) fn get_hash_file(
-        &self,
-        toolchain: &ToolchainDesc,
-        create_parent: bool,
-    ) -> Result<PathBuf> {
-        if create_parent {
-            utils::ensure_dir_exists(
-                "update-hash",
-                &self.update_hash_dir,
-                self.notify_handler.as_ref(),
-            )?;
-        }
-
-        Ok(self.update_hash_dir.join(toolchain.to_string()))
-    }
-
     #[tracing::instrument(level = "trace", skip_all)]
     pub(crate) fn upgrade_data(&self) -> Result<()> {
+        self.migrate_legacy_toolchain_names()?;
+
         let current_version = self.settings_file.with(|s| Ok(s.version))?;
         if current_version == MetadataVersion::default() {
             (self.notify_handler)(Notification::MetadataUpgradeNotNeeded(current_version));
@@ -491,6 +968,56 @@ impl<'a> Cfg<'a> {
         }
     }
 
+    /// Renames custom toolchains whose names are no longer accepted, along
+    /// with the default toolchain and overrides that refer to them.
+    fn migrate_legacy_toolchain_names(&self) -> Result<()> {
+        for old in self.list_legacy_toolchains()? {
+            let new = CustomToolchainName::migrate(&old)?;
+            self.rename_toolchain(&old, &new)?;
+        }
+        Ok(())
+    }
+
+    /// Renames the custom toolchain `old` to `new`, updating the default
+    /// toolchain and any overrides that refer to it.
+    ///
+    /// If the settings cannot be updated, the toolchain keeps its old name.
+    pub(crate) fn rename_toolchain(&self, old: &str, new: &CustomToolchainName) -> Result<()> {
+        let old_path = self.toolchains_dir.join(old);
+        let new_path = self.toolchains_dir.join(&**new);
+        // Linked toolchains are symlinks, which may be dangling.
+        if fs::symlink_metadata(&new_path).is_ok() {
+            bail!("cannot rename toolchain '{old}' to '{new}', as a toolchain by that name already exists");
+        }
+        utils::rename(
+            "toolchain",
+            &old_path,
+            &new_path,
+            self.notify_handler.as_ref(),
+        )?;
+        let updated = self.settings_file.with_mut(|s| {
+            if s.default_toolchain.as_deref() == Some(old) {
+                s.default_toolchain = Some(new.to_string());
+            }
+            for toolchain in s.overrides.values_mut().filter(|t| *t == old) {
+                *toolchain = new.to_string();
+            }
+            if let Some(pin) = s.pins.remove(old) {
+                s.pins.insert(new.to_string(), pin);
+            }
+            if let Some(env) = s.toolchain_env.remove(old) {
+                s.toolchain_env.insert(new.to_string(), env);
+            }
+            Ok(())
+        });
+        if let Err(e) = updated {
+            let _ = fs::rename(&new_path, &old_path);
+            return Err(e);
+        }
+        (self.notify_handler)(Notification::RenamedToolchain(old, new));
+        Ok(())
+    }
+
     pub(crate) fn find_default(&self) -> Result<Option<Toolchain<'_>>> {
         Ok(self
             .get_default()?
@@ -525,6 +1052,23 @@ impl<'a> Cfg<'a> {
         )
     }
 
+    /// The files that resolving the active toolchain of `current_dir` reads,
+    /// whether or not they exist, for telling when a cached resolution has
+    /// gone stale.
+    pub(crate) fn resolution_inputs(&self) -> Vec<PathBuf> {
+        let mut inputs = vec![
+            self.rustup_dir.join("settings.toml"),
+            // Some toolchain names only resolve if they are installed.
+            self.toolchains_dir.clone(),
+        ];
+        inputs.extend(self.fallback_settings.clone());
+        for dir in self.current_dir.ancestors() {
+            inputs.push(dir.join("rust-toolchain"));
+            inputs.push(dir.join("rust-toolchain.toml"));
+        }
+        inputs
+    }
+
     fn find_override_config(&self) -> Result<Option<(OverrideCfg, ActiveReason)>> {
         let override_config: Option<(OverrideCfg, ActiveReason)> =
             // First check +toolchain override from the command line
@@ -842,19 +1386,74 @@ impl<'a> Cfg<'a> {
         Ok((status, toolchain.into()))
     }
 
+    /// Warns if `name`, made active by the toolchain file at `path`, lacks
+    /// the components or targets the file asks for, which proxies only
+    /// install when toolchains are installed automatically.
+    ///
+    /// Proxies check on every call the answer isn't cached for, so only what
+    /// is recorded as installed is read, not the manifest: a component the
+    /// file names by a name it had before being renamed is taken as missing.
+    pub(crate) fn check_toolchain_file(
+        &self,
+        name: &LocalToolchainName,
+        path: &Path,
+    ) -> Result<()> {
+        let parse_mode = match path.extension() {
+            Some(ext) if ext == "toml" => ParseMode::OnlyToml,
+            _ => ParseMode::Both,
+        };
+        let contents = utils::read_file("toolchain file", path)?;
+        let file = Self::parse_override_file(contents, parse_mode)?;
+        let OverrideCfg::Official {
+            toolchain,
+            components,
+            targets,
+            ..
+        } = OverrideCfg::from_file(self, file)?
+        else {
+            return Ok(());
+        };
+        if components.is_empty() && targets.is_empty() {
+            return Ok(());
+        }
+        let Ok(distributable) = DistributableToolchain::new(self, toolchain) else {
+            return Ok(());
+        };
+
+        let installed = distributable
+            .get_manifestation()?
+            .read_config()?
+            .unwrap_or_default()
+            .components;
+        let has_component = |name: &String| {
+            installed
+                .iter()
+                .any(|c| c.pkg == *name || c.pkg == format!("{name}-preview"))
+        };
+        let has_target = |name: &String| {
+            installed
+                .iter()
+                .any(|c| c.pkg == "rust-std" && c.target() == *name)
+        };
+        if !(components.iter().all(has_component) && targets.iter().all(has_target)) {
+            (self.notify_handler)(Notification::MissingFromToolchainFile(name, path));
+        }
+        Ok(())
+    }
+
     /// Get the configured default toolchain.
     /// If none is configured, returns None
     /// If a bad toolchain name is configured, errors.
     pub(crate) fn get_default(&self) -> Result<Option<ToolchainName>> {
         let user_opt = self.settings_file.with(|s| Ok(s.default_toolchain.clone()));
-        let toolchain_maybe_str = if let Some(fallback_settings) = &self.fallback_settings {
-            match user_opt {
-                Err(_) | Ok(None) => Ok(fallback_settings.default_toolchain.clone()),
-                o => o,
+        let toolchain_maybe_str = match (user_opt, &self.fallback_settings) {
+            // Most users have a default of their own, so the centralised file
+            // is only parsed when they don't.
+            (Err(_) | Ok(None), Some(path)) => {
+                FallbackSettings::new(path)?.and_then(|s| s.default_toolchain)
             }
-        } else {
-            user_opt
-        }?;
+            (o, _) => o?,
+        };
         toolchain_maybe_str
             .map(ResolvableToolchainName::try_from)
             .transpose()?
@@ -869,20 +1468,43 @@ impl<'a> Cfg<'a> {
     /// Currently no notification of incorrect names or entry type is done.
     #[tracing::instrument(level = "trace", skip_all)]
     pub(crate) fn list_toolchains(&self) -> Result<Vec<ToolchainName>> {
+        let mut toolchains: Vec<_> = self
+            .list_toolchain_dirs()?
+            .into_iter()
+            .filter_map(|n| match ToolchainName::try_from(&n) {
+                Ok(name) => Some(name),
+                Err(_) => {
+                    if CustomToolchainName::is_legacy(&n) {
+                        (self.notify_handler)(Notification::LegacyToolchainName(&n));
+                    }
+                    None
+                }
+            })
+            .collect();
+
+        toolchains.sort();
+
+        Ok(toolchains)
+    }
+
+    fn list_legacy_toolchains(&self) -> Result<Vec<String>> {
+        Ok(self
+            .list_toolchain_dirs()?
+            .into_iter()
+            .filter(|n| CustomToolchainName::is_legacy(n))
+            .collect())
+    }
+
+    fn list_toolchain_dirs(&self) -> Result<Vec<String>> {
         if utils::is_directory(&self.toolchains_dir) {
-            let mut toolchains: Vec<_> = utils::read_dir("toolchains", &self.toolchains_dir)?
+            Ok(utils::read_dir("toolchains", &self.toolchains_dir)?
                 // TODO: this discards errors reading the directory, is that
                 // correct? could we get a short-read and report less toolchains
                 // than exist?
                 .filter_map(io::Result::ok)
                 .filter(|e| e.file_type().map(|f| !f.is_file()).unwrap_or(false))
                 .filter_map(|e| e.file_name().into_string().ok())
-                .filter_map(|n| ToolchainName::try_from(&n).ok())
-                .collect();
-
-            toolchains.sort();
-
-            Ok(toolchains)
+                .collect())
         } else {
             Ok(Vec::new())
         }
@@ -908,9 +1530,15 @@ impl<'a> Cfg<'a> {
     }
 
     /// Create an override for a toolchain
-    pub(crate) fn make_override(&self, path: &Path, toolchain: &ToolchainName) -> Result<()> {
+    pub(crate) fn make_overrides(
+        &self,
+        paths: &[PathBuf],
+        toolchain: &ToolchainName,
+    ) -> Result<()> {
         self.settings_file.with_mut(|s| {
-            s.add_override(path, toolchain.to_string(), self.notify_handler.as_ref());
+            for path in paths {
+                s.add_override(path, toolchain.to_string(), self.notify_handler.as_ref());
+            }
             Ok(())
         })
     }
@@ -919,8 +1547,15 @@ impl<'a> Cfg<'a> {
         &self,
         force_update: bool,
     ) -> Result<Vec<(ToolchainDesc, Result<UpdateStatus>)>> {
+        let pins = self.settings_file.with(|s| Ok(s.pins.clone()))?;
         let channels = self.list_channels()?;
-        let channels = channels.into_iter();
+        let channels = channels.into_iter().filter(|(desc, _)| {
+            let pinned = pins.contains_key(&desc.to_string());
+            if pinned {
+                (self.notify_handler)(Notification::SkippingPinnedToolchain(desc));
+            }
+            !pinned
+        });
         let profile = self.get_profile()?;
 
         // Update toolchains and collect the results
@@ -964,22 +1599,45 @@ impl<'a> Cfg<'a> {
     }
 }
 
+/// The dist server in use.
 pub(crate) fn dist_root_server(process: &Process) -> Result<String> {
-    Ok(match non_empty_env_var("RUSTUP_DIST_SERVER", process)? {
-        Some(s) => {
-            trace!("`RUSTUP_DIST_SERVER` has been set to `{s}`");
-            s
-        }
-        None => {
-            // For backward compatibility
-            non_empty_env_var("RUSTUP_DIST_ROOT", process)?
-                .inspect(|url| trace!("`RUSTUP_DIST_ROOT` has been set to `{url}`"))
-                .as_ref()
-                .map(|root| root.trim_end_matches("/dist"))
-                .unwrap_or(dist::DEFAULT_DIST_SERVER)
-                .to_owned()
+    Ok(dist_root_servers(process)?.swap_remove(0))
+}
+
+/// The dist servers to try in turn, starting with the one in use. The
+/// environment names a single server, while the settings may list several.
+pub(crate) fn dist_root_servers(process: &Process) -> Result<Vec<String>> {
+    let policy = Policy::load(&process.rustup_home()?)?;
+    dist_root_servers_with(process, &policy)
+}
+
+/// [`dist_root_servers`] with the policy already loaded.
+fn dist_root_servers_with(process: &Process, policy: &Policy) -> Result<Vec<String>> {
+    if let Some(s) = non_empty_env_var("RUSTUP_DIST_SERVER", process)? {
+        trace!("`RUSTUP_DIST_SERVER` has been set to `{s}`");
+        return Ok(vec![s]);
+    }
+    // For backward compatibility
+    if let Some(url) = non_empty_env_var("RUSTUP_DIST_ROOT", process)? {
+        trace!("`RUSTUP_DIST_ROOT` has been set to `{url}`");
+        return Ok(vec![url.trim_end_matches("/dist").to_owned()]);
+    }
+    let rustup_home = process.rustup_home()?;
+    let settings_path = rustup_home.join("settings.toml");
+    if settings_path.is_file() {
+        let servers = SettingsFile::new(settings_path).with(|s| Ok(s.dist_servers.clone()))?;
+        if !servers.is_empty() {
+            return Ok(servers
+                .iter()
+                .map(|s| s.trim_end_matches('/').to_owned())
+                .collect());
         }
-    })
+    }
+    // Otherwise, the mirror the policy names, if any.
+    Ok(vec![policy
+        .dist_server
+        .clone()
+        .unwrap_or_else(|| dist::DEFAULT_DIST_SERVER.to_owned())])
 }
 
 impl Debug for Cfg<'_> {
@@ -991,11 +1649,28 @@ impl Debug for Cfg<'_> {
             fallback_settings,
             toolchains_dir,
             update_hash_dir,
+            manifest_cache,
             download_dir,
             tmp_cx,
             toolchain_override,
             env_override,
             dist_root_url,
+            fallback_dist_roots,
+            dist_bundle,
+            cache_peers,
+            failed_cache_peers,
+            confirm_downloads_over,
+            post_install_hook,
+            store_dir,
+            dedup,
+            trusted_keys,
+            fallback_trusted_keys,
+            pgp_key_file,
+            credential_store,
+            io_profile,
+            io_threads,
+            max_downloads,
+            policy,
             notify_handler: _,
             current_dir,
             process: _,
@@ -1008,11 +1683,28 @@ impl Debug for Cfg<'_> {
             .field("fallback_settings", fallback_settings)
             .field("toolchains_dir", toolchains_dir)
             .field("update_hash_dir", update_hash_dir)
+            .field("manifest_cache", manifest_cache)
             .field("download_dir", download_dir)
             .field("tmp_cx", tmp_cx)
             .field("toolchain_override", toolchain_override)
             .field("env_override", env_override)
             .field("dist_root_url", dist_root_url)
+            .field("fallback_dist_roots", fallback_dist_roots)
+            .field("dist_bundle", dist_bundle)
+            .field("cache_peers", cache_peers)
+            .field("failed_cache_peers", failed_cache_peers)
+            .field("confirm_downloads_over", confirm_downloads_over)
+            .field("post_install_hook", post_install_hook)
+            .field("store_dir", store_dir)
+            .field("dedup", dedup)
+            .field("trusted_keys", trusted_keys)
+            .field("fallback_trusted_keys", fallback_trusted_keys)
+            .field("pgp_key_file", pgp_key_file)
+            .field("credential_store", credential_store)
+            .field("io_profile", io_profile)
+            .field("io_threads", io_threads)
+            .field("max_downloads", max_downloads)
+            .field("policy", policy)
             .field("current_dir", current_dir)
             .finish()
     }
@@ -1034,7 +1726,7 @@ fn non_empty_env_var(name: &str, process: &Process) -> anyhow::Result<Option<Str
     }
 }
 
-fn no_toolchain_error(process: &Process) -> anyhow::Error {
+pub(crate) fn no_toolchain_error(process: &Process) -> anyhow::Error {
     RustupError::ToolchainNotSelected(process.name().unwrap_or_else(|| "Rust".into())).into()
 }
 
@@ -1052,12 +1744,6 @@ enum ParseMode {
     Both,
 }
 
-/// Installed paths
-pub(crate) enum InstalledPath<'a> {
-    File { name: &'static str, path: PathBuf },
-    Dir { path: &'a Path },
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -5,7 +5,7 @@
 use std::{
     fmt::Debug,
     fs::{File, OpenOptions},
-    io::{self, Write},
+    io,
     path::Path,
     sync::{Arc, Mutex},
     time::Instant,
@@ -71,11 +71,12 @@ impl Executor for ImmediateUnpacker {
             super::Kind::Directory => super::create_dir(&item.full_path),
             super::Kind::File(ref contents) => {
                 if let super::FileBuffer::Immediate(ref contents) = &contents {
-                    super::write_file(&item.full_path, contents, item.mode)
+                    super::write_file(&item.full_path, contents, item.mode, item.sync)
                 } else {
                     unreachable!()
                 }
             }
+            super::Kind::RemoveFile => super::remove_file(&item.full_path),
             super::Kind::IncrementalFile(_incremental_file) => {
                 return {
                     // If there is a pending error, return it, otherwise stash the
@@ -148,6 +149,7 @@ impl Executor for ImmediateUnpacker {
 pub(super) struct IncrementalFileWriter {
     state: IncrementalFileState,
     file: Option<File>,
+    sync: bool,
     path_display: String,
 }
 
@@ -156,6 +158,7 @@ impl IncrementalFileWriter {
     pub(crate) fn new<P: AsRef<Path>>(
         path: P,
         mode: u32,
+        sync: bool,
         state: IncrementalFileState,
     ) -> std::result::Result<Self, io::Error> {
         let mut opts = OpenOptions::new();
@@ -173,6 +176,7 @@ impl IncrementalFileWriter {
         Ok(IncrementalFileWriter {
             state,
             file,
+            sync,
             path_display,
         })
     }
@@ -207,12 +211,13 @@ impl IncrementalFileWriter {
             if let Some(ref mut file) = self.file.as_mut() {
                 // Length 0 vector is used for clean EOF signalling.
                 if chunk.is_empty() {
-                    trace_scoped!("close", "name:": self.path_display);
-                    drop(std::mem::take(&mut self.file));
+                    if let Some(file) = self.file.take() {
+                        super::close(file, self.sync, &self.path_display)?;
+                    }
                     state.finished = true;
                 } else {
                     trace_scoped!("write_segment", "name": self.path_display, "len": chunk.len());
-                    file.write_all(&chunk)?;
+                    super::write_batched(file, &chunk, self.sync)?;
 
                     state.completed_chunks.push(chunk.len());
                 }
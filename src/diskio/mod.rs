@@ -52,6 +52,7 @@
 // f) data gathering: record (name, bytes, start, duration)
 //    write to disk afterwards as a csv file?
 pub(crate) mod immediate;
+pub(crate) mod storage;
 #[cfg(test)]
 mod test;
 pub(crate) mod threaded;
@@ -59,17 +60,88 @@ pub(crate) mod threaded;
 use std::io::{self, Write};
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::mpsc::Receiver;
 use std::thread::available_parallelism;
 use std::time::{Duration, Instant};
-use std::{fmt::Debug, fs::OpenOptions};
-
-use anyhow::{Context, Result};
+use std::{
+    fmt::{self, Debug},
+    fs::{self, File, OpenOptions},
+};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{builder::PossibleValue, ValueEnum};
+use itertools::Itertools;
+use retry::delay::{jitter, Fibonacci};
+use retry::{retry, OperationResult};
+use serde::{Deserialize, Serialize};
 
 use crate::process::Process;
 use crate::utils::notifications::Notification;
+use crate::utils::units::Unit;
+use storage::Storage;
 use threaded::PoolReference;
 
+/// How much memory and how many threads unpacking components may use.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoProfile {
+    /// Chosen from the memory available and the kind of storage.
+    #[default]
+    Auto,
+    /// One thread and the smallest buffers, for small machines.
+    LowMemory,
+    /// As many threads and as much memory as can help.
+    MaxThroughput,
+}
+
+impl IoProfile {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::LowMemory => "low-memory",
+            Self::MaxThroughput => "max-throughput",
+        }
+    }
+}
+
+impl ValueEnum for IoProfile {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Auto, Self::LowMemory, Self::MaxThroughput]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.as_str()))
+    }
+
+    fn from_str(input: &str, _: bool) -> Result<Self, String> {
+        <Self as FromStr>::from_str(input).map_err(|e| e.to_string())
+    }
+}
+
+impl FromStr for IoProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(profile: &str) -> Result<Self> {
+        match profile {
+            "auto" => Ok(Self::Auto),
+            "low-memory" => Ok(Self::LowMemory),
+            "max-throughput" => Ok(Self::MaxThroughput),
+            _ => Err(anyhow!(
+                "unknown IO profile: '{}'; valid profiles are {}",
+                profile,
+                Self::value_variants().iter().join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for IoProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Carries the implementation specific data for complete file transfers into the executor.
 #[derive(Debug)]
 pub(crate) enum FileBuffer {
@@ -181,6 +253,7 @@ pub(crate) enum Kind {
     Directory,
     File(FileBuffer),
     IncrementalFile(IncrementalFile),
+    RemoveFile,
 }
 
 /// The details of the IO operation
@@ -198,6 +271,8 @@ pub(crate) struct Item {
     pub(crate) result: io::Result<()>,
     /// The mode to apply
     mode: u32,
+    /// Whether to sync files to storage before closing them
+    sync: bool,
 }
 
 #[derive(Debug)]
@@ -218,10 +293,28 @@ impl Item {
             finish: None,
             result: Ok(()),
             mode,
+            sync: false,
+        }
+    }
+
+    pub(crate) fn remove_file(full_path: PathBuf) -> Self {
+        Self {
+            full_path,
+            kind: Kind::RemoveFile,
+            start: None,
+            finish: None,
+            result: Ok(()),
+            mode: 0,
+            sync: false,
         }
     }
 
-    pub(crate) fn write_file(full_path: PathBuf, mode: u32, content: FileBuffer) -> Self {
+    pub(crate) fn write_file(
+        full_path: PathBuf,
+        mode: u32,
+        sync: bool,
+        content: FileBuffer,
+    ) -> Self {
         Self {
             full_path,
             kind: Kind::File(content),
@@ -229,15 +322,18 @@ impl Item {
             finish: None,
             result: Ok(()),
             mode,
+            sync,
         }
     }
 
     pub(crate) fn write_file_segmented<'a>(
         full_path: PathBuf,
         mode: u32,
+        sync: bool,
         state: IncrementalFileState,
     ) -> Result<(Self, Box<dyn FnMut(FileBuffer) -> bool + 'a>)> {
-        let (chunk_submit, content_callback) = state.incremental_file_channel(&full_path, mode)?;
+        let (chunk_submit, content_callback) =
+            state.incremental_file_channel(&full_path, mode, sync)?;
         let result = Self {
             full_path,
             kind: Kind::IncrementalFile(content_callback),
@@ -245,6 +341,7 @@ impl Item {
             finish: None,
             result: Ok(()),
             mode,
+            sync,
         };
         Ok((result, Box::new(chunk_submit)))
     }
@@ -268,6 +365,7 @@ impl IncrementalFileState {
         &self,
         path: &Path,
         mode: u32,
+        sync: bool,
     ) -> Result<(Box<dyn FnMut(FileBuffer) -> bool>, IncrementalFile)> {
         use std::sync::mpsc::channel;
         match *self {
@@ -279,7 +377,8 @@ impl IncrementalFileState {
             }
             IncrementalFileState::Immediate(ref state) => {
                 let content_callback = IncrementalFile::ImmediateReceiver;
-                let mut writer = immediate::IncrementalFileWriter::new(path, mode, state.clone())?;
+                let mut writer =
+                    immediate::IncrementalFileWriter::new(path, mode, sync, state.clone())?;
                 let chunk_submit = move |chunk: FileBuffer| writer.chunk_submit(chunk);
                 Ok((Box::new(chunk_submit), content_callback))
             }
@@ -341,10 +440,10 @@ pub(crate) fn perform<F: Fn(usize)>(item: &mut Item, chunk_complete_callback: F)
             contents.clear();
             match contents {
                 FileBuffer::Immediate(ref contents) => {
-                    write_file(&item.full_path, contents, item.mode)
+                    write_file(&item.full_path, contents, item.mode, item.sync)
                 }
                 FileBuffer::Threaded(ref mut contents) => {
-                    write_file(&item.full_path, contents, item.mode)
+                    write_file(&item.full_path, contents, item.mode, item.sync)
                 }
             }
         }
@@ -352,8 +451,10 @@ pub(crate) fn perform<F: Fn(usize)>(item: &mut Item, chunk_complete_callback: F)
             &item.full_path,
             incremental_file,
             item.mode,
+            item.sync,
             chunk_complete_callback,
         ),
+        Kind::RemoveFile => remove_file(&item.full_path),
     };
     item.finish = item
         .start
@@ -365,6 +466,7 @@ pub(crate) fn write_file<P: AsRef<Path>, C: AsRef<[u8]>>(
     path: P,
     contents: C,
     mode: u32,
+    sync: bool,
 ) -> io::Result<()> {
     let mut opts = OpenOptions::new();
     #[cfg(unix)]
@@ -382,13 +484,9 @@ pub(crate) fn write_file<P: AsRef<Path>, C: AsRef<[u8]>>(
     let len = contents.len();
     {
         trace_scoped!("write", "name": path_display, "len": len);
-        f.write_all(contents)?;
-    }
-    {
-        trace_scoped!("close", "name:": path_display);
-        drop(f);
+        write_batched(&mut f, contents, sync)?;
     }
-    Ok(())
+    close(f, sync, &path_display)
 }
 
 #[allow(unused_variables)]
@@ -396,6 +494,7 @@ pub(crate) fn write_file_incremental<P: AsRef<Path>, F: Fn(usize)>(
     path: P,
     content_callback: &mut IncrementalFile,
     mode: u32,
+    sync: bool,
     chunk_complete_callback: F,
 ) -> io::Result<()> {
     let mut opts = OpenOptions::new();
@@ -424,7 +523,7 @@ pub(crate) fn write_file_incremental<P: AsRef<Path>, F: Fn(usize)>(
                 break;
             } else {
                 trace_scoped!("write_segment", "name": path_display, "len": len);
-                f.write_all(&contents)?;
+                write_batched(&mut f, &contents, sync)?;
                 drop(contents);
                 chunk_complete_callback(len);
             }
@@ -432,10 +531,44 @@ pub(crate) fn write_file_incremental<P: AsRef<Path>, F: Fn(usize)>(
     } else {
         unreachable!();
     }
-    {
-        trace_scoped!("close", "name:": path_display);
-        drop(f);
+    close(f, sync, &path_display)
+}
+
+/// How much of a file is written to a network file system before it is
+/// synced.
+const NETWORK_WRITE_BATCH: usize = 1024 * 1024;
+
+/// Writes `contents` to `f`, syncing after each batch of
+/// [`NETWORK_WRITE_BATCH`] bytes if `sync` is set.
+///
+/// A network file system client may otherwise hold many megabytes of a file
+/// that the server has yet to accept, and then stall writing them back all
+/// at once.
+fn write_batched(f: &mut File, contents: &[u8], sync: bool) -> io::Result<()> {
+    if !sync {
+        return f.write_all(contents);
+    }
+    for batch in contents.chunks(NETWORK_WRITE_BATCH) {
+        f.write_all(batch)?;
+        f.sync_data()?;
+    }
+    Ok(())
+}
+
+/// Closes a file that has been written.
+///
+/// A network file system may only report that writing back to the server
+/// failed, say for lack of space or quota, when the file is synced or
+/// closed, and dropping a `File` ignores errors from closing it. So on
+/// such storage files are synced first, lest a partly written file be
+/// taken for a good one.
+fn close(f: File, sync: bool, path_display: &str) -> io::Result<()> {
+    if sync {
+        trace_scoped!("sync", "name:": path_display);
+        f.sync_all()?;
     }
+    trace_scoped!("close", "name:": path_display);
+    drop(f);
     Ok(())
 }
 
@@ -446,21 +579,177 @@ pub(crate) fn create_dir<P: AsRef<Path>>(path: P) -> io::Result<()> {
     std::fs::create_dir(path)
 }
 
+/// Removes a file, or a link, retrying while a virus scanner or indexer has
+/// it open.
+pub(crate) fn remove_file(path: &Path) -> io::Result<()> {
+    let path_display = format!("{}", path.display());
+    trace_scoped!("remove_file", "name": path_display);
+    retry_while_in_use(|| match fs::remove_file(path) {
+        #[cfg(windows)]
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => remove_file_windows(path),
+        result => result,
+    })
+}
+
+/// Removes what `fs::remove_file` was denied: read-only files, and links to
+/// directories, which Windows removes as directories.
+#[cfg(windows)]
+fn remove_file_windows(path: &Path) -> io::Result<()> {
+    use std::os::windows::fs::FileTypeExt;
+
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink_dir() {
+        return fs::remove_dir(path);
+    }
+    let mut permissions = metadata.permissions();
+    if permissions.readonly() {
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(path, permissions)?;
+    }
+    fs::remove_file(path)
+}
+
+/// Removes an empty directory, retrying while it is in use. On Windows a
+/// directory also stays non-empty for as long as a file removed from it is
+/// still open.
+fn remove_empty_dir(path: &Path) -> io::Result<()> {
+    retry_while_in_use(|| match fs::remove_dir(path) {
+        #[cfg(windows)]
+        Err(e) if e.kind() == io::ErrorKind::DirectoryNotEmpty => {
+            Err(io::Error::from(io::ErrorKind::PermissionDenied))
+        }
+        result => result,
+    })
+}
+
+/// Retries `op` while it is denied permission, which is how a file that
+/// another program has open shows up on Windows.
+fn retry_while_in_use(mut op: impl FnMut() -> io::Result<()>) -> io::Result<()> {
+    retry(
+        Fibonacci::from_millis(1).map(jitter).take(10),
+        || match op() {
+            Ok(()) => OperationResult::Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => OperationResult::Retry(e),
+            Err(e) => OperationResult::Err(e),
+        },
+    )
+    .map_err(|e| e.error)
+}
+
+/// Removes `path` and everything in it, with the files removed on the IO
+/// threads and the progress reported in files. A link is removed, not
+/// what it links to.
+pub(crate) fn remove_dir_all(
+    path: &Path,
+    io_profile: IoProfile,
+    threads: Option<usize>,
+    notify_handler: Option<&dyn Fn(Notification<'_>)>,
+    process: &Process,
+) -> Result<()> {
+    // Removing needs no buffers, so the executor gets the least it takes.
+    const REMOVE_RAM: usize = 32 * 1024 * 1024;
+
+    if fs::symlink_metadata(path)?.file_type().is_symlink() {
+        return Ok(crate::utils::raw::remove_dir(path)?);
+    }
+
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    list_tree(path.to_owned(), &mut files, &mut dirs)?;
+
+    let io_threads = io_threads(io_profile, Storage::of(path), threads, process)?;
+    let mut io_executor = get_executor(None, REMOVE_RAM, io_threads);
+    if let Some(h) = notify_handler {
+        h(Notification::DownloadPushUnit(Unit::IO));
+        h(Notification::DownloadContentLengthReceived(
+            files.len() as u64
+        ));
+    }
+    let mut result = Ok(());
+    let mut finish = |op: CompletedIo| {
+        if let CompletedIo::Item(item) = op {
+            if let Some(h) = notify_handler {
+                h(Notification::DownloadDataReceived(&[0]));
+            }
+            if result.is_ok() {
+                result = item
+                    .result
+                    .with_context(|| format!("could not remove '{}'", item.full_path.display()));
+            }
+        }
+    };
+    for file in files {
+        for op in io_executor.execute(Item::remove_file(file)) {
+            finish(op);
+        }
+    }
+    for op in io_executor.join() {
+        finish(op);
+    }
+    if let Some(h) = notify_handler {
+        h(Notification::DownloadFinished);
+        h(Notification::DownloadPopUnit);
+    }
+    result?;
+
+    for dir in &dirs {
+        remove_empty_dir(dir).with_context(|| format!("could not remove '{}'", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Lists the files and links below `dir` into `files`, and `dir` and the
+/// directories below it into `dirs`, each directory after its contents.
+fn list_tree(dir: PathBuf, files: &mut Vec<PathBuf>, dirs: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        match entry.file_type()?.is_dir() {
+            true => list_tree(entry.path(), files, dirs)?,
+            false => files.push(entry.path()),
+        }
+    }
+    dirs.push(dir);
+    Ok(())
+}
+
+/// How many threads to do disk IO with, for the given profile and storage.
+/// `threads`, which `rustup --jobs` sets, takes precedence over
+/// `RUSTUP_IO_THREADS`, which takes precedence over both.
+pub(crate) fn io_threads(
+    profile: IoProfile,
+    storage: Storage,
+    threads: Option<usize>,
+    process: &Process,
+) -> Result<usize> {
+    if let Some(threads) = threads {
+        return Ok(threads);
+    }
+    if let Ok(n) = process.var("RUSTUP_IO_THREADS") {
+        return n
+            .parse::<usize>()
+            .context("invalid value in RUSTUP_IO_THREADS. Must be a natural number");
+    }
+    let cpus = available_parallelism().map(|p| p.get()).unwrap_or(1);
+    Ok(match (profile, storage) {
+        (IoProfile::LowMemory, _) => 1,
+        // Latency rather than CPU limits a network file system, so more
+        // service points than cores brings more throughput; see above.
+        (IoProfile::MaxThroughput, _) | (IoProfile::Auto, Storage::Network) => cpus * 2,
+        // Concurrent writes only make a spinning disk seek.
+        (IoProfile::Auto, Storage::Rotational) => cpus.min(2),
+        (IoProfile::Auto, Storage::Solid | Storage::Unknown) => cpus,
+    })
+}
+
 /// Get the executor for disk IO.
 pub(crate) fn get_executor<'a>(
     notify_handler: Option<&'a dyn Fn(Notification<'_>)>,
     ram_budget: usize,
-    process: &Process,
-) -> Result<Box<dyn Executor + 'a>> {
-    // If this gets lots of use, consider exposing via the config file.
-    let thread_count = match process.var("RUSTUP_IO_THREADS") {
-        Err(_) => available_parallelism().map(|p| p.get()).unwrap_or(1),
-        Ok(n) => n
-            .parse::<usize>()
-            .context("invalid value in RUSTUP_IO_THREADS. Must be a natural number")?,
-    };
-    Ok(match thread_count {
+    thread_count: usize,
+) -> Box<dyn Executor + 'a> {
+    match thread_count {
         0 | 1 => Box::new(immediate::ImmediateUnpacker::new()),
         n => Box::new(threaded::Threaded::new(notify_handler, n, ram_budget)),
-    })
+    }
 }
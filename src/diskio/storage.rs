@@ -0,0 +1,119 @@
+//! Telling what kind of storage a directory is on, so that unpacking can be
+//! tuned for it.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+
+/// The kind of storage a directory is on, as far as unpacking cares.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Storage {
+    /// An SSD, or anything else that doesn't mind concurrent writes.
+    Solid,
+    /// A spinning disk, which seeks between concurrent writes.
+    Rotational,
+    /// A network file system, where every create and close waits on the
+    /// network.
+    Network,
+    Unknown,
+}
+
+impl Storage {
+    pub(crate) fn of(path: &Path) -> Self {
+        if is_network(path) {
+            return Self::Network;
+        }
+        match is_rotational(path) {
+            Some(true) => Self::Rotational,
+            Some(false) => Self::Solid,
+            None => Self::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Solid => "solid-state storage",
+            Self::Rotational => "a spinning disk",
+            Self::Network => "a network file system",
+            Self::Unknown => "storage of unknown kind",
+        })
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn statfs(path: &Path) -> Option<libc::statfs> {
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` has room for a `statfs`.
+    if unsafe { libc::statfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: `statfs` succeeded, so it filled in `stat`.
+    Some(unsafe { stat.assume_init() })
+}
+
+#[cfg(target_os = "linux")]
+fn is_network(path: &Path) -> bool {
+    // From linux/magic.h, and the kernel's SMB client for CIFS and SMB2.
+    // FUSE is most often sshfs or similar.
+    const NETWORK_FS_MAGIC: &[u32] = &[
+        0x6969,      // NFS
+        0x517b,      // SMB
+        0xff53_4d42, // CIFS
+        0xfe53_4d42, // SMB2
+        0x00c3_6400, // Ceph
+        0x0102_1997, // 9p, as used by WSL 2 for Windows drives
+        0x5346_414f, // AFS
+        0x6573_5546, // FUSE
+    ];
+    // `f_type` is signed on some targets, so compare the low 32 bits.
+    statfs(path).is_some_and(|stat| NETWORK_FS_MAGIC.contains(&(stat.f_type as u32)))
+}
+
+#[cfg(target_os = "macos")]
+fn is_network(path: &Path) -> bool {
+    use std::ffi::CStr;
+
+    statfs(path).is_some_and(|stat| {
+        // SAFETY: the kernel NUL-terminates `f_fstypename`.
+        let name = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+        matches!(
+            name.to_bytes(),
+            b"nfs" | b"smbfs" | b"afpfs" | b"webdav" | b"macfuse" | b"osxfuse"
+        )
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn is_network(_: &Path) -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn is_rotational(path: &Path) -> Option<bool> {
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = fs::metadata(path).ok()?.dev();
+    // SAFETY: these only pick bits out of `dev`.
+    let (major, minor) = unsafe { (libc::major(dev), libc::minor(dev)) };
+    let device = fs::canonicalize(format!("/sys/dev/block/{major}:{minor}")).ok()?;
+    // A partition has no queue of its own, but the disk it is on does.
+    let queue = [device.clone(), device.parent()?.to_owned()]
+        .into_iter()
+        .find_map(|device| fs::read_to_string(device.join("queue/rotational")).ok())?;
+    Some(queue.trim() == "1")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_rotational(_: &Path) -> Option<bool> {
+    None
+}
@@ -1,10 +1,12 @@
+use std::cell::Cell;
 use std::collections::HashMap;
 
 use anyhow::Result;
 
-use super::{get_executor, Executor, Item, Kind};
+use super::{get_executor, storage::Storage, Executor, IoProfile, Item, Kind};
 use crate::process::TestProcess;
 use crate::test::test_dir;
+use crate::utils::notifications::Notification;
 
 impl Item {
     /// The length of the file, for files (for stats)
@@ -16,7 +18,7 @@ impl Item {
     }
 }
 
-fn test_incremental_file(io_threads: &str) -> Result<()> {
+fn test_incremental_file(io_threads: &str, sync: bool) -> Result<()> {
     let work_dir = test_dir()?;
     let mut vars = HashMap::new();
     vars.insert("RUSTUP_IO_THREADS".to_string(), io_threads.to_string());
@@ -24,10 +26,12 @@ fn test_incremental_file(io_threads: &str) -> Result<()> {
 
     let mut written = 0;
     let mut file_finished = false;
-    let mut io_executor: Box<dyn Executor> = get_executor(None, 32 * 1024 * 1024, &tp.process)?;
+    let threads = super::io_threads(IoProfile::Auto, Storage::Unknown, None, &tp.process)?;
+    let mut io_executor: Box<dyn Executor> = get_executor(None, 32 * 1024 * 1024, threads);
     let (item, mut sender) = Item::write_file_segmented(
         work_dir.path().join("scratch"),
         0o666,
+        sync,
         io_executor.incremental_file_state(),
     )?;
 
@@ -84,18 +88,19 @@ fn test_incremental_file(io_threads: &str) -> Result<()> {
     Ok(())
 }
 
-fn test_complete_file(io_threads: &str) -> Result<()> {
+fn test_complete_file(io_threads: &str, sync: bool) -> Result<()> {
     let work_dir = test_dir()?;
     let mut vars = HashMap::new();
     vars.insert("RUSTUP_IO_THREADS".to_string(), io_threads.to_string());
     let tp = TestProcess::with_vars(vars);
 
-    let mut io_executor: Box<dyn Executor> = get_executor(None, 32 * 1024 * 1024, &tp.process)?;
+    let threads = super::io_threads(IoProfile::Auto, Storage::Unknown, None, &tp.process)?;
+    let mut io_executor: Box<dyn Executor> = get_executor(None, 32 * 1024 * 1024, threads);
     let mut chunk = io_executor.get_buffer(10);
     chunk.extend(b"0123456789");
     assert_eq!(chunk.len(), 10);
     chunk = chunk.finished();
-    let item = Item::write_file(work_dir.path().join("scratch"), 0o666, chunk);
+    let item = Item::write_file(work_dir.path().join("scratch"), 0o666, sync, chunk);
     assert_eq!(item.size(), Some(10));
     let mut items = 0;
     let mut check_item = |item: Item| {
@@ -144,20 +149,140 @@ fn test_complete_file(io_threads: &str) -> Result<()> {
 
 #[test]
 fn test_incremental_file_immediate() {
-    test_incremental_file("1").unwrap()
+    test_incremental_file("1", false).unwrap()
 }
 
 #[test]
 fn test_incremental_file_threaded() {
-    test_incremental_file("2").unwrap()
+    test_incremental_file("2", false).unwrap()
+}
+
+#[test]
+fn test_incremental_file_synced() {
+    test_incremental_file("1", true).unwrap();
+    test_incremental_file("2", true).unwrap()
 }
 
 #[test]
 fn test_complete_file_immediate() {
-    test_complete_file("1").unwrap()
+    test_complete_file("1", false).unwrap()
 }
 
 #[test]
 fn test_complete_file_threaded() {
-    test_complete_file("2").unwrap()
+    test_complete_file("2", false).unwrap()
+}
+
+#[test]
+fn test_complete_file_synced() {
+    test_complete_file("1", true).unwrap();
+    test_complete_file("2", true).unwrap()
+}
+
+#[test]
+fn test_synced_write_in_batches() {
+    let work_dir = test_dir().unwrap();
+    let path = work_dir.path().join("scratch");
+    let contents = (0..super::NETWORK_WRITE_BATCH * 5 / 2)
+        .map(|i| i as u8)
+        .collect::<Vec<_>>();
+    super::write_file(&path, &contents, 0o666, true).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), contents);
+}
+
+#[test]
+fn test_io_threads_follow_profile_and_storage() {
+    let tp = TestProcess::default();
+    let threads =
+        |profile, storage| super::io_threads(profile, storage, None, &tp.process).unwrap();
+    let cpus = threads(IoProfile::Auto, Storage::Solid);
+
+    assert_eq!(threads(IoProfile::LowMemory, Storage::Network), 1);
+    assert_eq!(threads(IoProfile::Auto, Storage::Network), cpus * 2);
+    assert_eq!(threads(IoProfile::Auto, Storage::Rotational), cpus.min(2));
+    assert_eq!(
+        threads(IoProfile::MaxThroughput, Storage::Rotational),
+        cpus * 2
+    );
+
+    let mut vars = HashMap::new();
+    vars.insert("RUSTUP_IO_THREADS".to_string(), "3".to_string());
+    let tp = TestProcess::with_vars(vars);
+    assert_eq!(
+        super::io_threads(IoProfile::LowMemory, Storage::Solid, None, &tp.process).unwrap(),
+        3
+    );
+    // `--jobs` overrides the environment.
+    assert_eq!(
+        super::io_threads(IoProfile::LowMemory, Storage::Solid, Some(5), &tp.process).unwrap(),
+        5
+    );
+}
+
+fn test_remove_dir_all(io_threads: &str) -> Result<()> {
+    let work_dir = test_dir()?;
+    let mut vars = HashMap::new();
+    vars.insert("RUSTUP_IO_THREADS".to_string(), io_threads.to_string());
+    let tp = TestProcess::with_vars(vars);
+
+    let toolchain = work_dir.path().join("toolchain");
+    for i in 0..20 {
+        let dir = toolchain.join(format!("share/doc/{i}"));
+        std::fs::create_dir_all(&dir)?;
+        for j in 0..10 {
+            std::fs::write(dir.join(format!("{j}.html")), "docs")?;
+        }
+    }
+    std::fs::create_dir(toolchain.join("empty"))?;
+    let outside = work_dir.path().join("outside");
+    std::fs::create_dir(&outside)?;
+    std::fs::write(outside.join("kept"), "kept")?;
+    crate::utils::symlink_dir(&outside, &toolchain.join("link"), &|_: Notification<'_>| ())?;
+
+    let files = Cell::new(None);
+    let removed = Cell::new(0);
+    let notify = |n: Notification<'_>| match n {
+        Notification::DownloadContentLengthReceived(n) => files.set(Some(n)),
+        Notification::DownloadDataReceived(data) => removed.set(removed.get() + data.len()),
+        _ => {}
+    };
+    super::remove_dir_all(
+        &toolchain,
+        IoProfile::Auto,
+        None,
+        Some(&notify),
+        &tp.process,
+    )?;
+
+    assert!(!toolchain.exists());
+    assert_eq!(files.get(), Some(201));
+    assert_eq!(removed.get(), 201);
+    assert!(outside.join("kept").exists());
+    Ok(())
+}
+
+#[test]
+fn test_remove_dir_all_immediate() {
+    test_remove_dir_all("1").unwrap()
+}
+
+#[test]
+fn test_remove_dir_all_threaded() {
+    test_remove_dir_all("4").unwrap()
+}
+
+#[test]
+fn test_remove_dir_all_of_link() {
+    let work_dir = test_dir().unwrap();
+    let tp = TestProcess::default();
+    let target = work_dir.path().join("target");
+    std::fs::create_dir(&target).unwrap();
+    std::fs::write(target.join("kept"), "kept").unwrap();
+    let link = work_dir.path().join("link");
+    crate::utils::symlink_dir(&target, &link, &|_: Notification<'_>| ()).unwrap();
+
+    super::remove_dir_all(&link, IoProfile::Auto, None, None, &tp.process).unwrap();
+
+    assert!(!link.exists());
+    assert!(target.join("kept").exists());
 }
@@ -55,18 +55,14 @@ impl AsRef<[u8]> for PoolReference {
     }
 }
 
+#[derive(Default)]
 enum Task {
     Request(CompletedIo),
     // Used to synchronise in the join method.
+    #[default]
     Sentinel,
 }
 
-impl Default for Task {
-    fn default() -> Self {
-        Self::Sentinel
-    }
-}
-
 struct Pool {
     pool: Arc<sharded_slab::Pool<Vec<u8>>>,
     high_watermark: RefCell<usize>,
@@ -191,6 +187,7 @@ impl<'a> Threaded<'a> {
                 super::Kind::Directory => return,
                 super::Kind::File(content) => content.len(),
                 super::Kind::IncrementalFile(_) => return,
+                super::Kind::RemoveFile => return,
             },
             CompletedIo::Chunk(_) => super::IO_CHUNK_SIZE,
         };
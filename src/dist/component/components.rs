@@ -11,7 +11,6 @@ use crate::dist::component::package::{INSTALLER_VERSION, VERSION_FILE};
 use crate::dist::component::transaction::Transaction;
 use crate::dist::prefix::InstallPrefix;
 use crate::errors::RustupError;
-use crate::process::Process;
 use crate::utils;
 
 const COMPONENTS_FILE: &str = "components";
@@ -85,7 +84,7 @@ impl Components {
     }
     pub fn find(&self, name: &str) -> Result<Option<Component>> {
         let result = self.list()?;
-        Ok(result.into_iter().find(|c| (c.name() == name)))
+        Ok(result.into_iter().find(|c| c.name() == name))
     }
     pub(crate) fn prefix(&self) -> InstallPrefix {
         self.prefix.clone()
@@ -188,18 +187,14 @@ impl Component {
         }
         Ok(result)
     }
-    pub fn uninstall<'a>(
-        &self,
-        mut tx: Transaction<'a>,
-        process: &Process,
-    ) -> Result<Transaction<'a>> {
+    pub fn uninstall<'a>(&self, mut tx: Transaction<'a>) -> Result<Transaction<'a>> {
         // Update components file
         let path = self.components.rel_components_file();
         let abs_path = self.components.prefix.abs_path(&path);
         let temp = tx.temp().new_file()?;
         utils::filter_file("components", &abs_path, &temp, |l| l != self.name)?;
         tx.modify_file(path)?;
-        utils::rename("components", &temp, &abs_path, tx.notify_handler(), process)?;
+        utils::rename("components", &temp, &abs_path, tx.notify_handler())?;
 
         // TODO: If this is the last component remove the components file
         // and the version file.
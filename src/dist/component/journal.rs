@@ -0,0 +1,249 @@
+//! A record of the progress of a fresh toolchain install, so that an
+//! install that was interrupted can be resumed.
+//!
+//! The journal lists the components the install is going to end up
+//! with, and those that have been installed so far along with the hash
+//! of their archive. The paths added by the component that is being
+//! installed are appended to a separate log, since a component can
+//! add thousands of files. When the install is resumed these paths
+//! are removed, and the components that were already installed from
+//! the same archives are kept as they are.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::dist::component::Components;
+use crate::dist::manifest::Component;
+use crate::dist::notifications::Notification;
+use crate::dist::prefix::InstallPrefix;
+use crate::utils;
+
+const JOURNAL_FILE: &str = "rustup-journal.toml";
+const ADDED_FILE: &str = "rustup-journal-added";
+
+#[derive(Debug)]
+pub(crate) struct Journal {
+    prefix: InstallPrefix,
+    state: JournalState,
+    /// How many of the installed components were installed before the
+    /// install was resumed.
+    resumed: usize,
+    /// The log of the paths added by the component being installed.
+    added: Option<File>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct JournalState {
+    /// The date of the manifest that is being installed.
+    date: String,
+    /// All the components the install will end up with.
+    components: Vec<Component>,
+    #[serde(default)]
+    installed: Vec<InstalledComponent>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct InstalledComponent {
+    component: Component,
+    hash: String,
+}
+
+impl Journal {
+    /// Starts the journal of a fresh install of `components` from the
+    /// manifest of `date`.
+    pub(crate) fn begin(
+        prefix: &InstallPrefix,
+        date: &str,
+        components: Vec<Component>,
+    ) -> Result<Self> {
+        let journal = Self {
+            prefix: prefix.clone(),
+            state: JournalState {
+                date: date.to_owned(),
+                components,
+                installed: Vec::new(),
+            },
+            resumed: 0,
+            added: None,
+        };
+        journal.save()?;
+        Ok(journal)
+    }
+
+    /// Reads the journal of an interrupted install, if there is one.
+    pub(crate) fn load(prefix: &InstallPrefix) -> Result<Option<Self>> {
+        let path = prefix.manifest_file(JOURNAL_FILE);
+        if !utils::is_file(&path) {
+            return Ok(None);
+        }
+        let data = utils::read_file("install journal", &path)?;
+        let state = toml::from_str(&data).context("error parsing install journal")?;
+        Ok(Some(Self {
+            prefix: prefix.clone(),
+            state,
+            resumed: 0,
+            added: None,
+        }))
+    }
+
+    /// Whether the interrupted install can be carried on with the
+    /// manifest of `date`: the components registered in `installation`
+    /// must be exactly those that the journal has seen installed.
+    pub(crate) fn can_resume(&self, date: &str, installation: &Components) -> Result<bool> {
+        if self.state.date != date {
+            return Ok(false);
+        }
+        let mut registered = installation
+            .list()?
+            .iter()
+            .map(|c| c.name().to_owned())
+            .collect::<Vec<_>>();
+        let mut installed = self
+            .state
+            .installed
+            .iter()
+            .map(|i| i.component.name_in_manifest())
+            .collect::<Vec<_>>();
+        registered.sort();
+        installed.sort();
+        Ok(registered == installed)
+    }
+
+    /// Removes whatever the component that was being installed when the
+    /// install was interrupted had added, so that it can be installed
+    /// again.
+    pub(crate) fn resume(&mut self, notify_handler: &dyn Fn(Notification<'_>)) -> Result<()> {
+        let prefix = &self.prefix;
+        let added_path = prefix.manifest_file(ADDED_FILE);
+        if utils::is_file(&added_path) {
+            let added = utils::read_file("install journal", &added_path)?;
+            // The last line is only complete if the path was logged
+            // before the install was interrupted.
+            let complete = match added.rfind('\n') {
+                Some(end) => &added[..end],
+                None => "",
+            };
+            for relpath in complete.lines() {
+                let path = prefix.abs_path(relpath);
+                match fs::symlink_metadata(&path) {
+                    Ok(meta) if meta.is_dir() => {
+                        utils::remove_dir("component", &path, notify_handler)?
+                    }
+                    Ok(_) => utils::remove_file("component", &path)?,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err(e).with_context(|| format!("can't read '{}'", path.display()))
+                    }
+                }
+            }
+            utils::remove_file("install journal", &added_path)?;
+        }
+        self.resumed = self.state.installed.len();
+        Ok(())
+    }
+
+    /// Removes everything in `prefix`, so that an interrupted install
+    /// that can't be resumed starts over.
+    pub(crate) fn discard(
+        prefix: &InstallPrefix,
+        notify_handler: &dyn Fn(Notification<'_>),
+    ) -> Result<()> {
+        for entry in utils::read_dir("toolchain", prefix.path())? {
+            let path = entry?.path();
+            match fs::symlink_metadata(&path)?.is_dir() {
+                true => utils::remove_dir("toolchain", &path, notify_handler)?,
+                false => utils::remove_file("toolchain", &path)?,
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn components(&self) -> &[Component] {
+        &self.state.components
+    }
+
+    /// The names of the components that were installed before the
+    /// install was resumed.
+    pub(crate) fn resumed_components(&self) -> impl Iterator<Item = String> + '_ {
+        self.state.installed[..self.resumed]
+            .iter()
+            .map(|i| i.component.name_in_manifest())
+    }
+
+    /// Whether `component` was installed from the archive with `hash`.
+    pub(crate) fn is_installed(&self, component: &Component, hash: &str) -> bool {
+        self.state
+            .installed
+            .iter()
+            .any(|i| i.component == *component && i.hash == hash)
+    }
+
+    /// Logs that `relpath` is about to be added by the component that is
+    /// being installed.
+    pub(crate) fn add(&mut self, relpath: &Path) -> Result<()> {
+        let path = self.prefix.manifest_file(ADDED_FILE);
+        let mut file = match self.added.take() {
+            Some(file) => file,
+            None => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("can't open '{}'", path.display()))?,
+        };
+        let line = format!("{}\n", relpath.display());
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("can't write '{}'", path.display()))?;
+        self.added = Some(file);
+        Ok(())
+    }
+
+    /// Logs that `component` has been installed from the archive with
+    /// `hash`.
+    pub(crate) fn component_installed(&mut self, component: &Component, hash: &str) -> Result<()> {
+        self.state.installed.push(InstalledComponent {
+            component: component.clone(),
+            hash: hash.to_owned(),
+        });
+        self.save()?;
+        self.clear_added()
+    }
+
+    /// Forgets the components installed since the install was resumed,
+    /// once their changes have been rolled back.
+    pub(crate) fn roll_back(&mut self) -> Result<()> {
+        self.state.installed.truncate(self.resumed);
+        self.save()?;
+        self.clear_added()
+    }
+
+    /// Removes the journal once the install is complete.
+    pub(crate) fn finish(mut self) -> Result<()> {
+        self.clear_added()?;
+        let path = self.prefix.manifest_file(JOURNAL_FILE);
+        utils::ensure_file_removed("install journal", &path)
+    }
+
+    fn clear_added(&mut self) -> Result<()> {
+        self.added = None;
+        utils::ensure_file_removed("install journal", &self.prefix.manifest_file(ADDED_FILE))
+    }
+
+    /// Replaces the journal file in a single step, so that it is never
+    /// left half written.
+    fn save(&self) -> Result<()> {
+        let dir = self.prefix.manifest_dir();
+        utils::ensure_dir_exists("install journal", &dir, &|_: Notification<'_>| {})?;
+        let path = self.prefix.manifest_file(JOURNAL_FILE);
+        let tmp_path = path.with_extension("toml.tmp");
+        let mut file = File::create(&tmp_path)
+            .with_context(|| format!("can't create '{}'", tmp_path.display()))?;
+        file.write_all(toml::to_string(&self.state)?.as_bytes())
+            .and_then(|()| file.sync_all())
+            .with_context(|| format!("can't write '{}'", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path).with_context(|| format!("can't write '{}'", path.display()))
+    }
+}
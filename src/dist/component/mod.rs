@@ -5,12 +5,16 @@ pub use self::package::*;
 /// https://github.com/rust-lang/rust-installer
 pub use self::transaction::*;
 
+pub(crate) use self::journal::Journal;
+
 // Transactional file system tools
 mod transaction;
 // The representation of a package, its components, and installation
 mod package;
 // The representation of *installed* components, and uninstallation
 mod components;
+// The record of an install's progress, for resuming it
+mod journal;
 
 #[cfg(test)]
 mod tests;
@@ -12,7 +12,10 @@ use anyhow::{anyhow, bail, Context, Result};
 use tar::EntryType;
 use tracing::warn;
 
-use crate::diskio::{get_executor, CompletedIo, Executor, FileBuffer, Item, Kind, IO_CHUNK_SIZE};
+use crate::diskio::{
+    get_executor, io_threads, storage::Storage, CompletedIo, Executor, FileBuffer, IoProfile, Item,
+    Kind, IO_CHUNK_SIZE,
+};
 use crate::dist::component::components::*;
 use crate::dist::component::transaction::*;
 use crate::dist::temp;
@@ -24,6 +27,11 @@ use crate::utils::{self, Notification};
 pub(crate) const INSTALLER_VERSION: &str = "3";
 pub(crate) const VERSION_FILE: &str = "rust-installer-version";
 
+/// Far more entries than any component has; rust-docs has tens of thousands.
+const MAX_ENTRIES: usize = 1_000_000;
+/// Far more than any component unpacks to.
+const MAX_UNPACKED_SIZE: u64 = 16 * 1024 * 1024 * 1024;
+
 pub trait Package: fmt::Debug {
     fn contains(&self, component: &str, short_name: Option<&str>) -> bool;
     fn install<'a>(
@@ -51,7 +59,14 @@ impl DirectoryPackage {
         let components = content
             .lines()
             .map(std::borrow::ToOwned::to_owned)
-            .collect();
+            .collect::<HashSet<_>>();
+        // Each component is a directory of the package.
+        if let Some(name) = components
+            .iter()
+            .find(|name| Path::new(name).components().count() != 1 || !is_contained(name))
+        {
+            bail!("package component name '{name}' is not supported");
+        }
         Ok(Self {
             path,
             components,
@@ -104,6 +119,9 @@ impl Package for DirectoryPackage {
                 .ok_or_else(|| RustupError::CorruptComponent(name.to_owned()))?;
 
             let path = part.1;
+            if !is_contained(&path) {
+                return Err(RustupError::CorruptComponent(name.to_owned()).into());
+            }
             let src_path = root.join(&path);
 
             match &*part.0 {
@@ -143,6 +161,8 @@ impl<'a> TarPackage<'a> {
     pub(crate) fn new<R: Read>(
         stream: R,
         tmp_cx: &'a temp::Context,
+        io_profile: IoProfile,
+        threads: Option<usize>,
         notify_handler: Option<&'a dyn Fn(Notification<'_>)>,
         process: &Process,
     ) -> Result<Self> {
@@ -151,8 +171,15 @@ impl<'a> TarPackage<'a> {
         // The rust-installer packages unpack to a directory called
         // $pkgname-$version-$target. Skip that directory when
         // unpacking.
-        unpack_without_first_dir(&mut archive, &temp_dir, notify_handler, process)
-            .context("failed to extract package")?;
+        unpack_without_first_dir(
+            &mut archive,
+            &temp_dir,
+            io_profile,
+            threads,
+            notify_handler,
+            process,
+        )
+        .context("failed to extract package")?;
 
         Ok(TarPackage(
             DirectoryPackage::new(temp_dir.to_owned(), false)?,
@@ -165,6 +192,7 @@ impl<'a> TarPackage<'a> {
 fn unpack_ram(
     io_chunk_size: usize,
     effective_max_ram: Option<usize>,
+    io_profile: IoProfile,
     notify_handler: Option<&dyn Fn(Notification<'_>)>,
     process: &Process,
 ) -> usize {
@@ -203,10 +231,14 @@ fn unpack_ram(
             }
         }
         None => {
+            let default_unpack_ram = match io_profile {
+                IoProfile::LowMemory => minimum_ram,
+                IoProfile::Auto | IoProfile::MaxThroughput => default_max_unpack_ram,
+            };
             if let Some(h) = notify_handler {
-                h(Notification::SetDefaultBufferSize(default_max_unpack_ram))
+                h(Notification::SetDefaultBufferSize(default_unpack_ram))
             }
-            default_max_unpack_ram
+            default_unpack_ram
         }
     };
 
@@ -281,6 +313,21 @@ fn trigger_children(
     Ok(result)
 }
 
+/// Whether `path` is relative and stays below the directory it is relative
+/// to, so that joining it to a prefix cannot escape the prefix.
+fn is_contained(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    // Some very early rust tarballs include a "." segment which we have to
+    // support, despite not liking it.
+    path.components().next().is_some()
+        && path.components().all(|part| {
+            matches!(
+                part,
+                std::path::Component::Normal(_) | std::path::Component::CurDir
+            )
+        })
+}
+
 /// What is the status of this directory ?
 enum DirStatus {
     Exists,
@@ -290,6 +337,8 @@ enum DirStatus {
 fn unpack_without_first_dir<R: Read>(
     archive: &mut tar::Archive<R>,
     path: &Path,
+    io_profile: IoProfile,
+    threads: Option<usize>,
     notify_handler: Option<&dyn Fn(Notification<'_>)>,
     process: &Process,
 ) -> Result<()> {
@@ -303,13 +352,29 @@ fn unpack_without_first_dir<R: Read>(
             None
         }
     };
-    let unpack_ram = unpack_ram(IO_CHUNK_SIZE, effective_max_ram, notify_handler, process);
-    let mut io_executor: Box<dyn Executor> = get_executor(notify_handler, unpack_ram, process)?;
+    let unpack_ram = unpack_ram(
+        IO_CHUNK_SIZE,
+        effective_max_ram,
+        io_profile,
+        notify_handler,
+        process,
+    );
+    let storage = Storage::of(path);
+    let sync_files = storage == Storage::Network;
+    let io_threads = io_threads(io_profile, storage, threads, process)?;
+    if let Some(h) = notify_handler {
+        h(Notification::IoTuning(
+            io_profile, storage, io_threads, unpack_ram,
+        ))
+    }
+    let mut io_executor: Box<dyn Executor> = get_executor(notify_handler, unpack_ram, io_threads);
 
     let mut directories: HashMap<PathBuf, DirStatus> = HashMap::new();
     // Path is presumed to exist. Call it a precondition.
     directories.insert(path.to_owned(), DirStatus::Exists);
 
+    let mut count = 0;
+    let mut unpacked_size = 0;
     'entries: for entry in entries {
         // drain completed results to keep memory pressure low and respond
         // rapidly to completed events even if we couldn't submit work (because
@@ -327,13 +392,18 @@ fn unpack_without_first_dir<R: Read>(
             path.into_owned()
         };
         // Reject path components that are not normal (..|/| etc)
-        for part in relpath.components() {
-            match part {
-                // Some very early rust tarballs include a "." segment which we have to
-                // support, despite not liking it.
-                std::path::Component::Normal(_) | std::path::Component::CurDir => {}
-                _ => bail!(format!("tar path '{}' is not supported", relpath.display())),
-            }
+        if !is_contained(&relpath) {
+            bail!(format!("tar path '{}' is not supported", relpath.display()));
+        }
+        // Dist servers are user-configurable, so don't let an archive fill
+        // the disk either.
+        count += 1;
+        if count > MAX_ENTRIES {
+            bail!("tar archive has more than {MAX_ENTRIES} entries");
+        }
+        unpacked_size += entry.header().size()?;
+        if unpacked_size > MAX_UNPACKED_SIZE {
+            bail!("tar archive unpacks to more than {MAX_UNPACKED_SIZE} bytes");
         }
         let mut components = relpath.components();
         // Throw away the first path component: our root was supplied.
@@ -404,7 +474,15 @@ fn unpack_without_first_dir<R: Read>(
         // https://play.rust-lang.org/?version=stable&mode=debug&edition=2018&gist=a8549057f0827bf3a068d8917256765a
         // is a translation of the prior helper function into an in-iterator
         // application.
-        let tar_mode = entry.header().mode().ok().unwrap();
+        let tar_mode = entry.header().mode()?;
+        // Modes are normalised below, which drops these bits anyway, but
+        // nothing rustup installs should ask for them.
+        if kind == EntryType::Regular && tar_mode & 0o6000 != 0 {
+            bail!(format!(
+                "tar path '{}' is setuid or setgid, which is not supported",
+                relpath.display()
+            ));
+        }
         // That said, the tarballs that are shipped way back have single-user
         // permissions:
         // -rwx------ rustbuild/rustbuild  ..... release/test-release.sh
@@ -438,6 +516,7 @@ fn unpack_without_first_dir<R: Read>(
                     let (item, sender) = Item::write_file_segmented(
                         full_path.clone(),
                         mode,
+                        sync_files,
                         io_executor.incremental_file_state(),
                     )?;
                     incremental_file_sender = Some(sender);
@@ -446,7 +525,7 @@ fn unpack_without_first_dir<R: Read>(
                     let mut content = io_executor.get_buffer(size as usize);
                     entry.read_to_end(&mut content)?;
                     content = content.finished();
-                    Item::write_file(full_path.clone(), mode, content)
+                    Item::write_file(full_path.clone(), mode, sync_files, content)
                 }
             }
             _ => bail!(format!("tar entry kind '{kind:?}' is not supported")),
@@ -558,6 +637,8 @@ impl<'a> TarGzPackage<'a> {
     pub(crate) fn new<R: Read>(
         stream: R,
         tmp_cx: &'a temp::Context,
+        io_profile: IoProfile,
+        threads: Option<usize>,
         notify_handler: Option<&'a dyn Fn(Notification<'_>)>,
         process: &Process,
     ) -> Result<Self> {
@@ -565,6 +646,8 @@ impl<'a> TarGzPackage<'a> {
         Ok(TarGzPackage(TarPackage::new(
             stream,
             tmp_cx,
+            io_profile,
+            threads,
             notify_handler,
             process,
         )?))
@@ -596,6 +679,8 @@ impl<'a> TarXzPackage<'a> {
     pub(crate) fn new<R: Read>(
         stream: R,
         tmp_cx: &'a temp::Context,
+        io_profile: IoProfile,
+        threads: Option<usize>,
         notify_handler: Option<&'a dyn Fn(Notification<'_>)>,
         process: &Process,
     ) -> Result<Self> {
@@ -603,6 +688,8 @@ impl<'a> TarXzPackage<'a> {
         Ok(TarXzPackage(TarPackage::new(
             stream,
             tmp_cx,
+            io_profile,
+            threads,
             notify_handler,
             process,
         )?))
@@ -634,6 +721,8 @@ impl<'a> TarZStdPackage<'a> {
     pub(crate) fn new<R: Read>(
         stream: R,
         tmp_cx: &'a temp::Context,
+        io_profile: IoProfile,
+        threads: Option<usize>,
         notify_handler: Option<&'a dyn Fn(Notification<'_>)>,
         process: &Process,
     ) -> Result<Self> {
@@ -641,6 +730,8 @@ impl<'a> TarZStdPackage<'a> {
         Ok(TarZStdPackage(TarPackage::new(
             stream,
             tmp_cx,
+            io_profile,
+            threads,
             notify_handler,
             process,
         )?))
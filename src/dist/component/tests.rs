@@ -2,7 +2,8 @@ use std::fs;
 use std::io::Write;
 use std::path::PathBuf;
 
-use crate::dist::component::Transaction;
+use crate::diskio::IoProfile;
+use crate::dist::component::{Components, Package, TarPackage, Transaction};
 use crate::dist::prefix::InstallPrefix;
 use crate::dist::temp;
 use crate::dist::Notification;
@@ -25,8 +26,7 @@ fn add_file() {
     );
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let mut file = tx.add_file("c", PathBuf::from("foo/bar")).unwrap();
     write!(file, "test").unwrap();
@@ -54,8 +54,7 @@ fn add_file_then_rollback() {
     );
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     tx.add_file("c", PathBuf::from("foo/bar")).unwrap();
     drop(tx);
@@ -77,8 +76,7 @@ fn add_file_that_exists() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     fs::create_dir_all(prefixdir.path().join("foo")).unwrap();
     utils::write_file("", &prefixdir.path().join("foo/bar"), "").unwrap();
@@ -109,8 +107,7 @@ fn copy_file() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let srcpath = srcdir.path().join("bar");
     utils::write_file("", &srcpath, "").unwrap();
@@ -137,8 +134,7 @@ fn copy_file_then_rollback() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let srcpath = srcdir.path().join("bar");
     utils::write_file("", &srcpath, "").unwrap();
@@ -165,8 +161,7 @@ fn copy_file_that_exists() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let srcpath = srcdir.path().join("bar");
     utils::write_file("", &srcpath, "").unwrap();
@@ -202,8 +197,7 @@ fn copy_dir() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let srcpath1 = srcdir.path().join("foo");
     let srcpath2 = srcdir.path().join("bar/baz");
@@ -237,8 +231,7 @@ fn copy_dir_then_rollback() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let srcpath1 = srcdir.path().join("foo");
     let srcpath2 = srcdir.path().join("bar/baz");
@@ -272,8 +265,7 @@ fn copy_dir_that_exists() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     fs::create_dir_all(prefix.path().join("a")).unwrap();
 
@@ -304,8 +296,7 @@ fn remove_file() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let filepath = prefixdir.path().join("foo");
     utils::write_file("", &filepath, "").unwrap();
@@ -330,8 +321,7 @@ fn remove_file_then_rollback() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let filepath = prefixdir.path().join("foo");
     utils::write_file("", &filepath, "").unwrap();
@@ -356,8 +346,7 @@ fn remove_file_that_not_exists() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let err = tx.remove_file("c", PathBuf::from("foo")).unwrap_err();
 
@@ -384,8 +373,7 @@ fn remove_dir() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let filepath = prefixdir.path().join("foo/bar");
     fs::create_dir_all(filepath.parent().unwrap()).unwrap();
@@ -411,8 +399,7 @@ fn remove_dir_then_rollback() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let filepath = prefixdir.path().join("foo/bar");
     fs::create_dir_all(filepath.parent().unwrap()).unwrap();
@@ -438,8 +425,7 @@ fn remove_dir_that_not_exists() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let err = tx.remove_dir("c", PathBuf::from("foo")).unwrap_err();
 
@@ -452,6 +438,76 @@ fn remove_dir_that_not_exists() {
     }
 }
 
+#[test]
+fn removals_share_one_backup_dir() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let notify = |_: Notification<'_>| ();
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
+
+    for name in ["a", "b", "c"] {
+        utils::write_file("", &prefixdir.path().join(name), "").unwrap();
+    }
+    fs::create_dir(prefixdir.path().join("d")).unwrap();
+    utils::write_file("", &prefixdir.path().join("d/e"), "").unwrap();
+
+    for name in ["a", "b", "c"] {
+        tx.remove_file("c", PathBuf::from(name)).unwrap();
+    }
+    tx.remove_dir("c", PathBuf::from("d")).unwrap();
+    assert_eq!(fs::read_dir(txdir.path()).unwrap().count(), 1);
+
+    tx.commit();
+
+    assert_eq!(fs::read_dir(prefixdir.path()).unwrap().count(), 0);
+    assert_eq!(fs::read_dir(txdir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn removals_then_rollback() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+
+    let notify = |_: Notification<'_>| ();
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
+
+    for name in ["a", "b"] {
+        utils::write_file("", &prefixdir.path().join(name), name).unwrap();
+    }
+    fs::create_dir(prefixdir.path().join("d")).unwrap();
+    utils::write_file("", &prefixdir.path().join("d/e"), "e").unwrap();
+
+    tx.remove_file("c", PathBuf::from("a")).unwrap();
+    tx.remove_dir("c", PathBuf::from("d")).unwrap();
+    tx.remove_file("c", PathBuf::from("b")).unwrap();
+    // A removal that fails leaves the earlier ones to be rolled back.
+    tx.remove_file("c", PathBuf::from("missing")).unwrap_err();
+    drop(tx);
+
+    for name in ["a", "b", "d/e"] {
+        let path = prefixdir.path().join(name);
+        assert_eq!(fs::read_to_string(path).unwrap(), &name[name.len() - 1..]);
+    }
+    assert_eq!(fs::read_dir(txdir.path()).unwrap().count(), 0);
+}
+
 #[test]
 fn write_file() {
     let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
@@ -466,8 +522,7 @@ fn write_file() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let content = "hi".to_string();
     tx.write_file("c", PathBuf::from("foo/bar"), content.clone())
@@ -494,8 +549,7 @@ fn write_file_then_rollback() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let content = "hi".to_string();
     tx.write_file("c", PathBuf::from("foo/bar"), content)
@@ -519,8 +573,7 @@ fn write_file_that_exists() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let content = "hi".to_string();
     utils_raw::write_file(&prefix.path().join("a"), &content).unwrap();
@@ -551,8 +604,7 @@ fn modify_file_that_not_exists() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     tx.modify_file(PathBuf::from("foo/bar")).unwrap();
     tx.commit();
@@ -576,8 +628,7 @@ fn modify_file_that_exists() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let path = prefix.path().join("foo");
     utils_raw::write_file(&path, "wow").unwrap();
@@ -601,8 +652,7 @@ fn modify_file_that_not_exists_then_rollback() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     tx.modify_file(PathBuf::from("foo/bar")).unwrap();
     drop(tx);
@@ -624,8 +674,7 @@ fn modify_file_that_exists_then_rollback() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let path = prefix.path().join("foo");
     utils_raw::write_file(&path, "wow").unwrap();
@@ -652,8 +701,7 @@ fn modify_twice_then_rollback() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let path = prefix.path().join("foo");
     utils_raw::write_file(&path, "wow").unwrap();
@@ -680,8 +728,7 @@ fn do_multiple_op_transaction(rollback: bool) {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     // copy_file
     let relpath1 = PathBuf::from("bin/rustc");
@@ -782,8 +829,7 @@ fn rollback_failure_keeps_going() {
     let prefix = InstallPrefix::from(prefixdir.path());
 
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     write!(tx.add_file("", PathBuf::from("foo")).unwrap(), "").unwrap();
     write!(tx.add_file("", PathBuf::from("bar")).unwrap(), "").unwrap();
@@ -802,3 +848,169 @@ fn rollback_failure_keeps_going() {
 #[test]
 #[ignore]
 fn intermediate_dir_rollback() {}
+
+/// A tar entry, with its path written as is so that the archive can hold
+/// paths and modes that `tar::Builder` would refuse to write.
+fn tar_entry(
+    builder: &mut tar::Builder<Vec<u8>>,
+    path: &str,
+    kind: tar::EntryType,
+    mode: u32,
+    data: &[u8],
+) {
+    let mut header = tar::Header::new_gnu();
+    header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+    header.set_entry_type(kind);
+    header.set_mode(mode);
+    header.set_size(data.len() as u64);
+    header.set_cksum();
+    builder.append(&header, data).unwrap();
+}
+
+/// Unpacks an archive with a valid package around the given entries.
+fn unpack_with(
+    tmp_cx: &temp::Context,
+    entries: impl FnOnce(&mut tar::Builder<Vec<u8>>),
+) -> anyhow::Result<TarPackage<'_>> {
+    let mut builder = tar::Builder::new(Vec::new());
+    tar_entry(&mut builder, "pkg/", tar::EntryType::Directory, 0o755, b"");
+    tar_entry(
+        &mut builder,
+        "pkg/rust-installer-version",
+        tar::EntryType::Regular,
+        0o644,
+        b"3\n",
+    );
+    entries(&mut builder);
+    let archive = builder.into_inner().unwrap();
+
+    let tp = TestProcess::default();
+    TarPackage::new(
+        &archive[..],
+        tmp_cx,
+        IoProfile::Auto,
+        None,
+        None,
+        &tp.process,
+    )
+}
+
+fn assert_rejected(entries: impl FnOnce(&mut tar::Builder<Vec<u8>>), message: &str) {
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+    let err = format!("{:#}", unpack_with(&tmp_cx, entries).unwrap_err());
+    assert!(err.contains(message), "{err}");
+}
+
+#[test]
+fn unpack_rejects_escaping_paths() {
+    for path in ["/etc/passwd", "pkg/../../escape", "pkg/bin/../../../escape"] {
+        assert_rejected(
+            |b| tar_entry(b, path, tar::EntryType::Regular, 0o644, b""),
+            "is not supported",
+        );
+    }
+}
+
+#[test]
+fn unpack_rejects_links() {
+    for kind in [tar::EntryType::Symlink, tar::EntryType::Link] {
+        assert_rejected(
+            |b| {
+                let mut header = tar::Header::new_gnu();
+                header.set_path("pkg/lib").unwrap();
+                header.set_link_name("/usr/lib").unwrap();
+                header.set_entry_type(kind);
+                header.set_mode(0o777);
+                header.set_size(0);
+                header.set_cksum();
+                b.append(&header, &b""[..]).unwrap();
+            },
+            "is not supported",
+        );
+    }
+}
+
+#[test]
+fn unpack_rejects_setuid_files() {
+    for mode in [0o4755, 0o2755, 0o4777] {
+        assert_rejected(
+            |b| tar_entry(b, "pkg/rustc", tar::EntryType::Regular, mode, b""),
+            "is setuid or setgid",
+        );
+    }
+}
+
+#[test]
+fn unpack_rejects_absurd_sizes() {
+    assert_rejected(
+        |b| {
+            let mut header = tar::Header::new_gnu();
+            header.set_path("pkg/huge").unwrap();
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_mode(0o644);
+            header.set_size(1 << 40);
+            header.set_cksum();
+            // The size is checked before any data is read.
+            b.get_mut().extend_from_slice(header.as_bytes());
+        },
+        "unpacks to more than",
+    );
+}
+
+#[test]
+fn unpack_rejects_escaping_component_names() {
+    assert_rejected(
+        |b| {
+            tar_entry(
+                b,
+                "pkg/components",
+                tar::EntryType::Regular,
+                0o644,
+                b"../c\n",
+            )
+        },
+        "package component name '../c' is not supported",
+    );
+}
+
+#[test]
+fn install_rejects_escaping_manifest() {
+    let prefixdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let txdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let tmp_cx = temp::Context::new(
+        txdir.path().to_owned(),
+        DEFAULT_DIST_SERVER,
+        Box::new(|_| ()),
+    );
+    let package = unpack_with(&tmp_cx, |b| {
+        tar_entry(b, "pkg/components", tar::EntryType::Regular, 0o644, b"c\n");
+        tar_entry(b, "pkg/c/", tar::EntryType::Directory, 0o755, b"");
+        let manifest = b"file:../../escape\n";
+        tar_entry(
+            b,
+            "pkg/c/manifest.in",
+            tar::EntryType::Regular,
+            0o644,
+            manifest,
+        );
+    })
+    .unwrap();
+
+    let prefix = InstallPrefix::from(prefixdir.path());
+    let components = Components::open(prefix.clone()).unwrap();
+    let notify = |_: Notification<'_>| ();
+    let tx = Transaction::new(prefix, &tmp_cx, &notify);
+
+    let Err(err) = package.install(&components, "c", None, tx) else {
+        panic!("the package was installed");
+    };
+    match err.downcast::<RustupError>() {
+        Ok(RustupError::CorruptComponent(name)) => assert_eq!(name, "c"),
+        _ => panic!(),
+    }
+}
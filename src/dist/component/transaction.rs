@@ -14,11 +14,12 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
 
+use crate::dist::component::journal::Journal;
+use crate::dist::manifest::Component;
 use crate::dist::notifications::*;
 use crate::dist::prefix::InstallPrefix;
 use crate::dist::temp;
 use crate::errors::*;
-use crate::process::Process;
 use crate::utils;
 
 /// A Transaction tracks changes to the file system, allowing them to
@@ -28,6 +29,11 @@ use crate::utils;
 /// into place. If the transaction is committed, these files are
 /// automatically cleaned up using the temp system.
 ///
+/// Removing a component removes thousands of files, so everything the
+/// transaction removes goes into a single backup directory, which is
+/// deleted all at once when the transaction is done, rather than each
+/// file getting a temporary file of its own.
+///
 /// All operations that create files will automatically create any
 /// intermediate directories in the path to the file if they do not
 /// already exist.
@@ -40,7 +46,10 @@ pub struct Transaction<'a> {
     tmp_cx: &'a temp::Context,
     notify_handler: &'a dyn Fn(Notification<'_>),
     committed: bool,
-    process: &'a Process,
+    journal: Option<Journal>,
+    /// Where removed files and directories are kept until the transaction
+    /// is done, created with the first one.
+    backup: Option<temp::Dir<'a>>,
 }
 
 impl<'a> Transaction<'a> {
@@ -48,7 +57,6 @@ impl<'a> Transaction<'a> {
         prefix: InstallPrefix,
         tmp_cx: &'a temp::Context,
         notify_handler: &'a dyn Fn(Notification<'_>),
-        process: &'a Process,
     ) -> Self {
         Transaction {
             prefix,
@@ -56,7 +64,8 @@ impl<'a> Transaction<'a> {
             tmp_cx,
             notify_handler,
             committed: false,
-            process,
+            journal: None,
+            backup: None,
         }
     }
 
@@ -64,17 +73,57 @@ impl<'a> Transaction<'a> {
     /// called the transaction will be rolled back on drop.
     pub fn commit(mut self) {
         self.committed = true;
+        if let Some(journal) = self.journal.take() {
+            if let Err(e) = journal.finish() {
+                (self.notify_handler)(Notification::NonFatalError(&e));
+            }
+        }
+    }
+
+    /// Keeps `journal` up to date with the files this transaction adds,
+    /// so that an install that is interrupted can be resumed.
+    pub(crate) fn set_journal(&mut self, journal: Journal) {
+        self.journal = Some(journal);
+    }
+
+    /// Records in the journal, if any, that `component` has been
+    /// installed from the archive with `hash`.
+    pub(crate) fn component_installed(&mut self, component: &Component, hash: &str) -> Result<()> {
+        match &mut self.journal {
+            Some(journal) => journal.component_installed(component, hash),
+            None => Ok(()),
+        }
     }
 
     fn change(&mut self, item: ChangedItem<'a>) {
         self.changes.push(item);
     }
 
+    /// A path in the backup directory to move something that is removed
+    /// to, named after the change that removes it.
+    fn backup_path(&mut self) -> Result<PathBuf> {
+        let backup = match &self.backup {
+            Some(backup) => backup,
+            None => self.backup.insert(self.tmp_cx.new_directory()?),
+        };
+        Ok(backup.join(self.changes.len().to_string()))
+    }
+
+    /// Logs a path before it's added, so that a resumed install can
+    /// remove it even if the transaction never finds out it was added.
+    fn journal_add(&mut self, relpath: &Path) -> Result<()> {
+        match &mut self.journal {
+            Some(journal) => journal.add(relpath),
+            None => Ok(()),
+        }
+    }
+
     /// Add a file at a relative path to the install prefix. Returns a
     /// `File` that may be used to subsequently write the
     /// contents.
     pub fn add_file(&mut self, component: &str, relpath: PathBuf) -> Result<File> {
         assert!(relpath.is_relative());
+        self.journal_add(&relpath)?;
         let (item, file) = ChangedItem::add_file(&self.prefix, component, relpath)?;
         self.change(item);
         Ok(file)
@@ -83,6 +132,7 @@ impl<'a> Transaction<'a> {
     /// Copy a file to a relative path of the install prefix.
     pub fn copy_file(&mut self, component: &str, relpath: PathBuf, src: &Path) -> Result<()> {
         assert!(relpath.is_relative());
+        self.journal_add(&relpath)?;
         let item = ChangedItem::copy_file(&self.prefix, component, relpath, src)?;
         self.change(item);
         Ok(())
@@ -91,6 +141,7 @@ impl<'a> Transaction<'a> {
     /// Recursively copy a directory to a relative path of the install prefix.
     pub fn copy_dir(&mut self, component: &str, relpath: PathBuf, src: &Path) -> Result<()> {
         assert!(relpath.is_relative());
+        self.journal_add(&relpath)?;
         let item = ChangedItem::copy_dir(&self.prefix, component, relpath, src)?;
         self.change(item);
         Ok(())
@@ -99,13 +150,13 @@ impl<'a> Transaction<'a> {
     /// Remove a file from a relative path to the install prefix.
     pub fn remove_file(&mut self, component: &str, relpath: PathBuf) -> Result<()> {
         assert!(relpath.is_relative());
+        let backup = self.backup_path()?;
         let item = ChangedItem::remove_file(
             &self.prefix,
             component,
             relpath,
-            self.tmp_cx,
+            backup,
             self.notify_handler(),
-            self.process,
         )?;
         self.change(item);
         Ok(())
@@ -115,13 +166,13 @@ impl<'a> Transaction<'a> {
     /// install prefix.
     pub fn remove_dir(&mut self, component: &str, relpath: PathBuf) -> Result<()> {
         assert!(relpath.is_relative());
+        let backup = self.backup_path()?;
         let item = ChangedItem::remove_dir(
             &self.prefix,
             component,
             relpath,
-            self.tmp_cx,
+            backup,
             self.notify_handler(),
-            self.process,
         )?;
         self.change(item);
         Ok(())
@@ -131,6 +182,7 @@ impl<'a> Transaction<'a> {
     /// the install prefix.
     pub fn write_file(&mut self, component: &str, relpath: PathBuf, content: String) -> Result<()> {
         assert!(relpath.is_relative());
+        self.journal_add(&relpath)?;
         let (item, mut file) = ChangedItem::add_file(&self.prefix, component, relpath.clone())?;
         self.change(item);
         utils::write_str(
@@ -161,14 +213,9 @@ impl<'a> Transaction<'a> {
         src: &Path,
     ) -> Result<()> {
         assert!(relpath.is_relative());
-        let item = ChangedItem::move_file(
-            &self.prefix,
-            component,
-            relpath,
-            src,
-            self.notify_handler(),
-            self.process,
-        )?;
+        self.journal_add(&relpath)?;
+        let item =
+            ChangedItem::move_file(&self.prefix, component, relpath, src, self.notify_handler())?;
         self.change(item);
         Ok(())
     }
@@ -176,14 +223,9 @@ impl<'a> Transaction<'a> {
     /// Recursively move a directory to a relative path of the install prefix.
     pub(crate) fn move_dir(&mut self, component: &str, relpath: PathBuf, src: &Path) -> Result<()> {
         assert!(relpath.is_relative());
-        let item = ChangedItem::move_dir(
-            &self.prefix,
-            component,
-            relpath,
-            src,
-            self.notify_handler(),
-            self.process,
-        )?;
+        self.journal_add(&relpath)?;
+        let item =
+            ChangedItem::move_dir(&self.prefix, component, relpath, src, self.notify_handler())?;
         self.change(item);
         Ok(())
     }
@@ -205,13 +247,18 @@ impl Drop for Transaction<'_> {
             for item in self.changes.iter().rev() {
                 // ok_ntfy!(self.notify_handler,
                 //          Notification::NonFatalError,
-                match item.roll_back(&self.prefix, self.notify_handler(), self.process) {
+                match item.roll_back(&self.prefix, self.notify_handler()) {
                     Ok(()) => {}
                     Err(e) => {
                         (self.notify_handler)(Notification::NonFatalError(&e));
                     }
                 }
             }
+            if let Some(journal) = &mut self.journal {
+                if let Err(e) = journal.roll_back() {
+                    (self.notify_handler)(Notification::NonFatalError(&e));
+                }
+            }
         }
     }
 }
@@ -224,8 +271,10 @@ impl Drop for Transaction<'_> {
 enum ChangedItem<'a> {
     AddedFile(PathBuf),
     AddedDir(PathBuf),
-    RemovedFile(PathBuf, temp::File<'a>),
-    RemovedDir(PathBuf, temp::Dir<'a>),
+    /// A removed file or directory, and where it is kept in the
+    /// transaction's backup directory.
+    RemovedFile(PathBuf, PathBuf),
+    RemovedDir(PathBuf, PathBuf),
     ModifiedFile(PathBuf, Option<temp::File<'a>>),
 }
 
@@ -234,22 +283,17 @@ impl<'a> ChangedItem<'a> {
         &self,
         prefix: &InstallPrefix,
         notify: &'a dyn Fn(Notification<'_>),
-        process: &Process,
     ) -> Result<()> {
         use self::ChangedItem::*;
         match self {
             AddedFile(path) => utils::remove_file("component", &prefix.abs_path(path))?,
             AddedDir(path) => utils::remove_dir("component", &prefix.abs_path(path), notify)?,
-            RemovedFile(path, tmp) | ModifiedFile(path, Some(tmp)) => {
-                utils::rename("component", tmp, &prefix.abs_path(path), notify, process)?
+            RemovedFile(path, backup) | RemovedDir(path, backup) => {
+                utils::rename("component", backup, &prefix.abs_path(path), notify)?
+            }
+            ModifiedFile(path, Some(tmp)) => {
+                utils::rename("component", tmp, &prefix.abs_path(path), notify)?
             }
-            RemovedDir(path, tmp) => utils::rename(
-                "component",
-                &tmp.join("bk"),
-                &prefix.abs_path(path),
-                notify,
-                process,
-            )?,
             ModifiedFile(path, None) => {
                 let abs_path = prefix.abs_path(path);
                 if utils::is_file(&abs_path) {
@@ -303,12 +347,10 @@ impl<'a> ChangedItem<'a> {
         prefix: &InstallPrefix,
         component: &str,
         relpath: PathBuf,
-        tmp_cx: &'a temp::Context,
+        backup: PathBuf,
         notify: &'a dyn Fn(Notification<'_>),
-        process: &Process,
     ) -> Result<Self> {
         let abs_path = prefix.abs_path(&relpath);
-        let backup = tmp_cx.new_file()?;
         if !utils::path_exists(&abs_path) {
             Err(RustupError::ComponentMissingFile {
                 name: component.to_owned(),
@@ -316,7 +358,7 @@ impl<'a> ChangedItem<'a> {
             }
             .into())
         } else {
-            utils::rename("component", &abs_path, &backup, notify, process)?;
+            utils::rename("component", &abs_path, &backup, notify)?;
             Ok(ChangedItem::RemovedFile(relpath, backup))
         }
     }
@@ -324,12 +366,10 @@ impl<'a> ChangedItem<'a> {
         prefix: &InstallPrefix,
         component: &str,
         relpath: PathBuf,
-        tmp_cx: &'a temp::Context,
+        backup: PathBuf,
         notify: &'a dyn Fn(Notification<'_>),
-        process: &Process,
     ) -> Result<Self> {
         let abs_path = prefix.abs_path(&relpath);
-        let backup = tmp_cx.new_directory()?;
         if !utils::path_exists(&abs_path) {
             Err(RustupError::ComponentMissingDir {
                 name: component.to_owned(),
@@ -337,7 +377,7 @@ impl<'a> ChangedItem<'a> {
             }
             .into())
         } else {
-            utils::rename("component", &abs_path, &backup.join("bk"), notify, process)?;
+            utils::rename("component", &abs_path, &backup, notify)?;
             Ok(ChangedItem::RemovedDir(relpath, backup))
         }
     }
@@ -365,10 +405,9 @@ impl<'a> ChangedItem<'a> {
         relpath: PathBuf,
         src: &Path,
         notify: &'a dyn Fn(Notification<'_>),
-        process: &Process,
     ) -> Result<Self> {
         let abs_path = ChangedItem::dest_abs_path(prefix, component, &relpath)?;
-        utils::rename("component", src, &abs_path, notify, process)?;
+        utils::rename("component", src, &abs_path, notify)?;
         Ok(ChangedItem::AddedFile(relpath))
     }
     fn move_dir(
@@ -377,10 +416,9 @@ impl<'a> ChangedItem<'a> {
         relpath: PathBuf,
         src: &Path,
         notify: &'a dyn Fn(Notification<'_>),
-        process: &Process,
     ) -> Result<Self> {
         let abs_path = ChangedItem::dest_abs_path(prefix, component, &relpath)?;
-        utils::rename("component", src, &abs_path, notify, process)?;
+        utils::rename("component", src, &abs_path, notify)?;
         Ok(ChangedItem::AddedDir(relpath))
     }
 }
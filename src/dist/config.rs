@@ -11,6 +11,10 @@ use crate::errors::*;
 pub struct Config {
     pub config_version: ConfigVersion,
     pub components: Vec<Component>,
+    /// The packages of the components that were installed from a URL rather
+    /// than from the channel manifest, which updates leave alone.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub from_url: Vec<String>,
 }
 
 impl Config {
@@ -0,0 +1,417 @@
+//! Tokens for dist servers that require authentication.
+//!
+//! Tokens are kept in the platform's credential store where there is one:
+//! the Credential Manager on Windows, the login keychain on macOS, and the
+//! Secret Service elsewhere, through `secret-tool`. Without one they go to
+//! `credentials.toml` in the rustup home, which only its owner may read,
+//! never to `settings.toml`. A token is looked up by the host of the
+//! server, see [`download::token_key`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::process::Process;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum CredentialStore {
+    /// The platform's credential store.
+    Keychain,
+    /// A file only its owner may read.
+    File(PathBuf),
+}
+
+impl CredentialStore {
+    /// The platform's credential store if it has one, unless
+    /// `RUSTUP_CREDENTIAL_STORE` says otherwise.
+    pub(crate) fn from_env(rustup_dir: &Path, process: &Process) -> Result<Self> {
+        let file = Self::File(rustup_dir.join("credentials.toml"));
+        Ok(match process.var("RUSTUP_CREDENTIAL_STORE").as_deref() {
+            Ok("keychain") => Self::Keychain,
+            Ok("file") => file,
+            Ok("") | Err(_) if keychain::available(process) => Self::Keychain,
+            Ok("") | Err(_) => file,
+            Ok(other) => {
+                bail!("invalid RUSTUP_CREDENTIAL_STORE '{other}', expected 'keychain' or 'file'")
+            }
+        })
+    }
+
+    /// The token of the server with the given key.
+    pub(crate) fn get(&self, key: &str) -> Result<Option<String>> {
+        match self {
+            Self::Keychain => keychain::get(key),
+            Self::File(path) => Ok(CredentialsFile::read(path)?.tokens.remove(key)),
+        }
+    }
+
+    /// Stores the token of the server with the given key, replacing any
+    /// it had.
+    pub(crate) fn set(&self, key: &str, token: &str) -> Result<()> {
+        match self {
+            Self::Keychain => keychain::set(key, token),
+            Self::File(path) => {
+                let mut file = CredentialsFile::read(path)?;
+                file.tokens.insert(key.to_owned(), token.to_owned());
+                file.write(path)
+            }
+        }
+    }
+
+    /// Removes the token of the server with the given key, returning
+    /// whether there was one.
+    pub(crate) fn remove(&self, key: &str) -> Result<bool> {
+        match self {
+            Self::Keychain => keychain::remove(key),
+            Self::File(path) => {
+                let mut file = CredentialsFile::read(path)?;
+                if file.tokens.remove(key).is_none() {
+                    return Ok(false);
+                }
+                file.write(path)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Makes downloads authenticate with the tokens in this store. Each
+    /// server's token is looked up once, on its first download.
+    pub(crate) fn provide_tokens(self) {
+        let found = Mutex::new(HashMap::new());
+        download::set_token_provider(Arc::new(move |key: &str| {
+            let mut found = found.lock().unwrap_or_else(PoisonError::into_inner);
+            found
+                .entry(key.to_owned())
+                .or_insert_with(|| {
+                    self.get(key).unwrap_or_else(|e| {
+                        warn!("could not read the token for '{key}' from {self}: {e:#}");
+                        None
+                    })
+                })
+                .clone()
+        }));
+    }
+}
+
+impl fmt::Display for CredentialStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keychain => f.write_str(keychain::NAME),
+            Self::File(path) => write!(f, "'{}'", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct CredentialsFile {
+    #[serde(default)]
+    tokens: BTreeMap<String, String>,
+}
+
+impl CredentialsFile {
+    fn read(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| format!("could not parse '{}'", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("could not read '{}'", path.display())),
+        }
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // A file created by an older rustup, or by hand, may be readable
+            // by others.
+            if path.exists() {
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("could not write '{}'", path.display()))?;
+        file.write_all(toml::to_string(self)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod keychain {
+    use std::ptr;
+
+    use anyhow::Result;
+    use windows_sys::Win32::Foundation::{GetLastError, ERROR_NOT_FOUND};
+    use windows_sys::Win32::Security::Credentials::{
+        CredDeleteW, CredFree, CredReadW, CredWriteW, CREDENTIALW, CRED_PERSIST_LOCAL_MACHINE,
+        CRED_TYPE_GENERIC,
+    };
+
+    use crate::process::Process;
+    use crate::utils::raw::windows::to_u16s;
+
+    pub(super) const NAME: &str = "the Windows Credential Manager";
+
+    pub(super) fn available(_: &Process) -> bool {
+        true
+    }
+
+    fn target(key: &str) -> Result<Vec<u16>> {
+        Ok(to_u16s(format!("rustup:{key}"))?)
+    }
+
+    pub(super) fn get(key: &str) -> Result<Option<String>> {
+        let target = target(key)?;
+        let mut credential: *mut CREDENTIALW = ptr::null_mut();
+        // SAFETY: `target` is nul-terminated, and the credential returned is
+        // freed once its blob has been copied.
+        unsafe {
+            if CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) == 0 {
+                return match GetLastError() {
+                    ERROR_NOT_FOUND => Ok(None),
+                    _ => Err(std::io::Error::last_os_error().into()),
+                };
+            }
+            let blob = std::slice::from_raw_parts(
+                (*credential).CredentialBlob,
+                (*credential).CredentialBlobSize as usize,
+            );
+            let token = String::from_utf8_lossy(blob).into_owned();
+            CredFree(credential.cast());
+            Ok(Some(token))
+        }
+    }
+
+    pub(super) fn set(key: &str, token: &str) -> Result<()> {
+        let mut target = target(key)?;
+        let mut user = to_u16s("rustup")?;
+        let mut blob = token.as_bytes().to_vec();
+        // SAFETY: all fields not set are valid when zeroed, and the strings
+        // and blob outlive the call.
+        unsafe {
+            let mut credential: CREDENTIALW = std::mem::zeroed();
+            credential.Type = CRED_TYPE_GENERIC;
+            credential.TargetName = target.as_mut_ptr();
+            credential.UserName = user.as_mut_ptr();
+            credential.CredentialBlob = blob.as_mut_ptr();
+            credential.CredentialBlobSize = blob.len() as u32;
+            credential.Persist = CRED_PERSIST_LOCAL_MACHINE;
+            if CredWriteW(&credential, 0) == 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn remove(key: &str) -> Result<bool> {
+        let target = target(key)?;
+        // SAFETY: `target` is nul-terminated.
+        unsafe {
+            if CredDeleteW(target.as_ptr(), CRED_TYPE_GENERIC, 0) == 0 {
+                return match GetLastError() {
+                    ERROR_NOT_FOUND => Ok(false),
+                    _ => Err(std::io::Error::last_os_error().into()),
+                };
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod keychain {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    use anyhow::{bail, Context, Result};
+
+    use crate::process::Process;
+
+    pub(super) const NAME: &str = "the macOS keychain";
+
+    /// The `security` exit status for an item that isn't there.
+    const ITEM_NOT_FOUND: i32 = 44;
+
+    pub(super) fn available(_: &Process) -> bool {
+        true
+    }
+
+    fn security(args: &[&str]) -> Result<Option<String>> {
+        let output = Command::new("security")
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .context("could not run security")?;
+        match output.status.code() {
+            Some(0) => Ok(Some(
+                String::from_utf8_lossy(&output.stdout)
+                    .trim_end_matches('\n')
+                    .to_owned(),
+            )),
+            Some(ITEM_NOT_FOUND) => Ok(None),
+            _ => bail!(
+                "security {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        }
+    }
+
+    pub(super) fn get(key: &str) -> Result<Option<String>> {
+        security(&["find-generic-password", "-s", "rustup", "-a", key, "-w"])
+    }
+
+    /// Stores `token` by running the command in `security`'s interactive
+    /// mode, which reads it from stdin, so that the token never appears on
+    /// a command line that other users could see with `ps`.
+    pub(super) fn set(key: &str, token: &str) -> Result<()> {
+        if token.contains(['\n', '\r']) || key.contains(['\n', '\r']) {
+            bail!("tokens and server names can't contain line breaks");
+        }
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let command = format!(
+            "add-generic-password -U -s rustup -a {} -w {}\n",
+            quote(key),
+            quote(token)
+        );
+        let mut child = Command::new("security")
+            .arg("-i")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("could not run security")?;
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(command.as_bytes())?;
+        drop(stdin);
+        let output = child.wait_with_output()?;
+        // The interactive mode succeeds whatever its commands do, so their
+        // failures are told by what they print.
+        if !output.status.success() || !output.stderr.is_empty() {
+            bail!(
+                "security add-generic-password failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    pub(super) fn remove(key: &str) -> Result<bool> {
+        Ok(security(&["delete-generic-password", "-s", "rustup", "-a", key])?.is_some())
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+mod keychain {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    use anyhow::{bail, Context, Result};
+
+    use crate::process::Process;
+    use crate::utils;
+
+    pub(super) const NAME: &str = "the Secret Service";
+
+    /// Whether there is a session with a Secret Service to store tokens in.
+    pub(super) fn available(process: &Process) -> bool {
+        process.var_os("DBUS_SESSION_BUS_ADDRESS").is_some()
+            && utils::find_cmd(&["secret-tool"], process).is_some()
+    }
+
+    /// Runs `secret-tool` on the item holding the token of the server with
+    /// the given key, returning what it prints, or `None` if there is no
+    /// such item.
+    fn secret_tool(args: &[&str], key: &str, input: Option<&str>) -> Result<Option<String>> {
+        let mut child = Command::new("secret-tool")
+            .args(args)
+            .args(["service", "rustup", "server", key])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("could not run secret-tool")?;
+        let mut stdin = child.stdin.take().unwrap();
+        if let Some(input) = input {
+            stdin.write_all(input.as_bytes())?;
+        }
+        drop(stdin);
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            // `lookup` fails quietly when there is nothing to find.
+            if output.stderr.is_empty() {
+                return Ok(None);
+            }
+            bail!(
+                "secret-tool {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+
+    pub(super) fn get(key: &str) -> Result<Option<String>> {
+        Ok(secret_tool(&["lookup"], key, None)?
+            .map(|token| token.trim_end_matches('\n').to_owned())
+            .filter(|token| !token.is_empty()))
+    }
+
+    pub(super) fn set(key: &str, token: &str) -> Result<()> {
+        let label = format!("--label=rustup token for {key}");
+        secret_tool(&["store", &label], key, Some(token))?;
+        Ok(())
+    }
+
+    pub(super) fn remove(key: &str) -> Result<bool> {
+        // `clear` succeeds whether or not there was an item to remove.
+        let found = get(key)?.is_some();
+        secret_tool(&["clear"], key, None)?;
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("credentials.toml");
+        let store = CredentialStore::File(path.clone());
+        assert_eq!(store.get("dist.example.com").unwrap(), None);
+        assert!(!store.remove("dist.example.com").unwrap());
+
+        store.set("dist.example.com", "secret").unwrap();
+        store.set("mirror.example.com:8443", "other").unwrap();
+        store.set("dist.example.com", "newer").unwrap();
+        assert_eq!(
+            store.get("dist.example.com").unwrap().as_deref(),
+            Some("newer")
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert!(store.remove("dist.example.com").unwrap());
+        assert_eq!(store.get("dist.example.com").unwrap(), None);
+        assert_eq!(
+            store.get("mirror.example.com:8443").unwrap().as_deref(),
+            Some("other")
+        );
+    }
+}
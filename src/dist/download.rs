@@ -1,24 +1,62 @@
+use std::collections::HashSet;
 use std::fs;
+use std::io::{self, Read};
 use std::ops;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use tracing::{debug, Instrument};
 use url::Url;
 
+use crate::diskio::IoProfile;
 use crate::dist::notifications::*;
 use crate::dist::temp;
+use crate::dist::timings::{self, Phase};
+use crate::dist::trust::TrustedKey;
 use crate::errors::*;
 use crate::process::Process;
 use crate::utils;
 
 const UPDATE_HASH_LEN: usize = 20;
+const DOWNLOAD_STATS_FILE: &str = "download-stats.json";
 
 #[derive(Copy, Clone)]
 pub struct DownloadCfg<'a> {
     pub dist_root: &'a str,
+    /// The dist roots of further servers, tried in turn for files that
+    /// can't be downloaded from `dist_root`.
+    pub fallback_dist_roots: &'a [String],
+    /// A directory laid out like the dist server below `dist_root`, that
+    /// files are read from instead of the network.
+    pub bundle: Option<&'a Path>,
     pub tmp_cx: &'a temp::Context,
     pub download_dir: &'a PathBuf,
+    /// Caches to try before the dist server.
+    pub cache_peers: &'a [String],
+    /// The caches that failed so far, which are not tried again.
+    pub failed_cache_peers: &'a Mutex<HashSet<String>>,
+    /// Updates that download more than this many bytes ask for confirmation.
+    pub confirm_downloads_over: Option<u64>,
+    /// A program run on each ELF file an update installs.
+    pub post_install_hook: Option<&'a str>,
+    /// Where installed files are linked to a single copy of each content.
+    pub store: Option<&'a Path>,
+    /// Keys that manifests from `dist_root` must be signed with.
+    pub trusted_keys: &'a [TrustedKey],
+    /// The keys trusted for each of `fallback_dist_roots`.
+    pub fallback_trusted_keys: &'a [Vec<TrustedKey>],
+    /// A file with an OpenPGP key trusted for `dist_root` instead of
+    /// `trusted_keys`.
+    pub pgp_key_file: Option<&'a Path>,
+    /// How much memory and how many threads unpacking may use.
+    pub io_profile: IoProfile,
+    /// How many threads unpacking uses, in place of what `io_profile` allows.
+    pub io_threads: Option<usize>,
+    /// How many components are downloaded at once.
+    pub max_downloads: usize,
     pub notify_handler: &'a dyn Fn(Notification<'_>),
     pub process: &'a Process,
 }
@@ -27,6 +65,71 @@ pub(crate) struct File {
     path: PathBuf,
 }
 
+/// How much of an archive an earlier attempt left in the download directory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Downloaded {
+    Not,
+    Partly,
+    Fully,
+}
+
+/// How many component archives came from each place, kept next to the
+/// download directory for `rustup stats`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct DownloadStats {
+    /// Archives already in the download directory.
+    pub reused: u64,
+    /// Archives fetched from a cache peer or content store.
+    pub cached: u64,
+    /// Archives downloaded from the dist server.
+    pub downloaded: u64,
+    /// The size of those archives.
+    #[serde(default)]
+    pub bytes: u64,
+}
+
+impl DownloadStats {
+    /// The counts for `download_dir`, or zeroes if there are none yet.
+    pub(crate) fn load(download_dir: &Path) -> Self {
+        fs::read(download_dir.with_file_name(DOWNLOAD_STATS_FILE))
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// What was counted since `earlier` was loaded.
+    pub(crate) fn since(&self, earlier: &Self) -> Self {
+        Self {
+            reused: self.reused.saturating_sub(earlier.reused),
+            cached: self.cached.saturating_sub(earlier.cached),
+            downloaded: self.downloaded.saturating_sub(earlier.downloaded),
+            bytes: self.bytes.saturating_sub(earlier.bytes),
+        }
+    }
+
+    /// Counts one more archive with `count`. The counts are only
+    /// informational, so failing to write them is no problem.
+    fn record(download_dir: &Path, count: impl FnOnce(&mut Self)) {
+        let mut stats = Self::load(download_dir);
+        count(&mut stats);
+        if let Ok(json) = serde_json::to_vec(&stats) {
+            let _ = fs::write(download_dir.with_file_name(DOWNLOAD_STATS_FILE), json);
+        }
+    }
+}
+
+/// Where the download of `target_file` is kept until it is complete.
+fn partial_file_path(target_file: &Path) -> PathBuf {
+    target_file.with_file_name(
+        target_file
+            .file_name()
+            .map(|s| s.to_str().unwrap_or("_"))
+            .unwrap_or("_")
+            .to_owned()
+            + ".partial",
+    )
+}
+
 impl ops::Deref for File {
     type Target = Path;
 
@@ -36,11 +139,17 @@ impl ops::Deref for File {
 }
 
 impl<'a> DownloadCfg<'a> {
+    /// Whether manifests must be signed with a trusted key.
+    pub(crate) fn checks_signatures(&self) -> bool {
+        self.pgp_key_file.is_some() || !self.trusted_keys.is_empty()
+    }
+
     /// Downloads a file and validates its hash. Resumes interrupted downloads.
     /// Partial downloads are stored in `self.download_dir`, keyed by hash. If the
     /// target file already exists, then the hash is checked and it is returned
     /// immediately without re-downloading.
     pub(crate) async fn download(&self, url: &Url, hash: &str) -> Result<File> {
+        let url = &self.resolve_url(url.as_str(), true)?;
         utils::ensure_dir_exists(
             "Download Directory",
             self.download_dir,
@@ -49,10 +158,12 @@ impl<'a> DownloadCfg<'a> {
         let target_file = self.download_dir.join(Path::new(hash));
 
         if target_file.exists() {
+            let _verify = timings::phase(Phase::Verify).entered();
             let cached_result = file_hash(&target_file, self.notify_handler)?;
             if hash == cached_result {
                 (self.notify_handler)(Notification::FileAlreadyDownloaded);
                 (self.notify_handler)(Notification::ChecksumValid(url.as_ref()));
+                DownloadStats::record(self.download_dir, |s| s.reused += 1);
                 return Ok(File { path: target_file });
             } else {
                 (self.notify_handler)(Notification::CachedFileChecksumFailed);
@@ -60,36 +171,89 @@ impl<'a> DownloadCfg<'a> {
             }
         }
 
-        let partial_file_path = target_file.with_file_name(
-            target_file
-                .file_name()
-                .map(|s| s.to_str().unwrap_or("_"))
-                .unwrap_or("_")
-                .to_owned()
-                + ".partial",
-        );
+        self.fetch(url, hash, &target_file)
+            .instrument(timings::phase(Phase::Download))
+            .await
+    }
+
+    /// How much of the archive with `hash` is already in
+    /// `self.download_dir`. A complete archive is still checked against the
+    /// hash when it is used.
+    pub(crate) fn downloaded(&self, hash: &str) -> Downloaded {
+        if self.download_dir.join(hash).is_file() {
+            Downloaded::Fully
+        } else if partial_file_path(&self.download_dir.join(hash)).is_file() {
+            Downloaded::Partly
+        } else {
+            Downloaded::Not
+        }
+    }
+
+    /// Downloads a file that isn't in `self.download_dir` yet, from a cache
+    /// or from `url`.
+    async fn fetch(&self, url: &Url, hash: &str, target_file: &Path) -> Result<File> {
+        // Caches are tried before the dist server, starting with the peers
+        // on the local network.
+        #[cfg(feature = "content-addressed")]
+        let store = self.process.var("RUSTUP_CONTENT_STORE").ok();
+        #[cfg(not(feature = "content-addressed"))]
+        let store = None::<String>;
+        let caches = match self.bundle {
+            Some(_) => Vec::new(),
+            None => self.cache_peers.iter().chain(&store).collect::<Vec<_>>(),
+        };
+        for cache in &caches {
+            if self.cache_failed(cache) {
+                continue;
+            }
+            if let Some(file) = self.download_from_cache(cache, hash, target_file).await? {
+                DownloadStats::record(self.download_dir, |s| s.cached += 1);
+                return Ok(file);
+            }
+        }
+        if !caches.is_empty() {
+            (self.notify_handler)(Notification::CacheMiss(url.as_ref()));
+        }
+
+        let partial_file_path = partial_file_path(target_file);
 
         let partial_file_existed = partial_file_path.exists();
 
         let mut hasher = Sha256::new();
 
-        if let Err(e) = utils::download_file_with_resume(
-            url,
-            &partial_file_path,
-            Some(&mut hasher),
-            true,
-            &|n| (self.notify_handler)(n.into()),
-            self.process,
-        )
-        .await
-        {
+        let urls = self.fallback_urls(url);
+        let mut urls = urls.iter().peekable();
+        while let Some(url) = urls.next() {
+            let Err(e) = utils::download_file_with_resume(
+                url,
+                &partial_file_path,
+                Some(&mut hasher),
+                true,
+                &|n| (self.notify_handler)(n.into()),
+                self.process,
+            )
+            .await
+            else {
+                break;
+            };
+            if let Some(next) = urls.peek().filter(|_| is_server_failure(&e)) {
+                (self.notify_handler)(Notification::TryingNextDistServer(
+                    url.as_str(),
+                    next.as_str(),
+                ));
+                // The next server starts over, so that a partial file from
+                // this one isn't mixed into it.
+                let _ = fs::remove_file(&partial_file_path);
+                hasher = Sha256::new();
+                continue;
+            }
             let err = Err(e);
             if partial_file_existed {
                 return err.context(RustupError::BrokenPartialFile);
             } else {
                 return err;
             }
-        };
+        }
 
         let actual_hash = format!("{:x}", hasher.finalize());
 
@@ -112,14 +276,183 @@ impl<'a> DownloadCfg<'a> {
             utils::rename(
                 "downloaded",
                 &partial_file_path,
-                &target_file,
+                target_file,
                 self.notify_handler,
-                self.process,
             )?;
-            Ok(File { path: target_file })
+            let size = fs::metadata(target_file).map_or(0, |m| m.len());
+            DownloadStats::record(self.download_dir, |s| {
+                s.downloaded += 1;
+                s.bytes += size;
+            });
+            Ok(File {
+                path: target_file.to_owned(),
+            })
         }
     }
 
+    /// Fetches the file with the SHA-256 `hash` from the cache at `cache`.
+    /// Returns `None` if the cache does not have the file, or sent something
+    /// else.
+    ///
+    /// Cache peers and the content-addressed store are laid out alike: flat,
+    /// with each file at `<cache>/<hash>`, the hash in lowercase hex as the
+    /// manifest gives it. That is how a download directory is laid out too,
+    /// so `rustup serve-cache` can serve one as it is.
+    async fn download_from_cache(
+        &self,
+        cache: &str,
+        hash: &str,
+        target_file: &Path,
+    ) -> Result<Option<File>> {
+        let cache_url = match utils::parse_url(&format!("{}/{hash}", cache.trim_end_matches('/'))) {
+            Ok(url) => url,
+            Err(e) => {
+                debug!("{e:#}");
+                return Ok(None);
+            }
+        };
+        let cache_file = target_file.with_extension("cache");
+
+        let mut hasher = Sha256::new();
+        let res = utils::download_file(
+            &cache_url,
+            &cache_file,
+            Some(&mut hasher),
+            &|n| (self.notify_handler)(n.into()),
+            self.process,
+        )
+        .await
+        .and_then(|()| {
+            let actual_hash = format!("{:x}", hasher.finalize());
+            match hash == actual_hash {
+                true => Ok(()),
+                false => Err(RustupError::ChecksumFailed {
+                    url: cache_url.to_string(),
+                    expected: hash.to_string(),
+                    calculated: actual_hash,
+                }
+                .into()),
+            }
+        });
+        if let Err(e) = res {
+            debug!("{e:#}");
+            let _ = fs::remove_file(&cache_file);
+            // A cache that doesn't have the file may have the next one, but
+            // one that can't be reached or fails would only slow down each
+            // download after this one.
+            let missing = matches!(
+                e.downcast_ref::<RustupError>(),
+                Some(RustupError::DownloadNotExists { .. } | RustupError::ChecksumFailed { .. })
+            );
+            // Downloads in flight at once may each find it failing, but it
+            // is only reported once.
+            let newly_failed = !missing
+                && self
+                    .failed_cache_peers
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(cache.to_owned());
+            if newly_failed {
+                (self.notify_handler)(Notification::CachePeerFailed(cache));
+            }
+            return Ok(None);
+        }
+
+        (self.notify_handler)(Notification::ChecksumValid(cache_url.as_ref()));
+        utils::rename("downloaded", &cache_file, target_file, self.notify_handler)?;
+        Ok(Some(File {
+            path: target_file.to_owned(),
+        }))
+    }
+
+    fn cache_failed(&self, cache: &str) -> bool {
+        self.failed_cache_peers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(cache)
+    }
+
+    /// Hashes the archive at `path`, obtained some other way than through a
+    /// download.
+    pub(crate) fn hash_local_file(&self, path: &Path) -> Result<String> {
+        file_hash(path, self.notify_handler)
+    }
+
+    /// Puts a copy of the archive at `path` with the given `hash` where
+    /// [`Self::download`] looks for it, so that it isn't downloaded.
+    pub(crate) fn add_local_file(&self, path: &Path, hash: &str) -> Result<()> {
+        utils::ensure_dir_exists(
+            "Download Directory",
+            self.download_dir,
+            &self.notify_handler,
+        )?;
+        utils::copy_file(path, &self.download_dir.join(hash))
+    }
+
+    /// The dist roots to try in turn, starting with `self.dist_root`. A
+    /// bundle has all its files, so nothing else is tried with one.
+    pub(crate) fn dist_roots(&self) -> impl Iterator<Item = &'a str> {
+        let fallbacks = match self.bundle {
+            Some(_) => &[][..],
+            None => self.fallback_dist_roots,
+        };
+        [self.dist_root]
+            .into_iter()
+            .chain(fallbacks.iter().map(String::as_str))
+    }
+
+    /// This configuration with `root`, one of [`Self::dist_roots`], in place
+    /// of `self.dist_root`, and the keys trusted for its server.
+    pub(crate) fn with_dist_root(&self, root: &'a str) -> Self {
+        if root == self.dist_root {
+            return *self;
+        }
+        let keys = self
+            .fallback_dist_roots
+            .iter()
+            .position(|fallback| fallback == root)
+            .and_then(|i| self.fallback_trusted_keys.get(i));
+        Self {
+            dist_root: root,
+            trusted_keys: keys.map_or(&[], Vec::as_slice),
+            pgp_key_file: None,
+            ..*self
+        }
+    }
+
+    /// `url`, followed by the same file on each fallback dist server if it
+    /// is below `self.dist_root`.
+    fn fallback_urls(&self, url: &Url) -> Vec<Url> {
+        let Some(path) = url.as_str().strip_prefix(self.dist_root) else {
+            return vec![url.clone()];
+        };
+        self.dist_roots()
+            .filter_map(|root| Url::parse(&format!("{root}{path}")).ok())
+            .collect()
+    }
+
+    /// The URL to get the file at `url` from, which is a file in the
+    /// bundle when installing from one.
+    ///
+    /// Files are looked for at their path below the dist root, such as
+    /// `2024-09-05/cargo-1.81.0-x86_64-unknown-linux-gnu.tar.xz`. An
+    /// `archive` is checked against the hash in the manifest, so it may
+    /// also be found by its file name at the top of the bundle.
+    pub(crate) fn resolve_url(&self, url: &str, archive: bool) -> Result<Url> {
+        let Some(bundle) = self.bundle else {
+            return utils::parse_url(url);
+        };
+        let in_tree = url
+            .strip_prefix(self.dist_root)
+            .map(|relative| bundle.join(relative.trim_start_matches('/')));
+        let path = match in_tree {
+            Some(path) if path.is_file() || !archive => path,
+            _ => bundle.join(url.rsplit('/').next().unwrap_or(url)),
+        };
+        Url::from_file_path(&path)
+            .map_err(|()| anyhow!("invalid path in bundle: '{}'", path.display()))
+    }
+
     pub(crate) fn clean(&self, hashes: &[String]) -> Result<()> {
         for hash in hashes.iter() {
             let used_file = self.download_dir.join(hash);
@@ -130,8 +463,10 @@ impl<'a> DownloadCfg<'a> {
         Ok(())
     }
 
-    async fn download_hash(&self, url: &str) -> Result<String> {
-        let hash_url = utils::parse_url(&(url.to_owned() + ".sha256"))?;
+    /// The SHA-256 hash of the file at `url`, from the same url with a
+    /// `.sha256` suffix.
+    pub(crate) async fn download_hash(&self, url: &str) -> Result<String> {
+        let hash_url = self.resolve_url(&(url.to_owned() + ".sha256"), false)?;
         let hash_file = self.tmp_cx.new_file()?;
 
         utils::download_file(
@@ -147,35 +482,32 @@ impl<'a> DownloadCfg<'a> {
     }
 
     /// Downloads a file, sourcing its hash from the same url with a `.sha256` suffix.
-    /// If `update_hash` is present, then that will be compared to the downloaded hash,
-    /// and if they match, the download is skipped.
-    /// Verifies the signature found at the same url with a `.asc` suffix, and prints a
-    /// warning when the signature does not verify, or is not found.
+    /// If `update_hash` is present, then that will be compared to the start of the
+    /// downloaded hash, and if they match, the download is skipped.
     pub(crate) async fn download_and_check(
         &self,
         url_str: &str,
-        update_hash: Option<&Path>,
+        update_hash: Option<&str>,
         ext: &str,
     ) -> Result<Option<(temp::File<'a>, String)>> {
         let hash = self.download_hash(url_str).await?;
-        let partial_hash: String = hash.chars().take(UPDATE_HASH_LEN).collect();
-
-        if let Some(hash_file) = update_hash {
-            if utils::is_file(hash_file) {
-                if let Ok(contents) = utils::read_file("update hash", hash_file) {
-                    if contents == partial_hash {
-                        // Skip download, update hash matches
-                        return Ok(None);
-                    }
-                } else {
-                    (self.notify_handler)(Notification::CantReadUpdateHash(hash_file));
-                }
-            } else {
-                (self.notify_handler)(Notification::NoUpdateHash(hash_file));
-            }
+        let partial_hash = update_hash_of(&hash);
+        if update_hash == Some(partial_hash.as_str()) {
+            return Ok(None);
         }
+        let file = self.download_with_hash(url_str, hash, ext).await?;
+        Ok(Some((file, partial_hash)))
+    }
 
-        let url = utils::parse_url(url_str)?;
+    /// Downloads the file at `url_str` to a temporary file, checking that
+    /// its SHA-256 hash is `hash`.
+    pub(crate) async fn download_with_hash(
+        &self,
+        url_str: &str,
+        hash: String,
+        ext: &str,
+    ) -> Result<temp::File<'a>> {
+        let url = self.resolve_url(url_str, false)?;
         let file = self.tmp_cx.new_file_with_ext("", ext)?;
 
         let mut hasher = Sha256::new();
@@ -201,7 +533,67 @@ impl<'a> DownloadCfg<'a> {
             (self.notify_handler)(Notification::ChecksumValid(url_str));
         }
 
-        Ok(Some((file, partial_hash)))
+        Ok(file)
+    }
+}
+
+/// The start of the SHA-256 `hash` of a manifest, which is what is kept to
+/// tell whether a toolchain is up to date with it.
+pub(crate) fn update_hash_of(hash: &str) -> String {
+    hash.chars().take(UPDATE_HASH_LEN).collect()
+}
+
+/// Whether `err` means the dist server doesn't have a file or couldn't
+/// send it, so that the next server is worth trying.
+pub(crate) fn is_server_failure(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<RustupError>(),
+        Some(
+            RustupError::DownloadNotExists { .. }
+                | RustupError::DownloadingFile { .. }
+                | RustupError::DownloadServerFailed { .. }
+        )
+    )
+}
+
+/// Whether `err` means the server rate limited a download, even after it was
+/// retried as the server asked.
+pub(crate) fn is_rate_limited(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<RustupError>(),
+        Some(RustupError::DownloadRateLimited { .. })
+    )
+}
+
+/// Unpacks the bundle `archive` into `dir`, returning the directory that
+/// holds its files: the single directory at the top of the archive, if
+/// that's all there is.
+pub(crate) fn unpack_bundle(archive: &Path, dir: &Path) -> Result<PathBuf> {
+    let file = fs::File::open(archive)
+        .with_context(|| format!("could not open '{}'", archive.display()))?;
+    let name = archive.to_string_lossy();
+    let reader: Box<dyn Read> = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Box::new(flate2::read::GzDecoder::new(file))
+    } else if name.ends_with(".tar.xz") {
+        Box::new(xz2::read::XzDecoder::new(file))
+    } else if name.ends_with(".tar.zst") {
+        Box::new(zstd::Decoder::new(file)?)
+    } else if name.ends_with(".tar") {
+        Box::new(file)
+    } else {
+        bail!(
+            "'{}' is neither a directory nor a .tar, .tar.gz, .tar.xz or .tar.zst archive",
+            archive.display()
+        );
+    };
+    tar::Archive::new(reader)
+        .unpack(dir)
+        .with_context(|| format!("could not unpack '{}'", archive.display()))?;
+
+    let entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    match &entries[..] {
+        [entry] if entry.file_type()?.is_dir() => Ok(entry.path()),
+        _ => Ok(dir.to_owned()),
     }
 }
 
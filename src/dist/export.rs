@@ -0,0 +1,198 @@
+//! Bundles of a toolchain's manifest and component archives, for installing
+//! it on a machine without network access with `--from-bundle`.
+//!
+//! A bundle is laid out like the dist server below its root: the channel
+//! manifest with its `.sha256` file, any signatures the trusted keys need
+//! and the Rust release key if it is one of them, and the archives in their
+//! dated directories. Nothing else is needed to check it, since the manifest
+//! lists the hash of every archive.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use tracing::{debug, info};
+
+use crate::dist::download::DownloadCfg;
+use crate::dist::manifest::Manifest;
+use crate::dist::manifestation::{Changes, Manifestation};
+use crate::dist::prefix::InstallPrefix;
+use crate::dist::{requested_components, trust, Profile, ToolchainDesc};
+use crate::errors::RustupError;
+use crate::utils;
+
+/// Downloads the manifest of `toolchain` and the archives of the components
+/// that installing it with `profile`, `components` and `targets` would
+/// need, and writes them to the bundle archive `output`.
+pub(crate) async fn export_bundle(
+    download: DownloadCfg<'_>,
+    toolchain: &ToolchainDesc,
+    profile: Profile,
+    components: &[&str],
+    targets: &[&str],
+    output: &Path,
+) -> Result<()> {
+    // Checked before downloading anything.
+    let compression = Compression::from_path(output)?;
+
+    let manifest_url = toolchain.manifest_v2_url(download.dist_root, download.process);
+    let (manifest_file, _) = download
+        .download_and_check(&manifest_url, None, ".toml")
+        .await?
+        .ok_or_else(|| anyhow!("no manifest for '{toolchain}'"))?;
+    trust::verify_manifest(&download, &manifest_url, &manifest_file).await?;
+    let manifest =
+        Manifest::parse(&utils::read_file("manifest", &manifest_file)?).with_context(|| {
+            RustupError::ParsingFile {
+                name: "manifest",
+                path: manifest_file.to_path_buf(),
+            }
+        })?;
+
+    let manifest_name = relative_path(download.dist_root, &manifest_url);
+    let hash = download.hash_local_file(&manifest_file)?;
+    let file_name = manifest_name.rsplit('/').next().unwrap_or(&manifest_name);
+    let hash_file = download.tmp_cx.new_file()?;
+    utils::write_file("hash", &hash_file, &format!("{hash}  {file_name}\n"))?;
+
+    let mut files = vec![
+        (manifest_file.to_path_buf(), manifest_name.clone()),
+        (hash_file.to_path_buf(), format!("{manifest_name}.sha256")),
+    ];
+    let mut signatures = Vec::new();
+    let suffixes = match download.pgp_key_file {
+        Some(_) => vec![".asc"],
+        None => trust::signature_suffixes(download.trusted_keys),
+    };
+    for suffix in suffixes {
+        let signature = download.tmp_cx.new_file()?;
+        let url = format!("{manifest_url}{suffix}");
+        match trust::fetch(&download, "signature", &url, &signature).await {
+            Ok(()) => files.push((signature.to_path_buf(), format!("{manifest_name}{suffix}"))),
+            // Only one of the signatures has to verify.
+            Err(e) => debug!("{e:#}"),
+        }
+        signatures.push(signature);
+    }
+
+    // What installing the toolchain afresh would download.
+    let prefix = download.tmp_cx.new_directory()?;
+    let manifestation =
+        Manifestation::open(InstallPrefix::from(&*prefix), toolchain.target.clone())?;
+    let changes = Changes {
+        explicit_add_components: requested_components(
+            &manifest,
+            toolchain,
+            Some(profile),
+            components,
+            targets,
+        )?,
+        remove_components: Vec::new(),
+    };
+    let plan = manifestation.compute_changes(&manifest, &changes)?;
+    if !plan.unavailable.is_empty() {
+        return Err(RustupError::RequestedComponentsUnavailable {
+            components: plan.unavailable,
+            manifest,
+            toolchain: toolchain.to_string(),
+        }
+        .into());
+    }
+
+    for planned in &plan.downloads {
+        let url = utils::parse_url(&planned.url)?;
+        let archive = download.download(&url, &planned.hash).await?;
+        files.push((
+            archive.to_path_buf(),
+            relative_path(download.dist_root, &planned.url),
+        ));
+    }
+
+    compression
+        .write(output, &files)
+        .with_context(|| format!("could not write '{}'", output.display()))?;
+    // As after an install, the archives aren't kept in the download
+    // directory.
+    let hashes = plan
+        .downloads
+        .iter()
+        .map(|planned| planned.hash.clone())
+        .collect::<Vec<_>>();
+    download.clean(&hashes)?;
+    info!(
+        "exported {} components of toolchain '{toolchain}' to '{}'",
+        plan.downloads.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// The path of the file at `url` in the bundle: its path below the dist
+/// root, or just its file name if it is served from elsewhere.
+fn relative_path(dist_root: &str, url: &str) -> String {
+    match url.strip_prefix(dist_root) {
+        Some(relative) => relative.trim_start_matches('/').to_owned(),
+        None => url.rsplit('/').next().unwrap_or(url).to_owned(),
+    }
+}
+
+/// The formats [`unpack_bundle`](super::download::unpack_bundle) reads.
+enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    fn from_path(path: &Path) -> Result<Self> {
+        let name = path.to_string_lossy();
+        Ok(if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Self::Gzip
+        } else if name.ends_with(".tar.xz") {
+            Self::Xz
+        } else if name.ends_with(".tar.zst") {
+            Self::Zstd
+        } else if name.ends_with(".tar") {
+            Self::None
+        } else {
+            bail!(
+                "'{}' is not a .tar, .tar.gz, .tar.xz or .tar.zst file name",
+                path.display()
+            );
+        })
+    }
+
+    /// Writes `files`, each a path and its name in the bundle, to the
+    /// archive `output`.
+    fn write(&self, output: &Path, files: &[(PathBuf, String)]) -> Result<()> {
+        let file = fs::File::create(output)?;
+        match self {
+            Self::None => append(file, files)?.flush()?,
+            Self::Gzip => {
+                append(
+                    flate2::write::GzEncoder::new(file, flate2::Compression::default()),
+                    files,
+                )?
+                .finish()?;
+            }
+            Self::Xz => {
+                append(xz2::write::XzEncoder::new(file, 6), files)?.finish()?;
+            }
+            Self::Zstd => {
+                append(zstd::Encoder::new(file, 0)?, files)?.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn append<W: Write>(writer: W, files: &[(PathBuf, String)]) -> Result<W> {
+    let mut archive = tar::Builder::new(writer);
+    archive.mode(tar::HeaderMode::Deterministic);
+    for (path, name) in files {
+        archive.append_path_with_name(path, name)?;
+    }
+    Ok(archive.into_inner()?)
+}
@@ -33,6 +33,12 @@ pub(crate) struct ComponentStatus {
     pub name: String,
     pub installed: bool,
     pub available: bool,
+    /// The size of the archive that would be downloaded, if known.
+    pub download_size: Option<u64>,
+    /// The size of the component once installed, if known.
+    pub installed_size: Option<u64>,
+    /// The support tier of the component's target, if known.
+    pub tier: Option<u8>,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -102,6 +108,10 @@ pub struct TargetedPackage {
     #[serde(default)]
     pub bins: Vec<HashedBinary>,
     pub components: Vec<Component>,
+    /// The size of the package once unpacked, if declared by the manifest.
+    pub installed_size: Option<u64>,
+    /// The support tier of the target, if declared by the manifest.
+    pub tier: Option<u8>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -109,10 +119,15 @@ struct Target {
     available: bool,
     url: Option<String>,
     hash: Option<String>,
+    size: Option<u64>,
     xz_url: Option<String>,
     xz_hash: Option<String>,
+    xz_size: Option<u64>,
     zst_url: Option<String>,
     zst_hash: Option<String>,
+    zst_size: Option<u64>,
+    installed_size: Option<u64>,
+    tier: Option<u8>,
     components: Option<Vec<Component>>,
     extensions: Option<Vec<Component>>,
 }
@@ -128,14 +143,21 @@ impl From<Target> for TargetedPackage {
         }
 
         let mut bins = Vec::new();
+        let (installed_size, tier) = (target.installed_size, target.tier);
         if !target.available {
-            return Self { bins, components };
+            return Self {
+                bins,
+                components,
+                installed_size,
+                tier,
+            };
         }
 
         if let (Some(url), Some(hash)) = (target.zst_url, target.zst_hash) {
             bins.push(HashedBinary {
                 url,
                 hash,
+                size: target.zst_size,
                 compression: CompressionKind::ZStd,
             });
         }
@@ -144,6 +166,7 @@ impl From<Target> for TargetedPackage {
             bins.push(HashedBinary {
                 url,
                 hash,
+                size: target.xz_size,
                 compression: CompressionKind::XZ,
             });
         }
@@ -152,33 +175,42 @@ impl From<Target> for TargetedPackage {
             bins.push(HashedBinary {
                 url,
                 hash,
+                size: target.size,
                 compression: CompressionKind::GZip,
             });
         }
 
-        Self { bins, components }
+        Self {
+            bins,
+            components,
+            installed_size,
+            tier,
+        }
     }
 }
 
 impl From<TargetedPackage> for Target {
     fn from(tpkg: TargetedPackage) -> Self {
-        let (mut url, mut hash) = (None, None);
-        let (mut xz_url, mut xz_hash) = (None, None);
-        let (mut zst_url, mut zst_hash) = (None, None);
+        let (mut url, mut hash, mut size) = (None, None, None);
+        let (mut xz_url, mut xz_hash, mut xz_size) = (None, None, None);
+        let (mut zst_url, mut zst_hash, mut zst_size) = (None, None, None);
         let available = !tpkg.bins.is_empty();
         for bin in tpkg.bins {
             match bin.compression {
                 CompressionKind::GZip => {
                     url = Some(bin.url);
                     hash = Some(bin.hash);
+                    size = bin.size;
                 }
                 CompressionKind::XZ => {
                     xz_url = Some(bin.url);
                     xz_hash = Some(bin.hash);
+                    xz_size = bin.size;
                 }
                 CompressionKind::ZStd => {
                     zst_url = Some(bin.url);
                     zst_hash = Some(bin.hash);
+                    zst_size = bin.size;
                 }
             }
         }
@@ -197,10 +229,15 @@ impl From<TargetedPackage> for Target {
             available,
             url,
             hash,
+            size,
             xz_url,
             xz_hash,
+            xz_size,
             zst_url,
             zst_hash,
+            zst_size,
+            installed_size: tpkg.installed_size,
+            tier: tpkg.tier,
             components: Some(components),
             extensions: Some(extensions),
         }
@@ -214,10 +251,27 @@ pub enum CompressionKind {
     ZStd,
 }
 
+impl CompressionKind {
+    /// The format of an archive, from the extension of its file name.
+    pub(crate) fn from_file_name(name: &str) -> Option<Self> {
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::GZip)
+        } else if name.ends_with(".tar.xz") {
+            Some(Self::XZ)
+        } else if name.ends_with(".tar.zst") {
+            Some(Self::ZStd)
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct HashedBinary {
     pub url: String,
     pub hash: String,
+    /// The size of the archive, if declared by the manifest.
+    pub size: Option<u64>,
     pub compression: CompressionKind,
 }
 
@@ -383,6 +437,31 @@ impl Manifest {
         Ok(())
     }
 
+    /// Finds the component of the toolchain for `target` that has an archive
+    /// with the given hash, and puts that archive first among the archives of
+    /// the component so that it is the one installed.
+    pub(crate) fn select_archive(
+        &mut self,
+        target: &TargetTriple,
+        hash: &str,
+    ) -> Option<Component> {
+        let rust_pkg = self.packages.get("rust")?.targets.get(target)?;
+        for component in rust_pkg.components.clone() {
+            let Some(tpkg) = self
+                .packages
+                .get_mut(component.short_name_in_manifest())
+                .and_then(|pkg| pkg.get_target_mut(component.target.as_ref()))
+            else {
+                continue;
+            };
+            if let Some(i) = tpkg.bins.iter().position(|bin| bin.hash == hash) {
+                tpkg.bins[..=i].rotate_right(1);
+                return Some(component);
+            }
+        }
+        None
+    }
+
     // If the component should be renamed by this manifest, then return a new
     // component with the new name. If not, return `None`.
     pub(crate) fn rename_component(&self, component: &Component) -> Option<Component> {
@@ -436,6 +515,9 @@ impl Manifest {
                 name: component.name(self),
                 installed,
                 available: component_target_pkg.available(),
+                download_size: component_target_pkg.download_size(),
+                installed_size: component_target_pkg.installed_size,
+                tier: component_target_pkg.tier,
             });
         }
 
@@ -446,6 +528,13 @@ impl Manifest {
 }
 
 impl Package {
+    fn get_target_mut(&mut self, target: Option<&TargetTriple>) -> Option<&mut TargetedPackage> {
+        match &mut self.targets {
+            PackageTargets::Wildcard(tpkg) => Some(tpkg),
+            PackageTargets::Targeted(tpkgs) => tpkgs.get_mut(target?),
+        }
+    }
+
     pub fn get_target(&self, target: Option<&TargetTriple>) -> Result<&TargetedPackage> {
         match self.targets {
             PackageTargets::Wildcard(ref tpkg) => Ok(tpkg),
@@ -482,6 +571,12 @@ impl TargetedPackage {
     pub fn available(&self) -> bool {
         !self.bins.is_empty()
     }
+
+    /// The size of the archive rustup would download, which is the first one
+    /// in order of preference.
+    pub(crate) fn download_size(&self) -> Option<u64> {
+        self.bins.first().and_then(|bin| bin.size)
+    }
 }
 
 impl Component {
@@ -689,6 +784,49 @@ mod tests {
         assert_eq!(original, new);
     }
 
+    #[test]
+    fn parse_sizes() {
+        let manifest = r#"
+manifest-version = "2"
+date = "2015-10-10"
+[pkg.rust]
+  version = "rustc 1.3.0 (9a92aaf19 2015-09-15)"
+  [pkg.rust.target.x86_64-unknown-linux-gnu]
+    available = true
+    url = "example.com"
+    hash = "..."
+    size = 300
+    xz_url = "example.com"
+    xz_hash = "..."
+    xz_size = 200
+    installed_size = 1000
+"#;
+
+        let manifest = Manifest::parse(manifest).unwrap();
+        let target = TargetTriple::new("x86_64-unknown-linux-gnu");
+        let tpkg = manifest
+            .get_package("rust")
+            .unwrap()
+            .get_target(Some(&target))
+            .unwrap();
+        assert_eq!(tpkg.bins[0].size, Some(200));
+        assert_eq!(tpkg.bins[1].size, Some(300));
+        assert_eq!(tpkg.download_size(), Some(200));
+        assert_eq!(tpkg.installed_size, Some(1000));
+
+        let serialized = manifest.clone().stringify().unwrap();
+        assert_eq!(Manifest::parse(&serialized).unwrap(), manifest);
+
+        let tpkg = Manifest::parse(EXAMPLE).unwrap();
+        let tpkg = tpkg
+            .get_package("rust")
+            .unwrap()
+            .get_target(Some(&target))
+            .unwrap();
+        assert_eq!(tpkg.download_size(), None);
+        assert_eq!(tpkg.installed_size, None);
+    }
+
     #[test]
     fn validate_components_have_corresponding_packages() {
         let manifest = r#"
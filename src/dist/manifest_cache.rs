@@ -0,0 +1,391 @@
+//! The manifests rustup has fetched from the dist server, and which of them
+//! each installed toolchain was last updated from.
+//!
+//! Entries are keyed by channel and date, as in `nightly` or
+//! `nightly-2024-09-05`, since that is what a manifest is published for, so
+//! the toolchains of a channel for every target share an entry. A manifest
+//! with a date never changes and is used for as long as it is kept, which
+//! saves downloading the same nightlies again while looking for one that has
+//! all the components a toolchain needs. The manifest of a channel is only
+//! used without asking the dist server for its hash while it is younger than
+//! the TTL, which is zero unless `RUSTUP_MANIFEST_TTL` sets it.
+//!
+//! Where entries are kept is up to a [`ManifestStore`]: the sqlite database
+//! of all toolchain state in `RUSTUP_HOME`, or a directory there in builds
+//! without the `sqlite` feature. Those builds still read the hashes that
+//! earlier versions of rustup kept in `RUSTUP_HOME/update-hashes`, and
+//! replace them as each toolchain is updated.
+
+#[cfg(feature = "sqlite")]
+pub(crate) mod sqlite;
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::dist::ToolchainDesc;
+use crate::notifications::Notification;
+use crate::utils;
+
+/// Where the entries of a [`ManifestCache`] are kept.
+pub(crate) trait ManifestStore: Debug {
+    /// The entry for `key`, if there is one.
+    fn entry(&self, key: &str) -> Result<Option<CacheEntry>>;
+
+    /// Replaces the entry for `key`.
+    fn set_entry(&self, key: &str, entry: &CacheEntry) -> Result<()>;
+
+    /// The manifest kept for `key`, if there is one.
+    fn manifest(&self, key: &str) -> Result<Option<String>>;
+
+    /// Keeps `manifest` for `key`, replacing the one kept before.
+    fn set_manifest(&self, key: &str, manifest: &str) -> Result<()>;
+}
+
+/// What is known about the manifest of a channel and date.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct CacheEntry {
+    /// The SHA-256 hash of the manifest that is kept, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// When the dist server was last found to have that manifest, in
+    /// seconds since the epoch.
+    #[serde(default)]
+    pub checked: u64,
+    /// The toolchains installed or updated from a manifest of this channel
+    /// and date, by target.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub installed: BTreeMap<String, Installed>,
+}
+
+/// The manifest a toolchain was last installed or updated from.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct Installed {
+    /// The start of the hash of the manifest.
+    pub hash: String,
+    /// When the toolchain was installed or updated, in seconds since the
+    /// epoch.
+    pub updated: u64,
+}
+
+/// The store for the entries of the rustup home `rustup_dir`.
+pub(crate) fn default_store(rustup_dir: &Path) -> Box<dyn ManifestStore> {
+    #[cfg(feature = "sqlite")]
+    let store = sqlite::MetadataDb::new(rustup_dir);
+    #[cfg(not(feature = "sqlite"))]
+    let store = FsManifestStore::new(rustup_dir.join("manifests"));
+    Box::new(store)
+}
+
+/// Keeps each entry as a JSON file in a directory, with its manifest next
+/// to it. The manifests can be deleted at any time.
+#[derive(Debug)]
+pub(crate) struct FsManifestStore {
+    dir: PathBuf,
+}
+
+impl FsManifestStore {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn read(&self, file: &str) -> Result<Option<String>> {
+        let path = self.dir.join(file);
+        match fs::read_to_string(&path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("could not read '{}'", path.display())),
+        }
+    }
+
+    /// Replaces `file` in a single step, so that it is never seen half
+    /// written by another rustup.
+    fn write(&self, file: &str, data: &str) -> Result<()> {
+        utils::ensure_dir_exists("manifest cache", &self.dir, &|_: Notification<'_>| ())?;
+        let path = self.dir.join(file);
+        let tmp_path = self
+            .dir
+            .join(format!("{file}.{}", utils::raw::random_string(8)));
+        utils::write_file("manifest cache", &tmp_path, data)?;
+        fs::rename(&tmp_path, &path)
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&tmp_path);
+            })
+            .with_context(|| format!("could not write '{}'", path.display()))
+    }
+}
+
+impl ManifestStore for FsManifestStore {
+    fn entry(&self, key: &str) -> Result<Option<CacheEntry>> {
+        let Some(data) = self.read(&format!("{key}.json"))? else {
+            return Ok(None);
+        };
+        // An entry that can't be read is only a cache miss.
+        Ok(serde_json::from_str(&data)
+            .inspect_err(|e| debug!("invalid manifest cache entry for '{key}': {e}"))
+            .ok())
+    }
+
+    fn set_entry(&self, key: &str, entry: &CacheEntry) -> Result<()> {
+        self.write(&format!("{key}.json"), &serde_json::to_string(entry)?)
+    }
+
+    fn manifest(&self, key: &str) -> Result<Option<String>> {
+        self.read(&format!("{key}.toml"))
+    }
+
+    fn set_manifest(&self, key: &str, manifest: &str) -> Result<()> {
+        self.write(&format!("{key}.toml"), manifest)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ManifestCache {
+    store: Box<dyn ManifestStore>,
+    /// How long the manifest of a channel is used without asking the dist
+    /// server whether it changed.
+    ttl: Duration,
+    /// Where earlier versions of rustup kept the hash of the manifest each
+    /// toolchain was updated from, in a file of its own.
+    legacy_dir: PathBuf,
+}
+
+impl ManifestCache {
+    pub(crate) fn new(store: Box<dyn ManifestStore>, ttl: Duration, legacy_dir: PathBuf) -> Self {
+        Self {
+            store,
+            ttl,
+            legacy_dir,
+        }
+    }
+
+    /// The manifest for `toolchain` and its hash, if it can be used without
+    /// asking the dist server.
+    pub(crate) fn fresh_manifest(
+        &self,
+        toolchain: &ToolchainDesc,
+    ) -> Result<Option<(String, String)>> {
+        let key = toolchain.manifest_name();
+        let Some(CacheEntry {
+            hash: Some(hash),
+            checked,
+            ..
+        }) = self.store.entry(&key)?
+        else {
+            return Ok(None);
+        };
+        let age = Duration::from_secs(now().saturating_sub(checked));
+        if toolchain.date.is_none() && age >= self.ttl {
+            return Ok(None);
+        }
+        Ok(self.store.manifest(&key)?.map(|manifest| (manifest, hash)))
+    }
+
+    /// The kept manifest for `toolchain` if its hash is `hash`, which the
+    /// dist server was just found to have.
+    pub(crate) fn manifest_with_hash(
+        &self,
+        toolchain: &ToolchainDesc,
+        hash: &str,
+    ) -> Result<Option<String>> {
+        let key = toolchain.manifest_name();
+        let Some(mut entry) = self.store.entry(&key)? else {
+            return Ok(None);
+        };
+        if entry.hash.as_deref() != Some(hash) {
+            return Ok(None);
+        }
+        let Some(manifest) = self.store.manifest(&key)? else {
+            return Ok(None);
+        };
+        entry.checked = now();
+        self.store.set_entry(&key, &entry)?;
+        Ok(Some(manifest))
+    }
+
+    /// Keeps `manifest`, whose SHA-256 hash is `hash`, as the one for
+    /// `toolchain`.
+    pub(crate) fn add_manifest(
+        &self,
+        toolchain: &ToolchainDesc,
+        hash: &str,
+        manifest: &str,
+    ) -> Result<()> {
+        let key = toolchain.manifest_name();
+        let mut entry = self.store.entry(&key)?.unwrap_or_default();
+        self.store.set_manifest(&key, manifest)?;
+        entry.hash = Some(hash.to_owned());
+        entry.checked = now();
+        self.store.set_entry(&key, &entry)
+    }
+
+    /// The manifest `toolchain` was last installed or updated from, if
+    /// known.
+    pub(crate) fn installed(&self, toolchain: &ToolchainDesc) -> Result<Option<Installed>> {
+        let installed = self
+            .store
+            .entry(&toolchain.manifest_name())?
+            .and_then(|mut entry| entry.installed.remove(&toolchain.target.to_string()));
+        if installed.is_some() {
+            return Ok(installed);
+        }
+        let legacy_path = self.legacy_dir.join(toolchain.to_string());
+        let Ok(hash) = fs::read_to_string(&legacy_path) else {
+            return Ok(None);
+        };
+        let updated = fs::metadata(&legacy_path)
+            .and_then(|meta| meta.modified())
+            .map_or(0, secs_since_epoch);
+        Ok(Some(Installed { hash, updated }))
+    }
+
+    /// Notes that `toolchain` has been installed or updated from the
+    /// manifest whose hash starts with `hash`.
+    pub(crate) fn set_installed(&self, toolchain: &ToolchainDesc, hash: &str) -> Result<()> {
+        let key = toolchain.manifest_name();
+        let mut entry = self.store.entry(&key)?.unwrap_or_default();
+        entry.installed.insert(
+            toolchain.target.to_string(),
+            Installed {
+                hash: hash.to_owned(),
+                updated: now(),
+            },
+        );
+        self.store.set_entry(&key, &entry)?;
+        utils::ensure_file_removed("update hash", &self.legacy_dir.join(toolchain.to_string()))
+    }
+
+    /// Forgets the manifest `toolchain` was installed from, returning
+    /// whether there was one.
+    pub(crate) fn remove_installed(&self, toolchain: &ToolchainDesc) -> Result<bool> {
+        let key = toolchain.manifest_name();
+        let mut removed = false;
+        if let Some(mut entry) = self.store.entry(&key)? {
+            if entry
+                .installed
+                .remove(&toolchain.target.to_string())
+                .is_some()
+            {
+                self.store.set_entry(&key, &entry)?;
+                removed = true;
+            }
+        }
+        let legacy_path = self.legacy_dir.join(toolchain.to_string());
+        if legacy_path.is_file() {
+            utils::remove_file("update hash", &legacy_path)?;
+            removed = true;
+        }
+        Ok(removed)
+    }
+}
+
+fn now() -> u64 {
+    secs_since_epoch(SystemTime::now())
+}
+
+fn secs_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn new_cache(dir: &std::path::Path, ttl: Duration) -> ManifestCache {
+        ManifestCache::new(
+            Box::new(FsManifestStore::new(dir.join("manifests"))),
+            ttl,
+            dir.join("update-hashes"),
+        )
+    }
+
+    #[test]
+    fn channel_manifests_are_fresh_within_the_ttl() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let nightly = ToolchainDesc::from_str("nightly-x86_64-unknown-linux-gnu").unwrap();
+
+        let cache = new_cache(dir.path(), Duration::ZERO);
+        cache.add_manifest(&nightly, "hash", "manifest").unwrap();
+        assert_eq!(cache.fresh_manifest(&nightly).unwrap(), None);
+        assert_eq!(
+            cache
+                .manifest_with_hash(&nightly, "hash")
+                .unwrap()
+                .as_deref(),
+            Some("manifest")
+        );
+        assert_eq!(cache.manifest_with_hash(&nightly, "other").unwrap(), None);
+
+        let cache = new_cache(dir.path(), Duration::from_secs(3600));
+        assert_eq!(
+            cache.fresh_manifest(&nightly).unwrap(),
+            Some(("manifest".to_owned(), "hash".to_owned()))
+        );
+    }
+
+    #[test]
+    fn dated_manifests_are_always_fresh() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let dated = ToolchainDesc::from_str("nightly-2024-09-05-x86_64-unknown-linux-gnu").unwrap();
+        let cache = new_cache(dir.path(), Duration::ZERO);
+        cache.add_manifest(&dated, "hash", "manifest").unwrap();
+        assert_eq!(
+            cache.fresh_manifest(&dated).unwrap(),
+            Some(("manifest".to_owned(), "hash".to_owned()))
+        );
+    }
+
+    #[test]
+    fn installed_is_kept_per_target() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let linux = ToolchainDesc::from_str("stable-x86_64-unknown-linux-gnu").unwrap();
+        let windows = ToolchainDesc::from_str("stable-x86_64-pc-windows-msvc").unwrap();
+        let cache = new_cache(dir.path(), Duration::ZERO);
+
+        cache.set_installed(&linux, "linux").unwrap();
+        cache.set_installed(&windows, "windows").unwrap();
+        assert_eq!(cache.installed(&linux).unwrap().unwrap().hash, "linux");
+        assert_eq!(cache.installed(&windows).unwrap().unwrap().hash, "windows");
+
+        assert!(cache.remove_installed(&linux).unwrap());
+        assert!(!cache.remove_installed(&linux).unwrap());
+        assert_eq!(cache.installed(&linux).unwrap(), None);
+        assert_eq!(cache.installed(&windows).unwrap().unwrap().hash, "windows");
+    }
+
+    #[test]
+    fn legacy_update_hashes_are_read_and_replaced() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let stable = ToolchainDesc::from_str("stable-x86_64-unknown-linux-gnu").unwrap();
+        let legacy_path = dir.path().join("update-hashes").join(stable.to_string());
+        fs::create_dir_all(legacy_path.parent().unwrap()).unwrap();
+        fs::write(&legacy_path, "legacy").unwrap();
+        let cache = new_cache(dir.path(), Duration::ZERO);
+
+        let installed = cache.installed(&stable).unwrap().unwrap();
+        assert_eq!(installed.hash, "legacy");
+        assert!(installed.updated > 0);
+
+        cache.set_installed(&stable, "new").unwrap();
+        assert!(!legacy_path.exists());
+        assert_eq!(cache.installed(&stable).unwrap().unwrap().hash, "new");
+    }
+
+    #[test]
+    fn corrupt_entries_are_misses() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let store = FsManifestStore::new(dir.path().to_owned());
+        fs::write(dir.path().join("nightly.json"), "{").unwrap();
+        assert_eq!(store.entry("nightly").unwrap(), None);
+    }
+}
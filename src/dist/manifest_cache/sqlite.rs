@@ -0,0 +1,469 @@
+//! Keeps the state rustup has of each toolchain in a single sqlite database
+//! in `RUSTUP_HOME`: the entries of a [`ManifestCache`](super::ManifestCache),
+//! and how often proxies ran each toolchain. An entry and the toolchains
+//! installed from it are so always written together, and listing toolchains
+//! reads one file rather than one per toolchain.
+//!
+//! When the database is created, the files earlier versions of rustup kept
+//! this state in are moved into it: the `manifests` and `update-hashes`
+//! directories. Proxies still append their runs to `usage.log`, which is
+//! cheaper than a transaction, and its totals are moved into the database
+//! as the log is compacted.
+
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, TransactionBehavior};
+use tracing::debug;
+
+use super::{CacheEntry, FsManifestStore, Installed, ManifestStore};
+use crate::diskio::storage::Storage;
+use crate::dist::ToolchainDesc;
+
+/// The version of the schema, kept as the `user_version` of the database.
+const SCHEMA_VERSION: i32 = 2;
+
+/// The tables of each version of the schema, each created from the one
+/// before.
+const SCHEMA: [&str; SCHEMA_VERSION as usize] = [
+    "
+CREATE TABLE manifests (
+    key TEXT PRIMARY KEY,
+    hash TEXT,
+    checked INTEGER NOT NULL DEFAULT 0,
+    manifest TEXT
+);
+CREATE TABLE installed (
+    key TEXT NOT NULL,
+    target TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    updated INTEGER NOT NULL,
+    PRIMARY KEY (key, target)
+);
+",
+    "
+CREATE TABLE usage (
+    toolchain TEXT PRIMARY KEY,
+    runs INTEGER NOT NULL,
+    last_used INTEGER NOT NULL
+);
+",
+];
+
+pub(crate) struct MetadataDb {
+    path: PathBuf,
+    /// Where earlier versions of rustup kept the state, which is moved into
+    /// the database when it is created.
+    manifests_dir: PathBuf,
+    update_hashes_dir: PathBuf,
+    /// Opened on first use, since most commands never need it.
+    conn: OnceCell<Connection>,
+}
+
+impl MetadataDb {
+    /// The database of the rustup home `rustup_dir`.
+    pub(crate) fn new(rustup_dir: &Path) -> Self {
+        Self {
+            path: rustup_dir.join("metadata.db"),
+            manifests_dir: rustup_dir.join("manifests"),
+            update_hashes_dir: rustup_dir.join("update-hashes"),
+            conn: OnceCell::new(),
+        }
+    }
+
+    fn conn(&self) -> Result<&Connection> {
+        if let Some(conn) = self.conn.get() {
+            return Ok(conn);
+        }
+        let conn = self
+            .open()
+            .with_context(|| format!("could not open '{}'", self.path.display()))?;
+        Ok(self.conn.get_or_init(|| conn))
+    }
+
+    fn open(&self) -> Result<Connection> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        self.open_on(Storage::of(dir))
+    }
+
+    /// Opens the database, taking locks the way that works on `storage`.
+    ///
+    /// Not every network file system honours the POSIX locks sqlite takes by
+    /// default, and two rustups that each think they hold the lock corrupt
+    /// the database. There it locks by creating `metadata.db.lock` instead.
+    fn open_on(&self, storage: Storage) -> Result<Connection> {
+        let mut conn = match storage {
+            Storage::Network if cfg!(unix) => Connection::open_with_flags_and_vfs(
+                &self.path,
+                OpenFlags::default(),
+                "unix-dotfile",
+            )?,
+            _ => Connection::open(&self.path)?,
+        };
+        // Another rustup may be writing; wait for it rather than fail.
+        conn.busy_timeout(Duration::from_secs(30))?;
+        match user_version(&conn)? {
+            SCHEMA_VERSION => {}
+            version if version > SCHEMA_VERSION => {
+                bail!("unsupported metadata version {version}, from a newer rustup")
+            }
+            _ => self.upgrade(&mut conn)?,
+        }
+        Ok(conn)
+    }
+
+    /// Creates the tables that are missing, and moves what the legacy files
+    /// hold into them.
+    fn upgrade(&self, conn: &mut Connection) -> Result<()> {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        // Another rustup may have upgraded it while this one waited.
+        let version = user_version(&tx)?;
+        if version >= SCHEMA_VERSION {
+            return Ok(());
+        }
+        for tables in &SCHEMA[version as usize..] {
+            tx.execute_batch(tables)?;
+        }
+        let mut migrated = Vec::new();
+        if version < 1 && migrate_manifests(&tx, &self.manifests_dir)? {
+            migrated.push(&self.manifests_dir);
+        }
+        if migrate_update_hashes(&tx, &self.update_hashes_dir)? {
+            migrated.push(&self.update_hashes_dir);
+        }
+        tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        tx.commit()?;
+
+        for dir in migrated {
+            if let Err(e) = fs::remove_dir_all(dir) {
+                debug!(
+                    "could not remove '{}' after moving it into '{}': {e}",
+                    dir.display(),
+                    self.path.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds runs of toolchains, as `(toolchain, runs, last used)`, to the
+    /// totals of each.
+    pub(crate) fn add_usage<'a>(
+        &self,
+        usage: impl IntoIterator<Item = (&'a str, u64, u64)>,
+    ) -> Result<()> {
+        let tx = self.conn()?.unchecked_transaction()?;
+        for (toolchain, runs, last_used) in usage {
+            tx.execute(
+                "INSERT INTO usage (toolchain, runs, last_used) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (toolchain) DO UPDATE SET
+                     runs = runs + excluded.runs,
+                     last_used = max(last_used, excluded.last_used)",
+                params![toolchain, runs, last_used],
+            )?;
+        }
+        Ok(tx.commit()?)
+    }
+
+    /// The totals of the runs of each toolchain, as `(toolchain, runs, last
+    /// used)`.
+    pub(crate) fn usage(&self) -> Result<Vec<(String, u64, u64)>> {
+        Ok(self
+            .conn()?
+            .prepare("SELECT toolchain, runs, last_used FROM usage")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+impl fmt::Debug for MetadataDb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetadataDb")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ManifestStore for MetadataDb {
+    fn entry(&self, key: &str) -> Result<Option<CacheEntry>> {
+        let conn = self.conn()?;
+        let manifest = conn
+            .query_row(
+                "SELECT hash, checked FROM manifests WHERE key = ?1",
+                [key],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let installed = conn
+            .prepare("SELECT target, hash, updated FROM installed WHERE key = ?1")?
+            .query_map([key], |row| {
+                let installed = Installed {
+                    hash: row.get(1)?,
+                    updated: row.get(2)?,
+                };
+                Ok((row.get(0)?, installed))
+            })?
+            .collect::<rusqlite::Result<BTreeMap<_, _>>>()?;
+        if manifest.is_none() && installed.is_empty() {
+            return Ok(None);
+        }
+
+        let (hash, checked) = manifest.unwrap_or_default();
+        Ok(Some(CacheEntry {
+            hash,
+            checked,
+            installed,
+        }))
+    }
+
+    fn set_entry(&self, key: &str, entry: &CacheEntry) -> Result<()> {
+        let tx = self.conn()?.unchecked_transaction()?;
+        write_entry(&tx, key, entry)?;
+        Ok(tx.commit()?)
+    }
+
+    fn manifest(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn()?
+            .query_row(
+                "SELECT manifest FROM manifests WHERE key = ?1",
+                [key],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten())
+    }
+
+    fn set_manifest(&self, key: &str, manifest: &str) -> Result<()> {
+        write_manifest(self.conn()?, key, manifest)
+    }
+}
+
+fn user_version(conn: &Connection) -> Result<i32> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+fn write_entry(conn: &Connection, key: &str, entry: &CacheEntry) -> Result<()> {
+    conn.execute(
+        "INSERT INTO manifests (key, hash, checked) VALUES (?1, ?2, ?3)
+         ON CONFLICT (key) DO UPDATE SET hash = excluded.hash, checked = excluded.checked",
+        params![key, entry.hash, entry.checked],
+    )?;
+    conn.execute("DELETE FROM installed WHERE key = ?1", [key])?;
+    for (target, installed) in &entry.installed {
+        conn.execute(
+            "INSERT INTO installed (key, target, hash, updated) VALUES (?1, ?2, ?3, ?4)",
+            params![key, target, installed.hash, installed.updated],
+        )?;
+    }
+    Ok(())
+}
+
+fn write_manifest(conn: &Connection, key: &str, manifest: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO manifests (key, manifest) VALUES (?1, ?2)
+         ON CONFLICT (key) DO UPDATE SET manifest = excluded.manifest",
+        params![key, manifest],
+    )?;
+    Ok(())
+}
+
+/// Copies the entries an [`FsManifestStore`] kept in `dir`, returning
+/// whether there was such a directory.
+fn migrate_manifests(conn: &Connection, dir: &Path) -> Result<bool> {
+    let Some(files) = read_legacy_dir(dir)? else {
+        return Ok(false);
+    };
+    let legacy = FsManifestStore::new(dir.to_owned());
+    for file in files {
+        let file_name = file?.file_name();
+        let Some(key) = file_name.to_str().and_then(|n| n.strip_suffix(".json")) else {
+            continue;
+        };
+        let Some(entry) = legacy.entry(key)? else {
+            continue;
+        };
+        write_entry(conn, key, &entry)?;
+        if let Some(manifest) = legacy.manifest(key)? {
+            write_manifest(conn, key, &manifest)?;
+        }
+    }
+    Ok(true)
+}
+
+/// Copies the hashes of the manifests that toolchains were installed from,
+/// which very old versions of rustup kept in a file per toolchain in `dir`,
+/// unless a newer record is kept. Returns whether there was such a
+/// directory.
+fn migrate_update_hashes(conn: &Connection, dir: &Path) -> Result<bool> {
+    let Some(files) = read_legacy_dir(dir)? else {
+        return Ok(false);
+    };
+    for file in files {
+        let file = file?;
+        let Some(desc) = file
+            .file_name()
+            .to_str()
+            .and_then(|name| ToolchainDesc::from_str(name).ok())
+        else {
+            continue;
+        };
+        let path = file.path();
+        let hash = fs::read_to_string(&path)
+            .with_context(|| format!("could not read '{}'", path.display()))?;
+        let updated = file
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |elapsed| elapsed.as_secs());
+        conn.execute(
+            "INSERT OR IGNORE INTO installed (key, target, hash, updated) VALUES (?1, ?2, ?3, ?4)",
+            params![desc.manifest_name(), desc.target.to_string(), hash, updated],
+        )?;
+    }
+    Ok(true)
+}
+
+fn read_legacy_dir(dir: &Path) -> Result<Option<fs::ReadDir>> {
+    match fs::read_dir(dir) {
+        Ok(files) => Ok(Some(files)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("could not read '{}'", dir.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> CacheEntry {
+        CacheEntry {
+            hash: Some("hash".to_owned()),
+            checked: 1,
+            installed: BTreeMap::from([(
+                "x86_64-unknown-linux-gnu".to_owned(),
+                Installed {
+                    hash: "hash".to_owned(),
+                    updated: 2,
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let store = MetadataDb::new(dir.path());
+        assert_eq!(store.entry("nightly").unwrap(), None);
+        assert_eq!(store.manifest("nightly").unwrap(), None);
+
+        store.set_manifest("nightly", "manifest").unwrap();
+        store.set_entry("nightly", &entry()).unwrap();
+        assert_eq!(store.entry("nightly").unwrap(), Some(entry()));
+        assert_eq!(
+            store.manifest("nightly").unwrap().as_deref(),
+            Some("manifest")
+        );
+
+        let emptied = CacheEntry {
+            installed: BTreeMap::new(),
+            ..entry()
+        };
+        store.set_entry("nightly", &emptied).unwrap();
+        assert_eq!(store.entry("nightly").unwrap(), Some(emptied));
+        assert_eq!(store.entry("stable").unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn network_storage_is_locked_with_a_file() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let store = MetadataDb::new(dir.path());
+        let mut conn = store.open_on(Storage::Network).unwrap();
+        let lock = dir.path().join("metadata.db.lock");
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .unwrap();
+        assert!(lock.exists());
+        tx.commit().unwrap();
+        assert!(!lock.exists());
+
+        store.conn.set(conn).unwrap();
+        store.add_usage([("stable", 1, 100)]).unwrap();
+        assert_eq!(store.usage().unwrap(), [("stable".to_owned(), 1, 100)]);
+    }
+
+    #[test]
+    fn a_manifests_dir_is_moved_into_the_database() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let legacy_dir = dir.path().join("manifests");
+        let legacy = FsManifestStore::new(legacy_dir.clone());
+        legacy.set_manifest("nightly", "manifest").unwrap();
+        legacy.set_entry("nightly", &entry()).unwrap();
+
+        let update_hashes = dir.path().join("update-hashes");
+        fs::create_dir(&update_hashes).unwrap();
+        // Recorded again since, for this target.
+        fs::write(
+            update_hashes.join("nightly-x86_64-unknown-linux-gnu"),
+            "old",
+        )
+        .unwrap();
+        fs::write(
+            update_hashes.join("stable-x86_64-unknown-linux-gnu"),
+            "stable",
+        )
+        .unwrap();
+        fs::write(update_hashes.join("not a toolchain"), "").unwrap();
+
+        let store = MetadataDb::new(dir.path());
+        assert_eq!(store.entry("nightly").unwrap(), Some(entry()));
+        assert_eq!(
+            store.manifest("nightly").unwrap().as_deref(),
+            Some("manifest")
+        );
+        let stable = store.entry("stable").unwrap().unwrap();
+        assert_eq!(stable.installed["x86_64-unknown-linux-gnu"].hash, "stable");
+        assert!(!legacy_dir.exists());
+        assert!(!update_hashes.exists());
+    }
+
+    #[test]
+    fn a_first_version_database_is_upgraded() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let conn = Connection::open(dir.path().join("metadata.db")).unwrap();
+        conn.execute_batch(SCHEMA[0]).unwrap();
+        write_entry(&conn, "nightly", &entry()).unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+        drop(conn);
+
+        let store = MetadataDb::new(dir.path());
+        assert_eq!(store.entry("nightly").unwrap(), Some(entry()));
+        store.add_usage([("stable", 1, 100)]).unwrap();
+        assert_eq!(store.usage().unwrap(), [("stable".to_owned(), 1, 100)]);
+    }
+
+    #[test]
+    fn usage_adds_up() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let store = MetadataDb::new(dir.path());
+        store
+            .add_usage([("stable", 2, 100), ("nightly", 1, 90)])
+            .unwrap();
+        store.add_usage([("stable", 3, 80)]).unwrap();
+        let mut usage = store.usage().unwrap();
+        usage.sort();
+        assert_eq!(
+            usage,
+            [("nightly".to_owned(), 1, 90), ("stable".to_owned(), 5, 100)]
+        );
+    }
+}
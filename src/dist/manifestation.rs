@@ -5,23 +5,31 @@
 mod tests;
 
 use std::path::Path;
+use std::process::Command;
 
 use anyhow::{anyhow, bail, Context, Result};
+use futures_util::{stream, StreamExt, TryStreamExt};
 use tokio_retry::{strategy::FixedInterval, RetryIf};
+use tracing::{debug, warn, Instrument};
+use url::Url;
 
+use crate::cli::common;
+use crate::diskio::IoProfile;
 use crate::dist::component::{
-    Components, Package, TarGzPackage, TarXzPackage, TarZStdPackage, Transaction,
+    Components, Journal, Package, TarGzPackage, TarXzPackage, TarZStdPackage, Transaction,
 };
 use crate::dist::config::Config;
-use crate::dist::download::{DownloadCfg, File};
-use crate::dist::manifest::{Component, CompressionKind, Manifest, TargetedPackage};
+use crate::dist::download::{self, DownloadCfg, Downloaded, File};
+use crate::dist::manifest::{Component, CompressionKind, HashedBinary, Manifest, TargetedPackage};
 use crate::dist::notifications::*;
 use crate::dist::prefix::InstallPrefix;
+use crate::dist::store::FileStore;
 use crate::dist::temp;
+use crate::dist::timings::{self, Phase};
 use crate::dist::{Profile, TargetTriple, DEFAULT_DIST_SERVER};
 use crate::errors::RustupError;
 use crate::process::Process;
-use crate::utils;
+use crate::utils::{self, elf, runtime::in_runtime};
 
 pub(crate) const DIST_MANIFEST: &str = "multirust-channel-manifest.toml";
 pub(crate) const CONFIG_FILE: &str = "multirust-config.toml";
@@ -62,6 +70,42 @@ impl Changes {
     }
 }
 
+/// The changes that [`Manifestation::update`] would make to an installation.
+#[derive(Debug)]
+pub struct ChangePlan {
+    /// The archives to download, one for each component to install.
+    pub downloads: Vec<PlannedDownload>,
+    /// The installed components to remove, including any that are
+    /// reinstalled from the new manifest.
+    pub removals: Vec<Component>,
+    /// The components to install that the manifest has no archive for.
+    pub unavailable: Vec<Component>,
+    /// The total size of the downloads, if the manifest declares all of them.
+    pub download_size: Option<u64>,
+    /// The total size of the installed components, if the manifest declares
+    /// all of them.
+    pub installed_size: Option<u64>,
+}
+
+impl ChangePlan {
+    pub fn is_empty(&self) -> bool {
+        self.downloads.is_empty() && self.removals.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct PlannedDownload {
+    pub component: Component,
+    pub url: String,
+    pub hash: String,
+    pub compression: CompressionKind,
+    /// The size of the archive, if the manifest declares it.
+    pub size: Option<u64>,
+    /// The component's archives in other formats, in order of preference,
+    /// for when the first can't be downloaded.
+    pub fallbacks: Vec<HashedBinary>,
+}
+
 #[derive(PartialEq, Debug, Eq)]
 pub enum UpdateStatus {
     Changed,
@@ -83,6 +127,21 @@ impl Manifestation {
         })
     }
 
+    /// Computes what [`Self::update`] would download and remove to apply
+    /// `changes` with `new_manifest`, without changing anything.
+    ///
+    /// Unlike an update, this doesn't fail if some components are
+    /// unavailable; they are listed in [`ChangePlan::unavailable`] instead.
+    pub fn compute_changes(
+        &self,
+        new_manifest: &Manifest,
+        changes: &Changes,
+    ) -> Result<ChangePlan> {
+        let config = self.read_config()?;
+        let update = Update::build_update(self, new_manifest, changes, &config, &|_| {})?;
+        update.plan(new_manifest)
+    }
+
     /// Install or update from a given channel manifest, while
     /// selecting extension components to add or remove.
     ///
@@ -116,8 +175,40 @@ impl Manifestation {
         let rel_installed_manifest_path = prefix.rel_manifest_file(DIST_MANIFEST);
         let installed_manifest_path = prefix.path().join(&rel_installed_manifest_path);
 
+        // A fresh install keeps a journal of its progress, so that it can
+        // be resumed if it's interrupted.
+        let mut config = self.read_config()?;
+        let mut changes = changes;
+        let mut journal = None;
+        if config.is_none() {
+            match Journal::load(&prefix) {
+                Ok(None) => {}
+                Ok(Some(mut resumed))
+                    if resumed.can_resume(&new_manifest.date, &self.installation)? =>
+                {
+                    resumed.resume(&download_cfg.notify_handler)?;
+                    (download_cfg.notify_handler)(Notification::ResumingInstall(
+                        resumed.resumed_components().count(),
+                    ));
+                    // Carry on as if installing the components the journal
+                    // asks for into an empty toolchain.
+                    config = Some(Config::default());
+                    for component in resumed.components() {
+                        if !changes.explicit_add_components.contains(component) {
+                            changes.explicit_add_components.push(component.clone());
+                        }
+                    }
+                    journal = Some(resumed);
+                }
+                _ => {
+                    (download_cfg.notify_handler)(Notification::RestartingInstall);
+                    Journal::discard(&prefix, &download_cfg.notify_handler)?;
+                }
+            }
+        }
+        let fresh_install = config.is_none() && self.installation.list()?.is_empty();
+
         // Create the lists of components needed for installation
-        let config = self.read_config()?;
         let mut update = Update::build_update(
             self,
             new_manifest,
@@ -126,8 +217,10 @@ impl Manifestation {
             &download_cfg.notify_handler,
         )?;
 
-        if update.nothing_changes() {
-            return Ok(UpdateStatus::Unchanged);
+        match &journal {
+            Some(journal) => update.skip_installed(journal, new_manifest),
+            None if update.nothing_changes() => return Ok(UpdateStatus::Unchanged),
+            None => {}
         }
 
         // Validate that the requested components are available
@@ -150,10 +243,38 @@ impl Manifestation {
         let altered = tmp_cx.dist_server != DEFAULT_DIST_SERVER;
 
         // Download component packages and validate hashes
-        let mut things_to_install: Vec<(Component, CompressionKind, File)> = Vec::new();
-        let mut things_downloaded: Vec<String> = Vec::new();
-        let components = update.components_urls_and_hashes(new_manifest)?;
+        let plan = update.plan(new_manifest)?;
+        // Archives an interrupted attempt left behind are reused, and partial
+        // ones resumed, rather than downloaded again.
+        let (downloaded, partial) = plan.downloads.iter().fold((0, 0), |(full, partial), d| {
+            match download_cfg.downloaded(&d.hash) {
+                Downloaded::Fully => (full + 1, partial),
+                Downloaded::Partly => (full, partial + 1),
+                Downloaded::Not => (full, partial),
+            }
+        });
+        if downloaded + partial > 0 {
+            (download_cfg.notify_handler)(Notification::ResumingDownloads(
+                plan.downloads.len(),
+                downloaded,
+                partial,
+            ));
+        }
+        if let Some(download) = plan.download_size {
+            (download_cfg.notify_handler)(Notification::InstallSize(download, plan.installed_size));
+            if download_cfg
+                .confirm_downloads_over
+                .is_some_and(|limit| download > limit)
+                // Without a terminal to answer on, the download is declined.
+                && !common::confirm("Continue? (y/N)", false, download_cfg.process)
+                    .unwrap_or(false)
+            {
+                return Err(RustupError::DownloadNotConfirmed(download).into());
+            }
+        }
 
+        // Failed requests are retried when they are made; these retries start
+        // over from a partial download that turned out to be broken.
         const DEFAULT_MAX_RETRIES: usize = 3;
         let max_retries: usize = download_cfg
             .process
@@ -162,57 +283,103 @@ impl Manifestation {
             .and_then(|s| s.parse().ok())
             .unwrap_or(DEFAULT_MAX_RETRIES);
 
-        for (component, format, url, hash) in components {
-            (download_cfg.notify_handler)(Notification::DownloadingComponent(
-                &component.short_name(new_manifest),
-                &self.target_triple,
-                component.target.as_ref(),
-            ));
-            let url = if altered {
-                url.replace(DEFAULT_DIST_SERVER, tmp_cx.dist_server.as_str())
-            } else {
-                url
-            };
-
-            let url_url = utils::parse_url(&url)?;
-
-            let downloaded_file = RetryIf::spawn(
-                FixedInterval::from_millis(0).take(max_retries),
-                || download_cfg.download(&url_url, &hash),
-                |e: &anyhow::Error| {
-                    // retry only known retriable cases
-                    match e.downcast_ref::<RustupError>() {
-                        Some(RustupError::BrokenPartialFile)
-                        | Some(RustupError::DownloadingFile { .. }) => {
-                            (download_cfg.notify_handler)(Notification::RetryingDownload(&url));
-                            true
+        // Several components are downloaded at once, but they are still
+        // installed in the order of the plan.
+        let downloads = plan.downloads.into_iter().map(
+            |PlannedDownload {
+                 component,
+                 url,
+                 hash,
+                 compression,
+                 fallbacks,
+                 ..
+             }| async move {
+                let short_name = component.short_name(new_manifest);
+                (download_cfg.notify_handler)(Notification::DownloadingComponent(
+                    &short_name,
+                    &self.target_triple,
+                    component.target.as_ref(),
+                ));
+                let alter = |url: String| match altered {
+                    true => url.replace(DEFAULT_DIST_SERVER, tmp_cx.dist_server.as_str()),
+                    false => url,
+                };
+
+                // A dist server may not have every format the manifest lists,
+                // so the others are tried in turn.
+                let mut archives = fallbacks
+                    .into_iter()
+                    .map(|bin| (alter(bin.url), bin.hash, bin.compression))
+                    .peekable();
+                let (mut url, mut hash, mut compression) = (alter(url), hash, compression);
+                let downloaded_file = loop {
+                    let url_url = utils::parse_url(&url)?;
+                    let result = RetryIf::spawn(
+                        FixedInterval::from_millis(0).take(max_retries),
+                        || download_cfg.download(&url_url, &hash),
+                        |e: &anyhow::Error| match e.downcast_ref::<RustupError>() {
+                            Some(RustupError::BrokenPartialFile) => {
+                                (download_cfg.notify_handler)(Notification::RetryingDownload(&url));
+                                true
+                            }
+                            _ => false,
+                        },
+                    )
+                    .instrument(timings::component(&short_name))
+                    .await;
+                    match (result, archives.peek()) {
+                        (Ok(file), _) => break file,
+                        (Err(e), Some((next, _, _))) if download::is_server_failure(&e) => {
+                            (download_cfg.notify_handler)(Notification::TryingNextArchive(
+                                &url, next,
+                            ));
+                            (url, hash, compression) = archives.next().unwrap();
+                        }
+                        (Err(e), _) => {
+                            return Err(e.context(RustupError::ComponentDownloadFailed(
+                                component.name(new_manifest),
+                            )))
                         }
-                        _ => false,
                     }
-                },
-            )
-            .await
-            .with_context(|| RustupError::ComponentDownloadFailed(component.name(new_manifest)))?;
+                };
 
-            things_downloaded.push(hash);
+                Ok::<_, anyhow::Error>((component, compression, hash, downloaded_file))
+            },
+        );
+        // The retries wait on the timer of the runtime.
+        let downloaded = in_runtime(
+            stream::iter(downloads)
+                .buffered(download_cfg.max_downloads.max(1))
+                .try_collect::<Vec<_>>(),
+        )
+        .await?;
+
+        let mut things_to_install: Vec<(Component, CompressionKind, String, File)> = Vec::new();
+        let mut things_downloaded: Vec<String> = Vec::new();
+        for (component, compression, hash, downloaded_file) in downloaded {
+            things_downloaded.push(hash.clone());
 
-            things_to_install.push((component, format, downloaded_file));
+            things_to_install.push((component, compression, hash, downloaded_file));
         }
 
         // Begin transaction
-        let mut tx = Transaction::new(
-            prefix.clone(),
-            tmp_cx,
-            download_cfg.notify_handler,
-            download_cfg.process,
-        );
+        let mut tx = Transaction::new(prefix.clone(), tmp_cx, download_cfg.notify_handler);
+        let mut installed = Vec::new();
+        if fresh_install {
+            let final_list = update.final_component_list.clone();
+            journal = Some(Journal::begin(&prefix, &new_manifest.date, final_list)?);
+        }
+        if let Some(journal) = journal {
+            installed.extend(journal.resumed_components());
+            tx.set_journal(journal);
+        }
 
         // If the previous installation was from a v1 manifest we need
         // to uninstall it first.
-        tx = self.maybe_handle_v2_upgrade(&config, tx, download_cfg.process)?;
+        tx = self.maybe_handle_v2_upgrade(&config, tx)?;
 
         // Uninstall components
-        for component in &update.components_to_uninstall {
+        for component in &plan.removals {
             let notification = if implicit_modify {
                 Notification::RemovingOldComponent
             } else {
@@ -229,12 +396,11 @@ impl Manifestation {
                 new_manifest,
                 tx,
                 &download_cfg.notify_handler,
-                download_cfg.process,
             )?;
         }
 
         // Install components
-        for (component, format, installer_file) in things_to_install {
+        for (component, format, hash, installer_file) in things_to_install {
             // For historical reasons, the rust-installer component
             // names are not the same as the dist manifest component
             // names. Some are just the component name some are the
@@ -249,43 +415,20 @@ impl Manifestation {
                 component.target.as_ref(),
             ));
 
+            let _component = timings::component(&short_name).entered();
+            let _unpack = timings::phase(Phase::Unpack).entered();
             let notification_converter = |notification: crate::utils::Notification<'_>| {
                 (download_cfg.notify_handler)(notification.into());
             };
-            let gz;
-            let xz;
-            let zst;
-            let reader =
-                utils::FileReaderWithProgress::new_file(&installer_file, &notification_converter)?;
-            let package: &dyn Package = match format {
-                CompressionKind::GZip => {
-                    gz = TarGzPackage::new(
-                        reader,
-                        tmp_cx,
-                        Some(&notification_converter),
-                        download_cfg.process,
-                    )?;
-                    &gz
-                }
-                CompressionKind::XZ => {
-                    xz = TarXzPackage::new(
-                        reader,
-                        tmp_cx,
-                        Some(&notification_converter),
-                        download_cfg.process,
-                    )?;
-                    &xz
-                }
-                CompressionKind::ZStd => {
-                    zst = TarZStdPackage::new(
-                        reader,
-                        tmp_cx,
-                        Some(&notification_converter),
-                        download_cfg.process,
-                    )?;
-                    &zst
-                }
-            };
+            let package = unpack(
+                format,
+                &installer_file,
+                tmp_cx,
+                download_cfg.io_profile,
+                download_cfg.io_threads,
+                &notification_converter,
+                download_cfg.process,
+            )?;
 
             // If the package doesn't contain the component that the
             // manifest says it does then somebody must be playing a joke on us.
@@ -294,6 +437,14 @@ impl Manifestation {
             }
 
             tx = package.install(&self.installation, &pkg_name, Some(short_pkg_name), tx)?;
+            tx.component_installed(&component, &hash)?;
+            installed.push(pkg_name);
+        }
+
+        // Run the hook before committing, so that the update is rolled
+        // back if it fails to patch a file.
+        if let Some(hook) = download_cfg.post_install_hook {
+            self.run_post_install_hook(hook, &installed, download_cfg.process)?;
         }
 
         // Install new distribution manifest
@@ -307,8 +458,15 @@ impl Manifestation {
         // that identify installed components. The rust-installer metadata maintained by
         // `Components` *also* tracks what is installed, but it only tracks names, not
         // name/target. Needs to be fixed in rust-installer.
+        let from_url = config
+            .map(|c| c.from_url)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|pkg| update.final_component_list.iter().any(|c| &c.pkg == pkg))
+            .collect();
         let new_config = Config {
             components: update.final_component_list,
+            from_url,
             ..Config::default()
         };
         let config_str = new_config.stringify()?;
@@ -319,6 +477,10 @@ impl Manifestation {
 
         // End transaction
         tx.commit();
+        if let Some(store) = download_cfg.store {
+            self.link_to_store(store, &installed);
+        }
+        (download_cfg.notify_handler)(Notification::UpdateFinished);
 
         download_cfg.clean(&things_downloaded)?;
 
@@ -331,11 +493,10 @@ impl Manifestation {
         manifest: &Manifest,
         tmp_cx: &temp::Context,
         notify_handler: &dyn Fn(Notification<'_>),
-        process: &Process,
     ) -> Result<()> {
         let prefix = self.installation.prefix();
 
-        let mut tx = Transaction::new(prefix.clone(), tmp_cx, notify_handler, process);
+        let mut tx = Transaction::new(prefix.clone(), tmp_cx, notify_handler);
 
         // Read configuration and delete it
         let rel_config_path = prefix.rel_manifest_file(CONFIG_FILE);
@@ -348,7 +509,7 @@ impl Manifestation {
         tx.remove_file("dist config", rel_config_path)?;
 
         for component in config.components {
-            tx = self.uninstall_component(&component, manifest, tx, notify_handler, process)?;
+            tx = self.uninstall_component(&component, manifest, tx, notify_handler)?;
         }
         tx.commit();
 
@@ -361,7 +522,6 @@ impl Manifestation {
         manifest: &Manifest,
         mut tx: Transaction<'a>,
         notify_handler: &dyn Fn(Notification<'_>),
-        process: &Process,
     ) -> Result<Transaction<'a>> {
         // For historical reasons, the rust-installer component
         // names are not the same as the dist manifest component
@@ -370,9 +530,9 @@ impl Manifestation {
         let name = component.name_in_manifest();
         let short_name = component.short_name_in_manifest();
         if let Some(c) = self.installation.find(&name)? {
-            tx = c.uninstall(tx, process)?;
+            tx = c.uninstall(tx)?;
         } else if let Some(c) = self.installation.find(short_name)? {
-            tx = c.uninstall(tx, process)?;
+            tx = c.uninstall(tx)?;
         } else {
             notify_handler(Notification::MissingInstalledComponent(
                 &component.short_name(manifest),
@@ -382,6 +542,65 @@ impl Manifestation {
         Ok(tx)
     }
 
+    /// Runs `hook` with the path of each ELF file in the given installed
+    /// components, failing on the first file it can't handle.
+    fn run_post_install_hook(
+        &self,
+        hook: &str,
+        components: &[String],
+        process: &Process,
+    ) -> Result<()> {
+        let prefix = self.installation.prefix();
+        let mut files = Vec::new();
+        for name in components {
+            let Some(component) = self.installation.find(name)? else {
+                continue;
+            };
+            for part in component.parts()? {
+                elf::find(&prefix.abs_path(&part.1), &mut files)?;
+            }
+        }
+
+        for path in files {
+            debug!("running post-install hook on '{}'", path.display());
+            let status = process
+                .run(Command::new(hook).arg(&path))
+                .with_context(|| format!("could not run post-install hook '{hook}'"))?;
+            if !status.success() {
+                return Err(RustupError::PostInstallHookFailed {
+                    hook: hook.to_owned(),
+                    path,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Links the files of the given installed components to the copies of
+    /// their content in `store`. This only saves space, so the update has
+    /// succeeded whether or not it works.
+    fn link_to_store(&self, store: &Path, components: &[String]) {
+        let store = FileStore::new(store.to_owned());
+        let prefix = self.installation.prefix();
+        let link = || -> Result<u64> {
+            let mut saved = 0;
+            for name in components {
+                let Some(component) = self.installation.find(name)? else {
+                    continue;
+                };
+                for part in component.parts()? {
+                    saved += store.link_all(&prefix.abs_path(&part.1))?;
+                }
+            }
+            Ok(saved)
+        };
+        match link() {
+            Ok(saved) => debug!("sharing files with other toolchains saved {saved} bytes"),
+            Err(e) => warn!("could not share files with other toolchains: {e:#}"),
+        }
+    }
+
     // Read the config file. Config files are presently only created
     // for v2 installations.
     pub(crate) fn read_config(&self) -> Result<Option<Config>> {
@@ -419,11 +638,14 @@ impl Manifestation {
     }
 
     /// Installation using the legacy v1 manifest format
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn update_v1(
         &self,
         new_manifest: &[String],
-        update_hash: Option<&Path>,
+        update_hash: Option<&str>,
         tmp_cx: &temp::Context,
+        io_profile: IoProfile,
+        io_threads: Option<usize>,
         notify_handler: &dyn Fn(Notification<'_>),
         process: &Process,
     ) -> Result<Option<String>> {
@@ -458,7 +680,20 @@ impl Manifestation {
         let dld_dir = PathBuf::from("bogus");
         let dlcfg = DownloadCfg {
             dist_root: "bogus",
+            fallback_dist_roots: &[],
+            bundle: None,
             download_dir: &dld_dir,
+            cache_peers: &[],
+            failed_cache_peers: &Default::default(),
+            confirm_downloads_over: None,
+            post_install_hook: None,
+            store: None,
+            trusted_keys: &[],
+            fallback_trusted_keys: &[],
+            pgp_key_file: None,
+            io_profile,
+            io_threads,
+            max_downloads: 1,
             tmp_cx,
             notify_handler,
             process,
@@ -481,12 +716,12 @@ impl Manifestation {
         ));
 
         // Begin transaction
-        let mut tx = Transaction::new(prefix, tmp_cx, notify_handler, process);
+        let mut tx = Transaction::new(prefix, tmp_cx, notify_handler);
 
         // Uninstall components
         let components = self.installation.list()?;
         for component in components {
-            tx = component.uninstall(tx, process)?;
+            tx = component.uninstall(tx)?;
         }
 
         // Install all the components in the installer
@@ -495,8 +730,14 @@ impl Manifestation {
         };
         let reader =
             utils::FileReaderWithProgress::new_file(&installer_file, &notification_converter)?;
-        let package: &dyn Package =
-            &TarGzPackage::new(reader, tmp_cx, Some(&notification_converter), process)?;
+        let package: &dyn Package = &TarGzPackage::new(
+            reader,
+            tmp_cx,
+            io_profile,
+            io_threads,
+            Some(&notification_converter),
+            process,
+        )?;
 
         for component in package.components() {
             tx = package.install(&self.installation, &component, None, tx)?;
@@ -508,6 +749,95 @@ impl Manifestation {
         Ok(Some(installer_hash))
     }
 
+    /// Installs the component `name` from a rust-installer archive at `url`
+    /// that is not part of the channel manifest, replacing any earlier
+    /// version of it. The archive must have the given SHA-256 hash.
+    pub async fn install_from_url(
+        &self,
+        url: &Url,
+        hash: &str,
+        name: &str,
+        download_cfg: &DownloadCfg<'_>,
+    ) -> Result<()> {
+        let mut config = self.read_config()?.unwrap_or_default();
+        if config.components.iter().any(|c| {
+            (c.name_in_manifest() == name || c.short_name_in_manifest() == name)
+                && !config.from_url.contains(&c.pkg)
+        }) {
+            bail!("component '{name}' comes from the channel manifest and cannot be replaced");
+        }
+        let Some(format) = CompressionKind::from_file_name(url.path()) else {
+            bail!("'{url}' is not a .tar.gz, .tar.xz or .tar.zst archive");
+        };
+
+        (download_cfg.notify_handler)(Notification::DownloadingComponent(
+            name,
+            &self.target_triple,
+            None,
+        ));
+        let installer_file = download_cfg
+            .download(url, hash)
+            .instrument(timings::component(name))
+            .await?;
+
+        let mut tx = Transaction::new(
+            self.installation.prefix(),
+            download_cfg.tmp_cx,
+            download_cfg.notify_handler,
+        );
+        if let Some(component) = self.installation.find(name)? {
+            tx = component.uninstall(tx)?;
+        }
+
+        (download_cfg.notify_handler)(Notification::InstallingComponent(
+            name,
+            &self.target_triple,
+            None,
+        ));
+        let component_span = timings::component(name).entered();
+        let unpack_span = timings::phase(Phase::Unpack).entered();
+        let notification_converter = |notification: crate::utils::Notification<'_>| {
+            (download_cfg.notify_handler)(notification.into());
+        };
+        let package = unpack(
+            format,
+            &installer_file,
+            download_cfg.tmp_cx,
+            download_cfg.io_profile,
+            download_cfg.io_threads,
+            &notification_converter,
+            download_cfg.process,
+        )?;
+        if !package.contains(name, None) {
+            bail!("'{url}' does not contain a component named '{name}'");
+        }
+        tx = package.install(&self.installation, name, None, tx)?;
+        drop((unpack_span, component_span));
+
+        if let Some(hook) = download_cfg.post_install_hook {
+            self.run_post_install_hook(hook, &[name.to_owned()], download_cfg.process)?;
+        }
+
+        // Record the component, so that it can be removed like any other.
+        let component = Component::new(name.to_owned(), Some(self.target_triple.clone()), true);
+        if !config.components.contains(&component) {
+            config.components.push(component);
+            config.from_url.push(name.to_owned());
+        }
+        let prefix = self.installation.prefix();
+        let rel_config_path = prefix.rel_manifest_file(CONFIG_FILE);
+        let config_path = prefix.path().join(&rel_config_path);
+        tx.modify_file(rel_config_path)?;
+        utils::write_file("dist config", &config_path, &config.stringify()?)?;
+        tx.commit();
+        if let Some(store) = download_cfg.store {
+            self.link_to_store(store, &[name.to_owned()]);
+        }
+
+        download_cfg.clean(&[hash.to_owned()])?;
+        Ok(())
+    }
+
     // If the previous installation was from a v1 manifest, then it
     // doesn't have a configuration or manifest-derived list of
     // component/target pairs. Uninstall it using the installer's
@@ -516,7 +846,6 @@ impl Manifestation {
         &self,
         config: &Option<Config>,
         mut tx: Transaction<'a>,
-        process: &Process,
     ) -> Result<Transaction<'a>> {
         let installed_components = self.installation.list()?;
         let looks_like_v1 = config.is_none() && !installed_components.is_empty();
@@ -526,13 +855,52 @@ impl Manifestation {
         }
 
         for component in installed_components {
-            tx = component.uninstall(tx, process)?;
+            tx = component.uninstall(tx)?;
         }
 
         Ok(tx)
     }
 }
 
+/// Unpacks a component archive in the given format.
+fn unpack<'a>(
+    format: CompressionKind,
+    installer_file: &Path,
+    tmp_cx: &'a temp::Context,
+    io_profile: IoProfile,
+    io_threads: Option<usize>,
+    notify_handler: &'a dyn Fn(utils::Notification<'_>),
+    process: &Process,
+) -> Result<Box<dyn Package + 'a>> {
+    let reader = utils::FileReaderWithProgress::new_file(installer_file, notify_handler)?;
+    Ok(match format {
+        CompressionKind::GZip => Box::new(TarGzPackage::new(
+            reader,
+            tmp_cx,
+            io_profile,
+            io_threads,
+            Some(notify_handler),
+            process,
+        )?),
+        CompressionKind::XZ => Box::new(TarXzPackage::new(
+            reader,
+            tmp_cx,
+            io_profile,
+            io_threads,
+            Some(notify_handler),
+            process,
+        )?),
+        CompressionKind::ZStd => Box::new(TarZStdPackage::new(
+            reader,
+            tmp_cx,
+            io_profile,
+            io_threads,
+            Some(notify_handler),
+            process,
+        )?),
+    })
+}
+
 #[derive(Debug)]
 struct Update {
     components_to_uninstall: Vec<Component>,
@@ -557,10 +925,17 @@ impl Update {
 
         changes.check_invariants(config)?;
 
-        // The list of components already installed, empty if a new install
-        let mut starting_list = config
+        // The list of components already installed, empty if a new install.
+        // Those installed from a URL are kept unless removed, whatever the
+        // manifest says.
+        let (from_url, mut starting_list): (Vec<_>, Vec<_>) = config
             .as_ref()
-            .map(|c| c.components.clone())
+            .map(|c| {
+                c.components
+                    .iter()
+                    .cloned()
+                    .partition(|component| c.from_url.contains(&component.pkg))
+            })
             .unwrap_or_default();
 
         let installed_components = manifestation.installation.list()?;
@@ -623,6 +998,14 @@ impl Update {
                 .clone_from(&result.final_component_list);
         }
 
+        for component in from_url {
+            if changes.remove_components.contains(&component) {
+                result.components_to_uninstall.push(component);
+            } else {
+                result.final_component_list.push(component);
+            }
+        }
+
         Ok(result)
     }
 
@@ -716,35 +1099,67 @@ impl Update {
         Ok(())
     }
 
+    /// Leaves out the components that an interrupted install has already
+    /// installed from the archives of `new_manifest`.
+    fn skip_installed(&mut self, journal: &Journal, new_manifest: &Manifest) {
+        self.components_to_install.retain(|component| {
+            let bins = new_manifest
+                .get_package(component.short_name_in_manifest())
+                .and_then(|package| package.get_target(component.target.as_ref()))
+                .ok()
+                .map(|target_package| &target_package.bins[..])
+                .unwrap_or_default();
+            // The archive may have been one of the fallback formats.
+            !bins
+                .iter()
+                .any(|bin| journal.is_installed(component, &bin.hash))
+        });
+    }
+
     fn drop_components_to_install(&mut self, to_drop: &[Component]) {
         self.components_to_install.retain(|c| !to_drop.contains(c));
         self.final_component_list.retain(|c| !to_drop.contains(c));
     }
 
-    /// Map components to urls and hashes
-    fn components_urls_and_hashes(
-        &self,
-        new_manifest: &Manifest,
-    ) -> Result<Vec<(Component, CompressionKind, String, String)>> {
-        let mut components_urls_and_hashes = Vec::new();
+    /// The downloads and removals that carry out this update, and their sizes.
+    fn plan(&self, new_manifest: &Manifest) -> Result<ChangePlan> {
+        let mut downloads = Vec::new();
+        let mut unavailable = self.missing_components.clone();
+        let mut installed_size = Some(0);
         for component in &self.components_to_install {
             let package = new_manifest.get_package(component.short_name_in_manifest())?;
             let target_package = package.get_target(component.target.as_ref())?;
 
-            if target_package.bins.is_empty() {
-                // This package is not available, no files to download.
-                continue;
-            }
             // We prefer the first format in the list, since the parsing of the
             // manifest leaves us with the files/hash pairs in preference order.
-            components_urls_and_hashes.push((
-                component.clone(),
-                target_package.bins[0].compression,
-                target_package.bins[0].url.clone(),
-                target_package.bins[0].hash.clone(),
-            ));
+            let Some((bin, fallbacks)) = target_package.bins.split_first() else {
+                // This package is not available, no files to download.
+                unavailable.push(component.clone());
+                continue;
+            };
+            downloads.push(PlannedDownload {
+                component: component.clone(),
+                url: bin.url.clone(),
+                hash: bin.hash.clone(),
+                compression: bin.compression,
+                size: bin.size,
+                fallbacks: fallbacks.to_vec(),
+            });
+            installed_size = installed_size
+                .zip(target_package.installed_size)
+                .map(|(a, b)| a + b);
         }
 
-        Ok(components_urls_and_hashes)
+        let (download_size, installed_size) = match downloads.is_empty() {
+            true => (None, None),
+            false => (downloads.iter().map(|d| d.size).sum(), installed_size),
+        };
+        Ok(ChangePlan {
+            downloads,
+            removals: self.components_to_uninstall.clone(),
+            unavailable,
+            download_size,
+            installed_size,
+        })
     }
 }
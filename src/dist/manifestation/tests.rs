@@ -3,22 +3,24 @@
 #![allow(clippy::type_complexity)]
 
 use std::{
-    cell::Cell,
-    collections::HashMap,
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
     env, fs,
     path::{Path, PathBuf},
     str::FromStr,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
 use anyhow::{anyhow, Result};
 use url::Url;
 
 use crate::{
+    diskio::IoProfile,
     dist::{
+        component::{Journal, Transaction},
         download::DownloadCfg,
         manifest::{Component, Manifest},
-        manifestation::{Changes, Manifestation, UpdateStatus},
+        manifestation::{Changes, Manifestation, UpdateStatus, CONFIG_FILE, DIST_MANIFEST},
         prefix::InstallPrefix,
         temp, Notification, Profile, TargetTriple, ToolchainDesc, DEFAULT_DIST_SERVER,
     },
@@ -229,7 +231,7 @@ fn mock_dist_server_smoke_test() {
     let tempdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
     let path = tempdir.path();
 
-    create_mock_dist_server(path, None).write(&[MockManifestVersion::V2], false, false);
+    create_mock_dist_server(path, None).write(&[MockManifestVersion::V2], false, false, false);
 
     assert!(utils::path_exists(path.join(
         "dist/2016-02-01/rustc-nightly-x86_64-apple-darwin.tar.gz"
@@ -407,6 +409,7 @@ struct TestContext {
     download_dir: PathBuf,
     tp: TestProcess,
     tmp_cx: temp::Context,
+    failed_cache_peers: Mutex<HashSet<String>>,
     _tempdirs: Vec<tempfile::TempDir>,
 }
 
@@ -426,6 +429,7 @@ impl TestContext {
             &[MockManifestVersion::V2],
             comps.enable_xz(),
             comps.enable_zst(),
+            false,
         );
 
         let prefix_tempdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
@@ -453,6 +457,7 @@ impl TestContext {
             prefix,
             tp,
             tmp_cx,
+            failed_cache_peers: Mutex::default(),
             _tempdirs: vec![prefix_tempdir, work_tempdir],
         }
     }
@@ -460,8 +465,21 @@ impl TestContext {
     fn default_dl_cfg(&self) -> DownloadCfg<'_> {
         DownloadCfg {
             dist_root: "phony",
+            fallback_dist_roots: &[],
+            bundle: None,
             tmp_cx: &self.tmp_cx,
             download_dir: &self.download_dir,
+            cache_peers: &[],
+            failed_cache_peers: &self.failed_cache_peers,
+            confirm_downloads_over: None,
+            post_install_hook: None,
+            store: None,
+            trusted_keys: &[],
+            fallback_trusted_keys: &[],
+            pgp_key_file: None,
+            io_profile: IoProfile::Auto,
+            io_threads: None,
+            max_downloads: 4,
             notify_handler: &|event| println!("{event}"),
             process: &self.tp.process,
         }
@@ -526,7 +544,7 @@ impl TestContext {
         let manifestation = Manifestation::open(self.prefix.clone(), trip)?;
         let manifest = manifestation.load_manifest()?.unwrap();
 
-        manifestation.uninstall(&manifest, &self.tmp_cx, &|_| (), &self.tp.process)?;
+        manifestation.uninstall(&manifest, &self.tmp_cx, &|_| ())?;
 
         Ok(())
     }
@@ -555,6 +573,39 @@ async fn initial_install_zst() {
     initial_install(AddZStd).await;
 }
 
+#[tokio::test]
+async fn missing_zst_archives_fall_back_to_the_next_format() {
+    let cx = TestContext::new(None, AddZStd);
+    remove_archives(&cx.url.to_file_path().unwrap().join("dist"), ".tar.zst");
+
+    let fell_back = RefCell::new(Vec::new());
+    let dl_cfg = DownloadCfg {
+        notify_handler: &|n| {
+            if let Notification::TryingNextArchive(url, next) = n {
+                fell_back
+                    .borrow_mut()
+                    .push((url.to_owned(), next.to_owned()));
+            }
+        },
+        ..cx.default_dl_cfg()
+    };
+    cx.update_from_dist_with_dl_cfg(&[], &[], false, &dl_cfg)
+        .await
+        .unwrap();
+
+    assert!(utils::path_exists(cx.prefix.path().join("bin/rustc")));
+    assert!(utils::path_exists(cx.prefix.path().join("lib/libstd.rlib")));
+    let fell_back = fell_back.into_inner();
+    assert!(!fell_back.is_empty());
+    for (url, next) in fell_back {
+        assert_eq!(
+            url.strip_suffix(".tar.zst"),
+            next.strip_suffix(".tar.gz"),
+            "{url} -> {next}"
+        );
+    }
+}
+
 #[tokio::test]
 async fn test_uninstall() {
     let cx = TestContext::new(None, GZOnly);
@@ -1003,6 +1054,47 @@ async fn add_extensions_for_same_manifest() {
     ));
 }
 
+#[tokio::test]
+async fn compute_changes_for_same_manifest() {
+    let cx = TestContext::new(None, GZOnly);
+    cx.update_from_dist(&[], &[], false).await.unwrap();
+
+    let manifestation =
+        Manifestation::open(cx.prefix.clone(), cx.toolchain.target.clone()).unwrap();
+    let manifest = manifestation.load_manifest().unwrap().unwrap();
+    let add = Component::new(
+        "rust-std".to_string(),
+        Some(TargetTriple::new("i686-apple-darwin")),
+        false,
+    );
+    let remove = Component::new(
+        "rust-std".to_string(),
+        Some(TargetTriple::new("x86_64-apple-darwin")),
+        false,
+    );
+    let changes = Changes {
+        explicit_add_components: vec![add.clone()],
+        remove_components: vec![remove.clone()],
+    };
+
+    let plan = manifestation.compute_changes(&manifest, &changes).unwrap();
+    assert_eq!(
+        plan.downloads
+            .iter()
+            .map(|d| &d.component)
+            .collect::<Vec<_>>(),
+        [&add]
+    );
+    assert_eq!(plan.removals, [remove]);
+    assert!(plan.unavailable.is_empty());
+
+    // Nothing was changed.
+    assert!(!utils::path_exists(
+        cx.prefix.path().join("lib/i686-apple-darwin/libstd.rlib")
+    ));
+    assert!(utils::path_exists(cx.prefix.path().join("lib/libstd.rlib")));
+}
+
 #[tokio::test]
 async fn add_extensions_for_upgrade() {
     let cx = TestContext::new(None, GZOnly);
@@ -1528,3 +1620,247 @@ async fn handle_corrupt_partial_downloads() {
     assert!(utils::path_exists(cx.prefix.path().join("bin/rustc")));
     assert!(utils::path_exists(cx.prefix.path().join("lib/libstd.rlib")));
 }
+
+// Leaves the installation as if its install had been interrupted while
+// installing cargo from the manifest of `date`.
+fn interrupt_install(cx: &TestContext, date: &str) {
+    let manifestation =
+        Manifestation::open(cx.prefix.clone(), cx.toolchain.target.clone()).unwrap();
+    let manifest = manifestation.load_manifest().unwrap().unwrap();
+    let config = manifestation.read_config().unwrap().unwrap();
+    let (cargo, installed): (Vec<_>, Vec<_>) = config
+        .components
+        .iter()
+        .partition(|c| c.short_name_in_manifest() == "cargo");
+
+    let mut tx = Transaction::new(cx.prefix.clone(), &cx.tmp_cx, &|_| ());
+    tx = manifestation
+        .uninstall_component(cargo[0], &manifest, tx, &|_| ())
+        .unwrap();
+    tx.commit();
+    for file in [CONFIG_FILE, DIST_MANIFEST] {
+        utils::remove_file("test", &cx.prefix.manifest_file(file)).unwrap();
+    }
+
+    let mut journal = Journal::begin(&cx.prefix, date, config.components.clone()).unwrap();
+    for component in installed {
+        let package = manifest.get_package(component.short_name_in_manifest());
+        let target_package = package.unwrap().get_target(component.target.as_ref());
+        let hash = &target_package.unwrap().bins[0].hash;
+        journal.component_installed(component, hash).unwrap();
+    }
+    journal.add(Path::new("bin/cargo")).unwrap();
+    utils::write_file("test", &cx.prefix.path().join("bin/cargo"), "partial").unwrap();
+}
+
+#[tokio::test]
+async fn resume_interrupted_install() {
+    let cx = TestContext::new(None, GZOnly);
+    cx.update_from_dist(&[], &[], false).await.unwrap();
+    interrupt_install(&cx, "2016-02-02");
+
+    let resumed = Cell::new(None);
+    let installing = RefCell::new(Vec::new());
+    let dl_cfg = DownloadCfg {
+        notify_handler: &|n| match n {
+            Notification::ResumingInstall(n) => resumed.set(Some(n)),
+            Notification::InstallingComponent(c, _, _) => {
+                installing.borrow_mut().push(c.to_owned())
+            }
+            _ => {}
+        },
+        ..cx.default_dl_cfg()
+    };
+    cx.update_from_dist_with_dl_cfg(&[], &[], false, &dl_cfg)
+        .await
+        .unwrap();
+
+    assert_eq!(resumed.get(), Some(2));
+    assert_eq!(*installing.borrow(), ["cargo"]);
+    let cargo = utils::read_file("cargo", &cx.prefix.path().join("bin/cargo")).unwrap();
+    assert_ne!(cargo, "partial");
+    assert!(utils::path_exists(cx.prefix.path().join("bin/rustc")));
+    assert!(utils::path_exists(cx.prefix.manifest_file(CONFIG_FILE)));
+    assert!(Journal::load(&cx.prefix).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn restart_interrupted_install_from_another_manifest() {
+    let cx = TestContext::new(None, GZOnly);
+    cx.update_from_dist(&[], &[], false).await.unwrap();
+    interrupt_install(&cx, "2016-02-01");
+
+    let restarted = Cell::new(false);
+    let installing = RefCell::new(Vec::new());
+    let dl_cfg = DownloadCfg {
+        notify_handler: &|n| match n {
+            Notification::RestartingInstall => restarted.set(true),
+            Notification::InstallingComponent(c, _, _) => {
+                installing.borrow_mut().push(c.to_owned())
+            }
+            _ => {}
+        },
+        ..cx.default_dl_cfg()
+    };
+    cx.update_from_dist_with_dl_cfg(&[], &[], false, &dl_cfg)
+        .await
+        .unwrap();
+
+    assert!(restarted.get());
+    assert_eq!(installing.borrow().len(), 3);
+    let cargo = utils::read_file("cargo", &cx.prefix.path().join("bin/cargo")).unwrap();
+    assert_ne!(cargo, "partial");
+    assert!(Journal::load(&cx.prefix).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn resume_interrupted_downloads() {
+    let cx = TestContext::new(None, GZOnly);
+    cx.update_from_dist(&[], &[], false).await.unwrap();
+
+    // Start over with the archives of the first two components downloaded,
+    // the second one only in part.
+    let manifestation =
+        Manifestation::open(cx.prefix.clone(), cx.toolchain.target.clone()).unwrap();
+    let manifest = manifestation.load_manifest().unwrap().unwrap();
+    let config = manifestation.read_config().unwrap().unwrap();
+    let archives = config
+        .components
+        .iter()
+        .map(|component| {
+            let package = manifest.get_package(component.short_name_in_manifest());
+            let target_package = package.unwrap().get_target(component.target.as_ref());
+            let bin = &target_package.unwrap().bins[0];
+            let path = Url::parse(&bin.url).unwrap().to_file_path().unwrap();
+            (bin.hash.clone(), fs::read(path).unwrap())
+        })
+        .collect::<Vec<_>>();
+    fs::remove_dir_all(cx.prefix.path()).unwrap();
+    fs::create_dir_all(&cx.download_dir).unwrap();
+    let (hash, archive) = &archives[0];
+    fs::write(cx.download_dir.join(hash), archive).unwrap();
+    let (hash, archive) = &archives[1];
+    let partial = &archive[..archive.len() / 2];
+    fs::write(cx.download_dir.join(format!("{hash}.partial")), partial).unwrap();
+
+    let resumed = Cell::new(None);
+    let dl_cfg = DownloadCfg {
+        notify_handler: &|n| {
+            if let Notification::ResumingDownloads(total, downloaded, partial) = n {
+                resumed.set(Some((total, downloaded, partial)));
+            }
+        },
+        ..cx.default_dl_cfg()
+    };
+    cx.update_from_dist_with_dl_cfg(&[], &[], false, &dl_cfg)
+        .await
+        .unwrap();
+
+    assert_eq!(resumed.get(), Some((archives.len(), 1, 1)));
+    assert!(utils::path_exists(cx.prefix.path().join("bin/rustc")));
+    assert!(utils::path_exists(cx.prefix.path().join("bin/cargo")));
+}
+
+#[tokio::test]
+async fn fresh_downloads_are_not_resumed() {
+    let cx = TestContext::new(None, GZOnly);
+
+    let resumed = Cell::new(false);
+    let dl_cfg = DownloadCfg {
+        notify_handler: &|n| {
+            if let Notification::ResumingDownloads(..) = n {
+                resumed.set(true);
+            }
+        },
+        ..cx.default_dl_cfg()
+    };
+    cx.update_from_dist_with_dl_cfg(&[], &[], false, &dl_cfg)
+        .await
+        .unwrap();
+
+    assert!(!resumed.get());
+}
+
+/// Removes the archives below `dir` whose names end with `extension`.
+fn remove_archives(dir: &Path, extension: &str) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            remove_archives(&path, extension);
+        } else if path.to_string_lossy().ends_with(extension) {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn components_missing_from_the_dist_server_come_from_the_next() {
+    let cx = TestContext::new(None, GZOnly);
+
+    // The next server has everything, while the first one lost its
+    // archives.
+    let mirror_tempdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let dist = cx.url.to_file_path().unwrap().join("dist");
+    utils_raw::copy_dir(&dist, &mirror_tempdir.path().join("dist")).unwrap();
+    remove_archives(&dist, ".tar.gz");
+
+    let fallback_dist_roots = [format!(
+        "file://{}/dist",
+        mirror_tempdir.path().to_string_lossy()
+    )];
+    let dist_root = format!("{}/dist", cx.url);
+    let fell_back = RefCell::new(Vec::new());
+    let dl_cfg = DownloadCfg {
+        dist_root: &dist_root,
+        fallback_dist_roots: &fallback_dist_roots,
+        notify_handler: &|n| {
+            if let Notification::TryingNextDistServer(url, next) = n {
+                fell_back
+                    .borrow_mut()
+                    .push((url.to_owned(), next.to_owned()));
+            }
+        },
+        ..cx.default_dl_cfg()
+    };
+    cx.update_from_dist_with_dl_cfg(&[], &[], false, &dl_cfg)
+        .await
+        .unwrap();
+
+    assert!(utils::path_exists(cx.prefix.path().join("bin/rustc")));
+    let fell_back = fell_back.into_inner();
+    assert!(!fell_back.is_empty());
+    for (url, next) in fell_back {
+        assert!(url.starts_with(&dist_root));
+        assert_eq!(
+            next.strip_prefix(&fallback_dist_roots[0]),
+            url.strip_prefix(&dist_root)
+        );
+    }
+}
+
+#[tokio::test]
+async fn components_missing_from_every_dist_server_fail() {
+    let cx = TestContext::new(None, GZOnly);
+
+    let dist_root = format!("{}/dist", cx.url);
+    let fallback_dist_roots = ["file:///no-such-server/dist".to_owned()];
+    let dist = cx.url.to_file_path().unwrap().join("dist");
+    for entry in fs::read_dir(&dist).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            fs::remove_dir_all(path).unwrap();
+        }
+    }
+    let dl_cfg = DownloadCfg {
+        dist_root: &dist_root,
+        fallback_dist_roots: &fallback_dist_roots,
+        ..cx.default_dl_cfg()
+    };
+    let err = cx
+        .update_from_dist_with_dl_cfg(&[], &[], false, &dl_cfg)
+        .await
+        .unwrap_err();
+
+    // The error is about the last server tried.
+    assert!(format!("{err:#}").contains("'file:///no-such-server/dist/"));
+}
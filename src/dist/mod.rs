@@ -1,8 +1,6 @@
 //! Installation from a Rust distribution server
 
-use std::{
-    collections::HashSet, env, fmt, io::Write, ops::Deref, path::Path, str::FromStr, sync::LazyLock,
-};
+use std::{collections::HashSet, env, fmt, io::Write, ops::Deref, str::FromStr, sync::LazyLock};
 
 use anyhow::{anyhow, bail, Context, Result};
 use chrono::NaiveDate;
@@ -11,27 +9,30 @@ use itertools::Itertools;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use thiserror::Error as ThisError;
-use tracing::{info, warn};
+use tracing::{debug, info, warn, Instrument};
 
 use crate::{
-    config::{dist_root_server, Cfg},
-    errors::RustupError,
-    process::Process,
-    toolchain::ToolchainName,
-    utils,
+    config::Cfg, errors::RustupError, process::Process, toolchain::ToolchainName, utils,
+    utils::runtime::in_runtime,
 };
 
 pub mod component;
 pub(crate) mod config;
+pub(crate) mod credentials;
 
 pub mod download;
 use download::DownloadCfg;
 
+pub(crate) mod export;
+
 pub mod manifest;
 use manifest::{Component, Manifest as ManifestV2};
 
+pub(crate) mod manifest_cache;
+use manifest_cache::ManifestCache;
+
 pub mod manifestation;
-use manifestation::{Changes, Manifestation, UpdateStatus};
+use manifestation::{ChangePlan, Changes, Manifestation, UpdateStatus};
 
 pub(crate) mod notifications;
 pub use notifications::Notification;
@@ -39,8 +40,15 @@ pub use notifications::Notification;
 pub mod prefix;
 use prefix::InstallPrefix;
 
+pub(crate) mod store;
+
 pub mod temp;
 
+pub(crate) mod timings;
+use timings::Phase;
+
+pub mod trust;
+
 pub(crate) mod triple;
 pub(crate) use triple::*;
 
@@ -129,6 +137,8 @@ pub enum DistError {
     MissingReleaseForToolchain(String),
     #[error("invalid toolchain name: '{0}'")]
     InvalidOfficialName(String),
+    #[error("'{0}' only has a legacy release, whose signature can't be checked against the trusted keys; pass `--no-verify` to install it anyway")]
+    UnsignedLegacyRelease(String),
 }
 
 #[derive(Debug, PartialEq)]
@@ -796,6 +806,12 @@ impl fmt::Display for PartialToolchainDesc {
     }
 }
 
+impl Serialize for ToolchainDesc {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 impl fmt::Display for ToolchainDesc {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", &self.channel)?;
@@ -820,7 +836,6 @@ pub(crate) struct DistOptions<'a> {
     pub(crate) cfg: &'a Cfg<'a>,
     pub(crate) toolchain: &'a ToolchainDesc,
     pub(crate) profile: Profile,
-    pub(crate) update_hash: Option<&'a Path>,
     pub(crate) dl_cfg: DownloadCfg<'a>,
     /// --force bool is whether to force an update/install
     pub(crate) force: bool,
@@ -846,14 +861,18 @@ pub(crate) async fn update_from_dist(
     prefix: &InstallPrefix,
     opts: &DistOptions<'_>,
 ) -> Result<Option<String>> {
+    opts.cfg.policy.check_channel(&opts.toolchain.channel)?;
+
+    let cache = &opts.cfg.manifest_cache;
     let fresh_install = !prefix.path().exists();
-    if let Some(hash) = opts.update_hash {
-        // fresh_install means the toolchain isn't present, but hash_exists means there is a stray hash file
-        if fresh_install && Path::exists(hash) {
-            (opts.dl_cfg.notify_handler)(Notification::StrayHash(hash));
-            std::fs::remove_file(hash)?;
-        }
+    // fresh_install means the toolchain isn't present, so whatever manifest
+    // it was installed from is stray
+    if fresh_install && cache.remove_installed(opts.toolchain)? {
+        (opts.dl_cfg.notify_handler)(Notification::StrayHash(&opts.toolchain.to_string()));
     }
+    let update_hash = cache
+        .installed(opts.toolchain)?
+        .map(|installed| installed.hash);
 
     let mut fetched = String::new();
     let mut first_err = None;
@@ -905,7 +924,8 @@ pub(crate) async fn update_from_dist(
     let res = loop {
         let result = try_update_from_dist_(
             opts.dl_cfg,
-            opts.update_hash,
+            cache,
+            update_hash.as_deref(),
             &toolchain,
             match opts.exists {
                 false => Some(opts.profile),
@@ -994,10 +1014,55 @@ pub(crate) async fn update_from_dist(
     res
 }
 
+/// What [`update_from_dist`] would change, for `--dry-run`: the manifest it
+/// would install from and the plan for it, or `None` if the toolchain is up
+/// to date. Only the manifest is downloaded, and an older nightly is not
+/// looked for when the latest lacks a component.
+pub(crate) async fn plan_from_dist(
+    prefix: &InstallPrefix,
+    opts: &DistOptions<'_>,
+) -> Result<Option<(ManifestV2, ChangePlan)>> {
+    opts.cfg.policy.check_channel(&opts.toolchain.channel)?;
+
+    let cache = &opts.cfg.manifest_cache;
+    let update_hash = match prefix.path().exists() {
+        true => cache
+            .installed(opts.toolchain)?
+            .map(|installed| installed.hash),
+        false => None,
+    };
+    let update_hash = update_hash.filter(|_| opts.components.is_empty() && opts.targets.is_empty());
+    let Some((manifest, _)) = dl_v2_manifest(
+        opts.dl_cfg,
+        Some(cache),
+        update_hash.as_deref(),
+        opts.toolchain,
+    )
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let manifestation = Manifestation::open(prefix.clone(), opts.toolchain.target.clone())?;
+    let changes = Changes {
+        explicit_add_components: requested_components(
+            &manifest,
+            opts.toolchain,
+            (!opts.exists).then_some(opts.profile),
+            opts.components,
+            opts.targets,
+        )?,
+        remove_components: Vec::new(),
+    };
+    let plan = manifestation.compute_changes(&manifest, &changes)?;
+    Ok(Some((manifest, plan)))
+}
+
 #[allow(clippy::too_many_arguments)]
 async fn try_update_from_dist_(
     download: DownloadCfg<'_>,
-    update_hash: Option<&Path>,
+    cache: &ManifestCache,
+    update_hash: Option<&str>,
     toolchain: &ToolchainDesc,
     profile: Option<Profile>,
     prefix: &InstallPrefix,
@@ -1013,6 +1078,7 @@ async fn try_update_from_dist_(
     (download.notify_handler)(Notification::DownloadingManifest(&toolchain_str));
     match dl_v2_manifest(
         download,
+        Some(cache),
         // Even if manifest has not changed, we must continue to install requested components.
         // So if components or targets is not empty, we skip passing `update_hash` so that
         // we essentially degenerate to `rustup component add` / `rustup target add`
@@ -1031,44 +1097,8 @@ async fn try_update_from_dist_(
                 m.get_rust_version().ok(),
             ));
 
-            let profile_components = match profile {
-                Some(profile) => m.get_profile_components(profile, &toolchain.target)?,
-                None => Vec::new(),
-            };
-
-            let mut all_components: HashSet<Component> = profile_components.into_iter().collect();
-
-            let rust_package = m.get_package("rust")?;
-            let rust_target_package = rust_package.get_target(Some(&toolchain.target.clone()))?;
-
-            for component in components {
-                let mut component =
-                    Component::new(component.to_string(), Some(toolchain.target.clone()), false);
-                if let Some(renamed) = m.rename_component(&component) {
-                    component = renamed;
-                }
-                // Look up the newly constructed/renamed component and ensure that
-                // if it's a wildcard component we note such, otherwise we end up
-                // exacerbating the problem we thought we'd fixed with #2087 and #2115
-                if let Some(c) = rust_target_package
-                    .components
-                    .iter()
-                    .find(|c| c.short_name_in_manifest() == component.short_name_in_manifest())
-                {
-                    if c.target.is_none() {
-                        component = component.wildcard();
-                    }
-                }
-                all_components.insert(component);
-            }
-
-            for &target in targets {
-                let triple = TargetTriple::new(target);
-                all_components.insert(Component::new("rust-std".to_string(), Some(triple), false));
-            }
-
-            let mut explicit_add_components: Vec<_> = all_components.into_iter().collect();
-            explicit_add_components.sort();
+            let explicit_add_components =
+                requested_components(&m, toolchain, profile, components, targets)?;
 
             let changes = Changes {
                 explicit_add_components,
@@ -1140,11 +1170,19 @@ async fn try_update_from_dist_(
         },
     };
 
+    // Nothing in a v1 release is signed, so it can't stand in for a v2
+    // manifest that must be.
+    if download.checks_signatures() {
+        bail!(DistError::UnsignedLegacyRelease(toolchain.manifest_name()));
+    }
+
     let result = manifestation
         .update_v1(
             &manifest,
             update_hash,
             download.tmp_cx,
+            download.io_profile,
+            download.io_threads,
             &download.notify_handler,
             download.process,
         )
@@ -1162,49 +1200,216 @@ async fn try_update_from_dist_(
     result
 }
 
+/// The components of the toolchain to install for `profile`, with the
+/// extra `components` and the standard libraries of `targets`.
+fn requested_components(
+    m: &ManifestV2,
+    toolchain: &ToolchainDesc,
+    profile: Option<Profile>,
+    components: &[&str],
+    targets: &[&str],
+) -> Result<Vec<Component>> {
+    let profile_components = match profile {
+        Some(profile) => m.get_profile_components(profile, &toolchain.target)?,
+        None => Vec::new(),
+    };
+
+    let mut all_components: HashSet<Component> = profile_components.into_iter().collect();
+
+    let rust_package = m.get_package("rust")?;
+    let rust_target_package = rust_package.get_target(Some(&toolchain.target.clone()))?;
+
+    for component in components {
+        let mut component =
+            Component::new(component.to_string(), Some(toolchain.target.clone()), false);
+        if let Some(renamed) = m.rename_component(&component) {
+            component = renamed;
+        }
+        // Look up the newly constructed/renamed component and ensure that
+        // if it's a wildcard component we note such, otherwise we end up
+        // exacerbating the problem we thought we'd fixed with #2087 and #2115
+        if let Some(c) = rust_target_package
+            .components
+            .iter()
+            .find(|c| c.short_name_in_manifest() == component.short_name_in_manifest())
+        {
+            if c.target.is_none() {
+                component = component.wildcard();
+            }
+        }
+        all_components.insert(component);
+    }
+
+    for &target in targets {
+        let triple = TargetTriple::new(target);
+        all_components.insert(Component::new("rust-std".to_string(), Some(triple), false));
+    }
+
+    let mut all_components: Vec<_> = all_components.into_iter().collect();
+    all_components.sort();
+    Ok(all_components)
+}
+
+/// Fetches the manifest of `toolchain`, unless the start of its hash is
+/// `update_hash`. Kept manifests are used from `cache`, if any.
 pub(crate) async fn dl_v2_manifest(
     download: DownloadCfg<'_>,
-    update_hash: Option<&Path>,
+    cache: Option<&ManifestCache>,
+    update_hash: Option<&str>,
     toolchain: &ToolchainDesc,
 ) -> Result<Option<(ManifestV2, String)>> {
-    let manifest_url = toolchain.manifest_v2_url(download.dist_root, download.process);
-    match download
-        .download_and_check(&manifest_url, update_hash, ".toml")
-        .await
+    // A manifest with a date, or one fetched recently enough, is used
+    // without asking the dist server.
+    if let Some((manifest_str, hash)) = cache
+        .map(|cache| cache.fresh_manifest(toolchain))
+        .transpose()?
+        .flatten()
     {
-        Ok(manifest_dl) => {
-            // Downloaded ok!
-            let Some((manifest_file, manifest_hash)) = manifest_dl else {
-                return Ok(None);
-            };
-            let manifest_str = utils::read_file("manifest", &manifest_file)?;
-            let manifest =
-                ManifestV2::parse(&manifest_str).with_context(|| RustupError::ParsingFile {
-                    name: "manifest",
-                    path: manifest_file.to_path_buf(),
-                })?;
-
-            Ok(Some((manifest, manifest_hash)))
+        let manifest_hash = download::update_hash_of(&hash);
+        if update_hash == Some(manifest_hash.as_str()) {
+            return Ok(None);
         }
-        Err(any) => {
-            if let Some(err @ RustupError::ChecksumFailed { .. }) =
-                any.downcast_ref::<RustupError>()
-            {
-                // Manifest checksum mismatched.
-                warn!("{err}");
+        if let Some(manifest) = parse_cached_manifest(&manifest_str) {
+            return Ok(Some((manifest, manifest_hash)));
+        }
+    }
 
-                let server = dist_root_server(download.process)?;
-                if server == DEFAULT_DIST_SERVER {
-                    info!("this is likely due to an ongoing update of the official release server, please try again later");
-                    info!("see <https://github.com/rust-lang/rustup/issues/3390> for more details");
-                } else {
-                    info!("this might indicate an issue with the third-party release server '{server}'");
-                    info!("see <https://github.com/rust-lang/rustup/issues/3885> for more details");
-                }
+    // The manifest, its hash and its signature all come from the same
+    // server, the first one that has them.
+    let retry = utils::download_retry(download.process);
+    let mut retries = 0;
+    let mut roots = download.dist_roots().peekable();
+    let mut root = roots.next().expect("there is always a dist root");
+    loop {
+        let download = download.with_dist_root(root);
+        // Boxed, so that the retries don't make the future of every update
+        // too large for the stack.
+        let result = Box::pin(dl_v2_manifest_from(download, cache, update_hash, toolchain)).await;
+        match (result, roots.peek()) {
+            // Each request already waited as long as the server asked, but a
+            // rate limit can outlast those retries, so the server is given
+            // the time it asks for again before fetching it all once more.
+            (Err(e), _) if retries < retry.attempts && download::is_rate_limited(&e) => {
+                let url = utils::parse_url(&toolchain.manifest_v2_url(root, download.process))?;
+                let delay = retry.delay(retries, &e);
+                (download.notify_handler)(Notification::Utils(
+                    utils::Notification::DownloadRetrying(&url, delay),
+                ));
+                in_runtime(tokio::time::sleep(delay)).await;
+                retries += 1;
+            }
+            (Err(e), Some(next)) if download::is_server_failure(&e) => {
+                (download.notify_handler)(Notification::TryingNextDistServer(
+                    &toolchain.manifest_v2_url(root, download.process),
+                    &toolchain.manifest_v2_url(next, download.process),
+                ));
+                root = roots.next().unwrap();
+                retries = 0;
+            }
+            (result, _) => return result,
+        }
+    }
+}
+
+async fn dl_v2_manifest_from(
+    download: DownloadCfg<'_>,
+    cache: Option<&ManifestCache>,
+    update_hash: Option<&str>,
+    toolchain: &ToolchainDesc,
+) -> Result<Option<(ManifestV2, String)>> {
+    let manifest_url = toolchain.manifest_v2_url(download.dist_root, download.process);
+    // Phase spans take the component span that is current when they are
+    // made as their parent.
+    let manifest_span = timings::component("manifest");
+    let phase = |phase| manifest_span.in_scope(|| timings::phase(phase));
+    let result: Result<_> = async {
+        let hash = download
+            .download_hash(&manifest_url)
+            .instrument(phase(Phase::Download))
+            .await?;
+        let manifest_hash = download::update_hash_of(&hash);
+        if update_hash == Some(manifest_hash.as_str()) {
+            return Ok(None);
+        }
+
+        let cached = cache
+            .map(|cache| cache.manifest_with_hash(toolchain, &hash))
+            .transpose()?
+            .flatten()
+            .and_then(|manifest_str| {
+                parse_cached_manifest(&manifest_str).map(|manifest| (manifest_str, manifest))
+            });
+        let (manifest_file, manifest_str, manifest) = match cached {
+            // The signature the dist server has now is still checked.
+            Some((manifest_str, manifest)) => {
+                let manifest_file = download.tmp_cx.new_file()?;
+                utils::write_file("manifest", &manifest_file, &manifest_str)?;
+                (manifest_file, manifest_str, Some(manifest))
+            }
+            None => {
+                let manifest_file = download
+                    .download_with_hash(&manifest_url, hash.clone(), ".toml")
+                    .instrument(phase(Phase::Download))
+                    .await?;
+                let manifest_str = utils::read_file("manifest", &manifest_file)?;
+                (manifest_file, manifest_str, None)
+            }
+        };
+        trust::verify_manifest(&download, &manifest_url, &manifest_file)
+            .instrument(phase(Phase::Verify))
+            .await
+            .inspect_err(|_| {
+                info!("to install without checking the signature, pass `--no-verify`");
+            })?;
+        if let Some(manifest) = manifest {
+            return Ok(Some((manifest, manifest_hash)));
+        }
+
+        let manifest =
+            ManifestV2::parse(&manifest_str).with_context(|| RustupError::ParsingFile {
+                name: "manifest",
+                path: manifest_file.to_path_buf(),
+            })?;
+        if let Some(cache) = cache {
+            // Failing to keep the manifest only means downloading it again.
+            if let Err(e) = cache.add_manifest(toolchain, &hash, &manifest_str) {
+                debug!("could not keep the manifest of '{toolchain}': {e:#}");
             }
-            Err(any)
         }
+
+        Ok(Some((manifest, manifest_hash)))
     }
+    .await;
+
+    if let Err(any) = &result {
+        if let Some(err @ RustupError::ChecksumFailed { .. }) = any.downcast_ref::<RustupError>() {
+            // Manifest checksum mismatched.
+            warn!("{err}");
+
+            let server = download
+                .dist_root
+                .strip_suffix("/dist")
+                .unwrap_or(download.dist_root);
+            if server == DEFAULT_DIST_SERVER {
+                info!("this is likely due to an ongoing update of the official release server, please try again later");
+                info!("see <https://github.com/rust-lang/rustup/issues/3390> for more details");
+            } else {
+                info!(
+                    "this might indicate an issue with the third-party release server '{server}'"
+                );
+                info!("see <https://github.com/rust-lang/rustup/issues/3885> for more details");
+            }
+        }
+    }
+    result
+}
+
+/// A kept manifest, unless it can't be parsed, in which case it is
+/// downloaded again.
+fn parse_cached_manifest(manifest_str: &str) -> Option<ManifestV2> {
+    ManifestV2::parse(manifest_str)
+        .inspect_err(|e| debug!("could not parse a kept manifest: {e:#}"))
+        .ok()
 }
 
 async fn dl_v1_manifest(
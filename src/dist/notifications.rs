@@ -1,27 +1,28 @@
 use crate::dist::manifest::Component;
 use crate::dist::temp;
 use crate::dist::{TargetTriple, ToolchainDesc};
-use crate::utils::notify::NotificationLevel;
+use crate::utils::notify::{serialize, NotificationLevel};
+use crate::utils::units::format_bytes;
+use serde::Serialize;
 use std::fmt::{self, Display};
 use std::path::Path;
 
 use super::manifest::Manifest;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "data", rename_all = "kebab-case")]
 pub enum Notification<'a> {
-    Utils(crate::utils::Notification<'a>),
-    Temp(temp::Notification<'a>),
-
-    Extracting(&'a Path, &'a Path),
+    Extracting(
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+    ),
     ComponentAlreadyInstalled(&'a str),
-    CantReadUpdateHash(&'a Path),
-    NoUpdateHash(&'a Path),
     ChecksumValid(&'a str),
     FileAlreadyDownloaded,
     CachedFileChecksumFailed,
     RollingBack,
     ExtensionNotInstalled(&'a str),
-    NonFatalError(&'a anyhow::Error),
+    NonFatalError(#[serde(serialize_with = "serialize::error")] &'a anyhow::Error),
     MissingInstalledComponent(&'a str),
     DownloadingComponent(&'a str, &'a TargetTriple, Option<&'a TargetTriple>),
     InstallingComponent(&'a str, &'a TargetTriple, Option<&'a TargetTriple>),
@@ -30,12 +31,46 @@ pub enum Notification<'a> {
     DownloadingManifest(&'a str),
     DownloadedManifest(&'a str, Option<&'a str>),
     DownloadingLegacyManifest,
-    SkippingNightlyMissingComponent(&'a ToolchainDesc, &'a Manifest, &'a [Component]),
+    SkippingNightlyMissingComponent(
+        &'a ToolchainDesc,
+        #[serde(skip)] &'a Manifest,
+        &'a [Component],
+    ),
     ForcingUnavailableComponent(&'a str),
     ComponentUnavailable(&'a str, Option<&'a TargetTriple>),
-    StrayHash(&'a Path),
+    StrayHash(&'a str),
     SignatureInvalid(&'a str),
     RetryingDownload(&'a str),
+    /// A file could not be fetched from any cache, and is downloaded from its
+    /// URL instead.
+    CacheMiss(&'a str),
+    /// A cache peer could not be reached or failed, and is not tried again
+    /// for the rest of the update.
+    CachePeerFailed(&'a str),
+    /// A file could not be downloaded from its URL, and the same file is
+    /// tried on the next dist server.
+    TryingNextDistServer(&'a str, &'a str),
+    /// A component's archive could not be downloaded from any dist server,
+    /// and its archive in the next format is tried.
+    TryingNextArchive(&'a str, &'a str),
+    /// The download size and, if known, the installed size of an update.
+    InstallSize(u64, Option<u64>),
+    /// All components of an update have been installed.
+    UpdateFinished,
+    /// Archives left by an earlier attempt are reused: of the downloads of
+    /// an update, how many are complete and how many partial.
+    ResumingDownloads(usize, usize, usize),
+    /// An interrupted install is resumed, with this many components
+    /// already installed.
+    ResumingInstall(usize),
+    /// An interrupted install can't be resumed, and starts over.
+    RestartingInstall,
+
+    // Serialized as the notifications they wrap.
+    #[serde(untagged)]
+    Utils(crate::utils::Notification<'a>),
+    #[serde(untagged)]
+    Temp(temp::Notification<'a>),
 }
 
 impl<'a> From<crate::utils::Notification<'a>> for Notification<'a> {
@@ -57,9 +92,9 @@ impl Notification<'_> {
             Temp(n) => n.level(),
             Utils(n) => n.level(),
             ChecksumValid(_)
-            | NoUpdateHash(_)
             | FileAlreadyDownloaded
-            | DownloadingLegacyManifest => NotificationLevel::Debug,
+            | DownloadingLegacyManifest
+            | UpdateFinished => NotificationLevel::Debug,
             Extracting(_, _)
             | DownloadingComponent(_, _, _)
             | InstallingComponent(_, _, _)
@@ -70,14 +105,21 @@ impl Notification<'_> {
             | DownloadingManifest(_)
             | SkippingNightlyMissingComponent(_, _, _)
             | RetryingDownload(_)
+            | CacheMiss(_)
+            | TryingNextDistServer(_, _)
+            | TryingNextArchive(_, _)
+            | InstallSize(_, _)
+            | ResumingDownloads(_, _, _)
+            | ResumingInstall(_)
             | DownloadedManifest(_, _) => NotificationLevel::Info,
-            CantReadUpdateHash(_)
-            | ExtensionNotInstalled(_)
+            ExtensionNotInstalled(_)
             | MissingInstalledComponent(_)
             | CachedFileChecksumFailed
             | ComponentUnavailable(_, _)
             | ForcingUnavailableComponent(_)
-            | StrayHash(_) => NotificationLevel::Warn,
+            | StrayHash(_)
+            | CachePeerFailed(_)
+            | RestartingInstall => NotificationLevel::Warn,
             NonFatalError(_) => NotificationLevel::Error,
             SignatureInvalid(_) => NotificationLevel::Warn,
         }
@@ -92,12 +134,6 @@ impl Display for Notification<'_> {
             Utils(n) => n.fmt(f),
             Extracting(_, _) => write!(f, "extracting..."),
             ComponentAlreadyInstalled(c) => write!(f, "component {c} is up to date"),
-            CantReadUpdateHash(path) => write!(
-                f,
-                "can't read update hash file: '{}', can't skip update...",
-                path.display()
-            ),
-            NoUpdateHash(path) => write!(f, "no update hash at: '{}'", path.display()),
             ChecksumValid(_) => write!(f, "checksum passed"),
             FileAlreadyDownloaded => write!(f, "reusing previously downloaded file"),
             CachedFileChecksumFailed => write!(f, "bad checksum for cached download"),
@@ -107,6 +143,23 @@ impl Display for Notification<'_> {
             MissingInstalledComponent(c) => {
                 write!(f, "during uninstall component {c} was not found")
             }
+            UpdateFinished => write!(f, "update finished"),
+            ResumingDownloads(total, downloaded, partial) => write!(
+                f,
+                "resuming downloads: {downloaded} of {total} components already downloaded, {partial} partly downloaded"
+            ),
+            ResumingInstall(1) => write!(f, "resuming interrupted install, 1 component installed"),
+            ResumingInstall(n) => {
+                write!(f, "resuming interrupted install, {n} components installed")
+            }
+            RestartingInstall => write!(f, "can't resume interrupted install, starting over"),
+            InstallSize(download, installed) => {
+                write!(f, "this will download {}", format_bytes(*download))?;
+                match installed {
+                    Some(installed) => write!(f, ", use {} on disk", format_bytes(*installed)),
+                    None => Ok(()),
+                }
+            }
             DownloadingComponent(c, h, t) => {
                 if Some(h) == t.as_ref() || t.is_none() {
                     write!(f, "downloading component '{c}'")
@@ -155,10 +208,9 @@ impl Display for Notification<'_> {
                     write!(f, "component '{pkg}' is not available")
                 }
             }
-            StrayHash(path) => write!(
+            StrayHash(toolchain) => write!(
                 f,
-                "removing stray hash found at '{}' in order to continue",
-                path.display()
+                "removing stray hash for '{toolchain}' in order to continue"
             ),
             SkippingNightlyMissingComponent(toolchain, manifest, components) => write!(
                 f,
@@ -181,6 +233,20 @@ impl Display for Notification<'_> {
             }
             SignatureInvalid(url) => write!(f, "Signature verification failed for '{url}'"),
             RetryingDownload(url) => write!(f, "retrying download for '{url}'"),
+            CacheMiss(url) => write!(
+                f,
+                "'{url}' is not cached, downloading it from the dist server"
+            ),
+            CachePeerFailed(peer) => write!(
+                f,
+                "cache peer '{peer}' failed, and is skipped from now on"
+            ),
+            TryingNextDistServer(url, next) => {
+                write!(f, "could not download '{url}', trying '{next}'")
+            }
+            TryingNextArchive(url, next) => {
+                write!(f, "could not download '{url}', trying '{next}' instead")
+            }
         }
     }
 }
@@ -0,0 +1,210 @@
+//! A content-addressed store of the files of installed toolchains, kept in
+//! `RUSTUP_HOME/store` when `rustup set dedup true` is set.
+//!
+//! Each file an update installs is hashed, and if the store already has a
+//! file with that content, the installed one is replaced by a hard link to
+//! it; otherwise the installed file becomes the store's copy. Toolchains
+//! installed a day apart share most of their docs and sources, so keeping
+//! several nightlies then costs little more disk than keeping one.
+//!
+//! Nothing rustup installs is modified in place afterwards, which a hard
+//! link would make visible in every toolchain sharing the file. Where a
+//! hard link can't be made, e.g. because the store is on another file
+//! system, a reflink is tried instead, and failing that the file is left
+//! as it is.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+#[derive(Clone, Debug)]
+pub(crate) struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Links each file below `path` to the store's copy of its content,
+    /// returning how many bytes that saved.
+    pub(crate) fn link_all(&self, path: &Path) -> Result<u64> {
+        let meta = match fs::symlink_metadata(path) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => {
+                return Err(e).with_context(|| format!("could not read '{}'", path.display()))
+            }
+        };
+        if meta.is_file() {
+            return self.link(path, &meta);
+        } else if !meta.is_dir() {
+            return Ok(0);
+        }
+
+        let mut saved = 0;
+        for entry in fs::read_dir(path)? {
+            saved += self.link_all(&entry?.path())?;
+        }
+        Ok(saved)
+    }
+
+    fn link(&self, path: &Path, meta: &fs::Metadata) -> Result<u64> {
+        if meta.len() == 0 {
+            return Ok(0);
+        }
+        let stored = self.dir.join(key(path, meta)?);
+        let stored_meta = match fs::metadata(&stored) {
+            Ok(stored_meta) => stored_meta,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                // The first copy of this content becomes the store's.
+                fs::create_dir_all(stored.parent().unwrap())?;
+                if let Err(e) =
+                    fs::hard_link(path, &stored).or_else(|_| reflink_copy::reflink(path, &stored))
+                {
+                    debug!("could not add '{}' to the store: {e}", path.display());
+                }
+                return Ok(0);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // A stored file that has since changed size was modified through
+        // one of its links, and no longer matches its name.
+        if stored_meta.len() != meta.len() || same_file::is_same_file(path, &stored)? {
+            return Ok(0);
+        }
+
+        let tmp = path.with_extension("rustup-store");
+        let linked = fs::hard_link(&stored, &tmp)
+            .or_else(|_| reflink_copy::reflink(&stored, &tmp))
+            .and_then(|()| fs::rename(&tmp, path));
+        match linked {
+            Ok(()) => Ok(meta.len()),
+            Err(e) => {
+                debug!("could not link '{}' to the store: {e}", path.display());
+                let _ = fs::remove_file(&tmp);
+                Ok(0)
+            }
+        }
+    }
+
+    /// Removes the files no toolchain links to any more.
+    ///
+    /// Only Unix tells how many links a file has, so elsewhere the store
+    /// keeps growing until it is removed.
+    pub(crate) fn prune(&self) -> Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let dirs = match fs::read_dir(&self.dir) {
+                Ok(dirs) => dirs,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            for dir in dirs {
+                let dir = dir?.path();
+                for file in fs::read_dir(&dir)? {
+                    let file = file?;
+                    if file.metadata()?.nlink() == 1 {
+                        debug!("removing '{}' from the store", file.path().display());
+                        fs::remove_file(file.path())?;
+                    }
+                }
+                // Only succeeds once the directory is empty.
+                let _ = fs::remove_dir(&dir);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The name of the stored copy of `path`, from its content and, since a hard
+/// link shares them, whether it is executable.
+fn key(path: &Path, meta: &fs::Metadata) -> Result<PathBuf> {
+    let mut hasher = Sha256::new();
+    let mut file =
+        File::open(path).with_context(|| format!("could not read '{}'", path.display()))?;
+    io::copy(&mut file, &mut hasher)?;
+    let hash = format!("{:x}", hasher.finalize());
+
+    #[cfg(unix)]
+    let executable = {
+        use std::os::unix::fs::PermissionsExt;
+        meta.permissions().mode() & 0o111 != 0
+    };
+    #[cfg(not(unix))]
+    let executable = {
+        let _ = meta;
+        false
+    };
+
+    let dir = PathBuf::from(&hash[..2]);
+    let name = match executable {
+        true => format!("{hash}-x"),
+        false => hash,
+    };
+    Ok(dir.join(name))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use super::*;
+
+    #[test]
+    fn identical_files_are_linked_and_pruned() {
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let store = FileStore::new(dir.path().join("store"));
+        for toolchain in ["a", "b"] {
+            let docs = dir.path().join(toolchain).join("share/doc");
+            fs::create_dir_all(&docs).unwrap();
+            fs::write(docs.join("README.md"), "the same in both").unwrap();
+            fs::write(docs.join("VERSION"), toolchain).unwrap();
+        }
+
+        assert_eq!(store.link_all(&dir.path().join("a")).unwrap(), 0);
+        assert_eq!(store.link_all(&dir.path().join("b")).unwrap(), 16);
+        // Linking again finds nothing new to share.
+        assert_eq!(store.link_all(&dir.path().join("b")).unwrap(), 0);
+
+        let a = dir.path().join("a/share/doc/README.md");
+        assert!(same_file::is_same_file(&a, dir.path().join("b/share/doc/README.md")).unwrap());
+        assert_eq!(fs::metadata(&a).unwrap().nlink(), 3);
+        let b_version = dir.path().join("b/share/doc/VERSION");
+        assert_eq!(fs::read_to_string(&b_version).unwrap(), "b");
+        assert_eq!(fs::metadata(&b_version).unwrap().nlink(), 2);
+
+        fs::remove_dir_all(dir.path().join("a")).unwrap();
+        store.prune().unwrap();
+        assert_eq!(fs::metadata(&b_version).unwrap().nlink(), 2);
+        fs::remove_dir_all(dir.path().join("b")).unwrap();
+        store.prune().unwrap();
+        assert_eq!(fs::read_dir(dir.path().join("store")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn executables_are_stored_apart() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let store = FileStore::new(dir.path().join("store"));
+        let script = dir.path().join("script");
+        let text = dir.path().join("text");
+        fs::write(&script, "#!/bin/sh").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(&text, "#!/bin/sh").unwrap();
+
+        store.link_all(&script).unwrap();
+        assert_eq!(store.link_all(&text).unwrap(), 0);
+        assert!(!same_file::is_same_file(&script, &text).unwrap());
+        let mode = fs::metadata(&script).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+}
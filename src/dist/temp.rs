@@ -5,9 +5,11 @@ use std::ops;
 use std::path::{Path, PathBuf};
 
 pub(crate) use anyhow::{Context as _, Result};
+use serde::Serialize;
 use thiserror::Error as ThisError;
 
-use crate::utils::{self, notify::NotificationLevel, raw};
+use crate::utils::notify::{serialize, NotificationLevel};
+use crate::utils::{self, raw};
 
 #[derive(Debug, ThisError)]
 pub(crate) enum CreatingError {
@@ -68,13 +70,25 @@ impl Drop for File<'_> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "data", rename_all = "kebab-case")]
 pub enum Notification<'a> {
-    CreatingRoot(&'a Path),
-    CreatingFile(&'a Path),
-    CreatingDirectory(&'a Path),
-    FileDeletion(&'a Path, io::Result<()>),
-    DirectoryDeletion(&'a Path, io::Result<()>),
+    #[serde(rename = "creating-temp-root")]
+    CreatingRoot(#[serde(serialize_with = "serialize::path")] &'a Path),
+    #[serde(rename = "creating-temp-file")]
+    CreatingFile(#[serde(serialize_with = "serialize::path")] &'a Path),
+    #[serde(rename = "creating-temp-directory")]
+    CreatingDirectory(#[serde(serialize_with = "serialize::path")] &'a Path),
+    #[serde(rename = "temp-file-deletion")]
+    FileDeletion(
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+        #[serde(serialize_with = "serialize::io_error")] io::Result<()>,
+    ),
+    #[serde(rename = "temp-directory-deletion")]
+    DirectoryDeletion(
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+        #[serde(serialize_with = "serialize::io_error")] io::Result<()>,
+    ),
 }
 
 impl Notification<'_> {
@@ -0,0 +1,390 @@
+//! Per-component timings of an install, for `rustup --timings`.
+//!
+//! Installing a toolchain records a span for each phase of each component:
+//! downloading its archive, checking the hash of one downloaded before, and
+//! unpacking it into place. The manifest gets spans of its own, for its
+//! download and for checking its signature. With `--timings`, the
+//! [`Timings`] layer collects how long each of those spans was open, and at
+//! the end a report is written as JSON and as HTML, so that a slow mirror or
+//! a slow disk can be told apart in a report from a user.
+
+use std::fmt;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{trace_span, Span, Subscriber};
+use tracing_subscriber::layer::Context as LayerContext;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// The target of the spans that timings are recorded for.
+pub(crate) const TARGET: &str = "rustup::timings";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Phase {
+    Download,
+    Verify,
+    Unpack,
+}
+
+impl Phase {
+    const ALL: [Self; 3] = [Self::Download, Self::Verify, Self::Unpack];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Download => "download",
+            Self::Verify => "verify",
+            Self::Unpack => "unpack",
+        }
+    }
+
+    fn from_span_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|phase| phase.as_str() == name)
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            Self::Download => "#6b9bd1",
+            Self::Verify => "#e0b04c",
+            Self::Unpack => "#79b97c",
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The span that the phases of the component `name` are nested in.
+pub(crate) fn component(name: &str) -> Span {
+    trace_span!(target: TARGET, "component", name)
+}
+
+/// The span of one phase of the component whose span is current.
+pub(crate) fn phase(phase: Phase) -> Span {
+    match phase {
+        Phase::Download => trace_span!(target: TARGET, "download"),
+        Phase::Verify => trace_span!(target: TARGET, "verify"),
+        Phase::Unpack => trace_span!(target: TARGET, "unpack"),
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Timing {
+    component: String,
+    phase: Phase,
+    /// When the phase started, since recording did.
+    start: Duration,
+    duration: Duration,
+}
+
+struct Recording {
+    started: Instant,
+    timings: Vec<Timing>,
+}
+
+/// A [`Layer`] that records how long the phases of each component took,
+/// once [`Timings::start`] has been called.
+#[derive(Default)]
+pub(crate) struct Timings {
+    recording: Mutex<Option<Recording>>,
+}
+
+struct ComponentName(String);
+
+struct PhaseStarted(Instant);
+
+impl Timings {
+    /// Starts recording with the [`Timings`] layer of the current subscriber,
+    /// if it has one.
+    pub(crate) fn start() {
+        tracing::dispatcher::get_default(|dispatch| {
+            if let Some(timings) = dispatch.downcast_ref::<Self>() {
+                *timings.recording.lock().unwrap() = Some(Recording {
+                    started: Instant::now(),
+                    timings: Vec::new(),
+                });
+            }
+        })
+    }
+
+    /// Stops recording and writes what was recorded to `dir`, returning the
+    /// path of the HTML report.
+    pub(crate) fn finish(dir: &Path) -> Result<Option<PathBuf>> {
+        let recording = tracing::dispatcher::get_default(|dispatch| {
+            let timings = dispatch.downcast_ref::<Self>()?;
+            let recording = timings.recording.lock().unwrap().take()?;
+            Some((recording.started.elapsed(), recording.timings))
+        });
+        let Some((total, timings)) = recording else {
+            return Ok(None);
+        };
+
+        fs::create_dir_all(dir).with_context(|| format!("could not create '{}'", dir.display()))?;
+        let stamp = DateTime::<Utc>::from(SystemTime::now()).format("%Y%m%dT%H%M%S%3fZ");
+        let json = dir.join(format!("rustup-timing-{stamp}.json"));
+        let html = json.with_extension("html");
+        fs::write(&json, report_json(total, &timings))
+            .with_context(|| format!("could not write '{}'", json.display()))?;
+        fs::write(&html, report_html(total, &timings))
+            .with_context(|| format!("could not write '{}'", html.display()))?;
+        Ok(Some(html))
+    }
+
+    fn is_recording(&self) -> bool {
+        self.recording.lock().unwrap().is_some()
+    }
+}
+
+impl<S> Layer<S> for Timings
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: LayerContext<'_, S>) {
+        if !self.is_recording() {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        if attrs.metadata().name() == "component" {
+            let mut name = ComponentName(String::new());
+            attrs.record(&mut name);
+            span.extensions_mut().insert(name);
+        } else if Phase::from_span_name(attrs.metadata().name()).is_some() {
+            span.extensions_mut().insert(PhaseStarted(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(phase) = Phase::from_span_name(span.name()) else {
+            return;
+        };
+        let Some(started) = span.extensions().get::<PhaseStarted>().map(|s| s.0) else {
+            return;
+        };
+        let component = span
+            .scope()
+            .skip(1)
+            .find_map(|parent| {
+                let extensions = parent.extensions();
+                extensions.get::<ComponentName>().map(|name| name.0.clone())
+            })
+            .unwrap_or_default();
+
+        let mut recording = self.recording.lock().unwrap();
+        if let Some(recording) = &mut *recording {
+            recording.timings.push(Timing {
+                component,
+                phase,
+                start: started.saturating_duration_since(recording.started),
+                duration: started.elapsed(),
+            });
+        }
+    }
+}
+
+impl Visit for ComponentName {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = value.to_owned();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "name" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+/// The components in the order they were first seen, with the total time
+/// spent in each phase.
+fn by_component(timings: &[Timing]) -> Vec<(&str, [Duration; 3])> {
+    let mut components: Vec<(&str, [Duration; 3])> = Vec::new();
+    for timing in timings {
+        let index = match components
+            .iter()
+            .position(|(name, _)| *name == timing.component)
+        {
+            Some(index) => index,
+            None => {
+                components.push((&timing.component, [Duration::ZERO; 3]));
+                components.len() - 1
+            }
+        };
+        let phase = Phase::ALL.iter().position(|p| *p == timing.phase).unwrap();
+        components[index].1[phase] += timing.duration;
+    }
+    components
+}
+
+fn report_json(total: Duration, timings: &[Timing]) -> String {
+    let mut sorted = timings.to_vec();
+    sorted.sort_by_key(|timing| timing.start);
+    let phases = sorted
+        .iter()
+        .map(|timing| {
+            json!({
+                "component": timing.component,
+                "phase": timing.phase.as_str(),
+                "start": timing.start.as_secs_f64(),
+                "duration": timing.duration.as_secs_f64(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let report = json!({
+        "version": 1,
+        "rustup_version": env!("CARGO_PKG_VERSION"),
+        "total": total.as_secs_f64(),
+        "phases": phases,
+    });
+    serde_json::to_string_pretty(&report).unwrap()
+}
+
+fn report_html(total: Duration, timings: &[Timing]) -> String {
+    let mut sorted = timings.to_vec();
+    sorted.sort_by_key(|timing| timing.start);
+    let scale = total.as_secs_f64().max(f64::EPSILON);
+
+    let mut html = String::new();
+    _ = write!(
+        html,
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>rustup timings</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; margin-bottom: 2em; }}
+th, td {{ padding: 0.2em 0.8em; text-align: left; }}
+td.num {{ text-align: right; font-variant-numeric: tabular-nums; }}
+tr:nth-child(even) {{ background: #f3f3f3; }}
+.lane {{ position: relative; width: 40em; height: 1em; }}
+.bar {{ position: absolute; height: 100%; }}
+</style>
+</head>
+<body>
+<h1>rustup timings</h1>
+<p>rustup {version}, {total:.2}s in total.</p>
+<h2>Components</h2>
+<table>
+<tr><th>Component</th>"#,
+        version = env!("CARGO_PKG_VERSION"),
+        total = total.as_secs_f64(),
+    );
+    for phase in Phase::ALL {
+        _ = write!(html, "<th>{phase}</th>");
+    }
+    html.push_str("<th>total</th></tr>\n");
+    for (component, durations) in by_component(timings) {
+        _ = write!(html, "<tr><td>{}</td>", escape(component));
+        for duration in durations {
+            _ = write!(
+                html,
+                r#"<td class="num">{:.2}s</td>"#,
+                duration.as_secs_f64()
+            );
+        }
+        let sum = durations.iter().sum::<Duration>();
+        _ = writeln!(
+            html,
+            r#"<td class="num">{:.2}s</td></tr>"#,
+            sum.as_secs_f64()
+        );
+    }
+    html.push_str("</table>\n<h2>Timeline</h2>\n<table>\n");
+    for timing in &sorted {
+        _ = writeln!(
+            html,
+            r#"<tr><td>{component}</td><td>{phase}</td><td class="num">{duration:.2}s</td><td><div class="lane"><div class="bar" style="left: {left:.2}%; width: {width:.2}%; background: {color};"></div></div></td></tr>"#,
+            component = escape(&timing.component),
+            phase = timing.phase,
+            duration = timing.duration.as_secs_f64(),
+            left = timing.start.as_secs_f64() / scale * 100.0,
+            width = (timing.duration.as_secs_f64() / scale * 100.0).max(0.2),
+            color = timing.phase.color(),
+        );
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_phases_by_component() {
+        let timings = Timings::default();
+        let subscriber = tracing_subscriber::layer::SubscriberExt::with(
+            tracing_subscriber::Registry::default(),
+            timings,
+        );
+        let _guard = tracing::subscriber::set_default(subscriber);
+        Timings::start();
+
+        for name in ["rustc", "cargo"] {
+            let _component = component(name).entered();
+            for p in [Phase::Download, Phase::Unpack] {
+                let _phase = phase(p).entered();
+            }
+        }
+        phase(Phase::Verify).in_scope(|| {});
+
+        let recording = tracing::dispatcher::get_default(|dispatch| {
+            let timings = dispatch.downcast_ref::<Timings>().unwrap();
+            let recording = timings.recording.lock().unwrap();
+            recording.as_ref().unwrap().timings.clone()
+        });
+        let recorded = recording
+            .iter()
+            .map(|t| (t.component.as_str(), t.phase))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            recorded,
+            [
+                ("rustc", Phase::Download),
+                ("rustc", Phase::Unpack),
+                ("cargo", Phase::Download),
+                ("cargo", Phase::Unpack),
+                ("", Phase::Verify),
+            ]
+        );
+        assert_eq!(
+            by_component(&recording)
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>(),
+            ["rustc", "cargo", ""]
+        );
+
+        let json = serde_json::from_str::<serde_json::Value>(&report_json(
+            Duration::from_secs(1),
+            &recording,
+        ))
+        .unwrap();
+        assert_eq!(json["phases"].as_array().unwrap().len(), 5);
+        assert!(report_html(Duration::from_secs(1), &recording).contains("<td>cargo</td>"));
+    }
+}
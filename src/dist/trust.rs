@@ -0,0 +1,334 @@
+//! Signatures on the manifests of dist servers with trusted keys.
+//!
+//! A manifest lists the hash of every component archive, so a manifest with
+//! a good signature vouches for the components downloaded through it too.
+//! Signatures are checked by the usual tools rather than by rustup itself:
+//! `gpg` for OpenPGP keys, against `<manifest>.asc`, and `cosign` for
+//! Sigstore identities, against the bundle in `<manifest>.sigstore.json`.
+//!
+//! Manifests from the official dist server are checked against the key the
+//! Rust project signs its releases with, unless other keys are trusted for
+//! it. That key is built into rustup, so that the server can't swap it for
+//! another. Where gpg isn't installed, rustup warns that it can't check
+//! signatures with it, and fails when other keys were asked for instead.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::dist::download::DownloadCfg;
+use crate::dist::temp;
+use crate::errors::RustupError;
+use crate::utils;
+
+/// A key that manifests from a dist server must be signed with.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum TrustedKey {
+    /// An ASCII-armored OpenPGP public key.
+    Gpg { fingerprint: String, key: String },
+    /// The identity in the certificate of a keyless Sigstore signature,
+    /// and the OIDC issuer that vouched for it.
+    Sigstore { identity: String, issuer: String },
+}
+
+/// The fingerprint of the key the Rust project signs its releases with.
+const RUST_KEY_FINGERPRINT: &str = "108F66205EAEB0AAA8DD5E1C85AB96E6FA1BE5FE";
+/// That key, as published at https://static.rust-lang.org/rust-key.gpg.ascii.
+const RUST_KEY: &str = include_str!("rust-key.gpg.ascii");
+
+impl TrustedKey {
+    /// The key the Rust project signs its releases with.
+    pub(crate) fn rust_release_key() -> Self {
+        Self::Gpg {
+            fingerprint: RUST_KEY_FINGERPRINT.to_owned(),
+            key: RUST_KEY.to_owned(),
+        }
+    }
+
+    /// Reads the OpenPGP public key in the file `path`, as `RUSTUP_PGP_KEY`
+    /// names.
+    pub(crate) fn gpg_file(path: &Path, tmp_cx: &temp::Context) -> Result<Self> {
+        let key = utils::read_file("key", path)?;
+        Self::gpg(key, tmp_cx)
+            .with_context(|| format!("could not read the OpenPGP key in '{}'", path.display()))
+    }
+
+    /// Reads the fingerprint of an ASCII-armored OpenPGP public key.
+    pub(crate) fn gpg(key: String, tmp_cx: &temp::Context) -> Result<Self> {
+        let dir = tmp_cx.new_directory()?;
+        let file = dir.join("key.asc");
+        fs::write(&file, &key)?;
+        let output = Command::new("gpg")
+            .args(["--batch", "--with-colons", "--import-options", "show-only"])
+            .arg("--homedir")
+            .arg(&*dir)
+            .arg("--import")
+            .arg(&file)
+            .stderr(Stdio::null())
+            .output()
+            .context("could not run gpg, which is needed for OpenPGP keys")?;
+
+        // The first `fpr` record after each `pub` record is the fingerprint
+        // of that primary key.
+        let mut fingerprints = Vec::new();
+        let mut want_fingerprint = false;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let mut fields = line.split(':');
+            match fields.next() {
+                Some("pub") => want_fingerprint = true,
+                Some("fpr") if want_fingerprint => {
+                    fingerprints.extend(fields.nth(8).map(str::to_owned));
+                    want_fingerprint = false;
+                }
+                _ => {}
+            }
+        }
+        match &fingerprints[..] {
+            [fingerprint] => Ok(Self::Gpg {
+                fingerprint: fingerprint.clone(),
+                key,
+            }),
+            [] => bail!("no OpenPGP public key found"),
+            _ => bail!("add one OpenPGP public key at a time"),
+        }
+    }
+
+    /// What `rustup key remove` takes to name this key.
+    pub(crate) fn id(&self) -> &str {
+        match self {
+            Self::Gpg { fingerprint, .. } => fingerprint,
+            Self::Sigstore { identity, .. } => identity,
+        }
+    }
+}
+
+impl fmt::Display for TrustedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gpg { fingerprint, .. } => write!(f, "gpg {fingerprint}"),
+            Self::Sigstore { identity, issuer } => {
+                write!(f, "sigstore {identity} (issued by {issuer})")
+            }
+        }
+    }
+}
+
+/// Checks that the manifest downloaded from `url` to `manifest` is signed
+/// by one of the keys trusted for the dist server, if there are any.
+pub(crate) async fn verify_manifest(
+    download: &DownloadCfg<'_>,
+    url: &str,
+    manifest: &Path,
+) -> Result<()> {
+    let pgp_key;
+    let trusted_keys = match download.pgp_key_file {
+        Some(path) => {
+            pgp_key = [TrustedKey::gpg_file(path, download.tmp_cx)?];
+            &pgp_key[..]
+        }
+        None => download.trusted_keys,
+    };
+    if trusted_keys.is_empty() {
+        return Ok(());
+    }
+    // Only the default key is let off, as those who trust keys of their own
+    // need them checked.
+    if trusted_keys == [TrustedKey::rust_release_key()] && !gpg_installed() {
+        warn!("gpg isn't installed, so the signature of '{url}' can't be checked");
+        return Ok(());
+    }
+
+    let gpg_keys = trusted_keys
+        .iter()
+        .filter_map(|key| match key {
+            TrustedKey::Gpg { fingerprint, key } => Some((fingerprint.as_str(), key.as_str())),
+            TrustedKey::Sigstore { .. } => None,
+        })
+        .collect::<Vec<_>>();
+    let identities = trusted_keys
+        .iter()
+        .filter_map(|key| match key {
+            TrustedKey::Sigstore { identity, issuer } => Some((identity.as_str(), issuer.as_str())),
+            TrustedKey::Gpg { .. } => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut reasons = Vec::new();
+    if !gpg_keys.is_empty() {
+        match verify_gpg(download, url, manifest, &gpg_keys).await {
+            Ok(fingerprint) => {
+                debug!("manifest '{url}' is signed by {fingerprint}");
+                return Ok(());
+            }
+            Err(e) => reasons.push(format!("{e:#}")),
+        }
+    }
+    if !identities.is_empty() {
+        match verify_sigstore(download, url, manifest, &identities).await {
+            Ok(identity) => {
+                debug!("manifest '{url}' is signed by {identity}");
+                return Ok(());
+            }
+            Err(e) => reasons.push(format!("{e:#}")),
+        }
+    }
+
+    Err(RustupError::SignatureNotVerified {
+        url: url.to_owned(),
+        reason: reasons.join("; "),
+    }
+    .into())
+}
+
+/// Returns the fingerprint of the trusted key that signed `manifest`.
+async fn verify_gpg(
+    download: &DownloadCfg<'_>,
+    url: &str,
+    manifest: &Path,
+    keys: &[(&str, &str)],
+) -> Result<String> {
+    let home = download.tmp_cx.new_directory()?;
+    let signature = home.join("manifest.asc");
+    fetch(download, "signature", &format!("{url}.asc"), &signature).await?;
+
+    let keyring = home.join("keys.asc");
+    let armored = keys.iter().map(|(_, key)| *key).collect::<Vec<_>>();
+    fs::write(&keyring, armored.join("\n"))?;
+    let gpg = |args: &[&std::ffi::OsStr]| {
+        Command::new("gpg")
+            // Only public keys are used, so no agent is left running.
+            .args(["--batch", "--no-tty", "--quiet", "--no-autostart"])
+            .arg("--homedir")
+            .arg(&*home)
+            .args(args)
+            .stderr(Stdio::null())
+            .output()
+            .context("could not run gpg, which is needed for OpenPGP keys")
+    };
+    gpg(&["--import".as_ref(), keyring.as_ref()])?;
+    let output = gpg(&[
+        "--status-fd".as_ref(),
+        "1".as_ref(),
+        "--verify".as_ref(),
+        signature.as_ref(),
+        manifest.as_ref(),
+    ])?;
+
+    // `VALIDSIG` is followed by the fingerprint of the signing key, and last
+    // of all that of its primary key.
+    let status = String::from_utf8_lossy(&output.stdout);
+    let fingerprint = status
+        .lines()
+        .filter_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "))
+        .flat_map(|fields| [fields.split(' ').next(), fields.split(' ').next_back()])
+        .flatten()
+        .find(|fingerprint| keys.iter().any(|(trusted, _)| trusted == fingerprint))
+        .map(str::to_owned);
+    fingerprint
+        .ok_or_else(|| anyhow!("'{url}.asc' is not a good signature by a trusted OpenPGP key"))
+}
+
+/// Returns the identity that signed `manifest`.
+async fn verify_sigstore(
+    download: &DownloadCfg<'_>,
+    url: &str,
+    manifest: &Path,
+    identities: &[(&str, &str)],
+) -> Result<String> {
+    let bundle = download.tmp_cx.new_file()?;
+    fetch(
+        download,
+        "signature",
+        &format!("{url}.sigstore.json"),
+        &bundle,
+    )
+    .await?;
+
+    for (identity, issuer) in identities {
+        let status = Command::new("cosign")
+            .arg("verify-blob")
+            .arg("--bundle")
+            .arg(&*bundle)
+            .args(["--certificate-identity", identity])
+            .args(["--certificate-oidc-issuer", issuer])
+            .arg(manifest)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .context("could not run cosign, which is needed for Sigstore identities")?;
+        if status.success() {
+            return Ok(identity.to_string());
+        }
+    }
+    Err(anyhow!(
+        "'{url}.sigstore.json' is not a signature by a trusted Sigstore identity"
+    ))
+}
+
+fn gpg_installed() -> bool {
+    Command::new("gpg")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// The suffixes of the signature files next to a manifest that `keys` are
+/// checked against.
+pub(crate) fn signature_suffixes(keys: &[TrustedKey]) -> Vec<&'static str> {
+    let mut suffixes = Vec::new();
+    if keys.iter().any(|key| matches!(key, TrustedKey::Gpg { .. })) {
+        suffixes.push(".asc");
+    }
+    if keys
+        .iter()
+        .any(|key| matches!(key, TrustedKey::Sigstore { .. }))
+    {
+        suffixes.push(".sigstore.json");
+    }
+    suffixes
+}
+
+/// Downloads the signature or key at `url`, from the bundle when installing
+/// from one.
+pub(crate) async fn fetch(
+    download: &DownloadCfg<'_>,
+    what: &str,
+    url: &str,
+    path: &Path,
+) -> Result<()> {
+    utils::download_file(
+        &download.resolve_url(url, false)?,
+        path,
+        None,
+        &|n| (download.notify_handler)(n.into()),
+        download.process,
+    )
+    .await
+    .with_context(|| format!("could not download the {what} '{url}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dist::DEFAULT_DIST_SERVER;
+
+    #[test]
+    fn release_key_matches_its_fingerprint() {
+        if !gpg_installed() {
+            return;
+        }
+        let dir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let tmp_cx =
+            temp::Context::new(dir.path().to_owned(), DEFAULT_DIST_SERVER, Box::new(|_| ()));
+        let key = TrustedKey::gpg(RUST_KEY.to_owned(), &tmp_cx).unwrap();
+        assert_eq!(key.id(), RUST_KEY_FINGERPRINT);
+    }
+}
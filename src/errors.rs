@@ -6,6 +6,7 @@ use std::fmt::Debug;
 use std::io;
 use std::io::Write;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use thiserror::Error as ThisError;
 use url::Url;
@@ -16,6 +17,7 @@ use crate::{
         Channel, TargetTriple, ToolchainDesc,
     },
     toolchain::{PathBasedToolchainName, ToolchainName},
+    utils::units::format_bytes,
 };
 
 /// A type erasing thunk for the retry crate to permit use with anyhow. See <https://github.com/dtolnay/anyhow/issues/149>
@@ -62,6 +64,25 @@ pub enum RustupError {
     DownloadingFile { url: Url, path: PathBuf },
     #[error("could not download file from '{url}' to '{}'", .path.display())]
     DownloadNotExists { url: Url, path: PathBuf },
+    #[error("the server failed to send '{url}' with status {status}; run `rustup status` to see whether the Rust infrastructure has an incident")]
+    DownloadServerFailed { url: Url, status: u32 },
+    #[error("the server is rate limiting downloads of '{url}', please try again later")]
+    DownloadRateLimited {
+        url: Url,
+        retry_after: Option<Duration>,
+    },
+    #[error("update would download {}, which was not confirmed (pass `--yes` to rustup to skip the question)", format_bytes(*.0))]
+    DownloadNotConfirmed(u64),
+    #[error("could not verify the signature of '{url}' with the keys trusted for its dist server: {reason}")]
+    SignatureNotVerified { url: String, reason: String },
+    #[error(
+        "toolchains from the '{channel}' channel are not allowed by policy, only from '{allowed}'"
+    )]
+    ChannelNotAllowed { channel: String, allowed: String },
+    #[error("there is no component group '{0}' in the policy")]
+    UnknownComponentGroup(String),
+    #[error("post-install hook '{hook}' failed on '{}'", .path.display())]
+    PostInstallHookFailed { hook: String, path: PathBuf },
     #[error("Missing manifest in toolchain '{}'", .0)]
     MissingManifest(ToolchainDesc),
     #[error("server sent a broken manifest: missing package for component {0}")]
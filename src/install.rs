@@ -2,13 +2,15 @@
 //! toolchains
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 use crate::{
     config::Cfg,
+    diskio::{self, IoProfile},
     dist::{self, prefix::InstallPrefix, DistOptions, Notification},
     errors::RustupError,
     notifications::Notification as RootNotification,
+    process::Process,
     toolchain::{CustomToolchainName, LocalToolchainName, Toolchain},
     utils,
 };
@@ -20,6 +22,7 @@ pub(crate) enum UpdateStatus {
     Unchanged,
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone)]
 pub(crate) enum InstallMethod<'a> {
     Copy {
@@ -85,7 +88,14 @@ impl InstallMethod<'_> {
             match self {
                 InstallMethod::Dist { .. } => {}
                 _ => {
-                    uninstall(path, notify_handler)?;
+                    let cfg = self.cfg();
+                    uninstall(
+                        path,
+                        cfg.io_profile,
+                        cfg.io_threads,
+                        notify_handler,
+                        cfg.process,
+                    )?;
                 }
             }
         }
@@ -104,10 +114,9 @@ impl InstallMethod<'_> {
                 let maybe_new_hash = dist::update_from_dist(prefix, opts).await?;
 
                 if let Some(hash) = maybe_new_hash {
-                    if let Some(hash_file) = opts.update_hash {
-                        utils::write_file("update hash", hash_file, &hash)?;
-                    }
-
+                    opts.cfg
+                        .manifest_cache
+                        .set_installed(opts.toolchain, &hash)?;
                     Ok(true)
                 } else {
                     Ok(false)
@@ -151,6 +160,23 @@ impl InstallMethod<'_> {
     }
 }
 
-pub(crate) fn uninstall(path: &Path, notify_handler: &dyn Fn(Notification<'_>)) -> Result<()> {
-    utils::remove_dir("install", path, notify_handler)
+/// Removes the toolchain at `path`, with as many IO threads as `io_profile`
+/// allows, or `threads` if given, since a toolchain with docs and many
+/// targets has tens of thousands of files.
+pub(crate) fn uninstall(
+    path: &Path,
+    io_profile: IoProfile,
+    threads: Option<usize>,
+    notify_handler: &dyn Fn(Notification<'_>),
+    process: &Process,
+) -> Result<()> {
+    let name = "install";
+    notify_handler(utils::Notification::RemovingDirectory(name, path).into());
+    let utils_notify = |n: utils::Notification<'_>| notify_handler(n.into());
+    diskio::remove_dir_all(path, io_profile, threads, Some(&utils_notify), process).with_context(
+        || RustupError::RemovingDirectory {
+            name,
+            path: PathBuf::from(path),
+        },
+    )
 }
@@ -72,6 +72,10 @@ fn component_for_bin(binary: &str) -> Option<&'static str> {
     }
 }
 
+/// Embedders can supply their own transport for downloads with
+/// [`download::register_transport`].
+pub use download;
+
 #[macro_use]
 pub mod cli;
 mod command;
@@ -83,6 +87,7 @@ pub mod errors;
 mod fallback_settings;
 mod install;
 pub mod notifications;
+mod policy;
 pub mod process;
 mod settings;
 #[cfg(feature = "test")]
@@ -1,25 +1,49 @@
 use std::fmt::{self, Display};
 use std::path::{Path, PathBuf};
 
+use serde::Serialize;
+
 use crate::settings::MetadataVersion;
+use crate::utils::notify::serialize;
 use crate::{
     dist::{temp, ToolchainDesc},
-    toolchain::ToolchainName,
-    utils::notify::NotificationLevel,
+    toolchain::{CustomToolchainName, LocalToolchainName, ToolchainName},
+    utils::units::format_bytes,
 };
 
-#[derive(Debug)]
-pub(crate) enum Notification<'a> {
-    Install(crate::dist::Notification<'a>),
-    Utils(crate::utils::Notification<'a>),
-    Temp(temp::Notification<'a>),
+pub use crate::utils::notify::NotificationLevel;
+pub(crate) use event::EventSink;
+pub use event::{Event, EventHandler, EventKind, InstallStats};
+
+mod event;
 
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "data", rename_all = "kebab-case")]
+pub enum Notification<'a> {
     SetDefaultToolchain(Option<&'a ToolchainName>),
-    SetOverrideToolchain(&'a Path, &'a str),
+    SetOverrideToolchain(
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+        &'a str,
+    ),
     SetProfile(&'a str),
     SetSelfUpdate(&'a str),
+    SetCrashReports(&'a str),
+    SetOutputStyle(&'a str),
+    SetPalette(&'a str),
+    SetAutoInstall(&'a str),
+    SetProxyLinks(&'a str),
+    SetProxyDir(#[serde(serialize_with = "serialize::path")] &'a Path),
+    SetIoProfile(&'a str),
+    SetMaxDownloads(usize),
+    SetTlsRootSource(&'a str),
+    SetDistServers(&'a [String]),
+    SetCachePeers(&'a [String]),
+    SetConfirmDownloadsOver(Option<u64>),
+    SetPostInstallHook(Option<&'a str>),
+    SetDedup(bool),
+    SetToolchainRetention(Option<u64>),
     LookingForToolchain(&'a ToolchainDesc),
-    ToolchainDirectory(&'a Path),
+    ToolchainDirectory(#[serde(serialize_with = "serialize::path")] &'a Path),
     UpdatingToolchain(&'a str),
     InstallingToolchain(&'a str),
     InstalledToolchain(&'a str),
@@ -30,13 +54,47 @@ pub(crate) enum Notification<'a> {
     UpgradingMetadata(MetadataVersion, MetadataVersion),
     MetadataUpgradeNotNeeded(MetadataVersion),
     ReadMetadataVersion(MetadataVersion),
-    NonFatalError(&'a anyhow::Error),
+    NonFatalError(#[serde(serialize_with = "serialize::error")] &'a anyhow::Error),
     UpgradeRemovesToolchains,
+    LegacyToolchainName(&'a str),
+    RenamedToolchain(&'a str, &'a CustomToolchainName),
+    PinnedToolchain(&'a ToolchainName, Option<&'a str>),
+    UnpinnedToolchain(&'a ToolchainName),
+    AddedTrustedKey(&'a str, &'a str),
+    RemovedTrustedKey(&'a str, &'a str),
+    SetTlsHostRoots(
+        &'a str,
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+    ),
+    SetTlsHostInsecure(&'a str),
+    RemovedTlsHost(&'a str),
+    SkippingPinnedToolchain(&'a ToolchainDesc),
+    ComponentFromFile(
+        &'a str,
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+    ),
+    PinExpired(&'a str, &'a str),
+    HomeOnNetworkFs(#[serde(serialize_with = "serialize::path")] &'a Path),
+    /// The toolchain file asks for components or targets that aren't installed
+    MissingFromToolchainFile(
+        &'a LocalToolchainName,
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+    ),
     /// Both `rust-toolchain` and `rust-toolchain.toml` exist within a directory
     DuplicateToolchainFile {
+        #[serde(serialize_with = "serialize::path")]
         rust_toolchain: &'a Path,
+        #[serde(serialize_with = "serialize::path")]
         rust_toolchain_toml: &'a Path,
     },
+
+    // Serialized as the notifications they wrap.
+    #[serde(untagged)]
+    Install(crate::dist::Notification<'a>),
+    #[serde(untagged)]
+    Utils(crate::utils::Notification<'a>),
+    #[serde(untagged)]
+    Temp(temp::Notification<'a>),
 }
 
 impl<'a> From<crate::dist::Notification<'a>> for Notification<'a> {
@@ -73,13 +131,43 @@ impl Notification<'_> {
             | SetOverrideToolchain(_, _)
             | SetProfile(_)
             | SetSelfUpdate(_)
+            | SetCrashReports(_)
+            | SetOutputStyle(_)
+            | SetPalette(_)
+            | SetAutoInstall(_)
+            | SetProxyLinks(_)
+            | SetProxyDir(_)
+            | SetIoProfile(_)
+            | SetMaxDownloads(_)
+            | SetTlsRootSource(_)
+            | SetDistServers(_)
+            | SetCachePeers(_)
+            | SetConfirmDownloadsOver(_)
+            | SetPostInstallHook(_)
+            | SetDedup(_)
+            | SetToolchainRetention(_)
             | UsingExistingToolchain(_)
             | UninstallingToolchain(_)
             | UninstalledToolchain(_)
             | UpgradingMetadata(_, _)
-            | MetadataUpgradeNotNeeded(_) => NotificationLevel::Info,
+            | MetadataUpgradeNotNeeded(_)
+            | RenamedToolchain(_, _)
+            | PinnedToolchain(_, _)
+            | UnpinnedToolchain(_)
+            | AddedTrustedKey(_, _)
+            | RemovedTrustedKey(_, _)
+            | SetTlsHostRoots(_, _)
+            | RemovedTlsHost(_)
+            | SkippingPinnedToolchain(_)
+            | ComponentFromFile(_, _) => NotificationLevel::Info,
             NonFatalError(_) => NotificationLevel::Error,
-            UpgradeRemovesToolchains | DuplicateToolchainFile { .. } => NotificationLevel::Warn,
+            UpgradeRemovesToolchains
+            | LegacyToolchainName(_)
+            | DuplicateToolchainFile { .. }
+            | PinExpired(_, _)
+            | SetTlsHostInsecure(_)
+            | HomeOnNetworkFs(_)
+            | MissingFromToolchainFile(_, _) => NotificationLevel::Warn,
         }
     }
 }
@@ -101,6 +189,38 @@ impl Display for Notification<'_> {
             ),
             SetProfile(name) => write!(f, "profile set to '{name}'"),
             SetSelfUpdate(mode) => write!(f, "auto-self-update mode set to '{mode}'"),
+            SetCrashReports(mode) => write!(f, "crash-reports mode set to '{mode}'"),
+            SetOutputStyle(style) => write!(f, "output style set to '{style}'"),
+            SetPalette(palette) => write!(f, "palette set to '{palette}'"),
+            SetAutoInstall(mode) => write!(f, "auto-install mode set to '{mode}'"),
+            SetProxyLinks(links) => write!(f, "proxy links set to '{links}'"),
+            SetProxyDir(dir) => write!(f, "proxies moved to '{}'", dir.display()),
+            SetIoProfile(profile) => write!(f, "IO profile set to '{profile}'"),
+            SetMaxDownloads(max) => write!(f, "max downloads set to '{max}'"),
+            SetTlsRootSource(source) => write!(f, "TLS root source set to '{source}'"),
+            SetDistServers([]) => write!(f, "dist servers cleared"),
+            SetDistServers(servers) => {
+                write!(f, "dist servers set to '{}'", servers.join("', '"))
+            }
+            SetCachePeers([]) => write!(f, "cache peers cleared"),
+            SetCachePeers(peers) => write!(f, "cache peers set to '{}'", peers.join("', '")),
+            SetConfirmDownloadsOver(Some(limit)) => write!(
+                f,
+                "updates downloading more than {} now ask for confirmation",
+                format_bytes(*limit)
+            ),
+            SetConfirmDownloadsOver(None) => {
+                write!(f, "updates no longer ask for confirmation of their size")
+            }
+            SetPostInstallHook(Some(hook)) => write!(f, "post-install hook set to '{hook}'"),
+            SetPostInstallHook(None) => write!(f, "post-install hook removed"),
+            SetDedup(true) => write!(f, "files identical between toolchains are now shared"),
+            SetDedup(false) => write!(f, "files are no longer shared between new toolchains"),
+            SetToolchainRetention(Some(days)) => write!(
+                f,
+                "toolchains unused for {days} days are now removed by `rustup toolchain gc`"
+            ),
+            SetToolchainRetention(None) => write!(f, "toolchain retention period removed"),
             LookingForToolchain(name) => write!(f, "looking for installed toolchain '{name}'"),
             ToolchainDirectory(path) => write!(f, "toolchain directory: '{}'", path.display()),
             UpdatingToolchain(name) => write!(f, "updating existing install for '{name}'"),
@@ -123,6 +243,55 @@ impl Display for Notification<'_> {
                 f,
                 "this upgrade will remove all existing toolchains. you will need to reinstall them"
             ),
+            LegacyToolchainName(name) => write!(
+                f,
+                "ignoring toolchain '{name}' as its name is no longer allowed; run `rustup self upgrade-data` to rename it"
+            ),
+            RenamedToolchain(old, new) => {
+                write!(f, "renamed toolchain '{old}' to '{new}'")
+            }
+            PinnedToolchain(name, None) => write!(f, "pinned toolchain '{name}'"),
+            PinnedToolchain(name, Some(until)) => {
+                write!(f, "pinned toolchain '{name}' until {until}")
+            }
+            UnpinnedToolchain(name) => write!(f, "unpinned toolchain '{name}'"),
+            AddedTrustedKey(server, key) => {
+                write!(f, "manifests from '{server}' can now be signed by '{key}'")
+            }
+            RemovedTrustedKey(server, key) => {
+                write!(f, "manifests from '{server}' are no longer trusted if signed by '{key}'")
+            }
+            SetTlsHostRoots(host, path) => write!(
+                f,
+                "'{host}' is now also trusted if its certificate leads up to the roots in '{}'",
+                path.display()
+            ),
+            SetTlsHostInsecure(host) => write!(
+                f,
+                "the TLS certificate of '{host}' will no longer be verified; \
+                 only do this for servers on a network you trust"
+            ),
+            RemovedTlsHost(host) => write!(f, "removed the TLS settings of '{host}'"),
+            SkippingPinnedToolchain(name) => write!(f, "skipping pinned toolchain '{name}'"),
+            ComponentFromFile(component, path) => write!(
+                f,
+                "'{}' matches the manifest's archive of component '{component}'",
+                path.display()
+            ),
+            PinExpired(name, until) => write!(
+                f,
+                "the pin on toolchain '{name}' expired on {until}; update it, or extend the pin with `rustup toolchain pin`"
+            ),
+            HomeOnNetworkFs(path) => write!(
+                f,
+                "rustup home '{}' is on a network file system; installs will be slower, and running rustup on more than one machine at once can break toolchains",
+                path.display()
+            ),
+            MissingFromToolchainFile(toolchain, path) => write!(
+                f,
+                "'{toolchain}' lacks components or targets that '{}' asks for; run `rustup toolchain install` to add them, or `rustup set auto-install enable` to have proxies add them",
+                path.display()
+            ),
             DuplicateToolchainFile {
                 rust_toolchain,
                 rust_toolchain_toml,
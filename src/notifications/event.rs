@@ -0,0 +1,200 @@
+//! A serializable view of rustup's notifications.
+//!
+//! Notifications are raised all over rustup with borrowed data and are
+//! primarily meant to be rendered for the console. An [`Event`] is one of
+//! them along with its level and message, for embedders and the
+//! `RUSTUP_EVENT_LOG` machine interface, so that they don't have to parse
+//! human-readable messages. It serializes as the kind of notification in
+//! kebab case and the values it carries.
+
+use std::fmt;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use super::Notification;
+use crate::utils::notify::NotificationLevel;
+use crate::utils::units::format_bytes;
+
+/// A single notification raised by rustup.
+#[derive(Debug, Serialize)]
+pub struct Event<'a> {
+    pub level: NotificationLevel,
+    /// The message rustup shows on the console for this event.
+    pub message: String,
+    #[serde(flatten)]
+    pub kind: EventKind<'a>,
+}
+
+/// What happened, as in `installing-component`, and the values it carries.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "data", rename_all = "kebab-case")]
+pub enum EventKind<'a> {
+    /// An install or update has finished.
+    InstallSummary(&'a InstallStats),
+    #[serde(untagged)]
+    Notification(&'a Notification<'a>),
+}
+
+impl<'a> Event<'a> {
+    pub(crate) fn install_summary(stats: &'a InstallStats) -> Self {
+        Self {
+            level: NotificationLevel::Info,
+            message: stats.to_string(),
+            kind: EventKind::InstallSummary(stats),
+        }
+    }
+}
+
+impl<'a> Notification<'a> {
+    pub(crate) fn to_event(&'a self) -> Event<'a> {
+        Event {
+            level: self.level(),
+            message: self.to_string(),
+            kind: EventKind::Notification(self),
+        }
+    }
+}
+
+/// What an install or update did, once it has finished or so far.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InstallStats {
+    pub components: usize,
+    /// In bytes.
+    pub downloaded: usize,
+    pub cache_hits: usize,
+    pub download_secs: f64,
+    pub install_secs: f64,
+    /// The estimated seconds left of the download in progress, if any and if
+    /// its length and speed are known.
+    pub eta_secs: Option<f64>,
+}
+
+impl fmt::Display for InstallStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "installed {} component{} in {:.1}s (downloading {:.1}s, installing {:.1}s)\n\
+             downloaded {}, {} from the download cache",
+            self.components,
+            if self.components == 1 { "" } else { "s" },
+            self.download_secs + self.install_secs,
+            self.download_secs,
+            self.install_secs,
+            format_bytes(self.downloaded as u64),
+            self.cache_hits,
+        )
+    }
+}
+
+/// Receives the [`Event`] of every notification rustup raises.
+pub type EventHandler = dyn Fn(&Event<'_>) + Send + Sync;
+
+/// Where a process sends its events, if anywhere; set by embedders that run
+/// rustup's CLI in-process with [`crate::process::Process::with_event_handler`].
+#[derive(Clone, Default)]
+pub(crate) struct EventSink(pub(crate) Option<Arc<EventHandler>>);
+
+impl fmt::Debug for EventSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("EventSink(..)"),
+            None => f.write_str("EventSink(None)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::dist::{self, TargetTriple};
+    use crate::utils;
+
+    #[test]
+    fn structured_component_event() {
+        let host = TargetTriple::new("x86_64-unknown-linux-gnu");
+        let cross = TargetTriple::new("aarch64-unknown-linux-gnu");
+        let n = Notification::Install(dist::Notification::InstallingComponent(
+            "rust-std",
+            &host,
+            Some(&cross),
+        ));
+        let event = n.to_event();
+        assert_eq!(event.level, NotificationLevel::Info);
+        assert_eq!(
+            event.message,
+            "installing component 'rust-std' for 'aarch64-unknown-linux-gnu'"
+        );
+        assert!(matches!(
+            event.kind,
+            EventKind::Notification(Notification::Install(
+                dist::Notification::InstallingComponent("rust-std", ..)
+            ))
+        ));
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({
+                "level": "info",
+                "message": "installing component 'rust-std' for 'aarch64-unknown-linux-gnu'",
+                "kind": "installing-component",
+                "data": [
+                    "rust-std",
+                    "x86_64-unknown-linux-gnu",
+                    "aarch64-unknown-linux-gnu"
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn serialized_form() {
+        let n = Notification::SetOverrideToolchain(Path::new("/project"), "nightly");
+        assert_eq!(
+            serde_json::to_string(&n.to_event()).unwrap(),
+            r#"{"level":"info","message":"override toolchain for '/project' set to 'nightly'","kind":"set-override-toolchain","data":["/project","nightly"]}"#
+        );
+
+        let n = Notification::Utils(utils::Notification::DownloadDataReceived(&[0; 16]));
+        let event = serde_json::to_value(n.to_event()).unwrap();
+        assert_eq!(
+            (&event["kind"], &event["data"]),
+            (&json!("download-data-received"), &json!(16))
+        );
+
+        let n = Notification::UpdateHashMatches;
+        let event = serde_json::to_value(n.to_event()).unwrap();
+        assert_eq!(event.get("data"), None);
+
+        let stats = InstallStats {
+            components: 2,
+            downloaded: 1024,
+            cache_hits: 1,
+            download_secs: 1.0,
+            install_secs: 0.5,
+            eta_secs: Some(3.0),
+        };
+        let event = serde_json::to_value(Event::install_summary(&stats)).unwrap();
+        assert_eq!(
+            (&event["kind"], &event["data"]["cache-hits"]),
+            (&json!("install-summary"), &json!(1))
+        );
+        assert_eq!(event["data"]["eta-secs"], json!(3.0));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths_are_kept() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"/project/\xff"));
+        let n = Notification::HomeOnNetworkFs(path);
+        let event = serde_json::to_value(n.to_event()).unwrap();
+        assert_eq!(event["data"], json!("/project/\u{fffd}"));
+    }
+}
@@ -0,0 +1,212 @@
+//! Configuration shared by an organization, synced from a bundle it
+//! maintains with `rustup config sync`.
+//!
+//! The policy sits below the user's own settings: it provides the dist
+//! server, profile and post-install hook to use when the user hasn't chosen
+//! any, names groups of components, and limits the channels that can be
+//! installed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::dist::{Channel, Profile};
+use crate::errors::RustupError;
+use crate::utils;
+
+const POLICY_FILE: &str = "policy.toml";
+/// The name of a configuration bundle at the root of a git repository.
+pub(crate) const CONFIG_BUNDLE: &str = "rustup-config.toml";
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct Policy {
+    /// Where the policy was synced from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// The dist server, such as a mirror, used unless `RUSTUP_DIST_SERVER`
+    /// is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dist_server: Option<String>,
+    /// The profile used unless the user sets one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Profile>,
+    /// The program run on each ELF file an update installs, unless the user
+    /// sets one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_install_hook: Option<String>,
+    /// The channels toolchains may be installed from, all if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_channels: Vec<String>,
+    /// Components that can be added together as `@<group>`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub component_groups: BTreeMap<String, Vec<String>>,
+}
+
+impl Policy {
+    fn path(rustup_dir: &Path) -> PathBuf {
+        rustup_dir.join(POLICY_FILE)
+    }
+
+    /// The policy synced into `rustup_dir`, or an empty one.
+    pub(crate) fn load(rustup_dir: &Path) -> Result<Self> {
+        let path = Self::path(rustup_dir);
+        if !utils::is_file(&path) {
+            return Ok(Self::default());
+        }
+        let data = utils::read_file("policy", &path)?;
+        Self::parse(&data).with_context(|| RustupError::ParsingFile {
+            name: "policy",
+            path,
+        })
+    }
+
+    pub(crate) fn save(&self, rustup_dir: &Path) -> Result<()> {
+        utils::write_file("policy", &Self::path(rustup_dir), &toml::to_string(self)?)
+    }
+
+    pub(crate) fn parse(data: &str) -> Result<Self> {
+        toml::from_str(data).context("error parsing policy")
+    }
+
+    /// Fails unless toolchains may be installed from `channel`.
+    ///
+    /// An allowed version also allows the more precise ones it covers, so
+    /// `1.80` allows `1.80.1`.
+    pub(crate) fn check_channel(&self, channel: &Channel) -> Result<()> {
+        let name = channel.to_string();
+        let allowed = self.allowed_channels.is_empty()
+            || self.allowed_channels.iter().any(|allowed| {
+                name == *allowed
+                    || name
+                        .strip_prefix(allowed.as_str())
+                        .is_some_and(|rest| rest.starts_with('.'))
+            });
+        match allowed {
+            true => Ok(()),
+            false => Err(RustupError::ChannelNotAllowed {
+                channel: name,
+                allowed: self.allowed_channels.join("', '"),
+            }
+            .into()),
+        }
+    }
+
+    /// Replaces each `@<group>` in `components` with the components of that
+    /// group.
+    pub(crate) fn expand_component_groups(&self, components: &[String]) -> Result<Vec<String>> {
+        let mut expanded = Vec::new();
+        for component in components {
+            match component.strip_prefix('@') {
+                Some(group) => match self.component_groups.get(group) {
+                    Some(members) => expanded.extend(members.iter().cloned()),
+                    None => return Err(RustupError::UnknownComponentGroup(group.to_owned()).into()),
+                },
+                None => expanded.push(component.clone()),
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Describes how `other` differs from this policy, one setting per
+    /// line, or returns an empty string if they're the same.
+    pub(crate) fn drift(&self, other: &Self) -> String {
+        fn show<T: ToString>(value: &Option<T>) -> String {
+            value
+                .as_ref()
+                .map_or_else(|| "unset".to_owned(), |v| format!("'{}'", v.to_string()))
+        }
+        fn list(values: Option<&Vec<String>>) -> String {
+            match values {
+                Some(values) if !values.is_empty() => format!("'{}'", values.join("', '")),
+                _ => "unset".to_owned(),
+            }
+        }
+
+        let mut drift = String::new();
+        let mut differs = |name: &str, current: String, synced: String| {
+            if current != synced {
+                let _ = writeln!(drift, "{name}: {current}, bundle has {synced}");
+            }
+        };
+        differs(
+            "dist_server",
+            show(&self.dist_server),
+            show(&other.dist_server),
+        );
+        differs("profile", show(&self.profile), show(&other.profile));
+        differs(
+            "post_install_hook",
+            show(&self.post_install_hook),
+            show(&other.post_install_hook),
+        );
+        differs(
+            "allowed_channels",
+            list(Some(&self.allowed_channels)),
+            list(Some(&other.allowed_channels)),
+        );
+        let groups = self
+            .component_groups
+            .keys()
+            .chain(other.component_groups.keys())
+            .collect::<BTreeSet<_>>();
+        for group in groups {
+            differs(
+                &format!("component_groups.{group}"),
+                list(self.component_groups.get(group)),
+                list(other.component_groups.get(group)),
+            );
+        }
+        drift
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_channels() {
+        let policy = Policy {
+            allowed_channels: vec!["stable".to_owned(), "1.80".to_owned()],
+            ..Policy::default()
+        };
+        let channel = |name: &str| name.parse::<crate::dist::PartialToolchainDesc>().unwrap();
+        for allowed in ["stable", "1.80", "1.80.1"] {
+            assert!(policy.check_channel(&channel(allowed).channel).is_ok());
+        }
+        for refused in ["nightly", "1.8", "1.81.0"] {
+            assert!(policy.check_channel(&channel(refused).channel).is_err());
+        }
+        assert!(Policy::default()
+            .check_channel(&channel("nightly").channel)
+            .is_ok());
+    }
+
+    #[test]
+    fn drift() {
+        let current = Policy::parse(
+            r#"
+profile = "minimal"
+[component_groups]
+embedded = ["rust-src"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(current.drift(&current.clone()), "");
+
+        let synced = Policy {
+            profile: Some(Profile::Default),
+            dist_server: Some("https://mirror.example".to_owned()),
+            ..Policy::default()
+        };
+        assert_eq!(
+            current.drift(&synced),
+            "dist_server: unset, bundle has 'https://mirror.example'\n\
+             profile: 'minimal', bundle has 'default'\n\
+             component_groups.embedded: 'rust-src', bundle has unset\n"
+        );
+    }
+}
@@ -4,12 +4,15 @@ use std::fmt::Debug;
 use std::io;
 use std::io::IsTerminal;
 use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, OnceLock};
 #[cfg(feature = "test")]
 use std::{
     collections::HashMap,
-    io::Cursor,
+    ffi::OsStr,
+    io::{Cursor, Read, Write},
     path::Path,
-    sync::{Arc, Mutex},
+    sync::Mutex,
 };
 
 use anyhow::{Context, Result};
@@ -20,9 +23,14 @@ use tracing_subscriber::util::SubscriberInitExt;
 #[cfg(feature = "test")]
 use tracing_subscriber::{reload::Handle, EnvFilter, Registry};
 
+use crate::notifications::{EventHandler, EventSink};
+use crate::settings::SettingsFile;
+
 pub mod filesource;
 pub mod terminalsource;
 
+use terminalsource::{OutputStyle, Palette, TermSettings};
+
 /// Allows concrete types for the process abstraction.
 #[derive(Clone, Debug)]
 pub enum Process {
@@ -36,6 +44,26 @@ impl Process {
         Self::OsProcess(OsProcess::new())
     }
 
+    /// Sends every event of rustup's CLI run with this process to `handler`,
+    /// as when an embedder runs it in-process with [`crate::cli::dispatch::main`].
+    pub fn with_event_handler(mut self, handler: Arc<EventHandler>) -> Self {
+        let sink = match &mut self {
+            Process::OsProcess(p) => &mut p.event_sink,
+            #[cfg(feature = "test")]
+            Process::TestProcess(p) => &mut p.event_sink,
+        };
+        *sink = EventSink(Some(handler));
+        self
+    }
+
+    pub(crate) fn event_handler(&self) -> Option<&Arc<EventHandler>> {
+        match self {
+            Process::OsProcess(p) => p.event_sink.0.as_ref(),
+            #[cfg(feature = "test")]
+            Process::TestProcess(p) => p.event_sink.0.as_ref(),
+        }
+    }
+
     pub fn name(&self) -> Option<String> {
         let arg0 = match self.var("RUSTUP_FORCE_ARG0") {
             Ok(v) => Some(v),
@@ -61,6 +89,50 @@ impl Process {
         home::env::rustup_home_with_env(self).context("failed to determine rustup home dir")
     }
 
+    /// Whether output is kept plain, without colors or progress redrawn in
+    /// place, as chosen by `RUSTUP_OUTPUT_STYLE` or else by
+    /// `rustup set output-style`.
+    pub(crate) fn plain_output(&self) -> bool {
+        self.term_settings().style == OutputStyle::Plain
+    }
+
+    /// The colors of the terminal, as chosen by `RUSTUP_PALETTE` or else by
+    /// `rustup set palette`.
+    pub(crate) fn palette(&self) -> Palette {
+        self.term_settings().palette
+    }
+
+    fn term_settings(&self) -> TermSettings {
+        match self {
+            Process::OsProcess(p) => *p.term_settings.get_or_init(|| self.load_term_settings()),
+            #[cfg(feature = "test")]
+            Process::TestProcess(_) => self.load_term_settings(),
+        }
+    }
+
+    fn load_term_settings(&self) -> TermSettings {
+        let style = (self.var("RUSTUP_OUTPUT_STYLE").ok()).map(|s| s.parse().unwrap_or_default());
+        let palette = (self.var("RUSTUP_PALETTE").ok()).map(|s| s.parse().unwrap_or_default());
+        if let (Some(style), Some(palette)) = (style, palette) {
+            return TermSettings { style, palette };
+        }
+        let (saved_style, saved_palette) = self
+            .rustup_home()
+            .map(|home| home.join("settings.toml"))
+            .ok()
+            .filter(|path| path.exists())
+            .and_then(|path| {
+                SettingsFile::new(path)
+                    .with(|s| Ok((s.output_style, s.palette)))
+                    .ok()
+            })
+            .unwrap_or_default();
+        TermSettings {
+            style: style.or(saved_style).unwrap_or_default(),
+            palette: palette.or(saved_palette).unwrap_or_default(),
+        }
+    }
+
     pub fn var(&self, key: &str) -> Result<String, env::VarError> {
         match self {
             Process::OsProcess(_) => env::var(key),
@@ -127,6 +199,33 @@ impl Process {
             Process::TestProcess(p) => Ok(p.cwd.clone()),
         }
     }
+
+    /// Runs a tool in place of this process, returning its exit status if
+    /// this process lives on to see it.
+    pub(crate) fn exec(&self, cmd: &mut Command) -> io::Result<ExitStatus> {
+        match self {
+            Process::OsProcess(_) => {
+                // FIXME rust-lang/rust#32254. It's not clear to me
+                // when and why this is needed.
+                cmd.stdin(Stdio::inherit());
+                OsProcess::exec(cmd)
+            }
+            // An in-process test can't be replaced by the child, so run it to
+            // completion and collect its output instead.
+            #[cfg(feature = "test")]
+            Process::TestProcess(p) => p.run_child(cmd),
+        }
+    }
+
+    /// Runs a program to completion as a child of this process, with this
+    /// process' environment, and returns its exit status.
+    pub(crate) fn run(&self, cmd: &mut Command) -> io::Result<ExitStatus> {
+        match self {
+            Process::OsProcess(_) => cmd.status(),
+            #[cfg(feature = "test")]
+            Process::TestProcess(p) => p.run_child(cmd),
+        }
+    }
 }
 
 impl home::env::Env for Process {
@@ -157,6 +256,15 @@ impl home::env::Env for Process {
 pub struct OsProcess {
     pub(self) stderr_is_a_tty: bool,
     pub(self) stdout_is_a_tty: bool,
+    /// Read from the settings once, the first time output asks for it.
+    term_settings: OnceLock<TermSettings>,
+    event_sink: EventSink,
+    /// Whether stdout and stderr are legacy Windows consoles, found out the
+    /// first time output asks for it.
+    #[cfg(windows)]
+    pub(self) stdout_legacy_console: OnceLock<bool>,
+    #[cfg(windows)]
+    pub(self) stderr_legacy_console: OnceLock<bool>,
 }
 
 impl OsProcess {
@@ -164,7 +272,50 @@ impl OsProcess {
         OsProcess {
             stderr_is_a_tty: io::stderr().is_terminal(),
             stdout_is_a_tty: io::stdout().is_terminal(),
+            term_settings: OnceLock::new(),
+            event_sink: EventSink::default(),
+            #[cfg(windows)]
+            stdout_legacy_console: OnceLock::new(),
+            #[cfg(windows)]
+            stderr_legacy_console: OnceLock::new(),
+        }
+    }
+
+    // On Unix the tool replaces rustup, keeping its pid, so that signals,
+    // job control and the exit status reach it and its parent directly.
+    #[cfg(unix)]
+    fn exec(cmd: &mut Command) -> io::Result<ExitStatus> {
+        use std::io::Write;
+        use std::os::unix::prelude::*;
+
+        // Nothing of rustup runs after a successful `exec`, so what it would
+        // write on exit is written now.
+        let _ = io::stdout().flush();
+        rs_tracing::close_trace_file_fn();
+        #[cfg(feature = "otel")]
+        opentelemetry::global::shutdown_tracer_provider();
+        Err(cmd.exec())
+    }
+
+    #[cfg(windows)]
+    fn exec(cmd: &mut Command) -> io::Result<ExitStatus> {
+        use windows_sys::Win32::Foundation::{BOOL, FALSE, TRUE};
+        use windows_sys::Win32::System::Console::SetConsoleCtrlHandler;
+
+        unsafe extern "system" fn ctrlc_handler(_: u32) -> BOOL {
+            // Do nothing. Let the child process handle it.
+            TRUE
         }
+        unsafe {
+            if SetConsoleCtrlHandler(Some(ctrlc_handler), TRUE) == FALSE {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "Unable to set console handler",
+                ));
+            }
+        }
+
+        cmd.status()
     }
 }
 
@@ -198,6 +349,7 @@ impl TestProcess {
             stdin: Arc::new(Mutex::new(Cursor::new(stdin.to_string()))),
             stdout: Arc::default(),
             stderr: Arc::default(),
+            event_sink: EventSink::default(),
         })
     }
 
@@ -258,4 +410,49 @@ pub struct TestContext {
     stdin: filesource::TestStdinInner,
     stdout: filesource::TestWriterInner,
     stderr: filesource::TestWriterInner,
+    event_sink: EventSink,
+}
+
+#[cfg(feature = "test")]
+impl TestContext {
+    /// Runs a child process as if it had been spawned by this process.
+    ///
+    /// The child sees this process' environment and working directory
+    /// rather than those of the test runner, and its output is captured into
+    /// this process' stdout and stderr.
+    pub(crate) fn run_child(&self, cmd: &mut Command) -> io::Result<ExitStatus> {
+        let explicit = cmd
+            .get_envs()
+            .map(|(k, v)| (k.to_owned(), v.map(OsStr::to_owned)))
+            .collect::<Vec<_>>();
+        cmd.env_clear().envs(&self.vars);
+        for (key, value) in explicit {
+            match value {
+                Some(value) => cmd.env(key, value),
+                None => cmd.env_remove(key),
+            };
+        }
+        if cmd.get_current_dir().is_none() {
+            cmd.current_dir(&self.cwd);
+        }
+
+        let mut input = Vec::new();
+        self.stdin.lock().unwrap().read_to_end(&mut input)?;
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Written on its own thread, as a child that fills its output before
+        // reading all of its input would otherwise never finish.
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = std::thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+        let output = child.wait_with_output()?;
+        let _ = writer.join();
+        self.stdout.lock().unwrap().extend(output.stdout);
+        self.stderr.lock().unwrap().extend(output.stderr);
+        Ok(output.status)
+    }
 }
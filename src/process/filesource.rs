@@ -37,6 +37,10 @@ pub trait Writer: Write + Send + Sync {
     /// filtering in the Terminal layer?)
     fn is_a_tty(&self, process: &Process) -> bool;
 
+    /// Query whether the TTY is a legacy Windows console, which shows escape
+    /// sequences instead of following them.
+    fn is_legacy_console(&self, process: &Process) -> bool;
+
     /// Construct a terminal on this writer.
     fn terminal(&self, process: &Process) -> ColorableTerminal;
 }
@@ -54,6 +58,10 @@ impl Writer for io::Stdout {
         }
     }
 
+    fn is_legacy_console(&self, process: &Process) -> bool {
+        StreamSelector::Stdout.is_legacy_console(process)
+    }
+
     fn lock(&self) -> Box<dyn WriterLock + '_> {
         Box::new(io::Stdout::lock(self))
     }
@@ -74,6 +82,10 @@ impl Writer for io::Stderr {
         }
     }
 
+    fn is_legacy_console(&self, process: &Process) -> bool {
+        StreamSelector::Stderr.is_legacy_console(process)
+    }
+
     fn lock(&self) -> Box<dyn WriterLock + '_> {
         Box::new(io::Stderr::lock(self))
     }
@@ -169,6 +181,10 @@ mod test_support {
             false
         }
 
+        fn is_legacy_console(&self, _: &Process) -> bool {
+            false
+        }
+
         fn lock(&self) -> Box<dyn WriterLock + '_> {
             Box::new(self.lock())
         }
@@ -1,11 +1,17 @@
 use std::{
+    fmt,
     io::{self, Write},
     mem::MaybeUninit,
     ops::DerefMut,
     ptr::addr_of_mut,
+    str::FromStr,
     sync::{Arc, Mutex, MutexGuard},
 };
 
+use anyhow::{anyhow, Result};
+use clap::{builder::PossibleValue, ValueEnum};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 pub(crate) use termcolor::Color;
 use termcolor::{ColorChoice, ColorSpec, StandardStream, StandardStreamLock, WriteColor};
 
@@ -42,6 +48,224 @@ impl StreamSelector {
             StreamSelector::TestTtyWriter(_) => true,
         }
     }
+
+    /// Whether the stream is a legacy Windows console, which shows escape
+    /// sequences instead of following them.
+    ///
+    /// The first time this is asked, virtual terminal processing is enabled
+    /// on the console, which only fails before Windows 10.
+    #[cfg(windows)]
+    pub(super) fn is_legacy_console(&self, process: &Process) -> bool {
+        use windows_sys::Win32::System::Console::{STD_ERROR_HANDLE, STD_OUTPUT_HANDLE};
+
+        let p = match process {
+            Process::OsProcess(p) => p,
+            #[cfg(feature = "test")]
+            Process::TestProcess(_) => return false,
+        };
+        match self {
+            StreamSelector::Stdout => {
+                p.stdout_is_a_tty
+                    && *p
+                        .stdout_legacy_console
+                        .get_or_init(|| enable_virtual_terminal(STD_OUTPUT_HANDLE))
+            }
+            StreamSelector::Stderr => {
+                p.stderr_is_a_tty
+                    && *p
+                        .stderr_legacy_console
+                        .get_or_init(|| enable_virtual_terminal(STD_ERROR_HANDLE))
+            }
+            #[cfg(feature = "test")]
+            StreamSelector::TestWriter(_) => false,
+            #[cfg(all(test, feature = "test"))]
+            StreamSelector::TestTtyWriter(_) => false,
+        }
+    }
+
+    #[cfg(not(windows))]
+    pub(super) fn is_legacy_console(&self, _: &Process) -> bool {
+        false
+    }
+}
+
+/// Enables virtual terminal processing on the console behind the standard
+/// `handle`, returning whether it is a legacy console that can't do it.
+///
+/// A handle that is not a console, such as the pipe of mintty, passes escape
+/// sequences on to the terminal. ConEmu follows them itself and says so with
+/// `ConEmuANSI`, even where the console it wraps can't.
+#[cfg(windows)]
+fn enable_virtual_terminal(handle: windows_sys::Win32::System::Console::STD_HANDLE) -> bool {
+    use windows_sys::Win32::System::Console::{
+        GetConsoleMode, GetStdHandle, SetConsoleMode, ENABLE_VIRTUAL_TERMINAL_PROCESSING,
+    };
+
+    // SAFETY: the mode is plain data, written by the call.
+    let enabled = unsafe {
+        let handle = GetStdHandle(handle);
+        let mut mode = 0;
+        if GetConsoleMode(handle, &mut mode) == 0 {
+            return false;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+            || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    };
+    !enabled && !std::env::var("ConEmuANSI").is_ok_and(|v| v == "ON")
+}
+
+/// How rustup decorates its output on a terminal.
+///
+/// The `plain` style never uses colors, nor redraws progress in place with
+/// cursor control sequences, which screen readers and some terminals don't
+/// cope with.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputStyle {
+    #[default]
+    Auto,
+    Plain,
+}
+
+impl OutputStyle {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Plain => "plain",
+        }
+    }
+}
+
+impl ValueEnum for OutputStyle {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Auto, Self::Plain]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.as_str()))
+    }
+
+    fn from_str(input: &str, _: bool) -> Result<Self, String> {
+        <Self as FromStr>::from_str(input).map_err(|e| e.to_string())
+    }
+}
+
+impl FromStr for OutputStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(style: &str) -> Result<Self> {
+        match style {
+            "auto" => Ok(Self::Auto),
+            "plain" => Ok(Self::Plain),
+            _ => Err(anyhow!(
+                "unknown output style: '{}'; valid styles are {}",
+                style,
+                Self::value_variants().iter().join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The colors rustup uses on a terminal.
+///
+/// The `light` palette swaps the colors that are hard to read on a light
+/// background, such as the yellow of warnings, for darker ones.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Palette {
+    #[default]
+    Default,
+    Light,
+}
+
+impl Palette {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Light => "light",
+        }
+    }
+
+    /// The color shown for `color` in this palette.
+    pub(crate) fn fg(&self, color: Color) -> Color {
+        match (self, color) {
+            (Self::Light, Color::Yellow) => Color::Magenta,
+            (Self::Light, Color::Cyan) => Color::Blue,
+            (Self::Light, Color::White) => Color::Black,
+            _ => color,
+        }
+    }
+}
+
+impl ValueEnum for Palette {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Default, Self::Light]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.as_str()))
+    }
+
+    fn from_str(input: &str, _: bool) -> Result<Self, String> {
+        <Self as FromStr>::from_str(input).map_err(|e| e.to_string())
+    }
+}
+
+impl FromStr for Palette {
+    type Err = anyhow::Error;
+
+    fn from_str(palette: &str) -> Result<Self> {
+        match palette {
+            "default" => Ok(Self::Default),
+            "light" => Ok(Self::Light),
+            _ => Err(anyhow!(
+                "unknown palette: '{}'; valid palettes are {}",
+                palette,
+                Self::value_variants().iter().join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The output style and palette, from the environment or else the settings.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct TermSettings {
+    pub(crate) style: OutputStyle,
+    pub(crate) palette: Palette,
+}
+
+/// Whether to color a stream, as chosen by the environment.
+///
+/// `RUSTUP_TERM_COLOR` set to `always` or `never` comes first. Otherwise a
+/// non-empty `NO_COLOR` disables colors, and a `CLICOLOR_FORCE` other than `0`
+/// enables them even when the stream is not a tty.
+pub(crate) fn color_choice(is_a_tty: bool, process: &Process) -> ColorChoice {
+    match process.var("RUSTUP_TERM_COLOR") {
+        Ok(s) if s.eq_ignore_ascii_case("always") => return ColorChoice::Always,
+        Ok(s) if s.eq_ignore_ascii_case("never") => return ColorChoice::Never,
+        _ => {}
+    }
+    match process.var("NO_COLOR") {
+        Ok(s) if !s.is_empty() => return ColorChoice::Never,
+        _ => {}
+    }
+    match process.var("CLICOLOR_FORCE") {
+        Ok(s) if !s.is_empty() && s != "0" => ColorChoice::Always,
+        _ if is_a_tty => ColorChoice::Auto,
+        _ => ColorChoice::Never,
+    }
 }
 
 /// A colorable terminal that can be written to
@@ -53,6 +277,7 @@ pub struct ColorableTerminal {
     // source is important because otherwise parallel constructed terminals
     // would not be locked out.
     inner: Arc<Mutex<TerminalInner>>,
+    palette: Palette,
 }
 
 /// Internal state for ColorableTerminal
@@ -79,17 +304,24 @@ enum TerminalInnerLocked {
 
 impl ColorableTerminal {
     /// A terminal that supports colorisation of a stream.
-    /// If `RUSTUP_TERM_COLOR` is set to `always`, or if the stream is a tty and
-    /// `RUSTUP_TERM_COLOR` either unset or set to `auto`,
-    /// then color commands will be sent to the stream.
-    /// Otherwise color commands are discarded.
+    /// If [`color_choice`] enables colors for the stream, color commands are
+    /// sent to it in the chosen palette.
+    /// Otherwise, or with the plain output style, color commands are discarded.
     pub(super) fn new(stream: StreamSelector, process: &Process) -> Self {
-        let choice = match process.var("RUSTUP_TERM_COLOR") {
-            Ok(s) if s.eq_ignore_ascii_case("always") => ColorChoice::Always,
-            Ok(s) if s.eq_ignore_ascii_case("never") => ColorChoice::Never,
-            _ if stream.is_a_tty(process) => ColorChoice::Auto,
-            _ => ColorChoice::Never,
+        let choice = color_choice(stream.is_a_tty(process), process);
+        // The output style and palette may have to be read from the settings,
+        // so they are only looked up when colors would be used.
+        let (choice, palette) = match choice {
+            ColorChoice::Never => (choice, Palette::Default),
+            _ if process.plain_output() => (ColorChoice::Never, Palette::Default),
+            _ => (choice, process.palette()),
         };
+        // Colors are sent as escape sequences once virtual terminal processing
+        // is enabled on a Windows console, and through the console API on a
+        // legacy console that can't enable it.
+        if choice != ColorChoice::Never {
+            stream.is_legacy_console(process);
+        }
         let inner = match stream {
             StreamSelector::Stdout => {
                 TerminalInner::StandardStream(StandardStream::stdout(choice), ColorSpec::new())
@@ -104,6 +336,7 @@ impl ColorableTerminal {
         };
         ColorableTerminal {
             inner: Arc::new(Mutex::new(inner)),
+            palette,
         }
     }
 
@@ -138,7 +371,7 @@ impl ColorableTerminal {
     pub fn fg(&mut self, color: Color) -> io::Result<()> {
         match self.inner.lock().unwrap().deref_mut() {
             TerminalInner::StandardStream(s, spec) => {
-                spec.set_fg(Some(color));
+                spec.set_fg(Some(self.palette.fg(color)));
                 s.set_color(spec)
             }
             #[cfg(feature = "test")]
@@ -151,7 +384,7 @@ impl ColorableTerminal {
             TerminalInner::StandardStream(s, spec) => {
                 match attr {
                     Attr::Bold => spec.set_bold(true),
-                    Attr::ForegroundColor(color) => spec.set_fg(Some(color)),
+                    Attr::ForegroundColor(color) => spec.set_fg(Some(self.palette.fg(color))),
                 };
                 s.set_color(spec)
             }
@@ -269,4 +502,69 @@ mod tests {
             ColorChoice::Never,
         );
     }
+
+    #[test]
+    fn no_color_and_clicolor_force() {
+        fn assert_color_choice(vars: &[(&str, &str)], is_a_tty: bool, expected: ColorChoice) {
+            let mut env = HashMap::new();
+            for (key, value) in vars {
+                env.env(key, value);
+            }
+            let tp = TestProcess::with_vars(env);
+            assert_eq!(color_choice(is_a_tty, &tp.process), expected);
+        }
+
+        assert_color_choice(&[("NO_COLOR", "1")], true, ColorChoice::Never);
+        // An empty `NO_COLOR` is ignored.
+        assert_color_choice(&[("NO_COLOR", "")], true, ColorChoice::Auto);
+        assert_color_choice(&[("CLICOLOR_FORCE", "1")], false, ColorChoice::Always);
+        assert_color_choice(&[("CLICOLOR_FORCE", "0")], false, ColorChoice::Never);
+        assert_color_choice(
+            &[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")],
+            false,
+            ColorChoice::Never,
+        );
+        // `RUSTUP_TERM_COLOR` is prioritized over both.
+        assert_color_choice(
+            &[("RUSTUP_TERM_COLOR", "always"), ("NO_COLOR", "1")],
+            false,
+            ColorChoice::Always,
+        );
+        assert_color_choice(
+            &[("RUSTUP_TERM_COLOR", "never"), ("CLICOLOR_FORCE", "1")],
+            true,
+            ColorChoice::Never,
+        );
+    }
+
+    #[test]
+    fn light_palette() {
+        let mut vars = HashMap::new();
+        vars.env("RUSTUP_PALETTE", "light");
+        let tp = TestProcess::with_vars(vars);
+
+        let palette = tp.process.palette();
+        assert_eq!(palette, Palette::Light);
+        assert_eq!(palette.fg(Color::Yellow), Color::Magenta);
+        assert_eq!(palette.fg(Color::Red), Color::Red);
+        assert_eq!(Palette::Default.fg(Color::Yellow), Color::Yellow);
+    }
+
+    #[test]
+    fn plain_output_has_no_colors() {
+        let mut vars = HashMap::new();
+        vars.env("RUSTUP_TERM_COLOR", "always");
+        vars.env("RUSTUP_OUTPUT_STYLE", "plain");
+        let tp = TestProcess::with_vars(vars);
+
+        let term = ColorableTerminal::new(
+            StreamSelector::TestTtyWriter(Default::default()),
+            &tp.process,
+        );
+        let inner = term.inner.lock().unwrap();
+        assert!(matches!(
+            &*inner,
+            &TerminalInner::TestWriter(_, ColorChoice::Never)
+        ));
+    }
 }
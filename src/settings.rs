@@ -1,16 +1,24 @@
 use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::fmt;
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::cli::self_update::SelfUpdateMode;
-use crate::dist::Profile;
+use crate::cli::{
+    crash_report::CrashReportMode,
+    proxy_mode::AutoInstallMode,
+    self_update::{ProxyLinks, SelfUpdateMode, SystemRust},
+};
+use crate::diskio::{storage::Storage, IoProfile};
+use crate::dist::{trust::TrustedKey, Profile};
 use crate::errors::*;
 use crate::notifications::*;
+use crate::process::terminalsource::{OutputStyle, Palette};
 use crate::utils;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -29,12 +37,26 @@ impl SettingsFile {
 
     fn write_settings(&self) -> Result<()> {
         let settings = self.cache.borrow();
-        utils::write_file(
-            "settings",
-            &self.path,
-            &settings.as_ref().unwrap().stringify()?,
-        )?;
-        Ok(())
+        let contents = settings.as_ref().unwrap().stringify()?;
+        // On a network file system, a rustup on another machine sharing the
+        // home directory could read the file while it is rewritten in
+        // place, so write a new file and rename it over the old one.
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        if Storage::of(dir) != Storage::Network {
+            return utils::write_file("settings", &self.path, &contents);
+        }
+        let new_path = self
+            .path
+            .with_extension(format!("toml.{}", utils::raw::random_string(8)));
+        utils::write_file("settings", &new_path, &contents)?;
+        fs::rename(&new_path, &self.path)
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&new_path);
+            })
+            .with_context(|| RustupError::WritingFile {
+                name: "settings",
+                path: self.path.clone(),
+            })
     }
 
     fn read_settings(&self) -> Result<()> {
@@ -43,12 +65,24 @@ impl SettingsFile {
             let b = self.cache.borrow();
             if b.is_none() {
                 drop(b);
-                *self.cache.borrow_mut() = Some(if utils::is_file(&self.path) {
-                    let content = utils::read_file("settings", &self.path)?;
-                    Settings::parse(&content).with_context(|| RustupError::ParsingFile {
-                        name: "settings",
-                        path: self.path.clone(),
-                    })?
+                *self.cache.borrow_mut() = Some(if let Ok(meta) = fs::metadata(&self.path) {
+                    let stamp = CacheStamp::of(&meta);
+                    match stamp.as_ref().and_then(|stamp| self.read_cache(stamp)) {
+                        Some(settings) => settings,
+                        None => {
+                            let content = utils::read_file("settings", &self.path)?;
+                            let settings = Settings::parse(&content).with_context(|| {
+                                RustupError::ParsingFile {
+                                    name: "settings",
+                                    path: self.path.clone(),
+                                }
+                            })?;
+                            if let Some(stamp) = stamp.filter(|stamp| !stamp.is_recent()) {
+                                self.write_cache(&stamp, &settings);
+                            }
+                            settings
+                        }
+                    }
                 } else {
                     needs_save = true;
                     Default::default()
@@ -61,6 +95,32 @@ impl SettingsFile {
         Ok(())
     }
 
+    /// The settings cached for `settings.toml` with the given stamp, if any.
+    ///
+    /// Proxies read the settings on every run, and parsing TOML takes a
+    /// good part of their startup, so the settings are also kept as JSON,
+    /// which is much quicker to load.
+    fn read_cache(&self, stamp: &CacheStamp) -> Option<Settings> {
+        let data = fs::read(self.path.with_extension("cache")).ok()?;
+        let json = data
+            .strip_prefix(stamp.to_string().as_bytes())?
+            .strip_prefix(b"\n")?;
+        serde_json::from_slice(json).ok()
+    }
+
+    fn write_cache(&self, stamp: &CacheStamp, settings: &Settings) {
+        let Ok(json) = serde_json::to_string(settings) else {
+            return;
+        };
+        // The cache is only an optimization, so failing to write it, for
+        // example to a read-only home, is no problem. A reader that sees it
+        // half-written fails to parse it and falls back to the TOML.
+        let _ = fs::write(
+            self.path.with_extension("cache"),
+            format!("{stamp}\n{json}"),
+        );
+    }
+
     pub(crate) fn with<T, F: FnOnce(&Settings) -> Result<T>>(&self, f: F) -> Result<T> {
         self.read_settings()?;
 
@@ -78,6 +138,44 @@ impl SettingsFile {
     }
 }
 
+/// Identifies a version of a file, such as `settings.toml`, by its size and
+/// modification time, which change whenever the file is written.
+#[derive(Debug, PartialEq)]
+pub(crate) struct CacheStamp {
+    pub(crate) len: u64,
+    pub(crate) modified: SystemTime,
+}
+
+impl CacheStamp {
+    pub(crate) fn of(meta: &fs::Metadata) -> Option<Self> {
+        Some(Self {
+            len: meta.len(),
+            modified: meta.modified().ok()?,
+        })
+    }
+
+    /// Whether the file was modified so recently that a change of the same
+    /// size could follow without changing the modification time, on file
+    /// systems that only record it to the second. Such a version isn't
+    /// cached, like git does with its index.
+    pub(crate) fn is_recent(&self) -> bool {
+        SystemTime::now()
+            .duration_since(self.modified)
+            .map_or(true, |age| age < Duration::from_secs(2))
+    }
+}
+
+impl fmt::Display for CacheStamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let modified = self
+            .modified
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        write!(f, "{} {modified}", self.len)
+    }
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Settings {
     pub version: MetadataVersion,
@@ -93,6 +191,159 @@ pub struct Settings {
     pub pgp_keys: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub auto_self_update: Option<SelfUpdateMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crash_reports: Option<CrashReportMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_style: Option<OutputStyle>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette: Option<Palette>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_install: Option<AutoInstallMode>,
+    /// How proxies are linked to the rustup binary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_links: Option<ProxyLinks>,
+    /// Where the proxies are installed, if not in `CARGO_HOME/bin`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_dir: Option<PathBuf>,
+    /// Dist servers tried in turn for a manifest or component that the one
+    /// before doesn't have, or that can't be downloaded from it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dist_servers: Vec<String>,
+    /// Caches on the local network to fetch components from before the
+    /// dist server.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cache_peers: Vec<String>,
+    /// Updates that download more than this many bytes ask for confirmation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirm_downloads_over: Option<u64>,
+    /// A program run on each ELF file an update installs, e.g. to patch
+    /// it to run on NixOS.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_install_hook: Option<String>,
+    /// Whether files identical between toolchains are hard-linked to one
+    /// copy in `RUSTUP_HOME/store`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dedup: bool,
+    /// Toolchains unused for this many days are removed by
+    /// `rustup toolchain gc`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toolchain_retention_days: Option<u64>,
+    /// How much memory and how many threads unpacking may use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io_profile: Option<IoProfile>,
+    /// How many components are downloaded at once.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_downloads: Option<usize>,
+    /// Where the root certificates trusted by downloads come from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_root_source: Option<TlsRootSource>,
+    /// TLS settings of single servers, by host name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tls_hosts: BTreeMap<String, TlsHost>,
+    /// A Rust installation found in `PATH` when rustup was installed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_rust: Option<SystemRust>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pins: BTreeMap<String, Pin>,
+    /// Keys that manifests must be signed with, by dist server.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub trusted_keys: BTreeMap<String, Vec<TrustedKey>>,
+    /// Variables set for the tools of a toolchain, by its full name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub toolchain_env: BTreeMap<String, BTreeMap<String, String>>,
+    /// Variables set for the tools of the toolchain a directory override
+    /// makes active, keyed like `overrides`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub override_env: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// A toolchain that `rustup update` leaves alone.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Pin {
+    /// The `YYYY-MM-DD` date after which rustup warns about the pin.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+}
+
+/// Where the root certificates trusted by downloads come from, stored as
+/// `platform`, `bundled` or the path of a PEM file.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum TlsRootSource {
+    #[default]
+    Platform,
+    Bundled,
+    File(PathBuf),
+}
+
+impl FromStr for TlsRootSource {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        match source {
+            "platform" => Ok(Self::Platform),
+            "bundled" => Ok(Self::Bundled),
+            "" => Err(anyhow!(
+                "TLS root source must be 'platform', 'bundled' or the path of a PEM file"
+            )),
+            path => Ok(Self::File(PathBuf::from(path))),
+        }
+    }
+}
+
+impl TryFrom<String> for TlsRootSource {
+    type Error = anyhow::Error;
+
+    fn try_from(source: String) -> Result<Self> {
+        source.parse()
+    }
+}
+
+impl From<TlsRootSource> for String {
+    fn from(source: TlsRootSource) -> Self {
+        source.to_string()
+    }
+}
+
+impl fmt::Display for TlsRootSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Platform => write!(f, "platform"),
+            Self::Bundled => write!(f, "bundled"),
+            Self::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl From<TlsRootSource> for download::TlsRoots {
+    fn from(source: TlsRootSource) -> Self {
+        match source {
+            TlsRootSource::Platform => Self::Platform,
+            TlsRootSource::Bundled => Self::Bundled,
+            TlsRootSource::File(path) => Self::File(path),
+        }
+    }
+}
+
+/// TLS settings of a single server, such as a mirror behind a proxy that
+/// intercepts TLS.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TlsHost {
+    /// A PEM file of roots trusted for the server besides the others.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roots: Option<PathBuf>,
+    /// Don't verify the server's certificate at all.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub insecure: bool,
+}
+
+impl From<TlsHost> for download::TlsHost {
+    fn from(host: TlsHost) -> Self {
+        Self {
+            roots: host.roots,
+            insecure: host.insecure,
+        }
+    }
 }
 
 impl Settings {
@@ -115,6 +366,26 @@ impl Settings {
         self.overrides.remove(&key).is_some()
     }
 
+    /// The keys of the overrides whose directory no longer exists.
+    pub(crate) fn stale_overrides(&self) -> Vec<String> {
+        self.overrides
+            .keys()
+            .filter(|key| !Path::new(key).is_dir())
+            .cloned()
+            .collect()
+    }
+
+    /// Removes the overrides whose directory no longer exists, along with
+    /// their variables, returning their keys.
+    pub(crate) fn remove_stale_overrides(&mut self) -> Vec<String> {
+        let stale = self.stale_overrides();
+        for key in &stale {
+            self.overrides.remove(key);
+        }
+        self.override_env.retain(|key, _| Path::new(key).is_dir());
+        stale
+    }
+
     pub(crate) fn add_override(
         &mut self,
         path: &Path,
@@ -135,6 +406,15 @@ impl Settings {
         self.overrides.get(&key).cloned()
     }
 
+    pub(crate) fn dir_override_env(
+        &self,
+        dir: &Path,
+        notify_handler: &dyn Fn(Notification<'_>),
+    ) -> Option<&BTreeMap<String, String>> {
+        let key = Self::path_to_key(dir, notify_handler);
+        self.override_env.get(&key)
+    }
+
     pub(crate) fn parse(data: &str) -> Result<Self> {
         toml::from_str(data).context("error parsing settings")
     }
@@ -145,7 +425,7 @@ impl Settings {
 }
 
 #[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
-pub(crate) enum MetadataVersion {
+pub enum MetadataVersion {
     #[serde(rename = "2")]
     V2,
     #[serde(rename = "12")]
@@ -228,6 +508,102 @@ mod tests {
         assert_eq!(settings.profile, Some(Profile::Default));
     }
 
+    #[test]
+    fn pins_roundtrip() {
+        let mut settings = Settings::default();
+        settings.pins.insert(
+            "nightly-2024-03-14-x86_64-unknown-linux-gnu".to_owned(),
+            Pin {
+                until: Some("2024-09-01".to_owned()),
+            },
+        );
+        settings
+            .pins
+            .insert("stable-x86_64-unknown-linux-gnu".to_owned(), Pin::default());
+
+        let parsed = Settings::parse(&settings.stringify().unwrap()).unwrap();
+        assert_eq!(parsed, settings);
+    }
+
+    #[test]
+    fn env_tables() {
+        let settings = Settings::parse(
+            r#"version = "12"
+
+[toolchain_env.nightly-x86_64-unknown-linux-gnu]
+RUSTFLAGS = "-Zthreads=8"
+
+[override_env."/home/me/project"]
+RUSTC_WRAPPER = "sccache"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            settings.toolchain_env["nightly-x86_64-unknown-linux-gnu"]["RUSTFLAGS"],
+            "-Zthreads=8"
+        );
+        assert_eq!(
+            settings.override_env["/home/me/project"]["RUSTC_WRAPPER"],
+            "sccache"
+        );
+        let parsed = Settings::parse(&settings.stringify().unwrap()).unwrap();
+        assert_eq!(parsed, settings);
+    }
+
+    #[test]
+    fn stale_overrides_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let here = dir.path().display().to_string();
+        let gone = dir.path().join("gone").display().to_string();
+        let mut settings = Settings::default();
+        for key in [&here, &gone] {
+            settings.overrides.insert(key.clone(), "nightly".to_owned());
+            settings.override_env.insert(key.clone(), BTreeMap::new());
+        }
+
+        assert_eq!(settings.stale_overrides(), [gone.as_str()]);
+        assert_eq!(settings.remove_stale_overrides(), [gone]);
+        assert_eq!(settings.overrides.keys().collect::<Vec<_>>(), [&here]);
+        assert_eq!(settings.override_env.keys().collect::<Vec<_>>(), [&here]);
+        assert!(settings.remove_stale_overrides().is_empty());
+    }
+
+    #[test]
+    fn cache_follows_settings_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        let cache = dir.path().join("settings.cache");
+        let write = |toml: &str| {
+            fs::write(&path, toml).unwrap();
+            let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+            fs::File::options()
+                .append(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(an_hour_ago)
+                .unwrap();
+        };
+        let default_toolchain =
+            |file: SettingsFile| file.with(|s| Ok(s.default_toolchain.clone())).unwrap();
+
+        write(BASIC);
+        let stable = Some("stable-aarch64-apple-darwin".to_owned());
+        assert_eq!(default_toolchain(SettingsFile::new(path.clone())), stable);
+        assert!(cache.exists());
+        assert_eq!(default_toolchain(SettingsFile::new(path.clone())), stable);
+
+        // A changed file is parsed again.
+        write(&BASIC.replace("stable", "beta"));
+        let beta = Some("beta-aarch64-apple-darwin".to_owned());
+        assert_eq!(default_toolchain(SettingsFile::new(path.clone())), beta);
+
+        // As is one changed just now, which isn't cached.
+        fs::remove_file(&cache).unwrap();
+        fs::write(&path, BASIC).unwrap();
+        assert_eq!(default_toolchain(SettingsFile::new(path.clone())), stable);
+        assert!(!cache.exists());
+    }
+
     const BASIC: &str = r#"version = "12"
 default_toolchain = "stable-aarch64-apple-darwin"
 profile = "default"
@@ -16,11 +16,12 @@ use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[cfg(test)]
-use anyhow::Result;
+use anyhow::{Context, Result};
 
-use crate::dist::TargetTriple;
+use crate::dist::manifest_cache::{self, ManifestCache};
+use crate::dist::{TargetTriple, ToolchainDesc};
 use crate::process::TestProcess;
 
 #[cfg(windows)]
@@ -56,6 +57,11 @@ impl Env for HashMap<String, String> {
     }
 }
 
+/// Loads the settings file at `path` the way every rustup run does.
+pub fn load_settings(path: &Path) -> anyhow::Result<()> {
+    crate::settings::SettingsFile::new(path.to_owned()).with(|_| Ok(()))
+}
+
 /// The path to a dir for this test binaries state
 fn exe_test_dir() -> io::Result<PathBuf> {
     let current_exe_path = env::current_exe().unwrap();
@@ -212,6 +218,35 @@ impl RustupHome {
     pub fn remove(&self) -> io::Result<()> {
         remove_dir_all::remove_dir_all(&self.rustupdir)
     }
+
+    /// Makes the toolchain `name` look as if it was last installed or updated
+    /// at `updated`.
+    pub fn set_toolchain_updated(&self, name: &str, updated: SystemTime) -> Result<()> {
+        let desc = name.parse::<ToolchainDesc>()?;
+        let store = manifest_cache::default_store(&self.rustupdir);
+        let key = desc.manifest_name();
+        let mut entry = store
+            .entry(&key)?
+            .with_context(|| format!("no manifest cache entry for '{name}'"))?;
+        entry
+            .installed
+            .get_mut(&desc.target.to_string())
+            .with_context(|| format!("'{name}' isn't installed"))?
+            .updated = updated.duration_since(UNIX_EPOCH)?.as_secs();
+        store.set_entry(&key, &entry)
+    }
+
+    /// Forgets which manifest the toolchain `name` was installed from, as if
+    /// the record of it were lost.
+    pub fn forget_installed_manifest(&self, name: &str) -> Result<()> {
+        let cache = ManifestCache::new(
+            manifest_cache::default_store(&self.rustupdir),
+            Duration::ZERO,
+            self.join("update-hashes"),
+        );
+        cache.remove_installed(&name.parse()?)?;
+        Ok(())
+    }
 }
 
 impl fmt::Display for RustupHome {
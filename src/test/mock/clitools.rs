@@ -20,7 +20,8 @@ use enum_map::{enum_map, Enum, EnumMap};
 use tempfile::TempDir;
 use url::Url;
 
-use crate::cli::rustup_mode;
+use crate::cli::dispatch;
+use crate::is_proxyable_tools;
 use crate::process;
 use crate::test as rustup_test;
 use crate::test::const_dist_dir;
@@ -30,7 +31,7 @@ use crate::utils;
 use super::{
     dist::{
         change_channel_date, MockChannel, MockComponent, MockDistServer, MockManifestVersion,
-        MockPackage, MockTargetedPackage,
+        MockPackage, MockTargetedPackage, SIGNED_MARKER,
     },
     topical_doc_data, MockComponentBuilder, MockFile, MockInstallerBuilder,
 };
@@ -85,6 +86,9 @@ pub enum Scenario {
     SimpleV2,
     /// One date, v1 manifests
     SimpleV1,
+    /// One date, v2 manifests declaring the size of every package and the
+    /// tier of its target
+    SizedV2,
     /// One date, v2 manifests, MULTI_ARCH1 host
     MultiHost,
     /// One date, v2 manifests, beta with tag
@@ -157,6 +161,7 @@ impl ConstState {
                 Scenario::RemovedRls => RwLock::new(None),
                 Scenario::SimpleV1 => RwLock::new(None),
                 Scenario::SimpleV2 => RwLock::new(None),
+                Scenario::SizedV2 => RwLock::new(None),
                 Scenario::Unavailable => RwLock::new(None),
                 Scenario::UnavailableRls => RwLock::new(None),
             },
@@ -567,13 +572,24 @@ impl Config {
         // cargo, so we disable that check globally
         cmd.env("RUSTUP_INIT_SKIP_PATH_CHECK", "yes");
 
-        // Setup pgp test key
-        cmd.env(
-            "RUSTUP_PGP_KEY",
-            std::env::current_dir()
-                .unwrap()
-                .join("tests/mock/signing-key.pub.asc"),
-        );
+        // The tests expect the messages in English, whatever the locale
+        cmd.env("RUSTUP_LANG", "en-US");
+
+        // Setup pgp test key, for the dist servers whose manifests are signed
+        // with it
+        if distdir.join(SIGNED_MARKER).exists() {
+            cmd.env(
+                "RUSTUP_PGP_KEY",
+                std::env::current_dir()
+                    .unwrap()
+                    .join("tests/mock/signing-key.pub.asc"),
+            );
+        }
+
+        // Running the tests under sudo mustn't make rustup warn about it
+        for var in ["SUDO_UID", "SUDO_GID", "SUDO_USER"] {
+            cmd.env(var, "");
+        }
 
         // The unix fallback settings file may be present in the test environment, so override
         // the path to the settings file with a non-existing path to avoid interference
@@ -740,7 +756,7 @@ impl Config {
         I: IntoIterator<Item = A> + Clone + Debug,
         A: AsRef<OsStr>,
     {
-        let inprocess = allow_inprocess(name, args.clone());
+        let inprocess = self.allow_inprocess(name, args.clone());
         let start = Instant::now();
         let out = if inprocess {
             self.run_inprocess(name, args.clone(), env).await
@@ -750,6 +766,7 @@ impl Config {
         let duration = Instant::now() - start;
         let output = SanitizedOutput {
             ok: matches!(out.status, Some(0)),
+            status: out.status,
             stdout: String::from_utf8(out.stdout).unwrap(),
             stderr: String::from_utf8(out.stderr).unwrap(),
         };
@@ -764,6 +781,40 @@ impl Config {
         output
     }
 
+    fn allow_inprocess<I, A>(&self, name: &str, args: I) -> bool
+    where
+        I: IntoIterator<Item = A>,
+        A: AsRef<OsStr>,
+    {
+        // Proxies can run in-process as long as they have been installed, as
+        // the shell would otherwise fail to find them. Everything else that
+        // isn't the rustup alias still runs in a subprocess:
+        // - -init performs self-update which monkey with global external state.
+        // - self update executions cannot run in-process because on windows the
+        //    process replacement dance would replace the test process.
+        if name != "rustup" {
+            return args.into_iter().all(|arg| arg.as_ref().to_str().is_some())
+                && is_proxyable_tools(name).is_ok()
+                && self.exedir.join(format!("{name}{EXE_SUFFIX}")).exists();
+        }
+        let mut is_update = false;
+        let mut no_self_update = false;
+        let mut self_cmd = false;
+        for arg in args {
+            // The in-process harness only deals in UTF-8 arguments.
+            if arg.as_ref().to_str().is_none() {
+                return false;
+            } else if arg.as_ref() == "update" {
+                is_update = true;
+            } else if arg.as_ref() == "--no-self-update" {
+                no_self_update = true;
+            } else if arg.as_ref() == "self" {
+                self_cmd = true;
+            }
+        }
+        !(self_cmd || (is_update && !no_self_update))
+    }
+
     #[tracing::instrument(level = "trace", skip_all)]
     pub(crate) async fn run_inprocess<I, A>(
         &self,
@@ -793,12 +844,11 @@ impl Config {
         }
 
         let tp = process::TestProcess::new(&*self.workdir.borrow(), &arg_strings, vars, "");
-        let process_res = rustup_mode::main(
-            tp.process.current_dir().unwrap(),
-            &tp.process,
-            tp.console_filter.clone(),
-        )
-        .await;
+        // As in the binary, proxies take the thin way in.
+        let process_res = match dispatch::is_proxy(&tp.process) {
+            true => dispatch::proxy(&tp.process, tp.console_filter.clone()).await,
+            false => dispatch::main(&tp.process, tp.console_filter.clone()).await,
+        };
         // convert Err's into an ec
         let ec = match process_res {
             Ok(process_res) => process_res,
@@ -901,6 +951,7 @@ pub struct Output {
 #[derive(Debug)]
 pub struct SanitizedOutput {
     pub ok: bool,
+    pub status: Option<i32>,
     pub stdout: String,
     pub stderr: String,
 }
@@ -917,43 +968,6 @@ pub fn env<E: rustup_test::Env>(config: &Config, cmd: &mut E) {
     config.env(cmd)
 }
 
-fn allow_inprocess<I, A>(name: &str, args: I) -> bool
-where
-    I: IntoIterator<Item = A>,
-    A: AsRef<OsStr>,
-{
-    // Only the rustup alias is currently ready for in-process testing:
-    // - -init performs self-update which monkey with global external state.
-    // - proxies themselves behave appropriately the proxied output needs to be
-    //   collected for assertions to be made on it as our tests traverse layers.
-    // - self update executions cannot run in-process because on windows the
-    //    process replacement dance would replace the test process.
-    // - any command with --version in it is testing to see something was
-    //   installed properly, so we have to shell out to it to be sure
-    if name != "rustup" {
-        return false;
-    }
-    let mut is_update = false;
-    let mut no_self_update = false;
-    let mut self_cmd = false;
-    let mut run = false;
-    let mut version = false;
-    for arg in args {
-        if arg.as_ref() == "update" {
-            is_update = true;
-        } else if arg.as_ref() == "--no-self-update" {
-            no_self_update = true;
-        } else if arg.as_ref() == "self" {
-            self_cmd = true;
-        } else if arg.as_ref() == "run" {
-            run = true;
-        } else if arg.as_ref() == "--version" {
-            version = true;
-        }
-    }
-    !(run || self_cmd || version || (is_update && !no_self_update))
-}
-
 #[derive(Copy, Clone, Eq, PartialEq)]
 enum RlsStatus {
     Available,
@@ -1164,7 +1178,7 @@ fn create_mock_dist_server(path: &Path, s: Scenario) {
             Release::stable("1.78.0", "2024-05-01"),
             Release::stable("1.79.0", "2024-06-15").with_rls(RlsStatus::Unavailable),
         ],
-        Scenario::SimpleV1 | Scenario::SimpleV2 => vec![
+        Scenario::SimpleV1 | Scenario::SimpleV2 | Scenario::SizedV2 => vec![
             Release::new("nightly", "1.3.0", "2015-01-02", "2").with_rls(RlsStatus::Renamed),
             Release::beta("1.2.0", "2015-01-02"),
             Release::stable("1.1.0", "2015-01-02"),
@@ -1204,6 +1218,7 @@ fn create_mock_dist_server(path: &Path, s: Scenario) {
         Scenario::Full => vec![MockManifestVersion::V1, MockManifestVersion::V2],
         Scenario::SimpleV1 | Scenario::ArchivesV1 => vec![MockManifestVersion::V1],
         Scenario::SimpleV2
+        | Scenario::SizedV2
         | Scenario::ArchivesV2
         | Scenario::ArchivesV2_2015_01_01
         | Scenario::ArchivesV2TwoVersions
@@ -1223,7 +1238,7 @@ fn create_mock_dist_server(path: &Path, s: Scenario) {
         path: path.to_owned(),
         channels: chans.iter().map(|c| c.mock()).collect(),
     }
-    .write(&vs, true, true);
+    .write(&vs, true, true, s == Scenario::SizedV2);
 
     for chan in &chans {
         chan.link(path)
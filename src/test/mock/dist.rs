@@ -5,6 +5,7 @@ use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::sync::{LazyLock, Mutex};
 
 use sha2::{Digest, Sha256};
@@ -18,8 +19,9 @@ use crate::dist::{
     Profile, TargetTriple,
 };
 
-use super::clitools::hard_link;
+use super::clitools::{hard_link, CROSS_ARCH1, CROSS_ARCH2};
 use super::MockInstallerBuilder;
+use crate::test::this_host_triple;
 
 // This function changes the mock manifest for a given channel to that
 // of a particular date. For advancing the build from e.g. 2016-02-1
@@ -68,6 +70,74 @@ pub fn change_channel_date(dist_server: &Url, channel: &str, date: &str) {
     }
 }
 
+/// The secret half of `tests/mock/signing-key.pub.asc`, that v2 manifests
+/// are signed with where gpg is installed.
+const SIGNING_KEY: &str = include_str!("../../../tests/mock/signing-key.asc");
+
+/// A file in the root of a mock dist server whose v2 manifests are signed
+/// with the test key.
+pub const SIGNED_MARKER: &str = "signed-with-test-key";
+
+/// A gpg home with the test key imported, to sign manifests with.
+struct Signer {
+    home: tempfile::TempDir,
+}
+
+impl Signer {
+    /// Returns `None` where gpg can't be run.
+    fn new() -> Option<Self> {
+        let signer = Self {
+            home: tempfile::Builder::new()
+                .prefix("rustup-gpg")
+                .tempdir()
+                .ok()?,
+        };
+        let key = signer.home.path().join("signing-key.asc");
+        fs::write(&key, SIGNING_KEY).ok()?;
+        signer
+            .gpg()
+            .arg("--import")
+            .arg(&key)
+            .status()
+            .ok()?
+            .success()
+            .then_some(signer)
+    }
+
+    fn gpg(&self) -> Command {
+        let mut cmd = Command::new("gpg");
+        cmd.args(["--batch", "--quiet", "--no-permission-warning", "--homedir"])
+            .arg(self.home.path());
+        cmd
+    }
+
+    /// Writes the signature of `path` to `<path>.asc`.
+    fn sign(&self, path: &Path) {
+        // gpg would write through the hard link to an older signature.
+        let mut signature = path.as_os_str().to_owned();
+        signature.push(".asc");
+        let _ = fs::remove_file(&signature);
+        let status = self
+            .gpg()
+            .args(["--armor", "--detach-sign"])
+            .arg(path)
+            .status()
+            .unwrap();
+        assert!(status.success(), "could not sign '{}'", path.display());
+    }
+}
+
+impl Drop for Signer {
+    fn drop(&mut self) {
+        // Don't leave an agent behind for every mock dist server.
+        let _ = Command::new("gpgconf")
+            .arg("--homedir")
+            .arg(self.home.path())
+            .args(["--kill", "gpg-agent"])
+            .status();
+    }
+}
+
 // The manifest version created by this mock
 pub const MOCK_MANIFEST_VERSION: &str = "2";
 
@@ -130,11 +200,36 @@ pub enum MockManifestVersion {
     V2,
 }
 
+/// The support tier declared for a mock target. The second cross target has
+/// none, like targets that a manifest doesn't know the tier of.
+fn mock_tier(target: &str) -> Option<u8> {
+    if target == "*" || target == CROSS_ARCH2 {
+        None
+    } else if target == CROSS_ARCH1 {
+        Some(2)
+    } else if target == this_host_triple() {
+        Some(1)
+    } else {
+        Some(3)
+    }
+}
+
 impl MockDistServer {
     #[tracing::instrument(level = "trace", skip_all)]
-    pub fn write(&self, vs: &[MockManifestVersion], enable_xz: bool, enable_zst: bool) {
+    pub fn write(
+        &self,
+        vs: &[MockManifestVersion],
+        enable_xz: bool,
+        enable_zst: bool,
+        declare_metadata: bool,
+    ) {
         fs::create_dir_all(&self.path).unwrap();
 
+        let signer = vs
+            .iter()
+            .any(|v| matches!(v, MockManifestVersion::V2))
+            .then(Signer::new)
+            .flatten();
         for channel in self.channels.iter() {
             let mut hashes = HashMap::new();
             for package in &channel.packages {
@@ -144,10 +239,15 @@ impl MockDistServer {
             for v in vs {
                 match *v {
                     MockManifestVersion::V1 => self.write_manifest_v1(channel),
-                    MockManifestVersion::V2 => self.write_manifest_v2(channel, &hashes),
+                    MockManifestVersion::V2 => {
+                        self.write_manifest_v2(channel, &hashes, declare_metadata, &signer)
+                    }
                 }
             }
         }
+        if signer.is_some() {
+            write_file(&self.path.join(SIGNED_MARKER), "");
+        }
     }
 
     #[tracing::instrument(level = "trace", skip_all)]
@@ -312,6 +412,8 @@ impl MockDistServer {
         &self,
         channel: &MockChannel,
         hashes: &HashMap<MockComponent, MockHashes>,
+        declare_metadata: bool,
+        signer: &Option<Signer>,
     ) {
         let mut manifest = Manifest {
             manifest_version: ManifestVersion::V2,
@@ -331,6 +433,10 @@ impl MockDistServer {
                 let mut tpkg = TargetedPackage {
                     bins: Vec::new(),
                     components: Vec::new(),
+                    installed_size: declare_metadata.then(|| target.installer.installed_size()),
+                    tier: declare_metadata
+                        .then(|| mock_tier(&target.target))
+                        .flatten(),
                 };
 
                 let package_file_name = if target.target != "*" {
@@ -354,9 +460,14 @@ impl MockDistServer {
                 if target.available {
                     let hash = hashes[&component].clone();
                     let url = format!("file://{}", path.to_string_lossy());
+                    let size = |ext| {
+                        let path = path.with_extension(ext);
+                        declare_metadata.then(|| fs::metadata(path).unwrap().len())
+                    };
                     tpkg.bins.push(HashedBinary {
                         url: url.clone(),
                         hash: hash.gz,
+                        size: size("gz"),
                         compression: CompressionKind::GZip,
                     });
 
@@ -364,6 +475,7 @@ impl MockDistServer {
                         tpkg.bins.push(HashedBinary {
                             url: url.replace(".tar.gz", ".tar.xz"),
                             hash: xz_hash,
+                            size: size("xz"),
                             compression: CompressionKind::XZ,
                         });
                     }
@@ -372,6 +484,7 @@ impl MockDistServer {
                         tpkg.bins.push(HashedBinary {
                             url: url.replace(".tar.gz", ".tar.zst"),
                             hash: zst_hash,
+                            size: size("zst"),
                             compression: CompressionKind::ZStd,
                         });
                     }
@@ -439,10 +552,17 @@ impl MockDistServer {
             .path
             .join(format!("{archive_manifest_name}.toml.sha256"));
         hard_link(hash_path, archive_hash_path).unwrap();
+
+        if let Some(signer) = signer {
+            signer.sign(&manifest_path);
+            let sig_path = self.path.join(format!("{manifest_name}.toml.asc"));
+            let archive_sig_path = self.path.join(format!("{archive_manifest_name}.toml.asc"));
+            hard_link(sig_path, archive_sig_path).unwrap();
+        }
     }
 }
 
-fn create_tarball(relpath: &Path, src: &Path, dst: &Path) -> io::Result<()> {
+pub fn create_tarball(relpath: &Path, src: &Path, dst: &Path) -> io::Result<()> {
     match fs::remove_file(dst) {
         Ok(_) => {}
         Err(e) if e.kind() == io::ErrorKind::NotFound => {}
@@ -501,6 +621,14 @@ pub fn create_hash(src: &Path, dst: &Path) -> String {
     hex
 }
 
+/// Signs a manifest changed after the dist server was written, if the
+/// server's manifests are signed.
+pub fn sign_with_test_key(dist_server: &Path, manifest: &Path) {
+    if dist_server.join(SIGNED_MARKER).exists() {
+        Signer::new().unwrap().sign(manifest);
+    }
+}
+
 pub fn write_file(dst: &Path, contents: &str) {
     drop(fs::remove_file(dst));
     File::create(dst)
@@ -85,6 +85,13 @@ fn main() {
             let mut out = io::stderr();
             writeln!(out, "{}", std::env::var("PATH").unwrap()).unwrap();
         }
+        Some("--echo-env") => {
+            let mut out = io::stderr();
+            for name in args {
+                let value = std::env::var_os(&name).unwrap_or_default();
+                writeln!(out, "{}={}", name.to_string_lossy(), value.to_string_lossy()).unwrap();
+            }
+        }
         arg => panic!("bad mock proxy commandline: {:?}", arg),
     }
 }
@@ -84,6 +84,18 @@ impl MockInstallerBuilder {
         let mut ver = File::create(path.join("rust-installer-version")).unwrap();
         writeln!(ver, "3").unwrap();
     }
+
+    /// The total size of the files this installer puts on disk.
+    pub fn installed_size(&self) -> u64 {
+        self.components
+            .iter()
+            .flat_map(|component| &component.files)
+            .map(|file| match &file.contents {
+                Contents::File(contents) => contents.contents.len() as u64,
+                Contents::Dir(files) => files.iter().map(|(_, c)| c.contents.len() as u64).sum(),
+            })
+            .sum()
+    }
 }
 
 impl MockFile {
@@ -12,13 +12,17 @@ use std::{
 
 use anyhow::{anyhow, bail, Context};
 use fs_at::OpenOptions;
+use sha2::{Digest, Sha256};
 use tracing::info;
 use url::Url;
 use wait_timeout::ChildExt;
 
 use crate::{
-    config::{ActiveReason, Cfg, InstalledPath},
-    dist::PartialToolchainDesc,
+    config::{ActiveReason, Cfg},
+    dist::{
+        manifestation::DIST_MANIFEST, prefix::InstallPrefix, store::FileStore,
+        PartialToolchainDesc, Profile, TargetTriple,
+    },
     env_var, install,
     notifications::Notification,
     utils::{self, raw::open_dir_following_links},
@@ -44,24 +48,27 @@ pub(crate) struct Toolchain<'a> {
 }
 
 impl<'a> Toolchain<'a> {
+    /// The toolchain `name`, installed with the profile `install_if_missing`
+    /// if there is one and the toolchain isn't installed yet.
     pub(crate) async fn from_local(
         name: LocalToolchainName,
-        install_if_missing: bool,
+        install_if_missing: Option<Profile>,
         cfg: &'a Cfg<'a>,
     ) -> anyhow::Result<Toolchain<'a>> {
-        match Self::new(cfg, name) {
-            Ok(tc) => Ok(tc),
-            Err(RustupError::ToolchainNotInstalled {
-                name: ToolchainName::Official(desc),
-            }) if install_if_missing => {
-                Ok(
-                    DistributableToolchain::install(cfg, &desc, &[], &[], cfg.get_profile()?, true)
-                        .await?
-                        .1
-                        .toolchain,
-                )
-            }
-            Err(e) => Err(e.into()),
+        match (Self::new(cfg, name), install_if_missing) {
+            (Ok(tc), _) => Ok(tc),
+            (
+                Err(RustupError::ToolchainNotInstalled {
+                    name: ToolchainName::Official(desc),
+                }),
+                Some(profile),
+            ) => Ok(
+                DistributableToolchain::install(cfg, &desc, &[], &[], profile, true)
+                    .await?
+                    .1
+                    .toolchain,
+            ),
+            (Err(e), _) => Err(e.into()),
         }
     }
 
@@ -142,7 +149,7 @@ impl<'a> Toolchain<'a> {
         &self.name
     }
 
-    pub(super) fn path(&self) -> &Path {
+    pub(crate) fn path(&self) -> &Path {
         &self.path
     }
 
@@ -222,8 +229,11 @@ impl<'a> Toolchain<'a> {
         // Prepend CARGO_HOME/bin to the PATH variable so that we're sure to run
         // cargo/rustc via the proxy bins. There is no fallback case for if the
         // proxy bins don't exist. We'll just be running whatever happens to
-        // be on the PATH.
+        // be on the PATH. Proxies moved out of CARGO_HOME/bin come first.
         let mut path_entries = vec![];
+        if let Ok(Some(dir)) = self.cfg.settings_file.with(|s| Ok(s.proxy_dir.clone())) {
+            path_entries.push(dir);
+        }
         if let Ok(cargo_home) = self.cfg.process.cargo_home() {
             path_entries.push(cargo_home.join("bin"));
         }
@@ -422,6 +432,21 @@ impl<'a> Toolchain<'a> {
         Ok(doc_dir)
     }
 
+    /// Whether the standard library is installed for `target`, or `None`
+    /// if nothing is installed for it. Targets such as
+    /// `thumbv7em-none-eabihf` only have `core` and `alloc`.
+    pub(crate) fn has_std(&self, target: &TargetTriple) -> Option<bool> {
+        let mut lib_dir = self.path.clone();
+        lib_dir.extend(["lib", "rustlib", &**target, "lib"]);
+        let entries = fs::read_dir(lib_dir).ok()?;
+        Some(entries.filter_map(|entry| entry.ok()).any(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.starts_with("libstd"))
+        }))
+    }
+
     pub fn open_docs(
         &self,
         relative: impl AsRef<Path>,
@@ -435,6 +460,77 @@ impl<'a> Toolchain<'a> {
         utils::open_browser(doc_url.to_string())
     }
 
+    /// A stable digest of the toolchain's installed file tree.
+    ///
+    /// Entries are visited in byte order of their relative paths, and each
+    /// contributes its kind, its `/`-separated path, the executable bit and
+    /// either its content or its link target. Timestamps and ownership are
+    /// ignored, so two identical installs on different machines hash the same.
+    ///
+    /// The installed channel manifest is skipped: it records the URLs of the
+    /// dist server the toolchain was installed from, and what it says about
+    /// the installed components is already covered by their own manifests.
+    pub(crate) fn layout_hash(&self) -> anyhow::Result<String> {
+        let skip = InstallPrefix::from(self.path.clone()).manifest_file(DIST_MANIFEST);
+        let mut hasher = Sha256::new();
+        hash_dir(&mut hasher, &self.path, &skip, &mut Vec::new())
+            .with_context(|| format!("failed to hash toolchain '{}'", self.name))?;
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Writes the toolchain to `output` as a `.tar.zst` archive that can be
+    /// used without rustup.
+    ///
+    /// The toolchain goes in `<name>/toolchain`, next to a
+    /// `toolchain-manifest.toml` with its name, host and
+    /// [layout hash](Self::layout_hash), and an `install.sh` that copies it to
+    /// a directory of the user's choice. Entries are written in a fixed order
+    /// with fixed owners and timestamps, so packaging the same toolchain twice
+    /// gives the same archive.
+    pub(crate) fn package(&self, output: &Path) -> anyhow::Result<()> {
+        #[derive(serde::Serialize)]
+        struct PackageManifest {
+            name: String,
+            host: String,
+            layout_hash: String,
+        }
+
+        let name = self.name.to_string();
+        let host = match &self.name {
+            LocalToolchainName::Named(ToolchainName::Official(desc)) => desc.target.to_string(),
+            _ => self.cfg.get_default_host_triple()?.to_string(),
+        };
+        let manifest = toml::to_string(&PackageManifest {
+            name: name.clone(),
+            host,
+            layout_hash: self.layout_hash()?,
+        })?;
+        let install_script = include_str!("toolchain/install.sh").replace("{name}", &name);
+
+        let file = fs::File::create(output)
+            .with_context(|| format!("could not create '{}'", output.display()))?;
+        let mut archive = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+        archive.mode(tar::HeaderMode::Deterministic);
+        archive.follow_symlinks(false);
+
+        let mut append_file = |path: &str, mode: u32, data: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(mode);
+            archive.append_data(&mut header, format!("{name}/{path}"), data)
+        };
+        append_file("toolchain-manifest.toml", 0o644, manifest.as_bytes())?;
+        append_file("install.sh", 0o755, install_script.as_bytes())?;
+
+        let skip = InstallPrefix::from(self.path.clone()).manifest_file(DIST_MANIFEST);
+        let root = PathBuf::from(&name).join("toolchain");
+        archive.append_dir(&root, &self.path)?;
+        package_dir(&mut archive, &self.path, &skip, &root)
+            .with_context(|| format!("failed to package toolchain '{}'", self.name))?;
+        archive.into_inner()?.finish()?;
+        Ok(())
+    }
+
     /// Remove the toolchain from disk
     ///
     ///
@@ -447,20 +543,18 @@ impl<'a> Toolchain<'a> {
         let fs_modified = match Self::exists(cfg, &(&name).into())? {
             true => {
                 (cfg.notify_handler)(Notification::UninstallingToolchain(&name));
-                let installed_paths = match &name {
-                    ToolchainName::Custom(_) => Ok(vec![InstalledPath::Dir { path: &path }]),
-                    ToolchainName::Official(desc) => cfg.installed_paths(desc, &path),
-                }?;
-                for path in installed_paths {
-                    match path {
-                        InstalledPath::File { name, path } => {
-                            utils::ensure_file_removed(name, &path)?
-                        }
-                        InstalledPath::Dir { path } => {
-                            install::uninstall(path, &|n| (cfg.notify_handler)(n.into()))?
-                        }
-                    }
+                if let ToolchainName::Official(desc) = &name {
+                    cfg.manifest_cache.remove_installed(desc)?;
                 }
+                install::uninstall(
+                    &path,
+                    cfg.io_profile,
+                    cfg.io_threads,
+                    &|n| (cfg.notify_handler)(n.into()),
+                    cfg.process,
+                )?;
+                // Drop the files only the removed toolchain shared.
+                FileStore::new(cfg.store_dir.clone()).prune()?;
                 true
             }
             false => {
@@ -488,3 +582,94 @@ impl<'a> Toolchain<'a> {
         Ok(())
     }
 }
+
+/// Appends the contents of `dir` to `archive` under `prefix`, in the same
+/// order as [`hash_dir`].
+fn package_dir<W: io::Write>(
+    archive: &mut tar::Builder<W>,
+    dir: &Path,
+    skip: &Path,
+    prefix: &Path,
+) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by(|a, b| {
+        a.file_name()
+            .as_encoded_bytes()
+            .cmp(b.file_name().as_encoded_bytes())
+    });
+
+    for entry in entries {
+        let path = entry.path();
+        if path == skip {
+            continue;
+        }
+
+        let name = prefix.join(entry.file_name());
+        archive.append_path_with_name(&path, &name)?;
+        if entry.file_type()?.is_dir() {
+            package_dir(archive, &path, skip, &name)?;
+        }
+    }
+    Ok(())
+}
+
+fn hash_dir(hasher: &mut Sha256, dir: &Path, skip: &Path, prefix: &mut Vec<u8>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by(|a, b| {
+        a.file_name()
+            .as_encoded_bytes()
+            .cmp(b.file_name().as_encoded_bytes())
+    });
+
+    for entry in entries {
+        let path = entry.path();
+        if path == skip {
+            continue;
+        }
+
+        let len = prefix.len();
+        if !prefix.is_empty() {
+            prefix.push(b'/');
+        }
+        prefix.extend_from_slice(entry.file_name().as_encoded_bytes());
+
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            hasher.update(b"l\0");
+            hasher.update(&*prefix);
+            hasher.update(b"\0");
+            hasher.update(fs::read_link(&path)?.as_os_str().as_encoded_bytes());
+        } else if file_type.is_dir() {
+            hasher.update(b"d\0");
+            hasher.update(&*prefix);
+            hasher.update(b"\0");
+            hash_dir(hasher, &path, skip, prefix)?;
+        } else {
+            hasher.update(if is_executable(&entry.metadata()?) {
+                b"x\0"
+            } else {
+                b"f\0"
+            });
+            hasher.update(&*prefix);
+            hasher.update(b"\0");
+            let mut file = fs::File::open(&path)?;
+            let mut content = Sha256::new();
+            io::copy(&mut file, &mut content)?;
+            hasher.update(content.finalize());
+        }
+
+        prefix.truncate(len);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_: &fs::Metadata) -> bool {
+    false
+}
@@ -5,18 +5,21 @@ use std::{convert::Infallible, env::consts::EXE_SUFFIX, ffi::OsStr, path::Path,
 use anyhow::anyhow;
 #[cfg(windows)]
 use anyhow::Context;
+use url::Url;
 
 use crate::{
     component_for_bin,
     config::Cfg,
     dist::{
+        self,
         config::Config,
         manifest::{Component, ComponentStatus, Manifest},
-        manifestation::{Changes, Manifestation},
+        manifestation::{ChangePlan, Changes, Manifestation},
         prefix::InstallPrefix,
-        DistOptions, PartialToolchainDesc, Profile, ToolchainDesc,
+        DistOptions, PartialToolchainDesc, Profile, TargetTriple, ToolchainDesc,
     },
     install::{InstallMethod, UpdateStatus},
+    notifications::Notification,
     RustupError,
 };
 
@@ -48,10 +51,90 @@ impl<'a> DistributableToolchain<'a> {
         &self.desc
     }
 
-    pub(crate) async fn add_component(&self, mut component: Component) -> anyhow::Result<()> {
-        // TODO: take multiple components?
+    /// Checks that the installed `rustc-dev` was built from the same commit as
+    /// the installed `rustc`, which tools linking against `rustc_driver` need.
+    pub(crate) fn verify_rustc_dev(&self) -> anyhow::Result<()> {
+        let config = self.get_manifestation()?.read_config()?.unwrap_or_default();
+        verify_rustc_dev(
+            &self.get_manifest()?,
+            &config,
+            &self.desc,
+            &self.toolchain.rustc_version(),
+        )
+    }
+
+    /// Adds `components` in one update of the toolchain, which downloads
+    /// them all at once and installs them in one transaction, so that none
+    /// is added unless all can be.
+    pub(crate) async fn add_components(&self, components: Vec<Component>) -> anyhow::Result<()> {
+        let manifestation = self.get_manifestation()?;
+        let manifest = self.get_manifest()?;
+        let changes = Changes {
+            explicit_add_components: components
+                .into_iter()
+                .map(|c| self.component_to_add(&manifestation, &manifest, c))
+                .collect::<anyhow::Result<_>>()?,
+            remove_components: vec![],
+        };
+        self.apply_changes(&manifestation, &manifest, changes).await
+    }
+
+    async fn apply_changes(
+        &self,
+        manifestation: &Manifestation,
+        manifest: &Manifest,
+        changes: Changes,
+    ) -> anyhow::Result<()> {
+        let notify_handler =
+            &|n: crate::dist::Notification<'_>| (self.toolchain.cfg.notify_handler)(n.into());
+        let download_cfg = self.toolchain.cfg.download_cfg(&notify_handler);
+
+        manifestation
+            .update(
+                manifest,
+                changes,
+                false,
+                &download_cfg,
+                &self.desc.manifest_name(),
+                false,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Computes what adding and removing the given components would change,
+    /// without changing anything.
+    pub(crate) fn plan_changes(
+        &self,
+        add: Vec<Component>,
+        remove: Vec<Component>,
+    ) -> anyhow::Result<(Manifest, ChangePlan)> {
         let manifestation = self.get_manifestation()?;
         let manifest = self.get_manifest()?;
+        let config = manifestation.read_config()?.unwrap_or_default();
+        let changes = Changes {
+            explicit_add_components: add
+                .into_iter()
+                .map(|c| self.component_to_add(&manifestation, &manifest, c))
+                .collect::<anyhow::Result<_>>()?,
+            remove_components: remove
+                .into_iter()
+                .map(|c| self.component_to_remove(&config, &manifest, c))
+                .collect::<anyhow::Result<_>>()?,
+        };
+        let plan = manifestation.compute_changes(&manifest, &changes)?;
+        Ok((manifest, plan))
+    }
+
+    /// Checks that `component` can be added, and returns it under the name
+    /// the manifest knows it by.
+    fn component_to_add(
+        &self,
+        manifestation: &Manifestation,
+        manifest: &Manifest,
+        mut component: Component,
+    ) -> anyhow::Result<Component> {
         // Rename the component if necessary.
         if let Some(c) = manifest.rename_component(&component) {
             component = c;
@@ -74,7 +157,7 @@ impl<'a> DistributableToolchain<'a> {
             } else {
                 let config = manifestation.read_config()?.unwrap_or_default();
                 let suggestion =
-                    self.get_component_suggestion(&component, &config, &manifest, false);
+                    self.get_component_suggestion(&component, &config, manifest, false);
                 // Check if the target is supported.
                 if !targ_pkg
                     .components
@@ -90,22 +173,45 @@ impl<'a> DistributableToolchain<'a> {
                 }
                 return Err(RustupError::UnknownComponent {
                     desc: self.desc.clone(),
-                    component: component.description(&manifest),
+                    component: component.description(manifest),
                     suggestion,
                 }
                 .into());
             }
         }
 
-        let changes = Changes {
-            explicit_add_components: vec![component],
-            remove_components: vec![],
-        };
+        Ok(component)
+    }
 
+    /// Installs a component of the channel manifest from an archive that was
+    /// obtained some other way, such as over a faster network. The archive
+    /// must be one of those the manifest lists for the toolchain.
+    pub(crate) async fn add_component_from_file(&self, path: &Path) -> anyhow::Result<()> {
+        let manifestation = self.get_manifestation()?;
+        let mut manifest = self.get_manifest()?;
         let notify_handler =
             &|n: crate::dist::Notification<'_>| (self.toolchain.cfg.notify_handler)(n.into());
         let download_cfg = self.toolchain.cfg.download_cfg(&notify_handler);
 
+        let hash = download_cfg.hash_local_file(path)?;
+        let Some(component) = manifest.select_archive(&self.desc.target, &hash) else {
+            return Err(anyhow!(
+                "'{}' is not an archive of a component of toolchain '{}'; \
+                 it may be for another release or host, or be damaged",
+                path.display(),
+                self.desc
+            ));
+        };
+        (self.toolchain.cfg.notify_handler)(Notification::ComponentFromFile(
+            &component.short_name(&manifest),
+            path,
+        ));
+        download_cfg.add_local_file(path, &hash)?;
+
+        let changes = Changes {
+            explicit_add_components: vec![component],
+            remove_components: vec![],
+        };
         manifestation
             .update(
                 &manifest,
@@ -116,10 +222,26 @@ impl<'a> DistributableToolchain<'a> {
                 false,
             )
             .await?;
-
         Ok(())
     }
 
+    /// Installs a component that is not in the channel manifest from a
+    /// rust-installer archive, checking it against the given hash.
+    pub(crate) async fn add_component_from_url(
+        &self,
+        url: &Url,
+        hash: &str,
+        name: &str,
+    ) -> anyhow::Result<()> {
+        let manifestation = self.get_manifestation()?;
+        let notify_handler =
+            &|n: crate::dist::Notification<'_>| (self.toolchain.cfg.notify_handler)(n.into());
+        let download_cfg = self.toolchain.cfg.download_cfg(&notify_handler);
+        manifestation
+            .install_from_url(url, hash, name, &download_cfg)
+            .await
+    }
+
     pub(crate) fn components(&self) -> anyhow::Result<Vec<ComponentStatus>> {
         let manifestation = self.get_manifestation()?;
         let config = manifestation.read_config()?.unwrap_or_default();
@@ -127,6 +249,17 @@ impl<'a> DistributableToolchain<'a> {
         manifest.query_components(self.desc(), &config)
     }
 
+    /// The targets whose documentation is installed, from their
+    /// `rust-docs` components.
+    pub(crate) fn doc_sets(&self) -> anyhow::Result<Vec<TargetTriple>> {
+        Ok(self
+            .components()?
+            .into_iter()
+            .filter(|c| c.installed && c.component.short_name_in_manifest() == "rust-docs")
+            .filter_map(|c| c.component.target)
+            .collect())
+    }
+
     /// Are all the components installed in this distribution
     pub(crate) fn components_exist(
         &self,
@@ -334,14 +467,10 @@ impl<'a> DistributableToolchain<'a> {
         profile: Profile,
         force: bool,
     ) -> anyhow::Result<(UpdateStatus, DistributableToolchain<'a>)> {
-        let hash_path = cfg.get_hash_file(toolchain, true)?;
-        let update_hash = Some(&hash_path as &Path);
-
         let status = InstallMethod::Dist(DistOptions {
             cfg,
             toolchain,
             profile,
-            update_hash,
             dl_cfg: cfg.download_cfg(&|n| (cfg.notify_handler)(n.into())),
             force,
             allow_downgrade: false,
@@ -355,6 +484,32 @@ impl<'a> DistributableToolchain<'a> {
         Ok((status, Self::new(cfg, toolchain.clone())?))
     }
 
+    /// Computes what installing or updating `toolchain` would change, or
+    /// `None` if it is up to date, without changing anything.
+    pub(crate) async fn plan_update(
+        cfg: &'a Cfg<'a>,
+        toolchain: &ToolchainDesc,
+        components: &[&str],
+        targets: &[&str],
+        profile: Profile,
+    ) -> anyhow::Result<Option<(Manifest, ChangePlan)>> {
+        let path = cfg.toolchain_path(&toolchain.into());
+        let notify = |n: dist::Notification<'_>| (cfg.notify_handler)(n.into());
+        let opts = DistOptions {
+            cfg,
+            toolchain,
+            profile,
+            dl_cfg: cfg.download_cfg(&notify),
+            force: false,
+            allow_downgrade: false,
+            exists: path.exists(),
+            old_date_version: None,
+            components,
+            targets,
+        };
+        dist::plan_from_dist(&InstallPrefix::from(path), &opts).await
+    }
+
     #[tracing::instrument(level = "trace", err(level = "trace"), skip_all)]
     pub(crate) async fn update(
         &mut self,
@@ -392,14 +547,10 @@ impl<'a> DistributableToolchain<'a> {
                 .ok();
 
         let cfg = self.toolchain.cfg;
-        let hash_path = cfg.get_hash_file(&self.desc, true)?;
-        let update_hash = Some(&hash_path as &Path);
-
         InstallMethod::Dist(DistOptions {
             cfg,
             toolchain: &self.desc,
             profile,
-            update_hash,
             dl_cfg: cfg.download_cfg(&|n| (cfg.notify_handler)(n.into())),
             force,
             allow_downgrade,
@@ -447,12 +598,30 @@ impl<'a> DistributableToolchain<'a> {
         }
     }
 
-    pub(crate) async fn remove_component(&self, mut component: Component) -> anyhow::Result<()> {
-        // TODO: take multiple components?
+    /// Removes `components` in one transaction, so that none is removed
+    /// unless all can be.
+    pub(crate) async fn remove_components(&self, components: Vec<Component>) -> anyhow::Result<()> {
         let manifestation = self.get_manifestation()?;
         let config = manifestation.read_config()?.unwrap_or_default();
         let manifest = self.get_manifest()?;
+        let changes = Changes {
+            explicit_add_components: vec![],
+            remove_components: components
+                .into_iter()
+                .map(|c| self.component_to_remove(&config, &manifest, c))
+                .collect::<anyhow::Result<_>>()?,
+        };
+        self.apply_changes(&manifestation, &manifest, changes).await
+    }
 
+    /// Checks that `component` is installed, and returns it under the name
+    /// the manifest knows it by.
+    fn component_to_remove(
+        &self,
+        config: &Config,
+        manifest: &Manifest,
+        mut component: Component,
+    ) -> anyhow::Result<Component> {
         // Rename the component if necessary.
         if let Some(c) = manifest.rename_component(&component) {
             component = c;
@@ -463,8 +632,7 @@ impl<'a> DistributableToolchain<'a> {
             if config.components.contains(&wildcard_component) {
                 component = wildcard_component;
             } else {
-                let suggestion =
-                    self.get_component_suggestion(&component, &config, &manifest, true);
+                let suggestion = self.get_component_suggestion(&component, config, manifest, true);
                 // Check if the target is installed.
                 if !config
                     .components
@@ -480,43 +648,30 @@ impl<'a> DistributableToolchain<'a> {
                 }
                 return Err(RustupError::UnknownComponent {
                     desc: self.desc.clone(),
-                    component: component.description(&manifest),
+                    component: component.description(manifest),
                     suggestion,
                 }
                 .into());
             }
         }
 
-        let changes = Changes {
-            explicit_add_components: vec![],
-            remove_components: vec![component],
-        };
-
-        let notify_handler =
-            &|n: crate::dist::Notification<'_>| (self.toolchain.cfg.notify_handler)(n.into());
-        let download_cfg = self.toolchain.cfg.download_cfg(&notify_handler);
-
-        manifestation
-            .update(
-                &manifest,
-                changes,
-                false,
-                &download_cfg,
-                &self.desc.manifest_name(),
-                false,
-            )
-            .await?;
-
-        Ok(())
+        Ok(component)
     }
 
     pub async fn show_dist_version(&self) -> anyhow::Result<Option<String>> {
-        let update_hash = self.toolchain.cfg.get_hash_file(&self.desc, false)?;
-        let notify_handler =
-            &|n: crate::dist::Notification<'_>| (self.toolchain.cfg.notify_handler)(n.into());
-        let download_cfg = self.toolchain.cfg.download_cfg(&notify_handler);
-
-        match crate::dist::dl_v2_manifest(download_cfg, Some(&update_hash), &self.desc).await? {
+        let cfg = self.toolchain.cfg;
+        let update_hash = cfg.manifest_cache.installed(&self.desc)?.map(|i| i.hash);
+        let notify_handler = &|n: crate::dist::Notification<'_>| (cfg.notify_handler)(n.into());
+        let download_cfg = cfg.download_cfg(&notify_handler);
+
+        match crate::dist::dl_v2_manifest(
+            download_cfg,
+            Some(&cfg.manifest_cache),
+            update_hash.as_deref(),
+            &self.desc,
+        )
+        .await?
+        {
             Some((manifest, _)) => Ok(Some(manifest.get_rust_version()?.to_string())),
             None => Ok(None),
         }
@@ -549,3 +704,99 @@ impl<'a> From<DistributableToolchain<'a>> for Toolchain<'a> {
         value.toolchain
     }
 }
+
+/// `rustc_version` is what the installed `rustc --version` says, which
+/// `rustc-dev` is checked against rather than against the manifest's `rustc`,
+/// as both come from the same manifest.
+fn verify_rustc_dev(
+    manifest: &Manifest,
+    config: &Config,
+    desc: &ToolchainDesc,
+    rustc_version: &str,
+) -> anyhow::Result<()> {
+    for name in ["rustc", "rustc-dev"] {
+        let component = Component::new(name.to_owned(), Some(desc.target.clone()), false);
+        if !config.components.contains(&component) {
+            return Err(anyhow!(
+                "{} is not installed for the toolchain '{desc}'",
+                component.description(manifest)
+            ));
+        }
+    }
+    let rustc_dev = &manifest.get_package("rustc-dev")?.version;
+    let Some(rustc_commit) = commit_hash(rustc_version) else {
+        return Err(anyhow!(
+            "could not tell which commit rustc was built from: {rustc_version}"
+        ));
+    };
+    if commit_hash(rustc_dev) != Some(rustc_commit) {
+        return Err(anyhow!(
+            "rustc-dev {rustc_dev} does not match rustc {rustc_version}; reinstall the toolchain '{desc}'"
+        ));
+    }
+    Ok(())
+}
+
+/// The commit hash in a version such as `rustc 1.80.0-nightly (abcdef123
+/// 2024-04-30)`.
+fn commit_hash(version: &str) -> Option<&str> {
+    let (_, build) = version.rsplit_once(" (")?;
+    build.strip_suffix(')')?.split_whitespace().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(rustc_dev_version: &str) -> Manifest {
+        Manifest::parse(&format!(
+            r#"
+manifest-version = "2"
+date = "2024-05-01"
+[pkg.rustc]
+version = "1.80.0-nightly (abcdef123 2024-04-30)"
+[pkg.rustc.target.x86_64-unknown-linux-gnu]
+available = false
+[pkg.rustc-dev]
+version = "{rustc_dev_version}"
+[pkg.rustc-dev.target.x86_64-unknown-linux-gnu]
+available = false
+"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn rustc_dev_matches_rustc() {
+        let desc: ToolchainDesc = "nightly-x86_64-unknown-linux-gnu".parse().unwrap();
+        let host = Some(desc.target.clone());
+        let other = Some(TargetTriple::new("aarch64-unknown-linux-gnu"));
+        let config = |components: &[(&str, &Option<TargetTriple>)]| Config {
+            components: components
+                .iter()
+                .map(|(name, target)| Component::new(name.to_string(), (*target).clone(), false))
+                .collect(),
+            ..Config::default()
+        };
+        let installed = config(&[("rustc", &host), ("rustc-dev", &host)]);
+
+        let current = manifest("1.80.0-nightly (abcdef123 2024-04-30)");
+        let rustc = "rustc 1.80.0-nightly (abcdef123 2024-04-30)";
+        verify_rustc_dev(&current, &installed, &desc, rustc).unwrap();
+
+        // The installed rustc was built from another commit than the manifest
+        // says, as when its files were replaced.
+        let other_rustc = "rustc 1.80.0-nightly (987654fed 2024-04-29)";
+        let err = verify_rustc_dev(&current, &installed, &desc, other_rustc).unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{err}");
+
+        let err =
+            verify_rustc_dev(&current, &installed, &desc, "(rustc does not exist)").unwrap_err();
+        assert!(err.to_string().contains("could not tell"), "{err}");
+
+        // Installed, but for another target.
+        let cross = config(&[("rustc", &host), ("rustc-dev", &other)]);
+        let err = verify_rustc_dev(&current, &cross, &desc, rustc).unwrap_err();
+        assert!(err.to_string().contains("is not installed"), "{err}");
+    }
+}
@@ -0,0 +1,14 @@
+#!/bin/sh
+# Installs the toolchain packaged next to this script into the given
+# directory. The toolchain finds its libraries relative to `rustc`, so the
+# directory can be anywhere.
+set -eu
+
+if [ $# -ne 1 ]; then
+    echo "usage: $0 <dir>" >&2
+    exit 1
+fi
+
+mkdir -p "$1"
+cp -R "$(dirname "$0")/toolchain/." "$1"
+echo "installed {name} to $1, add $1/bin to PATH to use it"
@@ -48,6 +48,7 @@ use std::{
     str::FromStr,
 };
 
+use serde::{Serialize, Serializer};
 use thiserror::Error;
 
 use crate::dist::{PartialToolchainDesc, TargetTriple, ToolchainDesc};
@@ -84,6 +85,19 @@ macro_rules! from_variant {
     };
 }
 
+/// Serializes a name as it is displayed.
+macro_rules! serialize_as_display {
+    ($($ty:ident),*) => {$(
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+    )*};
+}
+
+serialize_as_display!(ToolchainName, LocalToolchainName, CustomToolchainName);
+
 macro_rules! try_from_str {
     ($to:ident) => {
         try_from_str!(&str, $to);
@@ -329,7 +343,7 @@ impl Display for ResolvableLocalToolchainName {
 /// variable, because that can take unresolved toolchain values that are not
 /// invalid for referring to an installed toolchain.
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub(crate) enum LocalToolchainName {
+pub enum LocalToolchainName {
     Named(ToolchainName),
     Path(PathBasedToolchainName),
 }
@@ -382,19 +396,52 @@ impl Display for LocalToolchainName {
 #[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
 pub struct CustomToolchainName(String);
 
+/// Names with a meaning of their own wherever a toolchain name is accepted,
+/// and which therefore cannot be used for custom toolchains.
+const RESERVED_NAMES: &[&str] = &["none", "self", ".", ".."];
+
 impl CustomToolchainName {
     fn validate(candidate: &str) -> Result<CustomToolchainName, InvalidName> {
         let candidate = validate(candidate)?;
-        if candidate.parse::<PartialToolchainDesc>().is_ok()
-            || candidate == "none"
+        if Self::shadows_official(candidate)
+            || RESERVED_NAMES.contains(&candidate)
             || candidate.contains('/')
             || candidate.contains('\\')
+            // `+name` selects a toolchain when passed to a proxy.
+            || candidate.starts_with('+')
+            || candidate.chars().any(char::is_control)
         {
             Err(InvalidName::CustomName(candidate.into()))
         } else {
             Ok(CustomToolchainName(candidate.into()))
         }
     }
+
+    /// Whether `name` could be mistaken for an official toolchain, even if it
+    /// only differs from one in case.
+    fn shadows_official(name: &str) -> bool {
+        name.to_ascii_lowercase()
+            .parse::<PartialToolchainDesc>()
+            .is_ok()
+    }
+
+    /// Whether `name` is a custom toolchain name that older versions of rustup
+    /// accepted, but which is rejected now.
+    pub(crate) fn is_legacy(name: &str) -> bool {
+        Self::validate(name).is_err()
+            && name.parse::<PartialToolchainDesc>().is_err()
+            && name != "none"
+            && !name.contains(['/', '\\'])
+    }
+
+    /// A name for a toolchain that [`Self::is_legacy`] flagged, which is valid
+    /// under the current rules.
+    pub(crate) fn migrate(name: &str) -> Result<Self, InvalidName> {
+        let name = name
+            .trim_start_matches('+')
+            .replace(|c: char| c.is_control(), "_");
+        Self::validate(&name).or_else(|_| Self::validate(&format!("{name}-custom")))
+    }
 }
 
 impl Deref for CustomToolchainName {
@@ -501,7 +548,7 @@ mod tests {
 
     prop_compose! {
         fn arb_custom_name()
-            (s in r"[^\\/]+") -> String {
+            (s in r"[^\\/+\p{Cc}][^\\/\p{Cc}]*".prop_filter("reserved name", |s| !matches!(s.as_str(), "." | ".."))) -> String {
                 // perhaps need to filter 'none' and partial toolchains - but they won't typically be generated anyway.
                 s
         }
@@ -556,6 +603,41 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_reserved_custom_names() {
+        for name in [
+            "none",
+            "self",
+            ".",
+            "..",
+            "+dev",
+            "Nightly",
+            "STABLE-2024-01-01",
+            "a\tb",
+        ] {
+            assert!(CustomToolchainName::try_from(name).is_err(), "{name}");
+        }
+        for name in ["selfish", "dev+1", "nightly-custom", "the cake is a lie"] {
+            CustomToolchainName::try_from(name).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_migrate_legacy_custom_names() {
+        for (old, new) in [
+            ("self", "self-custom"),
+            ("+dev", "dev"),
+            ("Beta", "Beta-custom"),
+            ("a\tb", "a_b"),
+        ] {
+            assert!(CustomToolchainName::is_legacy(old), "{old}");
+            assert_eq!(&*CustomToolchainName::migrate(old).unwrap(), new);
+        }
+        for name in ["dev", "none", "stable", "a/b"] {
+            assert!(!CustomToolchainName::is_legacy(name), "{name}");
+        }
+    }
+
     #[test]
     fn test_toolchain_sort() {
         let expected = vec![
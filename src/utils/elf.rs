@@ -0,0 +1,355 @@
+//! Just enough of an ELF reader to tell what a binary needs from the system
+//! it runs on: the dynamic linker it asks for and the shared libraries it
+//! links against.
+
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+
+/// What a dynamically linked ELF file expects to find when it is loaded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Linkage {
+    /// The dynamic linker, from `PT_INTERP`. Only executables have one.
+    pub interpreter: Option<String>,
+    /// The shared libraries from `DT_NEEDED`.
+    pub needed: Vec<String>,
+    /// The library search path from `DT_RUNPATH`, or `DT_RPATH`.
+    pub runpath: Option<String>,
+}
+
+/// Reads the linkage of the file at `path`, or `None` if it is not ELF.
+///
+/// The file may be damaged or made up, so every offset it gives is checked
+/// and an error returned rather than a panic.
+pub(crate) fn linkage(path: &Path) -> Result<Option<Linkage>> {
+    let mut file = File::open(path)?;
+    let mut ident = [0; 16];
+    if file.read_exact(&mut ident).is_err() || ident[..4] != *b"\x7fELF" {
+        return Ok(None);
+    }
+    let mut elf = Elf {
+        file,
+        wide: match ident[4] {
+            1 => false,
+            2 => true,
+            class => bail!("unknown ELF class {class}"),
+        },
+        big_endian: match ident[5] {
+            1 => false,
+            2 => true,
+            data => bail!("unknown ELF data encoding {data}"),
+        },
+    };
+
+    let header = elf.bytes_at(0, 64)?;
+    let (phoff, phentsize, phnum) = match elf.wide {
+        true => (
+            elf.int::<8>(&header, 0x20)?,
+            elf.int::<2>(&header, 0x36)?,
+            elf.int::<2>(&header, 0x38)?,
+        ),
+        false => (
+            elf.int::<4>(&header, 0x1c)?,
+            elf.int::<2>(&header, 0x2a)?,
+            elf.int::<2>(&header, 0x2c)?,
+        ),
+    };
+    // `bytes_at` stops at the end of the file, so a made-up size cannot
+    // ask for more memory than that.
+    let table = elf.bytes_at(phoff, checked(phentsize.checked_mul(phnum))?)?;
+    let segments = (0..phnum)
+        .map(|i| elf.segment(&table, checked(i.checked_mul(phentsize))?))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut linkage = Linkage::default();
+    for segment in &segments {
+        if segment.kind == PT_INTERP {
+            let interpreter = elf.bytes_at(segment.offset, segment.size)?;
+            linkage.interpreter = Some(c_str(&interpreter));
+        }
+    }
+
+    let Some(dynamic) = segments.iter().find(|s| s.kind == PT_DYNAMIC) else {
+        return Ok(Some(linkage));
+    };
+    let entries = elf.bytes_at(dynamic.offset, dynamic.size)?;
+    let entry_size = if elf.wide { 16 } else { 8 };
+    let mut strtab = None;
+    let mut needed = Vec::new();
+    let mut rpath = None;
+    let mut runpath = None;
+    for i in 0..entries.len() as u64 / entry_size {
+        let at = i * entry_size;
+        let (tag, value) = match elf.wide {
+            true => (elf.int::<8>(&entries, at)?, elf.int::<8>(&entries, at + 8)?),
+            false => (elf.int::<4>(&entries, at)?, elf.int::<4>(&entries, at + 4)?),
+        };
+        match tag {
+            DT_NULL => break,
+            DT_NEEDED => needed.push(value),
+            DT_STRTAB => strtab = Some(value),
+            DT_RPATH => rpath = Some(value),
+            DT_RUNPATH => runpath = Some(value),
+            _ => {}
+        }
+    }
+
+    // The string table is given by its address once loaded, so find where
+    // the segment holding it starts in the file.
+    let Some(strtab) = strtab else {
+        return Ok(Some(linkage));
+    };
+    let Some(strtab) = segments
+        .iter()
+        .filter(|s| s.kind == PT_LOAD)
+        .find(|s| {
+            s.address
+                .checked_add(s.size)
+                .is_some_and(|end| (s.address..end).contains(&strtab))
+        })
+        .and_then(|s| (strtab - s.address).checked_add(s.offset))
+    else {
+        bail!("ELF string table is outside of any loaded segment");
+    };
+    for name in needed {
+        linkage
+            .needed
+            .push(elf.c_str_at(checked(strtab.checked_add(name))?)?);
+    }
+    if let Some(path) = runpath.or(rpath) {
+        linkage.runpath = Some(elf.c_str_at(checked(strtab.checked_add(path))?)?);
+    }
+    Ok(Some(linkage))
+}
+
+/// Adds `path` to `found` if it is an ELF file, or the ELF files under it
+/// if it is a directory. Symlinks are not followed.
+pub(crate) fn find(path: &Path, found: &mut Vec<PathBuf>) -> Result<()> {
+    let file_type = fs::symlink_metadata(path)?.file_type();
+    if file_type.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            find(&entry, found)?;
+        }
+    } else if file_type.is_file() {
+        let mut magic = [0; 4];
+        let is_elf = File::open(path)?.read_exact(&mut magic).is_ok() && magic == *b"\x7fELF";
+        if is_elf {
+            found.push(path.to_owned());
+        }
+    }
+    Ok(())
+}
+
+struct Elf {
+    file: File,
+    wide: bool,
+    big_endian: bool,
+}
+
+struct Segment {
+    kind: u32,
+    offset: u64,
+    address: u64,
+    size: u64,
+}
+
+impl Elf {
+    /// Reads the program header at `at` in the program header `table`.
+    fn segment(&self, table: &[u8], at: u64) -> Result<Segment> {
+        Ok(match self.wide {
+            true => Segment {
+                kind: self.int::<4>(table, at)? as u32,
+                offset: self.int::<8>(table, at + 8)?,
+                address: self.int::<8>(table, at + 16)?,
+                size: self.int::<8>(table, at + 32)?,
+            },
+            false => Segment {
+                kind: self.int::<4>(table, at)? as u32,
+                offset: self.int::<4>(table, at + 4)?,
+                address: self.int::<4>(table, at + 8)?,
+                size: self.int::<4>(table, at + 16)?,
+            },
+        })
+    }
+
+    /// Reads an `N` byte unsigned integer at `at` in `bytes`.
+    fn int<const N: usize>(&self, bytes: &[u8], at: u64) -> Result<u64> {
+        let Some(field) = usize::try_from(at)
+            .ok()
+            .and_then(|start| bytes.get(start..start.checked_add(N)?))
+        else {
+            bail!("ELF file is truncated");
+        };
+        let mut buf = [0; 8];
+        Ok(match self.big_endian {
+            true => {
+                buf[8 - N..].copy_from_slice(field);
+                u64::from_be_bytes(buf)
+            }
+            false => {
+                buf[..N].copy_from_slice(field);
+                u64::from_le_bytes(buf)
+            }
+        })
+    }
+
+    /// Reads up to `len` bytes at `at`, fewer if the file ends first.
+    fn bytes_at(&mut self, at: u64, len: u64) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.file.seek(SeekFrom::Start(at))?;
+        (&mut self.file).take(len).read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn c_str_at(&mut self, at: u64) -> Result<String> {
+        // Library names and search paths are far shorter than this.
+        Ok(c_str(&self.bytes_at(at, 4096)?))
+    }
+}
+
+/// Turns a `None` from a checked operation into an error.
+fn checked(value: Option<u64>) -> Result<u64> {
+    match value {
+        Some(value) => Ok(value),
+        None => bail!("ELF offset is out of range"),
+    }
+}
+
+fn c_str(bytes: &[u8]) -> String {
+    let bytes = bytes.split(|&b| b == 0).next().unwrap_or_default();
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a 64-bit ELF file with the given byte order, with one loaded
+    /// segment covering the whole file, an interpreter and a dynamic section.
+    fn elf64(big_endian: bool) -> Vec<u8> {
+        let u16b = |v: u16| match big_endian {
+            true => v.to_be_bytes().to_vec(),
+            false => v.to_le_bytes().to_vec(),
+        };
+        let u32b = |v: u32| match big_endian {
+            true => v.to_be_bytes().to_vec(),
+            false => v.to_le_bytes().to_vec(),
+        };
+        let u64b = |v: u64| match big_endian {
+            true => v.to_be_bytes().to_vec(),
+            false => v.to_le_bytes().to_vec(),
+        };
+
+        const BASE: u64 = 0x40_0000;
+        let phoff = 64;
+        let dynamic = phoff + 3 * 56;
+        let strtab = dynamic + 5 * 16;
+        let strings = b"\0libc.so.6\0libm.so.6\0$ORIGIN/../lib\0/lib64/ld-linux-x86-64.so.2\0";
+        let interp = strtab + 36;
+
+        let mut elf = b"\x7fELF\x02".to_vec();
+        elf.push(if big_endian { 2 } else { 1 });
+        elf.resize(16, 0);
+        elf.extend(u16b(2)); // e_type
+        elf.extend(u16b(62)); // e_machine
+        elf.extend(u32b(1)); // e_version
+        elf.extend(u64b(0)); // e_entry
+        elf.extend(u64b(phoff)); // e_phoff
+        elf.extend(u64b(0)); // e_shoff
+        elf.extend(u32b(0)); // e_flags
+        elf.extend(u16b(64)); // e_ehsize
+        elf.extend(u16b(56)); // e_phentsize
+        elf.extend(u16b(3)); // e_phnum
+        elf.extend(u16b(0)); // e_shentsize
+        elf.extend(u16b(0)); // e_shnum
+        elf.extend(u16b(0)); // e_shstrndx
+        assert_eq!(elf.len() as u64, phoff);
+
+        let total = strtab + strings.len() as u64;
+        for (kind, offset, size) in [
+            (PT_LOAD, 0, total),
+            (PT_INTERP, interp, 27),
+            (PT_DYNAMIC, dynamic, 5 * 16),
+        ] {
+            elf.extend(u32b(kind));
+            elf.extend(u32b(0)); // p_flags
+            elf.extend(u64b(offset));
+            elf.extend(u64b(BASE + offset));
+            elf.extend(u64b(BASE + offset));
+            elf.extend(u64b(size));
+            elf.extend(u64b(size));
+            elf.extend(u64b(8)); // p_align
+        }
+        assert_eq!(elf.len() as u64, dynamic);
+
+        for (tag, value) in [
+            (DT_NEEDED, 1),
+            (DT_NEEDED, 11),
+            (DT_RUNPATH, 21),
+            (DT_STRTAB, BASE + strtab),
+            (DT_NULL, 0),
+        ] {
+            elf.extend(u64b(tag));
+            elf.extend(u64b(value));
+        }
+        elf.extend(strings);
+        elf
+    }
+
+    #[test]
+    fn reads_linkage() {
+        let dir = tempfile::tempdir().unwrap();
+        let expected = Linkage {
+            interpreter: Some("/lib64/ld-linux-x86-64.so.2".to_owned()),
+            needed: vec!["libc.so.6".to_owned(), "libm.so.6".to_owned()],
+            runpath: Some("$ORIGIN/../lib".to_owned()),
+        };
+        for big_endian in [false, true] {
+            let path = dir.path().join("elf");
+            std::fs::write(&path, elf64(big_endian)).unwrap();
+            assert_eq!(linkage(&path).unwrap(), Some(expected.clone()));
+        }
+    }
+
+    #[test]
+    fn rejects_damaged_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("elf");
+        let dynamic = 64 + 3 * 56;
+
+        let mut far_headers = elf64(false);
+        far_headers[0x20..0x28].copy_from_slice(&u64::MAX.to_le_bytes());
+        let mut far_name = elf64(false);
+        far_name[dynamic + 8..dynamic + 16].copy_from_slice(&u64::MAX.to_le_bytes());
+        let truncated = elf64(false)[..100].to_vec();
+
+        for elf in [far_headers, far_name, truncated] {
+            std::fs::write(&path, elf).unwrap();
+            assert!(linkage(&path).is_err());
+        }
+    }
+
+    #[test]
+    fn ignores_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script");
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        assert_eq!(linkage(&path).unwrap(), None);
+    }
+}
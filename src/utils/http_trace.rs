@@ -0,0 +1,147 @@
+//! Capture of HTTP traffic in the HAR format.
+//!
+//! When `RUSTUP_HTTP_TRACE` names a file, every download adds an entry to it
+//! with the request and response headers, status and timings. Bodies are left
+//! out, so the capture can be attached to a bug report about a misbehaving
+//! network without sharing what was downloaded.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Instant, SystemTime};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use url::Url;
+
+pub(crate) struct HttpTrace {
+    started: SystemTime,
+    start: Instant,
+    response: Option<Response>,
+    body_size: u64,
+}
+
+struct Response {
+    received: Instant,
+    http_version: String,
+    status: u32,
+    request_headers: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+}
+
+impl HttpTrace {
+    pub(crate) fn start() -> Self {
+        Self {
+            started: SystemTime::now(),
+            start: Instant::now(),
+            response: None,
+            body_size: 0,
+        }
+    }
+
+    pub(crate) fn response(&mut self, response: download::Response<'_>) {
+        self.response = Some(Response {
+            received: Instant::now(),
+            http_version: response.http_version.to_owned(),
+            status: response.status,
+            request_headers: response.request_headers.to_vec(),
+            headers: response.headers.to_vec(),
+        });
+    }
+
+    /// Counts body data, leaving out any partial download being resumed,
+    /// which is replayed before the response arrives.
+    pub(crate) fn data(&mut self, len: usize) {
+        if self.response.is_some() {
+            self.body_size += len as u64;
+        }
+    }
+
+    /// Appends the entry for this download to the HAR file at `path`.
+    pub(crate) fn finish(
+        self,
+        path: &Path,
+        url: &Url,
+        error: Option<&anyhow::Error>,
+    ) -> Result<()> {
+        let mut har = fs::read_to_string(path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<Value>(&data).ok())
+            .filter(|har| har["log"]["entries"].is_array())
+            .unwrap_or_else(|| {
+                json!({
+                    "log": {
+                        "version": "1.2",
+                        "creator": { "name": "rustup", "version": env!("CARGO_PKG_VERSION") },
+                        "entries": [],
+                    }
+                })
+            });
+
+        let end = Instant::now();
+        let millis = |from: Instant, to: Instant| (to - from).as_secs_f64() * 1000.0;
+        let (wait, receive) = match &self.response {
+            Some(res) => (millis(self.start, res.received), millis(res.received, end)),
+            None => (millis(self.start, end), 0.0),
+        };
+        let headers = |headers: &[(String, String)]| {
+            headers
+                .iter()
+                .map(|(name, value)| json!({ "name": name, "value": value }))
+                .collect::<Vec<_>>()
+        };
+        let (http_version, status, request_headers, response_headers) = match &self.response {
+            Some(res) => (
+                res.http_version.as_str(),
+                res.status,
+                headers(&res.request_headers),
+                headers(&res.headers),
+            ),
+            None => ("", 0, vec![], vec![]),
+        };
+        let mime_type = self
+            .response
+            .iter()
+            .flat_map(|res| &res.headers)
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map_or("", |(_, value)| value.as_str());
+
+        let mut entry = json!({
+            "startedDateTime": DateTime::<Utc>::from(self.started)
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            "time": wait + receive,
+            "request": {
+                "method": "GET",
+                "url": url.as_str(),
+                "httpVersion": http_version,
+                "cookies": [],
+                "headers": request_headers,
+                "queryString": [],
+                "headersSize": -1,
+                "bodySize": 0,
+            },
+            "response": {
+                "status": status,
+                "statusText": "",
+                "httpVersion": http_version,
+                "cookies": [],
+                "headers": response_headers,
+                "content": { "size": self.body_size, "mimeType": mime_type },
+                "redirectURL": "",
+                "headersSize": -1,
+                "bodySize": self.body_size,
+            },
+            "cache": {},
+            "timings": { "send": 0, "wait": wait, "receive": receive },
+        });
+        if let Some(error) = error {
+            entry["_error"] = format!("{error:#}").into();
+        }
+
+        if let Some(entries) = har["log"]["entries"].as_array_mut() {
+            entries.push(entry);
+        }
+        fs::write(path, serde_json::to_string_pretty(&har)?)
+            .with_context(|| format!("could not write HTTP trace to '{}'", path.display()))
+    }
+}
@@ -1,5 +1,6 @@
 //!  Utility functions for Rustup
 
+use std::collections::BTreeSet;
 use std::env;
 use std::ffi::OsStr;
 use std::fs::{self, File};
@@ -7,6 +8,7 @@ use std::io::{self, BufReader, Write};
 use std::ops::{BitAnd, BitAndAssign};
 use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
+use std::sync::{Mutex, PoisonError};
 
 use anyhow::{anyhow, bail, Context, Result};
 use retry::delay::{jitter, Fibonacci};
@@ -14,19 +16,27 @@ use retry::{retry, OperationResult};
 use sha2::Sha256;
 #[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
 use tracing::info;
+use tracing::warn;
 use url::Url;
 
 use crate::errors::*;
 use crate::process::Process;
+use crate::utils::http_trace::HttpTrace;
 
 #[cfg(not(windows))]
 pub(crate) use crate::utils::raw::find_cmd;
 pub use crate::utils::raw::{is_file, path_exists};
 pub(crate) use crate::utils::{notifications::Notification, raw::is_directory};
 
+pub(crate) mod elf;
+pub(crate) mod http_trace;
 pub(crate) mod notifications;
 pub(crate) mod notify;
+pub(crate) mod pe;
+pub(crate) mod permissions;
+pub(crate) mod processes;
 pub mod raw;
+pub mod runtime;
 pub(crate) mod units;
 
 #[must_use]
@@ -165,14 +175,14 @@ pub(crate) async fn download_file_with_resume(
     process: &Process,
 ) -> Result<()> {
     use download::DownloadError as DEK;
-    match download_file_(
+    match runtime::in_runtime(download_file_(
         url,
         path,
         hasher,
         resume_from_partial,
         notify_handler,
         process,
-    )
+    ))
     .await
     {
         Ok(_) => Ok(()),
@@ -180,6 +190,18 @@ pub(crate) async fn download_file_with_resume(
             if e.downcast_ref::<std::io::Error>().is_some() {
                 return Err(e);
             }
+            if let Some(&DEK::RateLimited { retry_after, .. }) = e.downcast_ref::<DEK>() {
+                return Err(e).context(RustupError::DownloadRateLimited {
+                    url: url.clone(),
+                    retry_after,
+                });
+            }
+            if let Some(&DEK::HttpStatus(status @ 500..=599)) = e.downcast_ref::<DEK>() {
+                return Err(e).context(RustupError::DownloadServerFailed {
+                    url: url.clone(),
+                    status,
+                });
+            }
             let is_client_error = match e.downcast_ref::<DEK>() {
                 // Specifically treat the bad partial range error as not our
                 // fault in case it was something odd which happened.
@@ -214,13 +236,15 @@ async fn download_file_(
 ) -> Result<()> {
     #[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
     use download::TlsBackend;
-    use download::{Backend, Event};
+    use download::{Backend, Event, ObjectStore, Transport};
     use sha2::Digest;
     use std::cell::RefCell;
 
     notify_handler(Notification::DownloadingFile(url, path));
 
     let hasher = RefCell::new(hasher);
+    let trace_path = process.var_os("RUSTUP_HTTP_TRACE").map(PathBuf::from);
+    let trace = RefCell::new(trace_path.as_ref().map(|_| HttpTrace::start()));
 
     // This callback will write the download to disk and optionally
     // hash the contents, then forward the notification up the stack
@@ -231,7 +255,16 @@ async fn download_file_(
             }
         }
 
+        if let Some(trace) = trace.borrow_mut().as_mut() {
+            match msg {
+                Event::ResponseReceived(res) => trace.response(res),
+                Event::DownloadDataReceived(data) => trace.data(data.len()),
+                _ => {}
+            }
+        }
+
         match msg {
+            Event::ResponseReceived(_) => {}
             Event::DownloadContentLengthReceived(len) => {
                 notify_handler(Notification::DownloadContentLengthReceived(len));
             }
@@ -241,6 +274,20 @@ async fn download_file_(
             Event::ResumingPartialDownload => {
                 notify_handler(Notification::ResumingPartialDownload);
             }
+            Event::Retrying(delay) => {
+                notify_handler(Notification::DownloadRetrying(url, delay));
+            }
+            Event::InsecureTls(host) => {
+                // Once per server and run is enough to be noticed.
+                static WARNED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+                let first = WARNED
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(host.to_owned());
+                if first {
+                    notify_handler(Notification::InsecureTls(host));
+                }
+            }
         }
 
         Ok(())
@@ -304,22 +351,66 @@ async fn download_file_(
         _ => Backend::Curl,
     };
 
-    notify_handler(match backend {
-        #[cfg(feature = "curl-backend")]
-        Backend::Curl => Notification::UsingCurl,
-        #[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
-        Backend::Reqwest(_) => Notification::UsingReqwest,
-    });
+    // A transport registered by an embedder takes precedence for the URLs
+    // it handles. Object storage is reached through the chosen backend.
+    let registered = download::registered_transport(url);
+    let object_store;
+    let transport: &dyn Transport = match &registered {
+        Some(transport) => {
+            notify_handler(Notification::UsingTransport(transport.name()));
+            transport.as_ref()
+        }
+        None => {
+            notify_handler(match backend {
+                #[cfg(feature = "curl-backend")]
+                Backend::Curl => Notification::UsingCurl,
+                #[cfg(any(feature = "reqwest-rustls-tls", feature = "reqwest-native-tls"))]
+                Backend::Reqwest(_) => Notification::UsingReqwest,
+            });
+            match url.scheme() {
+                "s3" | "gs" => {
+                    object_store = ObjectStore::from_env(backend, &|name| process.var(name).ok());
+                    &object_store
+                }
+                _ => &backend,
+            }
+        }
+    };
 
-    let res = backend
-        .download_to_path(url, path, resume_from_partial, Some(callback))
-        .await;
+    let res = download::download_with_retry(
+        transport,
+        url,
+        path,
+        resume_from_partial,
+        Some(callback),
+        &download_retry(process),
+    )
+    .await;
 
     notify_handler(Notification::DownloadFinished);
 
+    if let (Some(trace_path), Some(trace)) = (trace_path, trace.into_inner()) {
+        if let Err(e) = trace.finish(&trace_path, url, res.as_ref().err()) {
+            warn!("{e:#}");
+        }
+    }
+
     res
 }
 
+/// How failed downloads are retried, with as many retries as
+/// `RUSTUP_MAX_RETRIES` asks for.
+pub(crate) fn download_retry(process: &Process) -> download::Retry {
+    download::Retry {
+        attempts: process
+            .var("RUSTUP_MAX_RETRIES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(download::Retry::default().attempts),
+        ..download::Retry::default()
+    }
+}
+
 pub(crate) fn parse_url(url: &str) -> Result<Url> {
     Url::parse(url).with_context(|| format!("failed to parse url: {url}"))
 }
@@ -389,7 +480,7 @@ pub fn hardlink_file(src: &Path, dest: &Path) -> Result<()> {
 }
 
 #[cfg(unix)]
-fn symlink_file(src: &Path, dest: &Path) -> Result<()> {
+pub(crate) fn symlink_file(src: &Path, dest: &Path) -> Result<()> {
     std::os::unix::fs::symlink(src, dest).with_context(|| RustupError::LinkingFile {
         src: PathBuf::from(src),
         dest: PathBuf::from(dest),
@@ -397,7 +488,7 @@ fn symlink_file(src: &Path, dest: &Path) -> Result<()> {
 }
 
 #[cfg(windows)]
-fn symlink_file(src: &Path, dest: &Path) -> Result<()> {
+pub(crate) fn symlink_file(src: &Path, dest: &Path) -> Result<()> {
     std::os::windows::fs::symlink_file(src, dest).with_context(|| RustupError::LinkingFile {
         src: PathBuf::from(src),
         dest: PathBuf::from(dest),
@@ -568,7 +659,8 @@ pub(crate) fn format_path_for_display(path: &str) -> String {
     }
 }
 
-#[cfg(target_os = "linux")]
+/// Moves `src` to `dest` on another file system, where a rename can't: copies
+/// it, flushes the copy to disk, and only then removes `src`.
 fn copy_and_delete<'a, N>(
     name: &'static str,
     src: &'a Path,
@@ -579,27 +671,62 @@ where
     N: From<Notification<'a>>,
 {
     // https://github.com/rust-lang/rustup/issues/1239
-    // This uses std::fs::copy() instead of the faster std::fs::rename() to
-    // avoid cross-device link errors.
-    if src.is_dir() {
-        copy_dir(src, dest, notify_handler).and(remove_dir_all::remove_dir_all(src).with_context(
-            || RustupError::RemovingDirectory {
+    let meta = fs::symlink_metadata(src).with_context(|| RustupError::ReadingFile {
+        name: "metadata for",
+        path: PathBuf::from(src),
+    })?;
+    if meta.is_symlink() {
+        // `copy_file` would link `dest` to `src`, which is about to go.
+        let target = fs::read_link(src)?;
+        symlink_file(&target, dest)?;
+        return remove_file(name, src);
+    }
+
+    match meta.is_dir() {
+        true => copy_dir(src, dest, notify_handler)?,
+        false => copy_file(src, dest)?,
+    }
+    sync_all(dest).with_context(|| format!("could not flush '{}' to disk", dest.display()))?;
+    match meta.is_dir() {
+        true => {
+            remove_dir_all::remove_dir_all(src).with_context(|| RustupError::RemovingDirectory {
                 name,
                 path: PathBuf::from(src),
-            },
-        ))
-    } else {
-        copy_file(src, dest).and(remove_file(name, src))
+            })
+        }
+        false => remove_file(name, src),
+    }
+}
+
+/// Flushes the file at `path`, or everything in the directory at `path`, to
+/// disk.
+fn sync_all(path: &Path) -> io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        for entry in fs::read_dir(path)? {
+            sync_all(&entry?.path())?;
+        }
+    }
+    // Windows only flushes files opened for writing, and can't open
+    // directories as files.
+    #[cfg(windows)]
+    if meta.is_file() {
+        fs::OpenOptions::new().write(true).open(path)?.sync_all()?;
+    }
+    #[cfg(not(windows))]
+    if !meta.is_symlink() {
+        File::open(path)?.sync_all()?;
     }
+    Ok(())
 }
 
+/// Renames `src` to `dest`, and where they are on different file systems, as
+/// with OverlayFS in Docker, copies `src` instead and removes it after.
 pub fn rename<'a, N>(
     name: &'static str,
     src: &'a Path,
     dest: &'a Path,
     notify_handler: &'a dyn Fn(N),
-    #[allow(unused_variables)] // Only used on Linux
-    process: &Process,
 ) -> Result<()>
 where
     N: From<Notification<'a>>,
@@ -608,8 +735,6 @@ where
     // 21 fib steps from 1 sums to ~28 seconds, hopefully more than enough
     // for our previous poor performance that avoided the race condition with
     // McAfee and Norton.
-    #[cfg(target_os = "linux")]
-    use libc::EXDEV;
     retry(
         Fibonacci::from_millis(1).map(jitter).take(26),
         || match fs::rename(src, dest) {
@@ -619,10 +744,7 @@ where
                     notify_handler(Notification::RenameInUse(src, dest).into());
                     OperationResult::Retry(e)
                 }
-                #[cfg(target_os = "linux")]
-                _ if process.var_os("RUSTUP_PERMIT_COPY_RENAME").is_some()
-                    && Some(EXDEV) == e.raw_os_error() =>
-                {
+                io::ErrorKind::CrossesDevices => {
                     match copy_and_delete(name, src, dest, notify_handler) {
                         Ok(()) => OperationResult::Ok(()),
                         Err(_) => OperationResult::Err(e),
@@ -779,4 +901,27 @@ mod tests {
         assert!(!f_path.exists());
         assert!(ensure_file_removed("f", &f_path).is_ok());
     }
+
+    #[test]
+    fn copy_and_delete_moves_a_directory() {
+        let tempdir = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+        let src = tempdir.path().join("src");
+        fs::create_dir_all(src.join("lib")).unwrap();
+        fs::write(src.join("lib/libstd.rlib"), "std").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("lib/libstd.rlib", src.join("link")).unwrap();
+
+        let dest = tempdir.path().join("dest");
+        copy_and_delete("component", &src, &dest, &|_: Notification<'_>| ()).unwrap();
+        assert!(!src.exists());
+        assert_eq!(
+            fs::read_to_string(dest.join("lib/libstd.rlib")).unwrap(),
+            "std"
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(dest.join("link")).unwrap(),
+            Path::new("lib/libstd.rlib")
+        );
+    }
 }
@@ -1,22 +1,41 @@
 use std::fmt::{self, Display};
 use std::path::Path;
+use std::time::Duration;
 
+use serde::Serialize;
 use url::Url;
 
-use crate::utils::notify::NotificationLevel;
+use crate::diskio::{storage::Storage, IoProfile};
+use crate::utils::notify::{serialize, NotificationLevel};
 use crate::utils::units::{self, Unit};
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "data", rename_all = "kebab-case")]
 pub enum Notification<'a> {
-    CreatingDirectory(&'a str, &'a Path),
-    LinkingDirectory(&'a Path, &'a Path),
-    CopyingDirectory(&'a Path, &'a Path),
-    RemovingDirectory(&'a str, &'a Path),
-    DownloadingFile(&'a Url, &'a Path),
+    CreatingDirectory(
+        &'a str,
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+    ),
+    LinkingDirectory(
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+    ),
+    CopyingDirectory(
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+    ),
+    RemovingDirectory(
+        &'a str,
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+    ),
+    DownloadingFile(
+        #[serde(serialize_with = "serialize::display")] &'a Url,
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+    ),
     /// Received the Content-Length of the to-be downloaded data.
     DownloadContentLengthReceived(u64),
     /// Received some data.
-    DownloadDataReceived(&'a [u8]),
+    DownloadDataReceived(#[serde(serialize_with = "serialize::len")] &'a [u8]),
     /// Download has finished.
     DownloadFinished,
     /// The things we're tracking that are not counted in bytes.
@@ -25,21 +44,36 @@ pub enum Notification<'a> {
     DownloadPushUnit(Unit),
     /// finish using an unusual unit.
     DownloadPopUnit,
-    NoCanonicalPath(&'a Path),
+    NoCanonicalPath(#[serde(serialize_with = "serialize::path")] &'a Path),
     ResumingPartialDownload,
+    /// A download failed in a way that may not happen again, or was rate
+    /// limited, and is retried after the delay.
+    DownloadRetrying(
+        #[serde(serialize_with = "serialize::display")] &'a Url,
+        #[serde(serialize_with = "serialize::secs")] Duration,
+    ),
     /// This would make more sense as a crate::notifications::Notification
     /// member, but the notification callback is already narrowed to
     /// utils::notifications by the time tar unpacking is called.
     SetDefaultBufferSize(usize),
+    /// The IO threads and RAM unpacking was tuned to use.
+    IoTuning(IoProfile, Storage, usize, usize),
     Error(String),
     UsingCurl,
     UsingReqwest,
+    /// Downloading with a transport registered by an embedder of rustup.
+    UsingTransport(&'a str),
+    /// Connecting to a server without verifying its certificate.
+    InsecureTls(&'a str),
     /// Renaming encountered a file in use error and is retrying.
     /// The InUse aspect is a heuristic - the OS specifies
     /// Permission denied, but as we work in users home dirs and
     /// running programs like virus scanner are known to cause this
     /// the heuristic is quite good.
-    RenameInUse(&'a Path, &'a Path),
+    RenameInUse(
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+        #[serde(serialize_with = "serialize::path")] &'a Path,
+    ),
 }
 
 impl Notification<'_> {
@@ -59,9 +93,11 @@ impl Notification<'_> {
             | DownloadFinished
             | ResumingPartialDownload
             | UsingCurl
-            | UsingReqwest => NotificationLevel::Debug,
+            | UsingReqwest
+            | UsingTransport(_)
+            | IoTuning(..) => NotificationLevel::Debug,
             RenameInUse(_, _) => NotificationLevel::Info,
-            NoCanonicalPath(_) => NotificationLevel::Warn,
+            NoCanonicalPath(_) | InsecureTls(_) | DownloadRetrying(_, _) => NotificationLevel::Warn,
             Error(_) => NotificationLevel::Error,
         }
     }
@@ -91,6 +127,11 @@ impl Display for Notification<'_> {
                 "using up to {} of RAM to unpack components",
                 units::Size::new(*size, units::Unit::B, units::UnitMode::Norm)
             ),
+            IoTuning(profile, storage, threads, ram) => write!(
+                f,
+                "unpacking with {threads} IO threads and up to {} of RAM, for {storage} ({profile} IO profile)",
+                units::Size::new(*ram, units::Unit::B, units::UnitMode::Norm)
+            ),
             DownloadingFile(url, _) => write!(f, "downloading file from: '{url}'"),
             DownloadContentLengthReceived(len) => write!(f, "download size is: '{len}'"),
             DownloadDataReceived(data) => write!(f, "received some data of size {}", data.len()),
@@ -99,8 +140,19 @@ impl Display for Notification<'_> {
             DownloadFinished => write!(f, "download finished"),
             NoCanonicalPath(path) => write!(f, "could not canonicalize path: '{}'", path.display()),
             ResumingPartialDownload => write!(f, "resuming partial download"),
+            DownloadRetrying(url, delay) => write!(
+                f,
+                "download of '{url}' failed, retrying in {}s",
+                delay.as_secs()
+            ),
             UsingCurl => write!(f, "downloading with curl"),
             UsingReqwest => write!(f, "downloading with reqwest"),
+            UsingTransport(name) => write!(f, "downloading with {name}"),
+            InsecureTls(host) => write!(
+                f,
+                "not verifying the TLS certificate of '{host}', as its settings say; \
+                 anyone between here and it can tamper with what is downloaded"
+            ),
         }
     }
 }
@@ -1,9 +1,11 @@
 use std::fmt;
 
+use serde::Serialize;
 use tracing::Level;
 
-#[derive(Debug)]
-pub(crate) enum NotificationLevel {
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationLevel {
     Trace,
     Debug,
     Info,
@@ -34,3 +36,47 @@ impl From<Level> for NotificationLevel {
         }
     }
 }
+
+/// How the values of notifications that aren't data of their own are
+/// serialized.
+pub(crate) mod serialize {
+    use std::fmt::Display;
+    use std::io;
+    use std::path::Path;
+    use std::time::Duration;
+
+    use serde::Serializer;
+
+    pub(crate) fn display<T: Display, S: Serializer>(value: &T, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(value)
+    }
+
+    pub(crate) fn error<S: Serializer>(e: &&anyhow::Error, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(&format_args!("{e:#}"))
+    }
+
+    pub(crate) fn io_error<S: Serializer>(
+        result: &io::Result<()>,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        match result {
+            Ok(()) => s.serialize_none(),
+            Err(e) => s.collect_str(e),
+        }
+    }
+
+    /// Paths that aren't UTF-8 are written lossily rather than failing the
+    /// whole notification.
+    pub(crate) fn path<S: Serializer>(path: &&Path, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(&path.display())
+    }
+
+    /// Only the length of data is kept.
+    pub(crate) fn len<S: Serializer>(data: &&[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u64(data.len() as u64)
+    }
+
+    pub(crate) fn secs<S: Serializer>(delay: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u64(delay.as_secs())
+    }
+}
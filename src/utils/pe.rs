@@ -0,0 +1,186 @@
+//! Just enough of a PE reader to tell which DLLs a Windows binary imports,
+//! so that a binary the loader refuses to start can be explained.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+const IMPORT_DIRECTORY: usize = 1;
+const IMPORT_DESCRIPTOR_SIZE: usize = 20;
+
+/// Reads the names of the DLLs the file at `path` imports, or `None` if it
+/// is not a PE file.
+pub(crate) fn imports(path: &Path) -> Result<Option<Vec<String>>> {
+    let pe = Pe(fs::read(path)?);
+    if pe.0.get(..2) != Some(b"MZ") {
+        return Ok(None);
+    }
+    let header = pe.u32_at(0x3c)? as usize;
+    if pe.bytes_at(header, 4)? != b"PE\0\0" {
+        return Ok(None);
+    }
+    let coff = header + 4;
+    let sections = pe.u16_at(coff + 2)?;
+    let optional = coff + 20;
+    let optional_size = pe.u16_at(coff + 16)? as usize;
+    let directories = match pe.u16_at(optional)? {
+        PE32_MAGIC => optional + 96,
+        PE32_PLUS_MAGIC => optional + 112,
+        magic => bail!("unknown PE optional header magic {magic:#x}"),
+    };
+    let directory_count = pe.u32_at(directories - 4)? as usize;
+    if directory_count <= IMPORT_DIRECTORY {
+        return Ok(Some(Vec::new()));
+    }
+    let import_rva = pe.u32_at(directories + IMPORT_DIRECTORY * 8)?;
+    if import_rva == 0 {
+        return Ok(Some(Vec::new()));
+    }
+
+    // Imports are given by their address once loaded, so find where the
+    // section holding each starts in the file.
+    let mut layout = Vec::with_capacity(sections.into());
+    for i in 0..usize::from(sections) {
+        let at = optional + optional_size + i * 40;
+        let size = pe.u32_at(at + 8)?.max(pe.u32_at(at + 16)?);
+        let address = pe.u32_at(at + 12)?;
+        let offset = pe.u32_at(at + 20)?;
+        layout.push((address, size, offset));
+    }
+    let offset_of = |rva: u32| {
+        layout
+            .iter()
+            .find(|(address, size, _)| (*address..address + size).contains(&rva))
+            .map(|(address, _, offset)| (rva - address + offset) as usize)
+            .with_context(|| format!("PE address {rva:#x} is outside of any section"))
+    };
+
+    let mut names = Vec::new();
+    let mut descriptor = offset_of(import_rva)?;
+    loop {
+        let entry = pe.bytes_at(descriptor, IMPORT_DESCRIPTOR_SIZE)?;
+        if entry.iter().all(|b| *b == 0) {
+            break;
+        }
+        names.push(pe.c_str_at(offset_of(pe.u32_at(descriptor + 12)?)?)?);
+        descriptor += IMPORT_DESCRIPTOR_SIZE;
+    }
+    Ok(Some(names))
+}
+
+struct Pe(Vec<u8>);
+
+impl Pe {
+    fn bytes_at(&self, offset: usize, len: usize) -> Result<&[u8]> {
+        match self.0.get(offset..offset + len) {
+            Some(bytes) => Ok(bytes),
+            None => bail!("PE file ends before offset {:#x}", offset + len),
+        }
+    }
+
+    fn u16_at(&self, offset: usize) -> Result<u16> {
+        let bytes = self.bytes_at(offset, 2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32> {
+        let bytes = self.bytes_at(offset, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn c_str_at(&self, offset: usize) -> Result<String> {
+        let Some(rest) = self.0.get(offset..) else {
+            bail!("PE file ends before offset {offset:#x}");
+        };
+        let len = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a PE32+ file with a single section, mapped at 0x1000, that
+    /// holds the import descriptors and the names of the DLLs they import.
+    fn pe64(dlls: &[&str]) -> Vec<u8> {
+        const SECTION_RVA: u32 = 0x1000;
+        let header = 0x40;
+        let optional = header + 24;
+        let section_table = optional + 240;
+        let section = section_table + 40;
+        let descriptors = (dlls.len() + 1) * IMPORT_DESCRIPTOR_SIZE;
+
+        let mut strings = Vec::new();
+        let mut name_offsets = Vec::new();
+        for dll in dlls {
+            name_offsets.push(descriptors + strings.len());
+            strings.extend(dll.as_bytes());
+            strings.push(0);
+        }
+        let section_size = (descriptors + strings.len()) as u32;
+
+        let mut pe = b"MZ".to_vec();
+        pe.resize(0x3c, 0);
+        pe.extend((header as u32).to_le_bytes()); // e_lfanew
+        pe.resize(header, 0);
+        pe.extend(b"PE\0\0");
+        pe.extend(0x8664u16.to_le_bytes()); // Machine
+        pe.extend(1u16.to_le_bytes()); // NumberOfSections
+        pe.extend([0; 12]);
+        pe.extend(240u16.to_le_bytes()); // SizeOfOptionalHeader
+        pe.extend(0x22u16.to_le_bytes()); // Characteristics
+        assert_eq!(pe.len(), optional);
+
+        pe.extend(PE32_PLUS_MAGIC.to_le_bytes());
+        pe.resize(optional + 108, 0);
+        pe.extend(16u32.to_le_bytes()); // NumberOfRvaAndSizes
+        pe.extend(0u64.to_le_bytes()); // export table
+        pe.extend(SECTION_RVA.to_le_bytes()); // import table
+        pe.extend((descriptors as u32).to_le_bytes());
+        pe.resize(section_table, 0);
+
+        pe.extend(b".idata\0\0");
+        pe.extend(section_size.to_le_bytes()); // VirtualSize
+        pe.extend(SECTION_RVA.to_le_bytes()); // VirtualAddress
+        pe.extend(section_size.to_le_bytes()); // SizeOfRawData
+        pe.extend((section as u32).to_le_bytes()); // PointerToRawData
+        pe.resize(section, 0);
+
+        for name in &name_offsets {
+            pe.extend([0; 12]);
+            pe.extend((SECTION_RVA + *name as u32).to_le_bytes()); // Name
+            pe.extend([0; 4]);
+        }
+        pe.extend([0; IMPORT_DESCRIPTOR_SIZE]);
+        pe.extend(strings);
+        pe
+    }
+
+    #[test]
+    fn reads_imports() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rustup.exe");
+        let dlls = [
+            "KERNEL32.dll",
+            "VCRUNTIME140.dll",
+            "api-ms-win-crt-runtime-l1-1-0.dll",
+        ];
+        std::fs::write(&path, pe64(&dlls)).unwrap();
+        assert_eq!(
+            imports(&path).unwrap(),
+            Some(dlls.map(String::from).to_vec())
+        );
+    }
+
+    #[test]
+    fn ignores_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rustup");
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        assert_eq!(imports(&path).unwrap(), None);
+    }
+}
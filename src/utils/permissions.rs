@@ -0,0 +1,412 @@
+//! Whether RUSTUP_HOME belongs to the user running rustup.
+//!
+//! Running rustup once with `sudo` leaves files owned by root in the user's
+//! RUSTUP_HOME, after which installs fail deep inside a transaction with a
+//! bare "Permission denied". A quick look at the top of RUSTUP_HOME when
+//! rustup starts turns that into an error that says what is wrong, and
+//! `rustup doctor --fix-permissions` puts it right.
+//!
+//! Running rustup as root is checked for the same reason: under `sudo`,
+//! `HOME` often still points at the invoking user, so files that user can't
+//! change end up in their RUSTUP_HOME or CARGO_HOME. Container builds, which
+//! run as root on purpose, acknowledge that with `--allow-root`.
+//!
+//! Only Unix ownership and modes are checked.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::process::Process;
+
+/// Something in RUSTUP_HOME that would stop its owner from changing it.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Problem {
+    /// The entry belongs to another user.
+    Owner { path: PathBuf, uid: u32 },
+    /// The entry's owner can't read or change it, or can't enter it if it
+    /// is a directory.
+    Mode { path: PathBuf, mode: u32 },
+}
+
+impl Problem {
+    pub(crate) fn path(&self) -> &Path {
+        match self {
+            Self::Owner { path, .. } | Self::Mode { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Owner { path, uid } => {
+                write!(f, "'{}' is owned by {}", path.display(), user_name(*uid))
+            }
+            Self::Mode { path, mode } => write!(
+                f,
+                "'{}' has mode {:o}, which its owner can't use",
+                path.display(),
+                mode & 0o7777
+            ),
+        }
+    }
+}
+
+/// Fails if this user can't change the top of `home`. Only `home` and the
+/// entries directly in it are looked at, as this runs every time rustup
+/// starts. When running as root, [`check_root`] is what applies.
+#[cfg(unix)]
+pub(crate) fn check_home(home: &Path, process: &Process, allow_root: bool) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    if euid() == 0 {
+        return check_root(&[home], process, allow_root);
+    }
+    if !home.exists() {
+        return Ok(());
+    }
+
+    let mut entries = vec![home.to_owned()];
+    if let Ok(dir) = std::fs::read_dir(home) {
+        entries.extend(dir.filter_map(|entry| Some(entry.ok()?.path())));
+    }
+    let denied = entries
+        .into_iter()
+        .filter(|path| !path.is_symlink() && !writable(path))
+        .collect::<Vec<_>>();
+    let Some(path) = denied.first() else {
+        return Ok(());
+    };
+    let owner = std::fs::symlink_metadata(path)
+        .map(|meta| format!(", which belongs to {}", user_name(meta.uid())))
+        .unwrap_or_default();
+    let more = match denied.len() {
+        1 => String::new(),
+        n => format!(" or {} other entries in RUSTUP_HOME", n - 1),
+    };
+    anyhow::bail!(
+        "this user can't change '{}'{owner}{more}, which usually comes from running rustup with sudo; \
+         to give RUSTUP_HOME back to this user, run \
+         `sudo env RUSTUP_HOME='{}' rustup doctor --fix-permissions`",
+        path.display(),
+        home.display()
+    )
+}
+
+#[cfg(not(unix))]
+pub(crate) fn check_home(_home: &Path, _process: &Process, _allow_root: bool) -> Result<()> {
+    Ok(())
+}
+
+/// What running as root would do to the directories rustup writes to.
+#[cfg(unix)]
+#[derive(Debug, PartialEq)]
+enum RootUse<'a> {
+    /// Nothing that rustup run by another user would trip over.
+    Fine,
+    /// `home`, or the directory it would be created in, belongs to `uid`,
+    /// who couldn't change what rustup creates there.
+    OtherOwner { home: &'a Path, uid: u32 },
+    /// Running under `sudo` installs into root's homes, where the user
+    /// `uid` who ran `sudo` won't find the toolchains.
+    SudoIntoRoot { uid: u32 },
+}
+
+/// Fails if running as root would leave files in `homes` that their owner
+/// can't change, unless `allow_root`, and warns if running under `sudo`
+/// installs for root rather than for the user who ran it.
+#[cfg(unix)]
+pub(crate) fn check_root(homes: &[&Path], process: &Process, allow_root: bool) -> Result<()> {
+    if euid() != 0 || allow_root {
+        return Ok(());
+    }
+    let sudo_uid = process.var("SUDO_UID").ok().and_then(|id| id.parse().ok());
+    let how = match sudo_uid {
+        Some(_) => "under sudo",
+        None => "as root",
+    };
+    match root_use(homes, 0, sudo_uid) {
+        RootUse::Fine => Ok(()),
+        RootUse::OtherOwner { home, uid } => anyhow::bail!(
+            "rustup is running {how}, but '{}' belongs to {}, who couldn't change the files \
+             rustup would create there; run rustup as that user instead, or pass \
+             `--allow-root` if root should own them",
+            home.display(),
+            user_name(uid)
+        ),
+        RootUse::SudoIntoRoot { uid } => {
+            tracing::warn!(
+                "rustup is running under sudo, so it installs for root, and {} won't see what it \
+                 installs; run rustup without sudo to install for that user, or pass \
+                 `--allow-root` to install for root",
+                user_name(uid)
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn check_root(_homes: &[&Path], _process: &Process, _allow_root: bool) -> Result<()> {
+    Ok(())
+}
+
+/// Classifies running as `uid` with `homes`, where `sudo_uid` is the user
+/// who ran `sudo`, if anyone did. A home that doesn't exist yet counts as
+/// belonging to the owner of the nearest directory it would be created in.
+#[cfg(unix)]
+fn root_use<'a>(homes: &[&'a Path], uid: u32, sudo_uid: Option<u32>) -> RootUse<'a> {
+    use std::os::unix::fs::MetadataExt;
+
+    for &home in homes {
+        let owner = home
+            .ancestors()
+            .find_map(|path| std::fs::metadata(path).ok())
+            .map(|meta| meta.uid());
+        match owner {
+            Some(owner) if owner != uid => return RootUse::OtherOwner { home, uid: owner },
+            _ => {}
+        }
+    }
+    match sudo_uid {
+        Some(sudo_uid) if sudo_uid != uid => RootUse::SudoIntoRoot { uid: sudo_uid },
+        _ => RootUse::Fine,
+    }
+}
+
+/// Every entry in `home`, including `home`, that doesn't belong to `uid`
+/// or whose mode keeps its owner out. Symlinks aren't followed.
+#[cfg(unix)]
+pub(crate) fn audit(home: &Path, uid: u32) -> Vec<Problem> {
+    use std::os::unix::fs::MetadataExt;
+
+    fn walk(path: &Path, uid: u32, problems: &mut Vec<Problem>) {
+        let Ok(meta) = std::fs::symlink_metadata(path) else {
+            return;
+        };
+        if meta.uid() != uid {
+            problems.push(Problem::Owner {
+                path: path.to_owned(),
+                uid: meta.uid(),
+            });
+        }
+        if !meta.is_symlink() && meta.mode() & required_mode(&meta) != required_mode(&meta) {
+            problems.push(Problem::Mode {
+                path: path.to_owned(),
+                mode: meta.mode(),
+            });
+        }
+        if meta.is_dir() {
+            let Ok(entries) = std::fs::read_dir(path) else {
+                return;
+            };
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                walk(&entry.path(), uid, problems);
+            }
+        }
+    }
+
+    let mut problems = Vec::new();
+    walk(home, uid, &mut problems);
+    problems
+}
+
+#[cfg(not(unix))]
+pub(crate) fn audit(_home: &Path, _uid: u32) -> Vec<Problem> {
+    Vec::new()
+}
+
+/// Gives the entries of `problems` to `uid` and `gid`, and their owner the
+/// access it lacks.
+#[cfg(unix)]
+pub(crate) fn fix(problems: &[Problem], uid: u32, gid: u32) -> Result<()> {
+    use std::os::unix::fs::{lchown, MetadataExt, PermissionsExt};
+
+    use anyhow::Context;
+
+    for problem in problems {
+        let path = problem.path();
+        match problem {
+            Problem::Owner { .. } => lchown(path, Some(uid), Some(gid))
+                .with_context(|| format!("could not change the owner of '{}'", path.display()))?,
+            Problem::Mode { .. } => {
+                let meta = std::fs::symlink_metadata(path)?;
+                std::fs::set_permissions(
+                    path,
+                    std::fs::Permissions::from_mode(meta.mode() | required_mode(&meta)),
+                )
+                .with_context(|| format!("could not change the mode of '{}'", path.display()))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn fix(_problems: &[Problem], _uid: u32, _gid: u32) -> Result<()> {
+    Ok(())
+}
+
+/// The user and group that RUSTUP_HOME should belong to: whoever ran
+/// `sudo`, when running under it, or else the owner of `home` when running
+/// as root, or else this user.
+#[cfg(unix)]
+pub(crate) fn intended_owner(home: &Path, process: &Process) -> (u32, u32) {
+    use std::os::unix::fs::MetadataExt;
+
+    let sudo = |var| process.var(var).ok().and_then(|id| id.parse().ok());
+    if let (Some(uid), Some(gid)) = (sudo("SUDO_UID"), sudo("SUDO_GID")) {
+        return (uid, gid);
+    }
+    // SAFETY: `getegid` can't fail.
+    let (uid, gid) = (euid(), unsafe { libc::getegid() });
+    match std::fs::metadata(home) {
+        Ok(meta) if uid == 0 => (meta.uid(), meta.gid()),
+        _ => (uid, gid),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn intended_owner(_home: &Path, _process: &Process) -> (u32, u32) {
+    (0, 0)
+}
+
+#[cfg(unix)]
+fn euid() -> u32 {
+    // SAFETY: `geteuid` can't fail.
+    unsafe { libc::geteuid() }
+}
+
+/// The mode bits the owner of an entry needs: reading and writing it, and
+/// entering it if it is a directory.
+#[cfg(unix)]
+fn required_mode(meta: &std::fs::Metadata) -> u32 {
+    match meta.is_dir() {
+        true => 0o700,
+        false => 0o600,
+    }
+}
+
+#[cfg(unix)]
+fn writable(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return true;
+    };
+    // SAFETY: `path` is a valid C string.
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// The name of the user with `uid`, or the uid if it has none.
+#[cfg(unix)]
+fn user_name(uid: u32) -> String {
+    use std::ffi::CStr;
+
+    let mut buf = vec![0; 4096];
+    let mut pwd = std::mem::MaybeUninit::<libc::passwd>::uninit();
+    let mut result = std::ptr::null_mut();
+    // SAFETY: `buf` outlives the use of `pwd`'s fields.
+    unsafe {
+        match libc::getpwuid_r(
+            uid,
+            pwd.as_mut_ptr(),
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        ) {
+            0 if !result.is_null() => format!(
+                "user '{}'",
+                CStr::from_ptr(pwd.assume_init().pw_name).to_string_lossy()
+            ),
+            _ => format!("uid {uid}"),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn user_name(uid: u32) -> String {
+    format!("uid {uid}")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    use super::*;
+
+    #[test]
+    fn audit_and_fix_modes() {
+        let home = tempfile::tempdir().unwrap();
+        let uid = fs::metadata(home.path()).unwrap().uid();
+        let toolchains = home.path().join("toolchains");
+        fs::create_dir(&toolchains).unwrap();
+        let settings = home.path().join("settings.toml");
+        fs::write(&settings, "").unwrap();
+        assert_eq!(audit(home.path(), uid), []);
+
+        fs::set_permissions(&settings, fs::Permissions::from_mode(0o444)).unwrap();
+        fs::set_permissions(&toolchains, fs::Permissions::from_mode(0o555)).unwrap();
+        let mut problems = audit(home.path(), uid);
+        problems.sort_by(|a, b| a.path().cmp(b.path()));
+        assert_eq!(
+            problems,
+            [
+                Problem::Mode {
+                    path: settings.clone(),
+                    mode: 0o100444
+                },
+                Problem::Mode {
+                    path: toolchains.clone(),
+                    mode: 0o40555
+                },
+            ]
+        );
+
+        fix(&problems, uid, fs::metadata(home.path()).unwrap().gid()).unwrap();
+        assert_eq!(audit(home.path(), uid), []);
+        assert_eq!(fs::metadata(&toolchains).unwrap().mode() & 0o777, 0o755);
+    }
+
+    #[test]
+    fn root_use_of_homes() {
+        let dir = tempfile::tempdir().unwrap();
+        let uid = fs::metadata(dir.path()).unwrap().uid();
+        let home = dir.path().join("home");
+        fs::create_dir(&home).unwrap();
+        let missing = dir.path().join("missing").join("cargo");
+
+        assert_eq!(root_use(&[&home, &missing], uid, None), RootUse::Fine);
+        assert_eq!(
+            root_use(&[&home, &missing], uid, Some(uid + 1)),
+            RootUse::SudoIntoRoot { uid: uid + 1 }
+        );
+        assert_eq!(root_use(&[&home], uid, Some(uid)), RootUse::Fine);
+        // A home that doesn't exist yet belongs to whoever owns its parent.
+        assert_eq!(
+            root_use(&[&missing], uid + 1, Some(uid)),
+            RootUse::OtherOwner {
+                home: &missing,
+                uid
+            }
+        );
+    }
+
+    #[test]
+    fn audit_other_owner() {
+        let home = tempfile::tempdir().unwrap();
+        let uid = fs::metadata(home.path()).unwrap().uid();
+        let problems = audit(home.path(), uid + 1);
+        assert_eq!(
+            problems,
+            [Problem::Owner {
+                path: home.path().to_owned(),
+                uid
+            }]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Finding the processes that run an executable from a directory, so that a
+//! toolchain isn't removed from under a build that is still using it.
+//!
+//! This is best effort: on platforms without a way to list processes, and
+//! for processes we aren't allowed to inspect, nothing is found.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RunningProcess {
+    pub pid: u32,
+    /// The executable the process is running.
+    pub exe: PathBuf,
+}
+
+/// The processes, other than this one, whose executable is inside `dir`.
+pub(crate) fn running_from(dir: &Path) -> Vec<RunningProcess> {
+    // The executables are reported with symlinks resolved on some platforms
+    // and not on others, so both forms of `dir` are tried.
+    let mut dirs = vec![dir.to_owned()];
+    dirs.extend(fs::canonicalize(dir).ok());
+    let this = std::process::id();
+    let mut processes = list()
+        .into_iter()
+        .filter(|p| p.pid != this && dirs.iter().any(|dir| p.exe.starts_with(dir)))
+        .collect::<Vec<_>>();
+    processes.sort_by_key(|p| p.pid);
+    processes
+}
+
+#[cfg(target_os = "linux")]
+fn list() -> Vec<RunningProcess> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            let exe = fs::read_link(entry.path().join("exe")).ok()?;
+            Some(RunningProcess { pid, exe })
+        })
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn list() -> Vec<RunningProcess> {
+    // `comm` is the full path of the executable on macOS.
+    let Ok(output) = std::process::Command::new("ps")
+        .args(["-axo", "pid=,comm="])
+        .output()
+    else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (pid, exe) = line.trim_start().split_once(' ')?;
+            Some(RunningProcess {
+                pid: pid.parse().ok()?,
+                exe: PathBuf::from(exe.trim()),
+            })
+        })
+        .collect()
+}
+
+#[cfg(windows)]
+fn list() -> Vec<RunningProcess> {
+    use std::mem;
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+        TH32CS_SNAPPROCESS,
+    };
+
+    let mut processes = Vec::new();
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return processes;
+        }
+        let snapshot = scopeguard::guard(snapshot, |h| {
+            let _ = CloseHandle(h);
+        });
+
+        let mut entry: PROCESSENTRY32W = mem::zeroed();
+        entry.dwSize = mem::size_of::<PROCESSENTRY32W>() as u32;
+        let mut more = Process32FirstW(*snapshot, &mut entry) != 0;
+        while more {
+            if let Some(exe) = image_path(entry.th32ProcessID) {
+                processes.push(RunningProcess {
+                    pid: entry.th32ProcessID,
+                    exe,
+                });
+            }
+            more = Process32NextW(*snapshot, &mut entry) != 0;
+        }
+    }
+    processes
+}
+
+/// The full path of the executable of process `pid`. The snapshot only has
+/// its file name.
+#[cfg(windows)]
+unsafe fn image_path(pid: u32) -> Option<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+    if process.is_null() {
+        return None;
+    }
+    let process = scopeguard::guard(process, |h| {
+        let _ = CloseHandle(h);
+    });
+
+    let mut buf = vec![0u16; 32768];
+    let mut len = buf.len() as u32;
+    if QueryFullProcessImageNameW(*process, PROCESS_NAME_WIN32, buf.as_mut_ptr(), &mut len) == 0 {
+        return None;
+    }
+    Some(PathBuf::from(OsString::from_wide(&buf[..len as usize])))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn list() -> Vec<RunningProcess> {
+    Vec::new()
+}
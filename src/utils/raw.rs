@@ -252,6 +252,13 @@ pub(crate) fn copy_dir(src: &Path, dest: &Path) -> io::Result<()> {
         let kind = entry.file_type()?;
         let src = entry.path();
         let dest = dest.join(entry.file_name());
+        // Kept a link, as in a toolchain's `lib`. Windows needs privileges
+        // to make one, so there the file it links to is copied.
+        #[cfg(unix)]
+        if kind.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(&src)?, &dest)?;
+            continue;
+        }
         if kind.is_dir() {
             copy_dir(&src, &dest)?;
         } else {
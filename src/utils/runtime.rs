@@ -0,0 +1,77 @@
+//! Running rustup's futures without starting tokio up front.
+//!
+//! Only downloads need the tokio runtime, and most runs of rustup, such as
+//! those of the proxies or `rustup show`, download nothing. So the CLI is
+//! driven by a trivial executor, and the runtime is started the first time
+//! a download is reached.
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use tokio::runtime::{Builder, Handle, Runtime};
+
+/// Runs `future` to completion on the current thread.
+///
+/// Futures that need the tokio runtime must be wrapped in [`in_runtime`].
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// The runtime of this process, started on first use.
+pub fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start the tokio runtime")
+    })
+}
+
+/// Runs `future`, which needs the tokio runtime, on [`runtime`] unless it
+/// already runs on one, as it does in tests and for embedders.
+pub(crate) async fn in_runtime<F: Future>(future: F) -> F::Output {
+    match Handle::try_current() {
+        Ok(_) => future.await,
+        Err(_) => runtime().block_on(future),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn runtime_started_on_demand() {
+        let slept = block_on(async {
+            assert!(Handle::try_current().is_err());
+            in_runtime(async {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                Handle::try_current().is_ok()
+            })
+            .await
+        });
+        assert!(slept);
+    }
+}
@@ -1,6 +1,9 @@
 use std::fmt::{self, Display};
 
-#[derive(Copy, Clone, Debug)]
+use serde::Serialize;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Unit {
     B,
     IO,
@@ -75,6 +78,42 @@ impl Display for Size {
     }
 }
 
+/// Formats a number of bytes without the padding used by progress output.
+pub(crate) fn format_bytes(size: u64) -> String {
+    Size::new(size as usize, Unit::B, UnitMode::Norm)
+        .to_string()
+        .trim_start()
+        .to_owned()
+}
+
+/// Parses a number of bytes such as `500MiB`, `1.5 GB` or `1024`, with
+/// either binary or decimal units.
+pub(crate) fn parse_bytes(size: &str) -> anyhow::Result<u64> {
+    const UNITS: [(&str, u64); 9] = [
+        ("kib", 1 << 10),
+        ("mib", 1 << 20),
+        ("gib", 1 << 30),
+        ("tib", 1 << 40),
+        ("kb", 1_000),
+        ("mb", 1_000_000),
+        ("gb", 1_000_000_000),
+        ("tb", 1_000_000_000_000),
+        ("b", 1),
+    ];
+
+    let lower = size.trim().to_ascii_lowercase();
+    let (number, factor) = UNITS
+        .iter()
+        .find_map(|(unit, factor)| Some((lower.strip_suffix(unit)?, *factor)))
+        .unwrap_or((&lower, 1));
+    match number.trim().parse::<f64>() {
+        Ok(n) if n >= 0.0 && n.is_finite() => Ok((n * factor as f64) as u64),
+        _ => Err(anyhow::anyhow!(
+            "invalid size '{size}', expected a number of bytes such as '500MiB'"
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -153,4 +192,25 @@ mod tests {
             "  1.0 giga-IOPS"
         );
     }
+
+    #[test]
+    fn format_bytes_test() {
+        use crate::utils::units::format_bytes;
+
+        assert_eq!(format_bytes(12), "12 B");
+        assert_eq!(format_bytes(143 * 1024 * 1024), "143.0 MiB");
+    }
+
+    #[test]
+    fn parse_bytes_test() {
+        use crate::utils::units::parse_bytes;
+
+        assert_eq!(parse_bytes("1024").unwrap(), 1024);
+        assert_eq!(parse_bytes("500MiB").unwrap(), 500 * 1024 * 1024);
+        assert_eq!(parse_bytes("1.5 GB").unwrap(), 1_500_000_000);
+        assert_eq!(parse_bytes("2kib").unwrap(), 2048);
+        assert_eq!(parse_bytes("7 B").unwrap(), 7);
+        assert!(parse_bytes("lots").is_err());
+        assert!(parse_bytes("-1MiB").is_err());
+    }
 }
@@ -276,6 +276,111 @@ nightly-{0} - Update available : 1.2.0 (hash-nightly-1) -> 1.3.0 (hash-nightly-2
     ).await;
 }
 
+#[tokio::test]
+async fn check_updates_exit_code() {
+    let mut cx = CliTestContext::new(Scenario::None).await;
+    let _dist_guard = cx.with_update_server(env!("CARGO_PKG_VERSION"));
+
+    {
+        let mut cx = cx.with_dist_dir(Scenario::ArchivesV2_2015_01_01);
+        cx.config
+            .expect_ok(&["rustup", "toolchain", "add", "stable"])
+            .await;
+        let out = cx.config.run("rustup", ["check", "--exit-code"], &[]).await;
+        assert_eq!(out.status, Some(0));
+    }
+
+    let cx = cx.with_dist_dir(Scenario::SimpleV2);
+    let out = cx.config.run("rustup", ["check", "--exit-code"], &[]).await;
+    assert_eq!(out.status, Some(2));
+    assert!(out.stderr.is_empty(), "{}", out.stderr);
+    let out = cx.config.run("rustup", ["check"], &[]).await;
+    assert_eq!(out.status, Some(0));
+
+    let args = ["update", "--no-self-update", "--exit-code"];
+    let out = cx.config.run("rustup", args, &[]).await;
+    assert_eq!(out.status, Some(2));
+    let out = cx.config.run("rustup", args, &[]).await;
+    assert_eq!(out.status, Some(0));
+    let out = cx.config.run("rustup", ["check", "--exit-code"], &[]).await;
+    assert_eq!(out.status, Some(0));
+}
+
+#[tokio::test]
+async fn self_update_exit_code() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let _dist_guard = cx.with_update_server("2.0.0");
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "add", "stable"])
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "set", "auto-self-update", "check-only"])
+        .await;
+
+    let out = cx.config.run("rustup", ["check", "--exit-code"], &[]).await;
+    assert_eq!(out.status, Some(3));
+    let out = cx.config.run("rustup", ["check", "--quiet"], &[]).await;
+    assert_eq!(out.status, Some(3));
+    let out = cx
+        .config
+        .run("rustup", ["update", "--exit-code"], &[])
+        .await;
+    assert_eq!(out.status, Some(3), "{}", out.stderr);
+    let out = cx.config.run("rustup", ["update"], &[]).await;
+    assert_eq!(out.status, Some(0));
+}
+
+#[tokio::test]
+async fn check_updates_json_and_quiet() {
+    let mut cx = CliTestContext::new(Scenario::None).await;
+    let _dist_guard = cx.with_update_server(env!("CARGO_PKG_VERSION"));
+
+    {
+        let mut cx = cx.with_dist_dir(Scenario::ArchivesV2_2015_01_01);
+        cx.config
+            .expect_ok(&["rustup", "toolchain", "add", "stable"])
+            .await;
+        let out = cx.config.run("rustup", ["check", "--quiet"], &[]).await;
+        assert_eq!(out.status, Some(0));
+        assert_eq!(out.stdout, "");
+    }
+
+    let cx = cx.with_dist_dir(Scenario::SimpleV2);
+    let out = cx.config.run("rustup", ["check", "--quiet"], &[]).await;
+    assert_eq!(out.status, Some(2));
+    assert_eq!(out.stdout, "");
+
+    let out = cx
+        .config
+        .run("rustup", ["check", "--output", "json"], &[])
+        .await;
+    assert_eq!(out.status, Some(0), "{}", out.stderr);
+    let check = serde_json::from_str::<serde_json::Value>(&out.stdout).unwrap();
+    assert_eq!(
+        check,
+        serde_json::json!({
+            "version": 1,
+            "update_available": true,
+            "toolchains": [{
+                "name": for_host!("stable-{0}"),
+                "current_version": "1.0.0 (hash-stable-1.0.0)",
+                "available_version": "1.1.0 (hash-stable-1.1.0)",
+                "update_available": true,
+            }],
+            "rustup": {
+                "current_version": env!("CARGO_PKG_VERSION"),
+                "available_version": env!("CARGO_PKG_VERSION"),
+                "update_available": false,
+            },
+        })
+    );
+    let out = cx
+        .config
+        .run("rustup", ["check", "--output", "json", "--exit-code"], &[])
+        .await;
+    assert_eq!(out.status, Some(2));
+}
+
 #[tokio::test]
 async fn default() {
     let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
@@ -533,6 +638,48 @@ async fn list_overrides_with_nonexistent() {
         .await;
 }
 
+#[tokio::test]
+#[cfg_attr(target_os = "windows", ignore)] // FIXME #1103
+async fn list_overrides_clean() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let nonexistent_path = {
+        let dir = tempfile::Builder::new()
+            .prefix("rustup-test")
+            .tempdir()
+            .unwrap();
+        let mut cx = cx.change_dir(dir.path());
+        cx.config
+            .expect_ok(&["rustup", "override", "add", "nightly"])
+            .await;
+        std::fs::canonicalize(dir.path()).unwrap()
+    };
+    let _ = rustup::utils::raw::remove_dir(&nonexistent_path);
+    assert!(!nonexistent_path.exists());
+
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "override", "list", "--output", "json"],
+            r#""exists": false"#,
+        )
+        .await;
+    cx.config
+        .expect_ok_ex(
+            &["rustup", "override", "list", "--clean"],
+            "no overrides\n",
+            &format!(
+                "info: override toolchain for '{}' removed\n",
+                nonexistent_path.display()
+            ),
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "override", "list", "--output", "json"],
+            r#""overrides": []"#,
+        )
+        .await;
+}
+
 #[tokio::test]
 async fn update_no_manifest() {
     let cx = CliTestContext::new(Scenario::SimpleV2).await;
@@ -1,6 +1,7 @@
 //! Tests of the interactive console installer
 
 use std::env::consts::EXE_SUFFIX;
+use std::fs;
 use std::io::Write;
 use std::process::Stdio;
 
@@ -46,6 +47,7 @@ fn run_input_with_env(
 
     SanitizedOutput {
         ok: out.status.success(),
+        status: out.status.code(),
         stdout: String::from_utf8(out.stdout).unwrap(),
         stderr: String::from_utf8(out.stderr).unwrap(),
     }
@@ -113,6 +115,30 @@ Rust is installed now. Great!
     }
 }
 
+#[tokio::test]
+async fn install_in_another_language() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let out = run_input_with_env(
+        &cx.config,
+        &["rustup-init", "--no-modify-path"],
+        "\n\n",
+        &[("RUSTUP_LANG", "es_ES.UTF-8")],
+    );
+    assert!(out.ok);
+    for line in [
+        "Esta ruta tiene que estar en tu variable de entorno PATH,",
+        "       toolchain predeterminada: stable (predeterminada)",
+        "1) Continuar con la instalación estándar (predeterminado: pulsa Intro)",
+        "¡Rust ya está instalado! ¡Genial!",
+    ] {
+        assert!(
+            out.stdout.contains(line),
+            "'{line}' not in:\n{}",
+            out.stdout
+        );
+    }
+}
+
 #[tokio::test]
 async fn smoke_case_install_with_path_install() {
     let cx = CliTestContext::new(Scenario::SimpleV2).await;
@@ -240,7 +266,7 @@ Profile (which tools and data to install)? (minimal/default/complete) [minimal]
 #[tokio::test]
 async fn installer_shows_default_for_modify_path() {
     let cx = CliTestContext::new(Scenario::SimpleV2).await;
-    let out = run_input(&cx.config, &["rustup-init"], "2\n\n\n\n");
+    let out = run_input(&cx.config, &["rustup-init"], "2\n\n\n\n\n");
 
     println!("-- stdout --\n {}", out.stdout);
     println!("-- stderr --\n {}", out.stderr);
@@ -257,7 +283,7 @@ async fn installer_shows_default_for_modify_path_when_set_with_args() {
     let out = run_input(
         &cx.config,
         &["rustup-init", "--no-modify-path"],
-        "2\n\n\n\n",
+        "2\n\n\n\n\n",
     );
 
     println!("-- stdout --\n {}", out.stdout);
@@ -358,7 +384,7 @@ async fn set_no_modify_path() {
     let out = run_input(
         &cx.config,
         &["rustup-init", "--no-modify-path"],
-        "2\n\n\n\nno\n\n\n",
+        "2\n\n\n\n\nno\n\n\n",
     );
     assert!(out.ok);
 
@@ -373,7 +399,7 @@ async fn set_nightly_toolchain_and_unset() {
     let out = run_input(
         &cx.config,
         &["rustup-init", "--no-modify-path"],
-        "2\n\nnightly\n\n\n2\n\nbeta\n\n\n\n\n",
+        "2\n\nnightly\n\n\n\n2\n\nbeta\n\n\n\n\n\n",
     );
     println!("{:?}", out.stderr);
     println!("{:?}", out.stdout);
@@ -384,13 +410,31 @@ async fn set_nightly_toolchain_and_unset() {
         .await;
 }
 
+#[tokio::test]
+async fn select_components_and_targets() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let out = run_input(
+        &cx.config,
+        &["rustup-init", "--no-modify-path"],
+        "2\n\n\n\n3\n9\n\n\n\n",
+    );
+    assert!(out.ok);
+    assert!(out.stdout.contains("  3) [x] rust-src\n"));
+    assert!(out.stdout.contains("            components: rust-src\n"));
+    assert!(out.stderr.contains("'9' is not one of the listed numbers"));
+
+    cx.config
+        .expect_stdout_ok(&["rustup", "component", "list"], "rust-src (installed)")
+        .await;
+}
+
 #[tokio::test]
 async fn user_says_nope_after_advanced_install() {
     let cx = CliTestContext::new(Scenario::SimpleV2).await;
     let out = run_input(
         &cx.config,
         &["rustup-init", "--no-modify-path"],
-        "2\n\n\n\n\nn\n\n\n",
+        "2\n\n\n\n\n\nn\n\n\n",
     );
     assert!(out.ok);
     assert!(!cx.config.cargodir.join("bin").exists());
@@ -556,12 +600,62 @@ async fn with_no_prompt_install_succeeds_if_rustc_exists() {
             &[
                 ("RUSTUP_INIT_SKIP_PATH_CHECK", "no"),
                 ("PATH", temp_dir_path),
+                // gpg isn't on this PATH to check the test key with.
+                ("RUSTUP_PGP_KEY", ""),
             ],
         )
         .await;
     assert!(out.ok);
 }
 
+#[tokio::test]
+async fn install_records_choice_about_existing_rust() {
+    let temp_dir = tempfile::Builder::new()
+        .prefix("fakebin")
+        .tempdir()
+        .unwrap();
+    // Create fake executable
+    let fake_exe = temp_dir.path().join(format!("{}{}", "rustc", EXE_SUFFIX));
+    raw::append_file(&fake_exe, "").unwrap();
+    let temp_dir_path = temp_dir.path().to_str().unwrap();
+
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let out = run_input_with_env(
+        &cx.config,
+        &["rustup-init", "--no-modify-path"],
+        "2\n\n",
+        &[
+            ("RUSTUP_INIT_SKIP_PATH_CHECK", "no"),
+            ("PATH", temp_dir_path),
+            // gpg isn't on this PATH to check the test key with.
+            ("RUSTUP_PGP_KEY", ""),
+        ],
+    );
+    assert!(out.ok);
+    assert!(out
+        .stdout
+        .contains("2) Continue and keep the existing installation first in PATH"));
+
+    let settings = fs::read_to_string(cx.config.rustupdir.join("settings.toml")).unwrap();
+    assert!(settings.contains("[system_rust]"), "{settings}");
+    assert!(settings.contains("rustup_first = false"), "{settings}");
+
+    // Anything but the listed options cancels the installation.
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let out = run_input_with_env(
+        &cx.config,
+        &["rustup-init", "--no-modify-path"],
+        "nope\n",
+        &[
+            ("RUSTUP_INIT_SKIP_PATH_CHECK", "no"),
+            ("PATH", temp_dir_path),
+        ],
+    );
+    assert!(!out.ok);
+    assert!(out.stderr.contains("aborting installation"));
+    assert!(!cx.config.cargodir.join("bin").exists());
+}
+
 // Issue 2547
 #[tokio::test]
 async fn install_non_installable_toolchain() {
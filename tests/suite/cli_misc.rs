@@ -2,6 +2,8 @@
 //! dist server, mostly derived from multirust/test-v2.sh
 
 use std::fs;
+use std::io::Write;
+use std::process::Stdio;
 use std::str;
 use std::{env::consts::EXE_SUFFIX, path::Path};
 
@@ -109,6 +111,49 @@ async fn custom_invalid_names_with_archive_dates() {
         .await;
 }
 
+#[tokio::test]
+async fn custom_reserved_names() {
+    let cx = CliTestContext::new(Scenario::None).await;
+    let path = cx.config.customdir.join("custom-1");
+    let path = path.to_string_lossy();
+    for name in ["self", "none", "Stable", "+dev"] {
+        cx.config
+            .expect_err(
+                &["rustup", "toolchain", "link", name, &path],
+                &format!("invalid custom toolchain name '{name}'"),
+            )
+            .await;
+    }
+}
+
+#[tokio::test]
+async fn legacy_custom_names_are_migrated() {
+    let cx = CliTestContext::new(Scenario::None).await;
+    let toolchains = cx.config.rustupdir.join("toolchains");
+    fs::create_dir_all(&toolchains).unwrap();
+    symlink_dir(
+        &cx.config.customdir.join("custom-1"),
+        &toolchains.join("self"),
+    )
+    .unwrap();
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "toolchain", "list"],
+            "ignoring toolchain 'self' as its name is no longer allowed",
+        )
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "self", "upgrade-data"],
+            "renamed toolchain 'self' to 'self-custom'",
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "toolchain", "list"], "self-custom")
+        .await;
+}
+
 // Regression test for newline placement
 #[tokio::test]
 async fn update_all_no_update_whitespace() {
@@ -491,6 +536,48 @@ async fn rustup_run_not_installed() {
         .await;
 }
 
+#[tokio::test]
+async fn rustup_run_with_tools_from_other_toolchains() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "install", "stable"]).await;
+    cx.config.expect_ok(&["rustup", "install", "nightly"]).await;
+
+    // cargo from stable runs rustc from nightly through the proxy.
+    let run = &["rustup", "run", "stable", "--rustc-from", "nightly", "--"];
+    cx.config
+        .expect_stdout_ok(
+            &[&run[..], &["cargo", "--version"]].concat(),
+            "hash-stable-1.1.0",
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &[&run[..], &["cargo", "--call-rustc"]].concat(),
+            "hash-nightly-2",
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &[&run[..], &["rustc", "--version"]].concat(),
+            "hash-nightly-2",
+        )
+        .await;
+
+    let run = &["rustup", "run", "nightly", "--cargo-from", "stable", "--"];
+    cx.config
+        .expect_stdout_ok(
+            &[&run[..], &["cargo", "--version"]].concat(),
+            "hash-stable-1.1.0",
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &[&run[..], &["cargo", "--call-rustc"]].concat(),
+            "hash-nightly-2",
+        )
+        .await;
+}
+
 #[tokio::test]
 async fn rustup_run_install() {
     let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
@@ -502,7 +589,7 @@ async fn rustup_run_install() {
                 "run",
                 "--install",
                 "nightly",
-                "cargo",
+                "rustc",
                 "--version",
             ],
             "info: installing component 'rustc'",
@@ -510,6 +597,33 @@ async fn rustup_run_install() {
         .await;
 }
 
+#[tokio::test]
+async fn rustup_run_auto_installs_minimal() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config
+        .expect_ok(&["rustup", "set", "auto-install", "enable"])
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "run", "nightly", "rustc", "--version"],
+            "hash-nightly-2",
+        )
+        .await;
+    cx.config
+        .expect_not_stdout_ok(
+            &[
+                "rustup",
+                "component",
+                "list",
+                "--toolchain",
+                "nightly",
+                "--installed",
+            ],
+            "rust-docs",
+        )
+        .await;
+}
+
 #[tokio::test]
 async fn toolchains_are_resolved_early() {
     let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
@@ -524,6 +638,130 @@ async fn toolchains_are_resolved_early() {
         .await;
 }
 
+#[tokio::test]
+async fn proxies_set_toolchain_env() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "stable"])
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "override", "set", "stable"])
+        .await;
+    let dir = cx.config.current_dir().canonicalize().unwrap();
+    let settings = cx.config.rustupdir.join("settings.toml");
+    let mut toml = fs::read_to_string(&settings).unwrap();
+    toml.push_str(&format!(
+        r#"
+[toolchain_env.stable-{host}]
+RUSTFLAGS = "-Ctarget-cpu=native"
+RUSTC_WRAPPER = "ccache"
+
+[toolchain_env.nightly-{host}]
+RUSTFLAGS = "-Zthreads=8"
+
+[override_env.{dir:?}]
+RUSTC_WRAPPER = "sccache"
+"#,
+        host = this_host_triple(),
+    ));
+    fs::write(&settings, toml).unwrap();
+
+    let args = ["--echo-env", "RUSTFLAGS", "RUSTC_WRAPPER"];
+    cx.config
+        .expect_stderr_ok(
+            &["rustc"].iter().chain(&args).copied().collect::<Vec<_>>(),
+            "RUSTFLAGS=-Ctarget-cpu=native\nRUSTC_WRAPPER=sccache",
+        )
+        .await;
+    // The override's variables are only set when the override is active.
+    cx.config
+        .expect_stderr_ok(
+            &["rustc", "+nightly"]
+                .iter()
+                .chain(&args)
+                .copied()
+                .collect::<Vec<_>>(),
+            "RUSTFLAGS=-Zthreads=8\nRUSTC_WRAPPER=\n",
+        )
+        .await;
+    let out = cx
+        .config
+        .run("rustc", args, &[("RUSTFLAGS", "-Copt-level=1")])
+        .await;
+    assert!(out.stderr.contains("RUSTFLAGS=-Copt-level=1\n"));
+}
+
+#[tokio::test]
+async fn proxy_run_resolves_like_a_proxy() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "stable"])
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "proxy", "run", "rustc", "--version"],
+            "hash-nightly-2",
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "proxy", "run", "rustc", "+stable", "--version"],
+            "hash-stable-1.1.0",
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &[
+                "rustup",
+                "+stable",
+                "proxy",
+                "run",
+                "rustc",
+                "--",
+                "--version",
+            ],
+            "hash-stable-1.1.0",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "proxy", "run", "rustup-init"],
+            "unknown proxy name: 'rustup-init'",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn proxies_trace_their_phases() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let out = cx
+        .config
+        .run(
+            "rustc",
+            ["--version"],
+            &[("RUSTUP_LOG", "rustup::proxy=trace")],
+        )
+        .await;
+    assert!(out.ok);
+    for phase in [
+        "arg_parse",
+        "settings_load",
+        "override_walk",
+        "manifest_check",
+    ] {
+        assert!(
+            out.stderr
+                .contains(&format!("{phase}: rustup::proxy: close time.busy=")),
+            "{}",
+            out.stderr
+        );
+    }
+    assert!(out.stderr.contains("exec: rustup::proxy: running '"));
+}
+
 // #190
 #[tokio::test]
 async fn proxies_pass_empty_args() {
@@ -1096,6 +1334,79 @@ async fn which_asking_uninstalled_toolchain() {
         .await;
 }
 
+#[tokio::test]
+async fn which_all_and_json() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "stable"]).await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+    let stable = for_host!("stable-{0}");
+    let nightly = for_host!("nightly-{0}");
+    let toolchains = cx.config.rustupdir.join("toolchains");
+    let rustc = |toolchain: &str| {
+        toolchains
+            .join(toolchain)
+            .join("bin")
+            .join(format!("rustc{EXE_SUFFIX}"))
+    };
+
+    cx.config
+        .expect_ok_ex(
+            &["rustup", "which", "--all", "rustc"],
+            &format!(
+                "{stable} (active): {}\n{nightly}: {}\n",
+                rustc(stable).display(),
+                rustc(nightly).display(),
+            ),
+            "",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "which", "--all", "rustc-bogus"],
+            "no installed toolchain has the command 'rustc-bogus'",
+        )
+        .await;
+
+    let json = |out: clitools::SanitizedOutput| {
+        assert!(out.ok, "{}", out.stderr);
+        serde_json::from_str::<serde_json::Value>(&out.stdout).unwrap()
+    };
+    let which = json(
+        cx.config
+            .run("rustup", ["which", "rustc", "--output", "json"], &[])
+            .await,
+    );
+    assert_eq!(which["version"], 1);
+    assert_eq!(which["command"], "rustc");
+    assert_eq!(
+        which["binaries"],
+        serde_json::json!([{
+            "toolchain": stable,
+            "path": rustc(stable),
+            "sysroot": toolchains.join(stable),
+            "reason": { "kind": "default" },
+        }])
+    );
+
+    let which = json(
+        cx.config
+            .run(
+                "rustup",
+                ["which", "rustc", "--all", "--output", "json"],
+                &[("RUSTUP_TOOLCHAIN", "nightly")],
+            )
+            .await,
+    );
+    let binaries = which["binaries"].as_array().unwrap();
+    assert_eq!(binaries.len(), 2);
+    assert_eq!(binaries[0]["toolchain"], *stable);
+    assert!(binaries[0].get("reason").is_none());
+    assert_eq!(binaries[1]["toolchain"], *nightly);
+    assert_eq!(binaries[1]["reason"]["kind"], "environment");
+}
+
 #[tokio::test]
 async fn override_by_toolchain_on_the_command_line() {
     let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
@@ -1239,3 +1550,366 @@ async fn toolchain_install_multi_components_comma() {
             .await;
     }
 }
+
+#[tokio::test]
+async fn self_bench_proxy_against_baseline() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let timings = cx.config.test_root_dir.join("proxy-timings.toml");
+    let timings = timings.to_string_lossy();
+    cx.config
+        .expect_stdout_ok(
+            &[
+                "rustup",
+                "self",
+                "bench-proxy",
+                "--iterations",
+                "3",
+                "--save",
+                &timings,
+            ],
+            "overhead: ",
+        )
+        .await;
+    let saved = fs::read_to_string(&*timings).unwrap();
+    assert!(saved.contains("tool = \"rustc\""));
+
+    cx.config
+        .expect_err(
+            &["rustup", "self", "bench-proxy", "--iterations", "1"],
+            "at least 2 iterations are needed",
+        )
+        .await;
+    // A generous threshold keeps the comparison itself from being flaky.
+    cx.config
+        .expect_stderr_ok(
+            &[
+                "rustup",
+                "self",
+                "bench-proxy",
+                "--iterations",
+                "3",
+                "--baseline",
+                &timings,
+                "--threshold",
+                "100000",
+            ],
+            "of the baseline",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn self_diagnose_tls_needs_https() {
+    let cx = CliTestContext::new(Scenario::None).await;
+    // The test dist server is a `file:` URL.
+    cx.config
+        .expect_err(&["rustup", "self", "diagnose-tls"], "is not an https URL")
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "self", "diagnose-tls", "http://example.com"],
+            "'http://example.com/' is not an https URL",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn set_crash_reports() {
+    let cx = CliTestContext::new(Scenario::None).await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "crash-reports", "enable"],
+            "crash-reports mode set to 'enable'",
+        )
+        .await;
+    let settings =
+        fs::read_to_string(cx.config.rustupdir.join("settings.toml")).unwrap_or_default();
+    assert!(settings.contains("crash_reports = \"enable\""));
+
+    cx.config
+        .expect_err(
+            &["rustup", "set", "crash-reports", "upload"],
+            "invalid value 'upload'",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn set_output_style() {
+    let cx = CliTestContext::new(Scenario::None).await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "output-style", "plain"],
+            "output style set to 'plain'",
+        )
+        .await;
+    let settings =
+        fs::read_to_string(cx.config.rustupdir.join("settings.toml")).unwrap_or_default();
+    assert!(settings.contains("output_style = \"plain\""));
+
+    cx.config
+        .expect_err(
+            &["rustup", "set", "output-style", "quiet"],
+            "invalid value 'quiet'",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn set_palette() {
+    let cx = CliTestContext::new(Scenario::None).await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "palette", "light"],
+            "palette set to 'light'",
+        )
+        .await;
+    let settings =
+        fs::read_to_string(cx.config.rustupdir.join("settings.toml")).unwrap_or_default();
+    assert!(settings.contains("palette = \"light\""));
+}
+
+#[tokio::test]
+async fn schedule_status() {
+    let cx = CliTestContext::new(Scenario::None).await;
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "schedule", "status"],
+            "automatic updates are not scheduled\n",
+        )
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "schedule", "disable"],
+            "automatic updates are not scheduled",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "schedule", "enable-auto-update", "hourly"],
+            "invalid value 'hourly'",
+        )
+        .await;
+
+    #[cfg(target_os = "linux")]
+    {
+        let units = cx.config.homedir.join(".config/systemd/user");
+        fs::create_dir_all(&units).unwrap();
+        fs::write(
+            units.join("rustup-update.timer"),
+            "[Timer]\nOnCalendar=monthly\n",
+        )
+        .unwrap();
+        cx.config
+            .expect_stdout_ok(
+                &["rustup", "schedule", "status"],
+                "automatic updates run monthly (systemd timer",
+            )
+            .await;
+    }
+}
+
+#[tokio::test]
+async fn registered_transport() {
+    use rustup::download::{
+        self, DownloadCallback, DownloadError, Event, Transport, TransportFuture,
+    };
+    use std::sync::Arc;
+    use url::Url;
+
+    /// Serves `mirror://` URLs from the local file system.
+    struct Mirror;
+
+    impl Transport for Mirror {
+        fn name(&self) -> &str {
+            "the test mirror"
+        }
+
+        fn handles(&self, url: &Url) -> bool {
+            url.scheme() == "mirror"
+        }
+
+        fn download<'a>(
+            &'a self,
+            url: &'a Url,
+            resume_from: u64,
+            callback: DownloadCallback<'a>,
+        ) -> TransportFuture<'a> {
+            Box::pin(async move {
+                let data = fs::read(url.path()).map_err(|_| DownloadError::FileNotFound)?;
+                callback(Event::DownloadDataReceived(&data[resume_from as usize..]))
+            })
+        }
+    }
+
+    download::register_transport(Arc::new(Mirror));
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let dist_server = format!("mirror://{}", cx.config.distdir.as_ref().unwrap().display());
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["-v", "toolchain", "install", "nightly"],
+            &[("RUSTUP_DIST_SERVER", &dist_server)],
+        )
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    // The mock manifest points at `file://` URLs for the components, so only
+    // the manifest itself comes from the mirror.
+    assert!(out.stderr.contains(&format!(
+        "downloading file from: '{dist_server}/dist/channel-rust-nightly.toml'"
+    )));
+    assert!(out.stderr.contains("downloading with the test mirror"));
+    cx.config
+        .expect_stdout_ok(&["rustc", "+nightly", "--version"], "hash-nightly-2")
+        .await;
+}
+
+#[tokio::test]
+async fn rate_limited_manifest_is_retried() {
+    use rustup::download::{
+        self, DownloadCallback, DownloadError, Event, Transport, TransportFuture,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use url::Url;
+
+    /// Serves `throttled://` URLs from the local file system, but rate limits
+    /// the first requests for the manifest.
+    struct Throttled(AtomicUsize);
+
+    impl Transport for Throttled {
+        fn name(&self) -> &str {
+            "the throttled mirror"
+        }
+
+        fn handles(&self, url: &Url) -> bool {
+            url.scheme() == "throttled"
+        }
+
+        fn download<'a>(
+            &'a self,
+            url: &'a Url,
+            resume_from: u64,
+            callback: DownloadCallback<'a>,
+        ) -> TransportFuture<'a> {
+            Box::pin(async move {
+                if url.path().ends_with(".toml") && self.0.fetch_add(1, Ordering::SeqCst) < 2 {
+                    return Err(DownloadError::RateLimited {
+                        status: 429,
+                        retry_after: Some(Duration::ZERO),
+                    }
+                    .into());
+                }
+                let data = fs::read(url.path()).map_err(|_| DownloadError::FileNotFound)?;
+                callback(Event::DownloadDataReceived(&data[resume_from as usize..]))
+            })
+        }
+    }
+
+    download::register_transport(Arc::new(Throttled(AtomicUsize::new(0))));
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let dist_server = format!(
+        "throttled://{}",
+        cx.config.distdir.as_ref().unwrap().display()
+    );
+    // The one retry of each request isn't enough, but the manifest is
+    // fetched again once the server allows it.
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["toolchain", "install", "nightly"],
+            &[
+                ("RUSTUP_DIST_SERVER", &dist_server),
+                ("RUSTUP_MAX_RETRIES", "1"),
+            ],
+        )
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    let retrying = format!(
+        "download of '{dist_server}/dist/channel-rust-nightly.toml' failed, retrying in 0s"
+    );
+    assert_eq!(out.stderr.matches(&retrying).count(), 2, "{}", out.stderr);
+}
+
+#[tokio::test]
+async fn auth_tokens_stay_out_of_settings() {
+    let cx = CliTestContext::new(Scenario::None).await;
+    let run = |args: &'static [&'static str]| {
+        let config = &cx.config;
+        async move {
+            config
+                .run("rustup", args, &[("RUSTUP_CREDENTIAL_STORE", "file")])
+                .await
+        }
+    };
+
+    // The token is only read from stdin, to keep it out of the process list.
+    let mut login = clitools::cmd(
+        &cx.config,
+        "rustup",
+        ["auth", "login", "--dist-server", "https://rust.example.com"],
+    );
+    clitools::env(&cx.config, &mut login);
+    login.env("RUSTUP_CREDENTIAL_STORE", "file");
+    let mut child = login
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"s3cret\n").unwrap();
+    let out = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(out.status.success(), "{stderr}");
+    assert!(stderr.contains("token for 'rust.example.com' stored in '"));
+    assert!(!String::from_utf8(out.stdout).unwrap().contains("s3cret"));
+    let credentials = fs::read_to_string(cx.config.rustupdir.join("credentials.toml")).unwrap();
+    assert!(credentials.contains("s3cret"));
+    let settings =
+        fs::read_to_string(cx.config.rustupdir.join("settings.toml")).unwrap_or_default();
+    assert!(!settings.contains("s3cret"));
+
+    let out = run(&[
+        "auth",
+        "status",
+        "--dist-server",
+        "https://rust.example.com/",
+    ])
+    .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(out
+        .stdout
+        .starts_with("rust.example.com: logged in, with a token in '"));
+    let out = run(&[
+        "auth",
+        "status",
+        "--dist-server",
+        "https://rust.example.com:8443",
+    ])
+    .await;
+    assert_eq!(out.stdout, "rust.example.com:8443: not logged in\n");
+
+    let out = run(&[
+        "auth",
+        "logout",
+        "--dist-server",
+        "https://rust.example.com",
+    ])
+    .await;
+    assert!(out.ok, "{}", out.stderr);
+    let out = run(&[
+        "auth",
+        "logout",
+        "--dist-server",
+        "https://rust.example.com",
+    ])
+    .await;
+    assert!(!out.ok);
+    assert!(out
+        .stderr
+        .contains("there is no token for 'rust.example.com'"));
+}
@@ -360,6 +360,40 @@ export PATH="$HOME/apple/bin"
         assert_eq!(new_profile, FAKE_RC);
     }
 
+    #[tokio::test]
+    async fn toggle_system_switches_path_precedence() {
+        let mut cx = CliTestContext::new(Scenario::Empty).await;
+        let fake_bin = tempfile::Builder::new()
+            .prefix("fakebin")
+            .tempdir()
+            .unwrap();
+        raw::append_file(&fake_bin.path().join("rustc"), "").unwrap();
+        let path = fake_bin.path().to_str().unwrap();
+        let env = cx.config.cargodir.join("env");
+        let cargo_bin = cx.config.cargodir.join("bin");
+
+        cx.config.expect_ok(&INIT_NONE).await;
+        let prepend = format!("export PATH=\"{}:$PATH\"", cargo_bin.display());
+        assert!(fs::read_to_string(&env).unwrap().contains(&prepend));
+
+        let out = cx
+            .config
+            .run("rustup", ["toggle-system"], &[("PATH", path)])
+            .await;
+        assert!(out.ok);
+        assert!(out.stderr.contains("now comes before rustup in PATH"));
+        let append = format!("export PATH=\"$PATH:{}\"", cargo_bin.display());
+        assert!(fs::read_to_string(&env).unwrap().contains(&append));
+        let settings = fs::read_to_string(cx.config.rustupdir.join("settings.toml")).unwrap();
+        assert!(settings.contains("rustup_first = false"), "{settings}");
+
+        // The recorded installation is used even once it has left PATH.
+        cx.config
+            .expect_stderr_ok(&["rustup", "toggle-system"], "rustup now comes before")
+            .await;
+        assert!(fs::read_to_string(&env).unwrap().contains(&prepend));
+    }
+
     #[tokio::test]
     async fn install_doesnt_modify_path_if_passed_no_modify_path() {
         let mut cx = CliTestContext::new(Scenario::Empty).await;
@@ -520,6 +520,66 @@ async fn link() {
         .await;
 }
 
+#[tokio::test]
+async fn rename_custom_toolchain() {
+    let mut cx = CliTestContext::new(Scenario::None).await;
+    let path = cx.config.customdir.join("custom-1");
+    let path = path.to_string_lossy();
+    let path2 = cx.config.customdir.join("custom-2");
+    let path2 = path2.to_string_lossy();
+    let project = cx.config.current_dir().join("project");
+    fs::create_dir_all(&project).unwrap();
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "link", "custom", &path])
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "link", "other", &path2])
+        .await;
+    cx.config.expect_ok(&["rustup", "default", "custom"]).await;
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "override",
+            "set",
+            "custom",
+            "--path",
+            &project.to_string_lossy(),
+        ])
+        .await;
+
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "rename", "custom", "other"],
+            "as a toolchain by that name already exists",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "rename", "missing", "dev"],
+            "toolchain 'missing' is not installed",
+        )
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "toolchain", "rename", "custom", "dev"],
+            "renamed toolchain 'custom' to 'dev'",
+        )
+        .await;
+
+    cx.config
+        .expect_stdout_ok(&["rustc", "--version"], "hash-c-1")
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "default"], "dev (default)")
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "override", "list"], "dev")
+        .await;
+    cx.config
+        .expect_not_stdout_ok(&["rustup", "toolchain", "list"], "custom")
+        .await;
+}
+
 // Issue #809. When we call the fallback cargo, when it in turn invokes
 // "rustc", that rustc should actually be the rustup proxy, not the toolchain rustc.
 // That way the proxy can pick the correct toolchain.
@@ -914,6 +974,44 @@ async fn list_default_and_override_toolchain() {
         .await;
 }
 
+#[tokio::test]
+async fn show_json() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let out = cx
+        .config
+        .run("rustup", ["show", "--output", "json"], &[])
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    let show = serde_json::from_str::<serde_json::Value>(&out.stdout).unwrap();
+    assert_eq!(show["version"], 1);
+    assert_eq!(show["default_host"], this_host_triple());
+    assert_eq!(show["installed_toolchains"], serde_json::json!([]));
+    assert_eq!(show["active_toolchain"], serde_json::Value::Null);
+
+    cx.config
+        .expect_ok(&["rustup", "override", "add", "nightly"])
+        .await;
+    let out = cx
+        .config
+        .run("rustup", ["show", "--output", "json"], &[])
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    let show = serde_json::from_str::<serde_json::Value>(&out.stdout).unwrap();
+    let active = &show["active_toolchain"];
+    assert_eq!(active["name"], *for_host!("nightly-{}"));
+    assert_eq!(active["reason"]["kind"], "directory-override");
+    assert_eq!(
+        active["installed_targets"],
+        serde_json::json!([this_host_triple()])
+    );
+    assert!(active["installed_components"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!(for_host!("rustc-{}"))));
+    assert_eq!(show["installed_toolchains"][0]["active"], true);
+    assert_eq!(show["installed_toolchains"][0]["default"], false);
+}
+
 #[tokio::test]
 #[ignore = "FIXME: Windows shows UNC paths"]
 async fn show_toolchain_override() {
@@ -1112,6 +1210,63 @@ async fn override_set_unset_with_path() {
         .await;
 }
 
+#[tokio::test]
+async fn override_set_multiple_paths() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let root = tempfile::tempdir().unwrap();
+    let root = fs::canonicalize(root.path()).unwrap();
+    for dir in ["a", "b", "c", "crates/x", "crates/y"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    let list = root.join("overrides.txt");
+    fs::write(&list, "# extra projects\n\nc\n").unwrap();
+
+    let mut cx = cx.change_dir(&root);
+    cx.config
+        .expect_err(
+            &[
+                "rustup",
+                "override",
+                "set",
+                "nightly",
+                "--path",
+                "missing/*",
+            ],
+            "no directories match 'missing/*'",
+        )
+        .await;
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "override",
+            "set",
+            "nightly",
+            "--path",
+            "a",
+            "--path",
+            &root.join("b").to_string_lossy(),
+            "--path",
+            "crates/*",
+            "--from-file",
+            &list.to_string_lossy(),
+        ])
+        .await;
+
+    let out = cx.config.run("rustup", ["override", "list"], &[]).await;
+    assert!(out.ok);
+    let toolchain = format!("nightly-{}", this_host_triple());
+    for dir in ["a", "b", "c", "crates/x", "crates/y"] {
+        let dir = format!("{} ", root.join(dir).display());
+        assert!(
+            out.stdout
+                .lines()
+                .any(|l| l.starts_with(&dir) && l.ends_with(&toolchain)),
+            "{}",
+            out.stdout
+        );
+    }
+}
+
 #[tokio::test]
 async fn show_toolchain_env() {
     let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
@@ -1691,6 +1846,40 @@ async fn add_remove_multiple_components() {
     }
 }
 
+#[tokio::test]
+async fn add_multiple_components_at_once() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let src = format!(
+        "toolchains/nightly-{}/lib/rustlib/src/rust-src/foo.rs",
+        this_host_triple()
+    );
+
+    // Nothing is added unless everything can be.
+    cx.config
+        .expect_err(
+            &["rustup", "component", "add", "rust-src", "rust-bogus"],
+            "does not contain component 'rust-bogus'",
+        )
+        .await;
+    assert!(!cx.config.rustupdir.has(&src));
+
+    // Everything is downloaded before anything is installed.
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["component", "add", "rust-src", "rust-analysis"],
+            &[],
+        )
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    let last_download = out.stderr.rfind("downloading component").unwrap();
+    let first_install = out.stderr.find("installing component").unwrap();
+    assert!(last_download < first_install, "{}", out.stderr);
+    assert!(cx.config.rustupdir.has(&src));
+}
+
 #[tokio::test]
 async fn file_override() {
     let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
@@ -2178,6 +2367,134 @@ components = [ "rust-src" ]
         .await;
 }
 
+#[tokio::test]
+async fn file_override_toml_format_warns_of_missing_components() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "stable"]).await;
+
+    let cwd = cx.config.current_dir();
+    let toolchain_file = cwd.join("rust-toolchain.toml");
+    raw::write_file(
+        &toolchain_file,
+        r#"
+[toolchain]
+components = [ "rust-src" ]
+"#,
+    )
+    .unwrap();
+
+    cx.config
+        .expect_stderr_ok(&["rustc", "--version"], "lacks components or targets that")
+        .await;
+    // Warned about once, not on every call.
+    let out = cx
+        .config
+        .run(
+            "rustc",
+            ["--version"],
+            &[("RUSTUP_PROXY_WARNING_INTERVAL", "0")],
+        )
+        .await;
+    assert!(out.ok);
+    assert!(!out.stderr.contains("lacks components"), "{}", out.stderr);
+
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install"])
+        .await;
+    cx.config
+        .expect_not_stderr_ok(&["rustc", "--version"], "lacks components")
+        .await;
+}
+
+#[tokio::test]
+async fn file_override_check_is_not_fatal() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "stable"]).await;
+    let cwd = cx.config.current_dir();
+    raw::write_file(
+        &cwd.join("rust-toolchain.toml"),
+        "[toolchain]\ncomponents = [ \"rust-src\" ]",
+    )
+    .unwrap();
+    let installed = cx
+        .config
+        .rustupdir
+        .join("toolchains")
+        .join(for_host!("stable-{0}"))
+        .join("lib/rustlib/multirust-config.toml");
+    raw::write_file(&installed, "not toml").unwrap();
+
+    cx.config
+        .expect_stdout_ok(&["rustc", "--version"], "hash-stable-1.1.0")
+        .await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn proxies_cache_the_toolchain_of_a_directory() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "stable"]).await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+
+    let cwd = cx.config.current_dir();
+    let toolchain_file = cwd.join("rust-toolchain.toml");
+    let toolchains = cx.config.rustupdir.join("toolchains");
+    // What was just written isn't trusted to be unchanged, so is aged.
+    let age = |path: &Path| {
+        let an_hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        fs::File::open(path)
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+    };
+    let write_toolchain_file = |channel: &str| {
+        raw::write_file(
+            &toolchain_file,
+            &format!("[toolchain]\nchannel = \"{channel}\"\n"),
+        )
+        .unwrap();
+        age(&toolchain_file);
+    };
+    write_toolchain_file("nightly");
+    age(&cx.config.rustupdir.join("settings.toml"));
+    age(&toolchains);
+    for toolchain in fs::read_dir(&toolchains).unwrap() {
+        age(&toolchain
+            .unwrap()
+            .path()
+            .join("lib/rustlib/multirust-config.toml"));
+    }
+
+    let debug = [("RUSTUP_LOG", "rustup=debug")];
+    let out = cx.config.run("rustc", ["--version"], &debug).await;
+    assert!(out.stdout.contains("hash-nightly-2"), "{}", out.stdout);
+    assert!(!out.stderr.contains("using the cached toolchain"));
+    let out = cx.config.run("rustc", ["--version"], &debug).await;
+    assert!(out.stdout.contains("hash-nightly-2"), "{}", out.stdout);
+    assert!(
+        out.stderr.contains("using the cached toolchain"),
+        "{}",
+        out.stderr
+    );
+
+    // Editing a file it was found from makes the answer stale.
+    write_toolchain_file("stable");
+    let out = cx.config.run("rustc", ["--version"], &debug).await;
+    assert!(out.stdout.contains("hash-stable-1.1.0"), "{}", out.stdout);
+    assert!(out.stderr.contains("is stale"), "{}", out.stderr);
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "cache", "invalidate-resolution"],
+            "forgot the toolchains proxies cached for 1 directory",
+        )
+        .await;
+    let out = cx.config.run("rustc", ["--version"], &debug).await;
+    assert!(!out.stderr.contains("using the cached toolchain"));
+}
+
 #[tokio::test]
 async fn file_override_toml_format_add_missing_targets() {
     let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
@@ -2681,6 +2998,63 @@ async fn docs_missing() {
         .await;
 }
 
+#[tokio::test]
+async fn docs_for_target() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "stable"]).await;
+    cx.config
+        .expect_ok(&["rustup", "target", "add", clitools::CROSS_ARCH1])
+        .await;
+
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "doc", "--path", "--target", &this_host_triple()],
+            "index.html",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "doc", "--path", "--target", clitools::CROSS_ARCH1],
+            &format!(
+                "no documentation for target '{}' in toolchain 'stable-{host}'; documentation is installed for '{host}'",
+                clitools::CROSS_ARCH1,
+                host = this_host_triple(),
+            ),
+        )
+        .await;
+
+    // Without a standard library, the target's docs are those of `core`.
+    let std_lib = cx
+        .config
+        .rustupdir
+        .join("toolchains")
+        .join(for_host!("stable-{0}"))
+        .join("lib/rustlib")
+        .join(clitools::CROSS_ARCH1)
+        .join("lib/libstd.rlib");
+    fs::remove_file(std_lib).unwrap();
+    let core = format!("core{MAIN_SEPARATOR}index.html");
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "doc", "--path", "--target", clitools::CROSS_ARCH1],
+            &core,
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &[
+                "rustup",
+                "doc",
+                "--path",
+                "--target",
+                clitools::CROSS_ARCH1,
+                "--std",
+            ],
+            "has no standard library",
+        )
+        .await;
+}
+
 #[tokio::test]
 async fn docs_custom() {
     let mut cx = CliTestContext::new(Scenario::None).await;
@@ -2944,3 +3318,221 @@ async fn warn_on_duplicate_rust_toolchain_file() {
         )
         .await;
 }
+
+#[tokio::test]
+async fn rate_limit_proxy_warnings() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "stable"]).await;
+    let cwd = cx.config.current_dir();
+    raw::write_file(&cwd.join("rust-toolchain"), "stable").unwrap();
+    raw::write_file(&cwd.join("rust-toolchain.toml"), "[toolchain]").unwrap();
+
+    let warning = "warn: both `";
+    cx.config
+        .expect_stderr_ok(&["rustc", "--version"], warning)
+        .await;
+    cx.config
+        .expect_not_stderr_ok(&["rustc", "--version"], warning)
+        .await;
+
+    // Only proxies are rate limited.
+    cx.config
+        .expect_stderr_ok(&["rustup", "show", "active-toolchain"], warning)
+        .await;
+
+    // A cached answer doesn't look at the files again, so forget it.
+    cx.config
+        .expect_ok(&["rustup", "cache", "invalidate-resolution"])
+        .await;
+    let out = cx
+        .config
+        .run(
+            "rustc",
+            ["--version"],
+            &[("RUSTUP_PROXY_WARNING_INTERVAL", "0")],
+        )
+        .await;
+    assert!(out.ok);
+    assert!(out.stderr.contains(warning), "{}", out.stderr);
+}
+
+#[tokio::test]
+async fn auto_install_missing_toolchain() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let cwd = cx.config.current_dir();
+    raw::write_file(
+        &cwd.join("rust-toolchain.toml"),
+        "[toolchain]\nchannel = \"nightly\"",
+    )
+    .unwrap();
+
+    cx.config
+        .expect_err(
+            &["rustc", "--version"],
+            for_host!("toolchain 'nightly-{0}' is not installed"),
+        )
+        .await;
+
+    let out = cx
+        .config
+        .run("rustc", ["--version"], &[("RUSTUP_AUTO_INSTALL", "quiet")])
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(out.stdout.contains("hash-nightly-2"), "{}", out.stdout);
+    assert_eq!(out.stderr, "");
+
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "uninstall", "nightly"])
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "auto-install", "enable"],
+            "auto-install mode set to 'enable'",
+        )
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustc", "--version"],
+            for_host!("info: syncing channel updates for 'nightly-{0}'"),
+        )
+        .await;
+
+    let out = cx
+        .config
+        .run(
+            "rustc",
+            ["--version"],
+            &[("RUSTUP_AUTO_INSTALL", "sometimes")],
+        )
+        .await;
+    assert!(!out.ok);
+    assert!(
+        out.stderr
+            .contains("unknown auto install mode: 'sometimes'"),
+        "{}",
+        out.stderr
+    );
+}
+
+/// Starts `rustup agent`, returning once it listens.
+#[cfg(unix)]
+fn start_agent(config: &clitools::Config) -> std::process::Child {
+    use std::os::unix::net::UnixStream;
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    let agent = config
+        .cmd("rustup", ["agent"])
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let socket = config.rustupdir.join("agent.sock");
+    let started = Instant::now();
+    while UnixStream::connect(&socket).is_err() {
+        assert!(started.elapsed() < Duration::from_secs(30), "no agent");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    agent
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn agent_resolves_toolchains_for_proxies() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "stable"])
+        .await;
+
+    let mut agent = start_agent(&cx.config);
+    let socket = cx.config.rustupdir.join("agent.sock");
+    let dir = cx.config.current_dir();
+    let ask = |method: &str| {
+        let stream = UnixStream::connect(&socket).unwrap();
+        let request = serde_json::json!({ "method": method, "dir": dir });
+        (&stream)
+            .write_all(format!("{request}\n").as_bytes())
+            .unwrap();
+        let mut response = String::new();
+        BufReader::new(&stream).read_line(&mut response).unwrap();
+        serde_json::from_str::<serde_json::Value>(&response).unwrap()
+    };
+
+    let resolved = ask("resolve");
+    assert_eq!(resolved["toolchain"], *for_host!("nightly-{0}"));
+    assert_eq!(resolved["reason"]["kind"], "default");
+    let show = ask("show");
+    assert_eq!(show["active_toolchain"]["name"], *for_host!("nightly-{0}"));
+
+    // A changed setting is seen by the next request.
+    cx.config
+        .expect_ok(&["rustup", "override", "set", "stable"])
+        .await;
+    let resolved = ask("resolve");
+    assert_eq!(resolved["toolchain"], *for_host!("stable-{0}"));
+    assert_eq!(resolved["reason"]["kind"], "directory-override");
+
+    let out = cx
+        .config
+        .run("rustc", ["--version"], &[("RUSTUP_LOG", "rustup=debug")])
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(out.stdout.contains("hash-stable-1.1.0"), "{}", out.stdout);
+    assert!(
+        out.stderr.contains("rustup agent resolved the toolchain"),
+        "{}",
+        out.stderr
+    );
+
+    agent.kill().unwrap();
+    agent.wait().unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn agent_notices_toolchain_file_edits() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "stable"])
+        .await;
+    let mut agent = start_agent(&cx.config);
+
+    let toolchain_file = cx.config.current_dir().join("rust-toolchain.toml");
+    for (channel, version) in [
+        ("stable", "hash-stable-1.1.0"),
+        ("nightly", "hash-nightly-2"),
+        ("stable", "hash-stable-1.1.0"),
+    ] {
+        fs::write(
+            &toolchain_file,
+            format!("[toolchain]\nchannel = \"{channel}\"\n"),
+        )
+        .unwrap();
+        cx.config
+            .expect_stdout_ok(&["cargo", "--version"], version)
+            .await;
+    }
+    fs::remove_file(&toolchain_file).unwrap();
+    cx.config
+        .expect_stdout_ok(&["cargo", "--version"], "hash-nightly-2")
+        .await;
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "cache", "invalidate-resolution"],
+            "forgot the resolved toolchains of 1 directory",
+        )
+        .await;
+    agent.kill().unwrap();
+    agent.wait().unwrap();
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "cache", "invalidate-resolution"],
+            "no rustup agent is running",
+        )
+        .await;
+}
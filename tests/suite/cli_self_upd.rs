@@ -118,6 +118,118 @@ async fn install_twice() {
     assert!(rustup.exists());
 }
 
+// Windows can't make symbolic links without Developer Mode.
+#[cfg(unix)]
+#[tokio::test]
+async fn set_proxy_links_relinks_proxies() {
+    let mut cx = setup_empty_installed().await;
+    let bin = cx.config.cargodir.join("bin");
+    let cargo = bin.join(format!("cargo{EXE_SUFFIX}"));
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "proxy-links", "hardlink"],
+            "proxy links set to 'hardlink'",
+        )
+        .await;
+    assert!(!fs::symlink_metadata(&cargo).unwrap().is_symlink());
+
+    cx.config
+        .expect_ok(&["rustup", "set", "proxy-links", "symlink"])
+        .await;
+    for tool in TOOLS.iter().chain(DUP_TOOLS.iter()) {
+        let target = fs::read_link(bin.join(format!("{tool}{EXE_SUFFIX}"))).unwrap();
+        assert_eq!(target, Path::new(&format!("rustup{EXE_SUFFIX}")));
+    }
+}
+
+#[tokio::test]
+async fn set_proxy_dir_moves_proxies() {
+    let cx = setup_empty_installed().await;
+    let bin = cx.config.cargodir.join("bin");
+    let rustup = bin.join(format!("rustup{EXE_SUFFIX}"));
+    let proxies = cx.config.homedir.join("proxies");
+    let system = cx.config.homedir.join("system");
+    fs::create_dir_all(&system).unwrap();
+    fs::write(system.join(format!("cargo{EXE_SUFFIX}")), "").unwrap();
+
+    // A directory with another cargo is left alone.
+    cx.config
+        .expect_err(
+            &["rustup", "set", "proxy-dir", system.to_str().unwrap()],
+            "is already there and is not rustup",
+        )
+        .await;
+    assert!(bin.join(format!("cargo{EXE_SUFFIX}")).exists());
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "proxy-dir", proxies.to_str().unwrap()],
+            "proxies moved to '",
+        )
+        .await;
+    for tool in TOOLS {
+        assert!(!bin.join(format!("{tool}{EXE_SUFFIX}")).exists());
+        let proxy = proxies.join(format!("{tool}{EXE_SUFFIX}"));
+        assert!(same_file::is_same_file(&rustup, &proxy).unwrap());
+    }
+    assert!(rustup.exists());
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "proxy-dir", "default"],
+            "proxies moved to '",
+        )
+        .await;
+    for tool in TOOLS {
+        assert!(bin.join(format!("{tool}{EXE_SUFFIX}")).exists());
+        assert!(!proxies.join(format!("{tool}{EXE_SUFFIX}")).exists());
+    }
+    let settings = fs::read_to_string(cx.config.rustupdir.join("settings.toml")).unwrap();
+    assert!(!settings.contains("proxy_dir"), "{settings}");
+}
+
+#[tokio::test]
+async fn copied_proxies_are_copied_again() {
+    let mut cx = setup_empty_installed().await;
+    let bin = cx.config.cargodir.join("bin");
+    let rustup = bin.join(format!("rustup{EXE_SUFFIX}"));
+
+    cx.config
+        .expect_ok(&["rustup", "set", "proxy-links", "copy"])
+        .await;
+    for tool in TOOLS.iter().chain(DUP_TOOLS.iter()) {
+        let tool = bin.join(format!("{tool}{EXE_SUFFIX}"));
+        assert!(!same_file::is_same_file(&rustup, &tool).unwrap());
+        assert_eq!(fs::read(&tool).unwrap(), fs::read(&rustup).unwrap());
+    }
+    // Copies of rustup aren't mistaken for tools installed some other way.
+    cx.config
+        .expect_not_stderr_ok(
+            &["rustup", "set", "proxy-links", "copy"],
+            "is already installed",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn proxies_and_setting_kept_when_relinking_fails() {
+    let cx = setup_empty_installed().await;
+    let bin = cx.config.cargodir.join("bin");
+    let rustup = bin.join(format!("rustup{EXE_SUFFIX}"));
+    let cargo = bin.join(format!("cargo{EXE_SUFFIX}"));
+    // Nothing can be made where the new proxy would be put aside.
+    let blocker = bin.join(format!("cargo{EXE_SUFFIX}.rustup-tmp"));
+    fs::create_dir_all(blocker.join("in-the-way")).unwrap();
+
+    cx.config
+        .expect_err(&["rustup", "set", "proxy-links", "copy"], "")
+        .await;
+    assert!(same_file::is_same_file(&rustup, &cargo).unwrap());
+    let settings = fs::read_to_string(cx.config.rustupdir.join("settings.toml")).unwrap();
+    assert!(!settings.contains("copy"), "{settings}");
+}
+
 /// Smoke test for the entire install process when dirs need to be made :
 /// depending just on unit tests here could miss subtle dependencies being added
 /// earlier in the code, so a black-box test is needed.
@@ -243,6 +355,115 @@ async fn uninstall_fails_if_not_installed() {
         .await;
 }
 
+#[tokio::test]
+async fn doctor_runs_installed_rustup() {
+    let cx = setup_empty_installed().await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "self", "doctor"], "  starts: ok (rustup ")
+        .await;
+
+    let rustup = cx.config.cargodir.join(format!("bin/rustup{EXE_SUFFIX}"));
+    fs::remove_file(rustup).unwrap();
+    let out = cx.config.run("rustup", ["self", "doctor"], &[]).await;
+    assert!(!out.ok);
+    assert!(out.stdout.contains("  not installed"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn doctor_fixes_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let cx = setup_empty_installed().await;
+    let downloads = cx.config.rustupdir.join("downloads");
+    fs::create_dir_all(&downloads).unwrap();
+    fs::set_permissions(&downloads, fs::Permissions::from_mode(0o555)).unwrap();
+
+    let out = cx.config.run("rustup", ["doctor"], &[]).await;
+    assert!(!out.ok);
+    assert!(
+        out.stdout.contains("ownership: 1 problem"),
+        "{}",
+        out.stdout
+    );
+    assert!(out.stdout.contains("downloads' has mode 555"));
+    assert!(out.stdout.contains("rustup doctor --fix-permissions`"));
+
+    let out = cx
+        .config
+        .run("rustup", ["doctor", "--fix-permissions"], &[])
+        .await;
+    assert!(out.stdout.contains("ownership: ok"), "{}", out.stdout);
+}
+
+#[tokio::test]
+async fn doctor_checks_setup() {
+    let mut cx = setup_empty_installed().await;
+    let bin = cx.config.cargodir.join("bin");
+    let path = bin.to_string_lossy().into_owned();
+    // The bundled roots keep the result from depending on the machine's.
+    let env = [
+        ("PATH", path.as_str()),
+        ("RUSTUP_TLS_ROOT_SOURCE", "bundled"),
+    ];
+    let out = cx.config.run("rustup", ["doctor"], &env).await;
+    assert!(out.ok, "{}", out.stdout);
+    assert!(out.stdout.contains("PATH: ok"));
+    assert!(out.stdout.contains("linked toolchains: ok"));
+
+    let custom = cx.config.customdir.join("moved");
+    fs::create_dir_all(custom.join("bin")).unwrap();
+    fs::create_dir_all(custom.join("lib")).unwrap();
+    fs::write(custom.join(format!("bin/rustc{EXE_SUFFIX}")), "").unwrap();
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "toolchain",
+            "link",
+            "moved",
+            &custom.to_string_lossy(),
+        ])
+        .await;
+    fs::remove_dir_all(&custom).unwrap();
+    let cargo = bin.join(format!("cargo{EXE_SUFFIX}"));
+    fs::remove_file(&cargo).unwrap();
+    fs::write(&cargo, "an older rustup").unwrap();
+
+    let out = cx.config.run("rustup", ["doctor"], &env).await;
+    assert!(!out.ok);
+    assert!(out.stdout.contains("linked toolchains: 1 problem"));
+    assert!(out.stdout.contains("toolchain 'moved' links to"));
+    assert!(out
+        .stdout
+        .contains("fix: run `rustup toolchain uninstall moved`"));
+    assert!(out.stdout.contains("proxies: 1 problem"));
+    assert!(out.stdout.contains("may be left from an older rustup"));
+
+    let out = cx.config.run("rustup", ["doctor"], &[]).await;
+    assert!(out.stdout.contains(&format!("'{path}' is not in PATH")));
+
+    // A system Rust ahead of rustup is fine once chosen during setup.
+    let system = cx.config.customdir.join("system");
+    fs::create_dir_all(&system).unwrap();
+    fs::write(system.join(format!("rustc{EXE_SUFFIX}")), "").unwrap();
+    let path = env::join_paths([&system, &bin]).unwrap();
+    let env = [
+        ("PATH", path.to_str().unwrap()),
+        ("RUSTUP_TLS_ROOT_SOURCE", "bundled"),
+    ];
+    let out = cx.config.run("rustup", ["doctor"], &env).await;
+    assert!(out.stdout.contains("comes before rustup's rustc in PATH"));
+    let settings = cx.config.rustupdir.join("settings.toml");
+    let mut data = fs::read_to_string(&settings).unwrap();
+    data.push_str(&format!(
+        "\n[system_rust]\npath = {:?}\nrustup_first = false\n",
+        system.to_str().unwrap()
+    ));
+    fs::write(&settings, data).unwrap();
+    let out = cx.config.run("rustup", ["doctor"], &env).await;
+    assert!(out.stdout.contains("PATH: ok"), "{}", out.stdout);
+}
+
 // The other tests here just run rustup from a temp directory. This
 // does the uninstall by actually invoking the installed binary in
 // order to test that it can successfully delete itself.
@@ -43,6 +43,33 @@ async fn install_toolchain_from_channel() {
         .await;
 }
 
+#[tokio::test]
+async fn no_legacy_fallback_with_trusted_keys() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV1).await;
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "key",
+            "add",
+            "--sigstore-identity",
+            "release@example.com",
+            "--sigstore-issuer",
+            "https://issuer.example.com",
+        ])
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "install", "nightly"],
+            "only has a legacy release, whose signature can't be checked",
+        )
+        .await;
+    assert!(!cx.config.rustupdir.has("toolchains"));
+
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly", "--no-verify"])
+        .await;
+}
+
 #[tokio::test]
 async fn install_toolchain_from_archive() {
     let mut cx = CliTestContext::new(Scenario::ArchivesV1).await;
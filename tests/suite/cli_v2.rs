@@ -1,13 +1,25 @@
 //! Test cases of the rustup command, using v2 manifests, mostly
 //! derived from multirust/test-v2.sh
 
+use std::collections::HashMap;
+use std::env::consts::EXE_SUFFIX;
 use std::fs;
 use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-use rustup::dist::TargetTriple;
+use rustup::cli::common::PANIC_EXIT_CODE;
+use rustup::cli::dispatch;
+use rustup::dist::{self, TargetTriple};
 use rustup::for_host;
+use rustup::notifications::{EventKind, Notification};
+use rustup::process::TestProcess;
 use rustup::test::mock::clitools::{self, set_current_dist_date, CliTestContext, Config, Scenario};
+use rustup::test::mock::dist::{calc_hash, create_tarball, SIGNED_MARKER};
+use rustup::test::mock::{MockComponentBuilder, MockFile, MockInstallerBuilder};
 use rustup::test::this_host_triple;
+use url::Url;
 
 #[tokio::test]
 async fn rustc_no_default_toolchain() {
@@ -245,6 +257,45 @@ async fn remove_toolchain_active() {
         .await;
 }
 
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn remove_toolchain_in_use() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "stable"]).await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+    let toolchain_dir = cx
+        .config
+        .rustupdir
+        .join("toolchains")
+        .join(format!("nightly-{}", this_host_triple()));
+    let sleep = toolchain_dir.join("bin").join("sleep");
+    fs::copy("/bin/sleep", &sleep).unwrap();
+    let mut child = std::process::Command::new(&sleep)
+        .arg("60")
+        .spawn()
+        .unwrap();
+
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "remove", "nightly"],
+            for_host!("toolchain 'nightly-{0}' is in use by:"),
+        )
+        .await;
+    assert!(toolchain_dir.exists());
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "toolchain", "remove", "nightly", "--force"],
+            &sleep.display().to_string(),
+        )
+        .await;
+    assert!(!toolchain_dir.exists());
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
 // Issue #2873
 #[tokio::test]
 async fn remove_toolchain_ignore_trailing_slash() {
@@ -732,6 +783,8 @@ async fn remove_toolchain_then_add_again() {
 async fn upgrade_v1_to_v2() {
     let mut cx = CliTestContext::new(Scenario::Full).await;
     set_current_dist_date(&cx.config, "2015-01-01");
+    // Nothing in a v1 release is signed.
+    let _ = fs::remove_file(cx.config.distdir.as_ref().unwrap().join(SIGNED_MARKER));
     // Delete the v2 manifest so the first day we install from the v1s
     fs::remove_file(
         cx.config
@@ -758,6 +811,8 @@ async fn upgrade_v2_to_v1() {
     set_current_dist_date(&cx.config, "2015-01-01");
     cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
     set_current_dist_date(&cx.config, "2015-01-02");
+    // Nothing in a v1 release is signed.
+    let _ = fs::remove_file(cx.config.distdir.as_ref().unwrap().join(SIGNED_MARKER));
     fs::remove_file(
         cx.config
             .distdir
@@ -786,131 +841,2535 @@ async fn list_targets_no_toolchain() {
 }
 
 #[tokio::test]
-async fn list_targets_v1_toolchain() {
-    let mut cx = CliTestContext::new(Scenario::SimpleV1).await;
-    cx.config.expect_ok(&["rustup", "update", "nightly"]).await;
+async fn list_targets_v1_toolchain() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV1).await;
+    cx.config.expect_ok(&["rustup", "update", "nightly"]).await;
+    cx.config
+        .expect_err(
+            &["rustup", "target", "list", "--toolchain=nightly"],
+            for_host!("toolchain 'nightly-{0}' does not support components"),
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn list_targets_custom_toolchain() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let path = cx.config.customdir.join("custom-1");
+    let path = path.to_string_lossy();
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "link", "default-from-path", &path])
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "default", "default-from-path"])
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "target", "list"],
+            "toolchain 'default-from-path' does not support components",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn list_targets() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "target", "list"], clitools::CROSS_ARCH1)
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "target", "list"], clitools::CROSS_ARCH2)
+        .await;
+}
+
+#[tokio::test]
+async fn list_installed_targets() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let trip = this_host_triple();
+
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "target", "list", "--installed"], &trip)
+        .await;
+}
+
+#[tokio::test]
+async fn list_available_and_filtered_targets() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_ok(&["rustup", "target", "add", clitools::CROSS_ARCH1])
+        .await;
+    cx.config
+        .expect_ok_ex(
+            &["rustup", "target", "list", "--available"],
+            &format!("{}\n", clitools::CROSS_ARCH2),
+            "",
+        )
+        .await;
+    cx.config
+        .expect_ok_ex(
+            &[
+                "rustup",
+                "target",
+                "list",
+                "--installed",
+                "--filter",
+                clitools::CROSS_ARCH1,
+            ],
+            &format!("{}\n", clitools::CROSS_ARCH1),
+            "",
+        )
+        .await;
+    cx.config
+        .expect_ok_ex(
+            &["rustup", "target", "list", "--filter", "no-such-target"],
+            "",
+            "",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "target", "list", "--installed", "--available"],
+            "cannot be used with",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn list_porcelain() {
+    let mut cx = CliTestContext::new(Scenario::SizedV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_ok_ex(
+            &["rustup", "target", "list", "--porcelain"],
+            &format!(
+                "{}\tavailable\n{}\tinstalled\n{}\tavailable\n",
+                clitools::CROSS_ARCH2,
+                this_host_triple(),
+                clitools::CROSS_ARCH1,
+            ),
+            "",
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "component", "list", "--porcelain"],
+            for_host!("\nrustc-{}\tinstalled\n"),
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "component", "list", "--porcelain", "--verbose"],
+            "cannot be used with",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn list_json() {
+    let mut cx = CliTestContext::new(Scenario::SizedV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let json = |out: clitools::SanitizedOutput| {
+        assert!(out.ok, "{}", out.stderr);
+        let json = serde_json::from_str::<serde_json::Value>(&out.stdout).unwrap();
+        assert_eq!(json["version"], 1);
+        json
+    };
+
+    let toolchains = json(
+        cx.config
+            .run("rustup", ["toolchain", "list", "--output", "json"], &[])
+            .await,
+    );
+    assert_eq!(
+        toolchains["toolchains"],
+        serde_json::json!([{
+            "name": for_host!("nightly-{}"),
+            "path": cx.config.rustupdir.join("toolchains").join(for_host!("nightly-{}")),
+            "default": true,
+            "active": true,
+            "pinned": false,
+            "linked": false,
+        }])
+    );
+
+    let targets = json(
+        cx.config
+            .run("rustup", ["target", "list", "--output", "json"], &[])
+            .await,
+    );
+    assert_eq!(targets["toolchain"], *for_host!("nightly-{}"));
+    let installed = targets["targets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|target| target["installed"] == true)
+        .map(|target| target["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(installed, [this_host_triple()]);
+
+    let components = json(
+        cx.config
+            .run(
+                "rustup",
+                ["component", "list", "--installed", "--output", "json"],
+                &[],
+            )
+            .await,
+    );
+    let rustc = components["components"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|component| component["component"] == "rustc")
+        .unwrap();
+    assert_eq!(rustc["name"], *for_host!("rustc-{}"));
+    assert_eq!(rustc["component"], "rustc");
+    assert_eq!(rustc["target"], this_host_triple());
+    assert_eq!(rustc["installed"], true);
+
+    cx.config
+        .expect_err(
+            &[
+                "rustup",
+                "component",
+                "list",
+                "--porcelain",
+                "--output",
+                "json",
+            ],
+            "cannot be used with",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn list_json_is_sorted_and_versioned() {
+    let mut cx = CliTestContext::new(Scenario::SizedV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let names = |out: clitools::SanitizedOutput, list: &str| {
+        assert!(out.ok, "{}", out.stderr);
+        let json = serde_json::from_str::<serde_json::Value>(&out.stdout).unwrap();
+        assert_eq!(json["version"], 1);
+        json[list]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["name"].as_str().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    for (args, list) in [
+        (&["target", "list"][..], "targets"),
+        (&["component", "list"][..], "components"),
+    ] {
+        let args = [args, &["--output", "json", "--format-version", "1"]].concat();
+        let listed = names(cx.config.run("rustup", &args, &[]).await, list);
+        let mut sorted = listed.clone();
+        sorted.sort();
+        assert!(listed.len() > 1);
+        assert_eq!(listed, sorted);
+
+        let again = names(cx.config.run("rustup", &args, &[]).await, list);
+        assert_eq!(listed, again);
+    }
+
+    cx.config
+        .expect_err(
+            &[
+                "rustup",
+                "toolchain",
+                "list",
+                "--output",
+                "json",
+                "--format-version",
+                "2",
+            ],
+            "this rustup only prints version 1 of the JSON output",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "show", "--format-version", "1"],
+            "required arguments were not provided",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn list_targets_by_tier() {
+    let mut cx = CliTestContext::new(Scenario::SizedV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_ok_ex(
+            &["rustup", "target", "list"],
+            &format!(
+                "tier 1:\n{} (installed)\n\ntier 2:\n{}\n\nunknown tier:\n{}\n",
+                this_host_triple(),
+                clitools::CROSS_ARCH1,
+                clitools::CROSS_ARCH2,
+            ),
+            "",
+        )
+        .await;
+    cx.config
+        .expect_ok_ex(
+            &["rustup", "target", "list", "--quiet", "--available"],
+            &format!("{}\n{}\n", clitools::CROSS_ARCH2, clitools::CROSS_ARCH1),
+            "",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn add_target1() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_ok(&["rustup", "target", "add", clitools::CROSS_ARCH1])
+        .await;
+    let path = format!(
+        "toolchains/nightly-{}/lib/rustlib/{}/lib/libstd.rlib",
+        this_host_triple(),
+        clitools::CROSS_ARCH1
+    );
+    assert!(cx.config.rustupdir.has(path));
+}
+
+#[tokio::test]
+async fn add_and_remove_dry_run() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let path = format!(
+        "toolchains/nightly-{}/lib/rustlib/{}/lib/libstd.rlib",
+        this_host_triple(),
+        clitools::CROSS_ARCH1
+    );
+
+    cx.config
+        .expect_ok_ex(
+            &[
+                "rustup",
+                "target",
+                "add",
+                "--dry-run",
+                clitools::CROSS_ARCH1,
+            ],
+            &format!(
+                "install 'rust-std' for target '{}'\n",
+                clitools::CROSS_ARCH1
+            ),
+            "",
+        )
+        .await;
+    assert!(!cx.config.rustupdir.has(&path));
+
+    cx.config
+        .expect_ok(&["rustup", "target", "add", clitools::CROSS_ARCH1])
+        .await;
+    cx.config
+        .expect_ok_ex(
+            &["rustup", "component", "add", "--dry-run", "rust-std"],
+            "nothing to change\n",
+            "",
+        )
+        .await;
+    cx.config
+        .expect_ok_ex(
+            &[
+                "rustup",
+                "target",
+                "remove",
+                "--dry-run",
+                clitools::CROSS_ARCH1,
+            ],
+            &format!("remove 'rust-std' for target '{}'\n", clitools::CROSS_ARCH1),
+            "",
+        )
+        .await;
+    cx.config
+        .expect_ok_ex(
+            &["rustup", "component", "remove", "--dry-run", "rustc"],
+            &format!("remove 'rustc' for target '{}'\n", this_host_triple()),
+            "",
+        )
+        .await;
+    assert!(cx.config.rustupdir.has(&path));
+}
+
+#[tokio::test]
+async fn update_and_install_dry_run() {
+    let mut cx = CliTestContext::new(Scenario::ArchivesV2).await;
+    set_current_dist_date(&cx.config, "2015-01-01");
+    let nightly = cx
+        .config
+        .rustupdir
+        .join(for_host!("toolchains/nightly-{0}"));
+
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            [
+                "toolchain",
+                "install",
+                "--dry-run",
+                "--profile",
+                "minimal",
+                "nightly",
+            ],
+            &[],
+        )
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(
+        out.stdout.starts_with(for_host!(
+            "nightly-{0}:\n  install 'rustc' for target '{0}'"
+        )),
+        "{}",
+        out.stdout
+    );
+    assert!(!out.stdout.contains("cargo"), "{}", out.stdout);
+    assert!(!nightly.exists());
+
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "toolchain",
+            "install",
+            "--profile",
+            "minimal",
+            "nightly",
+        ])
+        .await;
+    cx.config
+        .expect_ok_ex(
+            &["rustup", "update", "--dry-run"],
+            for_host!("nightly-{0}:\n  up to date\n"),
+            "",
+        )
+        .await;
+
+    set_current_dist_date(&cx.config, "2015-01-02");
+    let out = cx.config.run("rustup", ["update", "--dry-run"], &[]).await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(
+        out.stdout
+            .contains(for_host!("  remove 'rustc' for target '{0}'\n")),
+        "{}",
+        out.stdout
+    );
+    assert!(
+        out.stdout
+            .contains(for_host!("  install 'rustc' for target '{0}'")),
+        "{}",
+        out.stdout
+    );
+    cx.config
+        .expect_stdout_ok(&["rustc", "--version"], "hash-nightly-1")
+        .await;
+}
+
+#[tokio::test]
+async fn add_target2() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_ok(&["rustup", "target", "add", clitools::CROSS_ARCH2])
+        .await;
+    let path = format!(
+        "toolchains/nightly-{}/lib/rustlib/{}/lib/libstd.rlib",
+        this_host_triple(),
+        clitools::CROSS_ARCH2
+    );
+    assert!(cx.config.rustupdir.has(path));
+}
+
+#[tokio::test]
+async fn add_all_targets() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_ok(&["rustup", "target", "add", "all"])
+        .await;
+    let path = format!(
+        "toolchains/nightly-{}/lib/rustlib/{}/lib/libstd.rlib",
+        this_host_triple(),
+        clitools::CROSS_ARCH1
+    );
+    assert!(cx.config.rustupdir.has(path));
+    let path = format!(
+        "toolchains/nightly-{}/lib/rustlib/{}/lib/libstd.rlib",
+        this_host_triple(),
+        clitools::CROSS_ARCH2
+    );
+    assert!(cx.config.rustupdir.has(path));
+}
+
+#[tokio::test]
+async fn add_all_targets_fail() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_err(
+            &[
+                "rustup",
+                "target",
+                "add",
+                clitools::CROSS_ARCH1,
+                "all",
+                clitools::CROSS_ARCH2,
+            ],
+            &format!(
+                "`rustup target add {} all {}` includes `all`",
+                clitools::CROSS_ARCH1,
+                clitools::CROSS_ARCH2
+            ),
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn add_remove_component_for_all_targets() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_ok(&["rustup", "component", "add", "rust-std", "--target", "all"])
+        .await;
+    for target in [clitools::CROSS_ARCH1, clitools::CROSS_ARCH2] {
+        let path = format!(
+            "toolchains/nightly-{}/lib/rustlib/{}/lib/libstd.rlib",
+            this_host_triple(),
+            target
+        );
+        assert!(cx.config.rustupdir.has(path));
+    }
+
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "component",
+            "remove",
+            "rust-std",
+            "--target",
+            "all",
+        ])
+        .await;
+    cx.config
+        .expect_ok_ex(&["rustup", "target", "list", "--installed"], "", "")
+        .await;
+}
+
+#[tokio::test]
+async fn add_component_for_installed_targets() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    cx.config
+        .expect_ok(&["rustup", "target", "add", clitools::CROSS_ARCH1])
+        .await;
+    // `rust-analysis` is only available for the host, so the cross target is skipped.
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "component",
+            "add",
+            "rust-analysis",
+            "--target",
+            "installed",
+        ])
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "component", "list", "--installed"],
+            &format!("rust-analysis-{}", this_host_triple()),
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn install_for_rustc_dev() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    // The mock channel has no `rustc-dev`, so the preset cannot be satisfied.
+    cx.config
+        .expect_err(
+            &[
+                "rustup",
+                "toolchain",
+                "install",
+                "nightly",
+                "--for-rustc-dev",
+            ],
+            for_host!("'rustc-dev' for target '{}'"),
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "install", "--for-rustc-dev"],
+            "the following required arguments were not provided",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn add_component_from_file() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+
+    let name = format!("rls-preview-nightly-{}.tar.gz", this_host_triple());
+    let archive = cx.config.homedir.join(&name);
+
+    // A file that is not one of the release's archives is refused.
+    fs::write(&archive, "not an archive").unwrap();
+    cx.config
+        .expect_err(
+            &[
+                "rustup",
+                "component",
+                "add",
+                "--file",
+                archive.to_str().unwrap(),
+            ],
+            "is not an archive of a component of toolchain 'nightly",
+        )
+        .await;
+
+    let dist = cx.config.distdir.as_ref().unwrap().join("dist");
+    let release = fs::read_dir(&dist)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().join(&name))
+        .find(|path| path.exists())
+        .unwrap();
+    fs::copy(release, &archive).unwrap();
+    let archive = archive.to_str().unwrap();
+    // Nothing is downloaded.
+    let offline = [("RUSTUP_DIST_SERVER", "file:///no-such-server")];
+
+    let out = cx
+        .config
+        .run("rustup", ["component", "add", "--file", archive], &offline)
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(
+        out.stderr.contains(&format!(
+            "info: '{archive}' matches the manifest's archive of component 'rls'"
+        )),
+        "{}",
+        out.stderr
+    );
+    cx.config.expect_component_executable("rls").await;
+}
+
+/// Builds a rust-installer archive of a `my-tool` component, returning its
+/// URL and hash.
+fn my_tool_archive(config: &Config) -> (String, String) {
+    let installer = MockInstallerBuilder {
+        components: vec![MockComponentBuilder {
+            name: "my-tool".to_owned(),
+            files: vec![MockFile::new("bin/my-tool", b"my-tool 1.0")],
+        }],
+    };
+    let src = config.homedir.join("my-tool-1.0");
+    fs::create_dir(&src).unwrap();
+    installer.build(&src);
+    let archive = config.homedir.join("my-tool-1.0.tar.gz");
+    create_tarball(Path::new("my-tool-1.0"), &src, &archive).unwrap();
+    let hash = calc_hash(&archive);
+    (Url::from_file_path(&archive).unwrap().to_string(), hash)
+}
+
+fn add_from_url<'a>(url: &'a str, hash: &'a str, name: &'a str) -> [&'a str; 9] {
+    [
+        "rustup",
+        "component",
+        "add",
+        "--url",
+        url,
+        "--sha256",
+        hash,
+        "--name",
+        name,
+    ]
+}
+
+#[tokio::test]
+async fn add_component_from_url() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let (url, hash) = my_tool_archive(&cx.config);
+
+    cx.config
+        .expect_err(
+            &add_from_url(&url, &"0".repeat(64), "my-tool"),
+            "checksum failed",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &add_from_url(&url, &hash, "other-tool"),
+            "does not contain a component named 'other-tool'",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &add_from_url(&url, &hash, "rustc"),
+            "component 'rustc' comes from the channel manifest",
+        )
+        .await;
+
+    cx.config
+        .expect_ok(&add_from_url(&url, &hash, "my-tool"))
+        .await;
+    let tool = cx
+        .config
+        .rustupdir
+        .join("toolchains")
+        .join(format!("nightly-{}", this_host_triple()))
+        .join("bin")
+        .join("my-tool");
+    assert_eq!(fs::read_to_string(tool).unwrap(), "my-tool 1.0");
+}
+
+#[tokio::test]
+async fn remove_component_from_url() {
+    let mut cx = CliTestContext::new(Scenario::ArchivesV2).await;
+    set_current_dist_date(&cx.config, "2015-01-01");
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let (url, hash) = my_tool_archive(&cx.config);
+    let tool = cx
+        .config
+        .rustupdir
+        .join("toolchains")
+        .join(format!("nightly-{}", this_host_triple()))
+        .join("bin")
+        .join("my-tool");
+
+    cx.config
+        .expect_ok(&add_from_url(&url, &hash, "my-tool"))
+        .await;
+    // It can be installed again, and is kept as the toolchain changes.
+    cx.config
+        .expect_ok(&add_from_url(&url, &hash, "my-tool"))
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "component", "add", "rust-src"])
+        .await;
+    set_current_dist_date(&cx.config, "2015-01-02");
+    cx.config.expect_ok(&["rustup", "update", "nightly"]).await;
+    assert!(tool.exists());
+
+    cx.config
+        .expect_ok(&["rustup", "component", "remove", "my-tool"])
+        .await;
+    assert!(!tool.exists());
+    cx.config
+        .expect_err(
+            &["rustup", "component", "remove", "my-tool"],
+            "toolchain 'nightly-",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn trusted_gpg_key() {
+    use std::process::Command;
+
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let gnupg = cx.config.homedir.join("gnupg");
+    fs::create_dir(&gnupg).unwrap();
+    let gpg = |args: &[&str]| {
+        Command::new("gpg")
+            .args(["--batch", "--quiet", "--homedir"])
+            .arg(&gnupg)
+            .args(args)
+            .output()
+    };
+    let generated = gpg(&[
+        "--passphrase",
+        "",
+        "--quick-gen-key",
+        "Dist Server <dist@example.com>",
+        "ed25519",
+        "sign",
+    ]);
+    // Nothing to test against without gpg.
+    if !generated.is_ok_and(|out| out.status.success()) {
+        return;
+    }
+    let key = cx.config.homedir.join("key.asc");
+    let exported = gpg(&["--armor", "--export"]).unwrap();
+    fs::write(&key, exported.stdout).unwrap();
+
+    // The manifest is signed with the key added below, not the test key.
+    let distdir = cx.config.distdir.clone().unwrap();
+    let manifest = distdir.join("dist").join("channel-rust-nightly.toml");
+    let _ = fs::remove_file(distdir.join(SIGNED_MARKER));
+    let _ = fs::remove_file(manifest.with_extension("toml.asc"));
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "key", "add", key.to_str().unwrap()],
+            "can now be signed by '",
+        )
+        .await;
+    let out = cx.config.run("rustup", ["key", "list"], &[]).await;
+    assert!(out.ok);
+    let fingerprint = out.stdout.lines().nth(1).unwrap().trim();
+    let fingerprint = fingerprint.strip_prefix("gpg ").unwrap().to_owned();
+
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "install", "nightly"],
+            "could not download the signature",
+        )
+        .await;
+
+    let signed = gpg(&["--armor", "--detach-sign", manifest.to_str().unwrap()]).unwrap();
+    assert!(signed.status.success());
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+
+    // Anything else in place of the signature is refused.
+    fs::write(manifest.with_extension("toml.asc"), "not a signature").unwrap();
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "uninstall", "nightly"])
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "install", "nightly"],
+            "is not a good signature by a trusted OpenPGP key",
+        )
+        .await;
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "key", "remove", &fingerprint],
+            "no longer trusted",
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "key", "list"], "")
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+}
+
+#[tokio::test]
+async fn pgp_key_env() {
+    use std::process::Command;
+
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let gnupg = cx.config.homedir.join("gnupg");
+    fs::create_dir(&gnupg).unwrap();
+    let gpg = |args: &[&str]| {
+        Command::new("gpg")
+            .args(["--batch", "--quiet", "--homedir"])
+            .arg(&gnupg)
+            .args(args)
+            .output()
+    };
+    let generated = gpg(&[
+        "--passphrase",
+        "",
+        "--quick-gen-key",
+        "Dist Server <dist@example.com>",
+        "ed25519",
+        "sign",
+    ]);
+    // Nothing to test against without gpg.
+    if !generated.is_ok_and(|out| out.status.success()) {
+        return;
+    }
+    let key = cx.config.homedir.join("key.asc");
+    let exported = gpg(&["--armor", "--export"]).unwrap();
+    fs::write(&key, exported.stdout).unwrap();
+    let env = [("RUSTUP_PGP_KEY", key.to_str().unwrap())];
+    let manifest = cx
+        .config
+        .distdir
+        .as_ref()
+        .unwrap()
+        .join("dist")
+        .join("channel-rust-nightly.toml");
+    let _ = fs::remove_file(manifest.with_extension("toml.asc"));
+
+    let out = cx
+        .config
+        .run("rustup", ["toolchain", "install", "nightly"], &env)
+        .await;
+    assert!(!out.ok);
+    assert!(out.stderr.contains("could not download the signature"));
+    assert!(out.stderr.contains("pass `--no-verify`"));
+
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["toolchain", "install", "nightly", "--no-verify"],
+            &env,
+        )
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(out
+        .stderr
+        .contains("not checking the signatures of channel manifests"));
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "uninstall", "nightly"])
+        .await;
+
+    let signed = gpg(&["--armor", "--detach-sign", manifest.to_str().unwrap()]).unwrap();
+    assert!(signed.status.success());
+    let out = cx
+        .config
+        .run("rustup", ["toolchain", "install", "nightly"], &env)
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+}
+
+#[tokio::test]
+async fn pgp_key_env_read_when_checking() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let env = [("RUSTUP_PGP_KEY", "no-such-key.asc")];
+
+    // Nothing is checked by a proxy, so it doesn't read the key.
+    let out = cx.config.run("rustc", ["--version"], &env).await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(out.stdout.contains("hash-nightly-2"));
+
+    let out = cx
+        .config
+        .run("rustup", ["toolchain", "install", "stable"], &env)
+        .await;
+    assert!(!out.ok);
+    assert!(
+        out.stderr.contains("could not read key file"),
+        "{}",
+        out.stderr
+    );
+}
+
+#[tokio::test]
+async fn config_sync() {
+    use std::process::Command;
+
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let gnupg = cx.config.homedir.join("gnupg");
+    fs::create_dir(&gnupg).unwrap();
+    let gpg = |args: &[&str]| {
+        Command::new("gpg")
+            .args(["--batch", "--quiet", "--homedir"])
+            .arg(&gnupg)
+            .args(args)
+            .output()
+    };
+    let generated = gpg(&[
+        "--passphrase",
+        "",
+        "--quick-gen-key",
+        "Rust Admins <rust@example.com>",
+        "ed25519",
+        "sign",
+    ]);
+    // Nothing to test against without gpg.
+    if !generated.is_ok_and(|out| out.status.success()) {
+        return;
+    }
+    let key = cx.config.homedir.join("key.asc");
+    let exported = gpg(&["--armor", "--export"]).unwrap();
+    fs::write(&key, exported.stdout).unwrap();
+
+    let bundle = cx.config.homedir.join("rustup-config.toml");
+    let url = Url::from_file_path(&bundle).unwrap().to_string();
+    let write_bundle = |contents: &str| {
+        fs::write(&bundle, contents).unwrap();
+        let _ = fs::remove_file(bundle.with_extension("toml.asc"));
+        let signed = gpg(&["--armor", "--detach-sign", bundle.to_str().unwrap()]).unwrap();
+        assert!(signed.status.success());
+    };
+    write_bundle(
+        r#"profile = "minimal"
+allowed_channels = ["stable"]
+
+[component_groups]
+docs = ["rust-docs"]
+"#,
+    );
+
+    cx.config
+        .expect_err(
+            &["rustup", "config", "sync", "--from", &url],
+            "no key is trusted to sign the configuration",
+        )
+        .await;
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "key",
+            "add",
+            key.to_str().unwrap(),
+            "--dist-server",
+            &url,
+        ])
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "config", "sync", "--from", &url],
+            "policy synced from '",
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "show", "profile"], "minimal")
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "install", "nightly"],
+            "toolchains from the 'nightly' channel are not allowed by policy",
+        )
+        .await;
+
+    // The user's own settings win over the policy.
+    cx.config
+        .expect_ok(&["rustup", "set", "profile", "default"])
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "show", "profile"], "default")
+        .await;
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "config", "sync", "--check"],
+            "policy is up to date",
+        )
+        .await;
+    write_bundle(
+        r#"allowed_channels = ["stable", "nightly"]
+
+[component_groups]
+docs = ["rust-docs"]
+"#,
+    );
+    let out = cx
+        .config
+        .run("rustup", ["config", "sync", "--check"], &[])
+        .await;
+    assert!(!out.ok);
+    assert_eq!(
+        out.stdout,
+        "profile: 'minimal', bundle has unset\n\
+         allowed_channels: 'stable', bundle has 'stable', 'nightly'\n"
+    );
+    cx.config.expect_ok(&["rustup", "config", "sync"]).await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly", "-c", "@docs"])
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "component", "add", "@tools"],
+            "there is no component group 'tools' in the policy",
+        )
+        .await;
+
+    // A forged bundle is refused, and the policy kept.
+    fs::write(&bundle, "allowed_channels = [\"beta\"]").unwrap();
+    cx.config
+        .expect_err(
+            &["rustup", "config", "sync"],
+            "is not a good signature by a trusted OpenPGP key",
+        )
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+
+    // A git repository has the bundle at its root.
+    let repo = cx.config.homedir.join("policy.git");
+    fs::create_dir(&repo).unwrap();
+    write_bundle(r#"profile = "complete""#);
+    for file in ["rustup-config.toml", "rustup-config.toml.asc"] {
+        fs::copy(cx.config.homedir.join(file), repo.join(file)).unwrap();
+    }
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .args([
+                "-c",
+                "user.name=Rust Admins",
+                "-c",
+                "user.email=rust@example.com",
+            ])
+            .args(args)
+            .current_dir(&repo)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success());
+    };
+    git(&["init", "--quiet"]);
+    git(&["add", "."]);
+    git(&["commit", "--quiet", "-m", "Policy"]);
+    let repo = repo.to_str().unwrap();
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "key",
+            "add",
+            key.to_str().unwrap(),
+            "--dist-server",
+            repo,
+        ])
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "config", "sync", "--from", repo])
+        .await;
+    let policy = fs::read_to_string(cx.config.rustupdir.join("policy.toml")).unwrap();
+    assert!(policy.contains(r#"profile = "complete""#), "{policy}");
+}
+
+#[tokio::test]
+async fn stats() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "stable"]).await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "stats"], "toolchains: 1, using ")
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "stats"], "never run\n")
+        .await;
+
+    cx.config.expect_ok(&["rustc", "--version"]).await;
+    cx.config.expect_ok(&["cargo", "--version"]).await;
+    let out = cx.config.run("rustup", ["stats"], &[]).await;
+    assert!(out.ok);
+    assert!(out.stdout.contains("  2 runs, last "), "{}", out.stdout);
+    assert!(
+        out.stdout.contains("0% of 4 archives found in the cache"),
+        "{}",
+        out.stdout
+    );
+    assert!(
+        out.stdout
+            .ends_with(for_host!("most used:\n  stable-{0} (2 runs)\n")),
+        "{}",
+        out.stdout
+    );
+}
+
+#[tokio::test]
+async fn toolchain_gc() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "stable"]).await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly", "beta"])
+        .await;
+    let long_ago = SystemTime::now() - Duration::from_secs(100 * 86400);
+    for channel in ["stable", "nightly", "beta"] {
+        let toolchain = format!("{channel}-{}", this_host_triple());
+        cx.config
+            .rustupdir
+            .set_toolchain_updated(&toolchain, long_ago)
+            .unwrap();
+    }
+    cx.config.expect_ok(&["cargo", "+beta", "--version"]).await;
+
+    cx.config
+        .expect_err(&["rustup", "toolchain", "gc"], "no retention period is set")
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "set", "toolchain-retention", "30"])
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "toolchain", "gc", "--dry-run"],
+            for_host!("would remove toolchain 'nightly-{0}', unused for 100 days"),
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "toolchain", "list"], "nightly")
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "toolchain", "gc"],
+            for_host!("removing toolchain 'nightly-{0}', unused for 100 days"),
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "toolchain", "list"],
+            for_host!("stable-{0} (active, default)\nbeta-{0}\n"),
+        )
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "toolchain", "gc", "--older-than", "200"],
+            "no toolchain has been unused for 200 days",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn install_from_bundle() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    // The bundle is the dist server's tree, which leaves the server empty.
+    let bundle = cx.config.homedir.join("bundle");
+    fs::rename(cx.config.distdir.as_ref().unwrap().join("dist"), &bundle).unwrap();
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "install", "stable"],
+            "no release found for 'stable'",
+        )
+        .await;
+
+    let bundle_arg = bundle.to_str().unwrap();
+    cx.config
+        .expect_stderr_ok(
+            &[
+                "rustup",
+                "toolchain",
+                "install",
+                "stable",
+                "--from-bundle",
+                bundle_arg,
+            ],
+            &format!("installing from the bundle '{bundle_arg}'"),
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "run", "stable", "rustc", "--version"], "1.1.0")
+        .await;
+
+    // An archive may also hold the component archives next to the manifest.
+    let flat = cx.config.homedir.join("flat");
+    fs::create_dir(&flat).unwrap();
+    for entry in fs::read_dir(&bundle).unwrap() {
+        let path = entry.unwrap().path();
+        let files = match path.is_dir() {
+            true => fs::read_dir(&path)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect(),
+            false => vec![path],
+        };
+        for file in files.into_iter().filter(|file| file.is_file()) {
+            fs::copy(&file, flat.join(file.file_name().unwrap())).unwrap();
+        }
+    }
+    let archive = cx.config.homedir.join("bundle.tar.gz");
+    create_tarball(Path::new("rust-bundle"), &flat, &archive).unwrap();
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "toolchain",
+            "install",
+            "nightly",
+            "--from-bundle",
+            archive.to_str().unwrap(),
+        ])
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "run", "nightly", "rustc", "--version"], "1.3.0")
+        .await;
+}
+
+#[tokio::test]
+async fn dist_export() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let bundle = cx.config.homedir.join("stable.tar.zst");
+    let bundle_arg = bundle.to_str().unwrap();
+    cx.config
+        .expect_err(
+            &["rustup", "dist", "export", "stable", "-o", "stable.zip"],
+            "'stable.zip' is not a .tar, .tar.gz, .tar.xz or .tar.zst file name",
+        )
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &[
+                "rustup",
+                "dist",
+                "export",
+                "stable",
+                "--target",
+                clitools::CROSS_ARCH1,
+                "-o",
+                bundle_arg,
+            ],
+            for_host!("exported 5 components of toolchain 'stable-{0}'"),
+        )
+        .await;
+
+    // Without the dist server, the toolchain installs from the bundle.
+    fs::remove_dir_all(cx.config.distdir.as_ref().unwrap().join("dist")).unwrap();
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "toolchain",
+            "install",
+            "stable",
+            "--target",
+            clitools::CROSS_ARCH1,
+            "--from-bundle",
+            bundle_arg,
+        ])
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustup", "run", "stable", "rustc", "--version"], "1.1.0")
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &[
+                "rustup",
+                "target",
+                "list",
+                "--installed",
+                "--toolchain",
+                "stable",
+            ],
+            clitools::CROSS_ARCH1,
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn io_profile() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "io-profile", "low-memory"],
+            "IO profile set to 'low-memory'",
+        )
+        .await;
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["--verbose", "toolchain", "install", "nightly"],
+            &[],
+        )
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    let tuning = out
+        .stderr
+        .lines()
+        .filter(|l| l.contains("unpacking with "))
+        .collect::<Vec<_>>();
+    assert_eq!(tuning.len(), 1, "{}", out.stderr);
+    assert!(tuning[0].contains("with 1 IO threads"), "{}", tuning[0]);
+    assert!(
+        tuning[0].ends_with("(low-memory IO profile)"),
+        "{}",
+        tuning[0]
+    );
+
+    cx.config
+        .expect_err(
+            &["rustup", "set", "io-profile", "fastest"],
+            "invalid value 'fastest'",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn jobs_set_the_io_threads() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            [
+                "--verbose",
+                "--jobs",
+                "3",
+                "toolchain",
+                "install",
+                "nightly",
+            ],
+            &[("RUSTUP_IO_THREADS", "2")],
+        )
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    let tuning = out
+        .stderr
+        .lines()
+        .find(|l| l.contains("unpacking with "))
+        .unwrap();
+    assert!(tuning.contains("with 3 IO threads"), "{tuning}");
+}
+
+/// A self-signed root certificate, for settings that only read roots.
+const TEST_ROOT: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBjTCCATOgAwIBAgIUe72cwKcxapR9Pv74jVEYwpexh9YwCgYIKoZIzj0EAwIw
+GzEZMBcGA1UEAwwQcnVzdHVwIHRlc3Qgcm9vdDAgFw0yNjEwMTgwODA0NDdaGA8y
+MTI2MDkyNDA4MDQ0N1owGzEZMBcGA1UEAwwQcnVzdHVwIHRlc3Qgcm9vdDBZMBMG
+ByqGSM49AgEGCCqGSM49AwEHA0IABL2zNONXSpe+kdUHu6xvulwhWf4GOfZ0iEO8
+E/wsuQLRWj4jiyuTeXLiT8rU+VYFCsQQ6YXDwXSlF4/jorZlKF+jUzBRMB0GA1Ud
+DgQWBBTn9t50fZ5uodraSqPvWpcvX9BQ3jAfBgNVHSMEGDAWgBTn9t50fZ5uodra
+SqPvWpcvX9BQ3jAPBgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0gAMEUCIEop
+xzoG/FC34LEW/G5adUhFEKyMMth3AtzZy/apjnFEAiEAwJpWDyTPB97lKWeEhtdN
+41bZgBSdIAcUV8FfhETuuU4=
+-----END CERTIFICATE-----
+";
+
+#[tokio::test]
+async fn tls_root_source() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "tls-root-source", "bundled"],
+            "TLS root source set to 'bundled'",
+        )
+        .await;
+    let settings = fs::read_to_string(cx.config.rustupdir.join("settings.toml")).unwrap();
+    assert!(
+        settings.contains("tls_root_source = \"bundled\""),
+        "{settings}"
+    );
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+
+    cx.config
+        .expect_err(
+            &["rustup", "set", "tls-root-source", "missing.pem"],
+            "could not read root certificates from '",
+        )
+        .await;
+
+    let roots = cx.config.current_dir().join("roots.pem");
+    fs::write(&roots, "").unwrap();
+    cx.config
+        .expect_err(
+            &["rustup", "set", "tls-root-source", "roots.pem"],
+            "roots.pem' contains no certificates",
+        )
+        .await;
+    fs::write(&roots, TEST_ROOT).unwrap();
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "tls-root-source", "roots.pem"],
+            &format!("TLS root source set to '{}'", roots.display()),
+        )
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "tls-root-source", "platform"],
+            "TLS root source set to 'platform'",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn tls_host() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let roots = cx.config.current_dir().join("corp-ca.pem");
+    fs::write(&roots, TEST_ROOT).unwrap();
+    cx.config
+        .expect_stderr_ok(
+            &[
+                "rustup",
+                "set",
+                "tls-host",
+                "Mirror.Corp.Example",
+                "--roots",
+                "corp-ca.pem",
+            ],
+            &format!(
+                "'mirror.corp.example' is now also trusted if its certificate leads up to the roots in '{}'",
+                roots.display()
+            ),
+        )
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "tls-host", "10.0.0.5", "--insecure"],
+            "warn: the TLS certificate of '10.0.0.5' will no longer be verified",
+        )
+        .await;
+    let settings = fs::read_to_string(cx.config.rustupdir.join("settings.toml")).unwrap();
+    assert!(
+        settings.contains("[tls_hosts.\"10.0.0.5\"]\ninsecure = true"),
+        "{settings}"
+    );
+    assert!(
+        settings.contains("[tls_hosts.\"mirror.corp.example\"]\nroots = "),
+        "{settings}"
+    );
+    // A file dist server has no TLS to relax.
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+
+    cx.config
+        .expect_err(
+            &[
+                "rustup",
+                "set",
+                "tls-host",
+                "static.rust-lang.org",
+                "--insecure",
+            ],
+            "certificates of rust-lang.org servers are always verified",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "set", "tls-host", "https://mirror.corp.example"],
+            "the following required arguments were not provided",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "set", "tls-host", "mirror:443", "--insecure"],
+            "'mirror:443' is not a host name",
+        )
+        .await;
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "tls-host", "10.0.0.5", "--remove"],
+            "removed the TLS settings of '10.0.0.5'",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "set", "tls-host", "10.0.0.5", "--remove"],
+            "'10.0.0.5' has no TLS settings",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn timings_report() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["--timings", "toolchain", "install", "nightly"],
+            &[],
+        )
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(
+        out.stderr.contains("info: timing report saved to '"),
+        "{}",
+        out.stderr
+    );
+
+    let dir = cx.config.rustupdir.join("timings");
+    let mut reports = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    reports.sort();
+    let [html, json] = &reports[..] else {
+        panic!("{reports:?}");
+    };
+    assert_eq!(json.extension().unwrap(), "json");
+    assert_eq!(html.extension().unwrap(), "html");
+
+    let report =
+        serde_json::from_str::<serde_json::Value>(&fs::read_to_string(json).unwrap()).unwrap();
+    let phases = report["phases"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| {
+            (
+                p["component"].as_str().unwrap().to_owned(),
+                p["phase"].as_str().unwrap().to_owned(),
+            )
+        })
+        .collect::<Vec<_>>();
+    for phase in [
+        ("manifest", "download"),
+        ("rustc", "download"),
+        ("rustc", "unpack"),
+        ("cargo", "download"),
+        ("cargo", "unpack"),
+    ] {
+        assert!(
+            phases.contains(&(phase.0.to_owned(), phase.1.to_owned())),
+            "{phases:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn component_sizes() {
+    let mut cx = CliTestContext::new(Scenario::SizedV2).await;
+    let out = cx
+        .config
+        .run("rustup", ["toolchain", "install", "nightly"], &[])
+        .await;
+    assert!(out.ok);
+    let line = out
+        .stderr
+        .lines()
+        .find(|l| l.starts_with("info: this will download "))
+        .unwrap();
+    assert!(line.ends_with(" on disk"));
+
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let out = cx
+        .config
+        .run("rustup", ["component", "list", "--verbose"], &[])
+        .await;
+    assert!(out.ok);
+    let line = out
+        .stdout
+        .lines()
+        .find(|l| l.starts_with(for_host!("rustc-{} (installed)")))
+        .unwrap();
+    assert!(line.contains("\tdownload: "));
+    assert!(!line.contains(": unknown"), "{line}");
+    cx.config
+        .expect_err(
+            &["rustup", "component", "list", "--verbose", "--quiet"],
+            "cannot be used with",
+        )
+        .await;
+
+    // Manifests that don't declare sizes don't get a size summary.
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config
+        .expect_not_stderr_ok(
+            &["rustup", "toolchain", "install", "nightly"],
+            "this will download",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn confirm_downloads_over() {
+    let mut cx = CliTestContext::new(Scenario::SizedV2).await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "confirm-downloads-over", "1KiB"],
+            "updates downloading more than 1.0 KiB now ask for confirmation",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "install", "nightly"],
+            "which was not confirmed",
+        )
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "--yes", "toolchain", "install", "nightly"])
+        .await;
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "confirm-downloads-over", "none"],
+            "updates no longer ask for confirmation",
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "set", "confirm-downloads-over", "lots"],
+            "invalid size 'lots'",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn max_downloads() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "max-downloads", "1"],
+            "max downloads set to '1'",
+        )
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "set", "max-downloads", "0"],
+            "invalid value '0'",
+        )
+        .await;
+
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["toolchain", "install", "stable"],
+            &[("RUSTUP_MAX_DOWNLOADS", "many")],
+        )
+        .await;
+    assert!(!out.ok);
+    assert!(
+        out.stderr.contains("invalid RUSTUP_MAX_DOWNLOADS 'many'"),
+        "{}",
+        out.stderr
+    );
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["toolchain", "install", "stable"],
+            &[("RUSTUP_MAX_DOWNLOADS", "8")],
+        )
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+}
+
+#[tokio::test]
+async fn toolchain_hash() {
+    async fn hash(cx: &CliTestContext) -> String {
+        let out = cx
+            .config
+            .run("rustup", ["toolchain", "hash", "nightly"], &[])
+            .await;
+        assert!(out.ok, "{}", out.stderr);
+        let hash = out.stdout.trim().to_owned();
+        assert_eq!(hash.len(), 64, "{hash}");
+        hash
+    }
+
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "hash", "nightly"],
+            for_host!("toolchain 'nightly-{0}' is not installed"),
+        )
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+    let first = hash(&cx).await;
+
+    // A separate install of the same toolchain hashes the same.
+    let mut other = CliTestContext::new(Scenario::SimpleV2).await;
+    other
+        .config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+    assert_eq!(hash(&other).await, first);
+
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "component",
+            "add",
+            "rust-src",
+            "--toolchain",
+            "nightly",
+        ])
+        .await;
+    assert_ne!(hash(&cx).await, first);
+}
+
+// The mock binaries are only dynamically linked ELF files on Linux.
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn post_install_hook_and_linkage() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let log = cx.config.homedir.join("hook.log");
+    let hook = cx.config.homedir.join("hook.sh");
+    let write_hook = |body: &str| {
+        fs::write(&hook, format!("#!/bin/sh\n{body}\n")).unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+    };
+
+    write_hook(&format!("echo \"$1\" >> '{}'", log.display()));
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "post-install-hook", hook.to_str().unwrap()],
+            "post-install hook set to",
+        )
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+    let patched = fs::read_to_string(&log).unwrap();
+    assert!(
+        patched.lines().any(|l| l.ends_with("/bin/rustc")),
+        "{patched}"
+    );
+
+    let out = cx
+        .config
+        .run("rustup", ["toolchain", "linkage", "nightly"], &[])
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(
+        out.stdout.contains("bin/rustc\n    interpreter: "),
+        "{}",
+        out.stdout
+    );
+
+    // A failing hook rolls the update back.
+    write_hook("exit 1");
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "install", "stable"],
+            "post-install hook",
+        )
+        .await;
+    assert!(!cx
+        .config
+        .rustupdir
+        .join("toolchains")
+        .join(format!("stable-{}", this_host_triple()))
+        .exists());
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "post-install-hook", "none"],
+            "post-install hook removed",
+        )
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "stable"])
+        .await;
+
+    // Without a hook of the user's own, the policy's is run.
+    write_hook(&format!("echo \"$1\" >> '{}'", log.display()));
+    fs::remove_file(&log).unwrap();
+    fs::write(
+        cx.config.rustupdir.join("policy.toml"),
+        format!("post_install_hook = '{}'\n", hook.display()),
+    )
+    .unwrap();
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "beta"])
+        .await;
+    assert!(fs::read_to_string(&log).unwrap().contains("/bin/rustc"));
+}
+
+#[tokio::test]
+async fn dedup_links_identical_files_between_toolchains() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "set", "dedup", "true"],
+            "files identical between toolchains are now shared",
+        )
+        .await;
+    for toolchain in ["nightly", "stable"] {
+        cx.config
+            .expect_ok(&["rustup", "toolchain", "install", toolchain])
+            .await;
+    }
+
+    let toolchains = cx.config.rustupdir.join("toolchains");
+    let file = |toolchain: &str, name: &str| {
+        toolchains
+            .join(format!("{toolchain}-{}", this_host_triple()))
+            .join("bin")
+            .join(name)
+    };
+    let rustc = format!("rustc{EXE_SUFFIX}");
+    assert!(same_file::is_same_file(file("nightly", &rustc), file("stable", &rustc)).unwrap());
+    assert!(!same_file::is_same_file(
+        file("nightly", &format!("{rustc}.version")),
+        file("stable", &format!("{rustc}.version"))
+    )
+    .unwrap());
+    cx.config
+        .expect_stdout_ok(&["rustc", "+stable", "--version"], "hash-stable-1.1.0")
+        .await;
+
+    // The store only keeps what installed toolchains link to.
+    for toolchain in ["nightly", "stable"] {
+        cx.config
+            .expect_ok(&["rustup", "toolchain", "remove", toolchain])
+            .await;
+    }
+    if cfg!(unix) {
+        let store = cx.config.rustupdir.join("store");
+        assert_eq!(fs::read_dir(store).unwrap().count(), 0);
+    }
+}
+
+#[tokio::test]
+async fn toolchain_package() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+    let archive = cx.config.homedir.join("nightly.tar.zst");
+    cx.config
+        .expect_stderr_ok(
+            &[
+                "rustup",
+                "toolchain",
+                "package",
+                "nightly",
+                "-o",
+                archive.to_str().unwrap(),
+            ],
+            "packaged toolchain",
+        )
+        .await;
+
+    let unpacked = cx.config.homedir.join("unpacked");
+    let file = fs::File::open(&archive).unwrap();
+    tar::Archive::new(zstd::Decoder::new(file).unwrap())
+        .unpack(&unpacked)
+        .unwrap();
+    let root = unpacked.join(for_host!("nightly-{}"));
+    assert!(root.join("install.sh").exists());
+    let manifest = fs::read_to_string(root.join("toolchain-manifest.toml")).unwrap();
+    assert!(
+        manifest.contains(for_host!("name = \"nightly-{0}\"")),
+        "{manifest}"
+    );
+
+    // The unpacked toolchain works on its own and hashes the same.
+    let toolchain = root.join("toolchain");
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "toolchain",
+            "link",
+            "packaged",
+            toolchain.to_str().unwrap(),
+        ])
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "run", "packaged", "rustc", "--version"],
+            "1.3.0",
+        )
+        .await;
+    let out = cx
+        .config
+        .run("rustup", ["toolchain", "hash", "packaged"], &[])
+        .await;
+    assert!(out.ok);
+    assert!(
+        manifest.contains(&format!("layout_hash = \"{}\"", out.stdout.trim())),
+        "{manifest}"
+    );
+
+    // Packaging again gives the same archive.
+    let again = cx.config.homedir.join("again.tar.zst");
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "toolchain",
+            "package",
+            "nightly",
+            "-o",
+            again.to_str().unwrap(),
+        ])
+        .await;
+    assert_eq!(fs::read(&archive).unwrap(), fs::read(&again).unwrap());
+}
+
+#[tokio::test]
+async fn describe_toolchain() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "toolchain",
+            "install",
+            "nightly",
+            "--target",
+            clitools::CROSS_ARCH1,
+        ])
+        .await;
+    let out = cx
+        .config
+        .run("rustup", ["describe-toolchain", "nightly"], &[])
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    let description = serde_json::from_str::<serde_json::Value>(&out.stdout).unwrap();
+    assert_eq!(
+        description["name"].as_str().unwrap(),
+        for_host!("nightly-{}")
+    );
+    assert!(description["version"].as_str().unwrap().contains("1.3.0"));
+    let rustc = description["tools"]["rustc"]["path"].as_str().unwrap();
+    assert!(std::path::Path::new(rustc).is_file(), "{rustc}");
+    assert_eq!(
+        description["tools"]["rustc"]["sha256"]
+            .as_str()
+            .unwrap()
+            .len(),
+        64
+    );
+    let std_lib = description["targets"][clitools::CROSS_ARCH1]["std_lib"]
+        .as_str()
+        .unwrap();
+    assert!(std::path::Path::new(std_lib).is_dir(), "{std_lib}");
+
+    let out = cx
+        .config
+        .run("rustup", ["describe-toolchain", "nightly", "--bazel"], &[])
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(out.stdout.contains(&format!(
+        "rust_toolchain(\n    name = \"rust_toolchain-{0}\",\n    rustc = \"bin/rustc\",",
+        clitools::CROSS_ARCH1
+    )));
+    assert!(out.stdout.contains(&format!(
+        "srcs = glob([\"lib/rustlib/{}/lib/*\"]),",
+        clitools::CROSS_ARCH1
+    )));
+}
+
+#[tokio::test]
+async fn toolchain_pin() {
+    let mut cx = CliTestContext::new(Scenario::None).await;
+    {
+        let mut cx = cx.with_dist_dir(Scenario::ArchivesV2_2015_01_01);
+        cx.config.expect_ok(&["rustup", "default", "stable"]).await;
+        cx.config
+            .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+            .await;
+    }
+
+    let cx = cx.with_dist_dir(Scenario::SimpleV2);
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "pin", "beta"],
+            for_host!("toolchain 'beta-{0}' is not installed"),
+        )
+        .await;
+    cx.config
+        .expect_err(
+            &["rustup", "toolchain", "pin", "stable", "--until", "someday"],
+            "expected YYYY-MM-DD",
+        )
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &[
+                "rustup",
+                "toolchain",
+                "pin",
+                "stable",
+                "--until",
+                "2000-01-01",
+            ],
+            for_host!("pinned toolchain 'stable-{0}' until 2000-01-01"),
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(
+            &["rustup", "toolchain", "list"],
+            for_host!("stable-{0} (active, default, pinned)"),
+        )
+        .await;
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "update", "--no-self-update"],
+            for_host!("skipping pinned toolchain 'stable-{0}'"),
+        )
+        .await;
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "update", "--no-self-update"],
+            for_host!("the pin on toolchain 'stable-{0}' expired on 2000-01-01"),
+        )
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustc", "--version"], "hash-stable-1.0.0")
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustc", "+nightly", "--version"], "hash-nightly-2")
+        .await;
+
+    cx.config
+        .expect_stderr_ok(
+            &["rustup", "toolchain", "unpin", "stable"],
+            for_host!("unpinned toolchain 'stable-{0}'"),
+        )
+        .await;
+    cx.config
+        .expect_not_stderr_ok(&["rustup", "update", "--no-self-update"], "pinned")
+        .await;
+    cx.config
+        .expect_stdout_ok(&["rustc", "--version"], "hash-stable-1.1.0")
+        .await;
+}
+
+#[tokio::test]
+async fn install_summary() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["--verbose", "toolchain", "install", "nightly"],
+            &[],
+        )
+        .await;
+    assert!(out.ok);
+    assert!(out.stderr.contains("debug: installed 4 components in "));
+    assert!(out.stderr.contains(", 0 from the download cache\n"));
+
+    // The summary is only shown on the console when attached to a terminal.
+    cx.config
+        .expect_not_stderr_ok(
+            &["rustup", "toolchain", "install", "beta"],
+            "components in ",
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn event_log() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let log = cx.config.rustupdir.join("events.jsonl");
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["toolchain", "install", "nightly"],
+            &[("RUSTUP_EVENT_LOG", &*log.to_string_lossy())],
+        )
+        .await;
+    assert!(out.ok);
+
+    let events = fs::read_to_string(&log).unwrap();
+    let events = events
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .collect::<Vec<_>>();
+    let installed = events
+        .iter()
+        .filter(|e| e["kind"] == "installing-component")
+        .map(|e| e["data"][0].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(installed, ["cargo", "rust-docs", "rust-std", "rustc"]);
+    assert!(events.iter().any(|e| e["kind"] == "downloaded-manifest"
+        && e["data"][1] == "1.3.0 (hash-nightly-2)"
+        && e["level"] == "info"));
+    let summary = events
+        .iter()
+        .find(|e| e["kind"] == "install-summary")
+        .unwrap();
+    assert_eq!(summary["data"]["components"], 4);
+}
+
+#[tokio::test]
+async fn embedders_receive_events() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let mut vars = HashMap::new();
+    clitools::env(&cx.config, &mut vars);
+    let tp = TestProcess::new(
+        &*cx.config.workdir.borrow(),
+        &["rustup", "toolchain", "install", "nightly"],
+        vars,
+        "",
+    );
+
+    let installed = Arc::new(Mutex::new(Vec::new()));
+    let process = tp.process.clone().with_event_handler(Arc::new({
+        let installed = installed.clone();
+        move |event| {
+            if let EventKind::Notification(Notification::Install(
+                dist::Notification::InstallingComponent(name, ..),
+            )) = event.kind
+            {
+                installed.lock().unwrap().push(name.to_string());
+            }
+        }
+    }));
+    let code = dispatch::main(&process, tp.console_filter.clone()).await;
+    assert_eq!(code.unwrap().0, 0);
+    assert_eq!(
+        *installed.lock().unwrap(),
+        ["cargo", "rust-docs", "rust-std", "rustc"]
+    );
+}
+
+#[tokio::test]
+async fn panic_reported_once_and_cleaned_up() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "install", "nightly"])
+        .await;
+
+    // An installed manifest without the host's rust package is a bug that
+    // rustup panics on.
+    let host = this_host_triple();
+    let manifest = cx
+        .config
+        .rustupdir
+        .join("toolchains")
+        .join(format!("nightly-{host}"))
+        .join("lib/rustlib/multirust-channel-manifest.toml");
+    let old = fs::read_to_string(&manifest).unwrap();
+    let new = old.replace(&format!("pkg.rust.target.{host}"), "pkg.rust.target.bogus");
+    assert_ne!(old, new);
+    fs::write(&manifest, new).unwrap();
+
+    let out = cx.config.run_subprocess(
+        "rustup",
+        ["component", "add", "rust-src", "--toolchain", "nightly"],
+        &[],
+    );
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert_eq!(out.status, Some(PANIC_EXIT_CODE), "{stderr}");
+    assert_eq!(
+        stderr
+            .matches("installed manifest should have a known target")
+            .count(),
+        1,
+        "{stderr}"
+    );
+    assert!(stderr.contains("error: rustup panicked at "), "{stderr}");
+    assert!(!stderr.contains("thread 'main' panicked"), "{stderr}");
+
+    // Unwinding deleted the temporary files.
+    let tmp = cx.config.rustupdir.join("tmp");
+    let left = fs::read_dir(&tmp)
+        .map(|dir| dir.map(|entry| entry.unwrap().path()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    assert!(left.is_empty(), "{left:?}");
+}
+
+#[tokio::test]
+async fn http_trace() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let har = cx.config.rustupdir.join("trace.har");
+    let env = [("RUSTUP_HTTP_TRACE", &*har.to_string_lossy())];
+    let out = cx
+        .config
+        .run("rustup", ["toolchain", "install", "nightly"], &env)
+        .await;
+    assert!(out.ok);
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["toolchain", "install", "nightly-2099-01-01"],
+            &env,
+        )
+        .await;
+    assert!(!out.ok);
+
+    let har =
+        serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&har).unwrap()).unwrap();
+    assert_eq!(har["log"]["version"], "1.2");
+    let entries = har["log"]["entries"].as_array().unwrap();
+    let urls = entries
+        .iter()
+        .map(|e| e["request"]["url"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert!(urls
+        .iter()
+        .any(|u| u.ends_with("/dist/channel-rust-nightly.toml.sha256")));
+    assert!(urls
+        .iter()
+        .any(|u| u.contains("/dist/2015-01-02/rustc-nightly-")));
+    let failed = entries.last().unwrap();
+    assert!(failed["request"]["url"]
+        .as_str()
+        .unwrap()
+        .contains("/dist/2099-01-01/"));
+    assert!(failed["_error"].is_string());
+}
+
+/// Copies every file of the mock dist server into `dir` under its SHA-256
+/// hash in lowercase hex, with no directories: the layout of a cache peer,
+/// of the content-addressed store and of a rustup download directory.
+fn store_dist_files(config: &Config, dir: &Path) {
+    use sha2::{Digest, Sha256};
+
+    for entry in walkdir::WalkDir::new(config.distdir.as_ref().unwrap()) {
+        let entry = entry.unwrap();
+        if entry.file_type().is_file() {
+            let data = fs::read(entry.path()).unwrap();
+            fs::write(dir.join(format!("{:x}", Sha256::digest(&data))), data).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn cache_peers() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let cache = cx.config.rustupdir.join("cache");
+    fs::create_dir(&cache).unwrap();
+    store_dist_files(&cx.config, &cache);
+    let cache_url = url::Url::from_directory_path(&cache).unwrap();
     cx.config
-        .expect_err(
-            &["rustup", "target", "list", "--toolchain=nightly"],
-            for_host!("toolchain 'nightly-{0}' does not support components"),
+        .expect_stderr_ok(
+            &["rustup", "set", "cache-peers", cache_url.as_str()],
+            "cache peers set to",
         )
         .await;
-}
+    let miss = "is not cached, downloading it from the dist server";
 
-#[tokio::test]
-async fn list_targets_custom_toolchain() {
-    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
-    let path = cx.config.customdir.join("custom-1");
-    let path = path.to_string_lossy();
     cx.config
-        .expect_ok(&["rustup", "toolchain", "link", "default-from-path", &path])
+        .expect_not_stderr_ok(&["rustup", "toolchain", "install", "nightly"], miss)
         .await;
     cx.config
-        .expect_ok(&["rustup", "default", "default-from-path"])
+        .expect_ok(&["rustup", "toolchain", "remove", "nightly"])
         .await;
+
+    // Anything whose hash does not match is ignored.
+    for entry in fs::read_dir(&cache).unwrap() {
+        fs::write(entry.unwrap().path(), "garbage").unwrap();
+    }
     cx.config
-        .expect_err(
-            &["rustup", "target", "list"],
-            "toolchain 'default-from-path' does not support components",
+        .expect_stderr_ok(&["rustup", "toolchain", "install", "nightly"], miss)
+        .await;
+
+    // A peer that can't be reached is only tried once.
+    store_dist_files(&cx.config, &cache);
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "set",
+            "cache-peers",
+            "http://127.0.0.1:1/",
+            cache_url.as_str(),
+        ])
+        .await;
+    cx.config
+        .expect_ok(&["rustup", "toolchain", "remove", "nightly"])
+        .await;
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["toolchain", "install", "nightly"],
+            &[("RUSTUP_MAX_DOWNLOADS", "1")],
         )
         .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert_eq!(
+        out.stderr
+            .matches("cache peer 'http://127.0.0.1:1/' failed, and is skipped from now on")
+            .count(),
+        1,
+        "{}",
+        out.stderr
+    );
+    let tried = out
+        .stderr
+        .split("'http://127.0.0.1:1/")
+        .skip(1)
+        .filter_map(|rest| rest.split_once('\'').map(|(hash, _)| hash))
+        .filter(|hash| !hash.is_empty())
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(tried.len(), 1, "{}", out.stderr);
+    assert!(!out.stderr.contains(miss), "{}", out.stderr);
+
+    cx.config
+        .expect_stderr_ok(&["rustup", "set", "cache-peers"], "cache peers cleared")
+        .await;
 }
 
 #[tokio::test]
-async fn list_targets() {
+async fn install_through_serve_cache() {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Stdio};
+
+    struct Server(Child);
+    impl Drop for Server {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
     let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
-    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    // The mirror has a home of its own, whose download cache has the
+    // archives of the mock dist server.
+    let mirror_home = cx.config.rustupdir.join("mirror");
+    let downloads = mirror_home.join("downloads");
+    fs::create_dir_all(&downloads).unwrap();
+    store_dist_files(&cx.config, &downloads);
+    let mut cmd = cx
+        .config
+        .cmd("rustup", ["serve-cache", "--listen", "127.0.0.1:0"]);
+    cmd.env("RUSTUP_HOME", &mirror_home)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let mut server = Server(cmd.spawn().unwrap());
+    let mut stderr = BufReader::new(server.0.stderr.take().unwrap());
+    let mut line = String::new();
+    let addr = loop {
+        line.clear();
+        assert_ne!(
+            stderr.read_line(&mut line).unwrap(),
+            0,
+            "serve-cache exited"
+        );
+        if let Some((_, rest)) = line.split_once("serving the download cache on ") {
+            break rest.split(',').next().unwrap().to_owned();
+        }
+    };
+
+    // Manifests come through the mirror as the dist server, and archives
+    // from its cache as a peer.
     cx.config
-        .expect_stdout_ok(&["rustup", "target", "list"], clitools::CROSS_ARCH1)
+        .expect_ok(&["rustup", "set", "cache-peers", &addr])
+        .await;
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["toolchain", "install", "nightly"],
+            &[("RUSTUP_DIST_SERVER", &*addr)],
+        )
         .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(!out.stderr.contains("is not cached"), "{}", out.stderr);
     cx.config
-        .expect_stdout_ok(&["rustup", "target", "list"], clitools::CROSS_ARCH2)
+        .expect_stdout_ok(&["rustc", "+nightly", "--version"], "hash-nightly-2")
         .await;
 }
 
 #[tokio::test]
-async fn list_installed_targets() {
-    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
-    let trip = this_host_triple();
+async fn dist_servers_are_tried_in_turn() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let dist_server = format!("file://{}", cx.config.distdir.as_ref().unwrap().display());
+    cx.config
+        .expect_stderr_ok(
+            &[
+                "rustup",
+                "set",
+                "dist-servers",
+                "file:///no-such-server/",
+                &dist_server,
+            ],
+            &format!("dist servers set to 'file:///no-such-server', '{dist_server}'"),
+        )
+        .await;
 
-    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    // The environment names a single server, so it's cleared for the
+    // settings to apply.
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["toolchain", "install", "nightly"],
+            &[("RUSTUP_DIST_SERVER", "")],
+        )
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(out.stderr.contains(&format!(
+        "could not download 'file:///no-such-server/dist/channel-rust-nightly.toml', \
+         trying '{dist_server}/dist/channel-rust-nightly.toml'"
+    )));
     cx.config
-        .expect_stdout_ok(&["rustup", "target", "list", "--installed"], &trip)
+        .expect_stdout_ok(&["rustc", "+nightly", "--version"], "hash-nightly-2")
         .await;
-}
 
-#[tokio::test]
-async fn add_target1() {
-    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
-    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
     cx.config
-        .expect_ok(&["rustup", "target", "add", clitools::CROSS_ARCH1])
+        .expect_stderr_ok(&["rustup", "set", "dist-servers"], "dist servers cleared")
         .await;
-    let path = format!(
-        "toolchains/nightly-{}/lib/rustlib/{}/lib/libstd.rlib",
-        this_host_triple(),
-        clitools::CROSS_ARCH1
-    );
-    assert!(cx.config.rustupdir.has(path));
 }
 
 #[tokio::test]
-async fn add_target2() {
+async fn dist_servers_have_their_own_keys() {
     let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
-    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let distdir = cx.config.distdir.clone().unwrap();
+    // Nothing to test against without the test key's signatures.
+    if !distdir.join(SIGNED_MARKER).exists() {
+        return;
+    }
+    let dist_server = format!("file://{}", distdir.display());
+    let test_key = std::env::current_dir()
+        .unwrap()
+        .join("tests/mock/signing-key.pub.asc");
     cx.config
-        .expect_ok(&["rustup", "target", "add", clitools::CROSS_ARCH2])
+        .expect_ok(&[
+            "rustup",
+            "set",
+            "dist-servers",
+            "file:///no-such-server/",
+            &dist_server,
+        ])
+        .await;
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "key",
+            "add",
+            "--sigstore-identity",
+            "release@example.com",
+            "--sigstore-issuer",
+            "https://issuer.example.com",
+            "--dist-server",
+            "file:///no-such-server",
+        ])
+        .await;
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "key",
+            "add",
+            test_key.to_str().unwrap(),
+            "--dist-server",
+            &dist_server,
+        ])
         .await;
-    let path = format!(
-        "toolchains/nightly-{}/lib/rustlib/{}/lib/libstd.rlib",
-        this_host_triple(),
-        clitools::CROSS_ARCH2
-    );
-    assert!(cx.config.rustupdir.has(path));
-}
 
-#[tokio::test]
-async fn add_all_targets() {
-    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
-    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    // The manifest from the second server is checked against the test key
+    // trusted for it, not the identity trusted for the first one.
+    let env = [("RUSTUP_DIST_SERVER", ""), ("RUSTUP_PGP_KEY", "")];
+    let out = cx
+        .config
+        .run("rustup", ["toolchain", "install", "nightly"], &env)
+        .await;
+    assert!(out.ok, "{}", out.stderr);
     cx.config
-        .expect_ok(&["rustup", "target", "add", "all"])
+        .expect_stdout_ok(&["rustc", "+nightly", "--version"], "hash-nightly-2")
         .await;
-    let path = format!(
-        "toolchains/nightly-{}/lib/rustlib/{}/lib/libstd.rlib",
-        this_host_triple(),
-        clitools::CROSS_ARCH1
-    );
-    assert!(cx.config.rustupdir.has(path));
-    let path = format!(
-        "toolchains/nightly-{}/lib/rustlib/{}/lib/libstd.rlib",
-        this_host_triple(),
-        clitools::CROSS_ARCH2
+
+    // Another signature is refused, even though the first server's keys
+    // have nothing to say about it.
+    let manifest = distdir.join("dist/channel-rust-beta.toml");
+    fs::write(manifest.with_extension("toml.asc"), "not a signature").unwrap();
+    let out = cx
+        .config
+        .run("rustup", ["toolchain", "install", "beta"], &env)
+        .await;
+    assert!(!out.ok);
+    assert!(
+        out.stderr
+            .contains("is not a good signature by a trusted OpenPGP key"),
+        "{}",
+        out.stderr
     );
-    assert!(cx.config.rustupdir.has(path));
 }
 
+#[cfg(feature = "content-addressed")]
 #[tokio::test]
-async fn add_all_targets_fail() {
+async fn content_addressed_store() {
     let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
-    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let store = cx.config.rustupdir.join("store");
+    fs::create_dir(&store).unwrap();
+    let store_url = url::Url::from_directory_path(&store).unwrap();
+    let env = [("RUSTUP_CONTENT_STORE", store_url.as_str())];
+    let fallback = "is not cached, downloading it from the dist server";
+
+    let out = cx
+        .config
+        .run("rustup", ["toolchain", "install", "nightly"], &env)
+        .await;
+    assert!(out.ok);
+    assert!(out.stderr.contains(fallback));
     cx.config
-        .expect_err(
-            &[
-                "rustup",
-                "target",
-                "add",
-                clitools::CROSS_ARCH1,
-                "all",
-                clitools::CROSS_ARCH2,
-            ],
-            &format!(
-                "`rustup target add {} all {}` includes `all`",
-                clitools::CROSS_ARCH1,
-                clitools::CROSS_ARCH2
-            ),
-        )
+        .expect_ok(&["rustup", "toolchain", "remove", "nightly"])
+        .await;
+
+    store_dist_files(&cx.config, &store);
+    let out = cx
+        .config
+        .run("rustup", ["toolchain", "install", "nightly"], &env)
         .await;
+    assert!(out.ok);
+    assert!(!out.stderr.contains(fallback));
 }
 
 #[tokio::test]
@@ -1080,6 +3539,78 @@ async fn add_target_host() {
         .await;
 }
 
+#[tokio::test]
+async fn target_doctor() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+    let nightly = for_host!("nightly-{0}");
+    let bin = tempfile::Builder::new().prefix("rustup").tempdir().unwrap();
+    let path = bin.path().to_str().unwrap();
+    let target = "riscv64gc-unknown-linux-gnu";
+    let gcc = format!("riscv64-linux-gnu-gcc{EXE_SUFFIX}");
+
+    let out = cx
+        .config
+        .run("rustup", ["target", "doctor", target], &[("PATH", path)])
+        .await;
+    assert!(!out.ok);
+    for expected in [
+        format!("'{nightly}' has no standard library for '{target}'"),
+        "linker: 2 problems".to_owned(),
+        "`riscv64-linux-gnu-gcc` was not found in PATH".to_owned(),
+        format!("run `rustup target doctor {target} --write-config`"),
+        "C compiler: 1 problem".to_owned(),
+    ] {
+        assert!(out.stdout.contains(&expected), "{expected}");
+    }
+
+    fs::write(bin.path().join(&gcc), "").unwrap();
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["target", "doctor", target, "--write-config"],
+            &[("PATH", path)],
+        )
+        .await;
+    assert!(out
+        .stdout
+        .contains("linker: ok (`riscv64-linux-gnu-gcc`, from '"));
+    assert!(out
+        .stdout
+        .contains("C compiler: ok (`riscv64-linux-gnu-gcc`)"));
+    let config = cx
+        .config
+        .workdir
+        .borrow()
+        .join(".cargo")
+        .join("config.toml");
+    assert_eq!(
+        fs::read_to_string(config).unwrap(),
+        format!("[target.{target}]\nlinker = \"riscv64-linux-gnu-gcc\"\n")
+    );
+
+    let cross = clitools::CROSS_ARCH1;
+    let out = cx
+        .config
+        .run("rustup", ["target", "doctor", cross], &[])
+        .await;
+    assert!(!out.ok);
+    assert!(out.stdout.contains(&format!(
+        "fix: run `rustup target add --toolchain {nightly} {cross}`"
+    )));
+    cx.config
+        .expect_ok(&["rustup", "target", "add", cross])
+        .await;
+    let out = cx
+        .config
+        .run("rustup", ["target", "doctor", cross], &[])
+        .await;
+    assert!(out
+        .stdout
+        .contains(&format!("target: ok (installed for '{nightly}')")));
+}
+
 #[tokio::test]
 async fn remove_target() {
     let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
@@ -1255,8 +3786,10 @@ async fn remove_target_missing_update_hash() {
     let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
     cx.config.expect_ok(&["rustup", "update", "nightly"]).await;
 
-    let file_name = format!("nightly-{}", this_host_triple());
-    fs::remove_file(cx.config.rustupdir.join("update-hashes").join(file_name)).unwrap();
+    cx.config
+        .rustupdir
+        .forget_installed_manifest(for_host!("nightly-{0}"))
+        .unwrap();
 
     cx.config
         .expect_ok(&["rustup", "toolchain", "remove", "nightly"])
@@ -1279,23 +3812,56 @@ async fn warn_about_and_remove_stray_hash() {
     cx.config
         .expect_stderr_ok(
             &["rustup", "toolchain", "install", "nightly"],
-            &format!(
-                "removing stray hash found at '{}' in order to continue",
-                hash_path.display()
-            ),
+            for_host!("removing stray hash for 'nightly-{0}' in order to continue"),
+        )
+        .await;
+    assert!(!hash_path.exists());
+}
+
+#[tokio::test]
+async fn manifests_are_reused_within_their_ttl() {
+    let mut cx = CliTestContext::new(Scenario::SimpleV2).await;
+    cx.config.expect_ok(&["rustup", "default", "nightly"]).await;
+
+    // Without a TTL the dist server is always asked.
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["check"],
+            &[("RUSTUP_DIST_SERVER", "file:///no-such-server")],
+        )
+        .await;
+    assert!(!out.ok, "{}", out.stdout);
+
+    cx.config
+        .expect_stdout_ok(&["rustup", "check"], for_host!("nightly-{0} - Up to date"))
+        .await;
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["check"],
+            &[
+                ("RUSTUP_DIST_SERVER", "file:///no-such-server"),
+                ("RUSTUP_MANIFEST_TTL", "3600"),
+            ],
         )
         .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(
+        out.stdout.contains(for_host!("nightly-{0} - Up to date")),
+        "{}",
+        out.stdout
+    );
 }
 
 fn make_component_unavailable(config: &Config, name: &str, target: String) {
     use rustup::dist::manifest::Manifest;
-    use rustup::test::mock::dist::create_hash;
+    use rustup::test::mock::dist::{create_hash, sign_with_test_key};
 
-    let manifest_path = config
-        .distdir
-        .as_ref()
-        .unwrap()
-        .join("dist/channel-rust-nightly.toml");
+    let distdir = config.distdir.as_ref().unwrap();
+    let manifest_path = distdir.join("dist/channel-rust-nightly.toml");
     let manifest_str = fs::read_to_string(&manifest_path).unwrap();
     let mut manifest = Manifest::parse(&manifest_str).unwrap();
     {
@@ -1311,6 +3877,7 @@ fn make_component_unavailable(config: &Config, name: &str, target: String) {
     let hash_path = manifest_path.with_extension("toml.sha256");
     println!("{}", hash_path.display());
     create_hash(&manifest_path, &hash_path);
+    sign_with_test_key(distdir, &manifest_path);
 }
 
 #[tokio::test]
@@ -1734,12 +4301,7 @@ async fn run_with_install_flag_against_unavailable_component() {
             for_host!(
                 r"info: syncing channel updates for 'nightly-{0}'
 info: latest update on 2015-01-02, rust version 1.3.0 (hash-nightly-2)
-warn: Force-skipping unavailable component 'rust-std-{0}'
-info: downloading component 'cargo'
-info: downloading component 'rust-docs'
 info: downloading component 'rustc'
-info: installing component 'cargo'
-info: installing component 'rust-docs'
 info: installing component 'rustc'
 "
             ),
@@ -1818,3 +4380,126 @@ async fn regression_2601() {
         )
         .await;
 }
+
+#[tokio::test]
+async fn status_tells_network_from_upstream() {
+    let cx = CliTestContext::new(Scenario::SimpleV2).await;
+    let feed = cx.config.current_dir().join("status.json");
+    let feed_url = Url::from_file_path(&feed).unwrap().to_string();
+
+    fs::write(
+        &feed,
+        r#"{"status": {"indicator": "none", "description": "All Systems Operational"}}"#,
+    )
+    .unwrap();
+    let out = cx
+        .config
+        .run("rustup", ["status"], &[("RUSTUP_STATUS_URL", &feed_url)])
+        .await;
+    assert!(out.ok, "{}", out.stderr);
+    assert!(out.stdout.contains("  reachable: ok ("));
+    assert!(out.stdout.contains("  status: All Systems Operational\n"));
+    assert!(out.stdout.contains("verdict: downloads should work\n"));
+
+    fs::write(
+        &feed,
+        r#"{"status": {"indicator": "major", "description": "Partial System Outage"}}"#,
+    )
+    .unwrap();
+    let out = cx
+        .config
+        .run("rustup", ["status"], &[("RUSTUP_STATUS_URL", &feed_url)])
+        .await;
+    assert!(!out.ok);
+    assert!(out.stdout.contains(
+        "verdict: upstream incident: the Rust infrastructure reports 'Partial System Outage'\n"
+    ));
+
+    fs::remove_file(&feed).unwrap();
+    let out = cx
+        .config
+        .run(
+            "rustup",
+            ["status"],
+            &[
+                ("RUSTUP_STATUS_URL", &feed_url),
+                ("RUSTUP_DIST_SERVER", "http://127.0.0.1:1"),
+                ("RUSTUP_MAX_RETRIES", "0"),
+            ],
+        )
+        .await;
+    assert!(!out.ok);
+    assert!(out.stdout.contains("verdict: your network looks broken"));
+}
+
+#[tokio::test]
+async fn report_file_summarizes_updates() {
+    let mut cx = CliTestContext::new(Scenario::ArchivesV2).await;
+    let report_file = cx.config.current_dir().join("report.json");
+    let report_path = report_file.to_str().unwrap();
+    let report = || -> serde_json::Value {
+        serde_json::from_str(&fs::read_to_string(&report_file).unwrap()).unwrap()
+    };
+    let nightly = for_host!("nightly-{0}");
+
+    set_current_dist_date(&cx.config, "2015-01-01");
+    cx.config
+        .expect_ok(&[
+            "rustup",
+            "toolchain",
+            "install",
+            "nightly",
+            "--report-file",
+            report_path,
+        ])
+        .await;
+    let installed = report();
+    assert_eq!(installed["command"], "install");
+    assert_eq!(installed["success"], true);
+    assert_eq!(installed["toolchains"][0]["name"], nightly.as_str());
+    assert_eq!(installed["toolchains"][0]["action"], "installed");
+    assert!(installed["downloads"]["downloaded"].as_u64().unwrap() > 0);
+    assert!(installed["downloads"]["bytes"].as_u64().unwrap() > 0);
+
+    cx.config
+        .expect_ok(&["rustup", "update", "--report-file", report_path])
+        .await;
+    let unchanged = report();
+    assert_eq!(unchanged["command"], "update");
+    assert_eq!(unchanged["toolchains"][0]["action"], "unchanged");
+    assert_eq!(
+        unchanged["toolchains"][0]["hash"],
+        installed["toolchains"][0]["hash"]
+    );
+    assert_eq!(unchanged["downloads"]["downloaded"], 0);
+
+    set_current_dist_date(&cx.config, "2015-01-02");
+    cx.config
+        .expect_ok(&["rustup", "update", "--report-file", report_path])
+        .await;
+    let updated = report();
+    assert_eq!(updated["toolchains"][0]["action"], "updated");
+    assert_ne!(
+        updated["toolchains"][0]["hash"],
+        installed["toolchains"][0]["hash"]
+    );
+
+    cx.config
+        .expect_err(
+            &[
+                "rustup",
+                "update",
+                "nightly-2016-01-01",
+                "--report-file",
+                report_path,
+            ],
+            "no release found",
+        )
+        .await;
+    let failed = report();
+    assert_eq!(failed["success"], false);
+    assert!(failed["error"]
+        .as_str()
+        .unwrap()
+        .contains("no release found"));
+}
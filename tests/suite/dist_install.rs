@@ -8,7 +8,6 @@ use rustup::dist::prefix::InstallPrefix;
 use rustup::dist::temp;
 use rustup::dist::Notification;
 use rustup::dist::DEFAULT_DIST_SERVER;
-use rustup::process::TestProcess;
 use rustup::utils;
 
 use rustup::test::mock::{MockComponentBuilder, MockFile, MockInstallerBuilder};
@@ -117,8 +116,7 @@ fn basic_install() {
         Box::new(|_| ()),
     );
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let components = Components::open(prefix).unwrap();
 
@@ -164,8 +162,7 @@ fn multiple_component_install() {
         Box::new(|_| ()),
     );
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let components = Components::open(prefix).unwrap();
 
@@ -215,8 +212,7 @@ fn uninstall() {
         Box::new(|_| ()),
     );
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let components = Components::open(prefix.clone()).unwrap();
 
@@ -228,10 +224,9 @@ fn uninstall() {
 
     // Now uninstall
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let mut tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
     for component in components.list().unwrap() {
-        tx = component.uninstall(tx, &tp.process).unwrap();
+        tx = component.uninstall(tx).unwrap();
     }
     tx.commit();
 
@@ -274,8 +269,7 @@ fn component_bad_version() {
         Box::new(|_| ()),
     );
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let components = Components::open(prefix.clone()).unwrap();
 
@@ -321,8 +315,7 @@ fn install_to_prefix_that_does_not_exist() {
         Box::new(|_| ()),
     );
     let notify = |_: Notification<'_>| ();
-    let tp = TestProcess::default();
-    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify, &tp.process);
+    let tx = Transaction::new(prefix.clone(), &tmp_cx, &notify);
 
     let components = Components::open(prefix).unwrap();
 